@@ -0,0 +1,208 @@
+// Microbenchmarks for the hot paths: parsing a request, serialising a
+// response, finding a route and queueing a job on the pool, then the
+// latency of small responses over loopback with Nagle's algorithm on and
+// off.
+//
+//     cargo bench --bench micro
+//
+// There is no bench framework in the tree, so each benchmark is a timed
+// loop: a warm-up, then as many iterations as fit in about a second,
+// reported as the mean time and heap allocations per iteration.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use server_app::http::{Request, Response};
+use server_app::net::{self, SocketOptions};
+use server_app::router::Router;
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::ThreadPool;
+
+const REQUEST: &[u8] = b"GET /hello?name=world HTTP/1.1\r\n\
+Host: 127.0.0.1:7878\r\n\
+User-Agent: bench\r\n\
+Accept: text/html,application/json;q=0.9\r\n\
+Accept-Encoding: gzip, deflate\r\n\
+Connection: keep-alive\r\n\r\n";
+
+// The system allocator, counting allocations so each benchmark can report
+// how many it makes.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn main() {
+    bench("Request::parse", || {
+        black_box(Request::parse(black_box(REQUEST)).unwrap());
+    });
+
+    let response = Response::new(200, "OK")
+        .with_header("Content-Type", "text/html")
+        .with_header("Cache-Control", "no-cache")
+        .with_body(vec![b'x'; 1024]);
+    let mut out = Vec::with_capacity(4096);
+    bench("Response::write_to (1 KiB body)", || {
+        out.clear();
+        black_box(&response).write_to(&mut out).unwrap();
+        black_box(&out);
+    });
+
+    // 200 routes, looked up by the router's tree and by trying each
+    // pattern in turn, as the router used to. The path matches the last
+    // route registered, the worst case for the scan.
+    let patterns: Vec<String> = (0..200)
+        .map(|n| match n % 4 {
+            0 => format!("/api/v1/resource{}", n),
+            1 => format!("/api/v1/resource{}/:id", n),
+            2 => format!("/api/v1/resource{}/:id/items/:item", n),
+            _ => format!("/static{}/*path", n),
+        })
+        .collect();
+    let mut router = Router::new();
+    for pattern in &patterns {
+        router.get(pattern, |_: &Request| Response::new(200, "OK"));
+    }
+    let parsed: Vec<Vec<&str>> = patterns
+        .iter()
+        .map(|pattern| pattern.split('/').filter(|s| !s.is_empty()).collect())
+        .collect();
+    let path = "/api/v1/resource198/42/items/7";
+    bench("Router lookup (200 routes, tree)", || {
+        black_box(router.matched("GET", black_box(path)).unwrap());
+    });
+    bench("Router lookup (200 routes, linear)", || {
+        black_box(parsed.iter().find_map(|segments| linear_match(segments, black_box(path))).unwrap());
+    });
+
+    // Round trip: queued, picked up by a worker, and reported back.
+    let pool = ThreadPool::new(4);
+    let (done, finished) = mpsc::channel();
+    bench("ThreadPool::execute (round trip)", || {
+        let done = done.clone();
+        pool.execute(move || done.send(()).unwrap());
+        finished.recv().unwrap();
+    });
+
+    // A 64-byte body, buffered and chunked. `Connection` gathers each
+    // response into as few writes as it can, so Nagle seldom has a tail
+    // to hold back for the client's delayed ACK; turning `nodelay` off
+    // shows what's left of that in the p99.
+    for nodelay in [true, false] {
+        latency(&format!("64 B response, nodelay={}", nodelay), nodelay, &[b'x'; 64], || {
+            Response::new(200, "OK").with_body(vec![b'x'; 64])
+        });
+        latency(&format!("64 B chunked response, nodelay={}", nodelay), nodelay, b"0\r\n\r\n", || {
+            Response::from_iter(200, "text/plain", vec![vec![b'x'; 32], vec![b'x'; 32]])
+        });
+    }
+}
+
+// Round trips of a keep-alive request on one loopback connection, served
+// with `nodelay` as the server would set it; each response ends with
+// `end`. Reports the median and p99.
+fn latency<F: Fn() -> Response + Send + 'static>(name: &str, nodelay: bool, end: &[u8], respond: F) {
+    let options = SocketOptions { nodelay, ..SocketOptions::default() };
+    let listener = net::bind("127.0.0.1:0", &options).unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    client.set_nodelay(true).unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        net::configure_stream(&stream, &options).unwrap();
+        let mut connection = Connection::new(stream);
+        let config = ServerConfig::default();
+        while let Incoming::Request(_) = connection.read_next_request(&config, |_| None) {
+            connection.send(&respond()).unwrap();
+        }
+    });
+
+    let mut samples = Vec::new();
+    let mut buffer = [0; 4096];
+    let mut round_trip = || {
+        let started = Instant::now();
+        client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(end) {
+            let n = client.read(&mut buffer).unwrap();
+            assert!(n > 0, "server closed the connection");
+            response.extend_from_slice(&buffer[..n]);
+        }
+        started.elapsed()
+    };
+    for _ in 0..100 {
+        round_trip();
+    }
+    let budget = Duration::from_secs(1);
+    let started = Instant::now();
+    while started.elapsed() < budget && samples.len() < 10_000 {
+        samples.push(round_trip());
+    }
+    drop(client);
+    server.join().unwrap();
+
+    samples.sort();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+    println!("{:<36} {:>12} round trips {:>12.2?} p50 {:>12.2?} p99", name, samples.len(), percentile(50), percentile(99));
+}
+
+// The router's matching before it kept a tree: one parsed pattern
+// against one path.
+fn linear_match(segments: &[&str], path: &str) -> Option<HashMap<String, String>> {
+    let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let mut params = HashMap::new();
+    for (i, segment) in segments.iter().enumerate() {
+        if let Some(name) = segment.strip_prefix('*') {
+            params.insert(name.to_string(), parts.get(i..).unwrap_or(&[]).join("/"));
+            return Some(params);
+        } else if let Some(name) = segment.strip_prefix(':') {
+            params.insert(name.to_string(), parts.get(i)?.to_string());
+        } else if parts.get(i) != Some(segment) {
+            return None;
+        }
+    }
+    (parts.len() == segments.len()).then_some(params)
+}
+
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    for _ in 0..1_000 {
+        f();
+    }
+
+    let budget = Duration::from_secs(1);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    let mut iterations: u64 = 0;
+    while started.elapsed() < budget {
+        for _ in 0..100 {
+            f();
+        }
+        iterations += 100;
+    }
+    let per_iteration = started.elapsed() / iterations as u32;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) as f64 / iterations as f64;
+    println!("{:<36} {:>12} iterations {:>12.2?}/iter {:>8.1} allocs/iter", name, iterations, per_iteration, allocations);
+}
@@ -0,0 +1,114 @@
+// Throughput and latency of `ThreadPool` on its own, as a baseline for
+// changes to how jobs are queued and handed to workers:
+//
+//     cargo bench --bench pool_throughput
+//
+// Results go to stderr. Results from a run are kept in BENCHMARKS.md.
+//
+// Like `micro`, there's no bench framework: each case runs as a timed
+// loop of whole batches, a warm-up batch first, reporting the mean and
+// best batch times. A batch is done once an `AtomicUsize`, bumped by
+// each job, reaches its size.
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use server_app::ThreadPool;
+
+const WORKERS: usize = 4;
+
+fn main() {
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    eprintln!("{} workers unless stated, {} cores available", WORKERS, cores);
+
+    let pool = ThreadPool::new(WORKERS);
+    batch("10 000 no-op jobs", 10_000, || {
+        submit_and_wait(&pool, 10_000, || {});
+    });
+    batch("1 000 jobs sleeping 1 µs", 1_000, || {
+        submit_and_wait(&pool, 1_000, || thread::sleep(Duration::from_micros(1)));
+    });
+    latency(&pool);
+    drop(pool);
+
+    // The same sleeping batch on more and more workers. Efficiency is the
+    // speed-up over one worker divided by the number of workers. A
+    // sleeping job leaves its core free, so this scales past the number
+    // of cores, and what it loses shows the cost of handing out jobs.
+    let mut single = None;
+    for workers in [1, 2, 4, 8, 16] {
+        let pool = ThreadPool::new(workers);
+        let mean = batch(&format!("1 000 sleeping jobs, {} workers", workers), 1_000, || {
+            submit_and_wait(&pool, 1_000, || thread::sleep(Duration::from_micros(1)));
+        });
+        let single = *single.get_or_insert(mean);
+        let speedup = single.as_secs_f64() / mean.as_secs_f64();
+        eprintln!("{:<36} {:>11.2}x speed-up {:>10.0}% efficiency", "", speedup, speedup / workers as f64 * 100.0);
+    }
+}
+
+// Queue `jobs` copies of `job` and wait until every one has run.
+fn submit_and_wait<F: Fn() + Send + Sync + Clone + 'static>(pool: &ThreadPool, jobs: usize, job: F) {
+    let done = Arc::new(AtomicUsize::new(0));
+    for _ in 0..jobs {
+        let done = Arc::clone(&done);
+        let job = job.clone();
+        pool.execute(move || {
+            job();
+            done.fetch_add(1, Ordering::Release);
+        });
+    }
+    while done.load(Ordering::Acquire) < jobs {
+        thread::yield_now();
+    }
+}
+
+// Time `f`, one batch of `jobs` jobs, as often as fits in about a second
+// and at least five times. Returns the mean.
+fn batch<F: FnMut()>(name: &str, jobs: usize, mut f: F) -> Duration {
+    f();
+    let budget = Duration::from_secs(1);
+    let started = Instant::now();
+    let mut times = Vec::new();
+    while started.elapsed() < budget || times.len() < 5 {
+        let batch = Instant::now();
+        f();
+        times.push(batch.elapsed());
+    }
+    let mean = times.iter().sum::<Duration>() / times.len() as u32;
+    let best = *times.iter().min().unwrap();
+    let per_second = jobs as f64 / mean.as_secs_f64();
+    eprintln!("{:<36} {:>6} batches {:>10.2?} mean {:>10.2?} best {:>12.0} jobs/s", name, times.len(), mean, best, per_second);
+    mean
+}
+
+// From `execute` to the job running, one job at a time on an idle pool,
+// and back: the wait a lone request sees. Reports the median and p99.
+fn latency(pool: &ThreadPool) {
+    let (done, finished) = mpsc::channel();
+    let mut samples = Vec::new();
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(1) && samples.len() < 10_000 {
+        let queued = Instant::now();
+        let done = done.clone();
+        pool.execute(move || done.send(black_box(queued).elapsed()).unwrap());
+        let picked_up = finished.recv().unwrap();
+        samples.push((picked_up, queued.elapsed()));
+    }
+    let percentile = |p: usize, of: &dyn Fn(&(Duration, Duration)) -> Duration| {
+        let mut times: Vec<Duration> = samples.iter().map(of).collect();
+        times.sort();
+        times[(times.len() - 1) * p / 100]
+    };
+    eprintln!(
+        "{:<36} {:>6} jobs {:>10.2?} p50 {:>10.2?} p99 to start, {:>10.2?} p50 {:>10.2?} p99 round trip",
+        "single job latency",
+        samples.len(),
+        percentile(50, &|s| s.0),
+        percentile(99, &|s| s.0),
+        percentile(50, &|s| s.1),
+        percentile(99, &|s| s.1),
+    );
+}
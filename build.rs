@@ -0,0 +1,94 @@
+// Bakes the commit being built into the binary as `GIT_HASH`, for
+// `GET /version`. Builds outside a git checkout, or without git
+// installed, get "unknown".
+//
+// Also generates the `embedded` module's file table from the directory
+// named by `SERVER_APP_EMBED_DIR` (relative to this crate; the test
+// fixtures by default), so a single binary can carry its static files.
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[path = "src/static_files/content_type.rs"]
+mod content_type;
+
+const DEFAULT_EMBED_DIR: &str = "tests/fixtures/embedded";
+
+fn main() {
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=GIT_HASH={}", hash);
+    // Rebuild when the checked-out commit changes.
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let embed_dir = manifest_dir.join(env::var("SERVER_APP_EMBED_DIR").unwrap_or_else(|_| DEFAULT_EMBED_DIR.to_string()));
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("embedded_files.rs");
+    fs::write(&out, embedded_files(&embed_dir).unwrap()).unwrap();
+    println!("cargo:rerun-if-env-changed=SERVER_APP_EMBED_DIR");
+    println!("cargo:rerun-if-changed={}", embed_dir.display());
+}
+
+// The `EMBEDDED_FILES` table for every file below `dir`, sorted by path.
+// A missing directory embeds nothing.
+fn embedded_files(dir: &Path) -> io::Result<String> {
+    let mut files = Vec::new();
+    if dir.is_dir() {
+        collect(dir, "", &mut files)?;
+    }
+    files.sort();
+
+    let mut table = String::from("pub static EMBEDDED_FILES: &[EmbeddedFile] = &[\n");
+    for (path, full) in files {
+        let bytes = fs::read(&full)?;
+        let extension = Path::new(&path).extension().and_then(|e| e.to_str()).unwrap_or("");
+        table.push_str(&format!(
+            "    EmbeddedFile {{ path: {:?}, content_type: {:?}, bytes: include_bytes!({:?}), etag: {:?} }},\n",
+            path,
+            content_type::for_extension(&extension.to_ascii_lowercase()),
+            full.display().to_string(),
+            format!("\"{:016x}\"", fnv1a_64(&bytes)),
+        ));
+    }
+    table.push_str("];\n");
+    Ok(table)
+}
+
+fn collect(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue, // Not reachable by a URL path anyway.
+        };
+        let full = entry.path();
+        let path = format!("{}{}", prefix, name);
+        if full.is_dir() {
+            collect(&full, &format!("{}/", path), files)?;
+        } else if full.is_file() {
+            files.push((path, full));
+        }
+    }
+    Ok(())
+}
+
+// The same hash as `hash::fnv1a_64`, which the build script can't reach.
+fn fnv1a_64(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in data {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
@@ -0,0 +1,271 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    collections::CircularBuffer,
+    favicon,
+    http::{Request, Response},
+    json,
+    log::{self, LogFormat},
+    server::Served,
+    timeutil,
+};
+
+/// How often buffered lines are written out when the log is quiet.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where the access log goes and when it rotates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessLogConfig{
+    pub path: PathBuf,
+    pub max_bytes: u64,     // Rotate once the file grows past this.
+    pub max_files: usize,   // Rotated files kept, `path.1` being the newest; 0 keeps none.
+    pub format: LogFormat,  // Common Log Format lines, or JSON objects.
+    pub log_favicon: bool,  // Whether `/favicon.ico` requests are logged too.
+    pub recent_lines: usize,    // Kept in memory for `AccessLog::recent`; 0 keeps none.
+}
+
+impl AccessLogConfig{
+    pub fn new<P: AsRef<Path>>(path: P) -> AccessLogConfig{
+        AccessLogConfig {
+            path: path.as_ref().to_path_buf(),
+            max_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+            format: LogFormat::Text,
+            log_favicon: true,
+            recent_lines: 100,
+        }
+    }
+}
+
+enum Message{
+    Line(String),
+    Shutdown,
+}
+
+/// An access log written to a file by a thread of its own.
+///
+/// Workers hand lines over a channel, so they never wait on the disk and
+/// lines never interleave. The thread buffers what it gets and writes it
+/// out every second, before rotating, and on `shutdown`. When the file
+/// passes `max_bytes` it becomes `path.1`, older ones move up a number
+/// (`path.1` to `path.2`, and so on, up to `max_files`) and a fresh file is
+/// started.
+///
+/// Trouble writing the log is reported on stdout and otherwise ignored:
+/// lines are lost, requests aren't. The latest `recent_lines` are also
+/// kept in memory, for a status page. Clones share the same thread and
+/// lines.
+#[derive(Clone)]
+pub struct AccessLog{
+    inner: Arc<Inner>,
+}
+
+struct Inner{
+    sender: mpsc::Sender<Message>,
+    format: LogFormat,
+    log_favicon: bool,
+    recent: Mutex<CircularBuffer<String>>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl AccessLog{
+    /// Open (or create) the log file and start its thread.
+    pub fn open(config: AccessLogConfig) -> io::Result<AccessLog>{
+        let file = open_append(&config.path)?;
+        let size = file.metadata()?.len();
+        let (sender, receiver) = mpsc::channel();
+        let (format, log_favicon) = (config.format, config.log_favicon);
+        let recent = Mutex::new(CircularBuffer::new(config.recent_lines));
+        let writer = Writer { config, file: Some(BufWriter::new(file)), size };
+        let thread = thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || writer.run(receiver))?;
+        Ok(AccessLog {
+            inner: Arc::new(Inner { sender, format, log_favicon, recent, thread: Mutex::new(Some(thread)) }),
+        })
+    }
+
+    /// Queue `line`; a newline is added.
+    pub fn log(&self, line: String){
+        // Sent while holding the lock, so the file has lines in the same order.
+        let mut recent = self.inner.recent.lock().unwrap_or_else(|e| e.into_inner());
+        if recent.capacity() > 0{
+            recent.push(line.clone());
+        }
+        let _ = self.inner.sender.send(Message::Line(line));
+    }
+
+    /// The latest lines logged, oldest first, at most `recent_lines` of
+    /// them, whether or not they have reached the file yet.
+    pub fn recent(&self) -> Vec<String>{
+        self.inner.recent.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    /// Queue the line for one answered request, in the configured format;
+    /// see `common_log_line` and `json_log_line`. Favicon requests are
+    /// left out unless `log_favicon` is set.
+    pub fn log_request(&self, peer: Option<SocketAddr>, request: &Request, response: &Response, served: &Served){
+        if !self.inner.log_favicon && favicon::is_favicon_request(request){
+            return;
+        }
+        let now = SystemTime::now();
+        self.log(match self.inner.format{
+            LogFormat::Text => common_log_line(peer, request, response, served, now),
+            LogFormat::Json => json_log_line(peer, request, response, served, now),
+        });
+    }
+
+    /// Write out everything queued so far and stop the thread. Lines
+    /// logged afterwards are dropped.
+    pub fn shutdown(&self){
+        let _ = self.inner.sender.send(Message::Shutdown);
+        let thread = self.inner.thread.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(thread) = thread{
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Inner{
+    fn drop(&mut self){
+        let _ = self.sender.send(Message::Shutdown);
+        if let Some(thread) = self.thread.get_mut().unwrap_or_else(|e| e.into_inner()).take(){
+            let _ = thread.join();
+        }
+    }
+}
+
+/// One line in the Common Log Format, e.g.
+/// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET / HTTP/1.1" 200 2326`,
+/// with the query parameters `log::redactor` names redacted.
+/// When the client went away partway through the response, the size is
+/// the bytes sent until then, head included, and `client-abort` follows.
+pub fn common_log_line(peer: Option<SocketAddr>, request: &Request, response: &Response, served: &Served, time: SystemTime) -> String{
+    let peer = peer.map_or_else(|| "-".to_string(), |peer| peer.ip().to_string());
+    let line = format!(
+        "{} - - [{}] \"{} {} {}\" {} {}",
+        peer, timeutil::format_clf_date(time), request.method, log::redactor().target(request), request.version, response.status,
+        served.client_abort.unwrap_or(response.body.len() as u64),
+    );
+    match served.client_abort{
+        Some(_) => format!("{} client-abort", line),
+        None => line,
+    }
+}
+
+/// One line of JSON with `ts` (RFC 3339), `level`, `request_id`, `method`,
+/// `path`, `status`, `duration_ms`, `bytes`, `client_abort`, `peer` and
+/// `user_agent`; the last two are `null` when unknown. A client that went
+/// away partway makes it a `debug` line, with `bytes` what was sent until
+/// then, head included, and `client_abort` true.
+pub fn json_log_line(peer: Option<SocketAddr>, request: &Request, response: &Response, served: &Served, time: SystemTime) -> String{
+    json::Value::object()
+        .with("ts", timeutil::format_rfc3339(time))
+        .with("level", if served.client_abort.is_some() { log::Level::Debug } else { log::Level::Info }.as_str())
+        .with("request_id", served.id)
+        .with("method", request.method.as_str())
+        .with("path", request.original_path.as_deref().unwrap_or(&request.path))
+        .with("status", response.status)
+        .with("duration_ms", served.duration.as_micros() as f64 / 1000.0)
+        .with("bytes", served.client_abort.unwrap_or(response.body.len() as u64))
+        .with("client_abort", served.client_abort.is_some())
+        .with("peer", peer.map(|peer| peer.ip().to_string()))
+        .with("user_agent", request.header("User-Agent"))
+        .to_string()
+}
+
+struct Writer{
+    config: AccessLogConfig,
+    file: Option<BufWriter<File>>,  // `None` after a failed rotation, until reopening works; lines are dropped meanwhile.
+    size: u64,                      // Bytes in the current file, buffered ones included.
+}
+
+impl Writer{
+    fn run(mut self, receiver: mpsc::Receiver<Message>){
+        loop{
+            match receiver.recv_timeout(FLUSH_INTERVAL){
+                Ok(Message::Line(line)) => self.write_line(&line),
+                Err(mpsc::RecvTimeoutError::Timeout) => self.flush(),
+                Ok(Message::Shutdown) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        self.flush();
+    }
+
+    fn write_line(&mut self, line: &str){
+        let file = match &mut self.file{
+            Some(file) => file,
+            None => return,
+        };
+        if let Err(e) = writeln!(file, "{}", line){
+            log::error(&format!("Could not write the access log: {}", e));
+            return;
+        }
+        self.size += line.len() as u64 + 1;
+        if self.size > self.config.max_bytes{
+            self.rotate();
+        }
+    }
+
+    // Also where a file that couldn't be opened is tried again, so a
+    // broken log complains once a second rather than once a line.
+    fn flush(&mut self){
+        if self.file.is_none(){
+            self.reopen();
+        }
+        if let Some(Err(e)) = self.file.as_mut().map(Write::flush){
+            log::error(&format!("Could not write the access log: {}", e));
+        }
+    }
+
+    // Shift `path.N` up one, move the current file to `path.1` and start
+    // afresh.
+    fn rotate(&mut self){
+        self.flush();
+        self.file = None;
+        let path = &self.config.path;
+        let numbered = |n: usize| {
+            let mut name = path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        let result = if self.config.max_files == 0{
+            fs::remove_file(path)
+        } else {
+            let _ = fs::remove_file(numbered(self.config.max_files));
+            for n in (1..self.config.max_files).rev(){
+                match fs::rename(numbered(n), numbered(n + 1)){
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => log::error(&format!("Could not rotate the access log: {}", e)),
+                    _ => {},
+                }
+            }
+            fs::rename(path, numbered(1))
+        };
+        if let Err(e) = result{
+            log::error(&format!("Could not rotate the access log: {}", e));
+        }
+        self.reopen();
+    }
+
+    fn reopen(&mut self){
+        match open_append(&self.config.path).and_then(|file| Ok((file.metadata()?.len(), file))){
+            Ok((size, file)) => {
+                self.size = size;
+                self.file = Some(BufWriter::new(file));
+            },
+            Err(e) => log::error(&format!("Could not open the access log {}: {}", self.config.path.display(), e)),
+        }
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File>{
+    OpenOptions::new().create(true).append(true).open(path)
+}
@@ -0,0 +1,228 @@
+// A load generator for the server: opens concurrent connections, sends
+// requests over them and reports throughput, latency and errors.
+//
+//     cargo run --release --bin bench_client -- --addr 127.0.0.1:7878 -c 16 -n 1000
+//
+// To compare execution modes, run the same load against the server
+// started with each `--mode` and label the runs, e.g.
+//
+//     cargo run --release --bin main -- --mode thread-per-conn --max-threads 64
+//     cargo run --release --bin bench_client -- -c 64 -n 500 --path /hello --label thread-per-conn
+use std::env;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: bench_client [--addr HOST:PORT] [-c CONNECTIONS] [-n REQUESTS_PER_CONNECTION] [--path PATH] [--keep-alive] [--label NAME]";
+
+struct Options {
+    addr: String,
+    connections: usize,
+    requests: usize,
+    path: String,
+    keep_alive: bool,
+    label: Option<String>, // Printed with the summary, to tell runs apart.
+}
+
+// What one connection's thread measured.
+struct Results {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+fn main() {
+    let options = parse_args(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let connection = if options.keep_alive { "keep-alive" } else { "close" };
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: {}\r\n\r\n", options.path, options.addr, connection);
+
+    let started = Instant::now();
+    let threads: Vec<_> = (0..options.connections)
+        .map(|_| {
+            let addr = options.addr.clone();
+            let request = request.clone().into_bytes();
+            let (requests, keep_alive) = (options.requests, options.keep_alive);
+            thread::spawn(move || run_connection(&addr, &request, requests, keep_alive))
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(options.connections * options.requests);
+    let mut errors = 0;
+    for thread in threads {
+        let results = thread.join().expect("benchmark thread panicked");
+        latencies.extend(results.latencies);
+        errors += results.errors;
+    }
+    let elapsed = started.elapsed();
+
+    print_summary(&options, &mut latencies, errors, elapsed);
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        addr: "127.0.0.1:7878".to_string(),
+        connections: 16,
+        requests: 1000,
+        path: "/".to_string(),
+        keep_alive: false,
+        label: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "--addr" => options.addr = value("--addr")?,
+            "-c" => options.connections = positive(&value("-c")?, "-c")?,
+            "-n" => options.requests = positive(&value("-n")?, "-n")?,
+            "--path" => options.path = value("--path")?,
+            "--keep-alive" | "-k" => options.keep_alive = true,
+            "--label" => options.label = Some(value("--label")?),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn positive(value: &str, name: &str) -> Result<usize, String> {
+    value.parse().ok().filter(|n| *n > 0).ok_or_else(|| format!("{} must be a positive number", name))
+}
+
+// Send `count` requests, reconnecting whenever the connection can't be
+// reused. The read buffer and the latency list are allocated once.
+fn run_connection(addr: &str, request: &[u8], count: usize, keep_alive: bool) -> Results {
+    let mut results = Results { latencies: Vec::with_capacity(count), errors: 0 };
+    let mut buffer = vec![0; 64 * 1024];
+    let mut stream: Option<TcpStream> = None;
+
+    for _ in 0..count {
+        let started = Instant::now();
+        if stream.is_none() {
+            match TcpStream::connect(addr) {
+                Ok(connected) => {
+                    let _ = connected.set_nodelay(true);
+                    let _ = connected.set_read_timeout(Some(Duration::from_secs(30)));
+                    stream = Some(connected);
+                }
+                Err(_) => {
+                    results.errors += 1;
+                    continue;
+                }
+            }
+        }
+        let connection = stream.as_mut().unwrap();
+        match exchange(connection, request, &mut buffer) {
+            Ok(reusable) => {
+                results.latencies.push(started.elapsed());
+                if !(keep_alive && reusable) {
+                    stream = None;
+                }
+            }
+            Err(_) => {
+                results.errors += 1;
+                stream = None;
+            }
+        }
+    }
+    results
+}
+
+// Send one request and read the whole response. Returns whether the
+// connection can carry another request. Non-2xx/3xx statuses count as errors.
+fn exchange(stream: &mut TcpStream, request: &[u8], buffer: &mut [u8]) -> io::Result<bool> {
+    stream.write_all(request)?;
+
+    let mut filled = 0;
+    let head_end = loop {
+        if filled == buffer.len() {
+            return Err(io::Error::other("response head too large"));
+        }
+        let n = stream.read(&mut buffer[filled..])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        filled += n;
+        if let Some(end) = buffer[..filled].windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let head = &buffer[..head_end];
+    let status = head.get(9..12).and_then(|code| std::str::from_utf8(code).ok()).and_then(|code| code.parse::<u16>().ok());
+    if !matches!(status, Some(200..=399)) {
+        return Err(io::Error::other("error status"));
+    }
+    let content_length = header_value(head, b"content-length").and_then(|value| value.parse::<usize>().ok());
+    let close = header_value(head, b"connection").is_some_and(|value| value.eq_ignore_ascii_case("close"));
+
+    let mut body_read = filled - head_end;
+    match content_length {
+        Some(length) => {
+            while body_read < length {
+                let n = stream.read(buffer)?;
+                if n == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                body_read += n;
+            }
+            Ok(!close)
+        }
+        // No length: the body runs to the end of the connection.
+        None => {
+            while stream.read(buffer)? > 0 {}
+            Ok(false)
+        }
+    }
+}
+
+// The value of header `name` (lowercase) in a response head, without
+// allocating.
+fn header_value<'a>(head: &'a [u8], name: &[u8]) -> Option<&'a str> {
+    head.split(|&b| b == b'\n').skip(1).find_map(|line| {
+        let colon = line.iter().position(|&b| b == b':')?;
+        if !line[..colon].eq_ignore_ascii_case(name) {
+            return None;
+        }
+        std::str::from_utf8(&line[colon + 1..]).ok().map(str::trim)
+    })
+}
+
+fn print_summary(options: &Options, latencies: &mut [Duration], errors: usize, elapsed: Duration) {
+    latencies.sort_unstable();
+    let completed = latencies.len();
+    let percentile = |p: f64| -> Duration {
+        if latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * completed as f64).ceil() as usize;
+        latencies[rank.clamp(1, completed) - 1]
+    };
+    let mean = if completed == 0 { Duration::ZERO } else { latencies.iter().sum::<Duration>() / completed as u32 };
+
+    if let Some(label) = &options.label {
+        println!("Run           {}", label);
+    }
+    println!("Target        {}{}", options.addr, options.path);
+    println!("Connections   {} ({})", options.connections, if options.keep_alive { "keep-alive" } else { "new per request" });
+    println!("Requests      {} completed, {} errors", completed, errors);
+    println!("Duration      {:.2?}", elapsed);
+    println!("Throughput    {:.1} requests/s", completed as f64 / elapsed.as_secs_f64());
+    println!();
+    println!("Latency");
+    for (name, value) in [
+        ("mean", mean),
+        ("p50", percentile(50.0)),
+        ("p95", percentile(95.0)),
+        ("p99", percentile(99.0)),
+        ("max", latencies.last().copied().unwrap_or_default()),
+    ] {
+        println!("  {:<11} {:>10.2?}", name, value);
+    }
+}
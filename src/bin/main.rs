@@ -1,77 +1,317 @@
-// The following code imports the necessary modules for TcpListener and TcpStream
-use std::net::TcpListener;
-use std::net::TcpStream;
-use std::io::prelude::*;
+use std::env;
 use std::fs;
-use std::thread;
-use std::time;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
 
 use server_app::ThreadPool;
+use server_app::embedded_assets;
+use server_app::favicon;
+use server_app::http::{self, Request, Response};
+use server_app::idempotency::IdempotencyMiddleware;
+use server_app::info;
+use server_app::log;
+use server_app::metrics::{self, RouteMetrics};
+use server_app::negotiation::{self, ContentNegotiationMiddleware};
+use server_app::proxy::ConnectHandler;
+use server_app::robots;
+use server_app::router::{Router, TraceMiddleware};
+use server_app::server::{self, App, ExecutionMode, RunOptions, ServerConfig};
+use server_app::sse::{self, Event, SseStream};
+use server_app::static_files::StaticFileServer;
+use server_app::templates;
+use server_app::timeutil;
+use server_app::websocket::Message;
 
 // This is the main function.
 fn main() {
-    // Create a new listener bound to localhost at port 7878
-    let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
+    // Settings come from the config file named on the command line, if any.
+    // `--socket-activation` serves on the socket systemd passes in,
+    // `--inherited-fd N` on one handed over by the process restarting, and
+    // `--mode pool|single|thread-per-conn` picks what serves connections
+    // (`--single-thread` is `--mode single`), with `--max-threads N`
+    // capping the threads of `thread-per-conn`.
+    let mut args = env::args().skip(1);
+    let mut options = RunOptions::default();
+    let mut socket_activation = false;
+    let mut max_threads = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket-activation" => socket_activation = true,
+            "--single-thread" => options.mode = ExecutionMode::Single,
+            "--mode" => {
+                let mode = args.next().unwrap_or_else(|| panic!("--mode needs pool, single or thread-per-conn"));
+                options.mode = mode.parse().unwrap_or_else(|e| panic!("{}", e));
+            }
+            "--max-threads" => {
+                let max = args.next().and_then(|max| max.parse().ok()).filter(|max| *max > 0);
+                max_threads = Some(max.unwrap_or_else(|| panic!("--max-threads needs a positive number")));
+            }
+            server::INHERITED_FD_FLAG => {
+                let flag = [arg].into_iter().chain(args.next());
+                options.inherited_fd = server::inherited_fd_arg(flag).unwrap_or_else(|e| panic!("{}", e));
+            }
+            _ => options.config_path = Some(PathBuf::from(arg)),
+        }
+    }
+    let mut config = match &options.config_path {
+        Some(path) => ServerConfig::load(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)),
+        None => ServerConfig::default(),
+    };
+    if socket_activation {
+        config.socket.socket_activation = true;
+    }
+    if let Some(max) = max_threads {
+        match &mut options.mode {
+            ExecutionMode::ThreadPerConnection { max_threads } => *max_threads = Some(max),
+            _ => panic!("--max-threads only applies to --mode thread-per-conn"),
+        }
+    }
+
+    // Listen on localhost at port 7878, unless configured otherwise, until
+    // Ctrl-C or SIGTERM.
+    server::run_server_with(config, options, routes).unwrap_or_else(|e| panic!("{}", e));
+    // A handler still running can't be interrupted, so leave without
+    // waiting for the workers.
+    std::process::exit(0);
+}
+
+// Register every page the server knows how to answer.
+fn routes(app: &App, config: &ServerConfig) -> Router {
+    let (pool, info, connections) = (&app.pool, &app.info, &app.connections);
+    let mut router = Router::new();
+
+    // Handlers that overrun the configured timeout get a 503 instead.
+    router.default_timeout(config.route_timeout);
+
+    // Requests counted and timed per route, first so the times include
+    // the rest of the middleware.
+    let route_metrics = config.route_metrics.then(RouteMetrics::new);
+    if let Some(route_metrics) = &route_metrics {
+        router.middleware(route_metrics.clone());
+    }
+
+    // TRACE echoes the request back, when turned on for debugging.
+    if config.enable_trace {
+        router.middleware(TraceMiddleware::new());
+    }
+
+    // CONNECT opens a tunnel, when this server is meant as a forward proxy.
+    if config.connect_tunnel {
+        router.middleware(ConnectHandler::new());
+    }
+
+    // Pages that pick a representation from Accept get `Vary: Accept`.
+    router.middleware(ContentNegotiationMiddleware::new());
+
+    // Retried POSTs with an Idempotency-Key get the first response again;
+    // the 10,000 most recent keys are remembered.
+    if let Some(ttl) = config.idempotency_ttl {
+        router.middleware(IdempotencyMiddleware::new(10_000, ttl));
+    }
+
+    // Text and JSON go out compressed when the client accepts it.
+    #[cfg(feature = "compression")]
+    router.middleware(server_app::compression::CompressionMiddleware::new());
+
+    // The home page shows how many visits it has had and the server's time.
+    let visits = Arc::new(AtomicU64::new(0));
+    let home_visits = Arc::clone(&visits);
+    router.get("/", move |_: &Request| index_page(&home_visits));
+
+    router.get("/sleep", move |_: &Request| {
+        // Sleep for 5 seconds, unless the server starts shutting down first.
+        let shutdown = server::current_request_context().map(|context| context.shutdown).unwrap_or_default();
+        if shutdown.sleep(Duration::from_secs(5)) {
+            index_page(&visits)
+        } else {
+            Response::new(503, http::reason_phrase(503))
+                .with_header("Connection", "close")
+                .with_body("Woke early: the server is shutting down.")
+        }
+    });
+    router.no_sitemap().pool("slow");
 
-    let pool = ThreadPool::new(4);    
+    // Answer in HTML or JSON, whichever the client's Accept header prefers.
+    router.get("/hello", |request: &Request| {
+        match request.negotiate_content_type(&["text/html", "application/json"]) {
+            Some("application/json") => Response::new(200, "OK")
+                .with_header("Content-Type", "application/json")
+                .with_body(r#"{"message":"Hello!"}"#),
+            Some(_) => Response::new(200, "OK")
+                .with_header("Content-Type", "text/html")
+                .with_body("<h1>Hello!</h1>"),
+            None => Response::new(406, "Not Acceptable"),
+        }
+    });
 
-    // Start listening to incoming connections.
-    // Incoming returns an iterator, meaning we can iterate over all incoming
-    // connections and handle them individually.
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
+    // "Hello" in whichever language the client's Accept-Language prefers.
+    router.get("/greeting", |request: &Request| {
+        const GREETINGS: [(&str, &str); 4] = [("en", "Hello"), ("fr", "Bonjour"), ("es", "Hola"), ("de", "Hallo")];
+        let available: Vec<&str> = GREETINGS.iter().map(|(tag, _)| *tag).collect();
+        let accept = request.header("Accept-Language").unwrap_or("");
+        let locale = negotiation::select_locale(accept, &available).unwrap_or("en");
+        let greeting = GREETINGS.iter().find(|(tag, _)| *tag == locale).map_or("Hello", |(_, greeting)| greeting);
+        Response::new(200, "OK")
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_header("Content-Language", locale)
+            .with_vary("Accept-Language")
+            .with_body(greeting)
+    });
 
-        pool.execute(|| {
-            println!("Hello from the pool!");
-            handle_connection(stream);
-        });  
+    // Which build is running, and since when.
+    if config.version_endpoint {
+        info::register(&mut router, Arc::clone(info));
+        router.no_sitemap();
     }
+
+    // The per-route counts, for Prometheus and as JSON.
+    if let Some(route_metrics) = route_metrics {
+        metrics::register(&mut router, route_metrics);
+    }
+
+    // robots.txt from the configured policy; a robots.txt next to the
+    // pages (in the working directory) wins.
+    if let Some(policy) = &config.robots_policy {
+        robots::register(&mut router, policy.clone(), Some(Path::new(".")));
+        router.no_sitemap();
+    }
+
+    // The favicon browsers ask for, from the working directory if there's
+    // one there and built in otherwise.
+    if let Some(fallback) = config.favicon {
+        favicon::register(&mut router, Some(Path::new(".")), fallback);
+        router.no_sitemap();
+    }
+
+    // Files under /static/: from ./static, built in, or built in with
+    // ./static for the rest, as `static_source` says.
+    let static_files = StaticFileServer::new("static")
+        .with_source(config.static_source)
+        .with_download_extensions(config.download_extensions.clone());
+    router.get("/static/*path", move |request: &Request| static_files.handle(request));
+    router.no_sitemap();
+
+    // A 100,000-row CSV, generated a row at a time as it's sent.
+    router.get("/export.csv", |_: &Request| {
+        let header = std::iter::once(b"id,name,value\n".to_vec());
+        let rows = (1..=100_000u32).map(|id| format!("{},item-{},{}\n", id, id, id.wrapping_mul(7919) % 1000).into_bytes());
+        Response::from_iter(200, "text/csv; charset=utf-8", header.chain(rows))
+            .as_attachment("export.csv")
+    });
+    router.no_sitemap();
+
+    // Readiness for load balancers: stop sending traffic once we're draining.
+    let draining = connections.clone();
+    router.get("/readyz", move |_: &Request| {
+        if draining.is_draining() {
+            Response::new(503, http::reason_phrase(503)).with_body("draining")
+        } else {
+            Response::new(200, "OK").with_body("ready")
+        }
+    });
+    router.no_sitemap();
+
+    // Liveness check for load balancers, in whichever format the client reads.
+    let health_info = Arc::clone(info);
+    router.get("/healthz", move |request: &Request| {
+        match request.negotiate_content_type(&["text/html", "application/json"]) {
+            Some("application/json") => Response::new(200, "OK")
+                .with_header("Content-Type", "application/json")
+                .with_body(format!(r#"{{"status":"ok",{}}}"#, health_info.json_fields())),
+            _ => Response::new(200, "OK")
+                .with_header("Content-Type", "text/html")
+                .with_body("<p>ok</p>"),
+        }
+    });
+    router.no_sitemap();
+
+    // Echo every WebSocket message back to the sender.
+    router.websocket("/ws", |ws| loop {
+        let sent = match ws.read_message() {
+            Ok(Message::Text(text)) => ws.send_text(&text),
+            Ok(Message::Binary(data)) => ws.send_binary(&data),
+            Ok(Message::Close(_)) => break,
+            Err(e) => {
+                log::info(&format!("WebSocket closed: {}", e));
+                break;
+            }
+        };
+        if sent.is_err() {
+            break;
+        }
+    });
+    router.no_sitemap();
+
+    // Stream a counter, one event a second, until the client goes away.
+    let events_pool = Arc::downgrade(pool);
+    router.get("/events", move |_: &Request| {
+        let pool = events_pool.clone();
+        sse::response(move |events| tick(pool.clone(), events, 0))
+    });
+    router.no_sitemap();
+
+    // Anything else gets the 404 page, or a JSON error for API clients.
+    router.fallback(|request: &Request| {
+        match request.negotiate_content_type(&["text/html", "application/json"]) {
+            Some("application/json") => negotiation::status_page(request, 404, "Not Found"),
+            _ => file_response(404, "404.html"),
+        }
+    });
+
+    router
 }
 
-// This function handles the TCP connection streams
-fn handle_connection(mut stream: TcpStream) {
-    // create a buffer to store the contents of the request
-    let mut buffer = [0; 1024];
-    stream.read(&mut buffer).unwrap();
-
-    // Check if the first line of the request starts with "GET / HTTP/1.1"
-    // We will only serve requests such as this one.
-    let get = b"GET / HTTP/1.1\r\n";
-    let sleep = b"GET /sleep HTTP/1.1\r\n";
-
-    // If it does start with the expected string, return index.html file,
-    // else return 404.html file
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK", "index.html")            
+// Send `count`, then schedule the next tick a second later. The chain ends
+// when a send fails because the client disconnected.
+fn tick(pool: Weak<ThreadPool>, events: SseStream, count: u64) {
+    let id = count.to_string();
+    if events.send(&Event::new(id.clone()).with_name("tick").with_id(&id)).is_err() {
+        return;
     }
-    else if buffer.starts_with(sleep){
-        // Sleep for 5 seconds
-        std::thread::sleep(std::time::Duration::from_secs(5));
-        ("HTTP/1.1 200 OK", "index.html")
+    if let Some(pool) = pool.upgrade() {
+        let weak = Arc::downgrade(&pool);
+        pool.execute_after(Duration::from_secs(1), move || tick(weak, events, count + 1));
     }
-    else{
-        ("HTTP/1.1 404 NOT FOUND", "404.html")
-    };
+}
 
-    // print the received request
-    println!("Request: {}", String::from_utf8_lossy(&buffer[..]));
+// Render index.html, counting the visit.
+fn index_page(visits: &AtomicU64) -> Response {
+    let mut vars = HashMap::new();
+    vars.insert("visits", (visits.fetch_add(1, Ordering::Relaxed) + 1).to_string());
+    vars.insert("time", timeutil::format_http_date(SystemTime::now()));
+    match Response::html_template("index.html", &vars) {
+        Ok(response) => response,
+        // Missing or unreadable on disk: render the copy built into the binary.
+        Err(e) => match embedded_assets::get("index.html") {
+            Some(asset) => {
+                let template = String::from_utf8_lossy(asset.bytes);
+                asset.response(200).with_body(templates::render(&template, &vars))
+            }
+            None => {
+                log::error(&format!("index.html: {}", e));
+                Response::new(500, http::reason_phrase(500))
+            }
+        },
+    }
+}
 
+// Build a response whose body is the contents of `filename`, or the copy
+// built into the binary when the file is missing.
+fn file_response(status: u16, filename: &str) -> Response {
     // Read the contents of file specified by filename variable
     // This should contain HTML that the client requested for.
-    let contents = fs::read_to_string(filename).unwrap();
-
-    // Generate HTTP response headers for the client, which include:
-    //   - status line
-    //   - content length
-    //   - and blank line to separate headers from body 
-    let response = format!("{}\r\nContent-Length: {}\r\n\r\n{}", 
-    status_line, 
-    contents.len(),
-    contents);
-
-    // Send the response to the stream (i.e. send it back to the client)
-    stream.write(response.as_bytes()).unwrap();
-    
-    // Flush the output stream.
-    stream.flush().unwrap();
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => match embedded_assets::get(filename) {
+            Some(asset) => return asset.response(status),
+            None => panic!("{}: {}", filename, e),
+        },
+    };
+
+    Response::new(status, http::reason_phrase(status))
+        .with_header("Content-Type", "text/html")
+        .with_body(contents)
 }
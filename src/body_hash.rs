@@ -0,0 +1,31 @@
+use crate::{
+    hash,
+    http::{Request, Response},
+    router::{Middleware, Next},
+};
+
+/// Works out the SHA-256 of every request body, for handlers that check
+/// signatures or spot duplicate submissions; they read it with
+/// `Request::body_hash`, as lowercase hex.
+///
+/// The server has read the whole body by the time middleware runs, so it
+/// is hashed where it lies, without a copy, and is still there for the
+/// handler to read. Requests without a body are left alone, as are ones
+/// already hashed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BodyHashMiddleware;
+
+impl BodyHashMiddleware{
+    pub fn new() -> BodyHashMiddleware{
+        BodyHashMiddleware
+    }
+}
+
+impl Middleware for BodyHashMiddleware{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        if !request.body.is_empty() && request.body_hash().is_none(){
+            request.set_body_hash(hash::to_hex(&hash::sha256(&request.body)));
+        }
+        next.run(request)
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    config::{Config, ConfigError},
+    http::{Request, Response},
+    router::{Middleware, Next},
+};
+
+/// Status codes whose responses may be stored.
+const CACHEABLE_STATUS: [u16; 3] = [200, 301, 404];
+
+/// Settings for a `ResponseCache`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheConfig{
+    pub max_bytes: usize,               // Bodies plus headers; least recently used entries go first.
+    pub default_ttl: Duration,          // Used when a response has no `max-age`.
+    pub vary_headers: Vec<String>,      // Request headers that become part of the key.
+}
+
+impl CacheConfig{
+    /// Read `max_bytes`, `default_ttl_secs` and `vary` from `section` of `config`.
+    pub fn from_config(config: &Config, section: &str) -> Result<CacheConfig, ConfigError>{
+        let key = |name: &str| format!("{}.{}", section, name);
+        let mut cache = CacheConfig::default();
+
+        if let Some(n) = config.get_int(&key("max_bytes"))?{
+            cache.max_bytes = usize::try_from(n)
+                .map_err(|_| ConfigError::invalid(&key("max_bytes"), "must not be negative"))?;
+        }
+        if let Some(secs) = config.get_int(&key("default_ttl_secs"))?{
+            let secs = u64::try_from(secs)
+                .map_err(|_| ConfigError::invalid(&key("default_ttl_secs"), "must not be negative"))?;
+            cache.default_ttl = Duration::from_secs(secs);
+        }
+        if let Some(vary) = config.get_str_array(&key("vary"))?{
+            cache.vary_headers = vary;
+        }
+        Ok(cache)
+    }
+}
+
+impl Default for CacheConfig{
+    fn default() -> CacheConfig{
+        CacheConfig {
+            max_bytes: 16 * 1024 * 1024,
+            default_ttl: Duration::from_secs(60),
+            vary_headers: Vec::new(),
+        }
+    }
+}
+
+struct Entry{
+    response: Response,
+    stored_at: Instant,
+    ttl: Duration,
+    size: usize,
+    last_used: u64,     // Key into `Entries::recency`.
+}
+
+#[derive(Default)]
+struct Entries{
+    by_key: HashMap<String, Entry>,
+    recency: BTreeMap<u64, String>,     // Oldest use first.
+    tick: u64,
+    total_bytes: usize,
+}
+
+impl Entries{
+    fn touch(&mut self, key: &str){
+        self.tick += 1;
+        if let Some(entry) = self.by_key.get_mut(key){
+            self.recency.remove(&entry.last_used);
+            entry.last_used = self.tick;
+            self.recency.insert(self.tick, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str){
+        if let Some(entry) = self.by_key.remove(key){
+            self.recency.remove(&entry.last_used);
+            self.total_bytes -= entry.size;
+        }
+    }
+}
+
+/// Stored responses keyed by method, target and selected request headers.
+pub struct ResponseCache{
+    entries: Mutex<Entries>,
+    config: CacheConfig,
+    clock: Arc<dyn Clock>,
+}
+
+impl ResponseCache{
+    pub fn new(config: CacheConfig) -> ResponseCache{
+        ResponseCache::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Create a cache that judges expiry with `clock`.
+    pub fn with_clock(config: CacheConfig, clock: Arc<dyn Clock>) -> ResponseCache{
+        ResponseCache {
+            entries: Mutex::new(Entries::default()),
+            config,
+            clock,
+        }
+    }
+
+    /// The key `request` is stored under. `HEAD` shares entries with `GET`.
+    pub fn key(&self, request: &Request) -> String{
+        let method = if request.method == "HEAD" { "GET" } else { request.method.as_str() };
+        let mut key = format!("{} {}", method, request.target());
+        for name in &self.config.vary_headers{
+            key.push('\n');
+            key.push_str(&name.to_ascii_lowercase());
+            key.push(':');
+            key.push_str(request.header(name).unwrap_or(""));
+        }
+        key
+    }
+
+    /// Look up a fresh response, with `Age` set. Expired entries are dropped.
+    pub fn get(&self, key: &str) -> Option<Response>{
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+
+        let entry = entries.by_key.get(key)?;
+        let age = now.saturating_duration_since(entry.stored_at);
+        if age >= entry.ttl{
+            entries.remove(key);
+            return None;
+        }
+
+        let response = entry.response.clone().with_header("Age", &age.as_secs().to_string());
+        entries.touch(key);
+        Some(response)
+    }
+
+    pub fn contains(&self, key: &str) -> bool{
+        self.entries.lock().unwrap().by_key.contains_key(key)
+    }
+
+    /// Store `response` for `ttl`, evicting least recently used entries to
+    /// stay within `max_bytes`. Responses bigger than the whole cache are
+    /// not stored.
+    pub fn insert(&self, key: &str, response: Response, ttl: Duration){
+        let size = key.len()
+            + response.body.len()
+            + response.headers.iter().map(|(n, v)| n.len() + v.len()).sum::<usize>();
+        if size > self.config.max_bytes{
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(key);
+        while entries.total_bytes + size > self.config.max_bytes{
+            let oldest = match entries.recency.first_key_value(){
+                Some((_, k)) => k.clone(),
+                None => break,
+            };
+            entries.remove(&oldest);
+        }
+
+        entries.total_bytes += size;
+        entries.by_key.insert(key.to_string(), Entry {
+            response,
+            stored_at: self.clock.now(),
+            ttl,
+            size,
+            last_used: 0,
+        });
+        entries.touch(key);
+    }
+
+    pub fn len(&self) -> usize{
+        self.entries.lock().unwrap().by_key.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    /// How long `response` may be stored for, or `None` if it must not be.
+    pub(crate) fn ttl_for(&self, response: &Response) -> Option<Duration>{
+        if !CACHEABLE_STATUS.contains(&response.status) || response.stream.is_some(){
+            return None;        // Streamed bodies are never buffered, so there is nothing to keep.
+        }
+        let headers = &response.headers;
+        if ["no-store", "no-cache", "private"].iter().any(|d| headers.has_token("Cache-Control", d)){
+            return None;
+        }
+
+        // Every header the response varies on must be part of our key.
+        for field in headers.get_all("Vary").flat_map(|v| v.split(',')).map(str::trim){
+            let keyed = self.config.vary_headers.iter().any(|h| h.eq_ignore_ascii_case(field));
+            if field == "*" || !keyed{
+                return None;
+            }
+        }
+
+        let max_age = headers.get_all("Cache-Control")
+            .flat_map(|v| v.split(','))
+            .filter_map(|d| d.trim().strip_prefix("max-age="))
+            .find_map(|secs| secs.trim_matches('"').parse::<u64>().ok());
+        match max_age{
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(self.config.default_ttl),
+        }
+    }
+}
+
+/// A map of at most `capacity` entries that each expire `ttl` after
+/// they're inserted. Inserting into a full cache drops the least recently
+/// used entry.
+pub struct LruCache<K, V>{
+    entries: HashMap<K, (V, Instant, u64)>,    // Value, when stored, and key into `recency`.
+    recency: BTreeMap<u64, K>,                  // Oldest use first.
+    tick: u64,
+    capacity: usize,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V>{
+    pub fn new(capacity: usize, ttl: Duration) -> LruCache<K, V>{
+        LruCache::with_clock(capacity, ttl, Arc::new(SystemClock))
+    }
+
+    /// A cache that judges expiry with `clock`.
+    pub fn with_clock(capacity: usize, ttl: Duration, clock: Arc<dyn Clock>) -> LruCache<K, V>{
+        LruCache {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            capacity: capacity.max(1),
+            ttl,
+            clock,
+        }
+    }
+
+    /// The value under `key`, if it hasn't expired, marking it used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        let now = self.clock.now();
+        let (_, stored_at, last_used) = self.entries.get(key)?;
+        let last_used = *last_used;
+        if now.saturating_duration_since(*stored_at) >= self.ttl{
+            self.remove(key);
+            return None;
+        }
+
+        self.tick += 1;
+        let owned = self.recency.remove(&last_used)?;
+        self.recency.insert(self.tick, owned);
+        let entry = self.entries.get_mut(key)?;
+        entry.2 = self.tick;
+        Some(&entry.0)
+    }
+
+    /// Store `value` under `key`, replacing any value there already.
+    pub fn insert(&mut self, key: K, value: V){
+        self.remove(&key);
+        if self.entries.len() >= self.capacity{
+            if let Some((_, oldest)) = self.recency.pop_first(){
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.clock.now(), self.tick));
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        let (value, _, last_used) = self.entries.remove(key)?;
+        self.recency.remove(&last_used);
+        Some(value)
+    }
+
+    /// Entries held, counting any that have expired but not been looked up since.
+    pub fn len(&self) -> usize{
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.entries.is_empty()
+    }
+}
+
+/// Serves repeated `GET`/`HEAD` requests from a `ResponseCache`.
+///
+/// Responses get `X-Cache: HIT` or `X-Cache: MISS`; hits also carry
+/// `Age`. Requests sent with `Cache-Control: no-store` skip the cache.
+pub struct CacheMiddleware{
+    cache: Arc<ResponseCache>,
+}
+
+impl CacheMiddleware{
+    pub fn new(cache: Arc<ResponseCache>) -> CacheMiddleware{
+        CacheMiddleware { cache }
+    }
+
+    pub fn cache(&self) -> &ResponseCache{
+        &self.cache
+    }
+}
+
+impl Middleware for CacheMiddleware{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        let cacheable_method = request.method == "GET" || request.method == "HEAD";
+        if !cacheable_method || request.headers.has_token("Cache-Control", "no-store"){
+            return next.run(request);
+        }
+
+        let key = self.cache.key(request);
+        if let Some(response) = self.cache.get(&key){
+            return response.with_header("X-Cache", "HIT");
+        }
+
+        let response = next.run(request);
+        if let Some(ttl) = self.cache.ttl_for(&response){
+            self.cache.insert(&key, response.clone(), ttl);
+        }
+        response.with_header("X-Cache", "MISS")
+    }
+}
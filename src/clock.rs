@@ -0,0 +1,46 @@
+use std::{sync::Mutex, time::{Duration, Instant}};
+
+/// A source of the current time, so time-dependent code can be driven by
+/// hand instead of by sleeping.
+pub trait Clock: Send + Sync{
+    fn now(&self) -> Instant;
+}
+
+/// The real monotonic clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock{
+    fn now(&self) -> Instant{
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when `advance` is called.
+#[derive(Debug)]
+pub struct MockClock{
+    now: Mutex<Instant>,
+}
+
+impl MockClock{
+    pub fn new() -> MockClock{
+        MockClock { now: Mutex::new(Instant::now()) }
+    }
+
+    /// Move the clock forward by `by`.
+    pub fn advance(&self, by: Duration){
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Default for MockClock{
+    fn default() -> MockClock{
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock{
+    fn now(&self) -> Instant{
+        *self.now.lock().unwrap()
+    }
+}
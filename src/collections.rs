@@ -0,0 +1,127 @@
+// Containers std doesn't have.
+use std::{fmt, iter::Chain, slice};
+
+mod bloom;
+
+pub use bloom::{BloomFilter, RotatingBloomFilter};
+
+/// A queue of at most `capacity` items that makes room for a new one by
+/// dropping the oldest, so its memory never grows past what it was given.
+///
+/// The storage is allocated up front and then reused in a ring. A
+/// capacity of 0 keeps nothing.
+#[derive(Clone)]
+pub struct CircularBuffer<T>{
+    items: Vec<T>,      // Filled up to `capacity`, then overwritten in turn.
+    start: usize,       // Where the oldest item is, once full.
+    capacity: usize,
+}
+
+impl<T> CircularBuffer<T>{
+    pub fn new(capacity: usize) -> CircularBuffer<T>{
+        CircularBuffer { items: Vec::with_capacity(capacity), start: 0, capacity }
+    }
+
+    /// Add `item` as the newest, returning the oldest one if it had to go
+    /// to make room (or `item` itself, with a capacity of 0).
+    pub fn push(&mut self, item: T) -> Option<T>{
+        if self.capacity == 0{
+            return Some(item);
+        }
+        if self.items.len() < self.capacity{
+            self.items.push(item);
+            return None;
+        }
+        let oldest = std::mem::replace(&mut self.items[self.start], item);
+        self.start = (self.start + 1) % self.capacity;
+        Some(oldest)
+    }
+
+    /// The items, oldest first.
+    pub fn iter(&self) -> Iter<'_, T>{
+        let (newer, older) = self.items.split_at(self.start);
+        Iter(older.iter().chain(newer.iter()))
+    }
+
+    /// The `index`th oldest item.
+    pub fn get(&self, index: usize) -> Option<&T>{
+        if index >= self.items.len(){
+            return None;
+        }
+        self.items.get((self.start + index) % self.items.len())
+    }
+
+    pub fn newest(&self) -> Option<&T>{
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    pub fn len(&self) -> usize{
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.items.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.items.len() == self.capacity
+    }
+
+    pub fn capacity(&self) -> usize{
+        self.capacity
+    }
+
+    /// Drop every item, keeping the storage.
+    pub fn clear(&mut self){
+        self.items.clear();
+        self.start = 0;
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CircularBuffer<T>{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Equal when they hold equal items in the same order, wherever the ring
+// happens to start.
+impl<T: PartialEq> PartialEq for CircularBuffer<T>{
+    fn eq(&self, other: &CircularBuffer<T>) -> bool{
+        self.capacity == other.capacity && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for CircularBuffer<T> {}
+
+impl<'a, T> IntoIterator for &'a CircularBuffer<T>{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T>{
+        self.iter()
+    }
+}
+
+/// The items of a `CircularBuffer`, oldest first.
+pub struct Iter<'a, T>(Chain<slice::Iter<'a, T>, slice::Iter<'a, T>>);
+
+impl<'a, T> Iterator for Iter<'a, T>{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T>{
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>){
+        self.0.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T>{
+    fn next_back(&mut self) -> Option<Self::Item>{
+        self.0.next_back()
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
@@ -0,0 +1,162 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::clock::{Clock, SystemClock};
+
+/// A set that answers "definitely not seen" or "probably seen" in a fixed
+/// amount of memory.
+///
+/// Sized for `expected_items` at a chosen false-positive rate: past that
+/// many items the rate climbs. Items can't be removed, only all cleared.
+pub struct BloomFilter<T: ?Sized>{
+    bits: Vec<u64>,
+    num_bits: u64,
+    hashes: u32,        // Bits set per item.
+    len: usize,         // Items inserted, counting repeats.
+    item: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash + ?Sized> BloomFilter<T>{
+    /// A filter for `expected_items` that wrongly reports an unseen item
+    /// as seen with probability `false_positive_rate` (between 0 and 1,
+    /// exclusive) once that many are in.
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> BloomFilter<T>{
+        let n = expected_items.max(1) as f64;
+        let p = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-n * p.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let hashes = ((num_bits as f64 / n) * ln2).round().clamp(1.0, 32.0) as u32;
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            hashes,
+            len: 0,
+            item: PhantomData,
+        }
+    }
+
+    pub fn insert(&mut self, item: &T){
+        for bit in self.bit_indexes(item){
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.len += 1;
+    }
+
+    /// `false` if `item` was never inserted; `true` if it probably was.
+    pub fn contains(&self, item: &T) -> bool{
+        self.bit_indexes(item).all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Insert `item`, returning whether it was probably there already.
+    pub fn check_and_insert(&mut self, item: &T) -> bool{
+        let seen = self.contains(item);
+        if !seen{
+            self.insert(item);
+        }
+        seen
+    }
+
+    pub fn clear(&mut self){
+        self.bits.iter_mut().for_each(|word| *word = 0);
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize{
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len == 0
+    }
+
+    /// Memory taken by the bits, in bytes.
+    pub fn size_bytes(&self) -> usize{
+        self.bits.len() * 8
+    }
+
+    // Double hashing: bit i is h1 + i * h2, from two differently seeded
+    // hashes of the item.
+    fn bit_indexes(&self, item: &T) -> impl Iterator<Item = u64>{
+        let hash = |seed: u8| {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            item.hash(&mut hasher);
+            hasher.finish()
+        };
+        let (h1, h2) = (hash(0), hash(1) | 1);
+        let num_bits = self.num_bits;
+        (0..u64::from(self.hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+/// A `BloomFilter` that forgets items after a while, for things such as
+/// nonces that must not be reused within their lifetime.
+///
+/// Items go into the current filter; every `ttl` it becomes the previous
+/// one and the one before is dropped. Lookups check both, so an item is
+/// remembered for at least `ttl` and at most twice that, in the memory of
+/// two filters.
+pub struct RotatingBloomFilter<T: ?Sized>{
+    current: BloomFilter<T>,
+    previous: BloomFilter<T>,
+    ttl: Duration,
+    rotated_at: Instant,
+    clock: Arc<dyn Clock>,
+}
+
+impl<T: Hash + ?Sized> RotatingBloomFilter<T>{
+    /// Filters sized for `expected_items` per `ttl`; see `BloomFilter::new`.
+    pub fn new(expected_items: usize, false_positive_rate: f64, ttl: Duration) -> RotatingBloomFilter<T>{
+        RotatingBloomFilter::with_clock(expected_items, false_positive_rate, ttl, Arc::new(SystemClock))
+    }
+
+    /// A filter that judges when to rotate by `clock`.
+    pub fn with_clock(expected_items: usize, false_positive_rate: f64, ttl: Duration, clock: Arc<dyn Clock>) -> RotatingBloomFilter<T>{
+        RotatingBloomFilter {
+            current: BloomFilter::new(expected_items, false_positive_rate),
+            previous: BloomFilter::new(expected_items, false_positive_rate),
+            ttl,
+            rotated_at: clock.now(),
+            clock,
+        }
+    }
+
+    pub fn insert(&mut self, item: &T){
+        self.rotate_if_due();
+        self.current.insert(item);
+    }
+
+    pub fn contains(&mut self, item: &T) -> bool{
+        self.rotate_if_due();
+        self.current.contains(item) || self.previous.contains(item)
+    }
+
+    /// Insert `item`, returning whether it was probably seen within the
+    /// last `ttl` or so.
+    pub fn check_and_insert(&mut self, item: &T) -> bool{
+        let seen = self.contains(item);
+        if !seen{
+            self.current.insert(item);
+        }
+        seen
+    }
+
+    fn rotate_if_due(&mut self){
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.rotated_at);
+        if elapsed < self.ttl{
+            return;
+        }
+        std::mem::swap(&mut self.current, &mut self.previous);
+        self.current.clear();
+        if elapsed >= self.ttl * 2{
+            self.previous.clear();      // Idle long enough that both are stale.
+        }
+        self.rotated_at = now;
+    }
+}
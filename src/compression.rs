@@ -0,0 +1,599 @@
+use crate::{
+    hash,
+    http::{Request, Response},
+    router::{Middleware, Next},
+};
+
+/// A content coding this server can produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding{
+    Brotli,
+    Gzip,
+    Deflate,    // The zlib format, which is what `deflate` means in HTTP.
+}
+
+impl Encoding{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    pub fn encode(&self, data: &[u8]) -> Vec<u8>{
+        match self{
+            Encoding::Brotli => brotli(data),
+            Encoding::Gzip => gzip(data),
+            Encoding::Deflate => zlib(data),
+        }
+    }
+}
+
+/// Pick the encoding to use for an `Accept-Encoding` value, or `None` to
+/// send the body as it is: the first of `negotiate_encodings`.
+pub fn negotiate_encoding(accept_encoding: &str) -> Option<Encoding>{
+    negotiate_encodings(accept_encoding).into_iter().next()
+}
+
+/// Every encoding an `Accept-Encoding` value allows, best first.
+///
+/// The client's quality values decide; on a tie the server's preference
+/// applies, which is the order `br`, `gzip`, `deflate`. `*` stands for
+/// any coding not listed, and `q=0` rules a coding out.
+pub fn negotiate_encodings(accept_encoding: &str) -> Vec<Encoding>{
+    let mut listed: Vec<(String, f32)> = Vec::new();
+    for item in accept_encoding.split(',').map(str::trim).filter(|item| !item.is_empty()){
+        let mut params = item.split(';').map(str::trim);
+        let coding = params.next().unwrap_or("").to_ascii_lowercase();
+        let q = params
+            .filter_map(|p| p.strip_prefix("q=").or_else(|| p.strip_prefix("Q=")))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0)
+            .clamp(0.0, 1.0);
+        listed.push((coding, q));
+    }
+
+    let star = listed.iter().find(|(coding, _)| coding == "*").map(|(_, q)| *q);
+    let q_for = |encoding: Encoding| -> f32{
+        let aliases: &[&str] = match encoding{
+            Encoding::Brotli => &["br"],
+            Encoding::Gzip => &["gzip", "x-gzip"],
+            Encoding::Deflate => &["deflate"],
+        };
+        listed.iter()
+            .find(|(coding, _)| aliases.contains(&coding.as_str()))
+            .map(|(_, q)| *q)
+            .or(star)
+            .unwrap_or(0.0)
+    };
+
+    let mut acceptable: Vec<(Encoding, f32)> = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate]
+        .into_iter()
+        .map(|encoding| (encoding, q_for(encoding)))
+        .filter(|(_, q)| *q > 0.0)
+        .collect();
+    // Stable, so equal quality values keep the server's order.
+    acceptable.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    acceptable.into_iter().map(|(encoding, _)| encoding).collect()
+}
+
+/// Compresses text and JSON responses with whichever encoding the client
+/// prefers (see `negotiate_encodings`) that makes the body smaller.
+///
+/// Only buffered bodies of at least `min_size` bytes with a `text/*` or
+/// `application/json` type are compressed, and not ones that already
+/// have a `Content-Encoding`. Every response of a compressible type gets
+/// `Vary: Accept-Encoding`, compressed or not, since either way the
+/// choice depended on the header. A compressed response's `ETag` gets
+/// the encoding appended, because it is a different representation.
+pub struct CompressionMiddleware{
+    min_size: usize,
+}
+
+impl CompressionMiddleware{
+    /// Compress bodies of 256 bytes and up; smaller ones rarely shrink.
+    pub fn new() -> CompressionMiddleware{
+        CompressionMiddleware { min_size: 256 }
+    }
+
+    pub fn with_min_size(mut self, min_size: usize) -> CompressionMiddleware{
+        self.min_size = min_size;
+        self
+    }
+}
+
+impl Default for CompressionMiddleware{
+    fn default() -> CompressionMiddleware{
+        CompressionMiddleware::new()
+    }
+}
+
+impl Middleware for CompressionMiddleware{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        let mut response = next.run(request);
+        if !compressible_type(response.header("Content-Type").unwrap_or("")){
+            return response;
+        }
+        response.vary("Accept-Encoding");
+
+        let skip = response.stream.is_some()
+            || response.upgrade.is_some()
+            || response.headers.contains("Content-Encoding")
+            || response.body.len() < self.min_size
+            || matches!(response.status, 204 | 206 | 304);
+        if skip{
+            return response;
+        }
+        // Data that is already dense can come out bigger.
+        let encoded = negotiate_encodings(request.header("Accept-Encoding").unwrap_or(""))
+            .into_iter()
+            .map(|encoding| (encoding, encoding.encode(&response.body)))
+            .find(|(_, encoded)| encoded.len() < response.body.len());
+        let (encoding, encoded) = match encoded{
+            Some(encoded) => encoded,
+            None => return response,
+        };
+        response.body = encoded;
+        response.headers.set("Content-Encoding", encoding.as_str());
+        if let Some(etag) = response.header("ETag").map(str::to_string){
+            let tagged = match etag.strip_suffix('"'){
+                Some(open) => format!("{}-{}\"", open, encoding.as_str()),
+                None => etag,
+            };
+            response.headers.set("ETag", &tagged);
+        }
+        response
+    }
+}
+
+fn compressible_type(content_type: &str) -> bool{
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    media_type.starts_with("text/") || media_type == "application/json"
+}
+
+/// `data` in the brotli format (RFC 7932), compressed: the same LZ77
+/// matching as `deflate`, in meta-blocks of up to 16M that each carry
+/// their own prefix codes built from the symbol counts. There is one
+/// block type of each kind and no static dictionary, which keeps it
+/// short at some cost in ratio next to a full encoder.
+pub fn brotli(data: &[u8]) -> Vec<u8>{
+    let mut bits = BitWriter::default();
+    bits.write(0, 1);       // WBITS 16; matches reach back 32K at most.
+    let mut blocks = data.chunks(1 << 24).peekable();
+    while let Some(block) = blocks.next(){
+        brotli_meta_block(&mut bits, block, blocks.peek().is_none());
+    }
+    if data.is_empty(){
+        bits.write(1, 1);   // ISLAST,
+        bits.write(1, 1);   // ISLASTEMPTY.
+    }
+    bits.finish()
+}
+
+const INSERT_BASE: [u32; 24] = [
+    0, 1, 2, 3, 4, 5, 6, 8, 10, 14, 18, 26, 34, 50, 66, 98, 130, 194, 322, 578, 1090, 2114, 6210, 22594,
+];
+const INSERT_EXTRA: [u8; 24] = [0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 12, 14, 24];
+const COPY_BASE: [u32; 24] = [
+    2, 3, 4, 5, 6, 7, 8, 9, 10, 12, 14, 18, 22, 30, 38, 54, 70, 102, 134, 198, 326, 582, 1094, 2118,
+];
+const COPY_EXTRA: [u8; 24] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 24];
+// The order code lengths of the code length alphabet are sent in.
+const CODE_LENGTH_ORDER: [usize; 18] = [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+// An insert-and-copy command: `literals` bytes as they are, then `copy`
+// bytes from `distance` back, if `copy` is not zero.
+struct Command{
+    literals: std::ops::Range<usize>,
+    copy: usize,
+    distance: usize,
+}
+
+impl Command{
+    // The insert-and-copy symbol and its extra bits, always with an
+    // explicit distance. A command that only inserts, which can only be
+    // the last, still needs a copy length; the decoder stops before it.
+    fn symbol(&self) -> (u16, [(u32, u8); 2]){
+        let insert = self.literals.len() as u32;
+        let copy = self.copy.max(2) as u32;
+        let insert_code = INSERT_BASE.iter().rposition(|&base| base <= insert).unwrap_or(0);
+        let copy_code = COPY_BASE.iter().rposition(|&base| base <= copy).unwrap_or(0);
+        let cell = match (insert_code / 8, copy_code / 8){
+            (0, 0) => 128,
+            (0, 1) => 192,
+            (0, _) => 384,
+            (1, 0) => 256,
+            (1, 1) => 320,
+            (1, _) => 512,
+            (_, 0) => 448,
+            (_, 1) => 576,
+            (_, _) => 640,
+        };
+        let symbol = cell + ((insert_code as u16 & 7) << 3) + (copy_code as u16 & 7);
+        let extra = [
+            (insert - INSERT_BASE[insert_code], INSERT_EXTRA[insert_code]),
+            (copy - COPY_BASE[copy_code], COPY_EXTRA[copy_code]),
+        ];
+        (symbol, extra)
+    }
+}
+
+// The distance symbol and its extra bits, with no postfix or direct codes.
+fn distance_symbol(distance: usize) -> (u16, u32, u8){
+    let x = distance as u32 + 3;
+    let extra_bits = 31 - x.leading_zeros() - 1;
+    let high = (x >> extra_bits) & 1;
+    let symbol = 16 + 2 * (extra_bits - 1) + high;
+    (symbol as u16, x - ((2 + high) << extra_bits), extra_bits as u8)
+}
+
+fn brotli_meta_block(bits: &mut BitWriter, data: &[u8], last: bool){
+    let mut commands = Vec::new();
+    let mut start = 0;
+    let mut at = 0;
+    for token in lz77(data){
+        match token{
+            Token::Literal(_) => at += 1,
+            Token::Match { len, dist } => {
+                commands.push(Command { literals: start..at, copy: len, distance: dist });
+                at += len;
+                start = at;
+            }
+        }
+    }
+    if start < data.len(){
+        commands.push(Command { literals: start..data.len(), copy: 0, distance: 0 });
+    }
+
+    let mut literal_counts = vec![0u32; 256];
+    let mut command_counts = vec![0u32; 704];
+    let mut distance_counts = vec![0u32; 64];
+    for command in &commands{
+        for &byte in &data[command.literals.clone()]{
+            literal_counts[usize::from(byte)] += 1;
+        }
+        command_counts[usize::from(command.symbol().0)] += 1;
+        if command.copy > 0{
+            distance_counts[usize::from(distance_symbol(command.distance).0)] += 1;
+        }
+    }
+
+    bits.write(u32::from(last), 1);     // ISLAST,
+    if last{
+        bits.write(0, 1);               // ISLASTEMPTY.
+    }
+    let len = data.len() as u32 - 1;
+    let nibbles = if len < 1 << 16 { 4 } else if len < 1 << 20 { 5 } else { 6 };
+    bits.write(nibbles - 4, 2);
+    bits.write(len, nibbles as u8 * 4);
+    if !last{
+        bits.write(0, 1);               // ISUNCOMPRESSED.
+    }
+    bits.write(0, 3);       // One block type each for literals, commands and distances.
+    bits.write(0, 6);       // NPOSTFIX and NDIRECT 0.
+    bits.write(0, 2);       // Context mode; with one tree it doesn't matter.
+    bits.write(0, 2);       // One literal tree and one distance tree.
+    let literal_code = write_prefix_code(bits, &literal_counts, 8);
+    let command_code = write_prefix_code(bits, &command_counts, 10);
+    let distance_code = write_prefix_code(bits, &distance_counts, 6);
+
+    for command in &commands{
+        let (symbol, extra) = command.symbol();
+        write_symbol(bits, &command_code, usize::from(symbol));
+        for (value, len) in extra{
+            bits.write(value, len);
+        }
+        for &byte in &data[command.literals.clone()]{
+            write_symbol(bits, &literal_code, usize::from(byte));
+        }
+        if command.copy > 0{
+            let (symbol, value, len) = distance_symbol(command.distance);
+            write_symbol(bits, &distance_code, usize::from(symbol));
+            bits.write(value, len);
+        }
+    }
+}
+
+// Codes and lengths, by symbol.
+type PrefixCode = Vec<(u16, u8)>;
+
+fn write_symbol(bits: &mut BitWriter, code: &PrefixCode, symbol: usize){
+    let (code, len) = code[symbol];
+    if len > 0{
+        bits.write_code(code, len);
+    }
+}
+
+// Writes the prefix code for `counts` and returns it. A single symbol
+// gets a simple prefix code, which spends no bits on it; anything more
+// gets the complex form, with the code lengths written one by one.
+fn write_prefix_code(bits: &mut BitWriter, counts: &[u32], alphabet_bits: u8) -> PrefixCode{
+    let used: Vec<usize> = (0..counts.len()).filter(|&symbol| counts[symbol] > 0).collect();
+    if used.len() <= 1{
+        bits.write(1, 2);       // Simple,
+        bits.write(0, 2);       // with one symbol.
+        bits.write(used.first().copied().unwrap_or(0) as u32, alphabet_bits);
+        return vec![(0, 0); counts.len()];
+    }
+
+    let lengths = huffman_lengths(counts, 15);
+    let mut length_counts = [0u32; 18];
+    let last = used[used.len() - 1];
+    for &len in &lengths[..=last]{
+        length_counts[usize::from(len)] += 1;
+    }
+    let mut length_lengths = huffman_lengths(&length_counts, 5);
+    let mut length_code = canonical_code(&length_lengths);
+    if let [single] = (0..18).filter(|&len| length_counts[len] > 0).collect::<Vec<_>>()[..]{
+        // Every symbol up to the last has the same code length, so each
+        // takes no bits; any non-zero length for it says so.
+        length_lengths[single] = 1;
+        length_code = vec![(0, 0); 18];
+    }
+
+    bits.write(0, 2);       // Complex, with no code lengths skipped.
+    let mut space = 32;
+    for &symbol in &CODE_LENGTH_ORDER{
+        // The fixed code for the code lengths of the code length alphabet.
+        let (value, len) = match length_lengths[symbol]{
+            0 => (0b00, 2),
+            1 => (0b0111, 4),
+            2 => (0b011, 3),
+            3 => (0b10, 2),
+            4 => (0b01, 2),
+            _ => (0b1111, 4),
+        };
+        bits.write(value, len);
+        if length_lengths[symbol] > 0{
+            space -= 32 >> length_lengths[symbol];
+            if space <= 0{
+                break;
+            }
+        }
+    }
+    // The decoder stops once the lengths fill the code space, which
+    // happens at the last used symbol.
+    for &len in &lengths[..=last]{
+        write_symbol(bits, &length_code, usize::from(len));
+    }
+    canonical_code(&lengths)
+}
+
+// Huffman code lengths for `counts`, none longer than `limit`. Past the
+// limit the rare symbols are counted as more common until they fit.
+fn huffman_lengths(counts: &[u32], limit: u8) -> Vec<u8>{
+    let mut floor = 1;
+    loop{
+        let mut nodes: Vec<(u64, Option<(usize, usize)>)> = Vec::new();
+        let mut leaves = Vec::new();
+        for (symbol, &count) in counts.iter().enumerate().filter(|(_, &count)| count > 0){
+            leaves.push(symbol);
+            nodes.push((u64::from(count.max(floor)), None));
+        }
+        let mut lengths = vec![0u8; counts.len()];
+        if leaves.len() < 2{
+            return lengths;
+        }
+        let mut heap: std::collections::BinaryHeap<std::cmp::Reverse<(u64, usize)>> =
+            nodes.iter().enumerate().map(|(i, (weight, _))| std::cmp::Reverse((*weight, i))).collect();
+        while heap.len() > 1{
+            let std::cmp::Reverse((a_weight, a)) = heap.pop().unwrap();
+            let std::cmp::Reverse((b_weight, b)) = heap.pop().unwrap();
+            nodes.push((a_weight + b_weight, Some((a, b))));
+            heap.push(std::cmp::Reverse((a_weight + b_weight, nodes.len() - 1)));
+        }
+        let mut depths = vec![0u8; nodes.len()];
+        for node in (0..nodes.len()).rev(){
+            if let Some((a, b)) = nodes[node].1{
+                depths[a] = depths[node] + 1;
+                depths[b] = depths[node] + 1;
+            }
+        }
+        if depths[..leaves.len()].iter().all(|&depth| depth <= limit){
+            for (leaf, &symbol) in leaves.iter().enumerate(){
+                lengths[symbol] = depths[leaf];
+            }
+            return lengths;
+        }
+        floor *= 2;
+    }
+}
+
+// Canonical codes for a set of code lengths (RFC 1951, 3.2.2).
+fn canonical_code(lengths: &[u8]) -> PrefixCode{
+    let mut length_counts = [0u16; 16];
+    for &len in lengths{
+        length_counts[usize::from(len)] += 1;
+    }
+    length_counts[0] = 0;
+    let mut next = [0u16; 16];
+    let mut code = 0;
+    for len in 1..16{
+        code = (code + length_counts[len - 1]) << 1;
+        next[len] = code;
+    }
+    lengths.iter()
+        .map(|&len|{
+            if len == 0{
+                return (0, 0);
+            }
+            let code = next[usize::from(len)];
+            next[usize::from(len)] += 1;
+            (code, len)
+        })
+        .collect()
+}
+
+/// `data` in the gzip format (RFC 1952).
+pub fn gzip(data: &[u8]) -> Vec<u8>{
+    // Magic, deflate, no flags, no mtime, no extra flags, unknown OS.
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&hash::crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+/// `data` in the zlib format (RFC 1950).
+pub fn zlib(data: &[u8]) -> Vec<u8>{
+    // 32K window, deflate; the check bits make the header a multiple of 31.
+    let mut out = vec![0x78, 0x9c];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&hash::adler32(data).to_be_bytes());
+    out
+}
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;     // Candidates tried per position; trades ratio for speed.
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// A raw DEFLATE stream (RFC 1951) for `data`: LZ77 matching over a 32K
+/// window, coded as a single block with the fixed Huffman codes. That
+/// gets most of the win on text without building code tables per body.
+pub fn deflate(data: &[u8]) -> Vec<u8>{
+    let mut bits = BitWriter::default();
+    bits.write(1, 1);       // Final block.
+    bits.write(1, 2);       // Fixed Huffman codes.
+    for token in lz77(data){
+        match token{
+            Token::Literal(byte) => write_literal(&mut bits, u16::from(byte)),
+            Token::Match { len, dist } => {
+                write_length(&mut bits, len);
+                write_distance(&mut bits, dist);
+            }
+        }
+    }
+    write_literal(&mut bits, 256);     // End of block.
+    bits.finish()
+}
+
+enum Token{
+    Literal(u8),
+    Match { len: usize, dist: usize },
+}
+
+// `data` as literals and back-references into the 32K before them.
+fn lz77(data: &[u8]) -> Vec<Token>{
+    let mut tokens = Vec::new();
+    let mut head = vec![usize::MAX; 1 << 15];   // Hash of 3 bytes -> latest position.
+    let mut prev = vec![usize::MAX; WINDOW];    // Position -> previous one with the same hash.
+    let hash_at = |i: usize| -> usize{
+        let h = (u32::from(data[i]) << 10) ^ (u32::from(data[i + 1]) << 5) ^ u32::from(data[i + 2]);
+        (h as usize) & ((1 << 15) - 1)
+    };
+    let insert = |i: usize, head: &mut Vec<usize>, prev: &mut Vec<usize>|{
+        if i + MIN_MATCH <= data.len(){
+            let h = hash_at(i);
+            prev[i % WINDOW] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len(){
+        let (mut best_len, mut best_dist) = (0, 0);
+        if i + MIN_MATCH <= data.len(){
+            let mut candidate = head[hash_at(i)];
+            let max_len = MAX_MATCH.min(data.len() - i);
+            let mut tries = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && tries < MAX_CHAIN{
+                let len = data[candidate..].iter().zip(&data[i..i + max_len]).take_while(|(a, b)| a == b).count();
+                if len > best_len{
+                    best_len = len;
+                    best_dist = i - candidate;
+                    if len == max_len{
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW];
+                // Older entries may have been overwritten by newer positions.
+                if next == usize::MAX || next >= candidate{
+                    break;
+                }
+                candidate = next;
+                tries += 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH{
+            tokens.push(Token::Match { len: best_len, dist: best_dist });
+            for j in i..i + best_len{
+                insert(j, &mut head, &mut prev);
+            }
+            i += best_len;
+        } else {
+            tokens.push(Token::Literal(data[i]));
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    tokens
+}
+
+// Fixed literal/length code for `symbol` (RFC 1951, 3.2.6).
+fn write_literal(bits: &mut BitWriter, symbol: u16){
+    let (code, len) = match symbol{
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+    bits.write_code(code, len);
+}
+
+fn write_length(bits: &mut BitWriter, len: usize){
+    let index = LENGTH_BASE.iter().rposition(|&base| usize::from(base) <= len).unwrap_or(0);
+    write_literal(bits, 257 + index as u16);
+    bits.write((len - usize::from(LENGTH_BASE[index])) as u32, LENGTH_EXTRA[index]);
+}
+
+fn write_distance(bits: &mut BitWriter, dist: usize){
+    let index = DIST_BASE.iter().rposition(|&base| usize::from(base) <= dist).unwrap_or(0);
+    bits.write_code(index as u16, 5);
+    bits.write((dist - usize::from(DIST_BASE[index])) as u32, DIST_EXTRA[index]);
+}
+
+// Packs bits least significant first, as DEFLATE and brotli want.
+#[derive(Default)]
+struct BitWriter{
+    out: Vec<u8>,
+    buffer: u64,
+    count: u8,
+}
+
+impl BitWriter{
+    fn write(&mut self, value: u32, len: u8){
+        self.buffer |= u64::from(value) << self.count;
+        self.count += len;
+        while self.count >= 8{
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first.
+    fn write_code(&mut self, code: u16, len: u8){
+        let reversed = (code.reverse_bits() >> (16 - len)) as u32;
+        self.write(reversed, len);
+    }
+
+    fn finish(mut self) -> Vec<u8>{
+        if self.count > 0{
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
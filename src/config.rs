@@ -0,0 +1,227 @@
+use std::{collections::HashMap, error::Error, fmt, fs, io, path::Path};
+
+/// A value on the right-hand side of `key = value`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value{
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// The problem with a config file, and the line it was found on.
+#[derive(Debug)]
+pub enum ConfigError{
+    Io(io::Error),
+    Syntax { line: usize, message: String },
+    Invalid { key: String, message: String },   // Well-formed, but not an acceptable value for `key`.
+}
+
+impl ConfigError{
+    pub fn invalid(key: &str, message: &str) -> ConfigError{
+        ConfigError::Invalid { key: key.to_string(), message: message.to_string() }
+    }
+}
+
+impl fmt::Display for ConfigError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            ConfigError::Io(e) => write!(f, "could not read config: {}", e),
+            ConfigError::Syntax { line, message } => write!(f, "config line {}: {}", line, message),
+            ConfigError::Invalid { key, message } => write!(f, "config key `{}`: {}", key, message),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError{
+    fn from(e: io::Error) -> ConfigError{
+        ConfigError::Io(e)
+    }
+}
+
+/// Settings read from a small TOML-like file.
+///
+/// Each line is blank, a `# comment`, a `[section]` header, or
+/// `key = value` where the value is a double-quoted string, an integer,
+/// `true`/`false`, or a one-line `[...]` array of those. Keys inside a
+/// section are stored as `section.key`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config{
+    values: HashMap<String, Value>,
+}
+
+impl Config{
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError>{
+        Config::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, ConfigError>{
+        let mut values = HashMap::new();
+        let mut section = String::new();
+
+        for (index, raw) in text.lines().enumerate(){
+            let line_no = index + 1;
+            let syntax = |message: &str| ConfigError::Syntax { line: line_no, message: message.to_string() };
+            let line = strip_comment(raw).trim();
+            if line.is_empty(){
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')){
+                section = name.trim().to_string();
+                if section.is_empty(){
+                    return Err(syntax("empty section name"));
+                }
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| syntax("expected `key = value`"))?;
+            let key = key.trim();
+            if key.is_empty(){
+                return Err(syntax("missing key"));
+            }
+            let value = parse_value(value.trim()).map_err(|m| syntax(&m))?;
+
+            let full_key = if section.is_empty() { key.to_string() } else { format!("{}.{}", section, key) };
+            values.insert(full_key, value);
+        }
+
+        Ok(Config { values })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value>{
+        self.values.get(key)
+    }
+
+    pub fn set(&mut self, key: &str, value: Value){
+        self.values.insert(key.to_string(), value);
+    }
+
+    pub fn get_str(&self, key: &str) -> Result<Option<&str>, ConfigError>{
+        match self.get(key){
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(ConfigError::invalid(key, "expected a string")),
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>, ConfigError>{
+        match self.get(key){
+            None => Ok(None),
+            Some(Value::Integer(i)) => Ok(Some(*i)),
+            Some(_) => Err(ConfigError::invalid(key, "expected an integer")),
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, ConfigError>{
+        match self.get(key){
+            None => Ok(None),
+            Some(Value::Bool(b)) => Ok(Some(*b)),
+            Some(_) => Err(ConfigError::invalid(key, "expected true or false")),
+        }
+    }
+
+    pub fn get_str_array(&self, key: &str) -> Result<Option<Vec<String>>, ConfigError>{
+        match self.get(key){
+            None => Ok(None),
+            Some(Value::Array(items)) => items.iter()
+                .map(|v| match v{
+                    Value::String(s) => Ok(s.clone()),
+                    _ => Err(ConfigError::invalid(key, "expected an array of strings")),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(Some),
+            Some(_) => Err(ConfigError::invalid(key, "expected an array of strings")),
+        }
+    }
+}
+
+/// Drop a trailing `# comment`, ignoring `#` inside strings.
+fn strip_comment(line: &str) -> &str{
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices(){
+        match c{
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {},
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<Value, String>{
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')){
+        return split_array(inner)?
+            .into_iter()
+            .map(parse_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array);
+    }
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')){
+        return unescape(inner).map(Value::String);
+    }
+    match text{
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => text.replace('_', "").parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| format!("unrecognised value `{}`", text)),
+    }
+}
+
+/// Split the inside of an array on commas outside of strings.
+fn split_array(inner: &str) -> Result<Vec<&str>, String>{
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in inner.char_indices(){
+        match c{
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                items.push(inner[start..i].trim());
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    if in_string{
+        return Err("unterminated string in array".to_string());
+    }
+    items.push(inner[start..].trim());
+
+    // Allow a trailing comma and the empty array.
+    if items.last() == Some(&""){
+        items.pop();
+    }
+    if items.contains(&""){
+        return Err("empty array element".to_string());
+    }
+    Ok(items)
+}
+
+fn unescape(s: &str) -> Result<String, String>{
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next(){
+        if c != '\\'{
+            out.push(c);
+            continue;
+        }
+        match chars.next(){
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            other => return Err(format!("unknown escape `\\{}`", other.map(String::from).unwrap_or_default())),
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,19 @@
+// Static files compiled into the binary. The build script generates
+// `EMBEDDED_FILES` from the directory `SERVER_APP_EMBED_DIR` names at
+// build time; `StaticFileServer::with_source` serves them.
+
+/// One file from the embedded directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedFile{
+    pub path: &'static str,         // Relative to the embedded directory, with `/` separators.
+    pub content_type: &'static str,
+    pub bytes: &'static [u8],
+    pub etag: &'static str,         // Quoted FNV-1a hash of the contents, taken at build time.
+}
+
+include!(concat!(env!("OUT_DIR"), "/embedded_files.rs"));
+
+/// The embedded file at `path` (relative, like `docs/index.html`), if any.
+pub fn get(path: &str) -> Option<&'static EmbeddedFile>{
+    EMBEDDED_FILES.iter().find(|file| file.path == path)
+}
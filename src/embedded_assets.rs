@@ -0,0 +1,47 @@
+use crate::http::{self, Response};
+
+/// A file compiled into the binary, for the built-in pages that have to
+/// work even when nothing is on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Asset{
+    pub path: &'static str,         // Relative, as it would be under the document root.
+    pub content_type: &'static str,
+    pub bytes: &'static [u8],
+}
+
+impl Asset{
+    /// The asset as the body of a `status` response.
+    pub fn response(&self, status: u16) -> Response{
+        Response::new(status, http::reason_phrase(status))
+            .with_header("Content-Type", self.content_type)
+            .with_body(self.bytes)
+    }
+}
+
+static ASSETS: &[Asset] = &[
+    Asset {
+        path: "favicon.ico",
+        content_type: "image/x-icon",
+        bytes: include_bytes!("../assets/favicon.ico"),
+    },
+    Asset {
+        path: "index.html",
+        content_type: "text/html; charset=utf-8",
+        bytes: include_bytes!("../index.html"),
+    },
+    Asset {
+        path: "404.html",
+        content_type: "text/html; charset=utf-8",
+        bytes: include_bytes!("../404.html"),
+    },
+];
+
+/// The embedded asset at `path` (relative, like `404.html`), if any.
+pub fn get(path: &str) -> Option<&'static Asset>{
+    ASSETS.iter().find(|asset| asset.path == path)
+}
+
+/// Every embedded asset.
+pub fn all() -> &'static [Asset]{
+    ASSETS
+}
@@ -0,0 +1,113 @@
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 (RFC 4648) with `=` padding.
+pub fn base64_encode(data: &[u8]) -> String{
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3){
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+
+        // Three input bytes become four output characters, padded with `=`.
+        for i in 0..4{
+            if i <= chunk.len(){
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode standard base64. Padding is optional; any other character,
+/// including whitespace, makes the input invalid.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>>{
+    let text = text.trim_end_matches('=');
+    if text.len() % 4 == 1{
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes(){
+        let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8{
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// `text` as an RFC 5987 `ext-value`, like `UTF-8''na%C3%AFve.txt`, for
+/// header parameters such as `filename*` that must carry non-ASCII text.
+/// Everything but the letters, digits and `!#$&+-.^_`|~` is
+/// percent-encoded as UTF-8.
+pub fn rfc5987_encode(text: &str) -> String{
+    let mut out = String::from("UTF-8''");
+    for byte in text.bytes(){
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte){
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+/// `text` encoded for an `application/x-www-form-urlencoded` name or
+/// value, as browsers send forms: letters, digits and `*-._` as they
+/// are, spaces as `+`, and every other byte of the UTF-8 as `%XX`.
+pub fn form_urlencode(text: &str) -> String{
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes(){
+        match byte{
+            b' ' => out.push('+'),
+            b'*' | b'-' | b'.' | b'_' => out.push(byte as char),
+            _ if byte.is_ascii_alphanumeric() => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+/// Undo `form_urlencode`: `+` is a space and `%XX` a byte. `None` if a
+/// `%` isn't followed by two hex digits or the bytes aren't UTF-8.
+pub fn form_urldecode(text: &str) -> Option<String>{
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len(){
+        match bytes[i]{
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                if !hex.bytes().all(|b| b.is_ascii_hexdigit()){
+                    return None;
+                }
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 2;
+            },
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(out).ok()
+}
+
+/// The `(name, value)` pairs of an `application/x-www-form-urlencoded`
+/// body, in order, repeats included. Empty pieces between `&`s are
+/// skipped and a piece without `=` has an empty value. `None` if any
+/// name or value fails to decode.
+pub fn parse_form(body: &str) -> Option<Vec<(String, String)>>{
+    body.split('&')
+        .filter(|piece| !piece.is_empty())
+        .map(|piece| {
+            let (name, value) = piece.split_once('=').unwrap_or((piece, ""));
+            Some((form_urldecode(name)?, form_urldecode(value)?))
+        })
+        .collect()
+}
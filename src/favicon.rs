@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    embedded_assets,
+    http::{self, Request, Response},
+    router::Router,
+    static_files::StaticFileServer,
+};
+
+/// `Cache-Control` for the favicon, which browsers otherwise ask for on
+/// every page.
+pub const CACHE_CONTROL: &str = "public, max-age=604800";
+
+/// What `/favicon.ico` gets when the document root has none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fallback{
+    #[default]
+    Embedded,   // The icon compiled into the binary.
+    NoContent,  // `204 No Content`.
+}
+
+impl Fallback{
+    /// `"embedded"` or `"no_content"`.
+    pub fn parse(name: &str) -> Option<Fallback>{
+        match name{
+            "embedded" => Some(Fallback::Embedded),
+            "no_content" => Some(Fallback::NoContent),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `request` is a browser's favicon lookup, which some logs would
+/// rather leave out (see `server.log_favicon`).
+pub fn is_favicon_request(request: &Request) -> bool{
+    request.path == "/favicon.ico"
+}
+
+/// Serve `GET /favicon.ico` from `static_root` when it has one, and from
+/// `fallback` otherwise, always with `CACHE_CONTROL`. As with robots.txt,
+/// the file is looked for on every request.
+pub fn register(router: &mut Router, static_root: Option<&Path>, fallback: Fallback){
+    let static_root: Option<PathBuf> = static_root.map(Path::to_path_buf);
+    router.get("/favicon.ico", move |request: &Request| {
+        let response = match &static_root{
+            Some(root) if root.join("favicon.ico").is_file() => StaticFileServer::new(root).handle(request),
+            _ => match fallback{
+                Fallback::Embedded => match embedded_assets::get("favicon.ico"){
+                    Some(icon) => icon.response(200),
+                    None => Response::new(204, http::reason_phrase(204)),
+                },
+                Fallback::NoContent => Response::new(204, http::reason_phrase(204)),
+            },
+        };
+        response.with_header("Cache-Control", CACHE_CONTROL)
+    });
+}
@@ -0,0 +1,40 @@
+// Entry points for fuzzers, such as the `cargo fuzz` targets under
+// `fuzz/`. Each takes arbitrary bytes and must return without panicking,
+// whatever they hold.
+use std::time::Duration;
+
+use crate::{
+    http::{Limits, Request},
+    server::{Connection, Incoming, ServerConfig},
+    testing::MockStream,
+};
+
+/// Parse `data` as one request, with the default limits and with tight
+/// ones, so the limit checks get exercised on short inputs too. The
+/// tight ones take bare LF line endings, for the lenient parsing.
+pub fn parse_request(data: &[u8]){
+    let _ = Request::parse(data);
+    let tight = Limits {
+        max_request_line: 64,
+        max_header_line: 64,
+        max_headers: 4,
+        max_header_bytes: 256,
+        max_body_bytes: 64,
+        lenient_line_endings: true,
+    };
+    let _ = Request::parse_with_limits(data, &tight);
+}
+
+/// Feed `data` to the request loop a connection runs, split into reads at
+/// the positions of `0xff` bytes, until it rejects a request or runs dry.
+pub fn serve_connection(data: &[u8]){
+    let config = ServerConfig { keep_alive_timeout: Duration::from_millis(10), ..ServerConfig::default() };
+    let chunks: Vec<Vec<u8>> = data.split(|&b| b == 0xff)
+        .filter(|chunk| !chunk.is_empty())
+        .map(<[u8]>::to_vec)
+        .collect();
+    let mut connection = Connection::new(MockStream::new(chunks));
+    while let Incoming::Request(request) = connection.read_request(&config, |_| None){
+        let _ = request.target();
+    }
+}
@@ -0,0 +1,300 @@
+/// SHA-1 digest of `data` (FIPS 180-4).
+///
+/// SHA-1 is broken for collision resistance; it is here because the
+/// WebSocket handshake requires it, not for anything security-sensitive.
+pub fn sha1(data: &[u8]) -> [u8; 20]{
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    // Pad with a single 1 bit, zeros, and the message length in bits so the
+    // total is a multiple of 64 bytes.
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56{
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_be_bytes());
+
+    for block in message.chunks_exact(64){
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate(){
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80{
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate(){
+            let (f, k) = match i{
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]){
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(h){
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// SHA-256 digest of `data` (FIPS 180-4).
+pub fn sha256(data: &[u8]) -> [u8; 32]{
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// SHA-256 fed a piece at a time. Only the current 64-byte block is kept,
+/// so hashing a large body needs no copy of it.
+#[derive(Debug, Clone)]
+pub struct Sha256{
+    state: [u32; 8],
+    block: [u8; 64],
+    filled: usize,  // Bytes of `block` waiting for the rest of it.
+    length: u64,    // Bytes hashed so far.
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+impl Sha256{
+    pub fn new() -> Sha256{
+        Sha256 {
+            state: [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19],
+            block: [0; 64],
+            filled: 0,
+            length: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]){
+        self.length = self.length.wrapping_add(data.len() as u64);
+        if self.filled > 0{
+            let n = (64 - self.filled).min(data.len());
+            self.block[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+            self.filled += n;
+            data = &data[n..];
+            if self.filled < 64{
+                return;
+            }
+            let block = self.block;
+            sha256_block(&mut self.state, &block);
+            self.filled = 0;
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks{
+            sha256_block(&mut self.state, block);
+        }
+        let rest = blocks.remainder();
+        self.block[..rest.len()].copy_from_slice(rest);
+        self.filled = rest.len();
+    }
+
+    /// The digest of everything passed to `update`.
+    pub fn finish(mut self) -> [u8; 32]{
+        // Pad with a single 1 bit, zeros, and the message length in bits
+        // so the total is a multiple of 64 bytes.
+        let bits = self.length.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.filled != 56{
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+
+        let mut digest = [0u8; 32];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state){
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+impl Default for Sha256{
+    fn default() -> Sha256{
+        Sha256::new()
+    }
+}
+
+/// HMAC-SHA256 (RFC 2104) of `data` under `key`.
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32]{
+    let mut mac = HmacSha256::new(key);
+    mac.update(data);
+    mac.finish()
+}
+
+/// HMAC-SHA256 fed a piece at a time, like `Sha256`.
+#[derive(Debug, Clone)]
+pub struct HmacSha256{
+    inner: Sha256,      // Already fed the key XORed with the inner pad.
+    outer: Sha256,      // Likewise with the outer pad, waiting for the inner digest.
+}
+
+impl HmacSha256{
+    pub fn new(key: &[u8]) -> HmacSha256{
+        // Keys longer than a block are hashed first; shorter ones are
+        // padded with zeros.
+        let mut padded = [0u8; 64];
+        if key.len() > 64{
+            padded[..32].copy_from_slice(&sha256(key));
+        } else {
+            padded[..key.len()].copy_from_slice(key);
+        }
+        let (mut inner, mut outer) = (Sha256::new(), Sha256::new());
+        inner.update(&padded.map(|b| b ^ 0x36));
+        outer.update(&padded.map(|b| b ^ 0x5c));
+        HmacSha256 { inner, outer }
+    }
+
+    pub fn update(&mut self, data: &[u8]){
+        self.inner.update(data);
+    }
+
+    pub fn finish(self) -> [u8; 32]{
+        let mut outer = self.outer;
+        outer.update(&self.inner.finish());
+        outer.finish()
+    }
+}
+
+/// Whether `a` and `b` hold the same bytes, looking at every byte whatever
+/// it finds, so the time taken doesn't tell an attacker how much of a
+/// guessed MAC was right. Only the lengths are compared up front.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool{
+    if a.len() != b.len(){
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0u8, |difference, (x, y)| std::hint::black_box(difference | (x ^ y)));
+    difference == 0
+}
+
+fn sha256_block(state: &mut [u32; 8], block: &[u8]){
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks_exact(4).enumerate(){
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64{
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (&k, &word) in SHA256_K.iter().zip(&w){
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let choice = (e & f) ^ (!e & g);
+        let temp1 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(k).wrapping_add(word);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let majority = (a & b) ^ (a & c) ^ (b & c);
+        let temp2 = s0.wrapping_add(majority);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+
+    for (value, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]){
+        *value = value.wrapping_add(add);
+    }
+}
+
+/// Lowercase hexadecimal form of `bytes`.
+pub fn to_hex(bytes: &[u8]) -> String{
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The bytes `text` spells in hexadecimal, either case, or `None` if it
+/// isn't an even number of hex digits.
+pub fn from_hex(text: &str) -> Option<Vec<u8>>{
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|b| b.is_ascii_hexdigit()){
+        return None;
+    }
+    text.as_bytes()
+        .chunks_exact(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// 64-bit FNV-1a hash of `data`.
+///
+/// Fast and stable across builds and platforms, which makes it good for
+/// fingerprinting content; it is not a cryptographic hash.
+pub fn fnv1a_64(data: &[u8]) -> u64{
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in data{
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// CRC-32 (IEEE 802.3, as used by gzip and zip) of `data`.
+pub fn crc32(data: &[u8]) -> u32{
+    const TABLE: [u32; 256] = crc32_table();
+    let mut crc = !0u32;
+    for &byte in data{
+        crc = TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+const fn crc32_table() -> [u32; 256]{
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256{
+        let mut c = i as u32;
+        let mut bit = 0;
+        while bit < 8{
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            bit += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+/// Adler-32 (RFC 1950, the zlib checksum) of `data`.
+pub fn adler32(data: &[u8]) -> u32{
+    const MOD: u32 = 65_521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before `b` could overflow.
+    for chunk in data.chunks(5552){
+        for &byte in chunk{
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
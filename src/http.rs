@@ -0,0 +1,1485 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::{self, IoSlice, Write},
+    net::TcpStream,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+use crate::{encoding, negotiation, pool::BufferPool, templates};
+
+mod body;
+mod chunked;
+mod cookie;
+mod error;
+mod extensions;
+mod ndjson;
+mod range;
+
+pub use body::{read_chunked_body, BodyError, RequestBodyReader};
+pub use chunked::ChunkedResponseWriter;
+pub use cookie::{set_cookie_value, CookieError, CookieOptions, SameSite};
+pub use error::ServerError;
+pub use extensions::Extensions;
+pub use ndjson::{ndjson_line, NdjsonWriter};
+pub use range::{parse_ranges, ByteRange, MultiRangeResponse, RangeError, RangePart, RangeSpec, MAX_RANGES};
+
+/// An ordered list of header fields.
+///
+/// Field names are matched case-insensitively, as HTTP requires, but are
+/// stored and written back exactly as they were given.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers{
+    entries: Vec<(String, String)>,     // (name, value) pairs in insertion order.
+}
+
+impl Headers{
+    pub fn new() -> Headers{
+        Headers { entries: Vec::new() }
+    }
+
+    /// Returns the first value of the named header.
+    pub fn get(&self, name: &str) -> Option<&str>{
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns every value of the named header, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a{
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool{
+        self.get(name).is_some()
+    }
+
+    /// Replaces every existing value of the named header with `value`.
+    pub fn set(&mut self, name: &str, value: &str){
+        self.remove(name);
+        self.append(name, value);
+    }
+
+    /// Adds a value without touching existing values of the same header.
+    pub fn append(&mut self, name: &str, value: &str){
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+
+    /// Removes the named header, returning its first value if it was present.
+    pub fn remove(&mut self, name: &str) -> Option<String>{
+        let mut removed = None;
+        self.entries.retain(|(n, v)| {
+            if n.eq_ignore_ascii_case(name){
+                if removed.is_none(){
+                    removed = Some(v.clone());
+                }
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+
+    /// Checks whether a comma-separated header such as `Connection` lists
+    /// `token` in any of its values.
+    pub fn has_token(&self, name: &str, token: &str) -> bool{
+        self.get_all(name)
+            .flat_map(|v| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)>{
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize{
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.entries.is_empty()
+    }
+}
+
+/// Reasons a buffer could not be parsed as an HTTP request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError{
+    Incomplete,             // The header block (or declared body) has not fully arrived yet.
+    InvalidRequestLine,     // The first line is not `METHOD target HTTP/x.y`.
+    InvalidHeader,          // A header line is not `name: value`.
+    RequestLineTooLong,     // Longer than `Limits::max_request_line`.
+    HeaderLineTooLong,      // A header line is longer than `Limits::max_header_line`.
+    TooManyHeaders,         // More than `Limits::max_headers` header lines.
+    HeadersTooLarge,        // The whole header block is over `Limits::max_header_bytes`.
+    UnsupportedVersion,     // An `HTTP/x.y` other than 1.0 or 1.1.
+    PayloadTooLarge,        // The declared body is over `Limits::max_body_bytes`.
+    MissingHost,            // An HTTP/1.1 request without `Host`.
+    InvalidHost,            // More than one `Host`, or one that isn't `host[:port]`.
+    InvalidTarget,          // A target in none of the forms, or one the method can't use.
+    InvalidMethod,          // A method that isn't a token.
+    UnsupportedTransferCoding,  // A `Transfer-Encoding` other than just `chunked`.
+    ConflictingLength,      // Both `Transfer-Encoding` and `Content-Length`, or `Content-Length`s that differ.
+}
+
+impl ParseError{
+    /// The status code to answer with.
+    pub fn status(&self) -> u16{
+        match self{
+            ParseError::RequestLineTooLong => 414,
+            ParseError::HeaderLineTooLong | ParseError::TooManyHeaders | ParseError::HeadersTooLarge => 431,
+            ParseError::UnsupportedVersion => 505,
+            ParseError::UnsupportedTransferCoding => 501,
+            ParseError::PayloadTooLarge => 413,
+            _ => 400,
+        }
+    }
+}
+
+impl fmt::Display for ParseError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            ParseError::Incomplete => write!(f, "incomplete request"),
+            ParseError::InvalidRequestLine => write!(f, "invalid request line"),
+            ParseError::InvalidHeader => write!(f, "invalid header line"),
+            ParseError::RequestLineTooLong => write!(f, "request line too long"),
+            ParseError::HeaderLineTooLong => write!(f, "header line too long"),
+            ParseError::TooManyHeaders => write!(f, "too many header fields"),
+            ParseError::HeadersTooLarge => write!(f, "header block too large"),
+            ParseError::UnsupportedVersion => write!(f, "unsupported HTTP version"),
+            ParseError::PayloadTooLarge => write!(f, "request body too large"),
+            ParseError::MissingHost => write!(f, "Missing Host header"),
+            ParseError::InvalidHost => write!(f, "Invalid Host header"),
+            ParseError::InvalidTarget => write!(f, "invalid request target"),
+            ParseError::InvalidMethod => write!(f, "invalid request method"),
+            ParseError::UnsupportedTransferCoding => write!(f, "unsupported transfer coding"),
+            ParseError::ConflictingLength => write!(f, "conflicting message length"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// The form a request target takes (RFC 9112, section 3.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TargetForm{
+    Origin,     // `/path?query`, the usual kind.
+    Absolute,   // `http://host/path?query`, as sent to proxies; the path is taken from it.
+    Authority,  // `host:port`, only for `CONNECT`.
+    Asterisk,   // `*`, only for `OPTIONS` asking about the server as a whole.
+}
+
+impl TargetForm{
+    /// The form of `target`, or `None` if it isn't in one `method` may use.
+    pub fn of(method: &str, target: &str) -> Option<TargetForm>{
+        if method == "CONNECT"{
+            return matches!(parse_host(target), Some((host, Some(_))) if !host.is_empty()).then_some(TargetForm::Authority);
+        }
+        if target == "*"{
+            return (method == "OPTIONS").then_some(TargetForm::Asterisk);
+        }
+        if target.starts_with('/'){
+            Some(TargetForm::Origin)
+        } else if let Some((scheme, authority, _)) = split_absolute(target){
+            // No userinfo (`user@host`): it has no business in a request.
+            let http = scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https");
+            let host = !authority.contains('@') && matches!(parse_host(authority), Some((host, _)) if !host.is_empty());
+            (http && host).then_some(TargetForm::Absolute)
+        } else {
+            None
+        }
+    }
+}
+
+/// A request method: one of the standard ones, or any other valid token
+/// (`PURGE`, say) as an `Extension`. Methods are case-sensitive, so
+/// `get` is an extension method, not `GET`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method{
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Options,
+    Patch,
+    Trace,
+    Connect,
+    Extension(String),
+}
+
+impl Method{
+    pub fn as_str(&self) -> &str{
+        match self{
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Options => "OPTIONS",
+            Method::Patch => "PATCH",
+            Method::Trace => "TRACE",
+            Method::Connect => "CONNECT",
+            Method::Extension(name) => name,
+        }
+    }
+
+    /// Whether the method only asks for something, changing nothing
+    /// (RFC 9110, section 9.2.1).
+    pub fn is_safe(&self) -> bool{
+        matches!(self, Method::Get | Method::Head | Method::Options | Method::Trace)
+    }
+
+    /// Whether sending the request twice has the same effect as sending
+    /// it once (RFC 9110, section 9.2.2).
+    pub fn is_idempotent(&self) -> bool{
+        self.is_safe() || matches!(self, Method::Put | Method::Delete)
+    }
+}
+
+impl fmt::Display for Method{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Method{
+    type Err = ParseError;
+
+    /// A standard method, or an extension method if `s` is any other
+    /// token (RFC 9110, section 5.6.2); anything else is `InvalidMethod`.
+    fn from_str(s: &str) -> Result<Method, ParseError>{
+        Ok(match s{
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "OPTIONS" => Method::Options,
+            "PATCH" => Method::Patch,
+            "TRACE" => Method::Trace,
+            "CONNECT" => Method::Connect,
+            s if is_token(s) => Method::Extension(s.to_string()),
+            _ => return Err(ParseError::InvalidMethod),
+        })
+    }
+}
+
+impl PartialEq<str> for Method{
+    fn eq(&self, other: &str) -> bool{
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Method{
+    fn eq(&self, other: &&str) -> bool{
+        self.as_str() == *other
+    }
+}
+
+/// The HTTP versions this server speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HttpVersion{
+    Http10,
+    Http11,
+}
+
+impl HttpVersion{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+        }
+    }
+}
+
+impl fmt::Display for HttpVersion{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for HttpVersion{
+    type Err = ParseError;
+
+    /// `HTTP/1.0` or `HTTP/1.1`. Other `HTTP/` versions are
+    /// `UnsupportedVersion`; anything else isn't a version at all.
+    fn from_str(s: &str) -> Result<HttpVersion, ParseError>{
+        match s{
+            "HTTP/1.0" => Ok(HttpVersion::Http10),
+            "HTTP/1.1" => Ok(HttpVersion::Http11),
+            s if s.starts_with("HTTP/") => Err(ParseError::UnsupportedVersion),
+            _ => Err(ParseError::InvalidRequestLine),
+        }
+    }
+}
+
+/// Upper bounds on the parts of a request head, enforced while parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits{
+    pub max_request_line: usize,    // Bytes, without the CRLF.
+    pub max_header_line: usize,     // Bytes per header line, without the CRLF.
+    pub max_headers: usize,
+    pub max_header_bytes: usize,    // The whole head, request line included.
+    pub max_body_bytes: usize,      // Largest `Content-Length` accepted.
+    pub lenient_line_endings: bool, // Take a bare LF to end a head line, as well as CRLF.
+}
+
+impl Default for Limits{
+    fn default() -> Limits{
+        Limits {
+            max_request_line: 8 * 1024,
+            max_header_line: 8 * 1024,
+            max_headers: 100,
+            max_header_bytes: 64 * 1024,
+            max_body_bytes: 8 * 1024 * 1024,
+            lenient_line_endings: false,
+        }
+    }
+}
+
+/// A parsed HTTP request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request{
+    pub method: Method,
+    pub path: String,               // Target without the query string; normalized by `uri` before routing.
+    pub original_path: Option<String>,  // The path as sent, when normalizing changed it.
+    pub query: Option<String>,      // Everything after the first `?`, if any.
+    pub target_form: TargetForm,    // How the target was written; `path` is `*` for `Asterisk`.
+    pub scheme: Option<String>,     // From an absolute-form target, lowercased.
+    pub authority: Option<String>,  // From an absolute- or authority-form target; overrides `Host`.
+    pub version: HttpVersion,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    pub trailers: Headers,          // Trailer fields after a chunked body; empty otherwise.
+    pub params: HashMap<String, String>,    // Path parameters captured by the router.
+    pub secure: bool,               // Arrived over TLS; set by whatever accepted the connection.
+    negotiated: SharedFlag,         // Set once the response was chosen by `Accept`.
+    body_hash: OnceLock<String>,    // Hex SHA-256 of the body, once `BodyHashMiddleware` has it.
+    extensions: Extensions,         // Shared with every clone.
+}
+
+/// A flag shared by every clone of a request, so middleware can see what
+/// the handler did with its copy.
+#[derive(Debug, Clone, Default)]
+struct SharedFlag(Arc<AtomicBool>);
+
+impl SharedFlag{
+    fn set(&self){
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    fn get(&self) -> bool{
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl PartialEq for SharedFlag{
+    fn eq(&self, other: &SharedFlag) -> bool{
+        self.get() == other.get()
+    }
+}
+
+impl Eq for SharedFlag {}
+
+impl Request{
+    /// Create an HTTP/1.1 request with no headers and an empty body. A
+    /// target in no valid form is taken as origin-form, and a method that
+    /// isn't a token as an extension method all the same.
+    pub fn new(method: &str, target: &str) -> Request{
+        let target_form = TargetForm::of(method, target).unwrap_or(TargetForm::Origin);
+        let (path, query) = split_target(target, target_form);
+        let (scheme, authority) = target_authority(target, target_form);
+        Request {
+            method: method.parse().unwrap_or_else(|_| Method::Extension(method.to_string())),
+            path,
+            original_path: None,
+            query,
+            target_form,
+            scheme,
+            authority,
+            version: HttpVersion::Http11,
+            headers: Headers::new(),
+            body: Vec::new(),
+            trailers: Headers::new(),
+            params: HashMap::new(),
+            secure: false,
+            negotiated: SharedFlag::default(),
+            body_hash: OnceLock::new(),
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// Parse a request from a buffer holding the header block and, if a
+    /// `Content-Length` was sent, the body, with the default `Limits`.
+    ///
+    /// Returns `ParseError::Incomplete` when the buffer ends before the
+    /// blank line or before the end of the body, which is either the
+    /// declared length or the last chunk and trailers of a chunked one.
+    pub fn parse(buf: &[u8]) -> Result<Request, ParseError>{
+        Request::parse_with_limits(buf, &Limits::default())
+    }
+
+    /// Like `parse`, rejecting requests that exceed `limits`.
+    ///
+    /// Limits are checked on partial input too, so an oversized head is
+    /// refused as soon as it is detected rather than once it is complete.
+    pub fn parse_with_limits(buf: &[u8], limits: &Limits) -> Result<Request, ParseError>{
+        let (mut request, head_len) = Request::parse_head(buf, limits)?;
+        if is_chunked(&request.headers)?{
+            let mut rest = buf[head_len..].to_vec();
+            return match read_chunked_body(&mut io::empty(), &mut rest, limits){
+                Ok((body, trailers)) => {
+                    request.body = body;
+                    request.trailers = trailers;
+                    Ok(request)
+                },
+                Err(BodyError::TooLarge) => Err(ParseError::PayloadTooLarge),
+                Err(BodyError::Truncated) => Err(ParseError::Incomplete),
+                Err(_) => Err(ParseError::InvalidHeader),
+            };
+        }
+
+        // Only take as much body as the client declared.
+        let len = declared_body_len(&request.headers)?;
+        let reader = RequestBodyReader::new(&buf[head_len..], limits.max_body_bytes);
+        match reader.read_exact_len(len, &mut request.body){
+            Ok(()) => Ok(request),
+            Err(BodyError::TooLarge) => Err(ParseError::PayloadTooLarge),
+            Err(_) => Err(ParseError::Incomplete),
+        }
+    }
+
+    /// Parse only the head, returning the request with an empty body and
+    /// the number of bytes the head took, blank line included.
+    ///
+    /// This lets a server look at the headers (and refuse the request, or
+    /// answer `Expect: 100-continue`) before the body arrives. A declared
+    /// body over `limits.max_body_bytes` is refused here too.
+    ///
+    /// One empty line before the request line is skipped, as RFC 9112
+    /// (section 2.2) asks, since some clients send a CRLF after a body. A
+    /// bare LF or CR in the head is refused unless
+    /// `limits.lenient_line_endings` is set, in which case a bare LF ends
+    /// a line like CRLF does. So that the body's end can't be read two
+    /// ways, which is how requests are smuggled past a proxy, both
+    /// `Transfer-Encoding` and `Content-Length`, or `Content-Length`s that
+    /// differ, are refused with `ParseError::ConflictingLength`.
+    pub fn parse_head(buf: &[u8], limits: &Limits) -> Result<(Request, usize), ParseError>{
+        let lenient = limits.lenient_line_endings;
+        let skipped = match buf{
+            [b'\r', b'\n', ..] => 2,
+            [b'\n', ..] if lenient => 1,
+            _ => 0,
+        };
+        let buf = &buf[skipped..];
+        let head_end = header_end(buf, lenient);
+        let head = &buf[..head_end.map_or(buf.len(), |(end, _)| end)];
+        let request_line_len = head.iter().position(|&b| b == b'\n' || b == b'\r').unwrap_or(head.len());
+        if request_line_len > limits.max_request_line{
+            return Err(ParseError::RequestLineTooLong);
+        }
+        if head.len() > limits.max_header_bytes{
+            return Err(ParseError::HeadersTooLarge);
+        }
+        if !lenient{
+            // Checked before the head is complete, as a client sending bare
+            // LFs would otherwise be waited on for a CRLF that won't come.
+            let bare_lf = head.iter().enumerate().position(|(i, &b)| b == b'\n' && (i == 0 || head[i - 1] != b'\r'));
+            if let Some(at) = bare_lf{
+                return Err(if at <= request_line_len { ParseError::InvalidRequestLine } else { ParseError::InvalidHeader });
+            }
+        }
+        let (_, head_len) = head_end.ok_or(ParseError::Incomplete)?;
+
+        let head = std::str::from_utf8(head).map_err(|_| ParseError::InvalidHeader)?;
+        let lines: Vec<&str> = match lenient{
+            true => head.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).collect(),
+            false => head.split("\r\n").collect(),
+        };
+        let header_lines = &lines[1..];
+        if header_lines.len() > limits.max_headers{
+            return Err(ParseError::TooManyHeaders);
+        }
+        if header_lines.iter().any(|line| line.len() > limits.max_header_line){
+            return Err(ParseError::HeaderLineTooLong);
+        }
+
+        // The request line must have exactly three space-separated parts.
+        let request_line = lines[0];
+        if request_line.contains(['\r', '\n']){
+            return Err(ParseError::InvalidRequestLine);
+        }
+        let mut parts = request_line.split(' ');
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()){
+            (Some(m), Some(t), Some(v), None) if !m.is_empty() && !t.is_empty() => (m, t, v),
+            _ => return Err(ParseError::InvalidRequestLine),
+        };
+        let method: Method = method.parse()?;
+        let version: HttpVersion = version.parse()?;
+        let target_form = TargetForm::of(method.as_str(), target).ok_or(ParseError::InvalidTarget)?;
+
+        let headers = parse_header_lines(header_lines.iter().copied())?;
+        // HTTP/1.1 requires exactly one `Host`; 1.0 clients may leave it
+        // out. An absolute-form target names the host itself, so `Host`
+        // doesn't matter.
+        if target_form != TargetForm::Absolute{
+            check_host(&headers, version)?;
+        }
+        if headers.contains("Transfer-Encoding") && headers.contains("Content-Length"){
+            return Err(ParseError::ConflictingLength);
+        }
+        if !is_chunked(&headers)? && declared_body_len(&headers)? > limits.max_body_bytes{
+            return Err(ParseError::PayloadTooLarge);
+        }
+
+        let (path, query) = split_target(target, target_form);
+        let (scheme, authority) = target_authority(target, target_form);
+        let request = Request {
+            method,
+            path,
+            original_path: None,
+            query,
+            target_form,
+            scheme,
+            authority,
+            version,
+            headers,
+            body: Vec::new(),
+            trailers: Headers::new(),
+            params: HashMap::new(),
+            secure: false,
+            negotiated: SharedFlag::default(),
+            body_hash: OnceLock::new(),
+            extensions: Extensions::new(),
+        };
+        Ok((request, skipped + head_len))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str>{
+        self.headers.get(name)
+    }
+
+    /// The trailer fields sent after a chunked body.
+    pub fn trailers(&self) -> &Headers{
+        &self.trailers
+    }
+
+    /// The host (and port, if given) the request is for: the target's
+    /// authority when it has one, which takes precedence over `Host`
+    /// (RFC 9112, section 3.2.2), and otherwise the `Host` header.
+    pub fn effective_host(&self) -> Option<&str>{
+        self.authority.as_deref().or_else(|| self.header("Host"))
+    }
+
+    /// Returns a path parameter captured by the matched route.
+    pub fn param(&self, name: &str) -> Option<&str>{
+        self.params.get(name).map(String::as_str)
+    }
+
+    /// The body as text, without copying it; an error if it isn't UTF-8.
+    pub fn body_as_str(&self) -> Result<&str, std::str::Utf8Error>{
+        std::str::from_utf8(&self.body)
+    }
+
+    /// The body as an owned `String`; see `body_as_str`.
+    pub fn body_as_string(&self) -> Result<String, std::string::FromUtf8Error>{
+        String::from_utf8(self.body.clone())
+    }
+
+    /// The fields of an `application/x-www-form-urlencoded` body, in
+    /// order; see `encoding::parse_form`. `None` if the request has
+    /// another `Content-Type`, or the body doesn't decode.
+    pub fn form(&self) -> Option<Vec<(String, String)>>{
+        let content_type = self.header("Content-Type")?;
+        let essence = content_type.split(';').next().unwrap_or("").trim();
+        if !essence.eq_ignore_ascii_case("application/x-www-form-urlencoded"){
+            return None;
+        }
+        encoding::parse_form(self.body_as_str().ok()?)
+    }
+
+    /// Whether the client wants the connection kept open after this
+    /// exchange: HTTP/1.1 does unless it sent `Connection: close`, and
+    /// HTTP/1.0 only if it sent `Connection: keep-alive`.
+    pub fn wants_keep_alive(&self) -> bool{
+        match self.version{
+            HttpVersion::Http10 => self.headers.has_token("Connection", "keep-alive"),
+            HttpVersion::Http11 => !self.headers.has_token("Connection", "close"),
+        }
+    }
+
+    /// Pick one of `offered` by this request's `Accept` header (see
+    /// `negotiate_content_type`), noting that the response depends on it.
+    pub fn negotiate_content_type<'a>(&self, offered: &[&'a str]) -> Option<&'a str>{
+        self.set_used_accept_negotiation();
+        negotiate_content_type(self.header("Accept").unwrap_or(""), offered)
+    }
+
+    /// Record that the response was chosen using the `Accept` header.
+    ///
+    /// The flag is shared with every clone of this request, so middleware
+    /// sees it even though handlers get their own copy.
+    pub fn set_used_accept_negotiation(&self){
+        self.negotiated.set();
+    }
+
+    /// Whether the response was chosen using the `Accept` header.
+    pub fn used_accept_negotiation(&self) -> bool{
+        self.negotiated.get()
+    }
+
+    /// The lowercase hex SHA-256 of the body, when `BodyHashMiddleware`
+    /// has worked it out.
+    pub fn body_hash(&self) -> Option<&str>{
+        self.body_hash.get().map(String::as_str)
+    }
+
+    // Record the body's hash; it can be set once and is kept by clones.
+    pub(crate) fn set_body_hash(&self, hash: String){
+        let _ = self.body_hash.set(hash);
+    }
+
+    /// Typed values passed along with the request, from middleware to
+    /// handlers and back; see `Extensions`.
+    pub fn extensions(&self) -> &Extensions{
+        &self.extensions
+    }
+
+    /// The request target as it appears on the request line.
+    pub fn target(&self) -> String{
+        match &self.query{
+            Some(q) => format!("{}?{}", self.path, q),
+            None => self.path.clone(),
+        }
+    }
+
+    /// The target as the client sent it, before normalizing, for logs.
+    pub fn original_target(&self) -> String{
+        let path = self.original_path.as_deref().unwrap_or(&self.path);
+        match &self.query{
+            Some(q) => format!("{}?{}", path, q),
+            None => path.to_string(),
+        }
+    }
+
+    /// Serialise the request in wire format.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()>{
+        write!(w, "{} {} {}\r\n", self.method, self.target(), self.version)?;
+        for (name, value) in self.headers.iter(){
+            write!(w, "{}: {}\r\n", name, value)?;
+        }
+        w.write_all(b"\r\n")?;
+        w.write_all(&self.body)
+    }
+}
+
+/// Takes over a connection once a `101 Switching Protocols` response (or
+/// a `200` to `CONNECT`) has been written to it.
+#[derive(Clone)]
+pub struct Upgrade(Arc<dyn Fn(TcpStream) + Send + Sync>);
+
+impl Upgrade{
+    pub fn new<F>(f: F) -> Upgrade
+    where
+        F: Fn(TcpStream) + Send + Sync + 'static
+    {
+        Upgrade(Arc::new(f))
+    }
+
+    /// Hand the raw connection to the new protocol.
+    pub fn run(&self, stream: TcpStream){
+        (self.0)(stream)
+    }
+}
+
+impl fmt::Debug for Upgrade{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        f.write_str("Upgrade")
+    }
+}
+
+impl PartialEq for Upgrade{
+    fn eq(&self, other: &Upgrade) -> bool{
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Upgrade {}
+
+/// Produces a response body while it is being sent, for responses that
+/// stream (server-sent events, long downloads) rather than buffer.
+///
+/// The function gets the connection right after the head has been
+/// written and keeps it until it returns.
+#[derive(Clone)]
+pub struct StreamBody(Arc<Box<StreamFn>>);     // Boxed so `Body`, and so `Response`, stays small.
+
+type StreamFn = dyn Fn(&mut dyn Write) -> io::Result<()> + Send + Sync;
+
+impl StreamBody{
+    pub fn new<F>(f: F) -> StreamBody
+    where
+        F: Fn(&mut dyn Write) -> io::Result<()> + Send + Sync + 'static
+    {
+        StreamBody(Arc::new(Box::new(f)))
+    }
+
+    /// Write the body to `w`.
+    pub fn run(&self, w: &mut dyn Write) -> io::Result<()>{
+        (self.0)(w)
+    }
+}
+
+impl fmt::Debug for StreamBody{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        f.write_str("StreamBody")
+    }
+}
+
+impl PartialEq for StreamBody{
+    fn eq(&self, other: &StreamBody) -> bool{
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for StreamBody {}
+
+/// A response body that isn't held in `Response::body`, but produced as
+/// it's sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body{
+    Stream(StreamBody),     // Written by a function; the connection closes after.
+    Iter(IterBody),         // Pulled from an iterator a chunk at a time.
+}
+
+/// Produces a response body a chunk at a time from an iterator, so a big
+/// one (a CSV export, say) never has to be in memory whole.
+///
+/// It goes out with chunked encoding, so the connection can be reused,
+/// or close-delimited to an HTTP/1.0 client; `Response::finalize` picks.
+/// Writing stops at the first failed write, such as when the client has
+/// gone, and the iterator is dropped part way through, so producers must
+/// cope with not being run to the end. Empty chunks are skipped.
+///
+/// The iterator can only be run once: clones share it, and whichever is
+/// sent second has an empty body.
+#[derive(Clone)]
+pub struct IterBody{
+    chunks: Arc<Mutex<Option<Box<ChunkIter>>>>,
+    chunked: bool,
+}
+
+type ChunkIter = dyn Iterator<Item = Vec<u8>> + Send;
+
+impl IterBody{
+    pub fn new<I>(chunks: I) -> IterBody
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static
+    {
+        IterBody { chunks: Arc::new(Mutex::new(Some(Box::new(chunks.into_iter())))), chunked: true }
+    }
+
+    /// Whether the body is sent with chunked encoding rather than ended by
+    /// closing the connection.
+    pub fn is_chunked(&self) -> bool{
+        self.chunked
+    }
+
+    /// Send the body close-delimited instead of chunked.
+    pub fn close_delimited(mut self) -> IterBody{
+        self.chunked = false;
+        self
+    }
+
+    /// Write the body to `w`, if it hasn't been already.
+    pub fn run(&self, w: &mut dyn Write) -> io::Result<()>{
+        let chunks = self.chunks.lock().unwrap_or_else(|e| e.into_inner()).take();
+        match chunks{
+            Some(mut chunks) => chunked::write_chunks(w, &mut chunks, self.chunked),
+            None => chunked::write_chunks(w, &mut std::iter::empty(), self.chunked),
+        }
+    }
+}
+
+impl fmt::Debug for IterBody{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        f.debug_struct("IterBody").field("chunked", &self.chunked).finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for IterBody{
+    fn eq(&self, other: &IterBody) -> bool{
+        Arc::ptr_eq(&self.chunks, &other.chunks) && self.chunked == other.chunked
+    }
+}
+
+impl Eq for IterBody {}
+
+/// Builds a `Response` a part at a time, for code that decides on its
+/// headers as it goes.
+///
+/// Unlike `Response::with_header`, which replaces, `header` appends, so a
+/// field can be given more than once (`Set-Cookie`, say). Framing headers
+/// are never needed: `Response::write_to` derives them from the body.
+#[derive(Debug)]
+pub struct ResponseBuilder{
+    response: Response,
+}
+
+impl ResponseBuilder{
+    pub fn new(status: u16, reason: &str) -> ResponseBuilder{
+        ResponseBuilder { response: Response::new(status, reason) }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> ResponseBuilder{
+        self.response.headers.append(name, value);
+        self
+    }
+
+    pub fn body<B: Into<Vec<u8>>>(mut self, bytes: B) -> ResponseBuilder{
+        self.response.body = bytes.into();
+        self
+    }
+
+    /// Add `field` to `Vary`; see `Response::vary`.
+    pub fn vary(mut self, field: &str) -> ResponseBuilder{
+        self.response.vary(field);
+        self
+    }
+
+    pub fn build(self) -> Response{
+        self.response
+    }
+}
+
+/// An HTTP response, with either a fully buffered body or a streamed one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response{
+    pub status: u16,
+    pub reason: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    pub upgrade: Option<Upgrade>,   // Run with the connection after the response is sent.
+    pub stream: Option<Body>,       // When set, replaces `body`.
+    pub(crate) head_only: bool,     // Answers a `HEAD` request: the head describes the body, which isn't sent.
+    pub(crate) extensions: Extensions,  // Shared with every clone.
+}
+
+impl Response{
+    /// A `200` HTML page rendered from the template at `path` with
+    /// `vars`; see `templates::render`. The file is cached until it
+    /// changes on disk.
+    pub fn html_template<P: AsRef<Path>>(path: P, vars: &HashMap<&str, String>) -> io::Result<Response>{
+        let template = templates::load(path.as_ref())?;
+        Ok(Response::new(200, reason_phrase(200))
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(templates::render(&template, vars)))
+    }
+
+    /// Start building a response; see `ResponseBuilder`.
+    pub fn builder(status: u16, reason: &str) -> ResponseBuilder{
+        ResponseBuilder::new(status, reason)
+    }
+
+    pub fn new(status: u16, reason: &str) -> Response{
+        Response {
+            status,
+            reason: reason.to_string(),
+            headers: Headers::new(),
+            body: Vec::new(),
+            upgrade: None,
+            stream: None,
+            head_only: false,
+            extensions: Extensions::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Response{
+        self.headers.set(name, value);
+        self
+    }
+
+    pub fn with_body<B: Into<Vec<u8>>>(mut self, body: B) -> Response{
+        self.body = body.into();
+        self
+    }
+
+    /// Add `field` to `Vary`, keeping whatever other layers put there:
+    /// the fields are merged into one comma-separated value in the order
+    /// they were added, each once whatever its case, and `Vary: *`
+    /// already covers everything.
+    pub fn vary(&mut self, field: &str){
+        if self.headers.has_token("Vary", "*") || self.headers.has_token("Vary", field){
+            return;
+        }
+        let mut fields: Vec<&str> = self.headers.get_all("Vary")
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect();
+        fields.push(field);
+        let value = fields.join(", ");
+        self.headers.set("Vary", &value);
+    }
+
+    pub fn with_vary(mut self, field: &str) -> Response{
+        self.vary(field);
+        self
+    }
+
+    /// Have browsers save the body as `filename` instead of showing it,
+    /// with `Content-Disposition: attachment`.
+    ///
+    /// A plain ASCII name goes in `filename` as it is. Anything else gets
+    /// an ASCII stand-in there, with `_` for each other character, and
+    /// the real name RFC 5987-encoded in `filename*`, which browsers
+    /// prefer when they understand it.
+    pub fn as_attachment(mut self, filename: &str) -> Response{
+        let fallback: String = filename.chars()
+            .filter(|c| !c.is_control())
+            .map(|c| if c.is_ascii() { c } else { '_' })
+            .collect();
+        let quoted = fallback.replace('\\', "\\\\").replace('"', "\\\"");
+        let value = if fallback == filename{
+            format!("attachment; filename=\"{}\"", quoted)
+        } else {
+            format!("attachment; filename=\"{}\"; filename*={}", quoted, encoding::rfc5987_encode(filename))
+        };
+        self.headers.set("Content-Disposition", &value);
+        self
+    }
+
+    /// Switch protocols after this response: the connection is handed to
+    /// `upgrade` instead of being closed or reused for HTTP.
+    pub fn with_upgrade(mut self, upgrade: Upgrade) -> Response{
+        self.upgrade = Some(upgrade);
+        self
+    }
+
+    /// Stream the body instead of buffering it.
+    ///
+    /// A streamed response has no `Content-Length`; its end is marked by
+    /// closing the connection, so it is always sent with `Connection: close`.
+    pub fn with_stream(mut self, stream: StreamBody) -> Response{
+        self.stream = Some(Body::Stream(stream));
+        self
+    }
+
+    /// A response whose body is pulled from `chunks` as it's sent; see
+    /// `IterBody`.
+    pub fn from_iter<I>(status: u16, content_type: &str, chunks: I) -> Response
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static
+    {
+        let mut response = Response::new(status, reason_phrase(status)).with_header("Content-Type", content_type);
+        response.stream = Some(Body::Iter(IterBody::new(chunks)));
+        response
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str>{
+        self.headers.get(name)
+    }
+
+    /// Typed values passed back with the response, from the handler to
+    /// the middleware wrapping it; see `Extensions`.
+    pub fn extensions(&self) -> &Extensions{
+        &self.extensions
+    }
+
+    /// Settle the connection headers for answering `request`.
+    ///
+    /// The response says `Connection: close` unless both sides can keep
+    /// the connection open; an HTTP/1.0 client that asked for keep-alive
+    /// gets `Connection: keep-alive`, which 1.0 needs spelled out.
+    ///
+    /// A response to `HEAD` keeps the framing headers its body would
+    /// have had, its `Content-Length` or chunked `Transfer-Encoding`, but
+    /// the body itself isn't sent, and a streamed one is never run.
+    pub fn finalize(mut self, request: &Request) -> Response{
+        self.head_only = request.method == Method::Head;
+        if self.upgrade.is_some(){
+            return self;    // The protocol switch owns the connection headers.
+        }
+        if let (Some(Body::Iter(body)), HttpVersion::Http10) = (&self.stream, request.version){
+            self.stream = Some(Body::Iter(body.clone().close_delimited()));     // 1.0 has no chunked encoding.
+        }
+        let keep_alive = request.wants_keep_alive() && self.keeps_alive();
+        match (keep_alive, request.version){
+            (false, _) => self.headers.set("Connection", "close"),
+            (true, HttpVersion::Http10) => self.headers.set("Connection", "keep-alive"),
+            (true, HttpVersion::Http11) => {
+                self.headers.remove("Connection");
+            },
+        }
+        self
+    }
+
+    /// Whether the connection can carry another request after this
+    /// response has been sent.
+    pub fn keeps_alive(&self) -> bool{
+        !self.close_delimited() && self.upgrade.is_none() && !self.headers.has_token("Connection", "close")
+    }
+
+    // Whether the body's end is marked by closing the connection.
+    fn close_delimited(&self) -> bool{
+        match &self.stream{
+            None => false,
+            Some(Body::Stream(_)) => true,
+            Some(Body::Iter(body)) => !body.is_chunked(),
+        }
+    }
+
+    /// Serialise the response in wire format.
+    ///
+    /// The status line always says HTTP/1.1, the highest version we
+    /// speak, which 1.0 clients accept. Framing is ours to decide, so
+    /// `Content-Length`, `Transfer-Encoding` and `Trailer` set by hand are
+    /// ignored: a buffered body is sent with its length (informational,
+    /// `204` and `304` responses, which cannot have a body, get neither),
+    /// a streamed body is sent close-delimited with `Connection: close`,
+    /// and an `IterBody` chunked unless it has been made close-delimited.
+    /// `ChunkedResponseWriter` is there for chunked bodies with trailers.
+    ///
+    /// Handler-supplied text can't break out of its line: CR and LF are
+    /// stripped from the reason and header values, and headers whose names
+    /// aren't valid tokens are dropped.
+    ///
+    /// The head is built in a pooled buffer and goes out with the body in
+    /// one vectored write, so the body is never copied.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()>{
+        let mut head = BufferPool::local().get();
+        let has_body = self.write_head(&mut head);
+        if self.head_only{
+            return w.write_all(&head);
+        }
+        match &self.stream{
+            Some(Body::Stream(stream)) => {
+                w.write_all(&head)?;
+                w.flush()?;
+                return stream.run(w);
+            },
+            Some(Body::Iter(body)) if has_body => {
+                w.write_all(&head)?;
+                return body.run(w);
+            },
+            Some(Body::Iter(_)) => return w.write_all(&head),
+            None => {},
+        }
+        let body: &[u8] = if has_body { &self.body } else { &[] };
+        write_all_vectored(w, &mut [IoSlice::new(&head), IoSlice::new(body)])
+    }
+
+    // The status line and headers, through the blank line. Returns whether
+    // a body follows. Writing to a `Vec` can't fail.
+    fn write_head(&self, out: &mut Vec<u8>) -> bool{
+        self.write_fields(out, self.close_delimited());
+        // After a protocol switch or an answer to `CONNECT`, what follows the head isn't HTTP.
+        if self.status / 100 == 1 || self.status == 204 || self.status == 304 || self.upgrade.is_some(){
+            out.extend_from_slice(b"\r\n");
+            return false;
+        }
+        if self.close_delimited(){
+            out.extend_from_slice(b"Connection: close\r\n\r\n");
+        } else if self.stream.is_some(){
+            out.extend_from_slice(b"Transfer-Encoding: chunked\r\n\r\n");
+        } else {
+            let _ = write!(out, "Content-Length: {}\r\n\r\n", self.body.len());
+        }
+        true
+    }
+
+    // The status line and the headers that aren't about framing, leaving
+    // out `Connection` too when the framing needs its own.
+    fn write_fields(&self, out: &mut Vec<u8>, skip_connection: bool){
+        let _ = write!(out, "HTTP/1.1 {} {}\r\n", self.status, strip_line_breaks(&self.reason));
+        for (name, value) in self.headers.iter(){
+            if name.eq_ignore_ascii_case("Content-Length")
+                || name.eq_ignore_ascii_case("Transfer-Encoding")
+                || name.eq_ignore_ascii_case("Trailer")
+                || (skip_connection && name.eq_ignore_ascii_case("Connection"))
+                || !is_token(name){
+                continue;
+            }
+            let _ = write!(out, "{}: {}\r\n", name, strip_line_breaks(value));
+        }
+    }
+}
+
+/// Write every byte of `bufs`, in order, with as few calls as the writer
+/// allows.
+///
+/// Like `Write::write_all`, but for `write_vectored`: a writer may take
+/// any part of what it's offered, ending mid-slice, and one without real
+/// vectored writes takes only from the first non-empty slice. The slices
+/// are advanced past whatever was written, so they're left in an
+/// unspecified state.
+pub fn write_all_vectored<W: Write + ?Sized>(w: &mut W, mut bufs: &mut [IoSlice<'_>]) -> io::Result<()>{
+    skip_empty(&mut bufs);
+    while !bufs.is_empty(){
+        match w.write_vectored(bufs){
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+        skip_empty(&mut bufs);
+    }
+    Ok(())
+}
+
+fn skip_empty(bufs: &mut &mut [IoSlice<'_>]){
+    let empty = bufs.iter().take_while(|buf| buf.is_empty()).count();
+    *bufs = &mut std::mem::take(bufs)[empty..];
+}
+
+/// Writing to a response appends to its buffered body, so handlers can
+/// build it with `write!`. `Content-Length` is worked out when the
+/// response is sent, from whatever the body ends up holding.
+impl Write for Response{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        self.body.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()>{
+        self.body.extend_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+        Ok(())
+    }
+}
+
+/// Something a route handler can return in place of a `Response`.
+///
+/// Bodies without a content type of their own get one: strings are
+/// HTML, and bytes `application/octet-stream`. A bare status has no body,
+/// `None` is a `404`, and an `Err` is its `ServerError`'s response.
+pub trait IntoResponse{
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response{
+    fn into_response(self) -> Response{
+        self
+    }
+}
+
+/// `200`, as HTML.
+impl IntoResponse for String{
+    fn into_response(self) -> Response{
+        (200, self).into_response()
+    }
+}
+
+/// `200`, as HTML.
+impl IntoResponse for &'static str{
+    fn into_response(self) -> Response{
+        self.to_string().into_response()
+    }
+}
+
+/// `200`, as `application/octet-stream`.
+impl IntoResponse for Vec<u8>{
+    fn into_response(self) -> Response{
+        Response::new(200, reason_phrase(200))
+            .with_header("Content-Type", "application/octet-stream")
+            .with_body(self)
+    }
+}
+
+/// The status, with the string as an HTML body.
+impl IntoResponse for (u16, String){
+    fn into_response(self) -> Response{
+        Response::new(self.0, reason_phrase(self.0))
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(self.1)
+    }
+}
+
+/// The status with an empty body.
+impl IntoResponse for u16{
+    fn into_response(self) -> Response{
+        Response::new(self, reason_phrase(self))
+    }
+}
+
+/// `404` for `None`.
+impl<T: IntoResponse> IntoResponse for Option<T>{
+    fn into_response(self) -> Response{
+        match self{
+            Some(value) => value.into_response(),
+            None => Response::new(404, reason_phrase(404))
+                .with_header("Content-Type", "text/plain; charset=utf-8")
+                .with_body("Not found"),
+        }
+    }
+}
+
+/// The error as `ServerError::to_response` has it.
+impl<T: IntoResponse> IntoResponse for Result<T, ServerError>{
+    fn into_response(self) -> Response{
+        match self{
+            Ok(value) => value.into_response(),
+            Err(e) => e.to_response(),
+        }
+    }
+}
+
+/// The status, headers and body as given; `application/octet-stream`
+/// unless the headers say otherwise.
+impl IntoResponse for (u16, HashMap<String, String>, Vec<u8>){
+    fn into_response(self) -> Response{
+        let (status, headers, body) = self;
+        let mut response = Response::new(status, reason_phrase(status)).with_body(body);
+        for (name, value) in &headers{
+            response.headers.set(name, value);
+        }
+        if response.headers.get("Content-Type").is_none(){
+            response.headers.set("Content-Type", "application/octet-stream");
+        }
+        response
+    }
+}
+
+/// Returns the standard reason phrase for a status code.
+pub fn reason_phrase(status: u16) -> &'static str{
+    match status{
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "NOT FOUND",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        414 => "URI Too Long",
+        421 => "Misdirected Request",
+        426 => "Upgrade Required",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+/// Pick the offered content type the client prefers, according to an
+/// `Accept` header. See `negotiation::negotiate` for the rules.
+pub fn negotiate_content_type<'a>(accept: &str, offered: &[&'a str]) -> Option<&'a str>{
+    negotiation::negotiate(accept, offered)
+}
+
+fn check_host(headers: &Headers, version: HttpVersion) -> Result<(), ParseError>{
+    let mut hosts = headers.get_all("Host");
+    match (hosts.next(), hosts.next()){
+        (None, _) if version == HttpVersion::Http11 => Err(ParseError::MissingHost),
+        (Some(host), None) if parse_host(host).is_none() => Err(ParseError::InvalidHost),
+        (Some(_), Some(_)) => Err(ParseError::InvalidHost),
+        _ => Ok(()),
+    }
+}
+
+/// Split a `Host` value into host and port, or `None` if it isn't a valid
+/// `host[:port]`. An empty value is allowed (for targets without a host)
+/// and comes back as `("", None)`, as does an empty port.
+pub fn parse_host(value: &str) -> Option<(&str, Option<u16>)>{
+    let value = value.trim();
+    let (host, port) = if value.starts_with('['){
+        let end = value.find(']')?;
+        let port = match &value[end + 1..]{
+            "" => None,
+            rest => Some(rest.strip_prefix(':')?),
+        };
+        let literal = &value[1..end];
+        if literal.is_empty() || !literal.chars().all(|c| c.is_ascii_hexdigit() || c == ':' || c == '.'){
+            return None;
+        }
+        (&value[..=end], port)
+    } else {
+        let (host, port) = match value.rsplit_once(':'){
+            Some((host, port)) => (host, Some(port)),
+            None => (value, None),
+        };
+        let allowed = |c: char| c.is_ascii_alphanumeric() || "-._~%!$&'()*+,;=".contains(c);
+        if !host.chars().all(allowed){
+            return None;
+        }
+        (host, port)
+    };
+    let port = match port{
+        None | Some("") => None,
+        Some(port) if port.bytes().all(|b| b.is_ascii_digit()) => Some(port.parse().ok()?),
+        Some(_) => return None,
+    };
+    Some((host, port))
+}
+
+/// Returns the offset of the `\r\n\r\n` that ends the header block.
+pub fn find_header_end(buf: &[u8]) -> Option<usize>{
+    buf.windows(4).position(|w| w == b"\r\n\r\n")
+}
+
+// Where the head ends: the offset of the line break before the empty
+// line, and the offset just after the empty line. With `lenient`, either
+// line may end in a bare LF.
+fn header_end(buf: &[u8], lenient: bool) -> Option<(usize, usize)>{
+    if !lenient{
+        return find_header_end(buf).map(|end| (end, end + 4));
+    }
+    buf.iter().enumerate().filter(|(_, &b)| b == b'\n').find_map(|(i, _)| {
+        let blank = match &buf[i + 1..]{
+            [b'\n', ..] => 1,
+            [b'\r', b'\n', ..] => 2,
+            _ => return None,
+        };
+        let end = if i > 0 && buf[i - 1] == b'\r' { i - 1 } else { i };
+        Some((end, i + 1 + blank))
+    })
+}
+
+/// The body length the headers declare; no `Content-Length` means none.
+///
+/// Every `Content-Length` must be digits only, and a repeated one (or a
+/// list of them in one field) must agree, or it's
+/// `ParseError::ConflictingLength` (RFC 9110, section 8.6).
+pub(crate) fn declared_body_len(headers: &Headers) -> Result<usize, ParseError>{
+    let mut declared = None;
+    for len in headers.get_all("Content-Length").flat_map(|value| value.split(',')).map(str::trim){
+        if len.is_empty() || !len.bytes().all(|b| b.is_ascii_digit()){
+            return Err(ParseError::InvalidHeader);
+        }
+        let len: usize = len.parse().map_err(|_| ParseError::PayloadTooLarge)?;
+        if declared.is_some_and(|declared| declared != len){
+            return Err(ParseError::ConflictingLength);
+        }
+        declared = Some(len);
+    }
+    Ok(declared.unwrap_or(0))
+}
+
+/// Whether the body is chunked: a `Transfer-Encoding` of `chunked` and
+/// nothing else, which overrides any `Content-Length` (RFC 9112, section
+/// 6.3). Other codings aren't supported; `chunked` anywhere but last
+/// leaves the body's end unknowable.
+pub(crate) fn is_chunked(headers: &Headers) -> Result<bool, ParseError>{
+    let codings: Vec<&str> = headers.get_all("Transfer-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty())
+        .collect();
+    match codings.as_slice(){
+        [] => Ok(false),
+        [coding] if coding.eq_ignore_ascii_case("chunked") => Ok(true),
+        [.., last] if !last.eq_ignore_ascii_case("chunked") => Err(ParseError::InvalidHeader),
+        _ => Err(ParseError::UnsupportedTransferCoding),
+    }
+}
+
+/// Parse `name: value` lines up to the end of the iterator.
+pub(crate) fn parse_header_lines<'a, I>(lines: I) -> Result<Headers, ParseError>
+where
+    I: Iterator<Item = &'a str>
+{
+    let mut headers = Headers::new();
+    for line in lines{
+        let (name, value) = line.split_once(':').ok_or(ParseError::InvalidHeader)?;
+        // Anything but a token could be trimmed or read differently by
+        // the next parser along: `Content-Length\x0b` must not slip past
+        // as a header of its own.
+        if !is_token(name){
+            return Err(ParseError::InvalidHeader);
+        }
+        // A stray CR or NUL could end the value early for whatever reads it next.
+        if value.contains(['\r', '\n', '\0']){
+            return Err(ParseError::InvalidHeader);
+        }
+        headers.append(name, value.trim());
+    }
+    Ok(headers)
+}
+
+/// Whether `name` is a valid header field name (an RFC 9110 token).
+fn is_token(name: &str) -> bool{
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn strip_line_breaks(text: &str) -> std::borrow::Cow<'_, str>{
+    if text.contains(['\r', '\n']){
+        text.replace(['\r', '\n'], "").into()
+    } else {
+        text.into()
+    }
+}
+
+// Path and query of a target in `form`. Absolute-form keeps only what
+// follows the authority, `/` if nothing does; the other forms without a
+// path keep the target whole.
+fn split_target(target: &str, form: TargetForm) -> (String, Option<String>){
+    let target = match form{
+        TargetForm::Origin => target,
+        TargetForm::Absolute => split_absolute(target).map_or(target, |(_, _, rest)| rest),
+        TargetForm::Authority | TargetForm::Asterisk => return (target.to_string(), None),
+    };
+    let (path, query) = match target.split_once('?'){
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+    let path = if path.is_empty() && form == TargetForm::Absolute { "/" } else { path };
+    (path.to_string(), query)
+}
+
+// Scheme and authority of a target in `form`, where it has them.
+fn target_authority(target: &str, form: TargetForm) -> (Option<String>, Option<String>){
+    match form{
+        TargetForm::Absolute => split_absolute(target).map_or((None, None), |(scheme, authority, _)| {
+            (Some(scheme.to_ascii_lowercase()), Some(authority.to_string()))
+        }),
+        TargetForm::Authority => (None, Some(target.to_string())),
+        TargetForm::Origin | TargetForm::Asterisk => (None, None),
+    }
+}
+
+// Split an absolute-form target into scheme, authority and the rest (path
+// and query, possibly empty).
+fn split_absolute(target: &str) -> Option<(&str, &str, &str)>{
+    let (scheme, rest) = target.split_once("://")?;
+    let mut chars = scheme.chars();
+    let valid_scheme = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+    if !valid_scheme{
+        return None;
+    }
+    let end = rest.find(['/', '?']).unwrap_or(rest.len());
+    let (authority, rest) = rest.split_at(end);
+    if authority.is_empty(){
+        return None;
+    }
+    Some((scheme, authority, rest))
+}
@@ -0,0 +1,227 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read, Take},
+};
+
+use crate::http::{parse_header_lines, Headers, Limits};
+
+/// Longest chunk-size line accepted, extensions included.
+const MAX_CHUNK_LINE: usize = 1024;
+
+/// Consumed input kept in the buffer before it is dropped.
+const COMPACT_AFTER: usize = 64 * 1024;
+
+/// Why a request body could not be read.
+#[derive(Debug)]
+pub enum BodyError{
+    TooLarge,           // More than `max_body_bytes`.
+    Truncated,          // The stream ended before the declared length, or the last chunk.
+    Malformed,          // Chunked framing or trailer fields that don't parse.
+    Io(io::Error),
+}
+
+impl BodyError{
+    /// The status code to answer with, if the connection is still usable
+    /// enough to answer at all.
+    pub fn status(&self) -> Option<u16>{
+        match self{
+            BodyError::TooLarge => Some(413),
+            BodyError::Truncated | BodyError::Malformed => Some(400),
+            BodyError::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for BodyError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            BodyError::TooLarge => write!(f, "request body too large"),
+            BodyError::Truncated => write!(f, "request body ended early"),
+            BodyError::Malformed => write!(f, "malformed chunked request body"),
+            BodyError::Io(e) => write!(f, "could not read request body: {}", e),
+        }
+    }
+}
+
+impl Error for BodyError {}
+
+impl From<io::Error> for BodyError{
+    fn from(e: io::Error) -> BodyError{
+        BodyError::Io(e)
+    }
+}
+
+/// Reads a request body without ever holding more than `max_body_bytes`
+/// of it.
+///
+/// The stream is wrapped in `take`, so reading stops at the limit no
+/// matter what the client sends. When the limit is reached before the
+/// body's end, one more byte is probed for: if there is one the body was
+/// too big, which is an error rather than a silently shortened body.
+pub struct RequestBodyReader<R: Read>{
+    inner: Take<R>,
+}
+
+impl<R: Read> RequestBodyReader<R>{
+    pub fn new(inner: R, max_body_bytes: usize) -> RequestBodyReader<R>{
+        RequestBodyReader { inner: inner.take(max_body_bytes as u64) }
+    }
+
+    /// Read the rest of a body `len` bytes long onto `body`, which may
+    /// already hold its start. Bytes already in `body` count towards the
+    /// limit, and nothing past the body's end is read.
+    pub fn read_exact_len(mut self, len: usize, body: &mut Vec<u8>) -> Result<(), BodyError>{
+        if len as u64 > self.inner.limit() || body.len() > len{
+            return Err(BodyError::TooLarge);
+        }
+        self.inner.set_limit((len - body.len()) as u64);
+        self.inner.read_to_end(body)?;
+        if body.len() < len{
+            return Err(BodyError::Truncated);
+        }
+        Ok(())
+    }
+
+    /// Read a body that runs to the end of the stream onto `body`.
+    pub fn read_to_end(mut self, body: &mut Vec<u8>) -> Result<(), BodyError>{
+        let limit = self.inner.limit().checked_sub(body.len() as u64).ok_or(BodyError::TooLarge)?;
+        self.inner.set_limit(limit);
+        self.inner.read_to_end(body)?;
+        if self.inner.limit() == 0{
+            // Exactly at the limit is fine; a byte past it is not.
+            let mut probe = [0; 1];
+            let stream = self.inner.get_mut();
+            loop{
+                match stream.read(&mut probe){
+                    Ok(0) => break,
+                    Ok(_) => return Err(BodyError::TooLarge),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read a chunked request body (RFC 9112, section 7.1), starting with
+/// whatever of it is in `buffer` and reading the rest from `stream`.
+/// Returns the decoded body and the trailer fields.
+///
+/// Reading stops right after the trailer section, so the bytes of a
+/// pipelined request behind it are left in `buffer`. A decoded body over
+/// `max_body_bytes`, or trailers over the header limits, is `TooLarge`.
+/// Chunk extensions are ignored.
+pub fn read_chunked_body<S: Read>(stream: &mut S, buffer: &mut Vec<u8>, limits: &Limits) -> Result<(Vec<u8>, Headers), BodyError>{
+    let mut input = Input { stream, buffer, pos: 0 };
+    let decoded = decode_chunked(&mut input, limits);
+    let used = input.pos;
+    buffer.drain(..used);
+    decoded
+}
+
+fn decode_chunked<S: Read>(input: &mut Input<'_, S>, limits: &Limits) -> Result<(Vec<u8>, Headers), BodyError>{
+    let mut body = Vec::new();
+    loop{
+        let line = input.line(MAX_CHUNK_LINE)?;
+        let size = line.split(';').next().unwrap_or("").trim_end_matches([' ', '\t']);
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()){
+            return Err(BodyError::Malformed);
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| BodyError::TooLarge)?;
+        if size == 0{
+            break;
+        }
+        if size > limits.max_body_bytes - body.len(){
+            return Err(BodyError::TooLarge);
+        }
+        input.copy(size, &mut body)?;
+        if !input.line(MAX_CHUNK_LINE)?.is_empty(){
+            return Err(BodyError::Malformed);
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut trailer_bytes = 0;
+    loop{
+        let line = input.line(limits.max_header_line)?;
+        if line.is_empty(){
+            break;
+        }
+        trailer_bytes += line.len() + 2;
+        if lines.len() == limits.max_headers || trailer_bytes > limits.max_header_bytes{
+            return Err(BodyError::TooLarge);
+        }
+        lines.push(line);
+    }
+    let trailers = parse_header_lines(lines.iter().map(String::as_str)).map_err(|_| BodyError::Malformed)?;
+    Ok((body, trailers))
+}
+
+// The connection's read buffer from `pos` on, topped up from the stream.
+struct Input<'a, S>{
+    stream: &'a mut S,
+    buffer: &'a mut Vec<u8>,
+    pos: usize,     // Bytes of `buffer` used so far.
+}
+
+impl<S: Read> Input<'_, S>{
+    // The next CRLF-terminated line, without the CRLF; longer than `max`
+    // is `TooLarge`.
+    fn line(&mut self, max: usize) -> Result<String, BodyError>{
+        let mut scanned = self.pos;
+        let end = loop{
+            if let Some(offset) = self.buffer[scanned..].iter().position(|&b| b == b'\n'){
+                break scanned + offset;
+            }
+            scanned = self.buffer.len();
+            if scanned - self.pos > max + 1{
+                return Err(BodyError::TooLarge);
+            }
+            scanned -= self.fill()?;
+        };
+        let line = &self.buffer[self.pos..end];
+        let line = line.strip_suffix(b"\r").ok_or(BodyError::Malformed)?;
+        if line.len() > max{
+            return Err(BodyError::TooLarge);
+        }
+        let line = String::from_utf8(line.to_vec()).map_err(|_| BodyError::Malformed)?;
+        self.pos = end + 1;
+        Ok(line)
+    }
+
+    // Move the next `len` bytes onto `out`.
+    fn copy(&mut self, mut len: usize, out: &mut Vec<u8>) -> Result<(), BodyError>{
+        while len > 0{
+            if self.pos == self.buffer.len(){
+                self.fill()?;
+            }
+            let n = len.min(self.buffer.len() - self.pos);
+            out.extend_from_slice(&self.buffer[self.pos..self.pos + n]);
+            self.pos += n;
+            len -= n;
+        }
+        Ok(())
+    }
+
+    // Read more onto the buffer, first dropping what's been used if that's
+    // grown large. Returns how many bytes were dropped from the front.
+    fn fill(&mut self) -> Result<usize, BodyError>{
+        let dropped = if self.pos >= COMPACT_AFTER { self.pos } else { 0 };
+        self.buffer.drain(..dropped);
+        self.pos -= dropped;
+        let mut chunk = [0; 8 * 1024];
+        loop{
+            match self.stream.read(&mut chunk){
+                Ok(0) => return Err(BodyError::Truncated),
+                Ok(n) => {
+                    self.buffer.extend_from_slice(&chunk[..n]);
+                    return Ok(dropped);
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
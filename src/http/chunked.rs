@@ -0,0 +1,113 @@
+use std::{
+    io::{self, BufWriter, IoSlice, Write},
+    time::{Duration, Instant},
+};
+
+use super::{is_token, strip_line_breaks, write_all_vectored, Headers, Response};
+
+/// Writes a response with a chunked body, straight to a connection, so
+/// headers can follow the body as trailers: a checksum of what was sent,
+/// say, or timings only known at the end.
+///
+/// `new` sends the head, with `Transfer-Encoding: chunked` and a
+/// `Trailer` header naming the trailers to come. Every write is one chunk.
+/// `finish` sends the last, empty chunk and then the trailers given to
+/// `add_trailer`. Dropping the writer without finishing leaves the body
+/// unterminated, as the client will notice.
+///
+/// Chunked encoding is HTTP/1.1; don't use it to answer 1.0 requests.
+pub struct ChunkedResponseWriter<W: Write>{
+    inner: W,
+    trailers: Headers,
+}
+
+impl<W: Write> ChunkedResponseWriter<W>{
+    /// Send `response`'s status line and headers to `inner`, announcing
+    /// `trailer_names`. The response's own body is not sent.
+    pub fn new(mut inner: W, response: &Response, trailer_names: &[&str]) -> io::Result<ChunkedResponseWriter<W>>{
+        let mut head = Vec::new();
+        response.write_fields(&mut head, false);
+        let names: Vec<&str> = trailer_names.iter().copied().filter(|name| allowed_trailer(name)).collect();
+        if !names.is_empty(){
+            head.extend_from_slice(format!("Trailer: {}\r\n", names.join(", ")).as_bytes());
+        }
+        head.extend_from_slice(b"Transfer-Encoding: chunked\r\n\r\n");
+        inner.write_all(&head)?;
+        Ok(ChunkedResponseWriter { inner, trailers: Headers::new() })
+    }
+
+    /// Send `name: value` after the body. Framing headers, and names that
+    /// aren't valid tokens, are left out.
+    pub fn add_trailer(&mut self, name: &str, value: &str){
+        if allowed_trailer(name){
+            self.trailers.append(name, &strip_line_breaks(value));
+        }
+    }
+
+    /// End the body and send the trailers, handing back the connection.
+    pub fn finish(mut self) -> io::Result<W>{
+        let mut tail = b"0\r\n".to_vec();
+        for (name, value) in self.trailers.iter(){
+            tail.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        tail.extend_from_slice(b"\r\n");
+        self.inner.write_all(&tail)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedResponseWriter<W>{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        // An empty chunk would end the body.
+        if buf.is_empty(){
+            return Ok(0);
+        }
+        let size = format!("{:x}\r\n", buf.len());
+        write_all_vectored(&mut self.inner, &mut [IoSlice::new(size.as_bytes()), IoSlice::new(buf), IoSlice::new(b"\r\n")])?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+        self.inner.flush()
+    }
+}
+
+/// How long chunks of an `IterBody` may sit in the write buffer before
+/// they're sent anyway, for iterators that are slow to produce the next.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Send `chunks` to `w` as a body, chunked or as they are, through a
+// buffer so small chunks don't each cost a write. Stops at the first
+// failed write.
+pub(super) fn write_chunks(w: &mut dyn Write, chunks: &mut dyn Iterator<Item = Vec<u8>>, chunked: bool) -> io::Result<()>{
+    let mut out = BufWriter::with_capacity(16 * 1024, w);
+    let mut flushed = Instant::now();
+    for chunk in chunks.filter(|chunk| !chunk.is_empty()){
+        if chunked{
+            write!(out, "{:x}\r\n", chunk.len())?;
+            out.write_all(&chunk)?;
+            out.write_all(b"\r\n")?;
+        } else {
+            out.write_all(&chunk)?;
+        }
+        if flushed.elapsed() >= FLUSH_INTERVAL{
+            out.flush()?;
+            flushed = Instant::now();
+        }
+    }
+    if chunked{
+        out.write_all(b"0\r\n\r\n")?;
+    }
+    out.flush()
+}
+
+// Fields that say how to frame, route or authenticate a message can't be
+// trailers (RFC 9110, section 6.5.1).
+fn allowed_trailer(name: &str) -> bool{
+    const FORBIDDEN: [&str; 10] = [
+        "Content-Length", "Transfer-Encoding", "Trailer", "Content-Type", "Content-Encoding",
+        "Content-Range", "Host", "Cache-Control", "Authorization", "Set-Cookie",
+    ];
+    is_token(name) && !FORBIDDEN.iter().any(|forbidden| forbidden.eq_ignore_ascii_case(name))
+}
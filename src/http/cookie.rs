@@ -0,0 +1,131 @@
+use std::{error::Error, fmt, time::SystemTime};
+
+use crate::{
+    http::{is_token, Response},
+    timeutil,
+};
+
+/// Whether a cookie goes along with requests from other sites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SameSite{
+    Strict,     // Only with requests from this site.
+    Lax,        // Also with top-level navigations from elsewhere.
+    None,       // With every request; browsers insist on `Secure` too.
+}
+
+impl SameSite{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// The attributes of a `Set-Cookie`; the default is a session cookie
+/// with none of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CookieOptions{
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub max_age: Option<u64>,       // Seconds; 0 deletes the cookie.
+    pub expires: Option<SystemTime>,    // For old clients without `Max-Age`, which wins where both are known.
+    pub secure: bool,               // Only sent over HTTPS.
+    pub http_only: bool,            // Hidden from scripts.
+    pub same_site: Option<SameSite>,
+}
+
+/// Why a cookie can't be set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieError{
+    InvalidName,        // Not a token.
+    InvalidValue,       // Has a character a cookie value can't (whitespace, `"`, `,`, `;`, `\`, controls).
+    InvalidAttribute,   // A `Path` or `Domain` with a `;` or control character in it.
+    InvalidPrefix,      // A `__Host-` or `__Secure-` name without the attributes the prefix promises.
+}
+
+impl fmt::Display for CookieError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            CookieError::InvalidName => write!(f, "invalid cookie name"),
+            CookieError::InvalidValue => write!(f, "invalid cookie value"),
+            CookieError::InvalidAttribute => write!(f, "invalid cookie attribute"),
+            CookieError::InvalidPrefix => write!(f, "cookie attributes don't meet its name's prefix"),
+        }
+    }
+}
+
+impl Error for CookieError {}
+
+/// The `Set-Cookie` value for `name=value` with `options` (RFC 6265,
+/// section 4.1).
+///
+/// The name prefixes browsers enforce are checked here first, so a
+/// cookie they'd drop is an error instead (RFC 6265bis, section 4.1.3):
+/// a `__Secure-` cookie must be `Secure`, and a `__Host-` one must also
+/// have `Path=/` and no `Domain`, tying it to this host alone.
+pub fn set_cookie_value(name: &str, value: &str, options: &CookieOptions) -> Result<String, CookieError>{
+    if !is_token(name){
+        return Err(CookieError::InvalidName);
+    }
+    let unquoted = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+    if !unquoted.bytes().all(is_cookie_octet){
+        return Err(CookieError::InvalidValue);
+    }
+    check_prefix(name, options)?;
+
+    let mut cookie = format!("{}={}", name, value);
+    for (attribute, text) in [("Path", &options.path), ("Domain", &options.domain)]{
+        if let Some(text) = text{
+            if text.bytes().any(|b| b == b';' || b.is_ascii_control()){
+                return Err(CookieError::InvalidAttribute);
+            }
+            cookie.push_str(&format!("; {}={}", attribute, text));
+        }
+    }
+    if let Some(max_age) = options.max_age{
+        cookie.push_str(&format!("; Max-Age={}", max_age));
+    }
+    if let Some(expires) = options.expires{
+        cookie.push_str(&format!("; Expires={}", timeutil::format_cookie_expires(expires)));
+    }
+    if options.secure{
+        cookie.push_str("; Secure");
+    }
+    if options.http_only{
+        cookie.push_str("; HttpOnly");
+    }
+    if let Some(same_site) = options.same_site{
+        cookie.push_str(&format!("; SameSite={}", same_site.as_str()));
+    }
+    Ok(cookie)
+}
+
+impl Response{
+    /// Add a `Set-Cookie` header for `name=value`; it is appended, so
+    /// several cookies can be set. See `set_cookie_value` for what makes
+    /// a cookie invalid.
+    pub fn set_cookie(&mut self, name: &str, value: &str, options: &CookieOptions) -> Result<(), CookieError>{
+        let cookie = set_cookie_value(name, value, options)?;
+        self.headers.append("Set-Cookie", &cookie);
+        Ok(())
+    }
+}
+
+fn check_prefix(name: &str, options: &CookieOptions) -> Result<(), CookieError>{
+    let host_bound = options.path.as_deref() == Some("/") && options.domain.is_none();
+    let kept = if name.starts_with("__Host-"){
+        options.secure && host_bound
+    } else if name.starts_with("__Secure-"){
+        options.secure
+    } else {
+        true
+    };
+    if kept { Ok(()) } else { Err(CookieError::InvalidPrefix) }
+}
+
+// `cookie-octet`: printable ASCII but for `"`, `,`, `;` and `\`.
+fn is_cookie_octet(b: u8) -> bool{
+    matches!(b, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e)
+}
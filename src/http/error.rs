@@ -0,0 +1,72 @@
+use std::{error::Error, fmt, io};
+
+use crate::{
+    http::{reason_phrase, Response},
+    json::JsonError,
+    log,
+};
+
+/// Why a handler couldn't answer, for handlers that return a `Result` and
+/// use `?`.
+///
+/// A client error's message is sent as the body; a server error's is
+/// only logged, and the client gets the reason phrase, so internals don't
+/// leak out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerError{
+    pub status: u16,
+    pub message: String,
+}
+
+impl ServerError{
+    pub fn new<M: Into<String>>(status: u16, message: M) -> ServerError{
+        ServerError { status, message: message.into() }
+    }
+
+    pub fn bad_request<M: Into<String>>(message: M) -> ServerError{
+        ServerError::new(400, message)
+    }
+
+    pub fn not_found<M: Into<String>>(message: M) -> ServerError{
+        ServerError::new(404, message)
+    }
+
+    pub fn internal<M: Into<String>>(message: M) -> ServerError{
+        ServerError::new(500, message)
+    }
+
+    /// The response sent to the client, as plain text.
+    pub fn to_response(&self) -> Response{
+        let body = if self.status >= 500{
+            log::error(&format!("Handler failed with {}: {}", self.status, self.message));
+            reason_phrase(self.status)
+        } else {
+            self.message.as_str()
+        };
+        Response::new(self.status, reason_phrase(self.status))
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(body)
+    }
+}
+
+impl fmt::Display for ServerError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        write!(f, "{} {}: {}", self.status, reason_phrase(self.status), self.message)
+    }
+}
+
+impl Error for ServerError {}
+
+/// A `500`.
+impl From<io::Error> for ServerError{
+    fn from(e: io::Error) -> ServerError{
+        ServerError::internal(e.to_string())
+    }
+}
+
+/// A `400`: the client sent something that isn't JSON.
+impl From<JsonError> for ServerError{
+    fn from(e: JsonError) -> ServerError{
+        ServerError::bad_request(e.to_string())
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+type Entry = Arc<dyn Any + Send + Sync>;
+
+/// Values of any type, at most one of each, that middleware and handlers
+/// hand each other along with a request: the user an auth check let in,
+/// the quota a rate limiter has left. Responses carry their own, for what
+/// a handler hands back, like the `proxy::ProxyError` behind a `502`.
+///
+/// Values are stored behind an `Arc` and come back as one, so they need
+/// not be `Clone`. Every clone of an `Extensions` (and so of a request)
+/// shares the same values, which is how a handler's copy of the request
+/// sees what middleware put there; that takes `&self`, since middleware
+/// only has a shared reference to the request.
+///
+/// The server puts a few in itself: `server::RequestId` and
+/// `server::ConnectionInfo` before routing, and `router::RouteParams` once
+/// a route matches.
+#[derive(Clone, Default)]
+pub struct Extensions{
+    values: Arc<Mutex<HashMap<TypeId, Entry>>>,
+}
+
+impl Extensions{
+    pub fn new() -> Extensions{
+        Extensions::default()
+    }
+
+    /// Store `value`, returning the `T` it replaced, if there was one.
+    pub fn insert<T>(&self, value: T) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static
+    {
+        let previous = self.values.lock().unwrap().insert(TypeId::of::<T>(), Arc::new(value));
+        previous.and_then(|previous| previous.downcast().ok())
+    }
+
+    /// The `T` stored, if any. Asking for a type that was never inserted,
+    /// however alike its values look, gives `None`.
+    pub fn get<T>(&self) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static
+    {
+        let value = self.values.lock().unwrap().get(&TypeId::of::<T>()).cloned();
+        value.and_then(|value| value.downcast().ok())
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool{
+        self.values.lock().unwrap().contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn remove<T>(&self) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static
+    {
+        let removed = self.values.lock().unwrap().remove(&TypeId::of::<T>());
+        removed.and_then(|removed| removed.downcast().ok())
+    }
+
+    pub fn len(&self) -> usize{
+        self.values.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    fn types(&self) -> Vec<TypeId>{
+        let mut types: Vec<TypeId> = self.values.lock().unwrap().keys().copied().collect();
+        types.sort_unstable();
+        types
+    }
+}
+
+impl fmt::Debug for Extensions{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        write!(f, "Extensions({} values)", self.len())
+    }
+}
+
+// The values themselves can't be compared, so extensions are equal when
+// they hold values of the same types.
+impl PartialEq for Extensions{
+    fn eq(&self, other: &Extensions) -> bool{
+        Arc::ptr_eq(&self.values, &other.values) || self.types() == other.types()
+    }
+}
+
+impl Eq for Extensions {}
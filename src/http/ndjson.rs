@@ -0,0 +1,59 @@
+use std::io::{self, Write};
+
+use super::{ChunkedResponseWriter, Response};
+use crate::json::ToJson;
+
+/// Streams newline-delimited JSON (NDJSON) straight to a connection, one
+/// record per line, for exports too big to build as one array.
+///
+/// `new` sends a `200` head with `Content-Type: application/x-ndjson` and
+/// a chunked body; each `write_record` then goes out as one chunk and is
+/// flushed, so the client can act on every record as it arrives.
+/// `finish` ends the body. As with `ChunkedResponseWriter`, whose framing
+/// this uses, it's HTTP/1.1 only, and dropping the writer without
+/// finishing leaves the body unterminated.
+///
+/// Handlers that answer through the router can stream the same lines
+/// with `Response::from_iter` and `ndjson_line`.
+pub struct NdjsonWriter<W: Write>{
+    inner: ChunkedResponseWriter<W>,
+    records: usize,     // Written so far.
+}
+
+impl<W: Write> NdjsonWriter<W>{
+    pub fn new(stream: W) -> io::Result<NdjsonWriter<W>>{
+        NdjsonWriter::with_response(stream, Response::new(200, "OK"))
+    }
+
+    /// Like `new`, with `response`'s status and headers; its body is not
+    /// sent, and its `Content-Type` is replaced.
+    pub fn with_response(stream: W, mut response: Response) -> io::Result<NdjsonWriter<W>>{
+        response.headers.set("Content-Type", "application/x-ndjson");
+        Ok(NdjsonWriter { inner: ChunkedResponseWriter::new(stream, &response, &[])?, records: 0 })
+    }
+
+    /// Send `record`, compact, on a line of its own.
+    pub fn write_record<T: ToJson + ?Sized>(&mut self, record: &T) -> io::Result<()>{
+        self.inner.write_all(&ndjson_line(record))?;
+        self.inner.flush()?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn records(&self) -> usize{
+        self.records
+    }
+
+    /// End the body, handing back the connection.
+    pub fn finish(self) -> io::Result<W>{
+        self.inner.finish()
+    }
+}
+
+/// `record` as one NDJSON line, newline included. The JSON is compact,
+/// and newlines in strings are escaped, so the line can't break early.
+pub fn ndjson_line<T: ToJson + ?Sized>(record: &T) -> Vec<u8>{
+    let mut line = record.to_json().to_string().into_bytes();
+    line.push(b'\n');
+    line
+}
@@ -0,0 +1,234 @@
+use std::{
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    hash,
+    http::{reason_phrase, Response},
+};
+
+/// Most ranges honoured in one request; asking for more gets the whole
+/// file, so a flood of tiny ranges can't make a huge response.
+pub const MAX_RANGES: usize = 32;
+
+/// Why a `Range` header can't be served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeError{
+    Invalid,        // Not a `bytes` range we understand; serve the whole representation.
+    Unsatisfiable,  // Every range starts past the end; answer `416`.
+}
+
+/// One range of a `Range` header, as written: it means nothing until
+/// checked against the length of what it's a range of (see `validate`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange{
+    FromTo(u64, u64),   // `500-999`: from the first offset to the last, inclusive.
+    From(u64),          // `9500-`: from the offset to the end.
+    Suffix(u64),        // `-500`: the last this many bytes.
+}
+
+impl ByteRange{
+    /// The offsets of the first and last byte (inclusive) this range
+    /// covers in a representation of `file_size` bytes, clipped to what
+    /// exists; `Unsatisfiable` if it covers none of it, as when it starts
+    /// past the end or asks for the last 0 bytes.
+    pub fn validate(&self, file_size: u64) -> Result<(u64, u64), RangeError>{
+        match *self{
+            ByteRange::Suffix(suffix) if suffix > 0 && file_size > 0 => Ok((file_size.saturating_sub(suffix), file_size - 1)),
+            ByteRange::FromTo(first, last) if first < file_size => Ok((first, last.min(file_size - 1))),
+            ByteRange::From(first) if first < file_size => Ok((first, file_size - 1)),
+            _ => Err(RangeError::Unsatisfiable),
+        }
+    }
+}
+
+/// Parsing for `Range` headers (RFC 7233, section 2.1).
+pub struct RangeSpec;
+
+impl RangeSpec{
+    /// The byte ranges of a `Range` header, in the order given. A header
+    /// that isn't `bytes=` followed by a list of ranges, that has a range
+    /// ending before it starts, or that has more than `MAX_RANGES`, is
+    /// `Invalid`, and should be ignored in favour of the whole
+    /// representation.
+    pub fn parse(header: &str) -> Result<Vec<ByteRange>, RangeError>{
+        let header = header.trim();
+        let specs = match header.split_once('='){
+            Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+            _ => return Err(RangeError::Invalid),
+        };
+        let mut ranges = Vec::new();
+        for spec in specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()){
+            if ranges.len() == MAX_RANGES{
+                return Err(RangeError::Invalid);
+            }
+            let (first, last) = spec.split_once('-').ok_or(RangeError::Invalid)?;
+            let (first, last) = (first.trim(), last.trim());
+            let number = |s: &str| {
+                if s.bytes().all(|b| b.is_ascii_digit()){
+                    s.parse::<u64>().map_err(|_| RangeError::Invalid)
+                } else {
+                    Err(RangeError::Invalid)
+                }
+            };
+            ranges.push(match (first.is_empty(), last.is_empty()){
+                (true, true) => return Err(RangeError::Invalid),
+                (true, false) => ByteRange::Suffix(number(last)?),
+                (false, true) => ByteRange::From(number(first)?),
+                (false, false) => {
+                    let (first, last) = (number(first)?, number(last)?);
+                    if last < first{
+                        return Err(RangeError::Invalid);
+                    }
+                    ByteRange::FromTo(first, last)
+                },
+            });
+        }
+        if ranges.is_empty(){
+            return Err(RangeError::Invalid);
+        }
+        Ok(ranges)
+    }
+
+    /// The byte ranges of `ranges` that a representation of `file_size`
+    /// bytes has, inclusive, sorted and with overlapping or adjacent ones
+    /// merged; ranges past the end are dropped. `Unsatisfiable` if none
+    /// are left.
+    pub fn resolve(ranges: &[ByteRange], file_size: u64) -> Result<Vec<RangeInclusive<u64>>, RangeError>{
+        let mut resolved: Vec<(u64, u64)> = ranges.iter().filter_map(|range| range.validate(file_size).ok()).collect();
+        if resolved.is_empty(){
+            return Err(RangeError::Unsatisfiable);
+        }
+
+        resolved.sort_unstable();
+        let mut merged: Vec<RangeInclusive<u64>> = Vec::with_capacity(resolved.len());
+        for (first, last) in resolved{
+            match merged.last_mut(){
+                Some(previous) if first <= previous.end().saturating_add(1) => {
+                    *previous = *previous.start()..=(*previous.end()).max(last);
+                },
+                _ => merged.push(first..=last),
+            }
+        }
+        Ok(merged)
+    }
+
+    /// The `Content-Range` value for a `416` answer about a representation
+    /// of `file_size` bytes, e.g. `bytes */1234`.
+    pub fn unsatisfied_content_range(file_size: u64) -> String{
+        format!("bytes */{}", file_size)
+    }
+}
+
+/// Parse a `Range` header against a representation of `len` bytes.
+///
+/// Returns the byte ranges to send, inclusive, sorted and with overlapping
+/// or adjacent ones merged. Ranges that start past the end are skipped; a
+/// suffix range (`-500`) or open one (`9500-`) is clipped to what exists.
+/// See `RangeSpec` for the two steps this takes.
+pub fn parse_ranges(value: &str, len: u64) -> Result<Vec<RangeInclusive<u64>>, RangeError>{
+    RangeSpec::resolve(&RangeSpec::parse(value)?, len)
+}
+
+/// One range of a representation, with its bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangePart{
+    pub first: u64,             // Offset of the first byte.
+    pub last: u64,              // Offset of the last byte, inclusive.
+    pub complete_length: u64,   // Length of the whole representation.
+    pub data: Vec<u8>,
+}
+
+impl RangePart{
+    /// The range `range` of `contents`, which must lie within it.
+    pub fn slice(contents: &[u8], range: &RangeInclusive<u64>) -> RangePart{
+        let (first, last) = (*range.start(), *range.end());
+        RangePart {
+            first,
+            last,
+            complete_length: contents.len() as u64,
+            data: contents[first as usize..=last as usize].to_vec(),
+        }
+    }
+
+    /// The `Content-Range` value for this range, e.g. `bytes 0-499/1234`.
+    pub fn content_range(&self) -> String{
+        format!("bytes {}-{}/{}", self.first, self.last, self.complete_length)
+    }
+
+    /// A `206` response carrying just this range.
+    pub fn into_response(self, content_type: &str) -> Response{
+        Response::new(206, reason_phrase(206))
+            .with_header("Content-Type", content_type)
+            .with_header("Content-Range", &self.content_range())
+            .with_body(self.data)
+    }
+}
+
+/// A `206` response with several ranges of one representation, as a
+/// `multipart/byteranges` body (RFC 7233, appendix A).
+///
+/// Each part has its own `Content-Type` and `Content-Range`. The boundary
+/// is random and checked against the parts' contents, so it can't be
+/// mistaken for data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiRangeResponse{
+    content_type: String,
+    parts: Vec<RangePart>,
+    boundary: String,
+}
+
+impl MultiRangeResponse{
+    pub fn new(content_type: &str, parts: Vec<RangePart>) -> MultiRangeResponse{
+        let boundary = loop{
+            let boundary = random_boundary();
+            let needle = boundary.as_bytes();
+            if !parts.iter().any(|part| part.data.windows(needle.len()).any(|w| w == needle)){
+                break boundary;
+            }
+        };
+        MultiRangeResponse { content_type: content_type.to_string(), parts, boundary }
+    }
+
+    pub fn boundary(&self) -> &str{
+        &self.boundary
+    }
+
+    pub fn into_response(self) -> Response{
+        let mut body = Vec::with_capacity(self.parts.iter().map(|part| part.data.len() + 128).sum());
+        for part in &self.parts{
+            body.extend_from_slice(format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                self.boundary, self.content_type, part.content_range(),
+            ).as_bytes());
+            body.extend_from_slice(&part.data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+
+        Response::new(206, reason_phrase(206))
+            .with_header("Content-Type", &format!("multipart/byteranges; boundary={}", self.boundary))
+            .with_body(body)
+    }
+}
+
+impl From<MultiRangeResponse> for Response{
+    fn from(multi: MultiRangeResponse) -> Response{
+        multi.into_response()
+    }
+}
+
+// Unpredictable enough to never turn up by accident: the clock, a
+// counter and a stack address, hashed twice over.
+fn random_boundary() -> String{
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let local = 0u8;
+    let seed = format!("{}:{}:{:p}", nanos, count, &local);
+    let high = hash::fnv1a_64(seed.as_bytes());
+    let low = hash::fnv1a_64(&high.to_le_bytes());
+    format!("{:016x}{:016x}", high, low)
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    cache::LruCache,
+    http::{Request, Response},
+    negotiation,
+    router::{Middleware, Next},
+};
+
+/// Longest `Idempotency-Key` accepted.
+const MAX_KEY_LEN: usize = 255;
+
+/// Runs a request sent with an `Idempotency-Key` header once, and answers
+/// any retry carrying the same key with the response it got the first
+/// time, so a client that lost the response can safely send a `POST`
+/// again.
+///
+/// Keys are remembered for each method and target separately, for a TTL
+/// from when the first response was stored. Replayed responses carry
+/// `Idempotent-Replayed: true`. A retry that arrives while the first
+/// request is still being handled gets `409`. Server errors, streamed
+/// bodies and upgrades aren't stored, so retrying those runs the handler
+/// again. Safe methods (`GET`, `HEAD`, `OPTIONS`, `TRACE`) pass straight
+/// through.
+pub struct IdempotencyMiddleware{
+    cache: Arc<Mutex<LruCache<String, Response>>>,
+    in_flight: Mutex<HashSet<String>>,  // Keys whose first request is being handled now.
+}
+
+impl IdempotencyMiddleware{
+    /// Remember up to `capacity` keys, each for `ttl`.
+    pub fn new(capacity: usize, ttl: Duration) -> IdempotencyMiddleware{
+        IdempotencyMiddleware::with_cache(Arc::new(Mutex::new(LruCache::new(capacity, ttl))))
+    }
+
+    /// Store responses in `cache`, which may be shared with other instances.
+    pub fn with_cache(cache: Arc<Mutex<LruCache<String, Response>>>) -> IdempotencyMiddleware{
+        IdempotencyMiddleware { cache, in_flight: Mutex::new(HashSet::new()) }
+    }
+
+    pub fn cache(&self) -> &Arc<Mutex<LruCache<String, Response>>>{
+        &self.cache
+    }
+}
+
+impl Middleware for IdempotencyMiddleware{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        let idempotency_key = match request.header("Idempotency-Key"){
+            Some(key) if !request.method.is_safe() => key.trim(),
+            _ => return next.run(request),
+        };
+        if idempotency_key.is_empty() || idempotency_key.len() > MAX_KEY_LEN{
+            return negotiation::status_page(request, 400, "Idempotency-Key must be 1 to 255 characters");
+        }
+        let key = format!("{} {}\n{}", request.method, request.target(), idempotency_key);
+
+        {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if let Some(response) = self.cache.lock().unwrap().get(&key){
+                return response.clone().with_header("Idempotent-Replayed", "true");
+            }
+            if !in_flight.insert(key.clone()){
+                return negotiation::status_page(request, 409, "A request with this Idempotency-Key is still being handled");
+            }
+        }
+
+        // Let retries through again however the handler finishes, panics included.
+        struct Done<'a>(&'a Mutex<HashSet<String>>, &'a str);
+
+        impl Drop for Done<'_>{
+            fn drop(&mut self){
+                self.0.lock().unwrap_or_else(|e| e.into_inner()).remove(self.1);
+            }
+        }
+
+        let _done = Done(&self.in_flight, &key);
+        let response = next.run(request);
+        if response.status < 500 && response.stream.is_none() && response.upgrade.is_none(){
+            self.cache.lock().unwrap().insert(key.clone(), response.clone());
+        }
+        response
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    http::{self, Request, Response},
+    router::Router,
+};
+
+/// What's running: the crate version, the commit it was built from, when
+/// it started and how many workers it has.
+#[derive(Debug, Clone)]
+pub struct BuildInfo{
+    pub version: &'static str,
+    pub git_hash: &'static str,     // Short hash, or "unknown" when built outside git.
+    pub started: SystemTime,
+    pub workers: usize,
+    started_at: Instant,            // For uptime, which shouldn't jump with the wall clock.
+}
+
+impl BuildInfo{
+    /// Info for a server starting now with `workers` workers.
+    pub fn new(workers: usize) -> BuildInfo{
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_hash: env!("GIT_HASH"),
+            started: SystemTime::now(),
+            workers,
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn uptime_secs(&self) -> u64{
+        self.started_at.elapsed().as_secs()
+    }
+
+    /// The fields as JSON members without the braces, so other documents
+    /// (the health check) can include them.
+    pub fn json_fields(&self) -> String{
+        let started = self.started.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        format!(
+            r#""version":"{}","git_hash":"{}","started_at":{},"uptime_secs":{},"workers":{}"#,
+            self.version, self.git_hash, started, self.uptime_secs(), self.workers
+        )
+    }
+
+    pub fn to_json(&self) -> String{
+        format!("{{{}}}", self.json_fields())
+    }
+}
+
+/// Register `GET /version`, answering with `info` as JSON.
+pub fn register(router: &mut Router, info: Arc<BuildInfo>){
+    router.get("/version", move |_: &Request| {
+        Response::new(200, http::reason_phrase(200))
+            .with_header("Content-Type", "application/json")
+            .with_header("Cache-Control", "no-store")
+            .with_body(info.to_json())
+    });
+}
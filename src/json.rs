@@ -0,0 +1,405 @@
+use std::{error::Error, fmt};
+
+/// Nesting deeper than this is refused rather than risking the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A JSON value. Object members keep the order they were written or
+/// parsed in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value{
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value{
+    /// An empty object, to fill in with `with`.
+    pub fn object() -> Value{
+        Value::Object(Vec::new())
+    }
+
+    /// Add the member `name` to an object. Other values are left as they are.
+    pub fn with<V: Into<Value>>(mut self, name: &str, value: V) -> Value{
+        if let Value::Object(members) = &mut self{
+            members.push((name.to_string(), value.into()));
+        }
+        self
+    }
+
+    /// The first member called `name`, if this is an object.
+    pub fn get(&self, name: &str) -> Option<&Value>{
+        match self{
+            Value::Object(members) => members.iter().find(|(n, _)| n == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str>{
+        match self{
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64>{
+        match self{
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool>{
+        match self{
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]>{
+        match self{
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool{
+        *self == Value::Null
+    }
+}
+
+impl fmt::Display for Value{
+    /// Compact JSON: no whitespace between tokens. Numbers that JSON can't
+    /// represent (NaN, infinities) are written as `null`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Value::Number(_) => f.write_str("null"),
+            Value::String(s) => write!(f, "\"{}\"", escape(s)),
+            Value::Array(values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate(){
+                    if i > 0{
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str("]")
+            },
+            Value::Object(members) => {
+                f.write_str("{")?;
+                for (i, (name, value)) in members.iter().enumerate(){
+                    if i > 0{
+                        f.write_str(",")?;
+                    }
+                    write!(f, "\"{}\":{}", escape(name), value)?;
+                }
+                f.write_str("}")
+            },
+        }
+    }
+}
+
+impl From<bool> for Value{
+    fn from(b: bool) -> Value{
+        Value::Bool(b)
+    }
+}
+
+impl From<f64> for Value{
+    fn from(n: f64) -> Value{
+        Value::Number(n)
+    }
+}
+
+impl From<u64> for Value{
+    fn from(n: u64) -> Value{
+        Value::Number(n as f64)
+    }
+}
+
+impl From<i64> for Value{
+    fn from(n: i64) -> Value{
+        Value::Number(n as f64)
+    }
+}
+
+impl From<usize> for Value{
+    fn from(n: usize) -> Value{
+        Value::Number(n as f64)
+    }
+}
+
+impl From<u16> for Value{
+    fn from(n: u16) -> Value{
+        Value::Number(f64::from(n))
+    }
+}
+
+impl From<&str> for Value{
+    fn from(s: &str) -> Value{
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value{
+    fn from(s: String) -> Value{
+        Value::String(s)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value{
+    fn from(value: Option<T>) -> Value{
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value{
+    fn from(values: Vec<T>) -> Value{
+        Value::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Types that can be written as JSON, for APIs that take records of any
+/// type, such as `http::NdjsonWriter`.
+pub trait ToJson{
+    fn to_json(&self) -> Value;
+}
+
+impl ToJson for Value{
+    fn to_json(&self) -> Value{
+        self.clone()
+    }
+}
+
+/// `text` escaped for use between double quotes in JSON. Quotes,
+/// backslashes and control characters are escaped; everything else,
+/// non-ASCII included, is kept as it is.
+pub fn escape(text: &str) -> String{
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars(){
+        match c{
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Why a document isn't JSON, and the byte offset it was noticed at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError{
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for JsonError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        write!(f, "invalid JSON at byte {}: {}", self.offset, self.message)
+    }
+}
+
+impl Error for JsonError {}
+
+/// Parse one JSON document. Whitespace around it is allowed, anything
+/// else after it isn't.
+pub fn parse(text: &str) -> Result<Value, JsonError>{
+    let mut parser = Parser { bytes: text.as_bytes(), at: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.at < parser.bytes.len(){
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a>{
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Parser<'_>{
+    fn error(&self, message: &'static str) -> JsonError{
+        JsonError { offset: self.at, message }
+    }
+
+    fn skip_whitespace(&mut self){
+        while matches!(self.bytes.get(self.at), Some(b' ' | b'\t' | b'\n' | b'\r')){
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, literal: &str) -> bool{
+        if self.bytes[self.at..].starts_with(literal.as_bytes()){
+            self.at += literal.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, JsonError>{
+        if depth > MAX_DEPTH{
+            return Err(self.error("nested too deeply"));
+        }
+        self.skip_whitespace();
+        match self.bytes.get(self.at){
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) if self.eat("null") => Ok(Value::Null),
+            Some(_) if self.eat("true") => Ok(Value::Bool(true)),
+            Some(_) if self.eat("false") => Ok(Value::Bool(false)),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, JsonError>{
+        self.at += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat("}"){
+            return Ok(Value::Object(members));
+        }
+        loop{
+            self.skip_whitespace();
+            if self.bytes.get(self.at) != Some(&b'"'){
+                return Err(self.error("expected a member name"));
+            }
+            let name = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(":"){
+                return Err(self.error("expected `:`"));
+            }
+            members.push((name, self.value(depth + 1)?));
+            self.skip_whitespace();
+            if self.eat("}"){
+                return Ok(Value::Object(members));
+            }
+            if !self.eat(","){
+                return Err(self.error("expected `,` or `}`"));
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, JsonError>{
+        self.at += 1;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.eat("]"){
+            return Ok(Value::Array(values));
+        }
+        loop{
+            values.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            if self.eat("]"){
+                return Ok(Value::Array(values));
+            }
+            if !self.eat(","){
+                return Err(self.error("expected `,` or `]`"));
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError>{
+        let start = self.at;
+        let digits = |parser: &mut Parser| {
+            let from = parser.at;
+            while parser.bytes.get(parser.at).is_some_and(u8::is_ascii_digit){
+                parser.at += 1;
+            }
+            parser.at > from
+        };
+
+        self.eat("-");
+        if !self.eat("0") && !digits(self){
+            return Err(self.error("expected digits"));
+        }
+        if self.eat(".") && !digits(self){
+            return Err(self.error("expected digits after `.`"));
+        }
+        if matches!(self.bytes.get(self.at), Some(b'e' | b'E')){
+            self.at += 1;
+            if !self.eat("+"){
+                self.eat("-");
+            }
+            if !digits(self){
+                return Err(self.error("expected exponent digits"));
+            }
+        }
+        // Only ASCII was consumed, so this is a valid slice.
+        let text = std::str::from_utf8(&self.bytes[start..self.at]).unwrap_or_default();
+        text.parse().map(Value::Number).map_err(|_| JsonError { offset: start, message: "invalid number" })
+    }
+
+    fn string(&mut self) -> Result<String, JsonError>{
+        self.at += 1;
+        let mut out = Vec::new();
+        loop{
+            let byte = *self.bytes.get(self.at).ok_or_else(|| self.error("unterminated string"))?;
+            self.at += 1;
+            match byte{
+                b'"' => break,
+                b'\\' => {
+                    let escaped = *self.bytes.get(self.at).ok_or_else(|| self.error("unterminated string"))?;
+                    self.at += 1;
+                    let c = match escaped{
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    let mut utf8 = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                },
+                0..=0x1f => return Err(self.error("control character in string")),
+                _ => out.push(byte),
+            }
+        }
+        // The input was a `&str` and escapes were added as whole characters.
+        String::from_utf8(out).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    // After `\u`: four hex digits, or a surrogate pair of them.
+    fn unicode_escape(&mut self) -> Result<char, JsonError>{
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high){
+            return char::from_u32(high).ok_or_else(|| self.error("unpaired surrogate"));
+        }
+        if !self.eat("\\u"){
+            return Err(self.error("unpaired surrogate"));
+        }
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low){
+            return Err(self.error("unpaired surrogate"));
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)).ok_or_else(|| self.error("unpaired surrogate"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError>{
+        let digits = self.bytes.get(self.at..self.at + 4).ok_or_else(|| self.error("short \\u escape"))?;
+        if !digits.iter().all(u8::is_ascii_hexdigit){
+            return Err(self.error("invalid \\u escape"));
+        }
+        let value = digits.iter().fold(0, |n, digit| n * 16 + (*digit as char).to_digit(16).unwrap_or(0));
+        self.at += 4;
+        Ok(value)
+    }
+}
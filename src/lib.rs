@@ -1,14 +1,179 @@
-use std::{thread, sync::{mpsc, Arc, Mutex}};
+pub mod access_log;
+pub mod body_hash;
+pub mod cache;
+pub mod clock;
+pub mod collections;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod config;
+pub mod embedded;
+pub mod embedded_assets;
+pub mod encoding;
+pub mod favicon;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod hash;
+pub mod http;
+pub mod idempotency;
+pub mod info;
+pub mod json;
+pub mod log;
+pub mod metrics;
+pub mod middleware;
+pub mod negotiation;
+pub mod net;
+pub mod poller;
+pub mod pool;
+pub mod proxy;
+pub mod redact;
+pub mod robots;
+pub mod router;
+pub mod security;
+pub mod server;
+pub mod signal;
+pub mod sitemap;
+pub mod sse;
+pub mod static_files;
+pub mod templates;
+pub mod testing;
+pub mod timeutil;
+pub mod trace;
+pub mod transform;
+pub mod upgrade;
+pub mod uri;
+pub mod vhost;
+pub mod websocket;
+
+use std::{
+    any::Any,
+    cell::Cell,
+    cmp::Reverse,
+    collections::BinaryHeap,
+    error::Error,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    thread,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        mpsc, Arc, Condvar, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
 
 pub struct ThreadPool{
     workers: Vec<Worker>,           // Vector to hold worker threads.
-    sender: mpsc::Sender<Message>,      // Channel to send jobs from `execute` function.
+    sender: Mutex<mpsc::Sender<Message>>,   // Channel to send jobs; locked so a batch goes in as one run.
+    timer: Timer,                   // Holds jobs from `execute_after` until they are due.
+    wake: Arc<Wakers>,              // Idle workers park until a send wakes one of them.
+}
+
+// The worker threads, to unpark when there's something for them, and
+// which of them are idle.
+//
+// A job wakes one idle worker, not all of them to race for it. A worker
+// says it is idle before it looks at the queues a last time and parks,
+// so a job sent in between is either seen or wakes it. One that takes a
+// job while more are queued wakes the next idle worker for them.
+#[derive(Default)]
+struct Wakers{
+    threads: OnceLock<Box<[thread::Thread]>>,  // Set once every worker is spawned.
+    idle: Mutex<Vec<usize>>,    // Workers parked or about to park, the latest last.
+    idle_count: AtomicUsize,    // `idle.len()`, to skip the lock when no one is idle.
+    queued: AtomicUsize,        // Jobs sent and not yet taken by a worker.
 }
 
-type Job = Box<dyn FnOnce() + Send + 'static>;  // Type alias for closure job.
+impl Wakers{
+    fn all(&self){
+        self.threads.get().into_iter().flatten().for_each(thread::Thread::unpark);
+    }
+
+    fn one(&self, id: usize){
+        if let Some(threads) = self.threads.get(){
+            threads[id].unpark();
+        }
+    }
+
+    // Wake an idle worker, if there is one. Called after sending, and
+    // fenced against `parking` so that either the worker sees the job
+    // or this sees the worker.
+    fn any(&self){
+        fence(Ordering::SeqCst);
+        if self.idle_count.load(Ordering::SeqCst) == 0{
+            return;
+        }
+        let popped = self.idle.lock().unwrap().pop();
+        if let Some(id) = popped{
+            self.idle_count.fetch_sub(1, Ordering::SeqCst);
+            self.one(id);
+        }
+    }
+
+    // Count `n` jobs about to be sent.
+    fn sending(&self, n: usize){
+        self.queued.fetch_add(n, Ordering::SeqCst);
+    }
+
+    // A worker took one; pass the rest on.
+    fn took_job(&self){
+        if self.queued.fetch_sub(1, Ordering::SeqCst) > 1{
+            self.any();
+        }
+    }
+
+    // Called before a worker looks at the queues a last time.
+    fn parking(&self, id: usize){
+        let mut idle = self.idle.lock().unwrap();
+        if !idle.contains(&id){
+            idle.push(id);
+            self.idle_count.fetch_add(1, Ordering::SeqCst);
+        }
+        drop(idle);
+        fence(Ordering::SeqCst);
+    }
+
+    fn busy(&self, id: usize){
+        let mut idle = self.idle.lock().unwrap();
+        let before = idle.len();
+        idle.retain(|&idle| idle != id);
+        self.idle_count.fetch_sub(before - idle.len(), Ordering::SeqCst);
+    }
+}
+
+// How many times an idle worker yields and looks for work before parking.
+const IDLE_SPINS: usize = 8;
+
+pub type Job = Box<dyn FnOnce() + Send + 'static>;  // Type alias for closure job.
+
+thread_local!{
+    static WORKER_ID: Cell<Option<usize>> = const { Cell::new(None) };   // Set on each pool worker thread.
+}
+
+/// The id of the pool worker running the current job, or `None` outside
+/// a pool. Ids count from zero within each pool.
+pub fn current_worker_id() -> Option<usize>{
+    WORKER_ID.with(Cell::get)
+}
+
+// What a panic said, when it said it with a string.
+fn panic_message(panic: &(dyn Any + Send)) -> &str{
+    match panic.downcast_ref::<&str>(){
+        Some(message) => message,
+        None => panic.downcast_ref::<String>().map_or("(no message)", String::as_str),
+    }
+}
+
+/// Something jobs can be submitted to.
+///
+/// Code that only needs to hand work off can take any `PoolLike`, so tests
+/// can pass a `testing::MockThreadPool` instead of spawning real threads.
+pub trait PoolLike{
+    fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static;
+}
 
 enum Message{
-    NewJob(Job),
+    NewJob(Job, Option<AbortHandle>),  // Skipped if aborted before a worker picks it up.
     Terminate,
 }
 
@@ -28,79 +193,468 @@ impl ThreadPool{
         let receiver = Arc::new(Mutex::new(receiver)); // Wrapping the receiver in `Arc<Mutex<>>` to use it across multiple threads.
 
         let mut workers = Vec::with_capacity(size);  // Initializing an empty vector of worker threads with given size capacity.
+        let wake = Arc::new(Wakers::default());
 
         for id in 0..size{
             // create some threads and store them in the vector
             workers.push(Worker::new(id, 
-                Arc::clone(&receiver)));   // Cloning the `receiver` instead of sharing ownership.
+                Arc::clone(&receiver), Arc::clone(&wake)));   // Cloning the `receiver` instead of sharing ownership.
         }
+        let _ = wake.threads.set(workers.iter().map(|worker| worker.thread.as_ref().unwrap().thread().clone()).collect());
+
+        let timer = Timer::new(sender.clone(), Arc::clone(&wake));   // The timer feeds due jobs into the same channel.
 
         ThreadPool {
             workers,
-            sender           
+            sender: Mutex::new(sender),
+            timer,
+            wake,
         }
     }
 
-    pub fn execute<F>(&self, f: F)
+    /// Queue `f` to run on a worker. The returned handle can cancel it
+    /// until a worker starts it; callers that don't need that can ignore it.
+    /// A panic in `f` is logged, and the worker goes on to the next job.
+    pub fn execute<F>(&self, f: F) -> AbortHandle
     where
         F: FnOnce() + Send + 'static    // Ensure that function passed is only called once.
     {
         let job = Box::new(f);       // Wrapping the closure in box before passing to receiver.
+        let handle = AbortHandle::new();
 
-        self.sender.send(Message::NewJob(job)).unwrap();  // Sending the job to the receiver.
+        self.wake.sending(1);
+        self.sender.lock().unwrap().send(Message::NewJob(job, Some(handle.clone()))).unwrap();  // Sending the job to the receiver.
+        self.wake.any();
+        handle
+    }
+
+    /// Run `f` on worker `worker_id` (see `current_worker_id`) rather than
+    /// whichever is free, for work tied to per-thread state.
+    ///
+    /// Each worker takes jobs meant for it before shared ones, so `f` runs
+    /// after the worker's current job and any pinned before it.
+    pub fn execute_in_worker<F>(&self, worker_id: usize, f: F) -> Result<(), PoolError>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        let worker = self.workers.get(worker_id).ok_or(PoolError::InvalidWorkerId)?;
+        self.wake.sending(1);
+        worker.pinned.send(Message::NewJob(Box::new(f), None)).map_err(|_| PoolError::Disconnected)?;
+        self.wake.one(worker_id);
+        Ok(())
+    }
+
+    /// Run `f` on a worker and get its result through the returned
+    /// channel. Dropping the receiver abandons the result: `f` still runs
+    /// to the end, and what it returns is thrown away. A panic in `f` is
+    /// caught (the worker lives on) and disconnects the channel instead.
+    pub fn spawn_with_channel<F, T>(&self, f: F) -> mpsc::Receiver<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.execute(move || {
+            if let Ok(result) = panic::catch_unwind(AssertUnwindSafe(f)){
+                let _ = sender.send(result);
+            }
+        });
+        receiver
+    }
+
+    /// Submit `jobs` together and get a handle to wait for all of them.
+    ///
+    /// The jobs are queued back to back while no other job can be
+    /// submitted. If queueing fails part-way, the jobs already queued are
+    /// cancelled and skip running, so a batch runs completely or not at
+    /// all. A job that panics is counted as failed without stopping the
+    /// rest of the batch or killing its worker.
+    pub fn execute_batch(&self, jobs: Vec<Job>) -> Result<BatchHandle, PoolError>{
+        let latch = Arc::new(CountDownLatch::new(jobs.len()));
+        let failed = Arc::new(AtomicUsize::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let sender = self.sender.lock().unwrap();
+        for job in jobs{
+            let latch = Arc::clone(&latch);
+            let failed = Arc::clone(&failed);
+            let skip = Arc::clone(&cancelled);
+            let wrapped = Box::new(move || {
+                if !skip.load(Ordering::SeqCst) && panic::catch_unwind(AssertUnwindSafe(job)).is_err(){
+                    failed.fetch_add(1, Ordering::SeqCst);
+                }
+                latch.count_down();
+            });
+            self.wake.sending(1);
+            if sender.send(Message::NewJob(wrapped, None)).is_err(){
+                cancelled.store(true, Ordering::SeqCst);
+                return Err(PoolError::Disconnected);
+            }
+        }
+        self.wake.all();    // Enough work for every worker, likely.
+
+        Ok(BatchHandle { latch, failed })
+    }
+
+    /// Run `f` on the pool once `delay` has passed.
+    ///
+    /// The job waits on the pool's timer thread, not on a worker, so
+    /// pending jobs cost nothing until they are due. Jobs still waiting
+    /// when the pool is dropped are discarded.
+    pub fn execute_after<F>(&self, delay: Duration, f: F)
+    where
+        F: FnOnce() + Send + 'static
+    {
+        self.timer.schedule(Instant::now() + delay, Box::new(f));
+    }
+}
+
+impl PoolLike for ThreadPool{
+    fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static
+    {
+        ThreadPool::execute(self, f);
     }
 }
 
 impl Drop for ThreadPool{
     fn drop(&mut self){
-        println!("Sending terminate message to all workers.");
+        self.timer.stop();      // Stop feeding delayed jobs before the workers go away.
+
+        log::debug("Sending terminate message to all workers.");
 
         for _ in &self.workers{
-            self.sender.send(Message::Terminate).unwrap();  // Sending terminate message to all workers.
+            // Sending terminate message to all workers; it can't fail unless every one of them panicked.
+            let _ = self.sender.lock().unwrap().send(Message::Terminate);
         }
+        self.wake.all();
 
-        println!("Shutting down all workers.");
+        log::debug("Shutting down all workers.");
 
         for worker in &mut self.workers{
-            println!("Shutting down worker {}", worker.id);
+            log::debug(&format!("Shutting down worker {}", worker.id));
 
             if let Some(thread) = worker.thread.take(){   // Taking the thread out of the worker.
-                thread.join().unwrap();     // Joining the thread to wait for it to finish.
+                // Waiting for it to finish; a panic outside a job already ended it, so just say so.
+                if let Err(panic) = thread.join(){
+                    log::error(&format!("Worker {} panicked: {}", worker.id, panic_message(&*panic)));
+                }
             }
         }
     }
 }
+/// Cancels a job queued with `ThreadPool::execute` before it starts.
+///
+/// A job that a worker has already started runs to the end; aborting it
+/// does nothing. Clones control the same job.
+#[derive(Debug, Clone)]
+pub struct AbortHandle{
+    state: Arc<AtomicU8>,
+}
+
+const JOB_PENDING: u8 = 0;
+const JOB_RUNNING: u8 = 1;
+const JOB_ABORTED: u8 = 2;
+
+impl AbortHandle{
+    fn new() -> AbortHandle{
+        AbortHandle { state: Arc::new(AtomicU8::new(JOB_PENDING)) }
+    }
+
+    /// Cancel the job if it hasn't started. Returns whether it is now
+    /// certain not to run, which is `false` once a worker has begun it.
+    pub fn abort(&self) -> bool{
+        match self.state.compare_exchange(JOB_PENDING, JOB_ABORTED, Ordering::SeqCst, Ordering::SeqCst){
+            Ok(_) => true,
+            Err(state) => state == JOB_ABORTED,
+        }
+    }
+
+    pub fn is_aborted(&self) -> bool{
+        self.state.load(Ordering::SeqCst) == JOB_ABORTED
+    }
+
+    // Claim the job for a worker; `false` means it was aborted first.
+    fn start(&self) -> bool{
+        self.state.compare_exchange(JOB_PENDING, JOB_RUNNING, Ordering::SeqCst, Ordering::SeqCst).is_ok()
+    }
+}
+
+/// Why the pool could not do what was asked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolError{
+    Disconnected,           // The workers have shut down.
+    Panicked(usize),        // This many jobs of a batch panicked.
+    InvalidWorkerId,        // `execute_in_worker` was given an id past the last worker.
+}
+
+impl fmt::Display for PoolError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            PoolError::Disconnected => write!(f, "thread pool has shut down"),
+            PoolError::Panicked(n) => write!(f, "{} job(s) panicked", n),
+            PoolError::InvalidWorkerId => write!(f, "no worker has that id"),
+        }
+    }
+}
+
+impl Error for PoolError {}
+
+/// Lets threads wait until a count reaches zero.
+pub struct CountDownLatch{
+    remaining: AtomicUsize,
+    lock: Mutex<()>,        // Only there so waiters can block on the condvar.
+    zero: Condvar,
+}
+
+impl CountDownLatch{
+    pub fn new(count: usize) -> CountDownLatch{
+        CountDownLatch {
+            remaining: AtomicUsize::new(count),
+            lock: Mutex::new(()),
+            zero: Condvar::new(),
+        }
+    }
+
+    /// Decrement the count, waking every waiter when it reaches zero.
+    /// Counting down past zero does nothing.
+    pub fn count_down(&self){
+        let previous = self.remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if previous == Ok(1){
+            let _guard = self.lock.lock().unwrap();     // Don't slip between a waiter's check and its wait.
+            self.zero.notify_all();
+        }
+    }
+
+    pub fn count(&self) -> usize{
+        self.remaining.load(Ordering::SeqCst)
+    }
+
+    /// Block until the count is zero.
+    pub fn wait(&self){
+        let mut guard = self.lock.lock().unwrap();
+        while self.count() > 0{
+            guard = self.zero.wait(guard).unwrap();
+        }
+    }
+
+    /// Block until the count is zero or `timeout` passes, returning
+    /// whether it reached zero.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool{
+        let deadline = Instant::now() + timeout;
+        let mut guard = self.lock.lock().unwrap();
+        while self.count() > 0{
+            let now = Instant::now();
+            if now >= deadline{
+                return false;
+            }
+            guard = self.zero.wait_timeout(guard, deadline - now).unwrap().0;
+        }
+        true
+    }
+}
+
+/// Tracks the jobs submitted by `ThreadPool::execute_batch`.
+pub struct BatchHandle{
+    latch: Arc<CountDownLatch>,
+    failed: Arc<AtomicUsize>,
+}
+
+impl BatchHandle{
+    /// Jobs that have not finished yet.
+    pub fn remaining(&self) -> usize{
+        self.latch.count()
+    }
+
+    /// Block until every job has finished, failing if any of them panicked.
+    pub fn wait(&self) -> Result<(), PoolError>{
+        self.latch.wait();
+        match self.failed.load(Ordering::SeqCst){
+            0 => Ok(()),
+            n => Err(PoolError::Panicked(n)),
+        }
+    }
+}
+
+// A delayed job, ordered by due time and then by scheduling order.
+struct Delayed{
+    due: Instant,
+    seq: u64,
+    job: Job,
+}
+
+impl PartialEq for Delayed{
+    fn eq(&self, other: &Delayed) -> bool{
+        (self.due, self.seq) == (other.due, other.seq)
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed{
+    fn partial_cmp(&self, other: &Delayed) -> Option<std::cmp::Ordering>{
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed{
+    fn cmp(&self, other: &Delayed) -> std::cmp::Ordering{
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+#[derive(Default)]
+struct TimerState{
+    queue: BinaryHeap<Reverse<Delayed>>,    // Min-heap: the earliest job is on top.
+    next_seq: u64,
+    stopped: bool,
+}
+
+// One background thread that sleeps until the earliest delayed job is due
+// and then hands it to the workers.
+struct Timer{
+    shared: Arc<(Mutex<TimerState>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Timer{
+    fn new(sender: mpsc::Sender<Message>, wake: Arc<Wakers>) -> Timer{
+        let shared = Arc::new((Mutex::new(TimerState::default()), Condvar::new()));
+        let state = Arc::clone(&shared);
+
+        let thread = thread::spawn(move || {
+            let (lock, wakeup) = &*state;
+            let mut timers = lock.lock().unwrap();
+            loop{
+                if timers.stopped{
+                    break;
+                }
+                let now = Instant::now();
+                let next_due = timers.queue.peek().map(|Reverse(d)| d.due);
+                match next_due{
+                    Some(due) if due <= now => {
+                        let Reverse(delayed) = timers.queue.pop().unwrap();
+                        wake.sending(1);
+                        if sender.send(Message::NewJob(delayed.job, None)).is_err(){
+                            break;      // The workers are gone.
+                        }
+                        wake.any();
+                    },
+                    Some(due) => timers = wakeup.wait_timeout(timers, due - now).unwrap().0,
+                    None => timers = wakeup.wait(timers).unwrap(),
+                }
+            }
+        });
+
+        Timer {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    fn schedule(&self, due: Instant, job: Job){
+        let (lock, wakeup) = &*self.shared;
+        let mut timers = lock.lock().unwrap();
+        let seq = timers.next_seq;
+        timers.next_seq += 1;
+        timers.queue.push(Reverse(Delayed { due, seq, job }));
+        wakeup.notify_one();    // The new job may be due before the one being waited on.
+    }
+
+    fn stop(&mut self){
+        let (lock, wakeup) = &*self.shared;
+        lock.lock().unwrap().stopped = true;
+        wakeup.notify_one();
+        if let Some(thread) = self.thread.take(){
+            thread.join().unwrap();
+        }
+    }
+}
+
 struct Worker{
     id: usize,                  // Unique ID for every worker thread.
     thread: Option<thread::JoinHandle<()>>,   // Option to hold the thread.
+    pinned: mpsc::Sender<Message>,          // Jobs for this worker only (`execute_in_worker`).
 }
 
 impl Worker{
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker{
-        let thread = thread::spawn(move || loop{    // Spawning the thread which will execute the job.
-            let job = receiver
-            .lock()
-            .unwrap()          // Locking the mutex and unwrapping to get access to the data inside the lock.
-            .recv()            // Retreiving the message from the channel (blocking call).
-            .unwrap();
-
-            println!("Worker {} got a job; executing.", id);
-            match Message::Terminate{
-                Message::NewJob(job) => {
-                    println!("Worker {} got a job; executing.", id);
-                    job();
-                },
-                Message::Terminate => {
-                    println!("Worker {} was told to terminate.", id);
-                    break;
-                },
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>, wake: Arc<Wakers>) -> Worker{
+        let (pinned, own) = mpsc::channel();
+        let thread = thread::spawn(move || {    // Spawning the thread which will execute the job.
+            WORKER_ID.with(|worker| worker.set(Some(id)));  // So jobs can tell which worker runs them.
+
+            // Jobs pinned to this worker come first, then the shared queue.
+            // The lock is only held to look, so no worker sits on it.
+            let next = || own.try_recv().or_else(|_| receiver.lock().unwrap().try_recv());
+            // Look again a few times before parking: a job due any moment
+            // then costs no park and unpark.
+            let soon = ||{
+                let mut message = next();
+                for _ in 0..IDLE_SPINS{
+                    if !matches!(message, Err(mpsc::TryRecvError::Empty)){
+                        break;
+                    }
+                    thread::yield_now();
+                    message = next();
+                }
+                message
+            };
+            loop{
+                let message = match soon(){
+                    Err(mpsc::TryRecvError::Empty) => {
+                        wake.parking(id);
+                        let message = next();
+                        if let Err(mpsc::TryRecvError::Empty) = message{
+                            thread::park();
+                        }
+                        wake.busy(id);
+                        message
+                    },
+                    message => message,
+                };
+                let message = match message{
+                    Ok(message) => message,
+                    Err(mpsc::TryRecvError::Empty) => continue,
+                    Err(mpsc::TryRecvError::Disconnected) => break,
+                };
+                if let Message::NewJob(..) = message{
+                    wake.took_job();
+                }
+
+                match message{
+                    Message::NewJob(job, handle) => Worker::run(id, job, handle),
+                    Message::Terminate => {
+                        // Pinned jobs have nowhere else to go, so finish them first.
+                        while let Ok(Message::NewJob(job, handle)) = own.try_recv(){
+                            Worker::run(id, job, handle);
+                        }
+                        log::debug(&format!("Worker {} was told to terminate.", id));
+                        break;
+                    },
+                }
             }
         });
 
         Worker{
             id,
             thread: Some(thread),
+            pinned,
+        }
+    }
+
+    // A job that panics is logged; the worker carries on with the next.
+    fn run(id: usize, job: Job, handle: Option<AbortHandle>){
+        if handle.is_some_and(|handle| !handle.start()){
+            log::info(&format!("Worker {} skipped a cancelled job.", id));
+            return;
+        }
+        log::debug(&format!("Worker {} got a job; executing.", id));
+        if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(job)){
+            log::error(&format!("A job on worker {} panicked: {}", id, panic_message(&*panic)));
         }
+        server::clear_request_context();
+        trace::end();
     }
 }
 
@@ -0,0 +1,156 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc, RwLock,
+    },
+    time::SystemTime,
+};
+
+use crate::{http::Request, json, redact::Redactor, server, timeutil};
+
+/// How log lines are written, from `server.log_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat{
+    #[default]
+    Text,   // The message as it is.
+    Json,   // One JSON object per line, for log collectors.
+}
+
+impl LogFormat{
+    /// `"text"` or `"json"`.
+    pub fn parse(name: &str) -> Option<LogFormat>{
+        match name{
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level{
+    Debug,  // Routine detail, only written once `set_debug` turns it on.
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level{
+    pub fn as_str(self) -> &'static str{
+        match self{
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+static FORMAT: AtomicU8 = AtomicU8::new(0);     // 0 for text, 1 for JSON.
+static DEBUG: AtomicBool = AtomicBool::new(false);
+static REDACTOR: RwLock<Option<Arc<Redactor>>> = RwLock::new(None);    // `None` for the default.
+
+/// Set the format `info`, `warn` and `error` write in, for the whole
+/// process. Text until this is called.
+pub fn set_format(format: LogFormat){
+    FORMAT.store(if format == LogFormat::Json { 1 } else { 0 }, Ordering::Relaxed);
+}
+
+pub fn format() -> LogFormat{
+    if FORMAT.load(Ordering::Relaxed) == 1 { LogFormat::Json } else { LogFormat::Text }
+}
+
+/// `message` as a line in `format`. JSON lines carry `ts`, `level`,
+/// `message` and, when written while serving a request, its `request_id`.
+pub fn line(format: LogFormat, level: Level, message: &str, time: SystemTime) -> String{
+    match format{
+        LogFormat::Text => message.to_string(),
+        LogFormat::Json => {
+            let mut line = json::Value::object()
+                .with("ts", timeutil::format_rfc3339(time))
+                .with("level", level.as_str())
+                .with("message", message);
+            if let Some(context) = server::current_request_context(){
+                line = line.with("request_id", context.request_id);
+            }
+            line.to_string()
+        },
+    }
+}
+
+/// Whether `debug` messages are written, for the whole process. Off
+/// until this is called.
+pub fn set_debug(enabled: bool){
+    DEBUG.store(enabled, Ordering::Relaxed);
+}
+
+pub fn debug_enabled() -> bool{
+    DEBUG.load(Ordering::Relaxed)
+}
+
+pub fn debug(message: &str){
+    if debug_enabled(){
+        println!("{}", line(format(), Level::Debug, message, SystemTime::now()));
+    }
+}
+
+pub fn info(message: &str){
+    println!("{}", line(format(), Level::Info, message, SystemTime::now()));
+}
+
+pub fn warn(message: &str){
+    println!("{}", line(format(), Level::Warn, message, SystemTime::now()));
+}
+
+pub fn error(message: &str){
+    println!("{}", line(format(), Level::Error, message, SystemTime::now()));
+}
+
+/// Set what's redacted from requests written to the logs, the access log
+/// included, for the whole process. `Redactor::default()` until this is
+/// called.
+pub fn set_redactor(redactor: Redactor){
+    *REDACTOR.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(redactor));
+}
+
+pub fn redactor() -> Arc<Redactor>{
+    let redactor = REDACTOR.read().unwrap_or_else(|e| e.into_inner());
+    redactor.clone().unwrap_or_else(|| Arc::new(Redactor::default()))
+}
+
+/// A line for request `id`, parsed, with `redactor` applied: in text,
+/// `Request #id: ` and the request line, then the headers if `headers`
+/// is set; in JSON, that message without the headers and the request's
+/// fields as `request`, `method`, `target`, `version` and, if set,
+/// `headers`.
+pub fn request_line(format: LogFormat, id: u64, request: &Request, redactor: &Redactor, headers: bool, time: SystemTime) -> String{
+    match format{
+        LogFormat::Text => match headers{
+            true => format!("Request #{}: {}", id, redactor.describe(request)),
+            false => format!("Request #{}: {} {} {}", id, request.method, redactor.target(request), request.version),
+        },
+        LogFormat::Json => {
+            let message = format!("Request #{}: {} {} {}", id, request.method, redactor.target(request), request.version);
+            let mut fields = json::Value::object()
+                .with("method", request.method.as_str())
+                .with("target", redactor.target(request))
+                .with("version", request.version.to_string());
+            if headers{
+                fields = fields.with("headers", redactor.headers_json(&request.headers));
+            }
+            json::Value::object()
+                .with("ts", timeutil::format_rfc3339(time))
+                .with("level", Level::Info.as_str())
+                .with("message", message)
+                .with("request_id", id)
+                .with("request", fields)
+                .to_string()
+        },
+    }
+}
+
+/// Write that a request arrived, with its headers once `debug` messages
+/// are on.
+pub fn request(id: u64, request: &Request){
+    println!("{}", request_line(format(), id, request, &redactor(), debug_enabled(), SystemTime::now()));
+}
@@ -0,0 +1,234 @@
+// Request counts and latencies for each route, served as Prometheus text
+// at `/metrics` and as JSON at `/admin/routes`.
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, RandomState},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    http::{self, Request, Response},
+    json::Value,
+    router::{Middleware, Next, RouteParams, Router},
+};
+
+/// The route that requests no route took are counted under: `404`s,
+/// `405`s and `OPTIONS *`.
+pub const UNMATCHED: &str = "<unmatched>";
+
+/// Upper bounds of the latency histogram's buckets, in seconds. Slower
+/// requests only count in the last, unbounded bucket.
+pub const LATENCY_BUCKETS: [f64; 11] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+const SHARDS: usize = 16;
+
+/// Status classes, `1xx` to `5xx`, as the labels they get.
+const CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+#[derive(Default)]
+struct Counters{
+    classes: [AtomicU64; 5],                            // By status class, `1xx` first.
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],    // Per bucket, not cumulative; the last is unbounded.
+    latency_micros: AtomicU64,                          // Sum over every request.
+}
+
+/// What one route has answered so far, as `RouteMetrics::snapshot` found it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteStats{
+    pub route: String,          // The pattern, e.g. `/users/:id`, or `UNMATCHED`.
+    pub requests: u64,
+    pub classes: [u64; 5],      // By status class, `1xx` first; see `class`.
+    pub buckets: [u64; LATENCY_BUCKETS.len() + 1],  // Per `LATENCY_BUCKETS` bucket, not cumulative, then slower ones.
+    pub latency: Duration,      // Summed over every request.
+}
+
+impl RouteStats{
+    /// Responses with a status in `class`, 2 for `2xx` and so on.
+    pub fn class(&self, class: u16) -> u64{
+        match class{
+            1..=5 => self.classes[class as usize - 1],
+            _ => 0,
+        }
+    }
+
+    pub fn mean_latency(&self) -> Duration{
+        match self.requests{
+            0 => Duration::ZERO,
+            n => self.latency.div_f64(n as f64),
+        }
+    }
+
+    pub fn to_json(&self) -> Value{
+        let classes = CLASSES.iter().zip(self.classes).map(|(class, n)| (class.to_string(), Value::from(n)));
+        let buckets = LATENCY_BUCKETS.iter().map(|b| Value::from(*b)).chain([Value::Null]).zip(self.buckets)
+            .map(|(le, n)| Value::object().with("le", le).with("count", n));
+        Value::object()
+            .with("route", self.route.as_str())
+            .with("requests", self.requests)
+            .with("status", Value::Object(classes.collect()))
+            .with("latency_seconds", Value::object()
+                .with("sum", self.latency.as_secs_f64())
+                .with("mean", self.mean_latency().as_secs_f64())
+                .with("buckets", Value::Array(buckets.collect())))
+    }
+}
+
+/// Counts each route's responses by status class and times them in a
+/// histogram, keyed by the route's pattern, so `/users/1` and `/users/2`
+/// both count for `/users/:id`. Register it on a router as the first
+/// middleware, so its times include the rest of the chain; the pattern
+/// comes from the `RouteParams` the router gives each routed request,
+/// and requests without one count under `UNMATCHED`.
+///
+/// A request is timed until its response is ready, not until it has
+/// been sent, so the time for a streamed response leaves out the stream.
+///
+/// Routes are spread over a few shards, each behind a `RwLock` that a
+/// write only takes the first time a route is seen; the counts
+/// themselves are atomics. Clones share the counts.
+#[derive(Clone)]
+pub struct RouteMetrics{
+    inner: Arc<Inner>,
+}
+
+struct Inner{
+    shards: [RwLock<HashMap<String, Arc<Counters>>>; SHARDS],
+    hasher: RandomState,    // Picks a route's shard.
+}
+
+impl RouteMetrics{
+    pub fn new() -> RouteMetrics{
+        RouteMetrics {
+            inner: Arc::new(Inner { shards: Default::default(), hasher: RandomState::new() }),
+        }
+    }
+
+    /// Count a `status` response to a request for `route` that took `elapsed`.
+    pub fn record(&self, route: &str, status: u16, elapsed: Duration){
+        let counters = self.counters(route);
+        if let 1..=5 = status / 100{
+            counters.classes[status as usize / 100 - 1].fetch_add(1, Ordering::Relaxed);
+        }
+        let secs = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS.iter().position(|bound| secs <= *bound).unwrap_or(LATENCY_BUCKETS.len());
+        counters.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        counters.latency_micros.fetch_add(elapsed.as_micros().min(u64::MAX as u128) as u64, Ordering::Relaxed);
+    }
+
+    fn counters(&self, route: &str) -> Arc<Counters>{
+        let shard = &self.inner.shards[self.inner.hasher.hash_one(route) as usize % SHARDS];
+        if let Some(counters) = shard.read().unwrap_or_else(|e| e.into_inner()).get(route){
+            return Arc::clone(counters);
+        }
+        let mut routes = shard.write().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(routes.entry(route.to_string()).or_default())
+    }
+
+    /// Every route that has answered a request, the busiest first, then
+    /// by pattern.
+    pub fn snapshot(&self) -> Vec<RouteStats>{
+        let mut stats = Vec::new();
+        for shard in &self.inner.shards{
+            for (route, counters) in shard.read().unwrap_or_else(|e| e.into_inner()).iter(){
+                let buckets = counters.buckets.each_ref().map(|n| n.load(Ordering::Relaxed));
+                stats.push(RouteStats {
+                    route: route.clone(),
+                    requests: buckets.iter().sum(),
+                    classes: counters.classes.each_ref().map(|n| n.load(Ordering::Relaxed)),
+                    buckets,
+                    latency: Duration::from_micros(counters.latency_micros.load(Ordering::Relaxed)),
+                });
+            }
+        }
+        stats.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.route.cmp(&b.route)));
+        stats
+    }
+
+    /// The stats for `route`, if it has answered anything.
+    pub fn route(&self, route: &str) -> Option<RouteStats>{
+        self.snapshot().into_iter().find(|stats| stats.route == route)
+    }
+
+    /// The counts in the Prometheus text format:
+    /// `http_route_requests_total`, labelled with `route` and `status`
+    /// (the class), and the `http_route_request_duration_seconds`
+    /// histogram, labelled with `route`.
+    pub fn to_prometheus(&self) -> String{
+        let stats = self.snapshot();
+        let mut out = String::new();
+        out.push_str("# HELP http_route_requests_total Requests answered, by route pattern and status class.\n");
+        out.push_str("# TYPE http_route_requests_total counter\n");
+        for route in &stats{
+            let label = label_value(&route.route);
+            for (class, n) in CLASSES.iter().zip(route.classes){
+                out.push_str(&format!("http_route_requests_total{{route=\"{}\",status=\"{}\"}} {}\n", label, class, n));
+            }
+        }
+        out.push_str("# HELP http_route_request_duration_seconds Time to answer a request, by route pattern.\n");
+        out.push_str("# TYPE http_route_request_duration_seconds histogram\n");
+        for route in &stats{
+            let label = label_value(&route.route);
+            let mut cumulative = 0;
+            for (i, n) in route.buckets.iter().enumerate(){
+                cumulative += n;
+                let le = LATENCY_BUCKETS.get(i).map_or("+Inf".to_string(), |bound| bound.to_string());
+                out.push_str(&format!("http_route_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}\n", label, le, cumulative));
+            }
+            out.push_str(&format!("http_route_request_duration_seconds_sum{{route=\"{}\"}} {}\n", label, route.latency.as_secs_f64()));
+            out.push_str(&format!("http_route_request_duration_seconds_count{{route=\"{}\"}} {}\n", label, route.requests));
+        }
+        out
+    }
+
+    /// `snapshot` as a JSON array.
+    pub fn to_json(&self) -> String{
+        Value::Array(self.snapshot().iter().map(RouteStats::to_json).collect()).to_string()
+    }
+}
+
+impl Default for RouteMetrics{
+    fn default() -> RouteMetrics{
+        RouteMetrics::new()
+    }
+}
+
+impl Middleware for RouteMetrics{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        let started = Instant::now();
+        let response = next.run(request);
+        let routed = request.extensions().get::<RouteParams>();
+        let route = routed.as_ref().map_or(UNMATCHED, |routed| routed.pattern.as_str());
+        self.record(route, response.status, started.elapsed());
+        response
+    }
+}
+
+/// Register `GET /metrics`, with `metrics` in the Prometheus text format,
+/// and `GET /admin/routes`, with them as JSON. Neither goes in the
+/// sitemap.
+pub fn register(router: &mut Router, metrics: RouteMetrics){
+    let text = metrics.clone();
+    router.get("/metrics", move |_: &Request| {
+        Response::new(200, http::reason_phrase(200))
+            .with_header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+            .with_header("Cache-Control", "no-store")
+            .with_body(text.to_prometheus())
+    });
+    router.no_sitemap();
+    router.get("/admin/routes", move |_: &Request| {
+        Response::new(200, http::reason_phrase(200))
+            .with_header("Content-Type", "application/json")
+            .with_header("Cache-Control", "no-store")
+            .with_body(metrics.to_json())
+    });
+    router.no_sitemap();
+}
+
+// `value` escaped for a Prometheus label: backslashes, quotes and newlines.
+fn label_value(value: &str) -> String{
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
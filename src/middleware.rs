@@ -0,0 +1,5 @@
+mod digest;
+mod hmac;
+
+pub use digest::{DigestAuthMiddleware, DEFAULT_NONCE_TTL};
+pub use hmac::{HmacAlgorithm, HmacSignatureMiddleware};
@@ -0,0 +1,290 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    hash::BuildHasher,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    collections::RotatingBloomFilter,
+    hash,
+    http::{self, Request, Response},
+    router::{Middleware, Next},
+};
+
+/// How long a nonce is good for by default.
+pub const DEFAULT_NONCE_TTL: Duration = Duration::from_secs(300);
+
+/// Asks for HTTP Digest authentication (RFC 7616) with SHA-256 and
+/// `qop=auth`, against users given with `with_user`.
+///
+/// Nonces carry their issue time and a MAC under a key made for this
+/// middleware, so nothing is stored for one until it is used. Each
+/// request a client makes with a nonce counts up its `nc`; the `nonce`
+/// and `nc` pairs seen are remembered in a `RotatingBloomFilter`, in
+/// bounded memory, for as long as the nonce lasts. A request that repeats
+/// a pair, or whose nonce is older than the nonce TTL, is answered `401`
+/// with `stale=true` and a fresh nonce, which tells the client its
+/// credentials were right and it can retry without asking the user. One
+/// with the wrong credentials, or a nonce not issued here, gets a plain
+/// `401` challenge, and one whose `uri` isn't the request's gets `400`.
+///
+/// The filter's false positives show up as a fresh request taken for a
+/// replay: the client gets `stale=true` and retries with a new nonce.
+pub struct DigestAuthMiddleware{
+    realm: String,
+    users: HashMap<String, String>,     // Username -> password.
+    secret: [u8; 32],                   // Signs nonces.
+    nonce_ttl: Duration,
+    clock: Arc<dyn Clock>,
+    epoch: Instant,                     // Nonces' issue times count from here.
+    issued: AtomicU64,                  // Makes each nonce different.
+    expected_uses: usize,               // Requests per TTL the filter is sized for.
+    false_positive_rate: f64,
+    used: Mutex<RotatingBloomFilter<str>>,  // `nonce:nc` pairs seen.
+}
+
+impl DigestAuthMiddleware{
+    /// Challenge for `realm`, with no users yet, nonces good for
+    /// `DEFAULT_NONCE_TTL`, and room to remember 100,000 requests per TTL
+    /// at a one in 10,000 false-positive rate.
+    pub fn new(realm: &str) -> DigestAuthMiddleware{
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let (expected_uses, false_positive_rate) = (100_000, 0.0001);
+        DigestAuthMiddleware {
+            realm: realm.to_string(),
+            users: HashMap::new(),
+            secret: random_key(),
+            nonce_ttl: DEFAULT_NONCE_TTL,
+            epoch: clock.now(),
+            used: Mutex::new(RotatingBloomFilter::with_clock(expected_uses, false_positive_rate, DEFAULT_NONCE_TTL, Arc::clone(&clock))),
+            clock,
+            issued: AtomicU64::new(0),
+            expected_uses,
+            false_positive_rate,
+        }
+    }
+
+    pub fn with_user(mut self, username: &str, password: &str) -> DigestAuthMiddleware{
+        self.users.insert(username.to_string(), password.to_string());
+        self
+    }
+
+    /// Refuse nonces older than `ttl` as stale.
+    pub fn with_nonce_ttl(mut self, ttl: Duration) -> DigestAuthMiddleware{
+        self.nonce_ttl = ttl;
+        self.with_fresh_filter()
+    }
+
+    /// Size the replay filter for `expected_uses` requests per nonce TTL
+    /// at `false_positive_rate`; see `BloomFilter::new`.
+    pub fn with_replay_filter(mut self, expected_uses: usize, false_positive_rate: f64) -> DigestAuthMiddleware{
+        self.expected_uses = expected_uses;
+        self.false_positive_rate = false_positive_rate;
+        self.with_fresh_filter()
+    }
+
+    /// Judge nonces' age by `clock`. Nonces issued before are no longer
+    /// accepted.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> DigestAuthMiddleware{
+        self.epoch = clock.now();
+        self.clock = clock;
+        self.with_fresh_filter()
+    }
+
+    /// The realm challenges name.
+    pub fn realm(&self) -> &str{
+        &self.realm
+    }
+
+    /// A new nonce, good for the nonce TTL from now.
+    pub fn nonce(&self) -> String{
+        let issued = self.clock.now().saturating_duration_since(self.epoch).as_millis() as u64;
+        let count = self.issued.fetch_add(1, Ordering::Relaxed);
+        let signed = format!("{:x}.{:x}", issued, count);
+        let mac = hash::hmac_sha256(&self.secret, signed.as_bytes());
+        format!("{}.{}", signed, hash::to_hex(&mac[..16]))
+    }
+
+    // Entries outlive their nonces: the filter rotates every TTL and
+    // remembers for at least that.
+    fn with_fresh_filter(mut self) -> DigestAuthMiddleware{
+        let filter = RotatingBloomFilter::with_clock(
+            self.expected_uses,
+            self.false_positive_rate,
+            self.nonce_ttl,
+            Arc::clone(&self.clock),
+        );
+        self.used = Mutex::new(filter);
+        self
+    }
+
+    // When `nonce` was issued, counted from `epoch`, if it was issued
+    // here.
+    fn issued_at(&self, nonce: &str) -> Option<Duration>{
+        let (signed, mac) = nonce.rsplit_once('.')?;
+        let expected = hash::hmac_sha256(&self.secret, signed.as_bytes());
+        if !hash::constant_time_eq(&hash::from_hex(mac)?, &expected[..16]){
+            return None;
+        }
+        let (issued, _) = signed.split_once('.')?;
+        u64::from_str_radix(issued, 16).ok().map(Duration::from_millis)
+    }
+
+    fn verdict(&self, request: &Request) -> Verdict{
+        let params = match request.header("Authorization").and_then(|value| strip_scheme(value, "Digest")){
+            Some(params) => parse_params(params),
+            None => return Verdict::Unauthorized,
+        };
+        let param = |name: &str| params.get(name).map(String::as_str);
+        let (Some(username), Some(nonce), Some(uri), Some(response), Some(nc), Some(cnonce)) =
+            (param("username"), param("nonce"), param("uri"), param("response"), param("nc"), param("cnonce"))
+        else{
+            return Verdict::Unauthorized;
+        };
+        let algorithm_ok = param("algorithm").is_some_and(|algorithm| algorithm.eq_ignore_ascii_case("SHA-256"));
+        let nc_ok = nc.len() == 8 && nc.bytes().all(|b| b.is_ascii_hexdigit());
+        if param("realm") != Some(self.realm.as_str()) || param("qop") != Some("auth") || !algorithm_ok || !nc_ok{
+            return Verdict::Unauthorized;
+        }
+        if uri != request.original_target() && uri != request.target(){
+            return Verdict::BadRequest;
+        }
+        let issued = match self.issued_at(nonce){
+            Some(issued) => issued,
+            None => return Verdict::Unauthorized,
+        };
+        let password = match self.users.get(username){
+            Some(password) => password,
+            None => return Verdict::Unauthorized,
+        };
+
+        let ha1 = sha256_hex(&format!("{}:{}:{}", username, self.realm, password));
+        let ha2 = sha256_hex(&format!("{}:{}", request.method, uri));
+        let expected = sha256_hex(&format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2));
+        if !hash::constant_time_eq(response.to_ascii_lowercase().as_bytes(), expected.as_bytes()){
+            return Verdict::Unauthorized;
+        }
+
+        // The credentials are right from here on; only the nonce is in doubt.
+        let age = self.clock.now().saturating_duration_since(self.epoch).saturating_sub(issued);
+        if age > self.nonce_ttl{
+            return Verdict::Stale;
+        }
+        let used = format!("{}:{}", nonce, nc.to_ascii_lowercase());
+        if self.used.lock().unwrap().check_and_insert(used.as_str()){
+            return Verdict::Stale;
+        }
+        Verdict::Authorized
+    }
+
+    fn challenge(&self, stale: bool) -> Response{
+        let mut value = format!("Digest realm={}, qop=\"auth\", algorithm=SHA-256, nonce=\"{}\"", quote(&self.realm), self.nonce());
+        if stale{
+            value.push_str(", stale=true");
+        }
+        Response::new(401, http::reason_phrase(401))
+            .with_header("WWW-Authenticate", &value)
+            .with_header("Content-Type", "text/plain")
+            .with_body(if stale { "Stale nonce" } else { "Unauthorized" })
+    }
+}
+
+enum Verdict{
+    Authorized,
+    Unauthorized,   // No credentials, wrong ones, or a nonce not issued here.
+    Stale,          // Right credentials, with an expired or already used nonce.
+    BadRequest,     // `uri` names another target.
+}
+
+// Passwords stay out of logs.
+impl fmt::Debug for DigestAuthMiddleware{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        let mut users: Vec<&String> = self.users.keys().collect();
+        users.sort();
+        f.debug_struct("DigestAuthMiddleware")
+            .field("realm", &self.realm)
+            .field("users", &users)
+            .field("nonce_ttl", &self.nonce_ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Middleware for DigestAuthMiddleware{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        match self.verdict(request){
+            Verdict::Authorized => next.run(request),
+            Verdict::Unauthorized => self.challenge(false),
+            Verdict::Stale => self.challenge(true),
+            Verdict::BadRequest => Response::new(400, http::reason_phrase(400))
+                .with_header("Content-Type", "text/plain")
+                .with_body("Digest uri does not match the request target"),
+        }
+    }
+}
+
+fn random_key() -> [u8; 32]{
+    let random = RandomState::new();
+    let mut key = [0; 32];
+    for (i, chunk) in key.chunks_mut(8).enumerate(){
+        chunk.copy_from_slice(&random.hash_one(i).to_le_bytes());
+    }
+    key
+}
+
+fn sha256_hex(text: &str) -> String{
+    hash::to_hex(&hash::sha256(text.as_bytes()))
+}
+
+// What follows `scheme` in an `Authorization` value, if it is that scheme.
+fn strip_scheme<'a>(value: &'a str, scheme: &str) -> Option<&'a str>{
+    let value = value.trim_start();
+    let (name, rest) = value.split_at_checked(scheme.len())?;
+    (name.eq_ignore_ascii_case(scheme) && rest.starts_with(' ')).then(|| rest.trim_start())
+}
+
+// `name=token` and `name="quoted \"string\""` pairs, comma separated.
+// Names are lowercased; a name given twice keeps its first value.
+fn parse_params(text: &str) -> HashMap<String, String>{
+    let mut params = HashMap::new();
+    let mut rest = text;
+    loop{
+        rest = rest.trim_start_matches([' ', '\t', ',']);
+        let Some((name, after)) = rest.split_once('=') else{
+            return params;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let after = after.trim_start();
+        let value = if let Some(quoted) = after.strip_prefix('"'){
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next(){
+                match c{
+                    '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    },
+                    c => value.push(c),
+                }
+            }
+            rest = &quoted[end..];
+            value
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            rest = &after[end..];
+            after[..end].trim().to_string()
+        };
+        params.entry(name).or_insert(value);
+    }
+}
+
+fn quote(text: &str) -> String{
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,205 @@
+use crate::{
+    http::{self, Request, Response},
+    json,
+    router::{Middleware, Next},
+    templates::escape_html,
+};
+
+/// One entry of an `Accept` header, such as `text/*;q=0.5`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange{
+    pub kind: String,       // `text`, or `*`.
+    pub subtype: String,    // `html`, or `*`.
+    pub q: f32,             // Between 0 and 1; 1 when not given.
+}
+
+impl MediaRange{
+    /// How closely this range names `media_type`: 2 for an exact match,
+    /// 1 for `type/*`, 0 for `*/*`, or `None` if it doesn't match at all.
+    pub fn specificity(&self, media_type: &str) -> Option<u8>{
+        let (kind, subtype) = media_type.split_once('/')?;
+        match (self.kind.as_str(), self.subtype.as_str()){
+            ("*", "*") => Some(0),
+            (k, "*") if k.eq_ignore_ascii_case(kind) => Some(1),
+            (k, s) if k.eq_ignore_ascii_case(kind) && s.eq_ignore_ascii_case(subtype) => Some(2),
+            _ => None,
+        }
+    }
+}
+
+/// Parse an `Accept` header into its media ranges.
+///
+/// Entries that aren't `type/subtype` are skipped, as are unreadable
+/// `q` values (which count as 1), so a garbled header yields whatever
+/// could be made sense of.
+pub fn parse_accept(accept: &str) -> Vec<MediaRange>{
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let mut params = range.split(';');
+            let (kind, subtype) = params.next()?.trim().split_once('/')?;
+            let (kind, subtype) = (kind.trim(), subtype.trim());
+            if kind.is_empty() || subtype.is_empty() || (kind == "*" && subtype != "*"){
+                return None;
+            }
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .filter(|q| q.is_finite())
+                .unwrap_or(1.0);
+            Some(MediaRange { kind: kind.to_string(), subtype: subtype.to_string(), q: q.clamp(0.0, 1.0) })
+        })
+        .collect()
+}
+
+/// Pick the offered media type the client prefers, according to an
+/// `Accept` header.
+///
+/// Each offered type takes the quality (`q=`) of the most specific media
+/// range that matches it (`text/html` beats `text/*` beats `*/*`). The
+/// highest quality wins, ties go to the type offered first, and types
+/// with quality zero are never chosen. A missing or entirely unreadable
+/// header accepts anything, so the first offered type is the default.
+pub fn negotiate<'a>(accept: &str, offered: &[&'a str]) -> Option<&'a str>{
+    let ranges = parse_accept(accept);
+    if ranges.is_empty(){
+        return offered.first().copied();
+    }
+
+    let mut best: Option<(&'a str, f32)> = None;
+    for &ty in offered{
+        // Find the quality given by the most specific matching range.
+        let q = ranges.iter()
+            .filter_map(|range| Some((range.specificity(ty)?, range.q)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map_or(0.0, |(_, q)| q);
+
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q){
+            best = Some((ty, q));
+        }
+    }
+    best.map(|(ty, _)| ty)
+}
+
+/// Parsing for `Accept-Language` headers, such as `en-US,en;q=0.9,fr;q=0.7`.
+pub struct AcceptLanguage;
+
+impl AcceptLanguage{
+    /// The language ranges in `header` with their qualities, best first;
+    /// ranges of equal quality keep their order. Entries that aren't
+    /// language tags or `*` are skipped, and unreadable `q` values count
+    /// as 1.
+    pub fn parse(header: &str) -> Vec<(String, f32)>{
+        let mut ranges: Vec<(String, f32)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut params = entry.split(';');
+                let tag = params.next()?.trim();
+                let valid = tag == "*" || (!tag.is_empty()
+                    && tag.split('-').all(|part| (1..=8).contains(&part.len()) && part.bytes().all(|b| b.is_ascii_alphanumeric())));
+                if !valid{
+                    return None;
+                }
+                let q = params
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .filter(|q| q.is_finite())
+                    .unwrap_or(1.0);
+                Some((tag.to_string(), q.clamp(0.0, 1.0)))
+            })
+            .collect();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges
+    }
+}
+
+/// Pick the available locale the client prefers, according to an
+/// `Accept-Language` header.
+///
+/// Ranges are tried best first. Each matches an available tag that is
+/// the same (ignoring case), else one it starts with (`en-US` takes
+/// `en`), else one that starts with it (`en` takes `en-GB`), in that
+/// order, and `*` matches the first available tag the header doesn't
+/// otherwise mention. Tags given quality zero are never chosen. A missing
+/// or entirely unreadable header accepts anything, so the first
+/// available locale is the default; otherwise `None` means nothing
+/// available will do.
+pub fn select_locale<'a>(accept: &str, available: &[&'a str]) -> Option<&'a str>{
+    let ranges = AcceptLanguage::parse(accept);
+    if ranges.is_empty(){
+        return available.first().copied();
+    }
+
+    let refused = |tag: &str| ranges.iter().any(|(range, q)| *q == 0.0 && (range.eq_ignore_ascii_case(tag) || is_prefix(range, tag)));
+    let candidates: Vec<&'a str> = available.iter().copied().filter(|tag| !refused(tag)).collect();
+    for (range, _) in ranges.iter().filter(|(_, q)| *q > 0.0){
+        if range == "*"{
+            let mentioned = |tag: &str| ranges.iter().any(|(range, _)| range.eq_ignore_ascii_case(tag) || is_prefix(range, tag) || is_prefix(tag, range));
+            if let Some(tag) = candidates.iter().find(|tag| !mentioned(tag)){
+                return Some(tag);
+            }
+            continue;
+        }
+        let found = candidates.iter().find(|tag| tag.eq_ignore_ascii_case(range))
+            .or_else(|| candidates.iter().filter(|tag| is_prefix(tag, range)).max_by_key(|tag| tag.len()))
+            .or_else(|| candidates.iter().find(|tag| is_prefix(range, tag)));
+        if let Some(tag) = found{
+            return Some(tag);
+        }
+    }
+    None
+}
+
+// Whether `tag` is `prefix` followed by more subtags, as `en-US` is to `en`.
+fn is_prefix(prefix: &str, tag: &str) -> bool{
+    tag.len() > prefix.len() && tag.as_bytes()[prefix.len()] == b'-' && tag[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// A built-in status page, as HTML or JSON depending on what the client
+/// accepts. Clients that accept neither get HTML.
+pub fn status_page(request: &Request, status: u16, message: &str) -> Response{
+    let mut response = Response::new(status, http::reason_phrase(status));
+    match request.negotiate_content_type(&["text/html", "application/json"]){
+        Some("application/json") => {
+            response.headers.set("Content-Type", "application/json");
+            response.body = format!(r#"{{"status":{},"error":"{}"}}"#, status, json::escape(message)).into_bytes();
+        },
+        _ => {
+            response.headers.set("Content-Type", "text/html; charset=utf-8");
+            response.body = format!(
+                "<!DOCTYPE html>\n<html><head><title>{0} {1}</title></head><body><h1>{0} {1}</h1></body></html>\n",
+                status,
+                escape_html(message),
+            ).into_bytes();
+        },
+    }
+    response.vary("Accept");
+    response
+}
+
+/// Adds `Accept` to `Vary` on responses whose representation was picked
+/// from the request's `Accept` header, so caches keep one copy per media
+/// type.
+///
+/// Handlers signal this through `Request::negotiate_content_type` or
+/// `Request::set_used_accept_negotiation`. An existing `Vary` is extended
+/// rather than replaced, and `Vary: *` is left as it is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContentNegotiationMiddleware;
+
+impl ContentNegotiationMiddleware{
+    pub fn new() -> ContentNegotiationMiddleware{
+        ContentNegotiationMiddleware
+    }
+}
+
+impl Middleware for ContentNegotiationMiddleware{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        let mut response = next.run(request);
+        if request.used_accept_negotiation(){
+            response.vary("Accept");
+        }
+        response
+    }
+}
+
@@ -0,0 +1,199 @@
+use std::{
+    io,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    process::{Child, Command},
+    time::Duration,
+};
+
+use crate::{log, poller::Poller};
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+mod unix;
+
+/// Socket settings for the listener and the connections it accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions{
+    pub reuse_address: bool,                // `SO_REUSEADDR`, so a restart can bind while old connections linger.
+    pub reuse_port: bool,                   // `SO_REUSEPORT`, so several processes can listen on one port.
+    pub backlog: u32,                       // Connections the kernel queues before we accept them.
+    pub nodelay: bool,                      // `TCP_NODELAY`: send small responses without waiting (no Nagle).
+    pub keepalive: Option<Duration>,        // Idle time before TCP keepalive probes start; `None` disables them.
+    pub keepalive_interval: Option<Duration>,   // Time between probes, if not the system default.
+    pub socket_activation: bool,            // Prefer a listener passed in by systemd (`LISTEN_FDS`) to binding.
+}
+
+impl Default for SocketOptions{
+    fn default() -> SocketOptions{
+        SocketOptions {
+            reuse_address: true,
+            reuse_port: false,
+            backlog: 128,
+            nodelay: true,
+            keepalive: None,
+            keepalive_interval: None,
+            socket_activation: false,
+        }
+    }
+}
+
+/// Bind a listener to the first of `addr`'s addresses that works, with
+/// `options`' address and port reuse and backlog.
+///
+/// Where raw socket setup isn't available this falls back to
+/// `TcpListener::bind`, which picks all three itself; asking for port
+/// reuse there gets a warning.
+pub fn bind<A: ToSocketAddrs>(addr: A, options: &SocketOptions) -> io::Result<TcpListener>{
+    let mut last_error = None;
+    for addr in addr.to_socket_addrs()?{
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+        let bound = unix::bind(&addr, options.reuse_address, options.reuse_port, options.backlog);
+        #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+        let bound = {
+            if options.reuse_port{
+                log::warn("SO_REUSEPORT is not supported on this platform; binding without it");
+            }
+            TcpListener::bind(addr)
+        };
+
+        match bound{
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no addresses to bind")))
+}
+
+/// The listener to serve on: the one handed over by socket activation if
+/// `options.socket_activation` is set and there is one, else a fresh one
+/// bound to `addr`.
+///
+/// An inherited descriptor that isn't a listening TCP socket is reported
+/// and ignored rather than served on.
+pub fn listen<A: ToSocketAddrs>(addr: A, options: &SocketOptions) -> io::Result<TcpListener>{
+    if options.socket_activation{
+        match inherited_listener(){
+            Ok(Some(listener)) => return Ok(listener),
+            Ok(None) => log::info("No socket passed in by the service manager; binding instead."),
+            Err(e) => log::warn(&format!("Ignoring the socket passed in by the service manager: {}", e)),
+        }
+    }
+    bind(addr, options)
+}
+
+/// The first listener passed in by systemd-style socket activation, if
+/// `LISTEN_PID` names this process and `LISTEN_FDS` counts at least one.
+/// It is descriptor 3; any more are left alone.
+///
+/// The variables are cleared either way, so child processes don't take
+/// them as meant for themselves. Only unix has inherited descriptors;
+/// elsewhere this is always `None`.
+pub fn inherited_listener() -> io::Result<Option<TcpListener>>{
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    let for_us = pid.and_then(|pid| pid.trim().parse::<u32>().ok()) == Some(std::process::id());
+    let count = fds.and_then(|fds| fds.trim().parse::<u32>().ok()).unwrap_or(0);
+    if !for_us || count == 0{
+        return Ok(None);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+    return unix::listener_from_fd(LISTEN_FDS_START).map(Some);
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+    Ok(None)
+}
+
+/// Take over descriptor `fd`, inherited from the process that started
+/// this one, as the listener; it must be a listening TCP socket.
+pub fn inherited_fd(fd: i32) -> io::Result<TcpListener>{
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+    return unix::listener_from_fd(fd);
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+    {
+        let _ = fd;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "inherited sockets are not supported on this platform"))
+    }
+}
+
+/// Start `command` with `listener` open in it, under the same descriptor
+/// number, which is returned with the child. The descriptor is marked
+/// inheritable only for the spawn.
+pub fn spawn_with_listener(command: &mut Command, listener: &TcpListener) -> io::Result<(Child, i32)>{
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+    {
+        use std::os::fd::AsRawFd;
+
+        let fd = listener.as_raw_fd();
+        unix::set_inheritable(fd, true)?;
+        let spawned = command.spawn();
+        unix::set_inheritable(fd, false)?;
+        spawned.map(|child| (child, fd))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+    {
+        let _ = (command, listener);
+        Err(io::Error::new(io::ErrorKind::Unsupported, "inherited sockets are not supported on this platform"))
+    }
+}
+
+// The first descriptor socket activation passes (`SD_LISTEN_FDS_START`).
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Apply `options` to a freshly accepted connection.
+pub fn configure_stream(stream: &TcpStream, options: &SocketOptions) -> io::Result<()>{
+    stream.set_nodelay(options.nodelay)?;
+    if let Some(idle) = options.keepalive{
+        set_keepalive(stream, idle, options.keepalive_interval)?;
+    }
+    Ok(())
+}
+
+/// Turn on TCP keepalive: probe after `idle` without traffic, then every
+/// `interval` (or the system default).
+pub fn set_keepalive(stream: &TcpStream, idle: Duration, interval: Option<Duration>) -> io::Result<()>{
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+    return unix::set_keepalive(stream, idle, interval);
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+    {
+        let _ = (stream, idle, interval);
+        Err(io::Error::new(io::ErrorKind::Unsupported, "TCP keepalive is not supported on this platform"))
+    }
+}
+
+/// The idle time before `stream`'s keepalive probes start, or `None` if
+/// keepalive is off; what `set_keepalive` set, as the kernel has it.
+pub fn keepalive(stream: &TcpStream) -> io::Result<Option<Duration>>{
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+    return unix::keepalive(stream);
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+    {
+        let _ = stream;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "TCP keepalive is not supported on this platform"))
+    }
+}
+
+/// Wait up to `timeout` for `listener` to have a connection to accept.
+/// Returns whether one is (probably) waiting; `false` means the time ran
+/// out. Where readiness can't be polled this just sleeps and says yes.
+pub fn wait_readable(listener: &TcpListener, timeout: Duration) -> io::Result<bool>{
+    let mut poller = Poller::new();
+    poller.register(listener, 0);
+    match poller.wait(timeout){
+        Ok(ready) => Ok(!ready.is_empty()),
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            std::thread::sleep(timeout);
+            Ok(true)
+        },
+        Err(e) => Err(e),
+    }
+}
+
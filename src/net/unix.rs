@@ -0,0 +1,173 @@
+use std::{
+    ffi::c_void,
+    io, mem,
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::fd::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+    time::Duration,
+};
+
+// The handful of libc calls std doesn't wrap. std already links libc, so
+// declaring them is enough.
+mod ffi{
+    use std::ffi::c_void;
+
+    extern "C"{
+        pub fn socket(domain: i32, kind: i32, protocol: i32) -> i32;
+        pub fn setsockopt(fd: i32, level: i32, name: i32, value: *const c_void, len: u32) -> i32;
+        pub fn getsockopt(fd: i32, level: i32, name: i32, value: *mut c_void, len: *mut u32) -> i32;
+        pub fn bind(fd: i32, addr: *const u8, len: u32) -> i32;
+        pub fn listen(fd: i32, backlog: i32) -> i32;
+        pub fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+    }
+}
+
+const SOCK_STREAM: i32 = 1;
+const IPPROTO_TCP: i32 = 6;
+const F_SETFD: i32 = 2;
+const FD_CLOEXEC: i32 = 1;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod consts{
+    pub const AF_INET: i32 = 2;
+    pub const AF_INET6: i32 = 10;
+    pub const SOL_SOCKET: i32 = 1;
+    pub const SO_REUSEADDR: i32 = 2;
+    pub const SO_REUSEPORT: i32 = 15;
+    pub const SO_TYPE: i32 = 3;
+    pub const SO_ACCEPTCONN: i32 = 30;
+    pub const SO_KEEPALIVE: i32 = 9;
+    pub const TCP_KEEPIDLE: i32 = 4;
+    pub const TCP_KEEPINTVL: i32 = 5;
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod consts{
+    pub const AF_INET: i32 = 2;
+    pub const AF_INET6: i32 = 30;
+    pub const SOL_SOCKET: i32 = 0xffff;
+    pub const SO_REUSEADDR: i32 = 0x4;
+    pub const SO_REUSEPORT: i32 = 0x200;
+    pub const SO_TYPE: i32 = 0x1008;
+    pub const SO_ACCEPTCONN: i32 = 0x2;
+    pub const SO_KEEPALIVE: i32 = 0x8;
+    pub const TCP_KEEPIDLE: i32 = 0x10;     // Called TCP_KEEPALIVE here.
+    pub const TCP_KEEPINTVL: i32 = 0x101;
+}
+
+use consts::*;
+
+pub fn bind(addr: &SocketAddr, reuse_address: bool, reuse_port: bool, backlog: u32) -> io::Result<TcpListener>{
+    let domain = if addr.is_ipv4() { AF_INET } else { AF_INET6 };
+    let fd = check(unsafe { ffi::socket(domain, SOCK_STREAM, 0) })?;
+    // Owning the descriptor straight away means every error path closes it.
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+    check(unsafe { ffi::fcntl(fd, F_SETFD, FD_CLOEXEC) })?;
+    if reuse_address{
+        set_int(fd, SOL_SOCKET, SO_REUSEADDR, 1)?;
+    }
+    if reuse_port{
+        set_int(fd, SOL_SOCKET, SO_REUSEPORT, 1)?;
+    }
+    let sockaddr = encode_sockaddr(addr);
+    check(unsafe { ffi::bind(fd, sockaddr.as_ptr(), sockaddr.len() as u32) })?;
+    check(unsafe { ffi::listen(fd, i32::try_from(backlog).unwrap_or(i32::MAX)) })?;
+    Ok(listener)
+}
+
+/// Take over `fd` as a listener, once it checks out as a listening TCP
+/// socket. It is marked close-on-exec, as our own sockets are.
+pub fn listener_from_fd(fd: RawFd) -> io::Result<TcpListener>{
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("descriptor {} {}", fd, msg));
+    if get_int(fd, SOL_SOCKET, SO_TYPE).map_err(|_| invalid("is not a socket"))? != SOCK_STREAM{
+        return Err(invalid("is not a stream socket"));
+    }
+    if get_int(fd, SOL_SOCKET, SO_ACCEPTCONN)? == 0{
+        return Err(invalid("is not listening"));
+    }
+    check(unsafe { ffi::fcntl(fd, F_SETFD, FD_CLOEXEC) })?;
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    // Only IPv4 and IPv6 sockets have an address std can read back.
+    if listener.local_addr().is_err(){
+        // Hand the descriptor back rather than closing something we don't own.
+        let _ = listener.into_raw_fd();
+        return Err(invalid("is not a TCP socket"));
+    }
+    Ok(listener)
+}
+
+/// Whether child processes inherit `fd`: clears or sets `FD_CLOEXEC`.
+pub fn set_inheritable(fd: RawFd, inheritable: bool) -> io::Result<()>{
+    check(unsafe { ffi::fcntl(fd, F_SETFD, if inheritable { 0 } else { FD_CLOEXEC }) }).map(|_| ())
+}
+
+pub fn set_keepalive(stream: &TcpStream, idle: Duration, interval: Option<Duration>) -> io::Result<()>{
+    let fd = stream.as_raw_fd();
+    set_int(fd, SOL_SOCKET, SO_KEEPALIVE, 1)?;
+    set_int(fd, IPPROTO_TCP, TCP_KEEPIDLE, seconds(idle))?;
+    if let Some(interval) = interval{
+        set_int(fd, IPPROTO_TCP, TCP_KEEPINTVL, seconds(interval))?;
+    }
+    Ok(())
+}
+
+pub fn keepalive(stream: &TcpStream) -> io::Result<Option<Duration>>{
+    let fd = stream.as_raw_fd();
+    if get_int(fd, SOL_SOCKET, SO_KEEPALIVE)? == 0{
+        return Ok(None);
+    }
+    let idle = get_int(fd, IPPROTO_TCP, TCP_KEEPIDLE)?;
+    Ok(Some(Duration::from_secs(u64::try_from(idle).unwrap_or(0))))
+}
+
+fn get_int(fd: RawFd, level: i32, name: i32) -> io::Result<i32>{
+    let mut value = 0i32;
+    let mut len = mem::size_of::<i32>() as u32;
+    check(unsafe { ffi::getsockopt(fd, level, name, &mut value as *mut i32 as *mut c_void, &mut len) })?;
+    Ok(value)
+}
+
+fn set_int(fd: RawFd, level: i32, name: i32, value: i32) -> io::Result<()>{
+    let len = mem::size_of::<i32>() as u32;
+    check(unsafe { ffi::setsockopt(fd, level, name, &value as *const i32 as *const c_void, len) }).map(|_| ())
+}
+
+// The kernel takes whole seconds, and at least one.
+fn seconds(duration: Duration) -> i32{
+    i32::try_from(duration.as_secs().max(1)).unwrap_or(i32::MAX)
+}
+
+fn check(result: i32) -> io::Result<i32>{
+    if result < 0 { Err(io::Error::last_os_error()) } else { Ok(result) }
+}
+
+/// Lay out a `sockaddr_in` or `sockaddr_in6` byte by byte, which avoids
+/// declaring the structs for each platform. Only the family field differs:
+/// BSDs prefix it with a length byte.
+fn encode_sockaddr(addr: &SocketAddr) -> Vec<u8>{
+    let (family, len) = match addr{
+        SocketAddr::V4(_) => (AF_INET, 16),
+        SocketAddr::V6(_) => (AF_INET6, 28),
+    };
+
+    let mut out = Vec::with_capacity(len);
+    if cfg!(any(target_os = "macos", target_os = "ios")){
+        out.push(len as u8);
+        out.push(family as u8);
+    } else {
+        out.extend_from_slice(&(family as u16).to_ne_bytes());
+    }
+    out.extend_from_slice(&addr.port().to_be_bytes());
+    match addr{
+        SocketAddr::V4(v4) => {
+            out.extend_from_slice(&v4.ip().octets());
+            out.extend_from_slice(&[0; 8]);
+        },
+        SocketAddr::V6(v6) => {
+            out.extend_from_slice(&v6.flowinfo().to_be_bytes());
+            out.extend_from_slice(&v6.ip().octets());
+            out.extend_from_slice(&v6.scope_id().to_ne_bytes());
+        },
+    }
+    out
+}
@@ -0,0 +1,182 @@
+// Readiness polling over many sockets at once: `poll(2)` on unix,
+// `WSAPoll` on Windows. The FFI stays in this module, behind `Poller`.
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+/// A socket a `Poller` can wait on: anything with a raw descriptor (a
+/// raw socket on Windows), such as a `TcpStream` or `TcpListener`.
+pub trait Source{
+    fn raw_socket(&self) -> sys::Raw;
+}
+
+#[cfg(unix)]
+impl<T: std::os::fd::AsRawFd> Source for T{
+    fn raw_socket(&self) -> sys::Raw{
+        self.as_raw_fd()
+    }
+}
+
+#[cfg(windows)]
+impl<T: std::os::windows::io::AsRawSocket> Source for T{
+    fn raw_socket(&self) -> sys::Raw{
+        self.as_raw_socket()
+    }
+}
+
+/// Waits for any of a set of sockets to become readable.
+///
+/// Each socket is registered under a token of the caller's choosing, and
+/// `wait` reports the tokens of those with something to read, including
+/// end of stream and errors, which a read will then report. The poller
+/// only holds the descriptor numbers: deregister a socket before closing
+/// it, or a later socket given the same number will be polled in its
+/// place.
+#[derive(Debug, Clone, Default)]
+pub struct Poller{
+    sockets: Vec<(sys::Raw, usize)>,    // Descriptor and token.
+}
+
+impl Poller{
+    pub fn new() -> Poller{
+        Poller::default()
+    }
+
+    /// Watch `socket` under `token`, replacing whatever had that token.
+    pub fn register<S: Source + ?Sized>(&mut self, socket: &S, token: usize){
+        self.deregister(token);
+        self.sockets.push((socket.raw_socket(), token));
+    }
+
+    /// Stop watching the socket under `token`. Returns whether there was one.
+    pub fn deregister(&mut self, token: usize) -> bool{
+        let before = self.sockets.len();
+        self.sockets.retain(|(_, registered)| *registered != token);
+        self.sockets.len() != before
+    }
+
+    pub fn len(&self) -> usize{
+        self.sockets.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.sockets.is_empty()
+    }
+
+    /// Wait up to `timeout` for registered sockets to be readable and
+    /// return their tokens, in registration order; none means the time
+    /// ran out. A wait cut short by a signal carries on for the rest of
+    /// the time.
+    pub fn wait(&self, timeout: Duration) -> io::Result<Vec<usize>>{
+        let deadline = Instant::now() + timeout;
+        loop{
+            let left = deadline.saturating_duration_since(Instant::now());
+            match sys::poll(&self.sockets.iter().map(|(raw, _)| *raw).collect::<Vec<_>>(), left){
+                Ok(ready) => {
+                    return Ok(self.sockets.iter().zip(ready).filter(|(_, ready)| *ready).map(|((_, token), _)| *token).collect());
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    if left.is_zero(){
+                        return Ok(Vec::new());
+                    }
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// Milliseconds for the system call, rounded up so a short wait doesn't
+// become a busy loop of zero-length ones.
+#[cfg(any(unix, windows))]
+fn millis(timeout: Duration) -> i32{
+    let millis = timeout.as_nanos().div_ceil(1_000_000);
+    i32::try_from(millis).unwrap_or(i32::MAX)
+}
+
+#[cfg(unix)]
+mod sys{
+    use std::{io, os::fd::RawFd, time::Duration};
+
+    pub type Raw = RawFd;
+
+    #[repr(C)]
+    struct PollFd{
+        fd: i32,
+        events: i16,
+        revents: i16,
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    type NfdsT = std::ffi::c_ulong;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    type NfdsT = std::ffi::c_uint;
+
+    const POLLIN: i16 = 1;
+
+    extern "C"{
+        #[link_name = "poll"]
+        fn sys_poll(fds: *mut PollFd, nfds: NfdsT, timeout: i32) -> i32;
+    }
+
+    pub fn poll(fds: &[Raw], timeout: Duration) -> io::Result<Vec<bool>>{
+        let mut polled: Vec<PollFd> = fds.iter().map(|&fd| PollFd { fd, events: POLLIN, revents: 0 }).collect();
+        // Safety: `polled` is a live array of `polled.len()` `pollfd`s, laid out as poll(2) expects.
+        let result = unsafe { sys_poll(polled.as_mut_ptr(), polled.len() as NfdsT, super::millis(timeout)) };
+        if result < 0{
+            return Err(io::Error::last_os_error());     // `Interrupted` for EINTR.
+        }
+        // Hang-ups and errors come back whether asked for or not.
+        Ok(polled.iter().map(|fd| fd.revents != 0).collect())
+    }
+}
+
+#[cfg(windows)]
+mod sys{
+    use std::{io, os::windows::io::RawSocket, time::Duration};
+
+    pub type Raw = RawSocket;
+
+    #[repr(C)]
+    struct WsaPollFd{
+        fd: usize,
+        events: i16,
+        revents: i16,
+    }
+
+    const POLLRDNORM: i16 = 0x0100;
+
+    #[link(name = "ws2_32")]
+    extern "system"{
+        fn WSAPoll(fds: *mut WsaPollFd, nfds: u32, timeout: i32) -> i32;
+    }
+
+    pub fn poll(fds: &[Raw], timeout: Duration) -> io::Result<Vec<bool>>{
+        if fds.is_empty(){
+            // WSAPoll refuses an empty set.
+            std::thread::sleep(timeout);
+            return Ok(Vec::new());
+        }
+        let mut polled: Vec<WsaPollFd> = fds.iter()
+            .map(|&fd| WsaPollFd { fd: fd as usize, events: POLLRDNORM, revents: 0 })
+            .collect();
+        // Safety: `polled` is a live array of `polled.len()` `WSAPOLLFD`s.
+        let result = unsafe { WSAPoll(polled.as_mut_ptr(), polled.len() as u32, super::millis(timeout)) };
+        if result < 0{
+            return Err(io::Error::last_os_error());
+        }
+        Ok(polled.iter().map(|fd| fd.revents != 0).collect())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys{
+    use std::{io, time::Duration};
+
+    pub type Raw = i32;
+
+    pub fn poll(_: &[Raw], _: Duration) -> io::Result<Vec<bool>>{
+        Err(io::Error::new(io::ErrorKind::Unsupported, "polling is not supported on this platform"))
+    }
+}
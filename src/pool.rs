@@ -0,0 +1,5 @@
+mod buffer;
+mod connection;
+
+pub use buffer::{BufferPool, PooledBuf};
+pub use connection::{ConnectionPool, PoolStats, PooledConnection};
@@ -0,0 +1,112 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+};
+
+/// Byte buffers kept for reuse, so a busy connection doesn't allocate a
+/// fresh one for every request.
+///
+/// `get` hands out an empty buffer, reusing a pooled one when there is
+/// one; dropping it gives it back, cleared but with its capacity intact.
+/// At most `max_buffers` wait in the pool, and one that grew past
+/// `max_capacity` is freed instead, so a single huge response doesn't
+/// pin its memory forever.
+///
+/// Clones share the same buffers. `BufferPool::local` gives each thread
+/// its own, which keeps workers from contending for one lock.
+#[derive(Clone)]
+pub struct BufferPool{
+    shared: Arc<Shared>,
+}
+
+struct Shared{
+    free: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+    max_capacity: usize,
+    initial_capacity: usize,    // For buffers the pool has to allocate.
+}
+
+thread_local!{
+    static LOCAL: BufferPool = BufferPool::new(16, 256 * 1024).with_initial_capacity(4096);
+}
+
+impl BufferPool{
+    pub fn new(max_buffers: usize, max_capacity: usize) -> BufferPool{
+        BufferPool {
+            shared: Arc::new(Shared {
+                free: Mutex::new(Vec::new()),
+                max_buffers,
+                max_capacity,
+                initial_capacity: 0,
+            }),
+        }
+    }
+
+    /// Allocate new buffers with room for `capacity` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has already been cloned.
+    pub fn with_initial_capacity(mut self, capacity: usize) -> BufferPool{
+        Arc::get_mut(&mut self.shared).expect("configured before sharing").initial_capacity = capacity;
+        self
+    }
+
+    /// This thread's pool, created the first time a thread asks: up to 16
+    /// buffers of at most 256 KiB each, starting at 4 KiB.
+    pub fn local() -> BufferPool{
+        LOCAL.with(BufferPool::clone)
+    }
+
+    pub fn get(&self) -> PooledBuf{
+        let buf = self.shared.free.lock().unwrap().pop()
+            .unwrap_or_else(|| Vec::with_capacity(self.shared.initial_capacity));
+        PooledBuf { buf, pool: Arc::clone(&self.shared) }
+    }
+
+    /// Buffers waiting to be reused.
+    pub fn pooled(&self) -> usize{
+        self.shared.free.lock().unwrap().len()
+    }
+}
+
+/// A buffer from a `BufferPool`, returned to it on drop.
+pub struct PooledBuf{
+    buf: Vec<u8>,
+    pool: Arc<Shared>,
+}
+
+impl PooledBuf{
+    /// Keep the buffer instead of returning it to the pool.
+    pub fn into_inner(mut self) -> Vec<u8>{
+        std::mem::take(&mut self.buf)
+    }
+}
+
+impl Deref for PooledBuf{
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8>{
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuf{
+    fn deref_mut(&mut self) -> &mut Vec<u8>{
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuf{
+    fn drop(&mut self){
+        let mut buf = std::mem::take(&mut self.buf);
+        if buf.capacity() == 0 || buf.capacity() > self.pool.max_capacity{
+            return;
+        }
+        buf.clear();
+        let mut free = self.pool.free.lock().unwrap_or_else(|e| e.into_inner());
+        if free.len() < self.pool.max_buffers{
+            free.push(buf);
+        }
+    }
+}
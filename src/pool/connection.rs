@@ -0,0 +1,202 @@
+use std::{
+    io,
+    net::{SocketAddr, TcpStream},
+    ops::{Deref, DerefMut},
+    sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Counters describing how well a `ConnectionPool` is reusing connections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats{
+    pub hits: u64,          // Checkouts served by an idle connection.
+    pub misses: u64,        // Checkouts that had to dial a new connection.
+    pub discards: u64,      // Idle connections dropped as dead, expired or surplus.
+}
+
+struct IdleConnection{
+    stream: TcpStream,
+    idle_since: Instant,
+}
+
+/// Idle keep-alive connections to one upstream server.
+pub struct ConnectionPool{
+    addr: SocketAddr,
+    slots: Mutex<Vec<Option<IdleConnection>>>,  // `capacity` of them; a full pool closes what comes back.
+    idle_timeout: Duration,                     // Connections idle longer than this are never reused.
+    hits: AtomicU64,
+    misses: AtomicU64,
+    discards: AtomicU64,
+}
+
+impl ConnectionPool{
+    /// Create a pool keeping at most `capacity` idle connections to
+    /// `addr`, each for up to a minute; see `with_idle_timeout`.
+    pub fn new(addr: SocketAddr, capacity: usize) -> ConnectionPool{
+        ConnectionPool {
+            addr,
+            slots: Mutex::new((0..capacity).map(|_| None).collect()),
+            idle_timeout: Duration::from_secs(60),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            discards: AtomicU64::new(0),
+        }
+    }
+
+    /// Close connections that have been idle for longer than `timeout`
+    /// instead of reusing them.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> ConnectionPool{
+        self.idle_timeout = timeout;
+        self
+    }
+
+    pub fn addr(&self) -> SocketAddr{
+        self.addr
+    }
+
+    /// Check out a connection, reusing a live idle one if possible and
+    /// dialing a new one otherwise. It goes back to the pool on drop once
+    /// marked reusable.
+    pub fn acquire(self: &Arc<Self>) -> io::Result<PooledConnection>{
+        loop{
+            // Only hold the lock while taking one; probing happens outside it.
+            let candidate = self.slots.lock().unwrap()
+                .iter_mut()
+                .find_map(Option::take);
+
+            let conn = match candidate{
+                Some(conn) => conn,
+                None => break,
+            };
+
+            if conn.idle_since.elapsed() < self.idle_timeout && is_alive(&conn.stream){
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(PooledConnection::new(self, conn.stream, true));
+            }
+            self.discards.fetch_add(1, Ordering::Relaxed);
+        }
+        self.dial()
+    }
+
+    /// Dial a fresh connection, bypassing idle ones.
+    pub fn dial(self: &Arc<Self>) -> io::Result<PooledConnection>{
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let stream = TcpStream::connect(self.addr)?;
+        Ok(PooledConnection::new(self, stream, false))
+    }
+
+    // Take back a connection whose last response was read completely.
+    fn release(&self, stream: TcpStream){
+        let mut slots = self.slots.lock().unwrap();
+
+        // Drop anything that expired while it sat in the pool.
+        let mut discarded = 0;
+        for slot in slots.iter_mut(){
+            if slot.as_ref().is_some_and(|c| c.idle_since.elapsed() >= self.idle_timeout){
+                *slot = None;
+                discarded += 1;
+            }
+        }
+
+        match slots.iter_mut().find(|slot| slot.is_none()){
+            Some(slot) => *slot = Some(IdleConnection { stream, idle_since: Instant::now() }),
+            None => discarded += 1,
+        }
+        self.discards.fetch_add(discarded, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> PoolStats{
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            discards: self.discards.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Number of idle connections currently held.
+    pub fn idle_count(&self) -> usize{
+        self.slots.lock().unwrap().iter().filter(|slot| slot.is_some()).count()
+    }
+}
+
+/// Probe an idle connection without consuming any data.
+///
+/// A closed socket reads as EOF and a socket with unsolicited bytes is out
+/// of sync with us; only a read that would block means the peer is still
+/// there and quiet. A zero-byte write would succeed on a socket the peer
+/// has already closed, so it can't tell.
+fn is_alive(stream: &TcpStream) -> bool{
+    if stream.set_nonblocking(true).is_err(){
+        return false;
+    }
+    let alive = match stream.peek(&mut [0u8; 1]){
+        Ok(_) => false,
+        Err(e) => e.kind() == io::ErrorKind::WouldBlock,
+    };
+    stream.set_nonblocking(false).is_ok() && alive
+}
+
+/// A checked-out connection that can return itself to its pool on drop.
+///
+/// It is closed on drop unless `mark_reusable` was called, which should
+/// only happen once a response has been read to its end and the upstream
+/// means to keep the connection open: an error path that drops it part-way
+/// through an exchange would otherwise pool a socket with a half-read
+/// response on it. Connections dropped while the thread is panicking are
+/// closed too.
+pub struct PooledConnection{
+    pool: Arc<ConnectionPool>,
+    stream: Option<TcpStream>,      // Taken on drop.
+    reused: bool,
+    reusable: bool,
+}
+
+impl PooledConnection{
+    fn new(pool: &Arc<ConnectionPool>, stream: TcpStream, reused: bool) -> PooledConnection{
+        PooledConnection {
+            pool: Arc::clone(pool),
+            stream: Some(stream),
+            reused,
+            reusable: false,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr{
+        self.pool.addr
+    }
+
+    /// Whether the connection came from the pool rather than a fresh dial.
+    pub fn reused(&self) -> bool{
+        self.reused
+    }
+
+    /// Return the connection to the pool on drop instead of closing it.
+    pub fn mark_reusable(&mut self){
+        self.reusable = true;
+    }
+}
+
+impl Deref for PooledConnection{
+    type Target = TcpStream;
+
+    fn deref(&self) -> &TcpStream{
+        self.stream.as_ref().expect("stream is only taken on drop")
+    }
+}
+
+impl DerefMut for PooledConnection{
+    fn deref_mut(&mut self) -> &mut TcpStream{
+        self.stream.as_mut().expect("stream is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection{
+    fn drop(&mut self){
+        if let Some(stream) = self.stream.take(){
+            if self.reusable && !thread::panicking(){
+                self.pool.release(stream);
+            }
+        }
+    }
+}
@@ -0,0 +1,588 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    config::{Config, ConfigError},
+    http::{self, Headers, HttpVersion, Request, Response, TargetForm, Upgrade},
+    log,
+    negotiation,
+    router::{Middleware, Next},
+};
+
+mod balancer;
+
+pub use balancer::{LoadBalancer, Strategy, UpstreamGuard};
+pub use crate::pool::{ConnectionPool, PoolStats, PooledConnection};
+
+/// Headers that only describe a single hop and must not be forwarded.
+const HOP_BY_HOP: [&str; 8] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// The largest upstream response body read by default; see
+/// `ReverseProxy::with_max_body_bytes`.
+pub const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Idle connections kept per upstream by default; see
+/// `ReverseProxy::with_pool_capacity`.
+pub const DEFAULT_POOL_CAPACITY: usize = 8;
+
+/// The longest status, header, chunk-size or trailer line accepted from an
+/// upstream, without its CRLF.
+const MAX_LINE: usize = 8 * 1024;
+
+/// Errors raised while forwarding a request to an upstream server.
+#[derive(Debug)]
+pub enum ProxyError{
+    Upstream(io::Error),        // Connecting to, writing to or reading from the upstream failed.
+    InvalidResponse(String),    // The upstream answered with something that isn't HTTP.
+    NoHealthyUpstream,          // Every upstream is currently ejected.
+    ResponseTooLarge,           // The body is over the proxy's limit, or a line over `MAX_LINE`.
+}
+
+impl ProxyError{
+    /// The response sent to the client when forwarding fails.
+    pub fn to_response(&self) -> Response{
+        let status = match self{
+            ProxyError::NoHealthyUpstream => 503,
+            _ => 502,
+        };
+        Response::new(status, http::reason_phrase(status))
+            .with_header("Content-Type", "text/plain")
+            .with_body(http::reason_phrase(status))
+    }
+}
+
+impl fmt::Display for ProxyError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            ProxyError::Upstream(e) => write!(f, "upstream error: {}", e),
+            ProxyError::InvalidResponse(msg) => write!(f, "invalid upstream response: {}", msg),
+            ProxyError::NoHealthyUpstream => write!(f, "no healthy upstream"),
+            ProxyError::ResponseTooLarge => write!(f, "upstream response too large"),
+        }
+    }
+}
+
+impl Error for ProxyError{
+    fn source(&self) -> Option<&(dyn Error + 'static)>{
+        match self{
+            ProxyError::Upstream(e) => Some(e),
+            ProxyError::InvalidResponse(_) | ProxyError::NoHealthyUpstream | ProxyError::ResponseTooLarge => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProxyError{
+    fn from(e: io::Error) -> ProxyError{
+        ProxyError::Upstream(e)
+    }
+}
+
+/// Forwards requests to one or more upstream servers over pooled connections.
+pub struct ReverseProxy{
+    balancer: LoadBalancer,
+    pools: Mutex<HashMap<String, Arc<ConnectionPool>>>,    // By upstream address, made on first use.
+    pool_capacity: usize,
+    max_body_bytes: usize,      // Larger upstream bodies are refused with `502`.
+}
+
+impl ReverseProxy{
+    /// Create a proxy for `upstream` (a `host:port` address).
+    pub fn new(upstream: &str) -> ReverseProxy{
+        ReverseProxy::balanced(LoadBalancer::new(vec![upstream.to_string()], Strategy::RoundRobin))
+    }
+
+    /// Create a proxy for the upstream `pool` connects to, sharing the
+    /// pool with other proxies.
+    pub fn with_pool(pool: Arc<ConnectionPool>) -> ReverseProxy{
+        let addr = pool.addr().to_string();
+        let proxy = ReverseProxy::new(&addr);
+        proxy.pools.lock().unwrap().insert(addr, pool);
+        proxy
+    }
+
+    /// Create a proxy that spreads requests over the balancer's upstreams,
+    /// with a pool for each.
+    pub fn balanced(balancer: LoadBalancer) -> ReverseProxy{
+        ReverseProxy {
+            balancer,
+            pools: Mutex::new(HashMap::new()),
+            pool_capacity: DEFAULT_POOL_CAPACITY,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+
+    /// Keep up to `capacity` idle connections to each upstream whose pool
+    /// this proxy makes. `DEFAULT_POOL_CAPACITY` until this is called.
+    pub fn with_pool_capacity(mut self, capacity: usize) -> ReverseProxy{
+        self.pool_capacity = capacity;
+        self
+    }
+
+    /// Refuse upstream responses whose body is over `max` bytes, whatever
+    /// their `Content-Length` or chunk sizes claim, rather than allocate
+    /// for them. `DEFAULT_MAX_BODY_BYTES` until this is called.
+    pub fn with_max_body_bytes(mut self, max: usize) -> ReverseProxy{
+        self.max_body_bytes = max;
+        self
+    }
+
+    /// Build a proxy from `section` of `config`; see `LoadBalancer::from_config`.
+    /// `max_body_bytes` sets `with_max_body_bytes`.
+    pub fn from_config(config: &Config, section: &str) -> Result<ReverseProxy, ConfigError>{
+        let mut proxy = ReverseProxy::balanced(LoadBalancer::from_config(config, section)?);
+        let key = format!("{}.max_body_bytes", section);
+        if let Some(n) = config.get_int(&key)?{
+            proxy.max_body_bytes = usize::try_from(n).map_err(|_| ConfigError::invalid(&key, "must not be negative"))?;
+        }
+        Ok(proxy)
+    }
+
+    pub fn balancer(&self) -> &LoadBalancer{
+        &self.balancer
+    }
+
+    /// The pool for `upstream`, once a request has gone there.
+    pub fn pool(&self, upstream: &str) -> Option<Arc<ConnectionPool>>{
+        self.pools.lock().unwrap().get(upstream).cloned()
+    }
+
+    // The pool for `upstream`, made on first use for the first address it
+    // resolves to.
+    fn pool_for(&self, upstream: &str) -> io::Result<Arc<ConnectionPool>>{
+        if let Some(pool) = self.pool(upstream){
+            return Ok(pool);
+        }
+        let addr = upstream.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses for the upstream"))?;
+        let mut pools = self.pools.lock().unwrap();
+        let pool = pools.entry(upstream.to_string())
+            .or_insert_with(|| Arc::new(ConnectionPool::new(addr, self.pool_capacity)));
+        Ok(Arc::clone(pool))
+    }
+
+    /// Send `request` upstream and return its response.
+    ///
+    /// If connecting to the chosen upstream fails, the other healthy
+    /// upstreams are tried in turn. A pooled connection can be closed by the
+    /// upstream between the liveness probe and our write, so a failure on a
+    /// reused connection is retried once on a freshly dialed one. Only
+    /// idempotent methods are: the failure may have come after the upstream
+    /// got the request, and a `POST` must not reach it twice.
+    pub fn forward(&self, request: &Request) -> Result<Response, ProxyError>{
+        let mut tried = Vec::new();
+        let mut connect_error = None;
+
+        while let Some(upstream) = self.balancer.pick(&tried){
+            tried.push(upstream.index());
+            let addr = upstream.addr();
+
+            let (pool, conn) = match self.pool_for(addr).and_then(|pool| pool.acquire().map(|conn| (pool, conn))){
+                Ok(checked_out) => checked_out,
+                Err(e) => {
+                    upstream.failed();
+                    connect_error = Some(e);
+                    continue;
+                },
+            };
+            upstream.succeeded();
+
+            let reused = conn.reused();
+            let outgoing = upstream_request(request, addr);
+            return match exchange(conn, &outgoing, self.max_body_bytes){
+                Err(ProxyError::Upstream(_)) if reused && request.method.is_idempotent() => exchange(pool.dial()?, &outgoing, self.max_body_bytes),
+                result => result,
+            };
+        }
+
+        Err(match connect_error{
+            Some(e) => ProxyError::Upstream(e),
+            None => ProxyError::NoHealthyUpstream,
+        })
+    }
+
+    /// Forward `request`, turning a failure into its error response.
+    ///
+    /// The failure goes in the response's extensions, so middleware can
+    /// tell a failed forward apart from an error response that the
+    /// upstream itself sent, whichever thread the handler ran on.
+    pub fn handle(&self, request: &Request) -> Response{
+        match self.forward(request){
+            Ok(response) => response,
+            Err(e) => {
+                log::warn(&format!("Proxying {} failed: {}", request.path, e));
+                let response = e.to_response();
+                response.extensions().insert(e);
+                response
+            },
+        }
+    }
+
+}
+
+/// Answers `CONNECT host:port` by opening a TCP connection to that
+/// address and relaying bytes both ways, so clients (browsers, mostly,
+/// with TLS inside) can use this server as a forward proxy.
+///
+/// Only ports in the allowed list are tunnelled to, 443 unless told
+/// otherwise: an open tunnel to any port would let clients reach mail
+/// servers and the like as if they were this host. The target's host
+/// must also pass `server.allowed_hosts`, which usually means emptying
+/// it on a proxy. Other methods pass through.
+pub struct ConnectHandler{
+    allowed_ports: Vec<u16>,    // Empty allows any.
+    connect_timeout: Duration,
+}
+
+impl ConnectHandler{
+    pub fn new() -> ConnectHandler{
+        ConnectHandler { allowed_ports: vec![443], connect_timeout: Duration::from_secs(10) }
+    }
+
+    /// Tunnel only to `ports`; an empty list allows every port.
+    pub fn with_allowed_ports(mut self, ports: Vec<u16>) -> ConnectHandler{
+        self.allowed_ports = ports;
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> ConnectHandler{
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Connect to `request`'s target and answer `200 Connection
+    /// Established`, with the tunnel to run once that is sent; or `400`
+    /// for a target that isn't `host:port`, `403` for a port that isn't
+    /// allowed and `502` when the target can't be reached.
+    pub fn handle(&self, request: &Request) -> Response{
+        let target = match (request.target_form, request.authority.as_deref()){
+            (TargetForm::Authority, Some(target)) => target,
+            _ => return negotiation::status_page(request, 400, "CONNECT needs a host:port target"),
+        };
+        let port = http::parse_host(target).and_then(|(_, port)| port).unwrap_or(0);
+        if !self.allowed_ports.is_empty() && !self.allowed_ports.contains(&port){
+            return negotiation::status_page(request, 403, "Tunnelling to that port is not allowed");
+        }
+
+        let upstream = match self.connect(target){
+            Ok(upstream) => upstream,
+            Err(e) => {
+                log::warn(&format!("CONNECT to {} failed: {}", target, e));
+                return ProxyError::Upstream(e).to_response();
+            },
+        };
+        // `Upgrade` may be called more than once in principle; the tunnel runs the first time.
+        let upstream = Mutex::new(Some(upstream));
+        Response::new(200, "Connection Established").with_upgrade(Upgrade::new(move |client| {
+            if let Some(upstream) = upstream.lock().unwrap().take(){
+                // Either end resetting the tunnel is routine.
+                if let Err(e) = tunnel(client, upstream){
+                    log::debug(&format!("Tunnel closed: {}", e));
+                }
+            }
+        }))
+    }
+
+    fn connect(&self, target: &str) -> io::Result<TcpStream>{
+        let mut last_error = None;
+        for addr in target.to_socket_addrs()?{
+            match TcpStream::connect_timeout(&addr, self.connect_timeout){
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses for the target")))
+    }
+}
+
+impl Default for ConnectHandler{
+    fn default() -> ConnectHandler{
+        ConnectHandler::new()
+    }
+}
+
+impl Middleware for ConnectHandler{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        if request.method != "CONNECT"{
+            return next.run(request);
+        }
+        ConnectHandler::handle(self, request)
+    }
+}
+
+/// Relay bytes between `client` and `upstream` until both directions
+/// have ended, copying one way on a thread of its own. Returns the bytes
+/// sent each way: client to upstream, then back.
+///
+/// The end of one side's stream is passed on as a half close, so the
+/// other can still answer. An error in either direction cuts both off.
+pub fn tunnel(client: TcpStream, upstream: TcpStream) -> io::Result<(u64, u64)>{
+    // The keep-alive timeout was for waiting on requests, not tunnelled data.
+    client.set_read_timeout(None)?;
+    upstream.set_read_timeout(None)?;
+
+    let (client_reader, upstream_writer) = (client.try_clone()?, upstream.try_clone()?);
+    let outbound = thread::spawn(move || relay(client_reader, upstream_writer));
+    let inbound = relay(upstream, client);
+    let outbound = outbound.join().unwrap_or_else(|_| Err(io::Error::other("tunnel thread panicked")));
+    Ok((outbound?, inbound?))
+}
+
+// Copy `from` to `to` until `from` ends, then half-close `to`.
+fn relay(mut from: TcpStream, mut to: TcpStream) -> io::Result<u64>{
+    match io::copy(&mut from, &mut to){
+        Ok(copied) => {
+            let _ = to.shutdown(Shutdown::Write);
+            Ok(copied)
+        },
+        Err(e) => {
+            // Wake the other direction too: the tunnel is broken.
+            let _ = from.shutdown(Shutdown::Both);
+            let _ = to.shutdown(Shutdown::Both);
+            Err(e)
+        },
+    }
+}
+
+/// Send one request over `conn` and read the response, with a body of at
+/// most `max_body` bytes.
+///
+/// The connection goes back to the pool when `conn` is dropped only if the
+/// response was read to its end and leaves it usable.
+fn exchange(mut conn: PooledConnection, request: &Request, max_body: usize) -> Result<Response, ProxyError>{
+    request.write_to(&mut *conn)?;
+    conn.flush()?;
+
+    let mut reader = BufReader::new(&*conn);
+    let (mut response, reusable) = read_response(&mut reader, request.method == "HEAD", max_body)?;
+
+    // Anything still buffered belongs to no request; don't reuse the socket.
+    if reusable && reader.buffer().is_empty(){
+        conn.mark_reusable();
+    }
+    strip_hop_by_hop(&mut response.headers);
+    Ok(response)
+}
+
+/// Delays between retries that grow by `factor` each time, up to `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExponentialBackoff{
+    pub initial: Duration,      // Delay before the first retry.
+    pub factor: u32,
+    pub max: Duration,
+}
+
+impl ExponentialBackoff{
+    /// The delay before retry number `retry`, counting from zero.
+    pub fn delay(&self, retry: u32) -> Duration{
+        let factor = self.factor.checked_pow(retry).unwrap_or(u32::MAX);
+        self.initial.checked_mul(factor).map_or(self.max, |d| d.min(self.max))
+    }
+}
+
+impl Default for ExponentialBackoff{
+    /// 50 ms, 100 ms, 200 ms, ... capped at 2 s.
+    fn default() -> ExponentialBackoff{
+        ExponentialBackoff {
+            initial: Duration::from_millis(50),
+            factor: 2,
+            max: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Retries requests whose proxied forward failed with `ProxyError::Upstream`.
+///
+/// Each request is tried at most `max_attempts` times, sleeping for the
+/// backoff delay in between; if the last attempt also fails its
+/// `502 Bad Gateway` is returned. Sleeping blocks the worker, which is
+/// acceptable because proxy handlers spend their time waiting on I/O anyway.
+///
+/// Only idempotent methods are retried: a `POST` may have reached the
+/// upstream before the connection failed, and sending it again could
+/// repeat its effect.
+pub struct RetryMiddleware{
+    pub max_attempts: u32,
+    pub backoff: ExponentialBackoff,
+}
+
+impl RetryMiddleware{
+    pub fn new(max_attempts: u32) -> RetryMiddleware{
+        RetryMiddleware {
+            max_attempts,
+            backoff: ExponentialBackoff::default(),
+        }
+    }
+}
+
+impl Middleware for RetryMiddleware{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        if !request.method.is_idempotent(){
+            return next.run(request);
+        }
+        let mut attempt = 1;
+        loop{
+            let response = next.run(request);
+
+            match response.extensions().get::<ProxyError>().as_deref(){
+                Some(ProxyError::Upstream(e)) if attempt < self.max_attempts => {
+                    let delay = self.backoff.delay(attempt - 1);
+                    log::info(&format!("Attempt {} for {} failed ({}); retrying in {:?}.", attempt, request.path, e, delay));
+                    thread::sleep(delay);
+                    attempt += 1;
+                },
+                _ => return response,
+            }
+        }
+    }
+}
+
+/// Copy of the client request suitable for sending upstream.
+fn upstream_request(request: &Request, upstream: &str) -> Request{
+    let mut outgoing = request.clone();
+    outgoing.version = HttpVersion::Http11;
+    strip_hop_by_hop(&mut outgoing.headers);
+    if !outgoing.headers.contains("Host"){
+        outgoing.headers.set("Host", upstream);
+    }
+    outgoing.headers.set("Content-Length", &outgoing.body.len().to_string());
+    outgoing.headers.set("Connection", "keep-alive");
+    outgoing
+}
+
+fn strip_hop_by_hop(headers: &mut Headers){
+    // Connection can name further hop-by-hop headers of its own.
+    let named: Vec<String> = headers.get_all("Connection")
+        .flat_map(|v| v.split(','))
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    for name in named.iter().map(String::as_str).chain(HOP_BY_HOP){
+        headers.remove(name);
+    }
+}
+
+/// Read one response from an upstream, refusing a body over `max_body`
+/// bytes before allocating for it.
+///
+/// The returned flag says whether the connection may carry another
+/// request: the body must have had explicit framing, and the upstream
+/// must not have asked to close.
+fn read_response<R: BufRead>(reader: &mut R, head_request: bool, max_body: usize) -> Result<(Response, bool), ProxyError>{
+    let status_line = read_line(reader)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or("");
+    let status: u16 = parts.next()
+        .and_then(|s| s.parse().ok())
+        .filter(|_| version.starts_with("HTTP/"))
+        .ok_or_else(|| ProxyError::InvalidResponse(status_line.clone()))?;
+    let reason = parts.next().unwrap_or("");
+
+    let mut lines = Vec::new();
+    loop{
+        let line = read_line(reader)?;
+        if line.is_empty(){
+            break;
+        }
+        if lines.len() == http::Limits::default().max_headers{
+            return Err(ProxyError::ResponseTooLarge);
+        }
+        lines.push(line);
+    }
+    let headers = http::parse_header_lines(lines.iter().map(String::as_str))
+        .map_err(|e| ProxyError::InvalidResponse(e.to_string()))?;
+
+    let mut reusable = if version == "HTTP/1.0"{
+        headers.has_token("Connection", "keep-alive")
+    } else {
+        !headers.has_token("Connection", "close")
+    };
+
+    let mut body = Vec::new();
+    let bodiless = head_request || status / 100 == 1 || status == 204 || status == 304;
+    if bodiless{
+        // Nothing follows the header block.
+    } else if headers.has_token("Transfer-Encoding", "chunked"){
+        read_chunked(reader, &mut body, max_body)?;
+    } else if let Some(len) = headers.get("Content-Length"){
+        let len: u64 = len.trim().parse()
+            .map_err(|_| ProxyError::InvalidResponse(format!("bad Content-Length {:?}", len)))?;
+        if len > max_body as u64{
+            return Err(ProxyError::ResponseTooLarge);
+        }
+        body.resize(len as usize, 0);
+        reader.read_exact(&mut body)?;
+    } else {
+        // Close-delimited: the end of the body is the end of the connection.
+        reader.by_ref().take(max_body as u64 + 1).read_to_end(&mut body)?;
+        if body.len() > max_body{
+            return Err(ProxyError::ResponseTooLarge);
+        }
+        reusable = false;
+    }
+
+    let mut response = Response::new(status, reason);
+    response.headers = headers;
+    response.body = body;
+    Ok((response, reusable))
+}
+
+fn read_chunked<R: BufRead>(reader: &mut R, body: &mut Vec<u8>, max_body: usize) -> Result<(), ProxyError>{
+    loop{
+        let line = read_line(reader)?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| ProxyError::InvalidResponse(format!("bad chunk size {:?}", line)))?;
+        if size == 0{
+            break;
+        }
+        let start = body.len();
+        let end = start.checked_add(size).filter(|&end| end <= max_body).ok_or(ProxyError::ResponseTooLarge)?;
+        body.resize(end, 0);
+        reader.read_exact(&mut body[start..])?;
+        read_line(reader)?;     // The CRLF that closes every chunk.
+    }
+
+    // Skip any trailer fields up to the closing blank line.
+    let mut trailers = 0;
+    while !read_line(reader)?.is_empty(){
+        trailers += 1;
+        if trailers > http::Limits::default().max_headers{
+            return Err(ProxyError::ResponseTooLarge);
+        }
+    }
+    Ok(())
+}
+
+/// Read a CRLF-terminated line of at most `MAX_LINE` bytes, without the
+/// terminator.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, ProxyError>{
+    let mut line = String::new();
+    if reader.by_ref().take(MAX_LINE as u64 + 2).read_line(&mut line)? == 0{
+        return Err(ProxyError::Upstream(io::ErrorKind::UnexpectedEof.into()));
+    }
+    while line.ends_with('\n') || line.ends_with('\r'){
+        line.pop();
+    }
+    if line.len() > MAX_LINE{
+        return Err(ProxyError::ResponseTooLarge);
+    }
+    Ok(line)
+}
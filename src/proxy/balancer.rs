@@ -0,0 +1,236 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::BuildHasher,
+    str::FromStr,
+    sync::{atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering}, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{clock::{Clock, SystemClock}, config::{Config, ConfigError}, log};
+
+/// How a `LoadBalancer` chooses between healthy upstreams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy{
+    RoundRobin,         // Take turns in configuration order.
+    Random,             // Pick uniformly at random.
+    LeastConnections,   // Pick the upstream with the fewest requests in flight.
+}
+
+impl FromStr for Strategy{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Strategy, String>{
+        match s{
+            "round_robin" => Ok(Strategy::RoundRobin),
+            "random" => Ok(Strategy::Random),
+            "least_connections" => Ok(Strategy::LeastConnections),
+            _ => Err(format!("unknown strategy `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Strategy{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        f.write_str(match self{
+            Strategy::RoundRobin => "round_robin",
+            Strategy::Random => "random",
+            Strategy::LeastConnections => "least_connections",
+        })
+    }
+}
+
+struct Upstream{
+    addr: String,
+    in_flight: AtomicUsize,                 // Requests currently being forwarded here.
+    failures: AtomicU32,                    // Consecutive connect failures.
+    ejected_until: Mutex<Option<Instant>>,  // Skipped by `pick` until this instant.
+}
+
+/// Spreads requests over several upstreams and ejects the ones that keep
+/// refusing connections.
+///
+/// After `max_failures` consecutive connect failures an upstream is left
+/// out for `cooldown`. Once the cooldown is over it is tried again; a
+/// single further failure ejects it again, a success makes it healthy.
+pub struct LoadBalancer{
+    upstreams: Vec<Upstream>,
+    strategy: Strategy,
+    max_failures: u32,
+    cooldown: Duration,
+    cursor: AtomicUsize,        // Round-robin position, also used to break ties.
+    rng: AtomicU64,             // xorshift64 state for `Strategy::Random`.
+    clock: Arc<dyn Clock>,
+}
+
+impl LoadBalancer{
+    /// Create a balancer over `upstreams` (`host:port` addresses).
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if `upstreams` is empty.
+    pub fn new(upstreams: Vec<String>, strategy: Strategy) -> LoadBalancer{
+        assert!(!upstreams.is_empty());
+
+        LoadBalancer {
+            upstreams: upstreams.into_iter()
+                .map(|addr| Upstream {
+                    addr,
+                    in_flight: AtomicUsize::new(0),
+                    failures: AtomicU32::new(0),
+                    ejected_until: Mutex::new(None),
+                })
+                .collect(),
+            strategy,
+            max_failures: 3,
+            cooldown: Duration::from_secs(10),
+            cursor: AtomicUsize::new(0),
+            rng: AtomicU64::new(RandomState::new().hash_one(0u64) | 1),   // xorshift state must be non-zero.
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Read `upstreams`, `strategy`, `max_failures` and `cooldown_secs`
+    /// from `section` of `config`.
+    pub fn from_config(config: &Config, section: &str) -> Result<LoadBalancer, ConfigError>{
+        let key = |name: &str| format!("{}.{}", section, name);
+
+        let upstreams = config.get_str_array(&key("upstreams"))?
+            .filter(|u| !u.is_empty())
+            .ok_or_else(|| ConfigError::invalid(&key("upstreams"), "at least one upstream is required"))?;
+        let strategy = match config.get_str(&key("strategy"))?{
+            Some(s) => s.parse().map_err(|e: String| ConfigError::invalid(&key("strategy"), &e))?,
+            None => Strategy::RoundRobin,
+        };
+
+        let mut balancer = LoadBalancer::new(upstreams, strategy);
+        if let Some(n) = config.get_int(&key("max_failures"))?{
+            balancer.max_failures = u32::try_from(n).ok()
+                .filter(|&n| n >= 1)
+                .ok_or_else(|| ConfigError::invalid(&key("max_failures"), "must be a positive integer"))?;
+        }
+        if let Some(secs) = config.get_int(&key("cooldown_secs"))?{
+            let secs = u64::try_from(secs)
+                .map_err(|_| ConfigError::invalid(&key("cooldown_secs"), "must not be negative"))?;
+            balancer.cooldown = Duration::from_secs(secs);
+        }
+        Ok(balancer)
+    }
+
+    /// Eject an upstream for `cooldown` after `max_failures` consecutive
+    /// connect failures.
+    pub fn with_ejection(mut self, max_failures: u32, cooldown: Duration) -> LoadBalancer{
+        self.max_failures = max_failures.max(1);
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Use `clock` to time ejections.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> LoadBalancer{
+        self.clock = clock;
+        self
+    }
+
+    pub fn strategy(&self) -> Strategy{
+        self.strategy
+    }
+
+    pub fn upstreams(&self) -> impl Iterator<Item = &str>{
+        self.upstreams.iter().map(|u| u.addr.as_str())
+    }
+
+    /// Whether `addr` is currently being skipped.
+    pub fn is_ejected(&self, addr: &str) -> bool{
+        let now = self.clock.now();
+        self.upstreams.iter().any(|u| u.addr == addr && !self.is_available(u, now))
+    }
+
+    /// Choose a healthy upstream that is not in `exclude` (indexes already
+    /// tried for this request). Returns `None` if there is none.
+    pub fn pick(&self, exclude: &[usize]) -> Option<UpstreamGuard<'_>>{
+        let now = self.clock.now();
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let count = self.upstreams.len();
+
+        // Candidates in round-robin order starting at the cursor.
+        let candidates: Vec<usize> = (0..count)
+            .map(|offset| (start + offset) % count)
+            .filter(|i| !exclude.contains(i) && self.is_available(&self.upstreams[*i], now))
+            .collect();
+
+        let index = match self.strategy{
+            Strategy::RoundRobin => candidates.first().copied(),
+            Strategy::Random => match candidates.len(){
+                0 => None,
+                n => Some(candidates[(self.next_random() % n as u64) as usize]),
+            },
+            Strategy::LeastConnections => candidates.iter()
+                .copied()
+                .min_by_key(|i| self.upstreams[*i].in_flight.load(Ordering::Relaxed)),
+        }?;
+
+        self.upstreams[index].in_flight.fetch_add(1, Ordering::Relaxed);
+        Some(UpstreamGuard { balancer: self, index })
+    }
+
+    fn is_available(&self, upstream: &Upstream, now: Instant) -> bool{
+        match *upstream.ejected_until.lock().unwrap(){
+            Some(until) => now >= until,
+            None => true,
+        }
+    }
+
+    fn next_random(&self) -> u64{
+        let mut x = self.rng.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng.store(x, Ordering::Relaxed);   // A lost update only repeats a number.
+        x
+    }
+}
+
+/// An upstream checked out by `LoadBalancer::pick`, counted as in flight
+/// until dropped.
+pub struct UpstreamGuard<'a>{
+    balancer: &'a LoadBalancer,
+    index: usize,
+}
+
+impl UpstreamGuard<'_>{
+    pub fn addr(&self) -> &str{
+        &self.upstream().addr
+    }
+
+    pub fn index(&self) -> usize{
+        self.index
+    }
+
+    /// Record a successful connection, making the upstream healthy again.
+    pub fn succeeded(&self){
+        let upstream = self.upstream();
+        upstream.failures.store(0, Ordering::Relaxed);
+        *upstream.ejected_until.lock().unwrap() = None;
+    }
+
+    /// Record a failed connection attempt.
+    pub fn failed(&self){
+        let upstream = self.upstream();
+        let failures = upstream.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.balancer.max_failures{
+            let until = self.balancer.clock.now() + self.balancer.cooldown;
+            *upstream.ejected_until.lock().unwrap() = Some(until);
+            log::warn(&format!("Upstream {} ejected for {:?} after {} failures.", upstream.addr, self.balancer.cooldown, failures));
+        }
+    }
+
+    fn upstream(&self) -> &Upstream{
+        &self.balancer.upstreams[self.index]
+    }
+}
+
+impl Drop for UpstreamGuard<'_>{
+    fn drop(&mut self){
+        self.upstream().in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
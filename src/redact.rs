@@ -0,0 +1,126 @@
+// Keeping credentials out of logs and the server's own pages.
+use std::borrow::Cow;
+
+use crate::{
+    http::{Headers, Request},
+    json,
+};
+
+/// What a redacted value is written as.
+pub const REDACTED: &str = "<redacted>";
+
+/// The headers and query parameters whose values are replaced with
+/// `<redacted>` wherever the server writes a request down.
+///
+/// Names are compared without regard to case. Query parameter names are
+/// compared as sent, before percent-decoding, so `api%5Fkey` isn't
+/// `api_key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redactor{
+    pub headers: Vec<String>,       // `Authorization`, `Cookie` and `Set-Cookie` by default.
+    pub query_params: Vec<String>,  // None by default.
+}
+
+impl Default for Redactor{
+    fn default() -> Redactor{
+        Redactor {
+            headers: ["Authorization", "Cookie", "Set-Cookie"].map(String::from).to_vec(),
+            query_params: Vec::new(),
+        }
+    }
+}
+
+impl Redactor{
+    /// One that redacts nothing.
+    pub fn none() -> Redactor{
+        Redactor { headers: Vec::new(), query_params: Vec::new() }
+    }
+
+    pub fn with_header(mut self, name: &str) -> Redactor{
+        self.headers.push(name.to_string());
+        self
+    }
+
+    pub fn with_query_param(mut self, name: &str) -> Redactor{
+        self.query_params.push(name.to_string());
+        self
+    }
+
+    pub fn redacts_header(&self, name: &str) -> bool{
+        self.headers.iter().any(|h| h.eq_ignore_ascii_case(name))
+    }
+
+    pub fn redacts_query_param(&self, name: &str) -> bool{
+        self.query_params.iter().any(|p| p.eq_ignore_ascii_case(name))
+    }
+
+    /// `value` as it may be written for header `name`.
+    pub fn header_value<'a>(&self, name: &str, value: &'a str) -> &'a str{
+        if self.redacts_header(name) { REDACTED } else { value }
+    }
+
+    /// `headers` as `(name, value)` pairs, in order, with values redacted.
+    pub fn headers<'a>(&self, headers: &'a Headers) -> Vec<(&'a str, &'a str)>{
+        headers.iter().map(|(name, value)| (name, self.header_value(name, value))).collect()
+    }
+
+    /// A query string with the values of redacted parameters replaced,
+    /// keeping everything else, separators included, as it was.
+    pub fn query<'a>(&self, query: &'a str) -> Cow<'a, str>{
+        if self.query_params.is_empty(){
+            return Cow::Borrowed(query);
+        }
+        let pairs: Vec<Cow<str>> = query.split('&')
+            .map(|pair| match pair.split_once('='){
+                Some((name, _)) if self.redacts_query_param(name) => Cow::Owned(format!("{}={}", name, REDACTED)),
+                _ => Cow::Borrowed(pair),
+            })
+            .collect();
+        Cow::Owned(pairs.join("&"))
+    }
+
+    /// The request's target as the client sent it, with its query redacted.
+    pub fn target(&self, request: &Request) -> String{
+        let path = request.original_path.as_deref().unwrap_or(&request.path);
+        match &request.query{
+            Some(query) => format!("{}?{}", path, self.query(query)),
+            None => path.to_string(),
+        }
+    }
+
+    /// The request line and headers, `GET /?q=1 HTTP/1.1; Host: a; ...`,
+    /// for a text log.
+    pub fn describe(&self, request: &Request) -> String{
+        let mut line = format!("{} {} {}", request.method, self.target(request), request.version);
+        for (name, value) in self.headers(&request.headers){
+            line.push_str(&format!("; {}: {}", name, value));
+        }
+        line
+    }
+
+    /// The request as a JSON object with `method`, `target`, `version` and
+    /// `headers`; see `headers_json`.
+    pub fn to_json(&self, request: &Request) -> json::Value{
+        json::Value::object()
+            .with("method", request.method.as_str())
+            .with("target", self.target(request))
+            .with("version", request.version.to_string())
+            .with("headers", self.headers_json(&request.headers))
+    }
+
+    /// `headers` as a JSON object, with values redacted; a repeated
+    /// header's values are joined with `, `.
+    pub fn headers_json(&self, headers: &Headers) -> json::Value{
+        let mut object = json::Value::object();
+        let mut seen: Vec<&str> = Vec::new();
+        for (name, _) in headers.iter(){
+            if seen.iter().any(|s| s.eq_ignore_ascii_case(name)){
+                continue;
+            }
+            seen.push(name);
+            let values: Vec<&str> = headers.get_all(name).map(|value| self.header_value(name, value)).collect();
+            object = object.with(name, values.join(", "));
+        }
+        object
+    }
+}
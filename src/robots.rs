@@ -0,0 +1,106 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    http::{self, Request, Response},
+    router::Router,
+    static_files::StaticFileServer,
+};
+
+/// A `robots.txt` policy: groups of `Allow`/`Disallow` paths per user
+/// agent, in the order they were added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RobotsTxt{
+    rules: Vec<RobotsRule>,
+}
+
+/// The paths one user agent (`*` for any) may and may not crawl.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RobotsRule{
+    pub user_agent: String,
+    pub allow: Vec<String>,
+    pub disallow: Vec<String>,
+}
+
+impl RobotsTxt{
+    pub fn builder() -> RobotsTxtBuilder{
+        RobotsTxtBuilder::default()
+    }
+
+    pub fn rules(&self) -> &[RobotsRule]{
+        &self.rules
+    }
+}
+
+/// The file itself. A group with nothing disallowed says `Disallow:`
+/// with no path, which is how robots.txt spells "allow everything".
+impl fmt::Display for RobotsTxt{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        for (i, rule) in self.rules.iter().enumerate(){
+            if i > 0{
+                writeln!(f)?;
+            }
+            writeln!(f, "User-agent: {}", rule.user_agent)?;
+            for path in &rule.allow{
+                writeln!(f, "Allow: {}", path)?;
+            }
+            for path in &rule.disallow{
+                writeln!(f, "Disallow: {}", path)?;
+            }
+            if rule.allow.is_empty() && rule.disallow.is_empty(){
+                writeln!(f, "Disallow:")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RobotsTxtBuilder{
+    rules: Vec<RobotsRule>,
+}
+
+impl RobotsTxtBuilder{
+    /// Keep every crawler out of the whole site.
+    pub fn disallow_all(self) -> RobotsTxtBuilder{
+        self.add_rule("*", &[], &["/"])
+    }
+
+    /// Let every crawler in everywhere.
+    pub fn allow_all(self) -> RobotsTxtBuilder{
+        self.add_rule("*", &[], &[])
+    }
+
+    pub fn add_rule(mut self, user_agent: &str, allow: &[&str], disallow: &[&str]) -> RobotsTxtBuilder{
+        self.rules.push(RobotsRule {
+            user_agent: user_agent.to_string(),
+            allow: allow.iter().map(|path| path.to_string()).collect(),
+            disallow: disallow.iter().map(|path| path.to_string()).collect(),
+        });
+        self
+    }
+
+    pub fn build(self) -> RobotsTxt{
+        RobotsTxt { rules: self.rules }
+    }
+}
+
+/// Serve `GET /robots.txt` from `policy`, unless `static_root` holds a
+/// `robots.txt` of its own, which is sent instead. The file is looked for
+/// on every request, so adding or removing it takes effect at once.
+pub fn register(router: &mut Router, policy: RobotsTxt, static_root: Option<&Path>){
+    let static_root: Option<PathBuf> = static_root.map(Path::to_path_buf);
+    let body = policy.to_string();
+    router.get("/robots.txt", move |request: &Request| {
+        if let Some(root) = &static_root{
+            if root.join("robots.txt").is_file(){
+                return StaticFileServer::new(root).handle(request);
+            }
+        }
+        Response::new(200, http::reason_phrase(200))
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(body.clone())
+    });
+}
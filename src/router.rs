@@ -0,0 +1,692 @@
+use std::{
+    collections::HashMap,
+    mem,
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    http::{self, IntoResponse, Method, Request, Response, TargetForm, Upgrade},
+    log,
+    negotiation,
+    proxy::ReverseProxy,
+    server,
+    trace,
+    websocket::{self, WebSocket},
+    ThreadPool,
+};
+
+mod tree;
+
+use tree::{parse_pattern, Node, Segment};
+
+/// The route a request matched and what its pattern captured, put in the
+/// request's `Extensions` before the route's middleware runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteParams{
+    pub pattern: String,                    // As the route was registered, e.g. `/users/:id`.
+    pub params: HashMap<String, String>,    // The same as `Request::params`.
+}
+
+/// A route handler: turns a request into a response.
+pub type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+/// Code that wraps a handler, running before and/or after it.
+///
+/// A middleware decides whether (and with which request) to call the rest
+/// of the chain through `next`, and may rewrite the response it gets back.
+pub trait Middleware: Send + Sync{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&Request, Next<'_>) -> Response + Send + Sync
+{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        self(request, next)
+    }
+}
+
+/// The remainder of a middleware chain, ending in the route handler.
+#[derive(Clone, Copy)]
+pub struct Next<'a>{
+    middleware: &'a [Arc<dyn Middleware>],
+    handler: &'a (dyn Fn(&Request) -> Response + Send + Sync),
+}
+
+impl<'a> Next<'a>{
+    /// Run the next middleware, or the handler once the chain is exhausted.
+    pub fn run(self, request: &Request) -> Response{
+        match self.middleware.split_first(){
+            Some((first, rest)) => first.handle(request, Next { middleware: rest, handler: self.handler }),
+            None => (self.handler)(request),
+        }
+    }
+}
+
+/// What a `StackMiddleware` decided about a request.
+pub enum MiddlewareResult{
+    Continue(Request),  // Carry on down the stack with this (possibly rewritten) request.
+    Halt(Response),     // Answer now; later middleware and the handler don't run.
+}
+
+/// Middleware that only looks at the request on its way in, for checks
+/// such as authentication or rate limiting that either let a request
+/// through or turn it away.
+pub trait StackMiddleware: Send + Sync{
+    fn call(&self, request: Request) -> MiddlewareResult;
+}
+
+impl<F> StackMiddleware for F
+where
+    F: Fn(Request) -> MiddlewareResult + Send + Sync
+{
+    fn call(&self, request: Request) -> MiddlewareResult{
+        self(request)
+    }
+}
+
+/// `StackMiddleware` run in order, stopping at the first that halts.
+///
+/// The stack as a whole is a `Middleware`, so it goes wherever one does:
+/// requests that make it through every entry continue down the chain
+/// with whatever changes the entries made.
+#[derive(Clone, Default)]
+pub struct MiddlewareStack{
+    middleware: Vec<Arc<dyn StackMiddleware>>,
+}
+
+impl MiddlewareStack{
+    pub fn new() -> MiddlewareStack{
+        MiddlewareStack::default()
+    }
+
+    pub fn with<M>(mut self, middleware: M) -> MiddlewareStack
+    where
+        M: StackMiddleware + 'static
+    {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Run `request` through the stack.
+    pub fn call(&self, mut request: Request) -> MiddlewareResult{
+        for middleware in &self.middleware{
+            request = match middleware.call(request){
+                MiddlewareResult::Continue(request) => request,
+                halt @ MiddlewareResult::Halt(_) => return halt,
+            };
+        }
+        MiddlewareResult::Continue(request)
+    }
+}
+
+impl Middleware for MiddlewareStack{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        match self.call(request.clone()){
+            MiddlewareResult::Continue(request) => next.run(&request),
+            MiddlewareResult::Halt(response) => response,
+        }
+    }
+}
+
+/// Header fields `TraceMiddleware` leaves out of its echo, since a page
+/// able to send TRACE could otherwise read them back (RFC 9110, 9.3.8).
+const UNTRACED_HEADERS: [&str; 3] = ["Authorization", "Proxy-Authorization", "Cookie"];
+
+/// Answers `TRACE` requests with the request as received, for debugging
+/// what reaches the server through proxies.
+///
+/// The response is `200` with `Content-Type: message/http` and the
+/// request line and headers as the body; credentials and cookies are left
+/// out, as are the headers `log::redactor` redacts, and the query
+/// parameters it redacts are redacted. Other methods go on down the
+/// chain. Off unless `server.enable_trace` is set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceMiddleware;
+
+impl TraceMiddleware{
+    pub fn new() -> TraceMiddleware{
+        TraceMiddleware
+    }
+}
+
+impl Middleware for TraceMiddleware{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        if request.method != "TRACE"{
+            return next.run(request);
+        }
+        let redactor = log::redactor();
+        let target = match &request.query{
+            Some(query) => format!("{}?{}", request.path, redactor.query(query)),
+            None => request.path.clone(),
+        };
+        let mut echo = format!("{} {} {}\r\n", request.method, target, request.version);
+        for (name, value) in request.headers.iter(){
+            let untraced = UNTRACED_HEADERS.iter().any(|untraced| untraced.eq_ignore_ascii_case(name));
+            if !untraced && !redactor.redacts_header(name){
+                echo.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        echo.push_str("\r\n");
+        Response::new(200, http::reason_phrase(200))
+            .with_header("Content-Type", "message/http")
+            .with_header("Cache-Control", "no-store")
+            .with_body(echo)
+    }
+}
+
+struct Route{
+    method: Option<Method>,     // `None` for any method.
+    pattern: String,
+    segments: Vec<Segment>,
+    handler: Handler,
+    middleware: Vec<Arc<dyn Middleware>>,   // Inherited from the group or mounted router it came from.
+    timeout: Option<Duration>,              // Overrides the router's default timeout.
+    in_sitemap: bool,                       // Cleared by `Router::no_sitemap`.
+    pool: Option<String>,                   // Named `Server` pool to serve the connection on.
+}
+
+impl Route{
+    // Whether the route serves `method` requests; `GET` routes serve `HEAD` too.
+    fn answers(&self, method: &str) -> bool{
+        match &self.method{
+            None => true,
+            Some(Method::Get) => method == "GET" || method == "HEAD",
+            Some(route_method) => route_method == method,
+        }
+    }
+
+    fn method_name(&self) -> &str{
+        self.method.as_ref().map_or("*", Method::as_str)
+    }
+}
+
+/// Maps a method and path to a handler.
+///
+/// Path patterns are split on `/`; a segment starting with `:` captures a
+/// single segment and one starting with `*` captures the rest of the path,
+/// which may be nothing. Empty segments don't count, so `/a//b/` is
+/// `/a/b`. Captures are made available through `Request::param`.
+///
+/// Routes are kept in a tree of their segments, so finding one takes time
+/// in proportion to the path, not the number of routes. Where patterns
+/// overlap the most specific wins, segment by segment from the left: a
+/// static segment over a `:param`, and a `:param` over a `*wildcard`;
+/// routes with the same pattern are tried in the order they were
+/// registered. Overlapping patterns must give their captures the same
+/// names; registering one that doesn't panics.
+///
+/// A route with a timeout (its own, or the router's default) has its
+/// handler run on the router's timeout pool while the connection's worker
+/// waits. If the handler overruns, the client gets `503` with
+/// `Retry-After` and whatever the handler returns later is discarded.
+/// The handler can't be stopped, so until it finishes it keeps its pool
+/// thread busy. That pool has `DEFAULT_TIMEOUT_POOL_SIZE` threads unless
+/// `timeout_pool` gives it another.
+pub struct Router{
+    routes: Vec<Route>,
+    tree: Node,                             // Indexes into `routes`.
+    middleware: Vec<Arc<dyn Middleware>>,   // Runs for every request this router dispatches.
+    fallback: Handler,                      // Used when no route matches.
+    default_timeout: Option<Duration>,      // For routes without a timeout of their own.
+    timeout_pool: OnceLock<Arc<ThreadPool>>,    // Runs handlers that have a timeout; started on first use.
+}
+
+/// Threads in the pool handlers with a timeout run on, when `timeout_pool`
+/// hasn't set one: how many overrunning handlers can be running at once
+/// before the rest queue behind them.
+pub const DEFAULT_TIMEOUT_POOL_SIZE: usize = 4;
+
+impl Router{
+    pub fn new() -> Router{
+        Router {
+            routes: Vec::new(),
+            tree: Node::default(),
+            middleware: Vec::new(),
+            fallback: Arc::new(|request: &Request| negotiation::status_page(request, 404, "Not Found")),
+            default_timeout: None,
+            timeout_pool: OnceLock::new(),
+        }
+    }
+
+    /// Register a handler for `method` requests matching `pattern`. It may
+    /// return anything that's `IntoResponse`, such as a plain `String`.
+    /// The method is uppercased, and may be an extension method such as
+    /// `PURGE`; `*` matches any method.
+    ///
+    /// # Panics
+    ///
+    /// If `method` isn't `*` or a valid token.
+    pub fn route<F, R>(&mut self, method: &str, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse
+    {
+        let method = match method{
+            "*" => None,
+            _ => Some(method.to_ascii_uppercase().parse().unwrap_or_else(|_| panic!("invalid method {:?}", method))),
+        };
+        self.add(Route {
+            method,
+            pattern: pattern.to_string(),
+            segments: parse_pattern(pattern),
+            handler: Arc::new(move |request: &Request| handler(request).into_response()),
+            middleware: Vec::new(),
+            timeout: None,
+            in_sitemap: true,
+            pool: None,
+        });
+        self
+    }
+
+    fn add(&mut self, route: Route){
+        self.tree.insert(&route.pattern, &route.segments, self.routes.len());
+        self.routes.push(route);
+    }
+
+    // The route a `method` request for `path` goes to and what its
+    // pattern captured, or else the methods of the routes matching the
+    // path, which don't answer `method`.
+    fn find(&self, method: &str, path: &str) -> Result<(&Route, HashMap<String, String>), Vec<&str>>{
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut allowed = Vec::new();
+        let mut found = None;
+        self.tree.find(&parts, &mut |routes, captures| {
+            for route in routes.iter().map(|&i| &self.routes[i]){
+                if route.answers(method){
+                    found = Some((route, captures.to_map()));
+                    return true;
+                }
+                allowed.push(route.method_name());
+            }
+            false
+        });
+        found.ok_or(allowed)
+    }
+
+    /// Register a `GET` handler that gets `timeout` to answer before the
+    /// client is sent `503 Service Unavailable`.
+    pub fn get_with_timeout<F, R>(&mut self, pattern: &str, handler: F, timeout: Duration) -> &mut Router
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse
+    {
+        self.route("GET", pattern, handler);
+        if let Some(route) = self.routes.last_mut(){
+            route.timeout = Some(timeout);
+        }
+        self
+    }
+
+    /// Leave the route registered last out of the sitemap (see
+    /// `sitemap::SitemapGenerator`), for pages that aren't for search
+    /// engines.
+    pub fn no_sitemap(&mut self) -> &mut Router{
+        if let Some(route) = self.routes.last_mut(){
+            route.in_sitemap = false;
+        }
+        self
+    }
+
+    /// Serve connections whose first request is for the route registered
+    /// last on the server pool called `name` (see `Server::with_pool`),
+    /// so slow routes can't starve the rest.
+    pub fn pool(&mut self, name: &str) -> &mut Router{
+        if let Some(route) = self.routes.last_mut(){
+            route.pool = Some(name.to_string());
+        }
+        self
+    }
+
+    /// The pool named for the route a `method` request for `path` would
+    /// be dispatched to, if it has one.
+    pub fn pool_for(&self, method: &str, path: &str) -> Option<&str>{
+        self.find(method, path).ok().and_then(|(route, _)| route.pool.as_deref())
+    }
+
+    /// The route a `method` request for `path` would be dispatched to,
+    /// with what its pattern captured, if any route would take it.
+    pub fn matched(&self, method: &str, path: &str) -> Option<RouteParams>{
+        let (route, params) = self.find(method, path).ok()?;
+        Some(RouteParams { pattern: route.pattern.clone(), params })
+    }
+
+    /// Give every route dispatched by this router that has no timeout of
+    /// its own this one, mounted routes included; `None` turns it off.
+    /// The handlers run on the timeout pool, `DEFAULT_TIMEOUT_POOL_SIZE`
+    /// threads unless `timeout_pool` says otherwise.
+    pub fn default_timeout(&mut self, timeout: Option<Duration>) -> &mut Router{
+        self.default_timeout = timeout;
+        self
+    }
+
+    /// Run handlers that have a timeout on `pool`, instead of a pool of
+    /// `DEFAULT_TIMEOUT_POOL_SIZE` started the first time one is needed.
+    /// Only takes effect before that happens.
+    pub fn timeout_pool(&mut self, pool: Arc<ThreadPool>) -> &mut Router{
+        let _ = self.timeout_pool.set(pool);
+        self
+    }
+
+    pub fn get<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    pub fn post<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    pub fn put<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse
+    {
+        self.route("PUT", pattern, handler)
+    }
+
+    pub fn delete<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse
+    {
+        self.route("DELETE", pattern, handler)
+    }
+
+    /// Register a handler for requests of any method matching `pattern`.
+    pub fn any<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse
+    {
+        self.route("*", pattern, handler)
+    }
+
+    /// Forward every request under `prefix` through `proxy`.
+    ///
+    /// The path is passed upstream unchanged, prefix included.
+    pub fn proxy(&mut self, prefix: &str, proxy: ReverseProxy) -> &mut Router{
+        let pattern = join_paths(prefix, "*");
+        self.any(&pattern, move |request| proxy.handle(request))
+    }
+
+    /// Accept WebSocket connections on `pattern`.
+    ///
+    /// Once the handshake has been answered the connection is handed to
+    /// `handler`, which runs on the pool worker that served the request
+    /// for as long as it keeps the socket open.
+    pub fn websocket<F>(&mut self, pattern: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&mut WebSocket<TcpStream>) + Send + Sync + 'static
+    {
+        let handler = Arc::new(handler);
+        self.get(pattern, move |request| {
+            match websocket::handshake(request){
+                Ok(response) => {
+                    let handler = Arc::clone(&handler);
+                    response.with_upgrade(Upgrade::new(move |stream| handler(&mut WebSocket::new(stream))))
+                },
+                Err(response) => response,
+            }
+        })
+    }
+
+    /// Add middleware that wraps every request dispatched by this router,
+    /// including ones that fall through to the fallback handler.
+    pub fn middleware<M>(&mut self, middleware: M) -> &mut Router
+    where
+        M: Middleware + 'static
+    {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Set the handler used when no route matches.
+    pub fn fallback<F, R>(&mut self, handler: F) -> &mut Router
+    where
+        F: Fn(&Request) -> R + Send + Sync + 'static,
+        R: IntoResponse
+    {
+        self.fallback = Arc::new(move |request: &Request| handler(request).into_response());
+        self
+    }
+
+    /// Register several routes that share middleware.
+    ///
+    /// Routes added inside `f` keep their paths as given; middleware added
+    /// inside `f` only wraps those routes.
+    pub fn group<F>(&mut self, f: F) -> &mut Router
+    where
+        F: FnOnce(&mut Router)
+    {
+        let mut group = Router::new();
+        f(&mut group);
+        self.mount("", group)
+    }
+
+    /// Nest every route of `router` under `prefix`.
+    ///
+    /// `router`'s middleware only wraps its own routes, so it only runs for
+    /// requests under `prefix`. So does its default timeout: it becomes the
+    /// timeout of each of its routes that has none of its own, ahead of
+    /// this router's default. Its fallback handler is not used.
+    pub fn mount(&mut self, prefix: &str, router: Router) -> &mut Router{
+        for mut route in router.routes{
+            route.pattern = join_paths(prefix, &route.pattern);
+            route.segments = parse_pattern(&route.pattern);
+            route.timeout = route.timeout.or(router.default_timeout);
+
+            // The sub-router's own middleware runs before anything the route
+            // picked up from deeper nesting.
+            let mut middleware = router.middleware.clone();
+            middleware.append(&mut route.middleware);
+            route.middleware = middleware;
+
+            self.add(route);
+        }
+        self
+    }
+
+    /// Every method some route answers to, sorted, with `HEAD` wherever
+    /// there's `GET` and always `OPTIONS`.
+    pub fn server_methods(&self) -> Vec<&str>{
+        let mut methods: Vec<&str> = self.routes.iter()
+            .filter_map(|r| r.method.as_ref().map(Method::as_str))
+            .collect();
+        if methods.contains(&"GET"){
+            methods.push("HEAD");
+        }
+        methods.push("OPTIONS");
+        methods.sort_unstable();
+        methods.dedup();
+        methods
+    }
+
+    /// Lists `(method, pattern)` for every registered route.
+    pub fn routes(&self) -> Vec<(&str, &str)>{
+        self.routes.iter().map(|r| (r.method_name(), r.pattern.as_str())).collect()
+    }
+
+    /// The patterns of `GET` routes that match exactly one path (no
+    /// captures) and weren't left out with `no_sitemap`.
+    pub fn sitemap_paths(&self) -> Vec<&str>{
+        self.routes.iter()
+            .filter(|r| r.method == Some(Method::Get) && r.in_sitemap)
+            .filter(|r| r.segments.iter().all(|segment| matches!(segment, Segment::Static(_))))
+            .map(|r| r.pattern.as_str())
+            .collect()
+    }
+
+    /// Find the matching route and run it through the middleware chain.
+    ///
+    /// `HEAD` requests are served by `GET` routes. A path that matches only
+    /// under other methods gets `405 Method Not Allowed` with an `Allow`
+    /// header. `OPTIONS *` gets `204 No Content`, with every method some
+    /// route answers to in `Allow`.
+    pub fn dispatch(&self, request: &Request) -> Response{
+        let routing = Instant::now();
+        if request.target_form == TargetForm::Asterisk{
+            let allow = self.server_methods().join(", ");
+            let handler = move |_: &Request| Response::new(204, http::reason_phrase(204)).with_header("Allow", &allow);
+            return run_traced(routing, Next { middleware: &self.middleware, handler: &handler }, request);
+        }
+
+        let mut allowed = match self.find(request.method.as_str(), &request.path){
+            Ok((route, params)) => {
+                // Only a route's parameters need a copy of the request to go
+                // in, and with it the body, which could be large.
+                let mut routed = None;
+                if params != request.params{
+                    let mut copy = request.clone();
+                    copy.params = params;
+                    routed = Some(copy);
+                }
+                let request = routed.as_ref().unwrap_or(request);
+                request.extensions().insert(RouteParams { pattern: route.pattern.clone(), params: request.params.clone() });
+
+                let chain: Vec<Arc<dyn Middleware>> = self.middleware.iter()
+                    .chain(route.middleware.iter())
+                    .cloned()
+                    .collect();
+                if let Some(timeout) = route.timeout.or(self.default_timeout){
+                    let pool = self.timeout_pool.get_or_init(|| Arc::new(ThreadPool::new(DEFAULT_TIMEOUT_POOL_SIZE)));
+                    let handler = |request: &Request| run_with_timeout(pool, &route.handler, request, timeout);
+                    return run_traced(routing, Next { middleware: &chain, handler: &handler }, request);
+                }
+                let next = Next { middleware: &chain, handler: route.handler.as_ref() };
+                return run_traced(routing, next, request);
+            },
+            Err(allowed) => allowed,
+        };
+
+        if !allowed.is_empty(){
+            allowed.sort_unstable();
+            allowed.dedup();
+            let allow = allowed.join(", ");
+            let handler = move |request: &Request| {
+                negotiation::status_page(request, 405, "Method Not Allowed").with_header("Allow", &allow)
+            };
+            return run_traced(routing, Next { middleware: &self.middleware, handler: &handler }, request);
+        }
+
+        run_traced(routing, Next { middleware: &self.middleware, handler: self.fallback.as_ref() }, request)
+    }
+}
+
+// Run `next`, recording the time since `routing` began as the request's
+// `route` span and the chain itself as its `handler` span.
+fn run_traced(routing: Instant, next: Next<'_>, request: &Request) -> Response{
+    trace::record("route", routing, routing.elapsed());
+    trace::span("handler", || next.run(request))
+}
+
+// Run `handler` on `pool`, in the request's context, waiting at most
+// `timeout` for its response.
+fn run_with_timeout(pool: &ThreadPool, handler: &Handler, request: &Request, timeout: Duration) -> Response{
+    let started = Instant::now();
+    let abandoned = Arc::new(AtomicBool::new(false));
+    let context = server::current_request_context();
+    let described = describe(request, context.as_ref().map(|context| context.request_id));
+    let result = {
+        let handler = Arc::clone(handler);
+        let request = request.clone();
+        let abandoned = Arc::clone(&abandoned);
+        let described = described.clone();
+        pool.spawn_with_channel(move || {
+            let run = || {
+                let response = handler(&request);
+                if abandoned.load(Ordering::SeqCst){
+                    log::warn(&format!("Discarding the response to {} that came {:?} after the request, past its timeout",
+                        described, started.elapsed()));
+                }
+                response
+            };
+            match context{
+                Some(context) => server::with_request_context(context, run),
+                None => run(),
+            }
+        })
+    };
+
+    match result.recv_timeout(timeout){
+        Ok(response) => response,
+        Err(RecvTimeoutError::Timeout) => {
+            abandoned.store(true, Ordering::SeqCst);
+            log::warn(&format!("{} timed out after {:?}", described, timeout));
+            let retry_after = timeout.as_secs().max(1).to_string();
+            negotiation::status_page(request, 503, "Service Unavailable").with_header("Retry-After", &retry_after)
+        },
+        Err(RecvTimeoutError::Disconnected) => {
+            log::error(&format!("The handler for {} panicked", described));
+            negotiation::status_page(request, 500, "Internal Server Error")
+        },
+    }
+}
+
+// `GET /path (request 12)`, for messages about a request.
+fn describe(request: &Request, request_id: Option<u64>) -> String{
+    match request_id{
+        Some(id) => format!("{} {} (request {})", request.method, request.path, id),
+        None => format!("{} {}", request.method, request.path),
+    }
+}
+
+impl Default for Router{
+    fn default() -> Router{
+        Router::new()
+    }
+}
+
+/// Routes registered from anywhere in the program with `register_route!`,
+/// for modules that want to declare their own routes instead of having
+/// `main` know about them.
+///
+/// Rust runs nothing before `main`, so registration still happens when a
+/// module's code runs; the usual shape is a `register()` function per
+/// module, called once at startup. The server then takes the routes
+/// with `take_global` when it builds its router.
+pub fn global() -> &'static Mutex<Router>{
+    static GLOBAL: OnceLock<Mutex<Router>> = OnceLock::new();
+    GLOBAL.get_or_init(|| Mutex::new(Router::new()))
+}
+
+/// Take every route registered globally so far, leaving the registry
+/// empty. Mount the result (`router.mount("", router::take_global())`)
+/// to serve them.
+pub fn take_global() -> Router{
+    mem::take(&mut *global().lock().unwrap())
+}
+
+/// Register a route with the global router: `register_route!(GET
+/// "/api/users", users_handler)`. Any method name works, as with
+/// `Router::route`.
+#[macro_export]
+macro_rules! register_route{
+    ($method:ident $pattern:expr, $handler:expr) => {
+        $crate::router::global().lock().unwrap().route(stringify!($method), $pattern, $handler);
+    };
+}
+
+fn join_paths(prefix: &str, path: &str) -> String{
+    let prefix = prefix.trim_matches('/');
+    let path = path.trim_start_matches('/');
+    match (prefix.is_empty(), path.is_empty()){
+        (true, _) => format!("/{}", path),
+        (false, true) => format!("/{}", prefix),
+        (false, false) => format!("/{}/{}", prefix, path),
+    }
+}
@@ -0,0 +1,159 @@
+// The router's routes as a prefix tree over path segments, so a lookup
+// walks the path once instead of trying every route in turn.
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Segment{
+    Static(String),     // Must match the path segment exactly.
+    Param(String),      // `:name` captures one segment.
+    Wildcard(String),   // `*name` captures the rest of the path.
+}
+
+/// Split a pattern into its segments. Empty segments are dropped, so
+/// `/a//b/` is `/a/b`, and anything after a wildcard is ignored.
+pub(super) fn parse_pattern(pattern: &str) -> Vec<Segment>{
+    let mut segments = Vec::new();
+    for s in pattern.split('/').filter(|s| !s.is_empty()){
+        if let Some(name) = s.strip_prefix(':'){
+            segments.push(Segment::Param(name.to_string()));
+        } else if let Some(name) = s.strip_prefix('*'){
+            segments.push(Segment::Wildcard(name.to_string()));
+            break;
+        } else {
+            segments.push(Segment::Static(s.to_string()));
+        }
+    }
+    segments
+}
+
+// A capture name, and the pattern that gave it, for conflict messages.
+struct Capture<T>{
+    name: String,
+    pattern: String,
+    then: T,
+}
+
+/// One position in the tree: every route whose pattern has the same
+/// segments up to here passes through it.
+#[derive(Default)]
+pub(super) struct Node{
+    statics: HashMap<String, Node>,
+    param: Option<Box<Capture<Node>>>,      // Every `:name` here has the same name.
+    wildcard: Option<Capture<Vec<usize>>>,  // Routes ending in `*name` here.
+    routes: Vec<usize>,                     // Routes ending here, by index, in registration order.
+}
+
+impl Node{
+    /// Add route `index`, registered as `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` captures under a different name from a route already
+    /// registered where the two overlap, since the names a handler gets
+    /// would then depend on which route matched.
+    pub(super) fn insert(&mut self, pattern: &str, segments: &[Segment], index: usize){
+        let (first, rest) = match segments.split_first(){
+            Some(split) => split,
+            None => return self.routes.push(index),
+        };
+        match first{
+            Segment::Static(s) => self.statics.entry(s.clone()).or_default().insert(pattern, rest, index),
+            Segment::Param(name) => {
+                let param = self.param.get_or_insert_with(|| {
+                    Box::new(Capture { name: name.clone(), pattern: pattern.to_string(), then: Node::default() })
+                });
+                check_name(&param.name, &param.pattern, name, pattern);
+                param.then.insert(pattern, rest, index);
+            },
+            Segment::Wildcard(name) => {
+                let wildcard = self.wildcard.get_or_insert_with(|| {
+                    Capture { name: name.clone(), pattern: pattern.to_string(), then: Vec::new() }
+                });
+                check_name(&wildcard.name, &wildcard.pattern, name, pattern);
+                wildcard.then.push(index);
+            },
+        }
+    }
+
+    /// Offer `visit` the routes matching `parts`, the segments of a path,
+    /// a node's worth at a time: a static segment before a parameter, and
+    /// a parameter before a wildcard, wherever they overlap. Stops once
+    /// `visit` returns true, and returns whether it did.
+    pub(super) fn find<'a, F>(&'a self, parts: &[&'a str], visit: &mut F) -> bool
+    where
+        F: FnMut(&[usize], &Captures<'a, '_>) -> bool
+    {
+        let mut captures = Vec::new();
+        self.search(parts, 0, &mut captures, visit)
+    }
+
+    fn search<'a, F>(&'a self, parts: &[&'a str], at: usize, captures: &mut Vec<Captured<'a>>, visit: &mut F) -> bool
+    where
+        F: FnMut(&[usize], &Captures<'a, '_>) -> bool
+    {
+        match parts.get(at){
+            None => {
+                if !self.routes.is_empty() && visit(&self.routes, &Captures { parts, captures }){
+                    return true;
+                }
+            },
+            Some(part) => {
+                if self.statics.get(*part).is_some_and(|next| next.search(parts, at + 1, captures, visit)){
+                    return true;
+                }
+                if let Some(param) = &self.param{
+                    captures.push(Captured { name: &param.name, at, rest: false });
+                    if param.then.search(parts, at + 1, captures, visit){
+                        return true;
+                    }
+                    captures.pop();
+                }
+            },
+        }
+        // A wildcard takes whatever is left, nothing included.
+        if let Some(wildcard) = &self.wildcard{
+            captures.push(Captured { name: &wildcard.name, at, rest: true });
+            if visit(&wildcard.then, &Captures { parts, captures }){
+                return true;
+            }
+            captures.pop();
+        }
+        false
+    }
+}
+
+fn check_name(existing: &str, existing_pattern: &str, name: &str, pattern: &str){
+    if existing != name{
+        panic!(
+            "route {:?} captures {:?} where {:?} captures {:?}; overlapping routes must use the same names",
+            pattern, name, existing_pattern, existing,
+        );
+    }
+}
+
+struct Captured<'a>{
+    name: &'a str,
+    at: usize,      // Index of the segment captured, or of the first one for a wildcard.
+    rest: bool,     // A wildcard: everything from `at` on.
+}
+
+/// What the patterns of the routes being offered captured from the path.
+pub(super) struct Captures<'a, 'c>{
+    parts: &'c [&'a str],
+    captures: &'c [Captured<'a>],
+}
+
+impl Captures<'_, '_>{
+    pub(super) fn to_map(&self) -> HashMap<String, String>{
+        self.captures.iter()
+            .filter(|captured| !(captured.rest && captured.name.is_empty()))
+            .map(|captured| {
+                let value = match captured.rest{
+                    true => self.parts[captured.at..].join("/"),
+                    false => self.parts[captured.at].to_string(),
+                };
+                (captured.name.to_string(), value)
+            })
+            .collect()
+    }
+}
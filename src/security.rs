@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use crate::{
+    config::{Config, ConfigError},
+    http::{Request, Response},
+    router::{Middleware, Next},
+};
+
+/// Settings for the `Strict-Transport-Security` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HstsConfig{
+    pub max_age: Duration,          // How long browsers should refuse plain HTTP for this host.
+    pub include_subdomains: bool,
+    pub preload: bool,              // Consent to inclusion in browsers' built-in HSTS lists.
+}
+
+impl HstsConfig{
+    /// Read `max_age_secs`, `include_subdomains` and `preload` from
+    /// `section` of `config`.
+    pub fn from_config(config: &Config, section: &str) -> Result<HstsConfig, ConfigError>{
+        let key = |name: &str| format!("{}.{}", section, name);
+        let mut hsts = HstsConfig::default();
+
+        if let Some(secs) = config.get_int(&key("max_age_secs"))?{
+            let secs = u64::try_from(secs)
+                .map_err(|_| ConfigError::invalid(&key("max_age_secs"), "must not be negative"))?;
+            hsts.max_age = Duration::from_secs(secs);
+        }
+        if let Some(include) = config.get_bool(&key("include_subdomains"))?{
+            hsts.include_subdomains = include;
+        }
+        if let Some(preload) = config.get_bool(&key("preload"))?{
+            hsts.preload = preload;
+        }
+        // Preload lists reject hosts that don't cover their subdomains.
+        if hsts.preload && !hsts.include_subdomains{
+            return Err(ConfigError::invalid(&key("preload"), "requires include_subdomains"));
+        }
+        Ok(hsts)
+    }
+
+    /// The header value, e.g. `max-age=31536000; includeSubDomains`.
+    pub fn header_value(&self) -> String{
+        let mut value = format!("max-age={}", self.max_age.as_secs());
+        if self.include_subdomains{
+            value.push_str("; includeSubDomains");
+        }
+        if self.preload{
+            value.push_str("; preload");
+        }
+        value
+    }
+}
+
+impl Default for HstsConfig{
+    fn default() -> HstsConfig{
+        HstsConfig {
+            max_age: Duration::from_secs(31_536_000),     // One year.
+            include_subdomains: true,
+            preload: false,
+        }
+    }
+}
+
+/// Adds security-related headers to every response.
+///
+/// `X-Content-Type-Options: nosniff` is always added. With HSTS
+/// configured, `Strict-Transport-Security` is added too, but only to
+/// requests that arrived over TLS: on plain HTTP the header is
+/// meaningless to browsers and could be injected by anyone on the path.
+/// Headers the handler already set are left alone.
+pub struct SecurityHeadersMiddleware{
+    hsts: Option<HstsConfig>,
+}
+
+impl SecurityHeadersMiddleware{
+    /// HSTS is on with the default settings when the `tls` feature is
+    /// enabled, and off otherwise.
+    pub fn new() -> SecurityHeadersMiddleware{
+        SecurityHeadersMiddleware {
+            hsts: if cfg!(feature = "tls") { Some(HstsConfig::default()) } else { None },
+        }
+    }
+
+    pub fn with_hsts(mut self, hsts: HstsConfig) -> SecurityHeadersMiddleware{
+        self.hsts = Some(hsts);
+        self
+    }
+
+    pub fn without_hsts(mut self) -> SecurityHeadersMiddleware{
+        self.hsts = None;
+        self
+    }
+
+    pub fn hsts(&self) -> Option<&HstsConfig>{
+        self.hsts.as_ref()
+    }
+}
+
+impl Default for SecurityHeadersMiddleware{
+    fn default() -> SecurityHeadersMiddleware{
+        SecurityHeadersMiddleware::new()
+    }
+}
+
+impl Middleware for SecurityHeadersMiddleware{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        let mut response = next.run(request);
+        if !response.headers.contains("X-Content-Type-Options"){
+            response.headers.set("X-Content-Type-Options", "nosniff");
+        }
+        if let Some(hsts) = self.hsts.as_ref().filter(|_| request.secure){
+            if !response.headers.contains("Strict-Transport-Security"){
+                response.headers.set("Strict-Transport-Security", &hsts.header_value());
+            }
+        }
+        response
+    }
+}
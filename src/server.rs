@@ -0,0 +1,631 @@
+mod accept;
+mod connection;
+mod context;
+mod handover;
+mod handle;
+mod idle;
+mod run;
+mod shutdown;
+
+pub use accept::{AcceptLoop, ConnectionRegistry, ConnectionState, TrackedConnection};
+pub use connection::{is_client_abort, Connection, Delivery, ReadTimeout};
+pub use context::{current_request_context, with_request_context, ConnectionInfo, RequestContext, RequestId};
+pub use handle::{ConnectionHandler, ExecutionMode, PoolSelector, Server};
+pub use handover::{inherited_fd_arg, spawn_successor, INHERITED_FD_FLAG};
+pub use idle::{IdleConnection, IdleWatcher};
+pub use run::{run_server, run_server_with, App, RunOptions};
+pub use shutdown::ShutdownToken;
+pub(crate) use context::clear_request_context;
+
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    access_log::AccessLogConfig,
+    config::{Config, ConfigError},
+    favicon,
+    http::{self, BodyError, HttpVersion, Limits, Method, ParseError, Request, RequestBodyReader, Response},
+    log::{self, LogFormat},
+    negotiation,
+    net::SocketOptions,
+    redact::Redactor,
+    robots::RobotsTxt,
+    static_files::StaticSource,
+    trace,
+    uri,
+    vhost,
+};
+
+/// Settings for the HTTP server itself, read from the `[server]` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig{
+    pub addr: String,                   // Where to listen, e.g. `127.0.0.1:7878`.
+    pub workers: usize,                 // Threads serving connections.
+    pub job_workers: usize,             // Threads for jobs the routes schedule on `App::pool`; one per CPU by default.
+    pub limits: Limits,                 // Applied to every request head.
+    pub keep_alive_timeout: Duration,   // How long an idle connection may wait for its next request.
+    pub keep_alive_max_requests: Option<usize>, // Requests a connection may carry after its first; `None` means no limit.
+    pub evict_idle_when_busy: bool,     // With every worker busy, close the longest-idle keep-alive connection for a new one.
+    pub drain_timeout: Duration,        // How long shutdown waits for connections before cutting them off.
+    pub socket: SocketOptions,          // Applied when binding and to every accepted connection.
+    pub slow_request_warn: Option<Duration>,    // Warn about handlers slower than this; `None` turns it off.
+    pub large_response_warn: Option<usize>,     // Warn about response bodies bigger than this many bytes.
+    pub version_endpoint: bool,         // Serve the built-in `GET /version`.
+    pub route_metrics: bool,            // Count requests per route and serve them at `GET /metrics` and `GET /admin/routes`.
+    pub allowed_hosts: Vec<String>,     // `Host` values to serve (see `vhost::host_allowed`); empty allows any.
+    pub host_rejection_status: u16,     // 421 or 400, for a `Host` not in `allowed_hosts`.
+    pub route_timeout: Option<Duration>,    // Default for `Router::default_timeout`; `None` lets handlers take as long as they like.
+    pub robots_policy: Option<RobotsTxt>,   // Generates `GET /robots.txt` when set; see `robots::register`.
+    pub access_log: Option<AccessLogConfig>,    // Log each request to this file, rotating it; read at startup only.
+    pub sitemap_base_url: Option<String>,   // Serves `GET /sitemap.xml` with URLs under this, when set.
+    pub reexec_restart: bool,           // On `SIGUSR2`, hand the listener to a fresh copy of the binary and drain (unix).
+    pub log_format: LogFormat,          // For the access log and the server's own messages.
+    pub log_debug: bool,                // Write debug messages too, such as clients hanging up mid-response. Read at startup only.
+    pub trace_requests: bool,           // Time each request's spans; see `trace`. Read at startup only.
+    pub favicon: Option<favicon::Fallback>, // Serves `GET /favicon.ico` when set; see `favicon::register`.
+    pub log_favicon: bool,              // Whether favicon requests are logged, to the access log and otherwise.
+    pub enable_trace: bool,             // Answer `TRACE` with `router::TraceMiddleware`; off (`501`), as it's for debugging.
+    pub connect_tunnel: bool,           // Tunnel `CONNECT` to port 443 with `proxy::ConnectHandler`, as a forward proxy; off, it gets `405`.
+    pub static_source: StaticSource,    // Where `/static/` files come from: disk, the binary, or the binary then disk.
+    pub download_extensions: Vec<String>,   // `/static/` files sent as downloads, by extension.
+    pub idempotency_ttl: Option<Duration>,  // Replay responses to retried `Idempotency-Key` requests for this long; `None` turns it off.
+    pub lowercase_paths: bool,          // Lowercase request paths when normalizing them, for case-insensitive routing.
+    pub redact: Redactor,               // Header and query parameter values kept out of logs and `TRACE` echoes. Read at startup only.
+}
+
+impl ServerConfig{
+    /// Read the `[server]` section of `config`: `addr`, `workers`,
+    /// `job_workers`, `max_request_line`, `max_header_line`, `max_headers`,
+    /// `max_header_bytes`, `max_body_bytes`, `keep_alive_timeout_secs`,
+    /// `keep_alive_max_requests`, `evict_idle_when_busy`,
+    /// `drain_timeout_secs`, `reuse_address`, `reuse_port`, `backlog`,
+    /// `nodelay`, `socket_activation`, `tcp_keepalive_secs`,
+    /// `tcp_keepalive_interval_secs`, `slow_request_warn_ms`,
+    /// `large_response_warn_bytes`, `version_endpoint`, `route_metrics`,
+    /// `allowed_hosts`, `host_rejection_status`, `route_timeout_ms`, `robots`
+    /// (`"allow_all"` or `"disallow_all"`), `sitemap_base_url`,
+    /// `reexec_restart`, `access_log` (a file path),
+    /// `access_log_max_bytes`, `access_log_max_files`,
+    /// `access_log_recent_lines`, `log_format`
+    /// (`"text"` or `"json"`), `log_debug`, `trace_requests`, `favicon` (`"embedded"`,
+    /// `"no_content"` or `"off"`), `log_favicon`, `enable_trace`,
+    /// `connect_tunnel`, `static_source` (`"disk"`, `"embedded"` or
+    /// `"embedded_fallback"`), `download_extensions`,
+    /// `idempotency_ttl_secs`, `lowercase_paths`, `lenient_line_endings`
+    /// (bare LF line endings), `redact_headers` and
+    /// `redact_query_params`, the last two replacing the defaults. A
+    /// warning threshold, route timeout or idempotency TTL of 0 turns it
+    /// off.
+    pub fn from_config(config: &Config) -> Result<ServerConfig, ConfigError>{
+        let mut server = ServerConfig::default();
+        if let Some(addr) = config.get_str("server.addr")?{
+            server.addr = addr.to_string();
+        }
+        let limits = &mut server.limits;
+        for (name, field) in [
+            ("workers", &mut server.workers),
+            ("job_workers", &mut server.job_workers),
+            ("max_request_line", &mut limits.max_request_line),
+            ("max_header_line", &mut limits.max_header_line),
+            ("max_headers", &mut limits.max_headers),
+            ("max_header_bytes", &mut limits.max_header_bytes),
+            ("max_body_bytes", &mut limits.max_body_bytes),
+        ]{
+            let key = format!("server.{}", name);
+            if let Some(n) = config.get_int(&key)?{
+                *field = usize::try_from(n)
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| ConfigError::invalid(&key, "must be positive"))?;
+            }
+        }
+        if let Some(timeout) = positive_secs(config, "server.keep_alive_timeout_secs")?{
+            server.keep_alive_timeout = timeout;
+        }
+        if let Some(n) = threshold(config, "server.keep_alive_max_requests")?{
+            server.keep_alive_max_requests = Some(n as usize);
+        }
+        if let Some(enabled) = config.get_bool("server.evict_idle_when_busy")?{
+            server.evict_idle_when_busy = enabled;
+        }
+        if let Some(timeout) = positive_secs(config, "server.drain_timeout_secs")?{
+            server.drain_timeout = timeout;
+        }
+
+        let socket = &mut server.socket;
+        if let Some(reuse) = config.get_bool("server.reuse_address")?{
+            socket.reuse_address = reuse;
+        }
+        if let Some(reuse) = config.get_bool("server.reuse_port")?{
+            socket.reuse_port = reuse;
+        }
+        if let Some(backlog) = config.get_int("server.backlog")?{
+            socket.backlog = u32::try_from(backlog)
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| ConfigError::invalid("server.backlog", "must be positive"))?;
+        }
+        if let Some(nodelay) = config.get_bool("server.nodelay")?{
+            socket.nodelay = nodelay;
+        }
+        if let Some(activation) = config.get_bool("server.socket_activation")?{
+            socket.socket_activation = activation;
+        }
+        socket.keepalive = positive_secs(config, "server.tcp_keepalive_secs")?;
+        socket.keepalive_interval = positive_secs(config, "server.tcp_keepalive_interval_secs")?;
+
+        if let Some(ms) = threshold(config, "server.slow_request_warn_ms")?{
+            server.slow_request_warn = (ms > 0).then(|| Duration::from_millis(ms));
+        }
+        if let Some(bytes) = threshold(config, "server.large_response_warn_bytes")?{
+            server.large_response_warn = (bytes > 0).then_some(bytes as usize);
+        }
+        if let Some(enabled) = config.get_bool("server.version_endpoint")?{
+            server.version_endpoint = enabled;
+        }
+        if let Some(enabled) = config.get_bool("server.route_metrics")?{
+            server.route_metrics = enabled;
+        }
+        if let Some(hosts) = config.get_str_array("server.allowed_hosts")?{
+            server.allowed_hosts = hosts;
+        }
+        if let Some(status) = config.get_int("server.host_rejection_status")?{
+            server.host_rejection_status = match status{
+                400 | 421 => status as u16,
+                _ => return Err(ConfigError::invalid("server.host_rejection_status", "must be 400 or 421")),
+            };
+        }
+        if let Some(ms) = threshold(config, "server.route_timeout_ms")?{
+            server.route_timeout = (ms > 0).then(|| Duration::from_millis(ms));
+        }
+        if let Some(policy) = config.get_str("server.robots")?{
+            server.robots_policy = Some(match policy{
+                "allow_all" => RobotsTxt::builder().allow_all().build(),
+                "disallow_all" => RobotsTxt::builder().disallow_all().build(),
+                _ => return Err(ConfigError::invalid("server.robots", "must be \"allow_all\" or \"disallow_all\"")),
+            });
+        }
+        if let Some(path) = config.get_str("server.access_log")?{
+            let mut log = AccessLogConfig::new(path);
+            if let Some(bytes) = config.get_int("server.access_log_max_bytes")?{
+                log.max_bytes = u64::try_from(bytes)
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| ConfigError::invalid("server.access_log_max_bytes", "must be positive"))?;
+            }
+            if let Some(files) = threshold(config, "server.access_log_max_files")?{
+                log.max_files = files as usize;
+            }
+            if let Some(lines) = threshold(config, "server.access_log_recent_lines")?{
+                log.recent_lines = lines as usize;
+            }
+            server.access_log = Some(log);
+        }
+        if let Some(base_url) = config.get_str("server.sitemap_base_url")?{
+            server.sitemap_base_url = Some(base_url.to_string());
+        }
+        if let Some(enabled) = config.get_bool("server.reexec_restart")?{
+            server.reexec_restart = enabled;
+        }
+        if let Some(format) = config.get_str("server.log_format")?{
+            server.log_format = LogFormat::parse(format)
+                .ok_or_else(|| ConfigError::invalid("server.log_format", "must be \"text\" or \"json\""))?;
+        }
+        if let Some(enabled) = config.get_bool("server.log_debug")?{
+            server.log_debug = enabled;
+        }
+        if let Some(enabled) = config.get_bool("server.trace_requests")?{
+            server.trace_requests = enabled;
+        }
+        if let Some(fallback) = config.get_str("server.favicon")?{
+            server.favicon = match fallback{
+                "off" => None,
+                _ => Some(favicon::Fallback::parse(fallback).ok_or_else(|| {
+                    ConfigError::invalid("server.favicon", "must be \"embedded\", \"no_content\" or \"off\"")
+                })?),
+            };
+        }
+        if let Some(enabled) = config.get_bool("server.log_favicon")?{
+            server.log_favicon = enabled;
+        }
+        if let Some(enabled) = config.get_bool("server.enable_trace")?{
+            server.enable_trace = enabled;
+        }
+        if let Some(enabled) = config.get_bool("server.connect_tunnel")?{
+            server.connect_tunnel = enabled;
+        }
+        if let Some(source) = config.get_str("server.static_source")?{
+            server.static_source = StaticSource::parse(source).ok_or_else(|| {
+                ConfigError::invalid("server.static_source", "must be \"disk\", \"embedded\" or \"embedded_fallback\"")
+            })?;
+        }
+        if let Some(extensions) = config.get_str_array("server.download_extensions")?{
+            server.download_extensions = extensions;
+        }
+        if let Some(secs) = threshold(config, "server.idempotency_ttl_secs")?{
+            server.idempotency_ttl = (secs > 0).then(|| Duration::from_secs(secs));
+        }
+        if let Some(enabled) = config.get_bool("server.lowercase_paths")?{
+            server.lowercase_paths = enabled;
+        }
+        if let Some(enabled) = config.get_bool("server.lenient_line_endings")?{
+            server.limits.lenient_line_endings = enabled;
+        }
+        if let Some(headers) = config.get_str_array("server.redact_headers")?{
+            server.redact.headers = headers;
+        }
+        if let Some(params) = config.get_str_array("server.redact_query_params")?{
+            server.redact.query_params = params;
+        }
+        if let Some(log) = &mut server.access_log{
+            log.format = server.log_format;
+            log.log_favicon = server.log_favicon;
+        }
+        Ok(server)
+    }
+
+    /// Read the config file at `path`; see `from_config`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ServerConfig, ConfigError>{
+        ServerConfig::from_config(&Config::load(path)?)
+    }
+
+    /// Whether a connection that has now carried `served` requests, this
+    /// one included, must close after answering it. As with Apache's
+    /// `MaxKeepAliveRequests`, the first request doesn't count against
+    /// `keep_alive_max_requests`: a limit of 2 closes on the third.
+    pub fn keep_alive_exhausted(&self, served: usize) -> bool{
+        self.keep_alive_max_requests.is_some_and(|max| served > max)
+    }
+}
+
+impl Default for ServerConfig{
+    fn default() -> ServerConfig{
+        ServerConfig {
+            addr: "127.0.0.1:7878".to_string(),
+            workers: 4,
+            job_workers: thread::available_parallelism().map_or(4, |n| n.get()),
+            limits: Limits::default(),
+            keep_alive_timeout: Duration::from_secs(5),
+            keep_alive_max_requests: None,
+            evict_idle_when_busy: false,
+            drain_timeout: Duration::from_secs(30),
+            socket: SocketOptions::default(),
+            slow_request_warn: Some(Duration::from_secs(1)),
+            large_response_warn: Some(8 * 1024 * 1024),
+            version_endpoint: true,
+            route_metrics: true,
+            allowed_hosts: vec!["localhost".to_string(), "127.0.0.1".to_string()],
+            host_rejection_status: 421,
+            route_timeout: None,
+            robots_policy: None,
+            access_log: None,
+            sitemap_base_url: None,
+            reexec_restart: false,
+            log_format: LogFormat::Text,
+            log_debug: false,
+            trace_requests: false,
+            favicon: Some(favicon::Fallback::Embedded),
+            log_favicon: true,
+            enable_trace: false,
+            connect_tunnel: false,
+            static_source: StaticSource::Disk,
+            download_extensions: Vec::new(),
+            idempotency_ttl: None,
+            lowercase_paths: false,
+            redact: Redactor::default(),
+        }
+    }
+}
+
+fn positive_secs(config: &Config, key: &str) -> Result<Option<Duration>, ConfigError>{
+    match config.get_int(key)?{
+        Some(secs) if secs > 0 => Ok(Some(Duration::from_secs(secs as u64))),
+        Some(_) => Err(ConfigError::invalid(key, "must be positive")),
+        None => Ok(None),
+    }
+}
+
+fn threshold(config: &Config, key: &str) -> Result<Option<u64>, ConfigError>{
+    config.get_int(key)?
+        .map(|n| u64::try_from(n).map_err(|_| ConfigError::invalid(key, "must not be negative")))
+        .transpose()
+}
+
+/// Read from `stream` into `buffer` until it holds a whole request line,
+/// and return its method and path (the target without any query).
+///
+/// Only the request line is looked at; whatever else the last read
+/// brought in stays in `buffer` for `read_request`. `None` means the
+/// line is longer than `max_request_line`, malformed, or never finished
+/// because the client went quiet or away: the caller should carry on as
+/// usual and let `read_request` answer that.
+pub fn read_request_line<S: Read>(stream: &mut S, buffer: &mut Vec<u8>, max_request_line: usize) -> Option<(Method, String)>{
+    let mut chunk = [0; 1024];
+    let line_end = loop{
+        if let Some(end) = buffer.windows(2).position(|w| w == b"\r\n"){
+            break end;
+        }
+        if buffer.len() > max_request_line{
+            return None;
+        }
+        match stream.read(&mut chunk){
+            Ok(0) | Err(_) => return None,
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+        }
+    };
+
+    let line = std::str::from_utf8(&buffer[..line_end]).ok()?;
+    let mut parts = line.split(' ');
+    let method: Method = parts.next()?.parse().ok()?;
+    let target = parts.next()?;
+    let request = Request::new(method.as_str(), target);
+    Some((method, request.path))
+}
+
+/// One answered request, for the warning thresholds and the access log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Served<'a>{
+    pub id: u64,                    // From `next_request_id`.
+    pub method: &'a Method,
+    pub path: &'a str,
+    pub duration: Duration,         // Time the handler took to produce the response.
+    pub response_bytes: usize,      // Body length; streamed bodies count as 0.
+    pub worker: Option<usize>,      // See `current_worker_id`.
+    pub client_abort: Option<u64>,  // Bytes sent before the client went away, if it did; known once the response is written.
+}
+
+/// A process-wide id to tell requests apart in logs.
+pub fn next_request_id() -> u64{
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The warning to log for `served`, if it was slower or bigger than
+/// `config` allows.
+pub fn threshold_warning(config: &ServerConfig, served: &Served) -> Option<String>{
+    let slow = config.slow_request_warn.is_some_and(|limit| served.duration > limit);
+    let large = config.large_response_warn.is_some_and(|limit| served.response_bytes > limit);
+    let what = match (slow, large){
+        (false, false) => return None,
+        (true, false) => "Slow request",
+        (false, true) => "Large response",
+        (true, true) => "Slow request, large response",
+    };
+    let worker = served.worker.map_or_else(|| "no worker".to_string(), |id| format!("worker {}", id));
+    Some(format!(
+        "{} #{}: {} {} took {}ms, {} bytes, {}",
+        what, served.id, served.method, served.path, served.duration.as_millis(), served.response_bytes, worker
+    ))
+}
+
+/// What `read_request` got from the connection.
+#[derive(Debug)]
+pub enum Incoming{
+    Request(Request),
+    Reject(Response),   // Send this and close: the request was refused or unreadable.
+    Closed,             // The client closed or went quiet without starting a request.
+}
+
+/// Read the next request from `stream`.
+///
+/// `buffer` holds bytes read but not yet used and must be kept between
+/// calls on the same connection; the request's bytes are removed from it,
+/// leaving any pipelined data behind.
+///
+/// The request's path is normalized with `uri::normalize_request`, and a
+/// target that can't be is refused with `400`.
+///
+/// Once the head has arrived, `precheck` may refuse the request by
+/// returning a response, and a body over `max_body_bytes` is refused with
+/// `413`. Either happens before the body is read, and before
+/// `100 Continue`: a client that sent `Expect: 100-continue` only gets
+/// that go-ahead once the request has passed both. The body itself is
+/// read through a `RequestBodyReader`, so no more than the declared
+/// length is ever read, or with `http::read_chunked_body` when it's
+/// chunked, its trailers going in `Request::trailers`.
+///
+/// The whole body is read before the request is returned, whether or
+/// not the handler will look at it, so the next request on a keep-alive
+/// connection is parsed from exactly where this one ended. A body that
+/// won't be read (refused, too big or malformed) comes back as a
+/// `Reject`, which closes the connection rather than leave its bytes to
+/// be taken for a request.
+pub fn read_request<S, F>(stream: &mut S, buffer: &mut Vec<u8>, config: &ServerConfig, precheck: F) -> Incoming
+where
+    S: Read + Write,
+    F: Fn(&Request) -> Option<Response>
+{
+    let mut chunk = [0; 1024];
+    loop{
+        if !buffer.is_empty(){
+            let parsing = Instant::now();
+            match Request::parse_head(buffer, &config.limits){
+                Ok((mut request, head_len)) => {
+                    trace::record("parse", parsing, parsing.elapsed());
+                    let rejection = normalize_target(&mut request, config)
+                        .or_else(|| reject_method(&request, config))
+                        .or_else(|| reject_scheme(&request))
+                        .or_else(|| reject_host(&request, config));
+                    if let Some(response) = rejection{
+                        return Incoming::Reject(response);
+                    }
+                    warn_on_unexpected_port(&request, config);
+                    return read_body(stream, buffer, config, request, head_len, &precheck);
+                },
+                Err(ParseError::Incomplete) => {},
+                Err(e) => return Incoming::Reject(parse_error_response(&e)),
+            }
+        }
+
+        let bytes_read = match stream.read(&mut chunk){
+            Ok(n) => n,
+            Err(e) => {
+                if !buffer.is_empty(){
+                    log::warn(&format!("Could not read request: {}", e));
+                }
+                return Incoming::Closed;     // Or an idle keep-alive connection timed out.
+            },
+        };
+        if bytes_read == 0{
+            // The client stopped sending part-way through the head, unless
+            // all it sent was the empty line some send after a body.
+            return if buffer.is_empty() || buffer == b"\r\n"{
+                Incoming::Closed
+            } else {
+                Incoming::Reject(parse_error_response(&ParseError::Incomplete))
+            };
+        }
+        buffer.extend_from_slice(&chunk[..bytes_read]);
+    }
+}
+
+// With the head in `buffer`, settle whether the body is welcome and read it.
+fn read_body<S, F>(stream: &mut S, buffer: &mut Vec<u8>, config: &ServerConfig, mut request: Request, head_len: usize, precheck: &F) -> Incoming
+where
+    S: Read + Write,
+    F: Fn(&Request) -> Option<Response>
+{
+    if let Some(response) = precheck_request(&request, precheck){
+        return Incoming::Reject(response);
+    }
+    buffer.drain(..head_len);
+    // `parse_head` has already checked the framing headers.
+    let chunked = http::is_chunked(&request.headers).unwrap_or(false);
+    let len = if chunked { 0 } else { http::declared_body_len(&request.headers).unwrap_or(0) };
+
+    if !chunked && buffer.len() >= len{
+        request.body = buffer.drain(..len).collect();
+        return Incoming::Request(request);
+    }
+
+    // A client that already started on the body isn't waiting for us.
+    if request.version == HttpVersion::Http11 && expects_continue(&request) && buffer.is_empty(){
+        if let Err(e) = write_continue(stream){
+            log::warn(&format!("Could not send 100 Continue: {}", e));
+            return Incoming::Closed;
+        }
+    }
+
+    if chunked{
+        return match http::read_chunked_body(stream, buffer, &config.limits){
+            Ok((body, trailers)) => {
+                request.body = body;
+                request.trailers = trailers;
+                Incoming::Request(request)
+            },
+            Err(e) => body_error(e),
+        };
+    }
+
+    // Everything buffered is body, since the client is still sending it.
+    let mut body = std::mem::take(buffer);
+    match RequestBodyReader::new(&mut *stream, config.limits.max_body_bytes).read_exact_len(len, &mut body){
+        Ok(()) => {
+            request.body = body;
+            Incoming::Request(request)
+        },
+        Err(e) => body_error(e),
+    }
+}
+
+// A body that couldn't be read leaves the connection out of step, so
+// it's answered, if it still can be, and closed either way.
+fn body_error(e: BodyError) -> Incoming{
+    match e.status(){
+        Some(status) => Incoming::Reject(error_response(status, &e)),
+        None => {
+            log::warn(&e.to_string());
+            Incoming::Closed
+        },
+    }
+}
+
+// Refuse a `Host` we don't serve, so links and cached pages built from
+// it can't be pointed at someone else's name.
+fn reject_host(request: &Request, config: &ServerConfig) -> Option<Response>{
+    let host = request.effective_host()?;
+    if config.allowed_hosts.is_empty() || vhost::host_allowed(&config.allowed_hosts, host){
+        return None;
+    }
+    let status = config.host_rejection_status;
+    Some(negotiation::status_page(request, status, http::reason_phrase(status)).with_header("Connection", "close"))
+}
+
+// Route on the normalized path, refusing a target with no safe normal form.
+fn normalize_target(request: &mut Request, config: &ServerConfig) -> Option<Response>{
+    let e = uri::normalize_request(request, config.lowercase_paths).err()?;
+    Some(negotiation::status_page(request, e.status(), &e.to_string()).with_header("Connection", "close"))
+}
+
+// `CONNECT` and `TRACE` are refused unless turned on: one makes us an
+// open proxy and the other echoes headers (cookies included) back to
+// whatever script sent them.
+fn reject_method(request: &Request, config: &ServerConfig) -> Option<Response>{
+    let status = match request.method{
+        Method::Connect if !config.connect_tunnel => 405,
+        Method::Trace if !config.enable_trace => 501,
+        _ => return None,
+    };
+    Some(negotiation::status_page(request, status, http::reason_phrase(status)).with_header("Connection", "close"))
+}
+
+// An `https` absolute-form target can't be served over a connection
+// without TLS: the client thinks it's talking to someone else.
+fn reject_scheme(request: &Request) -> Option<Response>{
+    if request.scheme.as_deref() != Some("https") || request.secure{
+        return None;
+    }
+    Some(negotiation::status_page(request, 400, "An https target needs a TLS connection").with_header("Connection", "close"))
+}
+
+// An IP address in `Host` with a port we don't listen on suggests a
+// client (or something in between) confused about where it is talking to.
+fn warn_on_unexpected_port(request: &Request, config: &ServerConfig){
+    let listening = config.addr.parse::<SocketAddr>().ok().map(|addr| addr.port());
+    let host = request.effective_host().and_then(http::parse_host);
+    if let (Some(listening), Some((host, Some(port)))) = (listening, host){
+        let is_ip = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().is_ok();
+        if is_ip && port != listening{
+            log::warn(&format!("Host {}:{} names a port other than {}", host, port, listening));
+        }
+    }
+}
+
+// Refuse expectations we can't meet, then ask the caller.
+fn precheck_request<F>(request: &Request, precheck: &F) -> Option<Response>
+where
+    F: Fn(&Request) -> Option<Response>
+{
+    let unmet = request.headers.get_all("Expect").any(|e| !e.trim().eq_ignore_ascii_case("100-continue"));
+    if unmet{
+        return Some(Response::new(417, http::reason_phrase(417)).with_header("Connection", "close"));
+    }
+    precheck(request).map(|response| response.with_header("Connection", "close"))
+}
+
+fn expects_continue(request: &Request) -> bool{
+    request.headers.get_all("Expect").any(|e| e.trim().eq_ignore_ascii_case("100-continue"))
+}
+
+fn write_continue<S: Write>(stream: &mut S) -> io::Result<()>{
+    stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    stream.flush()
+}
+
+fn parse_error_response(e: &ParseError) -> Response{
+    error_response(e.status(), e)
+}
+
+fn error_response(status: u16, e: &dyn std::error::Error) -> Response{
+    Response::builder(status, http::reason_phrase(status))
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .header("Connection", "close")
+        .body(e.to_string())
+        .build()
+}
@@ -0,0 +1,314 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{log, net, server::ShutdownToken};
+
+/// Accepts connections without blocking forever, so the thread that owns
+/// the listener also gets to do periodic work.
+///
+/// Each pass accepts everything that's waiting, checks the shutdown flag,
+/// closes tracked connections past their deadline and runs any
+/// housekeeping that's due, then waits for the next connection for at
+/// most `poll_interval`. That bounds how long a shutdown request, or a
+/// due task, can go unnoticed.
+pub struct AcceptLoop{
+    listener: TcpListener,
+    shutdown: Arc<AtomicBool>,
+    poll_interval: Duration,
+    connections: ConnectionRegistry,
+    housekeeping: Vec<Housekeeping>,
+}
+
+struct Housekeeping{
+    every: Duration,
+    due: Instant,
+    task: Box<dyn FnMut() + Send>,
+}
+
+impl AcceptLoop{
+    /// Puts `listener` in non-blocking mode.
+    pub fn new(listener: TcpListener) -> io::Result<AcceptLoop>{
+        listener.set_nonblocking(true)?;
+        Ok(AcceptLoop {
+            listener,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            poll_interval: Duration::from_millis(50),
+            connections: ConnectionRegistry::new(),
+            housekeeping: Vec::new(),
+        })
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> AcceptLoop{
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Stop when `shutdown` is set, instead of the loop's own flag.
+    pub fn with_shutdown(mut self, shutdown: Arc<AtomicBool>) -> AcceptLoop{
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Setting this flag makes `run` return within about `poll_interval`.
+    pub fn shutdown_flag(&self) -> Arc<AtomicBool>{
+        Arc::clone(&self.shutdown)
+    }
+
+    /// The loop expires deadlines set through this tracker.
+    pub fn connections(&self) -> ConnectionRegistry{
+        self.connections.clone()
+    }
+
+    /// Run `task` on the loop's thread about every `every`, starting one
+    /// interval from now. A slow task delays accepting, so hand real work
+    /// to a pool.
+    pub fn every<F: FnMut() + Send + 'static>(&mut self, every: Duration, task: F) -> &mut AcceptLoop{
+        self.housekeeping.push(Housekeeping {
+            every,
+            due: Instant::now() + every,
+            task: Box::new(task),
+        });
+        self
+    }
+
+    /// Accept connections and pass each one, back in blocking mode, to
+    /// `handle` until the shutdown flag is set.
+    pub fn run<F: FnMut(TcpStream)>(&mut self, mut handle: F) -> io::Result<()>{
+        loop{
+            if self.shutdown.load(Ordering::SeqCst){
+                return Ok(());
+            }
+
+            let mut failed = false;
+            loop{
+                match self.listener.accept(){
+                    Ok((stream, _)) => {
+                        // Whether an accepted socket inherits non-blocking mode varies by platform.
+                        match stream.set_nonblocking(false){
+                            Ok(()) => handle(stream),
+                            Err(e) => log::error(&format!("Could not accept connection: {}", e)),
+                        }
+                    },
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    // The client gave up before we got to it.
+                    Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset | io::ErrorKind::Interrupted) => {},
+                    Err(e) => {
+                        // Out of descriptors, most likely; try again after a pause.
+                        log::error(&format!("Could not accept connection: {}", e));
+                        failed = true;
+                        break;
+                    },
+                }
+            }
+
+            let now = Instant::now();
+            self.connections.expire(now);
+            for job in &mut self.housekeeping{
+                if job.due <= now{
+                    (job.task)();
+                    job.due = now + job.every;
+                }
+            }
+
+            let next_due = self.housekeeping.iter().map(|job| job.due).min();
+            let wait = next_due.map_or(self.poll_interval, |due| {
+                due.saturating_duration_since(Instant::now()).min(self.poll_interval)
+            });
+            if failed{
+                thread::sleep(wait);
+            } else {
+                net::wait_readable(&self.listener, wait)?;
+            }
+        }
+    }
+}
+
+/// What a tracked connection is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState{
+    Reading,    // Waiting for, or part way through, a request.
+    Handling,   // A request is in hand: being handled or answered.
+    Idle,       // Kept alive between requests.
+}
+
+/// Every open connection, with what it's doing and when it last did
+/// something; the accept loop closes those whose deadline passes.
+///
+/// Workers register a connection and set a deadline while they wait on
+/// the client, for instance for the next request on a keep-alive
+/// connection. Closing it from the loop's side wakes the worker's read
+/// with end of stream (or an `IdleWatcher`'s poll). Clones share the
+/// same set.
+///
+/// The registry also carries the server's drain state: once
+/// `start_draining` is called, workers should finish the request in hand
+/// and close (`is_draining` tells them), handlers sleeping on the
+/// registry's `ShutdownToken` wake, and `close_all` cuts off whatever is
+/// left when the drain deadline passes.
+#[derive(Clone, Default)]
+pub struct ConnectionRegistry{
+    inner: Arc<Mutex<Tracked>>,
+    shutdown: ShutdownToken,    // Set when draining starts.
+}
+
+#[derive(Default)]
+struct Tracked{
+    next_id: u64,
+    connections: HashMap<u64, Entry>,
+}
+
+struct Entry{
+    stream: TcpStream,
+    deadline: Option<Instant>,
+    state: ConnectionState,
+    last_activity: Instant,
+}
+
+impl ConnectionRegistry{
+    pub fn new() -> ConnectionRegistry{
+        ConnectionRegistry::default()
+    }
+
+    /// Start tracking `stream`, as `Reading` with no deadline. It is
+    /// forgotten when the returned handle is dropped.
+    pub fn track(&self, stream: &TcpStream) -> io::Result<TrackedConnection>{
+        let stream = stream.try_clone()?;
+        let mut tracked = self.inner.lock().unwrap();
+        let id = tracked.next_id;
+        tracked.next_id += 1;
+        tracked.connections.insert(id, Entry {
+            stream,
+            deadline: None,
+            state: ConnectionState::Reading,
+            last_activity: Instant::now(),
+        });
+        Ok(TrackedConnection { id, inner: Arc::clone(&self.inner) })
+    }
+
+    /// Begin draining. Connections waiting for their next request have
+    /// no request in hand, so they are closed now; a request that was
+    /// partly received is answered with an error.
+    pub fn start_draining(&self){
+        self.shutdown.shutdown();
+        let tracked = self.inner.lock().unwrap();
+        for entry in tracked.connections.values(){
+            if entry.deadline.is_some(){
+                let _ = entry.stream.shutdown(Shutdown::Read);
+            }
+        }
+    }
+
+    pub fn is_draining(&self) -> bool{
+        self.shutdown.is_shutting_down()
+    }
+
+    /// The token set when draining starts, for handlers to watch.
+    pub fn shutdown_token(&self) -> ShutdownToken{
+        self.shutdown.clone()
+    }
+
+    /// Close every tracked connection, busy or not. Returns how many
+    /// there were.
+    pub fn close_all(&self) -> usize{
+        let mut tracked = self.inner.lock().unwrap();
+        for entry in tracked.connections.values(){
+            let _ = entry.stream.shutdown(Shutdown::Both);
+        }
+        let closed = tracked.connections.len();
+        tracked.connections.clear();
+        closed
+    }
+
+    /// Close and forget the `Idle` connection that has been idle longest,
+    /// to make room for a new one. Returns whether there was one.
+    pub fn close_longest_idle(&self) -> bool{
+        let mut tracked = self.inner.lock().unwrap();
+        let longest = tracked.connections.iter()
+            .filter(|(_, entry)| entry.state == ConnectionState::Idle)
+            .min_by_key(|(_, entry)| entry.last_activity)
+            .map(|(id, _)| *id);
+        match longest.and_then(|id| tracked.connections.remove(&id)){
+            Some(entry) => {
+                let _ = entry.stream.shutdown(Shutdown::Both);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize{
+        self.inner.lock().unwrap().connections.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    /// How many tracked connections are in `state`.
+    pub fn count(&self, state: ConnectionState) -> usize{
+        self.inner.lock().unwrap().connections.values().filter(|entry| entry.state == state).count()
+    }
+
+    /// Close and forget every connection whose deadline is at or before
+    /// `now`. Returns how many there were.
+    pub fn expire(&self, now: Instant) -> usize{
+        let mut tracked = self.inner.lock().unwrap();
+        let expired: Vec<u64> = tracked.connections.iter()
+            .filter(|(_, entry)| entry.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &expired{
+            if let Some(entry) = tracked.connections.remove(id){
+                let _ = entry.stream.shutdown(Shutdown::Both);
+            }
+        }
+        expired.len()
+    }
+}
+
+/// A worker's handle on a tracked connection.
+pub struct TrackedConnection{
+    id: u64,
+    inner: Arc<Mutex<Tracked>>,
+}
+
+impl TrackedConnection{
+    pub fn set_deadline(&self, deadline: Instant){
+        self.update(|entry| entry.deadline = Some(deadline));
+    }
+
+    pub fn clear_deadline(&self){
+        self.update(|entry| entry.deadline = None);
+    }
+
+    /// Move to `state`, which counts as activity.
+    pub fn set_state(&self, state: ConnectionState){
+        self.update(|entry| {
+            entry.state = state;
+            entry.last_activity = Instant::now();
+        });
+    }
+
+    fn update<F: FnOnce(&mut Entry)>(&self, f: F){
+        if let Some(entry) = self.inner.lock().unwrap().connections.get_mut(&self.id){
+            f(entry);
+        }
+    }
+}
+
+impl Drop for TrackedConnection{
+    fn drop(&mut self){
+        if let Ok(mut tracked) = self.inner.lock(){
+            tracked.connections.remove(&self.id);
+        }
+    }
+}
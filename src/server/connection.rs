@@ -0,0 +1,193 @@
+use std::{
+    io::{self, IoSlice, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use crate::{
+    http::{Request, Response},
+    log,
+    pool::{BufferPool, PooledBuf},
+    server::{self, Incoming, ServerConfig},
+};
+
+/// Writes smaller than this are gathered up until the next flush; larger
+/// ones go straight to the stream.
+const WRITE_BUFFER: usize = 8 * 1024;
+
+/// One side of an HTTP connection, with buffered reads and writes.
+///
+/// The read buffer holds whatever arrived past the end of the last
+/// request: the start of its body, or a whole pipelined request after
+/// it. `read_request` uses it before reading more, so nothing is lost
+/// between requests. Writes are gathered in a second buffer and go out
+/// on `flush`, which `send` does after every response; a big body skips
+/// the buffer and is written straight through.
+///
+/// `deliver` is `send` for the response path: it tells a client that
+/// went away partway, which is routine, from other write errors.
+///
+/// Both buffers come from the thread's `BufferPool`, so a worker serving
+/// connection after connection doesn't allocate them anew.
+pub struct Connection<S>{
+    stream: S,
+    read_buf: PooledBuf,    // Read but not yet used.
+    write_buf: PooledBuf,   // Written but not yet flushed.
+    sent: u64,              // Bytes the stream has taken so far.
+}
+
+/// A stream whose reads can be made to time out, as a `TcpStream`'s can.
+pub trait ReadTimeout{
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream{
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>{
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// How sending a response with `Connection::deliver` went.
+#[derive(Debug)]
+pub enum Delivery{
+    Complete(u64),          // Bytes written, head included.
+    ClientAborted(u64),     // The client hung up or reset the connection after this many bytes.
+    Failed(io::Error),      // Writing failed some other way.
+}
+
+/// Whether `error` from writing to a client means it went away: it
+/// closed the connection (`BrokenPipe`) or reset it.
+pub fn is_client_abort(error: &io::Error) -> bool{
+    matches!(error.kind(), io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted)
+}
+
+impl<S: Read + Write> Connection<S>{
+    pub fn new(stream: S) -> Connection<S>{
+        Connection::with_initial(stream, &[])
+    }
+
+    /// A connection where `initial` was already read from `stream`, say
+    /// to pick a pool by the request line.
+    pub fn with_initial(stream: S, initial: &[u8]) -> Connection<S>{
+        let pool = BufferPool::local();
+        let mut read_buf = pool.get();
+        read_buf.extend_from_slice(initial);
+        Connection { stream, read_buf, write_buf: pool.get(), sent: 0 }
+    }
+
+    /// Read the next request; see `server::read_request`.
+    pub fn read_request<F>(&mut self, config: &ServerConfig, precheck: F) -> Incoming
+    where
+        F: Fn(&Request) -> Option<Response>
+    {
+        server::read_request(&mut self.stream, &mut self.read_buf, config, precheck)
+    }
+
+    /// Read the next request of a keep-alive session, giving the client
+    /// `config.keep_alive_timeout` for each read. The timeout is set
+    /// afresh before every request, rather than once for the connection,
+    /// so it always runs from the last response and whatever settings the
+    /// connection is now served with apply: a client that keeps sending
+    /// requests can keep the connection as long as it likes, and one that
+    /// stalls is let go promptly. A timeout that can't be set is logged,
+    /// and the read goes ahead with the old one.
+    pub fn read_next_request<F>(&mut self, config: &ServerConfig, precheck: F) -> Incoming
+    where
+        S: ReadTimeout,
+        F: Fn(&Request) -> Option<Response>
+    {
+        if let Err(e) = self.stream.set_read_timeout(Some(config.keep_alive_timeout)){
+            log::warn(&format!("Could not set read timeout: {}", e));
+        }
+        self.read_request(config, precheck)
+    }
+
+    /// Write `response` and flush it.
+    pub fn send(&mut self, response: &Response) -> io::Result<()>{
+        response.write_to(self)?;
+        self.flush()
+    }
+
+    /// `send`, sorting out how it went. A streamed body stops at the first
+    /// failed write, so a client that hangs up ends it promptly.
+    pub fn deliver(&mut self, response: &Response) -> Delivery{
+        let before = self.sent;
+        match self.send(response){
+            Ok(()) => Delivery::Complete(self.sent - before),
+            Err(e) if is_client_abort(&e) => Delivery::ClientAborted(self.sent - before),
+            Err(e) => Delivery::Failed(e),
+        }
+    }
+
+    /// Bytes written to the stream over the connection's life, not
+    /// counting any still buffered.
+    pub fn bytes_sent(&self) -> u64{
+        self.sent
+    }
+
+    /// Bytes read past the last request.
+    pub fn buffered(&self) -> &[u8]{
+        &self.read_buf
+    }
+
+    pub fn get_ref(&self) -> &S{
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S{
+        &mut self.stream
+    }
+
+    /// The stream, for a protocol switch. Call it after `send`: unflushed
+    /// writes and unused reads are dropped.
+    pub fn into_inner(self) -> S{
+        self.stream
+    }
+
+    // `write_all`, counting what gets through even when it fails.
+    fn flush_buffer(&mut self) -> io::Result<()>{
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < self.write_buf.len(){
+            match self.stream.write(&self.write_buf[written..]){
+                Ok(0) => result = Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => result = Err(e),
+            }
+            if result.is_err(){
+                break;
+            }
+        }
+        self.sent += written as u64;
+        self.write_buf.clear();     // Whatever went wrong, the connection is finished.
+        result
+    }
+}
+
+impl<S: Read + Write> Write for Connection<S>{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize>{
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if self.write_buf.len() + len > WRITE_BUFFER{
+            self.flush_buffer()?;
+        }
+        if len >= WRITE_BUFFER{
+            let written = self.stream.write_vectored(bufs)?;
+            self.sent += written as u64;
+            return Ok(written);
+        }
+        for buf in bufs{
+            self.write_buf.extend_from_slice(buf);
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+        self.flush_buffer()?;
+        self.stream.flush()
+    }
+}
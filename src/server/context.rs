@@ -0,0 +1,99 @@
+use std::{cell::RefCell, net::SocketAddr};
+
+use crate::{
+    http::{Extensions, Request},
+    server::ShutdownToken,
+};
+
+/// Headers that follow a request from service to service, copied into
+/// its `RequestContext` when sent.
+const TRACE_HEADERS: [&str; 4] = ["traceparent", "tracestate", "X-Request-Id", "X-Correlation-Id"];
+
+/// What code serving a request may want to know about it without having
+/// it passed down through every call; see `current_request_context`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestContext{
+    pub request_id: u64,                        // From `next_request_id`.
+    pub worker_id: Option<usize>,               // See `current_worker_id`.
+    pub trace_headers: Vec<(String, String)>,   // The `TRACE_HEADERS` the request carried, as sent.
+    pub shutdown: ShutdownToken,                // Set once the server starts shutting down.
+    pub extensions: Extensions,                 // The request's own, shared with it.
+}
+
+/// The request's id, as the server numbered it, in its `Extensions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(pub u64);
+
+/// Where a request came from, in its `Extensions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionInfo{
+    pub peer: Option<SocketAddr>,   // `None` if the socket couldn't say.
+    pub local: Option<SocketAddr>,  // The address it was accepted on.
+    pub secure: bool,               // Over TLS.
+}
+
+impl RequestContext{
+    /// The context for `request`, served on the current thread, which
+    /// puts its `RequestId` in the request's extensions. Its shutdown
+    /// token is never set; see `with_shutdown`.
+    pub fn for_request(request_id: u64, request: &Request) -> RequestContext{
+        let trace_headers = TRACE_HEADERS.iter()
+            .filter_map(|name| Some((name.to_string(), request.header(name)?.to_string())))
+            .collect();
+        let extensions = request.extensions().clone();
+        extensions.insert(RequestId(request_id));
+        RequestContext {
+            request_id,
+            worker_id: crate::current_worker_id(),
+            trace_headers,
+            shutdown: ShutdownToken::new(),
+            extensions,
+        }
+    }
+
+    /// Let the handler watch `shutdown`, normally the server's
+    /// `ConnectionRegistry::shutdown_token`.
+    pub fn with_shutdown(mut self, shutdown: ShutdownToken) -> RequestContext{
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn trace_header(&self, name: &str) -> Option<&str>{
+        self.trace_headers.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+thread_local!{
+    static REQUEST_CONTEXT: RefCell<Option<RequestContext>> = const { RefCell::new(None) };
+}
+
+/// The context of the request this thread is serving, if any.
+pub fn current_request_context() -> Option<RequestContext>{
+    REQUEST_CONTEXT.with(|context| context.borrow().clone())
+}
+
+/// Run `f` with `context` as the current one. Whatever was current before
+/// is back afterwards, even if `f` panics.
+pub fn with_request_context<T, F>(context: RequestContext, f: F) -> T
+where
+    F: FnOnce() -> T
+{
+    struct Restore(Option<RequestContext>);
+
+    impl Drop for Restore{
+        fn drop(&mut self){
+            REQUEST_CONTEXT.with(|context| *context.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _restore = Restore(REQUEST_CONTEXT.with(|current| current.replace(Some(context))));
+    f()
+}
+
+// Pool workers call this after every job, so nothing a job left behind is
+// seen by the next one.
+pub(crate) fn clear_request_context(){
+    REQUEST_CONTEXT.with(|context| context.borrow_mut().take());
+}
@@ -0,0 +1,393 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::TcpStream,
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{log, net, server::{self, ConnectionRegistry, ServerConfig}, ThreadPool};
+
+/// Serves one accepted connection with the given settings. The bytes are
+/// what was already read from the connection (the start of its first
+/// request), to be parsed before reading any more.
+pub type ConnectionHandler = dyn Fn(TcpStream, Vec<u8>, &ServerConfig) + Send + Sync;
+
+/// Names the pool for a connection from its first request's method and
+/// path; `None` means the default pool.
+pub type PoolSelector = dyn Fn(&str, &str) -> Option<String> + Send + Sync;
+
+/// How a `Server` runs the connections it is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExecutionMode{
+    #[default]
+    Pool,       // On the worker pools: `config.workers` of them, and any named ones.
+    Single,     // Inline on the thread that calls `serve`, so one connection at a time.
+    ThreadPerConnection{
+        max_threads: Option<usize>,     // At most this many at once; `serve` waits for one to finish.
+    },
+}
+
+impl ExecutionMode{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            ExecutionMode::Pool => "pool",
+            ExecutionMode::Single => "single",
+            ExecutionMode::ThreadPerConnection { .. } => "thread-per-conn",
+        }
+    }
+}
+
+impl fmt::Display for ExecutionMode{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            ExecutionMode::ThreadPerConnection { max_threads: Some(max) } => write!(f, "{} (at most {})", self.as_str(), max),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
+
+impl FromStr for ExecutionMode{
+    type Err = String;
+
+    /// `pool`, `single` or `thread-per-conn`, the last with no cap.
+    fn from_str(s: &str) -> Result<ExecutionMode, String>{
+        match s{
+            "pool" => Ok(ExecutionMode::Pool),
+            "single" => Ok(ExecutionMode::Single),
+            "thread-per-conn" => Ok(ExecutionMode::ThreadPerConnection { max_threads: None }),
+            _ => Err(format!("unknown execution mode `{}`", s)),
+        }
+    }
+}
+
+/// The workers that serve connections, and the settings they serve them
+/// with, which can be replaced while the server keeps running.
+///
+/// The listener isn't part of this: whatever accepts connections hands
+/// them to `serve` and stays open throughout a restart.
+///
+/// Besides the default pool, sized by `config.workers`, a server can have
+/// named pools (`with_pool`) to keep slow routes from starving the rest.
+/// When it has any, a default worker first reads the request line, asks
+/// the selector which pool the connection belongs on, and hands it over
+/// with the bytes read so far. The whole connection stays on that pool,
+/// keep-alive requests included. Named pools keep running through
+/// `graceful_restart`.
+///
+/// That's in the default `ExecutionMode::Pool`. `with_mode` can have
+/// each connection served inline by the caller of `serve` instead, or on
+/// a thread of its own; neither has pools, so pools and the selector are
+/// ignored, and the current settings are all a restart swaps.
+///
+/// Given the registry its connections are tracked in
+/// (`with_connections`), a server with `evict_idle_when_busy` set closes
+/// the longest-idle keep-alive connection when a new one arrives and
+/// every default worker already has one, rather than leave the new one
+/// queued behind clients that may never send another request.
+pub struct Server{
+    handler: Arc<ConnectionHandler>,
+    current: Mutex<Generation>,
+    restarting: Mutex<()>,      // One restart at a time.
+    in_flight: Arc<InFlight>,
+    pools: Arc<HashMap<String, ThreadPool>>,
+    selector: Option<Arc<PoolSelector>>,
+    connections: Option<ConnectionRegistry>,
+    mode: ExecutionMode,
+    threads: Arc<Slots>,        // Taken by connection threads in `ThreadPerConnection`.
+}
+
+// A counting semaphore: at most `max` slots taken at once.
+#[derive(Default)]
+struct Slots{
+    max: Option<usize>,
+    taken: Mutex<usize>,
+    freed: Condvar,
+}
+
+// Gives a slot back when dropped, panics included.
+struct Slot(Arc<Slots>);
+
+impl Slots{
+    // Wait for a slot to be free, and take it.
+    fn take(slots: &Arc<Slots>) -> Slot{
+        let mut taken = slots.taken.lock().unwrap_or_else(|e| e.into_inner());
+        while slots.max.is_some_and(|max| *taken >= max){
+            taken = slots.freed.wait(taken).unwrap_or_else(|e| e.into_inner());
+        }
+        *taken += 1;
+        Slot(Arc::clone(slots))
+    }
+}
+
+impl Drop for Slot{
+    fn drop(&mut self){
+        *self.0.taken.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+// Connections handed to `serve` that haven't been finished with yet.
+#[derive(Default)]
+struct InFlight{
+    count: Mutex<usize>,
+    idle: Condvar,
+}
+
+// Counts a connection as finished when dropped, panics included.
+struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard{
+    fn drop(&mut self){
+        let mut count = self.0.count.lock().unwrap_or_else(|e| e.into_inner());
+        *count -= 1;
+        if *count == 0{
+            self.0.idle.notify_all();
+        }
+    }
+}
+
+struct Generation{
+    config: Arc<ServerConfig>,
+    pool: Option<ThreadPool>,   // Only in `ExecutionMode::Pool`.
+}
+
+impl Generation{
+    fn new(config: ServerConfig, mode: ExecutionMode) -> Generation{
+        let pool = (mode == ExecutionMode::Pool).then(|| ThreadPool::new(config.workers));
+        Generation { config: Arc::new(config), pool }
+    }
+}
+
+impl Server{
+    /// Start `config.workers` workers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.workers` is zero.
+    pub fn new<F>(config: ServerConfig, handler: F) -> Server
+    where
+        F: Fn(TcpStream, Vec<u8>, &ServerConfig) + Send + Sync + 'static
+    {
+        Server {
+            handler: Arc::new(handler),
+            current: Mutex::new(Generation::new(config, ExecutionMode::Pool)),
+            restarting: Mutex::new(()),
+            in_flight: Arc::default(),
+            pools: Arc::default(),
+            selector: None,
+            connections: None,
+            mode: ExecutionMode::Pool,
+            threads: Arc::default(),
+        }
+    }
+
+    /// Serve connections as `mode` says; see above. The default workers
+    /// are stopped if the mode doesn't use them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread cap is zero.
+    pub fn with_mode(mut self, mode: ExecutionMode) -> Server{
+        let max_threads = match mode{
+            ExecutionMode::ThreadPerConnection { max_threads } => max_threads,
+            _ => None,
+        };
+        assert!(max_threads != Some(0), "a thread cap must be positive");
+        let config = Arc::clone(&self.current.get_mut().unwrap().config);
+        *self.current.get_mut().unwrap() = Generation::new(ServerConfig::clone(&config), mode);
+        self.threads = Arc::new(Slots { max: max_threads, ..Slots::default() });
+        self.mode = mode;
+        self
+    }
+
+    pub fn mode(&self) -> ExecutionMode{
+        self.mode
+    }
+
+    /// Add a pool of `size` workers called `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero, or if called once the server is shared.
+    pub fn with_pool(mut self, name: &str, size: usize) -> Server{
+        Arc::get_mut(&mut self.pools)
+            .expect("pools are added before serving")
+            .insert(name.to_string(), ThreadPool::new(size));
+        self
+    }
+
+    /// Choose connections' pools with `selector`, typically
+    /// `Router::pool_for`. A name with no pool behind it means the default.
+    pub fn with_pool_selector<F>(mut self, selector: F) -> Server
+    where
+        F: Fn(&str, &str) -> Option<String> + Send + Sync + 'static
+    {
+        self.selector = Some(Arc::new(selector));
+        self
+    }
+
+    /// Evict idle connections from `connections` to make room; see above.
+    pub fn with_connections(mut self, connections: ConnectionRegistry) -> Server{
+        self.connections = Some(connections);
+        self
+    }
+
+    /// The settings new connections are served with.
+    pub fn config(&self) -> Arc<ServerConfig>{
+        Arc::clone(&self.current.lock().unwrap().config)
+    }
+
+    /// Queue `stream` for the current workers, which pass it on to a
+    /// named pool if the selector picks one; or, in the other modes, serve
+    /// it now or start its thread, waiting for one to finish first if
+    /// there are already as many as the cap allows.
+    ///
+    /// `config.socket`'s options, `TCP_NODELAY` and keepalive, are set on
+    /// `stream` first, before the handler sees it.
+    pub fn serve(&self, stream: TcpStream){
+        let current = self.current.lock().unwrap();
+        let config = Arc::clone(&current.config);
+        if let Err(e) = net::configure_stream(&stream, &config.socket){
+            log::warn(&format!("Could not configure connection: {}", e));
+        }
+        if let Some(connections) = &self.connections{
+            if config.evict_idle_when_busy && self.capacity(&config).is_some_and(|capacity| self.in_flight() >= capacity){
+                connections.close_longest_idle();
+            }
+        }
+        let handler = Arc::clone(&self.handler);
+        *self.in_flight.count.lock().unwrap() += 1;
+        let guard = InFlightGuard(Arc::clone(&self.in_flight));
+
+        let selector = match &self.selector{
+            Some(selector) if !self.pools.is_empty() && current.pool.is_some() => Arc::clone(selector),
+            _ => {
+                self.run(current, move || {
+                    let _guard = guard;
+                    handler(stream, Vec::new(), &config);
+                });
+                return;
+            },
+        };
+        let pools = Arc::clone(&self.pools);
+        self.run(current, move || {
+            let mut stream = stream;
+            let mut buffer = Vec::new();
+            let _ = stream.set_read_timeout(Some(config.keep_alive_timeout));
+            let line = server::read_request_line(&mut stream, &mut buffer, config.limits.max_request_line);
+            let pool = line
+                .and_then(|(method, path)| selector(method.as_str(), &path))
+                .and_then(|name| pools.get(&name));
+            match pool{
+                Some(pool) => {
+                    pool.execute(move || {
+                        let _guard = guard;
+                        handler(stream, buffer, &config);
+                    });
+                },
+                None => {
+                    let _guard = guard;
+                    handler(stream, buffer, &config);
+                },
+            }
+        });
+    }
+
+    /// Run `job` as `serve` would run a connection, with the current
+    /// settings, counted as a connection in flight: for carrying on with
+    /// a connection `serve` was given earlier, such as one an
+    /// `IdleWatcher` parked.
+    pub fn resume<F>(&self, job: F)
+    where
+        F: FnOnce(&ServerConfig) + Send + 'static
+    {
+        let current = self.current.lock().unwrap();
+        let config = Arc::clone(&current.config);
+        *self.in_flight.count.lock().unwrap() += 1;
+        let guard = InFlightGuard(Arc::clone(&self.in_flight));
+        self.run(current, move || {
+            let _guard = guard;
+            job(&config);
+        });
+    }
+
+    // Run `job` on the current workers, or as the mode says without them.
+    // The settings are let go of first, so a connection served inline
+    // doesn't hold up a restart.
+    fn run<F: FnOnce() + Send + 'static>(&self, current: MutexGuard<'_, Generation>, job: F){
+        if let Some(pool) = &current.pool{
+            pool.execute(job);
+            return;
+        }
+        drop(current);
+        if self.mode == ExecutionMode::Single{
+            job();
+            return;
+        }
+        let slot = Slots::take(&self.threads);
+        let spawned = thread::Builder::new().name("connection".to_string()).spawn(move || {
+            let _slot = slot;
+            job();
+        });
+        if let Err(e) = spawned{
+            log::warn(&format!("Could not start a connection thread: {}", e));
+        }
+    }
+
+    // How many connections can be served at once, if there's a limit.
+    fn capacity(&self, config: &ServerConfig) -> Option<usize>{
+        match self.mode{
+            ExecutionMode::Pool => Some(config.workers),
+            ExecutionMode::Single => None,
+            ExecutionMode::ThreadPerConnection { max_threads } => max_threads,
+        }
+    }
+
+    /// Connections passed to `serve` that are queued or being served.
+    pub fn in_flight(&self) -> usize{
+        *self.in_flight.count.lock().unwrap()
+    }
+
+    /// Wait up to `timeout` for every connection passed to `serve` to be
+    /// finished with. Returns whether they all were.
+    pub fn wait_idle(&self, timeout: Duration) -> bool{
+        let deadline = Instant::now() + timeout;
+        let mut count = self.in_flight.count.lock().unwrap();
+        while *count > 0{
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero(){
+                return false;
+            }
+            count = self.in_flight.idle.wait_timeout(count, left).unwrap().0;
+        }
+        true
+    }
+
+    /// Switch to `new_config` without dropping any connection.
+    ///
+    /// A new set of `new_config.workers` workers is started with the new
+    /// settings and takes every connection passed to `serve` from then on.
+    /// The old workers drain: they finish the connections they were
+    /// already given, queued ones included, and this returns once they
+    /// have exited. A handler that panics on an old worker is logged and
+    /// doesn't stop the restart. A long-lived connection (a WebSocket, an
+    /// event stream) holds the restart open until it ends, so call this
+    /// from a thread that can wait. Settings that belong to the listener,
+    /// such as the backlog, only change when it is rebound.
+    pub fn graceful_restart(&self, new_config: ServerConfig){
+        let _restarting = self.restarting.lock().unwrap();
+        let next = Generation::new(new_config, self.mode);
+        let old = std::mem::replace(&mut *self.current.lock().unwrap(), next);
+
+        if old.pool.is_some(){
+            log::info(&format!("Draining the previous {} workers.", old.config.workers));
+        }
+        // Terminate messages queue up behind the jobs already sent, so
+        // dropping the pool lets every one of them run first.
+        drop(old);
+        log::info("Restart complete.");
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    env, io,
+    net::TcpListener,
+    process::{Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::net;
+
+/// The startup flag naming a listener descriptor inherited from the
+/// previous process: `--inherited-fd N`.
+pub const INHERITED_FD_FLAG: &str = "--inherited-fd";
+
+/// How long a successor gets to prove it started before it counts as up.
+const STARTUP_GRACE: Duration = Duration::from_millis(500);
+
+/// Start a fresh copy of this binary that serves on `listener`, for a
+/// restart without systemd.
+///
+/// The child gets this process's arguments, with `--inherited-fd`
+/// pointing at the listener in place of any way of finding a listener
+/// this process was given. Both processes hold the same socket, so until
+/// the caller closes its copy connections queue for whichever accepts
+/// first and none are refused. The caller should stop accepting and
+/// drain only once this returns `Ok`; an error means the child couldn't
+/// be started or exited straight away, and this process should carry on.
+pub fn spawn_successor(listener: &TcpListener) -> io::Result<Child>{
+    let exe = env::current_exe()?;
+    let mut command = Command::new(exe);
+    // The child sees the listener under the same descriptor number.
+    let fd = listener_fd(listener)?;
+    command.args(successor_args(env::args().skip(1), fd));
+
+    let (mut child, _) = net::spawn_with_listener(&mut command, listener)?;
+    let started = Instant::now();
+    while started.elapsed() < STARTUP_GRACE{
+        if let Some(status) = child.try_wait()?{
+            return Err(io::Error::other(format!("the new process exited at once ({})", status)));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(child)
+}
+
+/// Parse `--inherited-fd N` out of the command line arguments, if given.
+pub fn inherited_fd_arg<I: IntoIterator<Item = String>>(args: I) -> Result<Option<i32>, String>{
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next(){
+        if arg == INHERITED_FD_FLAG{
+            return args.next()
+                .and_then(|fd| fd.parse::<i32>().ok())
+                .filter(|fd| *fd >= 0)
+                .map(Some)
+                .ok_or_else(|| format!("{} needs a descriptor number", INHERITED_FD_FLAG));
+        }
+    }
+    Ok(None)
+}
+
+// This process's arguments minus the ways it was handed a listener, plus
+// the flag that hands over `fd`.
+fn successor_args<I: Iterator<Item = String>>(mut args: I, fd: i32) -> Vec<String>{
+    let mut out = Vec::new();
+    while let Some(arg) = args.next(){
+        match arg.as_str(){
+            INHERITED_FD_FLAG => {
+                args.next();
+            },
+            "--socket-activation" => {},
+            _ => out.push(arg),
+        }
+    }
+    out.push(INHERITED_FD_FLAG.to_string());
+    out.push(fd.to_string());
+    out
+}
+
+#[cfg(unix)]
+fn listener_fd(listener: &TcpListener) -> io::Result<i32>{
+    use std::os::fd::AsRawFd;
+    Ok(listener.as_raw_fd())
+}
+
+#[cfg(not(unix))]
+fn listener_fd(_: &TcpListener) -> io::Result<i32>{
+    Err(io::Error::new(io::ErrorKind::Unsupported, "inherited sockets are not supported on this platform"))
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+use crate::{
+    log,
+    poller::Poller,
+    server::{ConnectionState, TrackedConnection},
+};
+
+/// How long one poll waits before checking the watcher is still wanted.
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The poller token of the waker; parked connections count up from 0.
+const WAKER: usize = usize::MAX;
+
+/// A keep-alive connection between requests, parked with an
+/// `IdleWatcher` so its worker can serve someone else meanwhile.
+pub struct IdleConnection{
+    pub stream: TcpStream,
+    pub tracked: Option<TrackedConnection>,
+    pub served: usize,      // Requests the connection has carried so far.
+}
+
+type Resume = dyn Fn(IdleConnection, IdleWatcher) + Send + Sync;
+
+/// Holds idle keep-alive connections off the workers, and hands each one
+/// to the `on_ready` callback once its client sends something.
+///
+/// One thread polls every parked socket. Parking marks a connection
+/// `Idle` with a deadline, so the accept loop's housekeeping closes it if
+/// the client stays quiet; the watcher then sees the end of the stream
+/// and drops it, as it does when the client hangs up. Clones share one
+/// watcher, whose thread stops once the last of them is dropped.
+#[derive(Clone)]
+pub struct IdleWatcher{
+    shared: Arc<Shared>,
+}
+
+struct Shared{
+    parked: Mutex<Parked>,
+    resume: Mutex<Option<Arc<Resume>>>,
+    waker: Waker,
+}
+
+struct Parked{
+    poller: Poller,         // The waker and every parked socket.
+    connections: HashMap<usize, IdleConnection>,
+    next_token: usize,
+}
+
+impl IdleWatcher{
+    /// Start the watcher's thread. Fails where sockets can't be polled.
+    pub fn start() -> io::Result<IdleWatcher>{
+        let waker = Waker::new()?;
+        let mut poller = Poller::new();
+        poller.register(&waker.receiver, WAKER);
+        poller.wait(Duration::ZERO)?;
+        let parked = Parked { poller, connections: HashMap::new(), next_token: 0 };
+        let shared = Arc::new(Shared { parked: Mutex::new(parked), resume: Mutex::new(None), waker });
+        let weak = Arc::downgrade(&shared);
+        thread::spawn(move || watch(weak));
+        Ok(IdleWatcher { shared })
+    }
+
+    /// Hand connections whose client has sent something to `resume`, on
+    /// the watcher's thread, along with the watcher to park them again.
+    /// Until this is set they are closed instead.
+    pub fn on_ready<F>(&self, resume: F)
+    where
+        F: Fn(IdleConnection, IdleWatcher) + Send + Sync + 'static
+    {
+        *self.shared.resume.lock().unwrap() = Some(Arc::new(resume));
+    }
+
+    /// Watch `idle` until its client sends its next request, or for
+    /// `timeout` at most.
+    pub fn park(&self, idle: IdleConnection, timeout: Duration){
+        if let Some(tracked) = &idle.tracked{
+            tracked.set_state(ConnectionState::Idle);
+            tracked.set_deadline(Instant::now() + timeout);
+        }
+        {
+            let mut parked = self.shared.parked.lock().unwrap();
+            let token = parked.next_token;
+            parked.next_token = (token + 1) % WAKER;
+            parked.poller.register(&idle.stream, token);
+            parked.connections.insert(token, idle);
+        }
+        self.shared.waker.wake();
+    }
+
+    /// Connections parked now.
+    pub fn len(&self) -> usize{
+        self.shared.parked.lock().unwrap().connections.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+}
+
+// The watcher's thread. It only holds on to the watcher between polls,
+// so dropping the last `IdleWatcher` ends it within `POLL_TIMEOUT`. It
+// polls a copy of the poller, outside the lock so parking isn't held up;
+// that's safe because only this thread takes connections out, so none of
+// the copy's sockets can close under it.
+fn watch(shared: Weak<Shared>){
+    loop{
+        let poller = match shared.upgrade(){
+            Some(shared) => shared.parked.lock().unwrap().poller.clone(),
+            None => return,
+        };
+        let ready = match poller.wait(POLL_TIMEOUT){
+            Ok(ready) => ready,
+            Err(e) => {
+                log::error(&format!("Idle connection watcher stopped: {}", e));
+                return;
+            },
+        };
+        let shared = match shared.upgrade(){
+            Some(shared) => shared,
+            None => return,
+        };
+        if ready.contains(&WAKER){
+            shared.waker.drain();
+        }
+
+        let woken: Vec<IdleConnection> = {
+            let mut parked = shared.parked.lock().unwrap();
+            ready.iter().filter(|token| **token != WAKER).filter_map(|token| {
+                parked.poller.deregister(*token);
+                parked.connections.remove(token)
+            }).collect()
+        };
+        if woken.is_empty(){
+            continue;
+        }
+        let resume = shared.resume.lock().unwrap().clone();
+        let watcher = IdleWatcher { shared };
+        for idle in woken{
+            let mut byte = [0; 1];
+            match (idle.stream.peek(&mut byte), &resume){
+                // The client sent its next request.
+                (Ok(n), Some(resume)) if n > 0 => {
+                    if let Some(tracked) = &idle.tracked{
+                        tracked.clear_deadline();
+                        tracked.set_state(ConnectionState::Reading);
+                    }
+                    resume(idle, watcher.clone());
+                },
+                // It hung up, or was closed for idling too long.
+                _ => drop(idle),
+            }
+        }
+    }
+}
+
+// Wakes the watcher's poll when a connection is parked: a byte down a
+// socket pair whose far end is always polled. Where there are no unix
+// sockets, a loopback TCP connection does the job.
+struct Waker{
+    sender: WakerStream,
+    receiver: WakerStream,
+}
+
+#[cfg(unix)]
+type WakerStream = UnixStream;
+#[cfg(not(unix))]
+type WakerStream = TcpStream;
+
+impl Waker{
+    #[cfg(unix)]
+    fn new() -> io::Result<Waker>{
+        let (sender, receiver) = UnixStream::pair()?;
+        Waker::nonblocking(sender, receiver)
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> io::Result<Waker>{
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let sender = TcpStream::connect(listener.local_addr()?)?;
+        let (receiver, _) = listener.accept()?;
+        sender.set_nodelay(true)?;
+        Waker::nonblocking(sender, receiver)
+    }
+
+    fn nonblocking(sender: WakerStream, receiver: WakerStream) -> io::Result<Waker>{
+        sender.set_nonblocking(true)?;
+        receiver.set_nonblocking(true)?;
+        Ok(Waker { sender, receiver })
+    }
+
+    fn wake(&self){
+        let _ = (&self.sender).write(&[1]);     // A full buffer means a wake-up is pending anyway.
+    }
+
+    fn drain(&self){
+        let mut buf = [0; 64];
+        while matches!((&self.receiver).read(&mut buf), Ok(n) if n > 0) {}
+    }
+}
@@ -0,0 +1,430 @@
+// The server as the binary runs it: listening, serving connections in
+// whichever execution mode was asked for, reloading the config file and
+// draining at shutdown. Which pages there are is up to the caller.
+use std::{
+    fs,
+    io,
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    access_log::AccessLog,
+    favicon,
+    info::BuildInfo,
+    log,
+    net,
+    router::{self, Router},
+    server::{
+        self, AcceptLoop, Connection, ConnectionInfo, ConnectionRegistry, ConnectionState, Delivery, ExecutionMode,
+        IdleConnection, IdleWatcher, Incoming, RequestContext, Served, Server, ServerConfig, TrackedConnection,
+    },
+    signal,
+    sitemap::SitemapGenerator,
+    trace,
+    ThreadPool,
+};
+
+/// How `run_server_with` runs, beyond the settings themselves.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunOptions{
+    pub config_path: Option<PathBuf>,   // Where the settings came from; reloaded when it changes.
+    pub inherited_fd: Option<i32>,      // A listener handed over by the process restarting, used instead of binding.
+    pub mode: ExecutionMode,            // What runs the connections: the pools, the accepting thread, or a thread each.
+}
+
+/// What the pages have to work with.
+#[derive(Clone)]
+pub struct App{
+    pub pool: Arc<ThreadPool>,          // For jobs the routes schedule; it carries on while the workers restart.
+    pub info: Arc<BuildInfo>,
+    pub connections: ConnectionRegistry,    // Knows when the server is draining.
+}
+
+/// Serve the routes registered with `register_route!` with `config`
+/// until a shutdown signal; see `run_server_with`.
+pub fn run_server(config: ServerConfig) -> io::Result<()>{
+    run_server_with(config, RunOptions::default(), |_, _| Router::new())
+}
+
+/// Serve the router `routes` builds, along with the routes registered
+/// with `register_route!` and the sitemap if there's a base URL for one,
+/// until Ctrl-C or SIGTERM; then let connections finish the request in
+/// hand for up to `drain_timeout`, and close the rest.
+///
+/// Connections are served as `mode` says: by default on the worker
+/// pools, with keep-alive ones waiting for their next request parked off
+/// them where sockets can be polled. Edits to the config file take effect
+/// through a graceful restart, and with `reexec_restart` SIGUSR2 hands
+/// the listener to a fresh copy of the binary.
+///
+/// `ExecutionMode::Single` serves each connection in turn on the
+/// accepting thread, which is handy for debugging: a slow page or a
+/// keep-alive client holds up everyone else, until the client goes quiet
+/// for `keep_alive_timeout`. `ThreadPerConnection` gives each its own
+/// thread, parked ones included when they resume.
+///
+/// Returns once the connections are done with. A handler still running
+/// can't be interrupted and is left to it, so a binary should exit then.
+pub fn run_server_with<F>(config: ServerConfig, options: RunOptions, routes: F) -> io::Result<()>
+where
+    F: FnOnce(&App, &ServerConfig) -> Router
+{
+    log::set_format(config.log_format);
+    log::set_debug(config.log_debug);
+    log::set_redactor(config.redact.clone());
+    trace::set_enabled(config.trace_requests);
+
+    let listener = match options.inherited_fd{
+        Some(fd) => net::inherited_fd(fd)?,
+        None => net::listen(config.addr.as_str(), &config.socket)?,
+    };
+    // A second handle on the socket, to pass on when re-executing.
+    let handover = listener.try_clone()?;
+
+    // Accept connections without blocking forever, so the loop can also
+    // close keep-alive connections that have sat idle too long.
+    let mut accept = AcceptLoop::new(listener)?;
+    let app = App {
+        pool: Arc::new(ThreadPool::new(config.job_workers)),
+        info: Arc::new(BuildInfo::new(config.workers)),
+        connections: accept.connections(),
+    };
+
+    let mut router = routes(&app, &config);
+    router.mount("", router::take_global());
+    // Every page so far that's meant for people, for search engines.
+    if let Some(base_url) = &config.sitemap_base_url{
+        SitemapGenerator::register(&mut router, base_url);
+    }
+
+    let access_log = match config.access_log.clone(){
+        Some(log) => {
+            let path = log.path.display().to_string();
+            Some(AccessLog::open(log).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?)
+        },
+        None => None,
+    };
+    let serving = Arc::new(Serving {
+        router: Arc::new(router),
+        connections: app.connections.clone(),
+        access_log: access_log.clone(),
+    });
+
+    // Ctrl-C or SIGTERM stops accepting and starts draining.
+    match signal::catch_shutdown_signals(){
+        Ok(()) => {
+            let stop = accept.shutdown_flag();
+            accept.every(Duration::from_millis(100), move || {
+                if signal::shutdown_requested(){
+                    stop.store(true, Ordering::SeqCst);
+                }
+            });
+        },
+        Err(e) => log::warn(&format!("Shutdown signals will not drain connections: {}", e)),
+    }
+
+    let served = serve(accept, handover, config, options, serving);
+    if let Some(log) = &access_log{
+        log.shutdown();
+    }
+    served
+}
+
+fn serve(
+    mut accept: AcceptLoop,
+    handover: TcpListener,
+    config: ServerConfig,
+    options: RunOptions,
+    serving: Arc<Serving>,
+) -> io::Result<()>{
+    let connections = serving.connections.clone();
+
+    // Keep-alive connections wait for their next request off the workers,
+    // where sockets can be polled, so idle clients can't take them all.
+    // Served on the accepting thread, there's nowhere to return them to.
+    let idle = match options.mode{
+        ExecutionMode::Single => None,
+        _ => match IdleWatcher::start(){
+            Ok(idle) => Some(idle),
+            Err(e) => {
+                log::warn(&format!("Idle keep-alive connections will each hold a worker: {}", e));
+                None
+            },
+        },
+    };
+
+    let reexec_restart = config.reexec_restart;
+    let handler_serving = Arc::clone(&serving);
+    let handler_idle = idle.clone();
+    let pool_router = Arc::clone(&serving.router);
+    let server = Server::new(config, move |stream, buffer, config| {
+        handle_connection(stream, buffer, config, &handler_serving, handler_idle.as_ref());
+    });
+    let mut server = server.with_connections(connections.clone()).with_mode(options.mode);
+    // Slow pages get workers of their own, so they can't hold up the rest.
+    if options.mode == ExecutionMode::Pool{
+        server = server
+            .with_pool("slow", 2)
+            .with_pool_selector(move |method, path| pool_router.pool_for(method, path).map(str::to_string));
+    }
+    let server = Arc::new(server);
+    log::info(&format!("Serving connections: {}.", options.mode));
+
+    // A parked connection goes back to the workers once its client sends
+    // the next request.
+    if let Some(idle) = &idle{
+        let server = Arc::downgrade(&server);
+        idle.on_ready(move |parked, idle| {
+            if let Some(server) = server.upgrade(){
+                let serving = Arc::clone(&serving);
+                server.resume(move |config| resume_connection(parked, config, &serving, &idle));
+            }
+        });
+    }
+
+    // With `reexec_restart`, SIGUSR2 starts a fresh copy of the binary on
+    // the same socket, then this process stops accepting and drains.
+    if reexec_restart{
+        match signal::catch_restart_signal(){
+            Ok(()) => {
+                let stop = accept.shutdown_flag();
+                accept.every(Duration::from_millis(100), move || {
+                    if !signal::restart_requested(){
+                        return;
+                    }
+                    match server::spawn_successor(&handover){
+                        Ok(child) => {
+                            log::info(&format!("Handed the listener to process {}; draining.", child.id()));
+                            stop.store(true, Ordering::SeqCst);
+                        },
+                        Err(e) => log::warn(&format!("Could not restart, carrying on: {}", e)),
+                    }
+                });
+            },
+            Err(e) => {
+                log::warn(&format!("Restart signals will not re-execute: {}", e));
+                drop(handover);
+            },
+        }
+    }else{
+        drop(handover);     // Nothing to hand it to, and it would keep the socket open past the loop.
+    }
+
+    // Edits to the config file take effect without dropping connections.
+    if let Some(path) = options.config_path{
+        let server = Arc::clone(&server);
+        let mut modified = modified_time(&path);
+        accept.every(Duration::from_secs(1), move || {
+            let now = modified_time(&path);
+            if now == modified{
+                return;
+            }
+            modified = now;
+            match ServerConfig::load(&path){
+                Ok(config) => {
+                    let server = Arc::clone(&server);
+                    thread::spawn(move || server.graceful_restart(config));
+                },
+                Err(e) => log::warn(&format!("Keeping the current settings: {}: {}", path.display(), e)),
+            }
+        });
+    }
+
+    accept.run(|stream| server.serve(stream))?;
+    drop(accept);   // Closes the listener, and the handover copy if the restart check held it.
+
+    // Let connections finish the request in hand, then cut off the rest.
+    let drain_timeout = server.config().drain_timeout;
+    log::info(&format!("Draining {} connections for up to {:?}.", server.in_flight(), drain_timeout));
+    connections.start_draining();
+    if !server.wait_idle(drain_timeout){
+        log::info(&format!("Closing {} connections still open after the drain.", connections.close_all()));
+    }
+    Ok(())
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime>{
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// What every connection is served with.
+struct Serving{
+    router: Arc<Router>,
+    connections: ConnectionRegistry,
+    access_log: Option<AccessLog>,
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    initial: Vec<u8>,
+    config: &ServerConfig,
+    serving: &Serving,
+    idle: Option<&IdleWatcher>,
+){
+    let tracked = serving.connections.track(&stream).ok();
+
+    // Reads and writes are buffered. Bytes read past one request (starting
+    // with whatever the server read to pick a pool) are kept for the next.
+    let connection = Connection::with_initial(stream, &initial);
+    serve_requests(connection, tracked, 0, config, serving, idle);
+}
+
+// Carry on with a connection the idle watcher had parked.
+fn resume_connection(parked: IdleConnection, config: &ServerConfig, serving: &Serving, idle: &IdleWatcher){
+    let connection = Connection::new(parked.stream);
+    serve_requests(connection, parked.tracked, parked.served, config, serving, Some(idle));
+}
+
+fn serve_requests(
+    mut connection: Connection<TcpStream>,
+    tracked: Option<TrackedConnection>,
+    mut served: usize,
+    config: &ServerConfig,
+    serving: &Serving,
+    idle: Option<&IdleWatcher>,
+){
+    let (router, connections, access_log) = (&serving.router, &serving.connections, serving.access_log.as_ref());
+    let peer = connection.get_ref().peer_addr().ok();
+    let local = connection.get_ref().local_addr().ok();
+
+    // Serve requests until either side wants the connection closed.
+    loop{
+        if let Some(tracked) = &tracked{
+            tracked.set_deadline(Instant::now() + config.keep_alive_timeout);
+        }
+        let trace = trace::begin();
+        let mut slow_trace = None;
+        let mut answered = None;    // The request, its id and how long it took, for the access log.
+        // An idle keep-alive connection gives its worker back after a
+        // while: a stalled read times out, the timeout starting afresh for
+        // each request, and the accept loop closes connections still
+        // waiting for their next request past the deadline.
+        let incoming = connection.read_next_request(config, |_| None);
+        if let Some(tracked) = &tracked{
+            tracked.clear_deadline();
+            tracked.set_state(ConnectionState::Handling);
+        }
+
+        // Let the router pick the page; anything that isn't acceptable HTTP
+        // gets the status for what was wrong with it, and the connection
+        // is closed since we can't tell where the next request would start.
+        let mut response = match incoming{
+            Incoming::Request(request) => {
+                served += 1;
+                let id = server::next_request_id();
+                if config.log_favicon || !favicon::is_favicon_request(&request){
+                    log::request(id, &request);
+                }
+
+                let started = Instant::now();
+                request.extensions().insert(ConnectionInfo { peer, local, secure: request.secure });
+                let context = RequestContext::for_request(id, &request).with_shutdown(connections.shutdown_token());
+                let mut response =
+                    server::with_request_context(context, || router.dispatch(&request).finalize(&request));
+                let served = Served {
+                    id,
+                    method: &request.method,
+                    path: &request.path,
+                    duration: started.elapsed(),
+                    response_bytes: response.body.len(),
+                    worker: crate::current_worker_id(),
+                    client_abort: None,
+                };
+                if let Some(warning) = server::threshold_warning(config, &served){
+                    log::warn(&warning);
+                }
+                // With tracing on, slow requests have their spans logged
+                // and `?trace=1` asks for them in `Server-Timing`.
+                if let Some(trace) = &trace{
+                    if config.slow_request_warn.is_some_and(|limit| served.duration > limit){
+                        slow_trace = Some(format!("#{} {} {}", id, request.method, request.path));
+                    }
+                    if let Some(timing) = trace::requested(&request).then(|| trace.server_timing()).flatten(){
+                        response.headers.set("Server-Timing", &timing);
+                    }
+                }
+                let duration = served.duration;
+                answered = Some((request, id, duration));
+                response
+            },
+            Incoming::Reject(response) => response,
+            Incoming::Closed => return,
+        };
+
+        // While draining, every connection ends after its current request,
+        // as does one that has carried as many requests as it may.
+        if (connections.is_draining() || config.keep_alive_exhausted(served)) && response.upgrade.is_none(){
+            response.headers.set("Connection", "close");
+        }
+
+        // Send the response with the status line, headers, content length
+        // and body, flushed once it's all written. A streamed response is
+        // written as it comes. A client hanging up partway is routine, and
+        // only logged at debug level; anything else going wrong is worth a
+        // warning.
+        let writing = Instant::now();
+        let delivery = connection.deliver(&response);
+        let client_abort = match &delivery{
+            Delivery::Complete(_) => None,
+            Delivery::ClientAborted(bytes) => {
+                log::debug(&format!("Client went away after {} bytes of the response", bytes));
+                Some(*bytes)
+            },
+            Delivery::Failed(e) => {
+                log::warn(&format!("Could not send the response: {}", e));
+                None
+            },
+        };
+        if let (Some(access_log), Some((request, id, duration))) = (access_log, &answered){
+            let served = Served {
+                id: *id,
+                method: &request.method,
+                path: &request.path,
+                duration: *duration,
+                response_bytes: response.body.len(),
+                worker: crate::current_worker_id(),
+                client_abort,
+            };
+            access_log.log_request(peer, request, &response, &served);
+        }
+        if !matches!(delivery, Delivery::Complete(_)){
+            return;
+        }
+        if let Some(trace) = &trace{
+            trace.record("write", writing, writing.elapsed());
+            if let Some(request) = slow_trace{
+                log::info(&format!("Trace of slow request {}:", request));
+                trace.tree().iter().for_each(|line| log::info(line));
+            }
+        }
+        trace::end();
+
+        // Protocol switches (WebSocket) keep the connection for themselves.
+        if let Some(upgrade) = response.upgrade{
+            upgrade.run(connection.into_inner());
+            return;
+        }
+
+        if !response.keeps_alive(){
+            return;
+        }
+
+        // Nothing more has arrived yet, so wait for it off the worker if
+        // we can, and on it otherwise.
+        if connection.buffered().is_empty(){
+            if let Some(idle) = idle{
+                let parked = IdleConnection { stream: connection.into_inner(), tracked, served };
+                idle.park(parked, config.keep_alive_timeout);
+                return;
+            }
+        }
+        if let Some(tracked) = &tracked{
+            let waiting = if connection.buffered().is_empty() { ConnectionState::Idle } else { ConnectionState::Reading };
+            tracked.set_state(waiting);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    fmt,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Tells long-running handlers the server has started shutting down, so
+/// they can wrap up instead of holding the drain up.
+///
+/// Clones share one flag. A handler finds the server's token in its
+/// `RequestContext`; the `ConnectionRegistry` sets it when draining
+/// starts.
+#[derive(Clone, Default)]
+pub struct ShutdownToken{
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl ShutdownToken{
+    pub fn new() -> ShutdownToken{
+        ShutdownToken::default()
+    }
+
+    /// Signal shutdown, waking everyone sleeping on the token.
+    pub fn shutdown(&self){
+        let (flag, wakeup) = &*self.inner;
+        *flag.lock().unwrap() = true;
+        wakeup.notify_all();
+    }
+
+    pub fn is_shutting_down(&self) -> bool{
+        *self.inner.0.lock().unwrap()
+    }
+
+    /// Sleep for `duration`, or until shutdown begins if that's sooner.
+    /// Returns `true` if the whole time passed, `false` if shutdown woke
+    /// it early (or had already begun).
+    pub fn sleep(&self, duration: Duration) -> bool{
+        let (flag, wakeup) = &*self.inner;
+        let deadline = Instant::now() + duration;
+        let mut shutting_down = flag.lock().unwrap();
+        while !*shutting_down{
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero(){
+                return true;
+            }
+            shutting_down = wakeup.wait_timeout(shutting_down, left).unwrap().0;
+        }
+        false
+    }
+}
+
+impl fmt::Debug for ShutdownToken{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        f.debug_struct("ShutdownToken").field("shutting_down", &self.is_shutting_down()).finish()
+    }
+}
+
+impl PartialEq for ShutdownToken{
+    fn eq(&self, other: &ShutdownToken) -> bool{
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for ShutdownToken {}
@@ -0,0 +1,77 @@
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static RESTART: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C"{
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+}
+#[cfg(unix)]
+const SIG_ERR: usize = usize::MAX;
+
+/// Note `SIGINT` (Ctrl-C) and `SIGTERM` instead of dying on them, so the
+/// server can shut down in its own time; poll `shutdown_requested` to
+/// find out. A second signal after the first still only sets the flag.
+pub fn catch_shutdown_signals() -> io::Result<()>{
+    #[cfg(unix)]
+    {
+        const SIGINT: i32 = 2;
+        const SIGTERM: i32 = 15;
+
+        for signum in [SIGINT, SIGTERM]{
+            if unsafe { signal(signum, on_signal) } == SIG_ERR{
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    Err(io::Error::new(io::ErrorKind::Unsupported, "signals are not supported on this platform"))
+}
+
+/// Whether a shutdown signal has arrived since `catch_shutdown_signals`.
+pub fn shutdown_requested() -> bool{
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Note `SIGUSR2`, the signal asking for a restart into a fresh copy of
+/// the binary; poll `restart_requested` to find out.
+pub fn catch_restart_signal() -> io::Result<()>{
+    #[cfg(unix)]
+    {
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        const SIGUSR2: i32 = 31;
+        #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+        const SIGUSR2: i32 = 12;
+
+        if unsafe { signal(SIGUSR2, on_restart_signal) } == SIG_ERR{
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    Err(io::Error::new(io::ErrorKind::Unsupported, "signals are not supported on this platform"))
+}
+
+/// Whether a restart signal has arrived since this was last asked;
+/// asking clears it, so each signal is acted on once.
+pub fn restart_requested() -> bool{
+    RESTART.swap(false, Ordering::SeqCst)
+}
+
+// Only async-signal-safe work is allowed here; an atomic store is.
+#[cfg(unix)]
+extern "C" fn on_signal(_: i32){
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+extern "C" fn on_restart_signal(_: i32){
+    RESTART.store(true, Ordering::SeqCst);
+}
@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+
+use crate::{
+    http::{self, Request, Response},
+    router::Router,
+    templates,
+};
+
+/// Builds `sitemap.xml` from a router's routes.
+///
+/// Only `GET` routes without `:param` or `*wildcard` segments are listed,
+/// since a pattern with captures stands for paths we can't enumerate.
+/// Pages for machines rather than search engines can be left out with
+/// `Router::no_sitemap`.
+pub struct SitemapGenerator;
+
+impl SitemapGenerator{
+    /// The sitemap for `router`, with every path prefixed by `base_url`
+    /// (`https://example.com`, say).
+    pub fn from_router(router: &Router, base_url: &str) -> String{
+        let base_url = base_url.trim_end_matches('/');
+        // A pattern can have several routes; list it once.
+        let mut seen = HashSet::new();
+        let mut paths = router.sitemap_paths();
+        paths.retain(|path| seen.insert(*path));
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+        for path in paths{
+            xml.push_str("  <url><loc>");
+            xml.push_str(&templates::escape_html(&format!("{}{}", base_url, path)));
+            xml.push_str("</loc></url>\n");
+        }
+        xml.push_str("</urlset>\n");
+        xml
+    }
+
+    /// Serve `GET /sitemap.xml` for the routes `router` has so far, so
+    /// call this once the rest are registered.
+    pub fn register(router: &mut Router, base_url: &str){
+        let xml = SitemapGenerator::from_router(router, base_url);
+        router.get("/sitemap.xml", move |_: &Request| {
+            Response::new(200, http::reason_phrase(200))
+                .with_header("Content-Type", "application/xml; charset=utf-8")
+                .with_body(xml.clone())
+        });
+        router.no_sitemap();
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{
+    collections::CircularBuffer,
+    http::{self, Request, Response, StreamBody},
+};
+
+/// One server-sent event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event{
+    pub name: Option<String>,       // The `event:` field; clients default to "message".
+    pub id: Option<String>,         // Sent back by the client as `Last-Event-ID` on reconnect.
+    pub data: String,
+    pub retry: Option<Duration>,    // How long the client should wait before reconnecting.
+}
+
+impl Event{
+    pub fn new<D: Into<String>>(data: D) -> Event{
+        Event {
+            name: None,
+            id: None,
+            data: data.into(),
+            retry: None,
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Event{
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_id(mut self, id: &str) -> Event{
+        self.id = Some(id.to_string());
+        self
+    }
+
+    pub fn with_retry(mut self, retry: Duration) -> Event{
+        self.retry = Some(retry);
+        self
+    }
+
+    /// The event in `text/event-stream` format, blank line included.
+    ///
+    /// Each line of `data` becomes its own `data:` field, so the client
+    /// reassembles it with the same line breaks. Line breaks in the name
+    /// or id would end the field early and are dropped.
+    pub fn to_wire(&self) -> String{
+        let mut out = String::new();
+        if let Some(id) = &self.id{
+            out.push_str(&format!("id: {}\n", single_line(id)));
+        }
+        if let Some(name) = &self.name{
+            out.push_str(&format!("event: {}\n", single_line(name)));
+        }
+        if let Some(retry) = self.retry{
+            out.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        for line in lines(&self.data){
+            out.push_str(&format!("data: {}\n", line));
+        }
+        out.push('\n');
+        out
+    }
+}
+
+/// A comment in `text/event-stream` format. Clients ignore comments, which
+/// makes them useful as keep-alives.
+pub fn comment_to_wire(text: &str) -> String{
+    lines(text).map(|line| format!(": {}\n", line)).collect()
+}
+
+/// Writes events straight to a connection, flushing after each one.
+///
+/// A write error means the client has gone away; once one is returned
+/// the producer should stop.
+pub struct SseWriter<W: Write>{
+    inner: W,
+}
+
+impl<W: Write> SseWriter<W>{
+    pub fn new(inner: W) -> SseWriter<W>{
+        SseWriter { inner }
+    }
+
+    pub fn send(&mut self, event: &Event) -> io::Result<()>{
+        self.write_frame(&event.to_wire())
+    }
+
+    /// Send `data` as an event of type `name`.
+    pub fn send_event(&mut self, name: &str, data: &str) -> io::Result<()>{
+        self.send(&Event::new(data).with_name(name))
+    }
+
+    pub fn send_comment(&mut self, text: &str) -> io::Result<()>{
+        self.write_frame(&comment_to_wire(text))
+    }
+
+    pub fn into_inner(self) -> W{
+        self.inner
+    }
+
+    fn write_frame(&mut self, frame: &str) -> io::Result<()>{
+        self.inner.write_all(frame.as_bytes())?;
+        self.inner.flush()
+    }
+}
+
+/// A handle for pushing events to a client from anywhere, including jobs
+/// that run later on other threads.
+///
+/// Clones share the connection. The stream stays open until every clone
+/// has been dropped. Once the client disconnects, sends fail with
+/// `BrokenPipe`; the write that discovers the disconnect happens on the
+/// connection's thread, so the send after it is the first to fail.
+#[derive(Clone)]
+pub struct SseStream{
+    frames: mpsc::Sender<String>,
+    disconnected: Arc<AtomicBool>,
+    replay: Option<Arc<ReplayBuffer>>,  // Where sent events are kept for clients that reconnect.
+    last_event_id: Option<String>,      // The client's `Last-Event-ID` when it connected.
+}
+
+impl SseStream{
+    /// Send `event`. On a stream from `replayed`, the event is also kept
+    /// in its buffer, given an id first if it has none.
+    pub fn send(&self, event: &Event) -> io::Result<()>{
+        match &self.replay{
+            Some(replay) => self.push(replay.record(event).to_wire()),
+            None => self.push(event.to_wire()),
+        }
+    }
+
+    /// Send `data` as an event of type `name`.
+    pub fn send_event(&self, name: &str, data: &str) -> io::Result<()>{
+        self.send(&Event::new(data).with_name(name))
+    }
+
+    pub fn send_comment(&self, text: &str) -> io::Result<()>{
+        self.push(comment_to_wire(text))
+    }
+
+    /// The id of the last event the client saw before reconnecting, from
+    /// its `Last-Event-ID` header; `None` on a first connection.
+    pub fn last_event_id(&self) -> Option<&str>{
+        self.last_event_id.as_deref()
+    }
+
+    /// Whether the client is known to have gone away.
+    pub fn is_closed(&self) -> bool{
+        self.disconnected.load(Ordering::SeqCst)
+    }
+
+    fn push(&self, frame: String) -> io::Result<()>{
+        if self.is_closed() || self.frames.send(frame).is_err(){
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"));
+        }
+        Ok(())
+    }
+}
+
+/// A `text/event-stream` response.
+///
+/// `start` is called with the stream once the head has been sent, and may
+/// hand it off (for instance to a scheduled job) and return; the
+/// connection is held open until the last `SseStream` clone is dropped or
+/// the client disconnects.
+pub fn response<F>(start: F) -> Response
+where
+    F: Fn(SseStream) + Send + Sync + 'static
+{
+    stream_response(None, None, start)
+}
+
+/// A `text/event-stream` response that picks up where a reconnecting
+/// client left off.
+///
+/// Events in `replay` newer than the request's `Last-Event-ID` are sent
+/// first; if that id is no longer buffered (or unknown), every buffered
+/// event is. A first connection, without the header, gets no replay.
+/// Events sent on the stream are then added to `replay`, so clients that
+/// connect later can catch up on them.
+pub fn replayed<F>(request: &Request, replay: Arc<ReplayBuffer>, start: F) -> Response
+where
+    F: Fn(SseStream) + Send + Sync + 'static
+{
+    let last_event_id = request.header("Last-Event-ID").map(str::to_string);
+    stream_response(Some(replay), last_event_id, start)
+}
+
+fn stream_response<F>(replay: Option<Arc<ReplayBuffer>>, last_event_id: Option<String>, start: F) -> Response
+where
+    F: Fn(SseStream) + Send + Sync + 'static
+{
+    let body = StreamBody::new(move |w: &mut dyn Write| {
+        let (frames, pending) = mpsc::channel();
+        let disconnected = Arc::new(AtomicBool::new(false));
+
+        // Missed events go in the queue ahead of anything `start` sends.
+        if let (Some(replay), Some(last)) = (&replay, &last_event_id){
+            for event in replay.since(last){
+                let _ = frames.send(event.to_wire());
+            }
+        }
+        start(SseStream {
+            frames,
+            disconnected: Arc::clone(&disconnected),
+            replay: replay.clone(),
+            last_event_id: last_event_id.clone(),
+        });
+
+        let mut writer = SseWriter::new(w);
+        for frame in pending{
+            if let Err(e) = writer.write_frame(&frame){
+                disconnected.store(true, Ordering::SeqCst);
+                return Err(e);
+            }
+        }
+        Ok(())
+    });
+
+    Response::new(200, http::reason_phrase(200))
+        .with_header("Content-Type", "text/event-stream")
+        .with_header("Cache-Control", "no-cache")
+        .with_stream(body)
+}
+
+/// The latest events sent on a stream, for clients that reconnect with
+/// `Last-Event-ID`; see `replayed`.
+///
+/// One buffer is shared by every connection to the same stream. Events
+/// are kept by id: one sent again with an id that's already buffered (as
+/// happens when it's broadcast to several clients) is kept once. Events
+/// without an id are numbered by the buffer. At most `depth` are kept,
+/// the oldest making room for each new one, however far behind the
+/// clients are.
+pub struct ReplayBuffer{
+    events: Mutex<CircularBuffer<Event>>,
+    next_id: AtomicU64,     // For events sent without an id.
+}
+
+impl ReplayBuffer{
+    pub fn new(depth: usize) -> ReplayBuffer{
+        ReplayBuffer {
+            events: Mutex::new(CircularBuffer::new(depth)),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Keep `event`, returning it as kept: with an id.
+    pub fn record(&self, event: &Event) -> Event{
+        let mut event = event.clone();
+        if event.id.is_none(){
+            event.id = Some(self.next_id.fetch_add(1, Ordering::Relaxed).to_string());
+        }
+        let mut events = self.events.lock().unwrap();
+        if events.iter().all(|kept| kept.id != event.id){
+            events.push(event.clone());
+        }
+        event
+    }
+
+    /// The events after the one with id `last_event_id`, oldest first; all
+    /// of them if that one isn't buffered.
+    pub fn since(&self, last_event_id: &str) -> Vec<Event>{
+        let events = self.events.lock().unwrap();
+        let start = events.iter()
+            .position(|event| event.id.as_deref() == Some(last_event_id))
+            .map_or(0, |at| at + 1);
+        events.iter().skip(start).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize{
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+}
+
+// Split on any of the line endings the event-stream format accepts.
+fn lines(text: &str) -> impl Iterator<Item = &str>{
+    text.split("\r\n").flat_map(|l| l.split(['\r', '\n']))
+}
+
+fn single_line(text: &str) -> String{
+    text.chars().filter(|c| *c != '\r' && *c != '\n').collect()
+}
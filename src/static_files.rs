@@ -0,0 +1,732 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    ops::RangeInclusive,
+    path::{Component, Path, PathBuf},
+    sync::{mpsc, Arc, RwLock},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    cache::ResponseCache,
+    embedded::{self, EmbeddedFile},
+    hash,
+    http::{self, Body, IterBody, MultiRangeResponse, RangeError, RangePart, RangeSpec, Request, Response},
+    log,
+    negotiation,
+    timeutil,
+    trace,
+    uri,
+};
+
+mod content_type;
+
+/// `Cache-Control` for fingerprinted assets, which never change under a
+/// given URL.
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Files bigger than this are streamed a chunk at a time instead of
+/// being read into memory whole.
+pub const STREAM_THRESHOLD: u64 = 256 * 1024;
+
+const STREAM_CHUNK: u64 = 64 * 1024;
+
+/// What the index knows about one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry{
+    pub modified: SystemTime,
+    pub len: u64,
+    pub etag: String,
+}
+
+impl IndexEntry{
+    fn from_metadata(metadata: &fs::Metadata) -> IndexEntry{
+        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let nanos = modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+        IndexEntry {
+            modified,
+            len: metadata.len(),
+            etag: format!("\"{:x}-{:x}\"", metadata.len(), nanos),
+        }
+    }
+}
+
+#[derive(Default)]
+struct IndexState{
+    files: HashMap<PathBuf, IndexEntry>,        // Relative path -> metadata.
+    dirs: HashMap<PathBuf, SystemTime>,         // Relative path -> mtime when last listed.
+}
+
+/// File metadata for a directory tree, kept in memory so serving a file
+/// does not need a `stat` per request.
+///
+/// `refresh` brings the index up to date: directories whose mtime moved
+/// (files added, removed or renamed) are listed again, and indexed files
+/// whose size or mtime changed get a new entry. Nothing else is touched.
+pub struct DirectoryIndex{
+    root: PathBuf,
+    state: RwLock<IndexState>,
+}
+
+impl DirectoryIndex{
+    /// Walk `root` once and index every file below it.
+    pub fn new(root: &Path) -> io::Result<DirectoryIndex>{
+        let index = DirectoryIndex {
+            root: root.to_path_buf(),
+            state: RwLock::new(IndexState::default()),
+        };
+        let mut state = IndexState::default();
+        index.scan_dir(Path::new(""), &mut state)?;
+        *index.state.write().unwrap() = state;
+        Ok(index)
+    }
+
+    pub fn root(&self) -> &Path{
+        &self.root
+    }
+
+    /// Metadata for `relative` (a path below the root), if it is an indexed file.
+    pub fn get(&self, relative: &Path) -> Option<IndexEntry>{
+        self.state.read().unwrap().files.get(relative).cloned()
+    }
+
+    pub fn len(&self) -> usize{
+        self.state.read().unwrap().files.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    /// Re-check the tree, returning how many entries changed.
+    pub fn refresh(&self) -> usize{
+        // Gather the changes without holding the lock, then apply them.
+        let (dirs, files) = {
+            let state = self.state.read().unwrap();
+            (state.dirs.clone(), state.files.keys().cloned().collect::<Vec<_>>())
+        };
+
+        let mut relisted = IndexState::default();
+        let mut stale_dirs = Vec::new();
+        for (dir, listed_mtime) in &dirs{
+            match fs::metadata(self.root.join(dir)).and_then(|m| m.modified()){
+                Ok(mtime) if mtime == *listed_mtime => {},
+                Ok(_) => {
+                    stale_dirs.push(dir.clone());
+                    if let Err(e) = self.scan_dir(dir, &mut relisted){
+                        log::warn(&format!("Could not re-index {}: {}", dir.display(), e));
+                    }
+                },
+                Err(_) => stale_dirs.push(dir.clone()),     // The directory is gone.
+            }
+        }
+
+        let mut updated = HashMap::new();
+        let mut removed = Vec::new();
+        {
+            let state = self.state.read().unwrap();
+            for file in &files{
+                if stale_dirs.iter().any(|d| file.starts_with(d)){
+                    continue;   // Covered by the fresh listing.
+                }
+                match fs::metadata(self.root.join(file)){
+                    Ok(m) if m.is_file() => {
+                        let entry = IndexEntry::from_metadata(&m);
+                        if state.files.get(file) != Some(&entry){
+                            updated.insert(file.clone(), entry);
+                        }
+                    },
+                    _ => removed.push(file.clone()),
+                }
+            }
+        }
+
+        let mut state = self.state.write().unwrap();
+        let mut changed = updated.len() + removed.len();
+
+        // Swap stale listings for fresh ones, counting only real differences.
+        let mut previous = HashMap::new();
+        for dir in &stale_dirs{
+            state.dirs.retain(|d, _| !d.starts_with(dir));
+            let under: Vec<PathBuf> = state.files.keys().filter(|f| f.starts_with(dir)).cloned().collect();
+            for file in under{
+                if let Some(entry) = state.files.remove(&file){
+                    previous.insert(file, entry);
+                }
+            }
+        }
+        changed += previous.iter().filter(|(f, e)| relisted.files.get(*f) != Some(e)).count();
+        changed += relisted.files.keys().filter(|f| !previous.contains_key(*f)).count();
+
+        for file in removed{
+            state.files.remove(&file);
+        }
+        state.files.extend(relisted.files);
+        state.dirs.extend(relisted.dirs);
+        state.files.extend(updated);
+        changed
+    }
+
+    /// Call `refresh` every `interval` on a background thread until the
+    /// returned watcher is dropped.
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> IndexWatcher{
+        let (stop, stopped) = mpsc::channel::<()>();
+        let index = Arc::clone(self);
+        let thread = thread::spawn(move || {
+            // Waiting on the channel doubles as the sleep; dropping the sender wakes us.
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval){
+                index.refresh();
+            }
+        });
+        IndexWatcher { stop: Some(stop), thread: Some(thread) }
+    }
+
+    /// Index the files directly in `dir` and recurse into subdirectories.
+    fn scan_dir(&self, dir: &Path, state: &mut IndexState) -> io::Result<()>{
+        let full = self.root.join(dir);
+        state.dirs.insert(dir.to_path_buf(), fs::metadata(&full)?.modified()?);
+
+        for entry in fs::read_dir(&full)?{
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir(){
+                self.scan_dir(&relative, state)?;
+            } else if file_type.is_file(){
+                state.files.insert(relative, IndexEntry::from_metadata(&entry.metadata()?));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Stops a `DirectoryIndex::watch` thread when dropped.
+pub struct IndexWatcher{
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for IndexWatcher{
+    fn drop(&mut self){
+        drop(self.stop.take());     // Disconnecting the channel ends the loop.
+        if let Some(thread) = self.thread.take(){
+            thread.join().unwrap();
+        }
+    }
+}
+
+/// Where a `StaticFileServer` looks for files, from `server.static_source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StaticSource{
+    #[default]
+    Disk,               // Only the root directory.
+    Embedded,           // Only the files compiled in (see `embedded`).
+    EmbeddedFallback,   // The compiled-in files, then the root directory for anything they lack.
+}
+
+impl StaticSource{
+    /// `"disk"`, `"embedded"` or `"embedded_fallback"`.
+    pub fn parse(name: &str) -> Option<StaticSource>{
+        match name{
+            "disk" => Some(StaticSource::Disk),
+            "embedded" => Some(StaticSource::Embedded),
+            "embedded_fallback" => Some(StaticSource::EmbeddedFallback),
+            _ => None,
+        }
+    }
+}
+
+/// Serves files below a root directory.
+///
+/// The request path (or the `path` capture when mounted under a route
+/// like `/static/*path`) is mapped onto the root, with `index.html`
+/// standing in for directories. Paths that try to climb out of the root are
+/// refused. Responses carry `ETag` and `Last-Modified`, and a matching
+/// `If-None-Match` gets `304 Not Modified`.
+///
+/// `Range` requests get `206 Partial Content`: one range as is, several
+/// as a `multipart/byteranges` body (see `http::MultiRangeResponse`).
+///
+/// Files under one of the immutable prefixes are sent with
+/// `Cache-Control: public, max-age=31536000, immutable`: their URLs are
+/// expected to carry a fingerprint (see `AssetFingerprints`), so a new
+/// version gets a new URL. The query string, `?v=` included, plays no
+/// part in finding the file. HTML is sent with `no-cache` so that pages
+/// pick up new fingerprints straight away.
+///
+/// Given a `ResponseCache` (the one a `CacheMiddleware` in front of it
+/// uses), `preload_cache` fills it at startup, so the first request for
+/// each small file doesn't have to touch the disk.
+///
+/// With a `StaticSource` other than `Disk`, files compiled into the
+/// binary are served too, with `ETag`s hashed at build time and no
+/// `Last-Modified`.
+///
+/// Files with one of the download extensions are sent as attachments
+/// (see `Response::as_attachment`), so browsers save them rather than
+/// show them.
+pub struct StaticFileServer{
+    root: PathBuf,
+    source: StaticSource,
+    download_extensions: Vec<String>,       // Lowercase, without the dot.
+    index: Option<Arc<DirectoryIndex>>,     // When set, metadata comes from here rather than `stat`.
+    immutable_prefixes: Vec<String>,        // Request paths such as `/assets/`.
+    cache: Option<(Arc<ResponseCache>, String)>,    // Filled by `preload_cache`, for files under the mount path.
+}
+
+impl StaticFileServer{
+    pub fn new<P: AsRef<Path>>(root: P) -> StaticFileServer{
+        StaticFileServer {
+            root: root.as_ref().to_path_buf(),
+            source: StaticSource::Disk,
+            download_extensions: Vec::new(),
+            index: None,
+            immutable_prefixes: Vec::new(),
+            cache: None,
+        }
+    }
+
+    /// Serve from `index`'s root, looking metadata up in the index.
+    ///
+    /// Files the index doesn't know about yet are treated as missing.
+    pub fn with_index(index: Arc<DirectoryIndex>) -> StaticFileServer{
+        StaticFileServer {
+            root: index.root().to_path_buf(),
+            source: StaticSource::Disk,
+            download_extensions: Vec::new(),
+            index: Some(index),
+            immutable_prefixes: Vec::new(),
+            cache: None,
+        }
+    }
+
+    /// Look for files in `source` rather than only on disk.
+    pub fn with_source(mut self, source: StaticSource) -> StaticFileServer{
+        self.source = source;
+        self
+    }
+
+    /// Send files whose extension is one of `extensions` (like `pdf` or
+    /// `.zip`, in any case) as downloads.
+    pub fn with_download_extensions(mut self, extensions: Vec<String>) -> StaticFileServer{
+        self.download_extensions = extensions.iter()
+            .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Treat request paths starting with any of `prefixes` as immutable.
+    pub fn with_immutable_prefixes(mut self, prefixes: Vec<String>) -> StaticFileServer{
+        self.immutable_prefixes = prefixes;
+        self
+    }
+
+    /// Preload `cache` with this server's files. `mount` is the request
+    /// path the root is served under, e.g. `/static/` for a route like
+    /// `/static/*path`, and becomes part of the cache keys.
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>, mount: &str) -> StaticFileServer{
+        self.cache = Some((cache, mount.to_string()));
+        self
+    }
+
+    /// Walk the root and store the response for every file smaller than
+    /// `max_file_size` bytes in the cache given to `with_cache`, as if it
+    /// had been requested with a plain `GET`. Returns how many were stored.
+    ///
+    /// Directories are descended into but symlinks are skipped, as are files
+    /// the cache wouldn't keep anyway (HTML, which is sent `no-cache`).
+    /// Without a cache there is nothing to do.
+    pub fn preload_cache(&self, max_file_size: usize) -> io::Result<usize>{
+        let (cache, mount) = match &self.cache{
+            Some((cache, mount)) => (cache, mount.trim_end_matches('/')),
+            None => return Ok(0),
+        };
+
+        let (mut files, mut bytes) = (0, 0);
+        let mut pending = vec![PathBuf::new()];
+        while let Some(dir) = pending.pop(){
+            for entry in fs::read_dir(self.root.join(&dir))?{
+                let entry = entry?;
+                let relative = dir.join(entry.file_name());
+                let file_type = entry.file_type()?;
+                if file_type.is_dir(){
+                    pending.push(relative);
+                    continue;
+                }
+                if !file_type.is_file() || entry.metadata()?.len() >= max_file_size as u64{
+                    continue;
+                }
+                let relative = match relative.to_str(){
+                    Some(relative) => relative.replace('\\', "/"),
+                    None => continue,
+                };
+
+                let mut request = Request::new("GET", &format!("{}/{}", mount, relative));
+                request.params.insert("path".to_string(), relative);
+                let response = self.handle(&request);
+                if response.status != 200{
+                    continue;
+                }
+                if let Some(ttl) = cache.ttl_for(&response){
+                    bytes += response.body.len();
+                    files += 1;
+                    cache.insert(&cache.key(&request), response, ttl);
+                }
+            }
+        }
+        log::info(&format!("Preloaded {} static files ({} bytes) into the response cache.", files, bytes));
+        Ok(files)
+    }
+
+    /// The `Cache-Control` to send for a file at request path `path`
+    /// with type `content_type`, if any.
+    pub fn cache_control_for(&self, path: &str, content_type: &str) -> Option<&'static str>{
+        if self.immutable_prefixes.iter().any(|prefix| path.starts_with(prefix.as_str())){
+            Some(IMMUTABLE)
+        } else if content_type.starts_with("text/html"){
+            Some("no-cache")
+        } else {
+            None
+        }
+    }
+
+    pub fn handle(&self, request: &Request) -> Response{
+        // Under a `*path` wildcard route the capture is the file; otherwise it's the whole path.
+        let path = request.param("path").unwrap_or(&request.path);
+        let relative = match safe_relative_path(path){
+            Some(path) => path,
+            None => return not_found(request),
+        };
+
+        if self.source != StaticSource::Disk{
+            if let Some(file) = lookup_embedded(&relative){
+                return self.embedded_response(request, file);
+            }
+            if self.source == StaticSource::Embedded{
+                return not_found(request);
+            }
+        }
+
+        let (relative, entry) = match self.lookup(&relative){
+            Some(found) => found,
+            None => return not_found(request),
+        };
+
+        let content_type = content_type_for(&relative);
+        let mut response = Response::new(200, http::reason_phrase(200))
+            .with_header("Accept-Ranges", "bytes")
+            .with_header("ETag", &entry.etag)
+            .with_header("Last-Modified", &timeutil::format_http_date(entry.modified))
+            .with_header("Content-Type", content_type);
+        response = self.as_download(response, &relative);
+        if let Some(cache_control) = self.cache_control_for(&request.path, content_type){
+            response.headers.set("Cache-Control", cache_control);
+        }
+
+        let if_none_match = request.header("If-None-Match").unwrap_or("");
+        if if_none_match.split(',').any(|tag| tag.trim() == entry.etag || tag.trim() == "*"){
+            return Response { status: 304, reason: http::reason_phrase(304).to_string(), ..response };
+        }
+
+        let mut file = match trace::span("fs_open", || File::open(self.root.join(&relative))){
+            Ok(file) => file,
+            Err(_) => return not_found(request),
+        };
+        let len = match file.metadata(){
+            Ok(metadata) => metadata.len(),
+            Err(_) => return not_found(request),
+        };
+        if len > STREAM_THRESHOLD{
+            return with_file_ranges(request, response, file, len, &entry.etag);
+        }
+        let mut contents = Vec::with_capacity(len as usize);
+        match trace::span("fs_read", || file.read_to_end(&mut contents)){
+            Ok(_) => with_ranges(request, response, contents, &entry.etag),
+            Err(_) => not_found(request),
+        }
+    }
+
+    fn embedded_response(&self, request: &Request, file: &EmbeddedFile) -> Response{
+        let mut response = Response::new(200, http::reason_phrase(200))
+            .with_header("Accept-Ranges", "bytes")
+            .with_header("ETag", file.etag)
+            .with_header("Content-Type", file.content_type);
+        response = self.as_download(response, Path::new(file.path));
+        if let Some(cache_control) = self.cache_control_for(&request.path, file.content_type){
+            response.headers.set("Cache-Control", cache_control);
+        }
+
+        let if_none_match = request.header("If-None-Match").unwrap_or("");
+        if if_none_match.split(',').any(|tag| tag.trim() == file.etag || tag.trim() == "*"){
+            return Response { status: 304, reason: http::reason_phrase(304).to_string(), ..response };
+        }
+        with_ranges(request, response, file.bytes.to_vec(), file.etag)
+    }
+
+    /// `response` as an attachment named after `file`, if it has a download extension.
+    fn as_download(&self, response: Response, file: &Path) -> Response{
+        let extension = file.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        match file.file_name().and_then(|name| name.to_str()){
+            Some(name) if self.download_extensions.contains(&extension) => response.as_attachment(name),
+            _ => response,
+        }
+    }
+
+    /// Find the file for `relative`, trying `index.html` for directories.
+    fn lookup(&self, relative: &Path) -> Option<(PathBuf, IndexEntry)>{
+        let candidates = [relative.to_path_buf(), relative.join("index.html")];
+        candidates.into_iter().find_map(|candidate| {
+            let entry = match &self.index{
+                Some(index) => index.get(&candidate)?,
+                None => {
+                    let metadata = fs::metadata(self.root.join(&candidate)).ok()?;
+                    if !metadata.is_file(){
+                        return None;
+                    }
+                    IndexEntry::from_metadata(&metadata)
+                },
+            };
+            Some((candidate, entry))
+        })
+    }
+}
+
+/// Content hashes for the files under a directory, taken once (at
+/// startup, say), for building cache-busting URLs.
+///
+/// `url_for("/assets/app.css")` gives `/assets/app.css?v=<hash>`, where
+/// the hash is the first 8 hex digits of the file's FNV-1a hash, so the
+/// URL changes exactly when the content does.
+#[derive(Debug, Clone, Default)]
+pub struct AssetFingerprints{
+    mount: String,                      // Request path prefix the directory is served under.
+    hashes: HashMap<PathBuf, String>,   // Relative path -> short hash.
+}
+
+impl AssetFingerprints{
+    /// Hash every file below `root`, which is served under the request
+    /// path prefix `mount` (e.g. `/assets/`).
+    pub fn scan<P: AsRef<Path>>(root: P, mount: &str) -> io::Result<AssetFingerprints>{
+        let root = root.as_ref();
+        let mut hashes = HashMap::new();
+        let mut pending = vec![PathBuf::new()];
+        while let Some(dir) = pending.pop(){
+            for entry in fs::read_dir(root.join(&dir))?{
+                let entry = entry?;
+                let relative = dir.join(entry.file_name());
+                if entry.file_type()?.is_dir(){
+                    pending.push(relative);
+                } else {
+                    let contents = fs::read(entry.path())?;
+                    hashes.insert(relative, fingerprint(&contents));
+                }
+            }
+        }
+        Ok(AssetFingerprints { mount: mount.to_string(), hashes })
+    }
+
+    /// The short hash of the file at `relative` below the root.
+    pub fn get<P: AsRef<Path>>(&self, relative: P) -> Option<&str>{
+        self.hashes.get(relative.as_ref()).map(String::as_str)
+    }
+
+    /// `path` with `?v=<hash>` added, or unchanged if it isn't a known
+    /// file under the mount.
+    pub fn url_for(&self, path: &str) -> String{
+        let hash = path.strip_prefix(self.mount.as_str())
+            .and_then(safe_relative_path)
+            .and_then(|relative| self.get(relative));
+        match hash{
+            Some(hash) => format!("{}?v={}", path, hash),
+            None => path.to_string(),
+        }
+    }
+
+    pub fn len(&self) -> usize{
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.hashes.is_empty()
+    }
+}
+
+/// The short content hash used in fingerprinted URLs.
+pub fn fingerprint(contents: &[u8]) -> String{
+    format!("{:016x}", hash::fnv1a_64(contents))[..8].to_string()
+}
+
+/// Turn a URL path into a relative filesystem path, refusing anything
+/// that could escape the root. The path is normalized the way the server
+/// normalizes request paths, so dot segments are resolved (or refused)
+/// and backslashes and NULs turned away there, in one place; what's left
+/// must be plain file names, which rules out a Windows drive prefix.
+fn safe_relative_path(path: &str) -> Option<PathBuf>{
+    let normalized = uri::normalize_path(&format!("/{}", path.trim_start_matches('/')), false).ok()?;
+    let relative = PathBuf::from(normalized.trim_start_matches('/'));
+    relative.components().all(|c| matches!(c, Component::Normal(_))).then_some(relative)
+}
+
+/// The embedded file for `relative`, trying `index.html` for directories.
+fn lookup_embedded(relative: &Path) -> Option<&'static EmbeddedFile>{
+    let mut parts = Vec::new();
+    for component in relative.components(){
+        if let Component::Normal(part) = component{
+            parts.push(part.to_str()?);
+        }
+    }
+    let path = parts.join("/");
+    embedded::get(&path).or_else(|| {
+        let index = if path.is_empty() { "index.html".to_string() } else { format!("{}/index.html", path) };
+        embedded::get(&index)
+    })
+}
+
+/// Guess a `Content-Type` from the file extension.
+pub fn content_type_for(path: &Path) -> &'static str{
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    content_type::for_extension(&extension.to_ascii_lowercase())
+}
+
+/// `response` with `contents` as its body, or just the parts the
+/// request's `Range` header asks for: one range gets a plain `206`,
+/// several get `multipart/byteranges`. An `If-Range` that no longer
+/// matches the file's `ETag` or `Last-Modified` means the whole file.
+fn with_ranges(request: &Request, response: Response, contents: Vec<u8>, etag: &str) -> Response{
+    match requested_ranges(request, &response, etag, contents.len() as u64){
+        Ranges::Whole => response.with_body(contents),
+        Ranges::Unsatisfiable => unsatisfiable(request, contents.len() as u64),
+        Ranges::Parts(ranges) => {
+            let parts = ranges.iter().map(|range| RangePart::slice(&contents, range)).collect();
+            partial(response, parts)
+        },
+    }
+}
+
+/// `with_ranges` for a file too big to read whole: the file, or a single
+/// range of it, is streamed a chunk at a time. Several ranges are read
+/// into memory, as `multipart/byteranges` needs them.
+fn with_file_ranges(request: &Request, response: Response, mut file: File, len: u64, etag: &str) -> Response{
+    match requested_ranges(request, &response, etag, len){
+        Ranges::Whole => {
+            let mut response = response;
+            response.stream = Some(Body::Iter(IterBody::new(FileChunks { file, left: len })));
+            response
+        },
+        Ranges::Unsatisfiable => unsatisfiable(request, len),
+        Ranges::Parts(ranges) if ranges.len() == 1 => {
+            let (first, last) = (*ranges[0].start(), *ranges[0].end());
+            if file.seek(SeekFrom::Start(first)).is_err(){
+                return not_found(request);
+            }
+            let content_type = response.header("Content-Type").unwrap_or("application/octet-stream").to_string();
+            let mut partial = Response::new(206, http::reason_phrase(206))
+                .with_header("Content-Type", &content_type)
+                .with_header("Content-Range", &format!("bytes {}-{}/{}", first, last, len));
+            partial.stream = Some(Body::Iter(IterBody::new(FileChunks { file, left: last - first + 1 })));
+            copy_headers(&response, &mut partial);
+            partial
+        },
+        Ranges::Parts(ranges) => {
+            let mut parts = Vec::with_capacity(ranges.len());
+            for range in &ranges{
+                let (first, last) = (*range.start(), *range.end());
+                let mut data = vec![0; (last - first + 1) as usize];
+                if file.seek(SeekFrom::Start(first)).and_then(|_| file.read_exact(&mut data)).is_err(){
+                    return not_found(request);
+                }
+                parts.push(RangePart { first, last, complete_length: len, data });
+            }
+            partial(response, parts)
+        },
+    }
+}
+
+// What a request's `Range` header comes to for a representation of `len` bytes.
+enum Ranges{
+    Whole,      // No `Range`, one we don't understand, or an `If-Range` that no longer matches.
+    Unsatisfiable,
+    Parts(Vec<RangeInclusive<u64>>),
+}
+
+fn requested_ranges(request: &Request, response: &Response, etag: &str, len: u64) -> Ranges{
+    let range = match request.header("Range"){
+        Some(range) if request.method == "GET" || request.method == "HEAD" => range,
+        _ => return Ranges::Whole,
+    };
+    if let Some(if_range) = request.header("If-Range"){
+        let if_range = if_range.trim();
+        if if_range != etag && Some(if_range) != response.header("Last-Modified"){
+            return Ranges::Whole;
+        }
+    }
+    match http::parse_ranges(range, len){
+        Err(RangeError::Invalid) => Ranges::Whole,
+        Err(RangeError::Unsatisfiable) => Ranges::Unsatisfiable,
+        Ok(ranges) => Ranges::Parts(ranges),
+    }
+}
+
+fn unsatisfiable(request: &Request, len: u64) -> Response{
+    negotiation::status_page(request, 416, http::reason_phrase(416))
+        .with_header("Content-Range", &RangeSpec::unsatisfied_content_range(len))
+}
+
+// A `206` for `parts`, with `response`'s headers but its own type.
+fn partial(response: Response, mut parts: Vec<RangePart>) -> Response{
+    let content_type = response.header("Content-Type").unwrap_or("application/octet-stream").to_string();
+    let mut partial = match parts.len(){
+        1 => parts.remove(0).into_response(&content_type),
+        _ => MultiRangeResponse::new(&content_type, parts).into_response(),
+    };
+    copy_headers(&response, &mut partial);
+    partial
+}
+
+fn copy_headers(from: &Response, to: &mut Response){
+    for (name, value) in from.headers.iter(){
+        if !name.eq_ignore_ascii_case("Content-Type"){
+            to.headers.set(name, value);
+        }
+    }
+}
+
+// `left` more bytes of `file`, from where it's positioned, a chunk at a
+// time. A read error or an early end just ends the body there.
+struct FileChunks{
+    file: File,
+    left: u64,
+}
+
+impl Iterator for FileChunks{
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>>{
+        if self.left == 0{
+            return None;
+        }
+        let mut chunk = vec![0; STREAM_CHUNK.min(self.left) as usize];
+        let mut filled = 0;
+        while filled < chunk.len(){
+            match self.file.read(&mut chunk[filled..]){
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+        if filled == 0{
+            self.left = 0;
+            return None;
+        }
+        chunk.truncate(filled);
+        self.left = if filled < chunk.capacity() { 0 } else { self.left - filled as u64 };
+        Some(chunk)
+    }
+}
+
+fn not_found(request: &Request) -> Response{
+    negotiation::status_page(request, 404, "Not Found")
+}
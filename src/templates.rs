@@ -0,0 +1,90 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::log;
+
+/// Fill in `template`'s variables from `vars`.
+///
+/// `{{name}}` inserts the value HTML-escaped and `{{{name}}}` inserts it
+/// as it is; spaces around the name are ignored. A variable that isn't
+/// in `vars` renders as nothing, with a warning logged. `\{{` stands for
+/// a literal `{{`, and braces that don't form a tag are left alone.
+pub fn render(template: &str, vars: &HashMap<&str, String>) -> String{
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{"){
+        if rest[..start].ends_with('\\'){
+            out.push_str(&rest[..start - 1]);
+            out.push_str("{{");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        let (raw, open, close) = if tag.starts_with("{{{") { (true, 3, "}}}") } else { (false, 2, "}}") };
+
+        let name = tag[open..].find(close).map(|end| (&tag[open..open + end], open + end + close.len()));
+        match name{
+            Some((name, len)) if !name.contains(['{', '}']) => {
+                let name = name.trim();
+                match vars.get(name){
+                    Some(value) if raw => out.push_str(value),
+                    Some(value) => out.push_str(&escape_html(value)),
+                    None => log::warn(&format!("Template variable `{}` is not set.", name)),
+                }
+                rest = &tag[len..];
+            },
+            // Not a tag after all; keep the braces and carry on after them.
+            _ => {
+                out.push_str("{{");
+                rest = &tag[2..];
+            },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Escape text for use in HTML content or a quoted attribute.
+pub fn escape_html(text: &str) -> String{
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars(){
+        match c{
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// `path` -> (modification time when read, contents).
+type TemplateCache = Mutex<HashMap<PathBuf, (Option<SystemTime>, Arc<str>)>>;
+
+fn cache() -> &'static TemplateCache{
+    static CACHE: OnceLock<TemplateCache> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The template at `path`, read once and kept until the file's
+/// modification time changes.
+pub fn load(path: &Path) -> io::Result<Arc<str>>{
+    let modified = fs::metadata(path)?.modified().ok();
+    if let Some((cached_at, contents)) = cache().lock().unwrap().get(path){
+        if modified.is_some() && *cached_at == modified{
+            return Ok(Arc::clone(contents));
+        }
+    }
+
+    let contents: Arc<str> = fs::read_to_string(path)?.into();
+    cache().lock().unwrap().insert(path.to_path_buf(), (modified, Arc::clone(&contents)));
+    Ok(contents)
+}
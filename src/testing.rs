@@ -0,0 +1,199 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{encoding, http::Request, server::ReadTimeout, PoolLike};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A `PoolLike` that queues jobs instead of running them.
+///
+/// Tests submit work as usual, check how much was queued with `pending`,
+/// and run it on the test's own thread with `drain_pending`, so nothing
+/// depends on thread scheduling or timing.
+#[derive(Default)]
+pub struct MockThreadPool{
+    jobs: Mutex<Vec<Job>>,
+    executed: Mutex<usize>,     // Jobs run so far by `drain_pending`.
+}
+
+impl MockThreadPool{
+    pub fn new() -> MockThreadPool{
+        MockThreadPool::default()
+    }
+
+    /// Number of jobs submitted but not run yet.
+    pub fn pending(&self) -> usize{
+        self.jobs.lock().unwrap().len()
+    }
+
+    /// Number of jobs run so far.
+    pub fn executed(&self) -> usize{
+        *self.executed.lock().unwrap()
+    }
+
+    /// Run every queued job in submission order, including jobs those jobs
+    /// submit, and return how many ran.
+    pub fn drain_pending(&self) -> usize{
+        let mut ran = 0;
+        loop{
+            // Take the queue first so jobs can submit more without deadlocking.
+            let jobs = std::mem::take(&mut *self.jobs.lock().unwrap());
+            if jobs.is_empty(){
+                break;
+            }
+            for job in jobs{
+                job();
+                ran += 1;
+            }
+        }
+        *self.executed.lock().unwrap() += ran;
+        ran
+    }
+}
+
+impl PoolLike for MockThreadPool{
+    fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static
+    {
+        self.jobs.lock().unwrap().push(Box::new(f));
+    }
+}
+
+/// An in-memory connection for driving server code in tests.
+///
+/// Reads return the scripted chunks one at a time, so a test can send a
+/// request in pieces, and then end of stream. Everything written is kept
+/// for inspection. `fail_writes_after` and `fail_after_bytes` simulate a
+/// client that hangs up. Read timeouts aren't enforced, only recorded.
+#[derive(Debug, Default)]
+pub struct MockStream{
+    input: VecDeque<Vec<u8>>,
+    written: Vec<u8>,
+    writes: usize,
+    write_limit: Option<usize>,
+    byte_limit: Option<usize>,
+    read_timeouts: Vec<Option<Duration>>,
+}
+
+impl MockStream{
+    pub fn new<I, C>(chunks: I) -> MockStream
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<Vec<u8>>
+    {
+        MockStream {
+            input: chunks.into_iter().map(Into::into).collect(),
+            ..MockStream::default()
+        }
+    }
+
+    /// Fail every write after the first `n` with `BrokenPipe`.
+    pub fn fail_writes_after(mut self, n: usize) -> MockStream{
+        self.write_limit = Some(n);
+        self
+    }
+
+    /// Take the first `n` bytes written, then fail every write with
+    /// `BrokenPipe`; the write that reaches the limit is cut short.
+    pub fn fail_after_bytes(mut self, n: usize) -> MockStream{
+        self.byte_limit = Some(n);
+        self
+    }
+
+    pub fn written(&self) -> &[u8]{
+        &self.written
+    }
+
+    /// Every read timeout set on the stream, in order.
+    pub fn read_timeouts(&self) -> &[Option<Duration>]{
+        &self.read_timeouts
+    }
+}
+
+impl ReadTimeout for MockStream{
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>{
+        self.read_timeouts.push(timeout);
+        Ok(())
+    }
+}
+
+impl Read for MockStream{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>{
+        let chunk = match self.input.front_mut(){
+            Some(chunk) => chunk,
+            None => return Ok(0),
+        };
+        let n = chunk.len().min(buf.len());
+        buf[..n].copy_from_slice(&chunk[..n]);
+        chunk.drain(..n);
+        if chunk.is_empty(){
+            self.input.pop_front();
+        }
+        Ok(n)
+    }
+}
+
+impl Write for MockStream{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        if self.write_limit.is_some_and(|limit| self.writes >= limit){
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "mock stream closed"));
+        }
+        let room = self.byte_limit.map_or(buf.len(), |limit| limit.saturating_sub(self.written.len()));
+        if room == 0 && !buf.is_empty(){
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "mock stream closed"));
+        }
+        let n = buf.len().min(room);
+        self.writes += 1;
+        self.written.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+        Ok(())
+    }
+}
+
+/// Builds an `application/x-www-form-urlencoded` body, so tests needn't
+/// write `b"name=Alice&age=30"` and its escapes by hand.
+///
+/// Fields are kept in the order they're added, repeats included, with
+/// names and values encoded by `encoding::form_urlencode`.
+#[derive(Debug, Clone, Default)]
+pub struct FormBuilder{
+    fields: Vec<(String, String)>,
+}
+
+impl FormBuilder{
+    pub fn new() -> FormBuilder{
+        FormBuilder::default()
+    }
+
+    pub fn field(mut self, name: &str, value: &str) -> FormBuilder{
+        self.fields.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The encoded body: each field as `name=value`, joined with `&`.
+    pub fn build(&self) -> Vec<u8>{
+        let fields: Vec<String> = self.fields.iter()
+            .map(|(name, value)| format!("{}={}", encoding::form_urlencode(name), encoding::form_urlencode(value)))
+            .collect();
+        fields.join("&").into_bytes()
+    }
+
+    /// A `POST` to `target` with the body and its `Content-Type` and
+    /// `Content-Length`.
+    pub fn into_request(self, target: &str) -> Request{
+        let body = self.build();
+        let mut request = Request::new("POST", target);
+        request.headers.set("Content-Type", "application/x-www-form-urlencoded");
+        request.headers.set("Content-Length", &body.len().to_string());
+        request.body = body;
+        request
+    }
+}
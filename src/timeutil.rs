@@ -0,0 +1,236 @@
+// Calendar arithmetic for `SystemTime`, which std leaves as a bare count
+// of seconds: the HTTP-date, RFC 3339, cookie and Common Log Format
+// dates, all UTC.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const LONG_WEEKDAYS: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// A moment split into its UTC calendar date and time of day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CivilTime{
+    pub year: i64,
+    pub month: u32,     // 1 to 12.
+    pub day: u32,       // Of the month, from 1.
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub nanos: u32,     // Past the second.
+    pub weekday: u32,   // 0 for Sunday to 6 for Saturday.
+}
+
+impl CivilTime{
+    /// The calendar fields of `time`, which may be before the epoch.
+    pub fn from_system_time(time: SystemTime) -> CivilTime{
+        let (secs, nanos) = match time.duration_since(UNIX_EPOCH){
+            Ok(since) => (since.as_secs() as i64, since.subsec_nanos()),
+            Err(e) => {
+                let before = e.duration();
+                match before.subsec_nanos(){
+                    0 => (-(before.as_secs() as i64), 0),
+                    nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+                }
+            },
+        };
+        let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400) as u32);
+        let (year, month, day) = civil_from_days(days);
+        CivilTime {
+            year,
+            month,
+            day,
+            hour: rem / 3600,
+            minute: rem % 3600 / 60,
+            second: rem % 60,
+            nanos,
+            weekday: (days + 4).rem_euclid(7) as u32,   // 1970-01-01 was a Thursday.
+        }
+    }
+
+    /// The moment these fields name, or `None` if they aren't a real date
+    /// and time. `weekday` is ignored, and a leap second (`:60`) is taken
+    /// as the first second of the next minute.
+    pub fn to_system_time(&self) -> Option<SystemTime>{
+        let valid = (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second <= 60
+            && self.nanos < 1_000_000_000;
+        if !valid{
+            return None;
+        }
+        let secs = days_from_civil(self.year, self.month, self.day)
+            .checked_mul(86_400)?
+            .checked_add(i64::from(self.hour * 3600 + self.minute * 60 + self.second))?;
+        let time = if secs >= 0{
+            UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))?
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+        };
+        time.checked_add(Duration::from_nanos(u64::from(self.nanos)))
+    }
+}
+
+/// `time` as an HTTP-date in the preferred IMF-fixdate form, e.g.
+/// `Sun, 06 Nov 1994 08:49:37 GMT` (RFC 7231, section 7.1.1.1).
+pub fn format_http_date(time: SystemTime) -> String{
+    let t = CivilTime::from_system_time(time);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[t.weekday as usize], t.day, MONTHS[t.month as usize - 1], t.year, t.hour, t.minute, t.second,
+    )
+}
+
+/// `time` for a cookie's `Expires` attribute. RFC 6265 asks for the same
+/// IMF-fixdate as HTTP, which every browser reads.
+pub fn format_cookie_expires(time: SystemTime) -> String{
+    format_http_date(time)
+}
+
+/// `time` as an RFC 3339 UTC timestamp with milliseconds, such as
+/// `1994-11-06T08:49:37.000Z`.
+pub fn format_rfc3339(time: SystemTime) -> String{
+    let t = CivilTime::from_system_time(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        t.year, t.month, t.day, t.hour, t.minute, t.second, t.nanos / 1_000_000,
+    )
+}
+
+/// `time` as the Common Log Format has it, e.g.
+/// `10/Oct/2000:13:55:36 +0000`.
+pub fn format_clf_date(time: SystemTime) -> String{
+    let t = CivilTime::from_system_time(time);
+    format!(
+        "{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000",
+        t.day, MONTHS[t.month as usize - 1], t.year, t.hour, t.minute, t.second,
+    )
+}
+
+/// Parse an HTTP-date in any of the three forms RFC 7231 (section
+/// 7.1.1.1) has recipients accept: IMF-fixdate
+/// (`Sun, 06 Nov 1994 08:49:37 GMT`), the obsolete RFC 850 form
+/// (`Sunday, 06-Nov-94 08:49:37 GMT`) and asctime
+/// (`Sun Nov  6 08:49:37 1994`). An RFC 850 two-digit year more than 50
+/// years ahead is taken to be in the last century. The weekday must be a
+/// real one but isn't checked against the date.
+pub fn parse_http_date(text: &str) -> Option<SystemTime>{
+    let text = text.trim();
+    let (weekday, rest) = match text.split_once(", "){
+        Some(parts) => parts,
+        None => return parse_asctime(text),
+    };
+    let fields: Vec<&str> = rest.split(' ').collect();
+    let time = match (WEEKDAYS.contains(&weekday), LONG_WEEKDAYS.contains(&weekday), fields.as_slice()){
+        (true, _, [day, month, year, clock, "GMT"]) if day.len() == 2 && year.len() == 4 => {
+            civil(number(year)?, month_number(month)?, number(day)?, clock)?
+        },
+        (_, true, [date, clock, "GMT"]) => {
+            let mut parts = date.split('-');
+            let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+            if parts.next().is_some() || day.len() != 2 || year.len() != 2{
+                return None;
+            }
+            civil(two_digit_year(number(year)?), month_number(month)?, number(day)?, clock)?
+        },
+        _ => return None,
+    };
+    time.to_system_time()
+}
+
+// `Sun Nov  6 08:49:37 1994`, the day space-padded to two characters.
+fn parse_asctime(text: &str) -> Option<SystemTime>{
+    if text.len() != 24 || !WEEKDAYS.contains(&text.get(..3)?) || text.get(3..4)? != " " || text.get(7..8)? != " "{
+        return None;
+    }
+    let day = text.get(8..10)?;
+    let day = day.strip_prefix(' ').unwrap_or(day);
+    match (text.get(10..11)?, text.get(19..20)?){
+        (" ", " ") => {},
+        _ => return None,
+    }
+    civil(number(text.get(20..)?)?, month_number(text.get(4..7)?)?, number(day)?, text.get(11..19)?)?.to_system_time()
+}
+
+// The fields of a date with an `HH:MM:SS` time, unchecked.
+fn civil(year: i64, month: u32, day: i64, clock: &str) -> Option<CivilTime>{
+    let mut parts = clock.split(':');
+    let mut part = || parts.next().filter(|part| part.len() == 2).and_then(number);
+    let (hour, minute, second) = (part()?, part()?, part()?);
+    if clock.len() != 8{
+        return None;
+    }
+    Some(CivilTime {
+        year,
+        month,
+        day: u32::try_from(day).ok()?,
+        hour: u32::try_from(hour).ok()?,
+        minute: u32::try_from(minute).ok()?,
+        second: u32::try_from(second).ok()?,
+        nanos: 0,
+        weekday: 0,
+    })
+}
+
+fn number(digits: &str) -> Option<i64>{
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()){
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn month_number(name: &str) -> Option<u32>{
+    MONTHS.iter().position(|month| *month == name).map(|i| i as u32 + 1)
+}
+
+// RFC 7231: a two-digit year that looks more than 50 years in the future
+// is the most recent past year ending in those digits.
+fn two_digit_year(yy: i64) -> i64{
+    let this_year = CivilTime::from_system_time(SystemTime::now()).year;
+    let mut year = this_year - this_year.rem_euclid(100) + yy;
+    if year > this_year + 50{
+        year -= 100;
+    }
+    year
+}
+
+fn is_leap_year(year: i64) -> bool{
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32{
+    match month{
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Year, month (1-12) and day of the month for a count of days since
+// 1970-01-01: Howard Hinnant's civil-from-days, with eras of 400 years.
+fn civil_from_days(days: i64) -> (i64, u32, u32){
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
+// The inverse: days since 1970-01-01 for a valid date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64{
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
@@ -0,0 +1,225 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::http::Request;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local!{
+    static CURRENT: RefCell<Option<Rc<Trace>>> = const { RefCell::new(None) };
+}
+
+/// One timed stretch of a request, such as reading a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span{
+    pub name: &'static str,
+    pub depth: usize,           // 0 for a top-level span; one more for each span it's inside.
+    pub start: Duration,        // From the start of the trace's first span.
+    pub duration: Duration,     // So far, for a span not yet exited.
+}
+
+/// Marks a span from `Trace::enter`, to hand back to `Trace::exit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanId(Option<usize>);
+
+/// The spans recorded while serving one request.
+///
+/// Spans nest: one entered while another is open is recorded inside it.
+/// A disabled trace records nothing, and its methods do no more than
+/// check that they have nothing to do.
+pub struct Trace{
+    spans: Option<RefCell<Vec<(Span, bool)>>>,  // With whether each has exited; `None` when disabled.
+    origin: Cell<Option<Instant>>,      // When the first span started.
+    open: Cell<usize>,                  // Spans entered but not yet exited.
+}
+
+impl Trace{
+    pub fn new() -> Trace{
+        Trace { spans: Some(RefCell::new(Vec::new())), origin: Cell::new(None), open: Cell::new(0) }
+    }
+
+    pub fn disabled() -> Trace{
+        Trace { spans: None, origin: Cell::new(None), open: Cell::new(0) }
+    }
+
+    pub fn is_enabled(&self) -> bool{
+        self.spans.is_some()
+    }
+
+    /// Start a span called `name`; it lasts until `exit` is given the id.
+    pub fn enter(&self, name: &'static str) -> SpanId{
+        self.start_span(name, Instant::now())
+    }
+
+    /// End the span `id` started.
+    pub fn exit(&self, id: SpanId){
+        if let (Some(spans), SpanId(Some(index)), Some(origin)) = (&self.spans, id, self.origin.get()){
+            let (span, exited) = &mut spans.borrow_mut()[index];
+            if !*exited{
+                span.duration = origin.elapsed().saturating_sub(span.start);
+                *exited = true;
+                self.open.set(self.open.get().saturating_sub(1));
+            }
+        }
+    }
+
+    /// Run `f` inside a span called `name`. The span ends when `f` does,
+    /// even by panicking.
+    pub fn span<T, F>(&self, name: &'static str, f: F) -> T
+    where
+        F: FnOnce() -> T
+    {
+        struct Exit<'a>(&'a Trace, SpanId);
+
+        impl Drop for Exit<'_>{
+            fn drop(&mut self){
+                self.0.exit(self.1);
+            }
+        }
+
+        let _exit = Exit(self, self.enter(name));
+        f()
+    }
+
+    /// Record a span measured elsewhere, which started at `started` and
+    /// took `duration`.
+    pub fn record(&self, name: &'static str, started: Instant, duration: Duration){
+        if let (Some(spans), SpanId(Some(index))) = (&self.spans, self.start_span(name, started)){
+            let (span, exited) = &mut spans.borrow_mut()[index];
+            span.duration = duration;
+            *exited = true;
+            self.open.set(self.open.get().saturating_sub(1));
+        }
+    }
+
+    /// Every span recorded so far, in the order they started.
+    pub fn spans(&self) -> Vec<Span>{
+        let origin = match self.origin.get(){
+            Some(origin) => origin,
+            None => return Vec::new(),
+        };
+        let open_for = origin.elapsed();
+        self.spans.iter().flat_map(|spans| spans.borrow().clone()).map(|(mut span, exited)| {
+            if !exited{
+                span.duration = open_for.saturating_sub(span.start);
+            }
+            span
+        }).collect()
+    }
+
+    /// A `Server-Timing` header value for the top-level spans, like
+    /// `parse;dur=0.052, handler;dur=3.100`, or `None` if there are none.
+    pub fn server_timing(&self) -> Option<String>{
+        let entries: Vec<String> = self.spans().iter()
+            .filter(|span| span.depth == 0)
+            .map(|span| format!("{};dur={:.3}", timing_name(span.name), millis(span.duration)))
+            .collect();
+        if entries.is_empty() { None } else { Some(entries.join(", ")) }
+    }
+
+    /// The spans as an indented tree, one line each, like
+    /// `  fs_read 1.250ms at +0.310ms`.
+    pub fn tree(&self) -> Vec<String>{
+        self.spans().iter().map(|span| {
+            format!(
+                "{}{} {:.3}ms at +{:.3}ms",
+                "  ".repeat(span.depth + 1), span.name, millis(span.duration), millis(span.start),
+            )
+        }).collect()
+    }
+
+    fn start_span(&self, name: &'static str, started: Instant) -> SpanId{
+        let spans = match &self.spans{
+            Some(spans) => spans,
+            None => return SpanId(None),
+        };
+        let origin = self.origin.get().unwrap_or(started);
+        self.origin.set(Some(origin));
+        let mut spans = spans.borrow_mut();
+        spans.push((Span {
+            name,
+            depth: self.open.get(),
+            start: started.saturating_duration_since(origin),
+            duration: Duration::ZERO,
+        }, false));
+        self.open.set(self.open.get() + 1);
+        SpanId(Some(spans.len() - 1))
+    }
+}
+
+impl Default for Trace{
+    fn default() -> Trace{
+        Trace::new()
+    }
+}
+
+/// Turn request tracing on or off for the whole process; off until
+/// `server.trace_requests` turns it on.
+pub fn set_enabled(enabled: bool){
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool{
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Start a trace for the request this thread is about to serve, if
+/// tracing is on; `span` and `record` add to it until `end`.
+pub fn begin() -> Option<Rc<Trace>>{
+    if !is_enabled(){
+        return None;
+    }
+    let trace = Rc::new(Trace::new());
+    CURRENT.with(|current| *current.borrow_mut() = Some(Rc::clone(&trace)));
+    Some(trace)
+}
+
+/// Stop adding spans to this thread's trace.
+pub fn end(){
+    CURRENT.with(|current| current.borrow_mut().take());
+}
+
+/// The trace of the request this thread is serving, if it has one.
+pub fn current() -> Option<Rc<Trace>>{
+    if !is_enabled(){
+        return None;
+    }
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Run `f` inside a span of the current trace; with no trace, just run it.
+pub fn span<T, F>(name: &'static str, f: F) -> T
+where
+    F: FnOnce() -> T
+{
+    match current(){
+        Some(trace) => trace.span(name, f),
+        None => f(),
+    }
+}
+
+/// Whether `request` asks for its spans back, with `trace=1` in its query.
+pub fn requested(request: &Request) -> bool{
+    request.query.as_deref().is_some_and(|query| query.split('&').any(|pair| pair == "trace=1"))
+}
+
+/// Add a span measured elsewhere to the current trace, if there is one.
+pub fn record(name: &'static str, started: Instant, duration: Duration){
+    if let Some(trace) = current(){
+        trace.record(name, started, duration);
+    }
+}
+
+fn millis(duration: Duration) -> f64{
+    duration.as_secs_f64() * 1000.0
+}
+
+// Span names are meant to be tokens already; anything else would break
+// the header apart.
+fn timing_name(name: &str) -> String{
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' }).collect()
+}
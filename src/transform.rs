@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use crate::{
+    http::{Request, Response},
+    router::{Middleware, Next},
+};
+
+type Transform = Arc<dyn Fn(Vec<u8>) -> Vec<u8> + Send + Sync>;
+
+/// Rewrites the body of every HTML response with a function, to inject
+/// an analytics snippet or a banner without touching the handlers.
+///
+/// Only buffered `text/html` bodies are passed to the function: streamed
+/// and upgraded responses, `204`, `206` and `304`, and bodies that
+/// already have a `Content-Encoding` go through as they are. So register
+/// it after `CompressionMiddleware`, which then compresses what it
+/// returns.
+///
+/// A transformed response's `Content-Length`, if the handler set one,
+/// is made to match, and its `ETag` is dropped, since it named the body
+/// before the change.
+#[derive(Clone)]
+pub struct ResponseBodyTransformer{
+    transform: Transform,
+}
+
+impl ResponseBodyTransformer{
+    pub fn new<F>(transform: F) -> ResponseBodyTransformer
+    where
+        F: Fn(Vec<u8>) -> Vec<u8> + Send + Sync + 'static
+    {
+        ResponseBodyTransformer { transform: Arc::new(transform) }
+    }
+
+    /// One that puts `snippet` just before the last `</body>` of each
+    /// page, whatever its case, or at the end of pages without one.
+    pub fn insert_before_body_end(snippet: &str) -> ResponseBodyTransformer{
+        let snippet = snippet.as_bytes().to_vec();
+        ResponseBodyTransformer::new(move |mut body| {
+            let at = body.windows(7).rposition(|w| w.eq_ignore_ascii_case(b"</body>")).unwrap_or(body.len());
+            body.splice(at..at, snippet.iter().copied());
+            body
+        })
+    }
+}
+
+impl Middleware for ResponseBodyTransformer{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        let mut response = next.run(request);
+        let skip = !is_html(response.header("Content-Type").unwrap_or(""))
+            || response.stream.is_some()
+            || response.upgrade.is_some()
+            || response.headers.contains("Content-Encoding")
+            || matches!(response.status, 204 | 206 | 304);
+        if skip{
+            return response;
+        }
+        response.body = (self.transform)(std::mem::take(&mut response.body));
+        if response.headers.contains("Content-Length"){
+            response.headers.set("Content-Length", &response.body.len().to_string());
+        }
+        response.headers.remove("ETag");
+        response
+    }
+}
+
+fn is_html(content_type: &str) -> bool{
+    content_type.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("text/html")
+}
@@ -0,0 +1,102 @@
+// Protocol switches requested with `Connection: Upgrade`, for protocols
+// other than WebSocket (which has `Router::websocket`), such as `h2c`.
+use std::{
+    io,
+    net::TcpStream,
+    sync::Arc,
+};
+
+use crate::{
+    http::{self, Request, Response, Upgrade},
+    log,
+    router::{Middleware, Next},
+};
+
+type UpgradeHandler = Arc<dyn Fn(TcpStream, Request) -> io::Result<()> + Send + Sync>;
+
+/// The protocols a connection may be upgraded to, and what takes the
+/// connection over for each.
+///
+/// Added to a router as middleware. A request with `Connection: Upgrade`
+/// naming a registered protocol in `Upgrade` is answered with
+/// `101 Switching Protocols`, and the raw stream is then handed to that
+/// protocol's handler along with the request. When the client offers
+/// several, the first of them that is registered wins.
+///
+/// A request for only unknown protocols still goes down the chain, so a
+/// WebSocket route keeps working. If nothing there switches protocols or
+/// refuses the request, the answer is `426 Upgrade Required` listing what
+/// is supported.
+#[derive(Clone, Default)]
+pub struct UpgradeRegistry{
+    handlers: Vec<(String, UpgradeHandler)>,    // In registration order, for the `426`'s list.
+}
+
+impl UpgradeRegistry{
+    pub fn new() -> UpgradeRegistry{
+        UpgradeRegistry::default()
+    }
+
+    /// Take over connections upgraded to `protocol` (matched ignoring
+    /// case) with `handler`, replacing any handler it had. An error from
+    /// the handler is logged; the connection is closed either way once
+    /// the handler returns.
+    pub fn register<F>(&mut self, protocol: &str, handler: F) -> &mut UpgradeRegistry
+    where
+        F: Fn(TcpStream, Request) -> io::Result<()> + Send + Sync + 'static
+    {
+        let handler: UpgradeHandler = Arc::new(handler);
+        match self.handlers.iter_mut().find(|(name, _)| name.eq_ignore_ascii_case(protocol)){
+            Some(entry) => entry.1 = handler,
+            None => self.handlers.push((protocol.to_string(), handler)),
+        }
+        self
+    }
+
+    /// The registered protocols, in the order they were registered.
+    pub fn protocols(&self) -> impl Iterator<Item = &str>{
+        self.handlers.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.handlers.is_empty()
+    }
+
+    // The first protocol the request offers that has a handler.
+    fn find(&self, request: &Request) -> Option<&(String, UpgradeHandler)>{
+        request.headers.get_all("Upgrade")
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .find_map(|offered| self.handlers.iter().find(|(name, _)| name.eq_ignore_ascii_case(offered)))
+    }
+}
+
+impl Middleware for UpgradeRegistry{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response{
+        if !request.headers.has_token("Connection", "Upgrade") || request.header("Upgrade").is_none(){
+            return next.run(request);
+        }
+        if let Some((protocol, handler)) = self.find(request){
+            let (handler, request, name) = (Arc::clone(handler), request.clone(), protocol.clone());
+            return Response::new(101, http::reason_phrase(101))
+                .with_header("Upgrade", protocol)
+                .with_header("Connection", "Upgrade")
+                .with_upgrade(Upgrade::new(move |stream| {
+                    if let Err(e) = handler(stream, request.clone()){
+                        log::warn(&format!("{} connection failed: {}", name, e));
+                    }
+                }));
+        }
+        // An error from the route says more than a `426` would.
+        let response = next.run(request);
+        if response.upgrade.is_some() || response.status >= 400{
+            return response;
+        }
+        let supported = self.protocols().collect::<Vec<_>>().join(", ");
+        Response::new(426, http::reason_phrase(426))
+            .with_header("Upgrade", &supported)
+            .with_header("Connection", "Upgrade")
+            .with_header("Content-Type", "text/plain")
+            .with_body(format!("Supported upgrades: {}\n", supported))
+    }
+}
@@ -0,0 +1,121 @@
+// Request-target validation and normalization, done before routing so
+// routes, middleware and static files all see one spelling of each path.
+use std::{error::Error, fmt};
+
+use crate::http::{Request, TargetForm};
+
+/// Why a request path was refused. Each is answered with `400`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UriError{
+    NotAbsolute,        // The path doesn't start with `/`.
+    InvalidCharacter,   // A control character, space or backslash.
+    InvalidEncoding,    // A `%` without two hex digits after it, or an encoded backslash.
+    EncodedControl,     // An encoded NUL or other control character, such as `%00` or `%0A`.
+    AboveRoot,          // A `..` segment with no segment before it to remove.
+}
+
+impl UriError{
+    pub fn status(&self) -> u16{
+        400
+    }
+}
+
+impl fmt::Display for UriError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            UriError::NotAbsolute => write!(f, "request path must start with /"),
+            UriError::InvalidCharacter => write!(f, "invalid character in request path"),
+            UriError::InvalidEncoding => write!(f, "invalid percent-encoding in request path"),
+            UriError::EncodedControl => write!(f, "encoded control character in request path"),
+            UriError::AboveRoot => write!(f, "request path climbs above the root"),
+        }
+    }
+}
+
+impl Error for UriError {}
+
+/// The canonical form of an origin-form `path` (no query string).
+///
+/// Percent-encoded unreserved characters are decoded, so `%2e` is a dot
+/// and `%7E` a tilde, and the hex digits of the escapes that remain are
+/// uppercased; an encoded `/` stays encoded, inside its segment. Runs of
+/// slashes collapse into one, and `.` and `..` segments are resolved
+/// lexically, as in RFC 3986, section 5.2.4, except that a `..` which
+/// would climb above the root is an error rather than dropped. A trailing
+/// slash is kept, and one is added where a dot segment ended the path.
+/// With `lowercase`, ASCII letters are lowercased, escapes excepted.
+pub fn normalize_path(path: &str, lowercase: bool) -> Result<String, UriError>{
+    let rest = path.strip_prefix('/').ok_or(UriError::NotAbsolute)?;
+    let decoded = decode_unreserved(rest, lowercase)?;
+
+    let mut segments: Vec<&str> = Vec::new();
+    let mut trailing_slash = false;
+    for segment in decoded.split('/'){
+        trailing_slash = true;
+        match segment{
+            "" | "." => {},
+            ".." => {
+                segments.pop().ok_or(UriError::AboveRoot)?;
+            },
+            segment => {
+                segments.push(segment);
+                trailing_slash = false;
+            },
+        }
+    }
+
+    let mut normalized = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty(){
+        normalized.push('/');
+    }
+    Ok(normalized)
+}
+
+/// Normalize `request.path` in place with `normalize_path`, keeping what
+/// the client sent in `request.original_path` when that differs. Only
+/// origin- and absolute-form targets have a path to normalize; the others
+/// are left alone.
+pub fn normalize_request(request: &mut Request, lowercase: bool) -> Result<(), UriError>{
+    if !matches!(request.target_form, TargetForm::Origin | TargetForm::Absolute){
+        return Ok(());
+    }
+    let normalized = normalize_path(&request.path, lowercase)?;
+    if normalized != request.path{
+        request.original_path = Some(std::mem::replace(&mut request.path, normalized));
+    }
+    Ok(())
+}
+
+// Check every byte, decode the escapes of unreserved characters and tidy
+// the rest.
+fn decode_unreserved(path: &str, lowercase: bool) -> Result<String, UriError>{
+    let case = |b: u8| if lowercase { b.to_ascii_lowercase() } else { b };
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len(){
+        match bytes[i]{
+            b'%' => {
+                let value = bytes.get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or(UriError::InvalidEncoding)?;
+                match value{
+                    b if b.is_ascii_control() => return Err(UriError::EncodedControl),
+                    b'\\' => return Err(UriError::InvalidEncoding),
+                    b if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') => out.push(case(b)),
+                    b => out.extend_from_slice(format!("%{:02X}", b).as_bytes()),
+                }
+                i += 3;
+            },
+            b if b <= b' ' || b == 0x7f || b == b'\\' => return Err(UriError::InvalidCharacter),
+            b => {
+                out.push(case(b));
+                i += 1;
+            },
+        }
+    }
+    // Only ASCII was added or changed, so the bytes are still UTF-8.
+    Ok(String::from_utf8(out).expect("normalized path is UTF-8"))
+}
@@ -0,0 +1,185 @@
+use std::{collections::HashMap, fs, path::PathBuf, sync::Arc};
+
+use crate::{
+    config::{Config, ConfigError},
+    http::{self, HttpVersion, Request, Response},
+    negotiation,
+    router::Router,
+    static_files::StaticFileServer,
+};
+
+/// One site's settings, read from its own config section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SiteConfig{
+    pub hosts: Vec<String>,             // Exact names or `*.domain` wildcards.
+    pub root: Option<PathBuf>,          // Files served for every path, if set.
+    pub not_found_page: Option<PathBuf>,    // HTML sent with 404s instead of the plain default.
+    pub immutable_prefixes: Vec<String>,    // Paths served with far-future caching.
+}
+
+impl SiteConfig{
+    /// Read `hosts` (required), `root`, `not_found_page` and
+    /// `immutable_prefixes` from `section` of `config`.
+    pub fn from_config(config: &Config, section: &str) -> Result<SiteConfig, ConfigError>{
+        let key = |name: &str| format!("{}.{}", section, name);
+        let hosts = config.get_str_array(&key("hosts"))?
+            .filter(|hosts| !hosts.is_empty())
+            .ok_or_else(|| ConfigError::invalid(&key("hosts"), "at least one host is required"))?;
+        Ok(SiteConfig {
+            hosts,
+            root: config.get_str(&key("root"))?.map(PathBuf::from),
+            not_found_page: config.get_str(&key("not_found_page"))?.map(PathBuf::from),
+            immutable_prefixes: config.get_str_array(&key("immutable_prefixes"))?.unwrap_or_default(),
+        })
+    }
+
+    /// A router that serves this site's files and error page.
+    pub fn into_router(self) -> Router{
+        let mut router = Router::new();
+        let not_found = Arc::new(move |request: &Request| not_found_response(request, self.not_found_page.as_ref()));
+
+        if let Some(root) = self.root{
+            let files = StaticFileServer::new(root).with_immutable_prefixes(self.immutable_prefixes);
+            let not_found = Arc::clone(&not_found);
+            router.get("/*path", move |request| {
+                let response = files.handle(request);
+                if response.status == 404 { not_found(request) } else { response }
+            });
+        }
+        router.fallback(move |request| not_found(request));
+        router
+    }
+}
+
+/// Picks a router by the request's `Host` header, so several sites can
+/// share one listener. An absolute-form target's authority takes the
+/// place of `Host` (see `Request::effective_host`).
+///
+/// Hosts are matched after normalising (see `normalize_host`). Exact names
+/// win over wildcards, and among wildcards the longest suffix wins; a
+/// `*.example.test` pattern matches any subdomain of `example.test` but not
+/// `example.test` itself. Requests for unknown hosts, and HTTP/1.0 requests
+/// without a `Host`, go to the default router. HTTP/1.1 requires `Host`,
+/// so a request without exactly one gets `400 Bad Request`.
+pub struct VirtualHosts{
+    exact: HashMap<String, Arc<Router>>,
+    wildcards: Vec<(String, Arc<Router>)>,  // (".example.test", router), longest suffix first.
+    default: Arc<Router>,
+}
+
+impl VirtualHosts{
+    pub fn new(default: Router) -> VirtualHosts{
+        VirtualHosts {
+            exact: HashMap::new(),
+            wildcards: Vec::new(),
+            default: Arc::new(default),
+        }
+    }
+
+    /// Build sites from config: `[vhosts] sites = [...]` names the
+    /// sections, each read with `SiteConfig::from_config`.
+    pub fn from_config(config: &Config, default: Router) -> Result<VirtualHosts, ConfigError>{
+        let mut hosts = VirtualHosts::new(default);
+        for section in config.get_str_array("vhosts.sites")?.unwrap_or_default(){
+            let site = SiteConfig::from_config(config, &section)?;
+            let patterns = site.hosts.clone();
+            let router = Arc::new(site.into_router());
+            for pattern in &patterns{
+                hosts.add(pattern, Arc::clone(&router));
+            }
+        }
+        Ok(hosts)
+    }
+
+    /// Serve requests for `pattern` (a host name or `*.domain`) with `router`.
+    pub fn add<R: Into<Arc<Router>>>(&mut self, pattern: &str, router: R) -> &mut VirtualHosts{
+        let pattern = normalize_host(pattern);
+        match pattern.strip_prefix('*'){
+            Some(suffix) if suffix.starts_with('.') => {
+                self.wildcards.push((suffix.to_string(), router.into()));
+                self.wildcards.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+            },
+            _ => {
+                self.exact.insert(pattern, router.into());
+            },
+        }
+        self
+    }
+
+    /// The router that serves `host`, which should already be normalised.
+    pub fn router_for(&self, host: &str) -> &Router{
+        if let Some(router) = self.exact.get(host){
+            return router;
+        }
+        self.wildcards.iter()
+            .find(|(suffix, _)| host.len() > suffix.len() && host.ends_with(suffix.as_str()))
+            .map_or(&self.default, |(_, router)| router)
+    }
+
+    pub fn dispatch(&self, request: &Request) -> Response{
+        if let Some(authority) = &request.authority{
+            return self.router_for(&normalize_host(authority)).dispatch(request);
+        }
+        let mut hosts = request.headers.get_all("Host");
+        let host = match (hosts.next(), hosts.next()){
+            (Some(host), None) => Some(normalize_host(host)),
+            (None, None) if request.version == HttpVersion::Http10 => None,
+            _ => return negotiation::status_page(request, 400, "Exactly one Host header is required"),
+        };
+
+        match host{
+            Some(host) => self.router_for(&host).dispatch(request),
+            None => self.default.dispatch(request),
+        }
+    }
+}
+
+/// Lowercase a `Host` value and drop its port and any trailing dot, so
+/// `Docs.Example.Test.:8080` and `docs.example.test` compare equal.
+/// IPv6 literals keep their brackets.
+pub fn normalize_host(host: &str) -> String{
+    let host = host.trim();
+    let without_port = if host.starts_with('['){
+        host.find(']').map_or(host, |end| &host[..=end])
+    } else {
+        host.rsplit_once(':').map_or(host, |(name, _)| name)
+    };
+    without_port.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Whether a `Host` value matches one of `patterns`.
+///
+/// A pattern is a host name, a `*.domain` wildcard (subdomains only), or
+/// an IP literal, optionally with a port. A pattern without a port
+/// matches the host on any port; one with a port only matches that port,
+/// where a `Host` without a port means 80. Names compare
+/// case-insensitively, ignoring a trailing dot.
+pub fn host_allowed(patterns: &[String], host: &str) -> bool{
+    let (name, port) = match http::parse_host(host){
+        Some((name, port)) => (normalize_host(name), port.unwrap_or(80)),
+        None => return false,
+    };
+    patterns.iter().any(|pattern| {
+        let (pattern_name, pattern_port) = match http::parse_host(pattern){
+            Some((pattern_name, pattern_port)) => (normalize_host(pattern_name), pattern_port),
+            None => return false,
+        };
+        if pattern_port.is_some_and(|pattern_port| pattern_port != port){
+            return false;
+        }
+        match pattern_name.strip_prefix('*'){
+            Some(suffix) => name.len() > suffix.len() && name.ends_with(suffix),
+            None => name == pattern_name,
+        }
+    })
+}
+
+// The site's own page when there is one, otherwise the built-in one.
+fn not_found_response(request: &Request, page: Option<&PathBuf>) -> Response{
+    match page.map(fs::read){
+        Some(Ok(contents)) => Response::new(404, http::reason_phrase(404))
+            .with_header("Content-Type", "text/html")
+            .with_body(contents),
+        _ => negotiation::status_page(request, 404, "Not Found"),
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    collections::hash_map::RandomState,
+    error::Error,
+    fmt,
+    hash::BuildHasher,
+    io::{self, Read, Write},
+};
+
+use crate::{encoding, hash, http::{self, HttpVersion, Request, Response}};
+
+/// Appended to the client's key before hashing (RFC 6455 section 1.3).
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Default limit on the size of a reassembled message.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// The `Sec-WebSocket-Accept` value answering `client_key`.
+pub fn accept_key(client_key: &str) -> String{
+    let digest = hash::sha1(format!("{}{}", client_key.trim(), ACCEPT_GUID).as_bytes());
+    encoding::base64_encode(&digest)
+}
+
+/// Check that `request` is a valid opening handshake and build the
+/// `101 Switching Protocols` answer.
+///
+/// An unsupported protocol version is refused with `426 Upgrade Required`
+/// naming version 13; anything else that is wrong gets `400 Bad Request`.
+pub fn handshake(request: &Request) -> Result<Response, Response>{
+    let bad_request = |reason: &str| {
+        Response::new(400, http::reason_phrase(400))
+            .with_header("Content-Type", "text/plain")
+            .with_body(reason.to_string())
+    };
+
+    if request.method != "GET" || request.version != HttpVersion::Http11{
+        return Err(bad_request("WebSocket handshakes must be HTTP/1.1 GET requests"));
+    }
+    if !request.headers.has_token("Upgrade", "websocket") || !request.headers.has_token("Connection", "Upgrade"){
+        return Err(bad_request("Expected Upgrade: websocket and Connection: Upgrade"));
+    }
+    if request.header("Sec-WebSocket-Version").map(str::trim) != Some("13"){
+        return Err(Response::new(426, http::reason_phrase(426))
+            .with_header("Sec-WebSocket-Version", "13")
+            .with_header("Upgrade", "websocket"));
+    }
+
+    // The key must be 16 random bytes, base64 encoded.
+    let key = request.header("Sec-WebSocket-Key").unwrap_or("").trim();
+    if encoding::base64_decode(key).map(|k| k.len()) != Some(16){
+        return Err(bad_request("Missing or malformed Sec-WebSocket-Key"));
+    }
+
+    Ok(Response::new(101, http::reason_phrase(101))
+        .with_header("Upgrade", "websocket")
+        .with_header("Connection", "Upgrade")
+        .with_header("Sec-WebSocket-Accept", &accept_key(key)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode{
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode{
+    fn from_u8(value: u8) -> Option<Opcode>{
+        match value{
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xA => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+
+    fn as_u8(self) -> u8{
+        match self{
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xA,
+        }
+    }
+
+    pub fn is_control(self) -> bool{
+        matches!(self, Opcode::Close | Opcode::Ping | Opcode::Pong)
+    }
+}
+
+/// A single frame, with its payload already unmasked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame{
+    pub fin: bool,          // Last frame of the message.
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+impl Frame{
+    pub fn new(opcode: Opcode, payload: Vec<u8>) -> Frame{
+        Frame { fin: true, opcode, payload }
+    }
+
+    /// A close frame carrying `code` and a UTF-8 `reason`.
+    pub fn close(code: u16, reason: &str) -> Frame{
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        Frame::new(Opcode::Close, payload)
+    }
+}
+
+/// A complete data message, or the peer's close.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message{
+    Text(String),
+    Binary(Vec<u8>),
+    Close(Option<(u16, String)>),   // Status code and reason, if the peer sent one.
+}
+
+#[derive(Debug)]
+pub enum WsError{
+    Io(io::Error),
+    Protocol(&'static str),     // The peer broke RFC 6455; the connection is being closed with 1002.
+    InvalidUtf8,                // A text message was not UTF-8; closed with 1007.
+    MessageTooLarge,            // A message exceeded the size limit; closed with 1009.
+}
+
+impl fmt::Display for WsError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            WsError::Io(e) => write!(f, "websocket I/O error: {}", e),
+            WsError::Protocol(msg) => write!(f, "websocket protocol error: {}", msg),
+            WsError::InvalidUtf8 => write!(f, "websocket text message is not valid UTF-8"),
+            WsError::MessageTooLarge => write!(f, "websocket message too large"),
+        }
+    }
+}
+
+impl Error for WsError{
+    fn source(&self) -> Option<&(dyn Error + 'static)>{
+        match self{
+            WsError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WsError{
+    fn from(e: io::Error) -> WsError{
+        WsError::Io(e)
+    }
+}
+
+/// Which end of the connection we are. Clients mask what they send and
+/// servers require it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role{
+    Server,
+    Client,
+}
+
+/// A WebSocket connection over an already upgraded stream.
+pub struct WebSocket<S>{
+    stream: S,
+    role: Role,
+    max_message_size: usize,
+    close_sent: bool,
+    random: RandomState,    // Source of client masking keys.
+    mask_counter: u64,
+}
+
+impl<S: Read + Write> WebSocket<S>{
+    /// Wrap the server side of an upgraded connection.
+    pub fn new(stream: S) -> WebSocket<S>{
+        WebSocket::with_role(stream, Role::Server)
+    }
+
+    pub fn with_role(stream: S, role: Role) -> WebSocket<S>{
+        WebSocket {
+            stream,
+            role,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            close_sent: false,
+            random: RandomState::new(),
+            mask_counter: 0,
+        }
+    }
+
+    /// Refuse messages (and single frames) larger than `bytes`.
+    pub fn with_max_message_size(mut self, bytes: usize) -> WebSocket<S>{
+        self.max_message_size = bytes;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S{
+        &self.stream
+    }
+
+    /// Read one frame.
+    pub fn read_frame(&mut self) -> Result<Frame, WsError>{
+        let mut head = [0u8; 2];
+        self.stream.read_exact(&mut head)?;
+
+        let fin = head[0] & 0x80 != 0;
+        if head[0] & 0x70 != 0{
+            return Err(self.fail(1002, WsError::Protocol("reserved bits set")));
+        }
+        let opcode = match Opcode::from_u8(head[0] & 0x0F){
+            Some(op) => op,
+            None => return Err(self.fail(1002, WsError::Protocol("unknown opcode"))),
+        };
+
+        let masked = head[1] & 0x80 != 0;
+        if masked != (self.role == Role::Server){
+            return Err(self.fail(1002, WsError::Protocol("wrong masking for this direction")));
+        }
+
+        let len = match head[1] & 0x7F{
+            126 => {
+                let mut ext = [0u8; 2];
+                self.stream.read_exact(&mut ext)?;
+                u64::from(u16::from_be_bytes(ext))
+            },
+            127 => {
+                let mut ext = [0u8; 8];
+                self.stream.read_exact(&mut ext)?;
+                u64::from_be_bytes(ext)
+            },
+            n => u64::from(n),
+        };
+        if opcode.is_control() && (!fin || len > 125){
+            return Err(self.fail(1002, WsError::Protocol("fragmented or oversized control frame")));
+        }
+        if len > self.max_message_size as u64{
+            return Err(self.fail(1009, WsError::MessageTooLarge));
+        }
+
+        let mut mask = [0u8; 4];
+        if masked{
+            self.stream.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0u8; len as usize];
+        self.stream.read_exact(&mut payload)?;
+        if masked{
+            apply_mask(&mut payload, mask);
+        }
+
+        Ok(Frame { fin, opcode, payload })
+    }
+
+    /// Write one frame, masking it if we are the client.
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<(), WsError>{
+        let mut out = Vec::with_capacity(frame.payload.len() + 14);
+        out.push(if frame.fin { 0x80 } else { 0 } | frame.opcode.as_u8());
+
+        let mask_bit = if self.role == Role::Client { 0x80 } else { 0 };
+        let len = frame.payload.len();
+        if len < 126{
+            out.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize{
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        if self.role == Role::Client{
+            self.mask_counter += 1;
+            let mask = (self.random.hash_one(self.mask_counter) as u32).to_be_bytes();
+            out.extend_from_slice(&mask);
+            let start = out.len();
+            out.extend_from_slice(&frame.payload);
+            apply_mask(&mut out[start..], mask);
+        } else {
+            out.extend_from_slice(&frame.payload);
+        }
+
+        self.stream.write_all(&out)?;
+        self.stream.flush()?;
+        if frame.opcode == Opcode::Close{
+            self.close_sent = true;
+        }
+        Ok(())
+    }
+
+    /// Read the next complete message, putting fragments back together.
+    ///
+    /// Pings are answered with pongs and pongs are ignored, so only data
+    /// messages and the peer's close are returned. A close is echoed back
+    /// before it is returned, after which the connection should be dropped.
+    pub fn read_message(&mut self) -> Result<Message, WsError>{
+        let mut kind: Option<Opcode> = None;
+        let mut data = Vec::new();
+
+        loop{
+            let frame = self.read_frame()?;
+            match frame.opcode{
+                Opcode::Ping => {
+                    self.write_frame(&Frame::new(Opcode::Pong, frame.payload))?;
+                    continue;
+                },
+                Opcode::Pong => continue,
+                Opcode::Close => return self.handle_close(frame.payload),
+                Opcode::Text | Opcode::Binary if kind.is_none() => kind = Some(frame.opcode),
+                Opcode::Continuation if kind.is_some() => {},
+                _ => return Err(self.fail(1002, WsError::Protocol("unexpected continuation state"))),
+            }
+
+            if data.len() + frame.payload.len() > self.max_message_size{
+                return Err(self.fail(1009, WsError::MessageTooLarge));
+            }
+            data.extend_from_slice(&frame.payload);
+
+            if frame.fin{
+                return match kind{
+                    Some(Opcode::Text) => match String::from_utf8(data){
+                        Ok(text) => Ok(Message::Text(text)),
+                        Err(_) => Err(self.fail(1007, WsError::InvalidUtf8)),
+                    },
+                    _ => Ok(Message::Binary(data)),
+                };
+            }
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<(), WsError>{
+        self.write_frame(&Frame::new(Opcode::Text, text.as_bytes().to_vec()))
+    }
+
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<(), WsError>{
+        self.write_frame(&Frame::new(Opcode::Binary, data.to_vec()))
+    }
+
+    pub fn ping(&mut self, payload: &[u8]) -> Result<(), WsError>{
+        self.write_frame(&Frame::new(Opcode::Ping, payload.to_vec()))
+    }
+
+    /// Start a clean close. The peer's answering close still has to be
+    /// read with `read_message`.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), WsError>{
+        if self.close_sent{
+            return Ok(());
+        }
+        self.write_frame(&Frame::close(code, reason))
+    }
+
+    fn handle_close(&mut self, payload: Vec<u8>) -> Result<Message, WsError>{
+        let status = match payload.len(){
+            0 => None,
+            1 => return Err(self.fail(1002, WsError::Protocol("truncated close frame"))),
+            _ => {
+                let code = u16::from_be_bytes([payload[0], payload[1]]);
+                match String::from_utf8(payload[2..].to_vec()){
+                    Ok(reason) => Some((code, reason)),
+                    Err(_) => return Err(self.fail(1007, WsError::InvalidUtf8)),
+                }
+            },
+        };
+
+        if !self.close_sent{
+            let code = status.as_ref().map_or(1000, |(code, _)| *code);
+            self.write_frame(&Frame::close(code, ""))?;
+        }
+        Ok(Message::Close(status))
+    }
+
+    /// Send a close with `code` (best effort) and hand back `error`.
+    fn fail(&mut self, code: u16, error: WsError) -> WsError{
+        if !self.close_sent{
+            let _ = self.write_frame(&Frame::close(code, ""));
+        }
+        error
+    }
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]){
+    for (i, byte) in data.iter_mut().enumerate(){
+        *byte ^= mask[i % 4];
+    }
+}
@@ -0,0 +1,82 @@
+// `AbortHandle` cancels a queued job before a worker starts it; a job
+// already running is left to finish.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use server_app::{AbortHandle, ThreadPool};
+
+const WAIT: Duration = Duration::from_secs(5);
+
+// A job that says when it starts, then waits to be let go.
+fn blocking_job(pool: &ThreadPool) -> (AbortHandle, mpsc::Receiver<()>, mpsc::Sender<()>, mpsc::Receiver<()>) {
+    let (started_tx, started) = mpsc::channel();
+    let (release, release_rx) = mpsc::channel::<()>();
+    let (finished_tx, finished) = mpsc::channel();
+    let handle = pool.execute(move || {
+        started_tx.send(()).unwrap();
+        release_rx.recv().unwrap();
+        finished_tx.send(()).unwrap();
+    });
+    (handle, started, release, finished)
+}
+
+#[test]
+fn an_aborted_queued_job_never_runs() {
+    let pool = ThreadPool::new(1);
+    let (_busy, started, release, finished) = blocking_job(&pool);
+    started.recv_timeout(WAIT).unwrap(); // The only worker is taken.
+
+    let ran = Arc::new(AtomicBool::new(false));
+    let queued = {
+        let ran = Arc::clone(&ran);
+        pool.execute(move || ran.store(true, Ordering::SeqCst))
+    };
+    assert!(!queued.is_aborted());
+    assert!(queued.abort());
+    assert!(queued.is_aborted());
+    assert!(queued.clone().abort(), "aborting again still says it won't run");
+
+    release.send(()).unwrap();
+    finished.recv_timeout(WAIT).unwrap();
+    // A job queued after it runs, so the worker has been past it.
+    let (after_tx, after) = mpsc::channel();
+    pool.execute(move || after_tx.send(()).unwrap());
+    after.recv_timeout(WAIT).unwrap();
+    assert!(!ran.load(Ordering::SeqCst));
+}
+
+#[test]
+fn aborting_a_running_job_does_nothing() {
+    let pool = ThreadPool::new(1);
+    let (handle, started, release, finished) = blocking_job(&pool);
+    started.recv_timeout(WAIT).unwrap();
+
+    assert!(!handle.abort());
+    assert!(!handle.is_aborted());
+    release.send(()).unwrap();
+    finished.recv_timeout(WAIT).expect("the job ran to the end");
+    assert!(!handle.abort(), "nor once it has finished");
+}
+
+#[test]
+fn other_queued_jobs_still_run() {
+    let pool = ThreadPool::new(1);
+    let (_busy, started, release, finished) = blocking_job(&pool);
+    started.recv_timeout(WAIT).unwrap();
+
+    let (done_tx, done) = mpsc::channel();
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let done_tx = done_tx.clone();
+            pool.execute(move || done_tx.send(i).unwrap())
+        })
+        .collect();
+    assert!(handles[1].abort());
+    assert!(handles[3].abort());
+    release.send(()).unwrap();
+    finished.recv_timeout(WAIT).unwrap();
+    drop(done_tx);
+    drop(pool); // Waits for the queue to empty.
+    assert_eq!(done.iter().collect::<Vec<_>>(), [0, 2]);
+}
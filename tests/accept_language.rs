@@ -0,0 +1,44 @@
+// Accept-Language is parsed best first, and select_locale matches tags
+// exactly, by BCP 47 prefix, or by wildcard.
+use server_app::negotiation::{select_locale, AcceptLanguage};
+
+const AVAILABLE: [&str; 3] = ["en", "fr", "de-CH"];
+
+#[test]
+fn parse_sorts_by_quality() {
+    assert_eq!(
+        AcceptLanguage::parse("fr;q=0.7, en-US, en;q=0.9, bad tag, de;q=x"),
+        vec![("en-US".to_string(), 1.0), ("de".to_string(), 1.0), ("en".to_string(), 0.9), ("fr".to_string(), 0.7)]
+    );
+    assert!(AcceptLanguage::parse("").is_empty());
+}
+
+#[test]
+fn exact_matches_are_case_insensitive() {
+    assert_eq!(select_locale("FR", &AVAILABLE), Some("fr"));
+    assert_eq!(select_locale("de-ch, fr;q=0.5", &AVAILABLE), Some("de-CH"));
+}
+
+#[test]
+fn prefix_matches_go_either_way() {
+    // A more specific range takes the language it starts with...
+    assert_eq!(select_locale("en-US,en;q=0.9,fr;q=0.7", &AVAILABLE), Some("en"));
+    // ...and a bare language takes a more specific tag.
+    assert_eq!(select_locale("de", &AVAILABLE), Some("de-CH"));
+    // But `en` is not a prefix of `english`.
+    assert_eq!(select_locale("en", &["english", "fr"]), None);
+}
+
+#[test]
+fn wildcard_takes_anything_not_mentioned() {
+    assert_eq!(select_locale("es, *;q=0.1", &AVAILABLE), Some("en"));
+    assert_eq!(select_locale("en;q=0, *", &AVAILABLE), Some("fr"));
+}
+
+#[test]
+fn no_match() {
+    assert_eq!(select_locale("ja, zh-Hant;q=0.8", &AVAILABLE), None);
+    assert_eq!(select_locale("fr;q=0", &["fr"]), None);
+    // With no usable header, anything goes.
+    assert_eq!(select_locale("", &AVAILABLE), Some("en"));
+}
@@ -0,0 +1,92 @@
+// `AcceptLoop` notices a shutdown request within a poll interval, hands
+// over connections in blocking mode, and runs housekeeping on time.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use server_app::server::AcceptLoop;
+
+fn accept_loop() -> AcceptLoop {
+    AcceptLoop::new(TcpListener::bind("127.0.0.1:0").unwrap()).unwrap()
+}
+
+#[test]
+fn a_shutdown_request_stops_the_loop_within_a_poll_interval() {
+    let mut accept = accept_loop(); // Polls every 50ms.
+    let shutdown = accept.shutdown_flag();
+    let (done, stopped) = mpsc::channel();
+    let running = thread::spawn(move || {
+        accept.run(|_| {}).unwrap();
+        done.send(Instant::now()).unwrap();
+    });
+    thread::sleep(Duration::from_millis(120)); // Well into a wait.
+
+    let asked = Instant::now();
+    shutdown.store(true, Ordering::SeqCst);
+    let stopped = stopped.recv_timeout(Duration::from_secs(5)).unwrap();
+    running.join().unwrap();
+    let took = stopped - asked;
+    assert!(took < Duration::from_millis(150), "took {:?}", took);
+}
+
+#[test]
+fn connections_are_handed_over_in_blocking_mode() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let mut accept = AcceptLoop::new(listener).unwrap().with_poll_interval(Duration::from_millis(10));
+    let shutdown = accept.shutdown_flag();
+    let (sender, handed) = mpsc::channel();
+    let running = thread::spawn(move || {
+        accept.run(|mut stream: TcpStream| {
+            // A read that would block fails in non-blocking mode; here it waits.
+            let mut byte = [0];
+            let read = stream.read(&mut byte).map(|_| byte[0]);
+            sender.send(read.map_err(|e| e.kind())).unwrap();
+        })
+    });
+
+    let mut client = TcpStream::connect(address).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    client.write_all(b"x").unwrap();
+    assert_eq!(handed.recv_timeout(Duration::from_secs(5)).unwrap(), Ok(b'x'));
+    shutdown.store(true, Ordering::SeqCst);
+    running.join().unwrap().unwrap();
+}
+
+#[test]
+fn housekeeping_runs_on_its_own_cadence_not_the_poll_interval() {
+    // The loop waits no longer than the next task is due, so a slow poll
+    // interval doesn't hold up a frequent task.
+    let mut accept = accept_loop().with_poll_interval(Duration::from_secs(1));
+    let shutdown = accept.shutdown_flag();
+    let runs = Arc::new(Mutex::new(Vec::new()));
+    let loop_thread = Arc::new(Mutex::new(None));
+    {
+        let runs = Arc::clone(&runs);
+        let loop_thread = Arc::clone(&loop_thread);
+        accept.every(Duration::from_millis(40), move || {
+            runs.lock().unwrap().push(Instant::now());
+            *loop_thread.lock().unwrap() = Some(thread::current().id());
+        });
+    }
+    let started = Instant::now();
+    let running = thread::spawn(move || accept.run(|_| {}));
+    let running_id = running.thread().id();
+    thread::sleep(Duration::from_millis(420));
+    shutdown.store(true, Ordering::SeqCst);
+    running.join().unwrap().unwrap();
+
+    let runs = runs.lock().unwrap();
+    assert!((5..=11).contains(&runs.len()), "{} runs", runs.len());
+    // Never early: the first a full interval in, the rest an interval
+    // apart, give or take the moment between a pass starting and its task.
+    let interval = Duration::from_millis(39);
+    assert!(runs[0] - started >= interval);
+    for pair in runs.windows(2) {
+        assert!(pair[1] - pair[0] >= interval, "{:?}", pair[1] - pair[0]);
+    }
+    assert_eq!(*loop_thread.lock().unwrap(), Some(running_id));
+}
@@ -0,0 +1,86 @@
+// With a tiny `max_bytes`, every couple of lines the access log moves to
+// `path.1`, the older files move up a number, and the oldest past
+// `max_files` goes.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use server_app::access_log::{AccessLog, AccessLogConfig};
+
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("access-log-rotation-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), n))
+}
+
+fn lines(path: &Path) -> Vec<String> {
+    fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)).lines().map(str::to_string).collect()
+}
+
+// Log `line-01` to `line-<count>`, 8 bytes each with the newline, to a
+// log that rotates once a file is past 10 bytes: every second line.
+fn log(path: &Path, max_files: usize, count: usize) {
+    let config = AccessLogConfig { max_bytes: 10, max_files, recent_lines: 0, ..AccessLogConfig::new(path) };
+    let log = AccessLog::open(config).unwrap();
+    for i in 1..=count {
+        log.log(format!("line-{:02}", i));
+    }
+    log.shutdown();
+}
+
+#[test]
+fn files_shift_along_the_chain_and_the_oldest_goes() {
+    let dir = dir("chain");
+    let path = dir.join("access.log");
+    log(&path, 3, 10);
+
+    // The live file was just rotated, so it's empty.
+    assert_eq!(lines(&path), Vec::<String>::new());
+    assert_eq!(lines(&numbered(&path, 1)), ["line-09", "line-10"]);
+    assert_eq!(lines(&numbered(&path, 2)), ["line-07", "line-08"]);
+    assert_eq!(lines(&numbered(&path, 3)), ["line-05", "line-06"]);
+    assert!(!numbered(&path, 4).exists(), "only max_files are kept");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn lines_short_of_the_limit_stay_in_the_live_file() {
+    let dir = dir("partial");
+    let path = dir.join("access.log");
+    log(&path, 3, 5);
+    assert_eq!(lines(&path), ["line-05"]);
+    assert_eq!(lines(&numbered(&path, 1)), ["line-03", "line-04"]);
+    assert_eq!(lines(&numbered(&path, 2)), ["line-01", "line-02"]);
+    assert!(!numbered(&path, 3).exists());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn reopening_carries_on_the_chain() {
+    let dir = dir("reopen");
+    let path = dir.join("access.log");
+    log(&path, 2, 3);
+    // `line-03` is already there, so the next line takes the file past
+    // the limit.
+    log(&path, 2, 1);
+    assert_eq!(lines(&path), Vec::<String>::new());
+    assert_eq!(lines(&numbered(&path, 1)), ["line-03", "line-01"]);
+    assert_eq!(lines(&numbered(&path, 2)), ["line-01", "line-02"]);
+    assert!(!numbered(&path, 3).exists());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn with_max_files_zero_nothing_is_kept() {
+    let dir = dir("none");
+    let path = dir.join("access.log");
+    log(&path, 0, 7);
+    assert_eq!(lines(&path), ["line-07"]);
+    assert!(!numbered(&path, 1).exists());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    let _ = fs::remove_dir_all(&dir);
+}
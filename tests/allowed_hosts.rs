@@ -0,0 +1,94 @@
+// `allowed_hosts` patterns: exact names, `*.domain` wildcards, ports and
+// case; and an absolute-form target is held to the list like `Host` is.
+use server_app::http::Request;
+use server_app::server::{read_request, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+use server_app::vhost::host_allowed;
+
+fn patterns(patterns: &[&str]) -> Vec<String> {
+    patterns.iter().map(|p| p.to_string()).collect()
+}
+
+fn read(allowed: &[&str], head: &str) -> Result<Request, u16> {
+    let config = ServerConfig { allowed_hosts: patterns(allowed), ..ServerConfig::default() };
+    match read_request(&mut MockStream::new([head.as_bytes().to_vec()]), &mut Vec::new(), &config, |_| None) {
+        Incoming::Request(request) => Ok(request),
+        Incoming::Reject(response) => Err(response.status),
+        Incoming::Closed => panic!("closed"),
+    }
+}
+
+#[test]
+fn a_wildcard_matches_subdomains_only() {
+    let allowed = patterns(&["*.example.com"]);
+    assert!(host_allowed(&allowed, "api.example.com"));
+    assert!(host_allowed(&allowed, "a.b.example.com"));
+    assert!(!host_allowed(&allowed, "example.com"));
+    assert!(!host_allowed(&allowed, "badexample.com"));
+    assert!(!host_allowed(&allowed, "example.com.evil.net"));
+    assert!(!host_allowed(&allowed, ".example.com"));
+}
+
+#[test]
+fn ports_are_stripped_unless_the_pattern_names_one() {
+    let any_port = patterns(&["example.com"]);
+    assert!(host_allowed(&any_port, "example.com"));
+    assert!(host_allowed(&any_port, "example.com:8080"));
+    assert!(host_allowed(&any_port, "example.com:"));
+
+    let one_port = patterns(&["example.com:8080", "*.example.org:443"]);
+    assert!(host_allowed(&one_port, "example.com:8080"));
+    assert!(!host_allowed(&one_port, "example.com:8081"));
+    assert!(!host_allowed(&one_port, "example.com"), "no port means 80");
+    assert!(host_allowed(&one_port, "www.example.org:443"));
+    assert!(!host_allowed(&one_port, "www.example.org"));
+    assert!(host_allowed(&patterns(&["example.com:80"]), "example.com"));
+
+    let literals = patterns(&["127.0.0.1", "[::1]:3000"]);
+    assert!(host_allowed(&literals, "127.0.0.1:7878"));
+    assert!(host_allowed(&literals, "[::1]:3000"));
+    assert!(!host_allowed(&literals, "[::1]"));
+}
+
+#[test]
+fn names_compare_without_case_or_a_trailing_dot() {
+    let allowed = patterns(&["Example.COM", "*.Apps.Example.net"]);
+    assert!(host_allowed(&allowed, "example.com"));
+    assert!(host_allowed(&allowed, "EXAMPLE.COM:8080"));
+    assert!(host_allowed(&allowed, "example.com."));
+    assert!(host_allowed(&allowed, "Build.APPS.example.NET"));
+    assert!(!host_allowed(&allowed, "apps.example.net"));
+    assert!(!host_allowed(&allowed, "user@example.com"));
+}
+
+#[test]
+fn requests_are_refused_by_their_host_header() {
+    let allowed = ["localhost", "*.example.com"];
+    assert!(read(&allowed, "GET / HTTP/1.1\r\nHost: LocalHost:7878\r\n\r\n").is_ok());
+    assert!(read(&allowed, "GET / HTTP/1.1\r\nHost: www.example.com\r\n\r\n").is_ok());
+    assert_eq!(read(&allowed, "GET / HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap_err(), 421);
+    assert_eq!(read(&allowed, "GET / HTTP/1.1\r\nHost: evil.net\r\n\r\n").unwrap_err(), 421);
+    // Nothing to check in a 1.0 request without one.
+    assert!(read(&allowed, "GET / HTTP/1.0\r\n\r\n").is_ok());
+}
+
+#[test]
+fn an_absolute_form_target_goes_through_the_list_too() {
+    let allowed = ["localhost"];
+    let request = read(&allowed, "GET http://LOCALHOST:7878/page HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    assert_eq!(request.path, "/page");
+    assert_eq!(request.effective_host(), Some("LOCALHOST:7878"));
+
+    // The target's authority wins over `Host`, so a good `Host` can't
+    // carry a bad target in, nor the other way round.
+    assert_eq!(read(&allowed, "GET http://evil.net/ HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap_err(), 421);
+    assert!(read(&allowed, "GET http://localhost/ HTTP/1.1\r\nHost: evil.net\r\n\r\n").is_ok());
+    // With no `Host` at all.
+    assert_eq!(read(&allowed, "GET http://evil.net/ HTTP/1.1\r\n\r\n").unwrap_err(), 421);
+    assert!(read(&allowed, "GET http://localhost/ HTTP/1.1\r\n\r\n").is_ok());
+
+    let config = ServerConfig { host_rejection_status: 400, ..ServerConfig::default() };
+    let head = b"GET http://evil.net/ HTTP/1.1\r\n\r\n".to_vec();
+    let rejected = read_request(&mut MockStream::new([head]), &mut Vec::new(), &config, |_| None);
+    assert!(matches!(rejected, Incoming::Reject(response) if response.status == 400));
+}
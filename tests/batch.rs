@@ -0,0 +1,83 @@
+// `execute_batch` submits a group of jobs at once, and its handle's
+// `wait` returns once every one of them has finished.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use server_app::{CountDownLatch, Job, PoolError, ThreadPool};
+
+#[test]
+fn wait_returns_after_every_job_has_finished() {
+    let pool = ThreadPool::new(4);
+    let finished = Arc::new(AtomicUsize::new(0));
+    let jobs: Vec<Job> = (0..16)
+        .map(|i| {
+            let finished = Arc::clone(&finished);
+            Box::new(move || {
+                thread::sleep(Duration::from_millis(5 * (i % 4)));
+                finished.fetch_add(1, Ordering::SeqCst);
+            }) as Job
+        })
+        .collect();
+    let batch = pool.execute_batch(jobs).unwrap();
+    batch.wait().unwrap();
+    assert_eq!(finished.load(Ordering::SeqCst), 16);
+    assert_eq!(batch.remaining(), 0);
+}
+
+#[test]
+fn a_failing_job_does_not_stop_the_others() {
+    let pool = ThreadPool::new(2);
+    let finished = Arc::new(AtomicUsize::new(0));
+    let jobs: Vec<Job> = (0..6)
+        .map(|i| {
+            let finished = Arc::clone(&finished);
+            Box::new(move || {
+                if i == 1 || i == 4 {
+                    panic!("job {} failed", i);
+                }
+                finished.fetch_add(1, Ordering::SeqCst);
+            }) as Job
+        })
+        .collect();
+    let batch = pool.execute_batch(jobs).unwrap();
+    assert!(matches!(batch.wait(), Err(PoolError::Panicked(2))));
+    assert_eq!(finished.load(Ordering::SeqCst), 4);
+
+    // Both workers survived.
+    let after = pool.execute_batch((0..2).map(|_| Box::new(|| {}) as Job).collect()).unwrap();
+    after.wait().unwrap();
+}
+
+#[test]
+fn results_can_be_gathered_fork_join_style() {
+    let pool = ThreadPool::new(3);
+    let sums = Arc::new(Mutex::new(Vec::new()));
+    let jobs: Vec<Job> = (0..3)
+        .map(|part| {
+            let sums = Arc::clone(&sums);
+            Box::new(move || {
+                let sum: u64 = (part * 100..(part + 1) * 100).sum();
+                sums.lock().unwrap().push(sum);
+            }) as Job
+        })
+        .collect();
+    pool.execute_batch(jobs).unwrap().wait().unwrap();
+    assert_eq!(sums.lock().unwrap().iter().sum::<u64>(), (0..300).sum());
+    assert!(pool.execute_batch(Vec::new()).unwrap().wait().is_ok());
+}
+
+#[test]
+fn the_latch_opens_at_zero() {
+    let latch = Arc::new(CountDownLatch::new(2));
+    assert!(!latch.wait_timeout(Duration::from_millis(10)));
+    let counter = Arc::clone(&latch);
+    let waiter = thread::spawn(move || counter.wait());
+    latch.count_down();
+    latch.count_down();
+    latch.count_down(); // Past zero does nothing.
+    waiter.join().unwrap();
+    assert_eq!(latch.count(), 0);
+    assert!(latch.wait_timeout(Duration::ZERO));
+}
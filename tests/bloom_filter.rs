@@ -0,0 +1,64 @@
+// `BloomFilter` never forgets an item and is wrong about unseen ones at
+// about the rate it was sized for; `RotatingBloomFilter` forgets items
+// between one and two TTLs after they went in.
+use std::sync::Arc;
+use std::time::Duration;
+
+use server_app::clock::MockClock;
+use server_app::collections::{BloomFilter, RotatingBloomFilter};
+
+#[test]
+fn inserted_items_are_always_found() {
+    let mut filter = BloomFilter::new(1_000, 0.01);
+    assert!(filter.is_empty());
+    for i in 0..1_000 {
+        filter.insert(&i);
+    }
+    assert!((0..1_000).all(|i| filter.contains(&i)));
+    assert_eq!(filter.len(), 1_000);
+
+    assert!(filter.check_and_insert(&5), "already there");
+    assert!(!filter.check_and_insert(&5_000));
+    assert!(filter.contains(&5_000));
+
+    filter.clear();
+    assert!(filter.is_empty());
+    assert!(!(0..1_000).any(|i| filter.contains(&i)));
+}
+
+#[test]
+fn the_false_positive_rate_is_about_what_was_asked_for() {
+    let mut filter = BloomFilter::<str>::new(10_000, 0.01);
+    for i in 0..10_000 {
+        filter.insert(&format!("nonce-{}", i));
+    }
+    let wrong = (0..100_000).filter(|i| filter.contains(&format!("other-{}", i))).count();
+    assert!(wrong < 2_000, "{} false positives in 100,000", wrong);
+    // About 9.6 bits an item at 1%.
+    assert!(filter.size_bytes() < 13_000, "{} bytes", filter.size_bytes());
+}
+
+#[test]
+fn rotation_forgets_items_after_one_to_two_ttls() {
+    let clock = Arc::new(MockClock::new());
+    let ttl = Duration::from_secs(60);
+    let mut filter = RotatingBloomFilter::with_clock(100, 0.01, ttl, Arc::clone(&clock) as _);
+    assert!(!filter.check_and_insert("early"));
+    clock.advance(Duration::from_secs(30));
+    assert!(!filter.check_and_insert("late"));
+
+    // The first rotation keeps both, in the previous filter.
+    clock.advance(Duration::from_secs(40));
+    assert!(filter.contains("early") && filter.contains("late"));
+    filter.insert("later");
+
+    // The second drops them, but not what went in since the first.
+    clock.advance(Duration::from_secs(60));
+    assert!(!filter.contains("early"));
+    assert!(!filter.contains("late"));
+    assert!(filter.contains("later"));
+
+    // Idle for two TTLs, everything is gone.
+    clock.advance(Duration::from_secs(120));
+    assert!(!filter.contains("later"));
+}
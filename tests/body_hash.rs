@@ -0,0 +1,124 @@
+// Request bodies are hashed with SHA-256 for handlers to check, in place
+// and with the body left for them to read.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use server_app::body_hash::BodyHashMiddleware;
+use server_app::hash::{self, Sha256};
+use server_app::http::{Request, Response};
+use server_app::router::Router;
+
+// The system allocator, tracking the bytes each thread has allocated and
+// their high-water mark, so a test can see how much memory a call took
+// without the tests running alongside it getting in the way.
+struct TrackingAlloc;
+
+thread_local! {
+    static IN_USE: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+fn track(bytes: isize) {
+    let _ = IN_USE.try_with(|in_use| {
+        in_use.set(in_use.get() + bytes);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(in_use.get())));
+    });
+}
+
+unsafe impl GlobalAlloc for TrackingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        track(layout.size() as isize);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        track(-(layout.size() as isize));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        track(new_size as isize - layout.size() as isize);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: TrackingAlloc = TrackingAlloc;
+
+// The most this thread had allocated at once while running `f`, beyond
+// what it had before.
+fn peak_during<F: FnOnce()>(f: F) -> isize {
+    let before = IN_USE.with(Cell::get);
+    PEAK.with(|peak| peak.set(before));
+    f();
+    PEAK.with(Cell::get) - before
+}
+
+// A router that answers with the body's length and hash, as its handler
+// sees them.
+fn router() -> Router {
+    let mut router = Router::new();
+    router.middleware(BodyHashMiddleware::new()).post("/upload", |request: &Request| {
+        Response::new(200, "OK").with_body(format!("{} {}", request.body.len(), request.body_hash().unwrap_or("none")))
+    });
+    router
+}
+
+fn post(body: Vec<u8>) -> Request {
+    let mut request = Request::new("POST", "/upload");
+    request.body = body;
+    request
+}
+
+// Digests from `sha256sum`.
+const KNOWN: [(&[u8], &str); 4] = [
+    (b"", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+    (b"abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+    (b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq", "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"),
+    (b"The quick brown fox jumps over the lazy dog", "d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592"),
+];
+
+#[test]
+fn digests_match_sha256sum() {
+    for (input, digest) in KNOWN {
+        assert_eq!(hash::to_hex(&hash::sha256(input)), digest);
+    }
+    let million = vec![b'a'; 1_000_000];
+    assert_eq!(hash::to_hex(&hash::sha256(&million)), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+
+    // Fed in pieces of every awkward size, the digest is the same.
+    let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+    for piece in [1, 3, 55, 56, 63, 64, 65, 127, 999] {
+        let mut hasher = Sha256::new();
+        data.chunks(piece).for_each(|chunk| hasher.update(chunk));
+        assert_eq!(hasher.finish(), hash::sha256(&data), "pieces of {}", piece);
+    }
+}
+
+#[test]
+fn the_handler_sees_the_body_and_its_hash() {
+    let router = router();
+    let response = router.dispatch(&post(b"abc".to_vec()));
+    assert_eq!(response.body, b"3 ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+    // No body, nothing to hash.
+    assert_eq!(router.dispatch(&post(Vec::new())).body, b"0 none");
+
+    let response = router.dispatch(&post(KNOWN[3].0.to_vec()));
+    assert_eq!(response.body, format!("43 {}", KNOWN[3].1).as_bytes());
+    assert_eq!(Request::new("POST", "/").body_hash(), None);
+}
+
+#[test]
+fn large_bodies_are_not_copied() {
+    let size = 3 * 1024 * 1024 + 17;
+    let body: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+    let request = post(body);
+    let router = router();
+    let mut response = None;
+    let peak = peak_during(|| response = Some(router.dispatch(&request)));
+    // A copy of the body would take over 3 MiB.
+    assert!(peak < 256 * 1024, "hashing took {} bytes", peak);
+    let expected = format!("{} fe2aaf82bfa2ffec207a0c6fa7ce7d4af268d67e2672fdaec675f3f9b65d0854", size);
+    assert_eq!(response.unwrap().body, expected.as_bytes());
+}
@@ -0,0 +1,116 @@
+// Request bodies are read up to `max_body_bytes` and no further: exactly
+// at the limit is fine, a byte past it is `413` without reading on.
+use std::io::{self, Read};
+
+use server_app::http::{read_chunked_body, BodyError, Limits, RequestBodyReader};
+use server_app::server::{read_request, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+const LIMIT: usize = 10;
+
+// Hands out one scripted piece per read, and counts what was taken.
+struct Pieces {
+    pieces: Vec<Vec<u8>>,
+    taken: usize, // Pieces read so far.
+    bytes: usize, // Bytes read so far.
+}
+
+impl Pieces {
+    fn new(pieces: &[&[u8]]) -> Pieces {
+        Pieces { pieces: pieces.iter().rev().map(|p| p.to_vec()).collect(), taken: 0, bytes: 0 }
+    }
+}
+
+impl Read for Pieces {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(mut piece) = self.pieces.pop() else { return Ok(0) };
+        let n = piece.len().min(buf.len());
+        buf[..n].copy_from_slice(&piece[..n]);
+        if n < piece.len() {
+            self.pieces.push(piece.split_off(n));
+        } else {
+            self.taken += 1;
+        }
+        self.bytes += n;
+        Ok(n)
+    }
+}
+
+fn limits() -> Limits {
+    Limits { max_body_bytes: LIMIT, ..Limits::default() }
+}
+
+#[test]
+fn a_declared_length_exactly_at_the_limit_is_read() {
+    let mut stream = Pieces::new(&[b"01234", b"56789", b"next request"]);
+    let mut body = Vec::new();
+    RequestBodyReader::new(&mut stream, LIMIT).read_exact_len(LIMIT, &mut body).unwrap();
+    assert_eq!(body, b"0123456789");
+    assert_eq!((stream.taken, stream.bytes), (2, LIMIT), "nothing past the body");
+}
+
+#[test]
+fn a_declared_length_one_over_is_refused_before_reading() {
+    let mut stream = Pieces::new(&[b"0123456789", b"a"]);
+    let mut body = Vec::new();
+    let error = RequestBodyReader::new(&mut stream, LIMIT).read_exact_len(LIMIT + 1, &mut body).unwrap_err();
+    assert!(matches!(error, BodyError::TooLarge));
+    assert_eq!(error.status(), Some(413));
+    assert_eq!(stream.bytes, 0);
+}
+
+#[test]
+fn a_body_to_the_end_of_the_stream_may_reach_the_limit_but_not_pass_it() {
+    let mut stream = Pieces::new(&[b"0123456789"]);
+    let mut body = Vec::new();
+    RequestBodyReader::new(&mut stream, LIMIT).read_to_end(&mut body).unwrap();
+    assert_eq!(body.len(), LIMIT);
+
+    // One byte over: the probe finds it, and reading stops there.
+    let mut stream = Pieces::new(&[b"0123456789", b"a", b"bcdefgh"]);
+    let mut body = Vec::new();
+    let error = RequestBodyReader::new(&mut stream, LIMIT).read_to_end(&mut body).unwrap_err();
+    assert!(matches!(error, BodyError::TooLarge));
+    assert_eq!(stream.bytes, LIMIT + 1);
+    assert!(body.len() <= LIMIT);
+}
+
+#[test]
+fn chunks_adding_up_to_the_limit_are_read() {
+    let mut stream = Pieces::new(&[b"4\r\n0123\r\n", b"4\r\n4567\r\n", b"2\r\n89\r\n", b"0\r\n\r\n"]);
+    let (body, _) = read_chunked_body(&mut stream, &mut Vec::new(), &limits()).unwrap();
+    assert_eq!(body, b"0123456789");
+}
+
+#[test]
+fn chunks_over_the_limit_stop_at_the_chunk_that_crosses_it() {
+    let mut stream = Pieces::new(&[b"4\r\n0123\r\n", b"4\r\n4567\r\n", b"3\r\n", b"89a\r\n", b"0\r\n\r\n"]);
+    let error = read_chunked_body(&mut stream, &mut Vec::new(), &limits()).unwrap_err();
+    assert!(matches!(error, BodyError::TooLarge));
+    assert_eq!(stream.taken, 3, "refused on the size line, before its data");
+}
+
+#[test]
+fn a_single_chunk_over_the_limit_is_refused_on_its_size() {
+    let mut stream = Pieces::new(&[b"b\r\n", b"0123456789a\r\n0\r\n\r\n"]);
+    let error = read_chunked_body(&mut stream, &mut Vec::new(), &limits()).unwrap_err();
+    assert!(matches!(error, BodyError::TooLarge));
+    assert_eq!(stream.taken, 1);
+}
+
+#[test]
+fn the_server_answers_an_oversized_body_with_413() {
+    let mut config = ServerConfig { limits: limits(), ..ServerConfig::default() };
+    config.allowed_hosts.clear();
+    let at_limit = b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\n0123456789".to_vec();
+    let request = read_request(&mut MockStream::new([at_limit]), &mut Vec::new(), &config, |_| None);
+    assert!(matches!(request, Incoming::Request(request) if request.body.len() == LIMIT));
+
+    let over = b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 11\r\n\r\n".to_vec();
+    let request = read_request(&mut MockStream::new([over, b"0123456789a".to_vec()]), &mut Vec::new(), &config, |_| None);
+    assert!(matches!(request, Incoming::Reject(response) if response.status == 413));
+
+    let chunked = b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n6\r\n012345\r\n6\r\n6789ab\r\n0\r\n\r\n".to_vec();
+    let request = read_request(&mut MockStream::new([chunked]), &mut Vec::new(), &config, |_| None);
+    assert!(matches!(request, Incoming::Reject(response) if response.status == 413));
+}
@@ -0,0 +1,51 @@
+// `body_as_str` borrows the body as text and `body_as_string` copies it;
+// both refuse bytes that aren't UTF-8 and say where they go wrong.
+use server_app::http::Request;
+
+fn with_body(body: &[u8]) -> Request {
+    let mut request = Request::new("POST", "/");
+    request.body = body.to_vec();
+    request
+}
+
+#[test]
+fn valid_utf8_comes_back_as_text() {
+    for body in ["", "plain ascii", "naïve café", "日本語", "emoji 🦀 too", "nul\0inside"] {
+        let request = with_body(body.as_bytes());
+        assert_eq!(request.body_as_str(), Ok(body));
+        assert_eq!(request.body_as_string().unwrap(), body);
+    }
+}
+
+#[test]
+fn the_borrowed_str_points_into_the_body() {
+    let request = with_body(b"no copy");
+    assert_eq!(request.body_as_str().unwrap().as_ptr(), request.body.as_ptr());
+}
+
+#[test]
+fn invalid_utf8_is_an_error_at_the_first_bad_byte() {
+    for (body, valid_up_to) in [
+        (&b"\xff"[..], 0),
+        (b"abc\x80def", 3),       // A lone continuation byte,
+        (b"caf\xc3", 3),          // a sequence cut short at the end,
+        (b"\xc3(", 0),            // or in the middle,
+        (b"ok \xed\xa0\x80", 3),  // an encoded surrogate,
+        (b"\xc0\xaf", 0),         // an overlong encoding.
+    ] {
+        let request = with_body(body);
+        let err = request.body_as_str().unwrap_err();
+        assert_eq!(err.valid_up_to(), valid_up_to, "{:?}", body);
+
+        let err = request.body_as_string().unwrap_err();
+        assert_eq!(err.utf8_error().valid_up_to(), valid_up_to, "{:?}", body);
+        assert_eq!(err.into_bytes(), body, "the bytes are handed back");
+    }
+}
+
+#[test]
+fn a_failed_conversion_leaves_the_body_alone() {
+    let request = with_body(b"bad \xff body");
+    assert!(request.body_as_string().is_err());
+    assert_eq!(request.body, b"bad \xff body");
+}
@@ -0,0 +1,87 @@
+// `ResponseBodyTransformer` rewrites HTML bodies on their way out, such
+// as to add a snippet to every page, and leaves everything else alone.
+#[cfg(feature = "compression")]
+use server_app::compression::{self, CompressionMiddleware};
+use server_app::http::{Request, Response};
+use server_app::router::Router;
+use server_app::transform::ResponseBodyTransformer;
+
+const MARK: &str = "<!-- served by rust_server -->";
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router
+        .middleware(ResponseBodyTransformer::insert_before_body_end(MARK))
+        .get("/", page)
+        .get("/data", |_: &Request| {
+            Response::new(200, "OK").with_header("Content-Type", "application/json").with_body("{\"body\":\"</body>\"}")
+        })
+        .get("/fragment", |_: &Request| Response::new(200, "OK").with_header("Content-Type", "TEXT/HTML").with_body("<p>"))
+        .get("/empty", |_: &Request| Response::new(204, "No Content").with_header("Content-Type", "text/html"));
+    router
+}
+
+fn page(_: &Request) -> Response {
+    Response::new(200, "OK")
+        .with_header("Content-Type", "text/html; charset=utf-8")
+        .with_header("Content-Length", "37")
+        .with_header("ETag", "\"page\"")
+        .with_body("<html><body><p>hi</p></BODY></html>")
+}
+
+fn wire(response: &Response) -> String {
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn the_snippet_goes_in_before_the_closing_body_tag() {
+    let request = Request::new("GET", "/");
+    let response = router().dispatch(&request).finalize(&request);
+    let expected = format!("<html><body><p>hi</p>{}</BODY></html>", MARK);
+    assert_eq!(String::from_utf8(response.body.clone()).unwrap(), expected);
+    assert_eq!(response.header("Content-Length"), Some(expected.len().to_string().as_str()));
+    assert_eq!(response.header("ETag"), None, "the old tag named the old body");
+    assert!(wire(&response).contains(&format!("Content-Length: {}\r\n", expected.len())));
+
+    // A page without one gets it at the end.
+    let body = router().dispatch(&Request::new("GET", "/fragment")).body;
+    assert_eq!(body, format!("<p>{}", MARK).as_bytes());
+}
+
+#[test]
+fn other_responses_pass_through() {
+    let router = router();
+    assert_eq!(router.dispatch(&Request::new("GET", "/data")).body, b"{\"body\":\"</body>\"}");
+    assert!(router.dispatch(&Request::new("GET", "/empty")).body.is_empty());
+    assert_eq!(router.dispatch(&Request::new("GET", "/missing")).status, 404);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compression_sees_the_transformed_page() {
+    let mut router = Router::new();
+    router
+        .middleware(CompressionMiddleware::new().with_min_size(1))
+        .middleware(ResponseBodyTransformer::insert_before_body_end(MARK))
+        .get("/", |_: &Request| {
+            let page = format!("<html><body>{}</body></html>", "<p>hi</p>".repeat(50));
+            Response::new(200, "OK").with_header("Content-Type", "text/html").with_body(page)
+        });
+    let mut request = Request::new("GET", "/");
+    request.headers.set("Accept-Encoding", "gzip");
+    let response = router.dispatch(&request);
+    assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+    let page = format!("<html><body>{}{}</body></html>", "<p>hi</p>".repeat(50), MARK);
+    assert_eq!(response.body, compression::gzip(page.as_bytes()));
+
+    // Registered the other way round, it leaves the compressed body be.
+    let mut router = Router::new();
+    router
+        .middleware(ResponseBodyTransformer::new(|_| b"replaced".to_vec()))
+        .middleware(CompressionMiddleware::new().with_min_size(1))
+        .get("/", |_: &Request| Response::new(200, "OK").with_header("Content-Type", "text/html").with_body("<p>page</p>".repeat(50)));
+    let response = router.dispatch(&request);
+    assert_eq!(response.body, compression::gzip("<p>page</p>".repeat(50).as_bytes()));
+}
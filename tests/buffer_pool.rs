@@ -0,0 +1,109 @@
+// `BufferPool` hands the same allocation back out after a drop, keeps at
+// most `max_buffers` of at most `max_capacity`, and can be shared.
+use std::collections::HashSet;
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+use server_app::pool::BufferPool;
+
+#[test]
+fn a_dropped_buffer_is_reused_cleared_with_its_capacity() {
+    let pool = BufferPool::new(4, 1024);
+    let mut buf = pool.get();
+    buf.extend_from_slice(b"request bytes");
+    buf.reserve(500);
+    let (ptr, capacity) = (buf.as_ptr(), buf.capacity());
+    drop(buf);
+    assert_eq!(pool.pooled(), 1);
+
+    let buf = pool.get();
+    assert_eq!(pool.pooled(), 0);
+    assert!(buf.is_empty());
+    assert_eq!(buf.as_ptr(), ptr);
+    assert_eq!(buf.capacity(), capacity);
+}
+
+#[test]
+fn new_buffers_start_at_the_initial_capacity() {
+    let pool = BufferPool::new(4, 1024).with_initial_capacity(256);
+    assert!(pool.get().capacity() >= 256);
+    // One that was never written to has nothing worth keeping without it.
+    let bare = BufferPool::new(4, 1024);
+    drop(bare.get());
+    assert_eq!(bare.pooled(), 0);
+}
+
+#[test]
+fn at_most_max_buffers_are_kept() {
+    let pool = BufferPool::new(2, 1024).with_initial_capacity(64);
+    let held: Vec<_> = (0..5).map(|_| pool.get()).collect();
+    drop(held);
+    assert_eq!(pool.pooled(), 2);
+}
+
+#[test]
+fn a_buffer_grown_past_max_capacity_is_freed() {
+    let pool = BufferPool::new(4, 1024).with_initial_capacity(1024);
+    let mut big = pool.get();
+    big.resize(4096, 0);
+    drop(big);
+    assert_eq!(pool.pooled(), 0);
+
+    // Filled right up to the limit, but not past it.
+    let mut fits = pool.get();
+    fits.resize(1024, 0);
+    assert_eq!(fits.capacity(), 1024);
+    drop(fits);
+    assert_eq!(pool.pooled(), 1);
+}
+
+#[test]
+fn into_inner_keeps_the_buffer_out_of_the_pool() {
+    let pool = BufferPool::new(4, 1024).with_initial_capacity(64);
+    let mut buf = pool.get();
+    buf.extend_from_slice(b"kept");
+    let owned = buf.into_inner();
+    assert_eq!(owned, b"kept");
+    assert_eq!(pool.pooled(), 0);
+}
+
+#[test]
+fn clones_share_buffers_across_threads() {
+    let pool = BufferPool::new(8, 1024).with_initial_capacity(64);
+    let barrier = Arc::new(Barrier::new(8));
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let pool = pool.clone();
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                for round in 0..1000 {
+                    let mut buf = pool.get();
+                    assert!(buf.is_empty(), "a buffer came back with data in it");
+                    buf.extend_from_slice(&[i as u8; 16]);
+                    buf.push(round as u8);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    // Never more than one buffer per thread was out at once.
+    assert!(pool.pooled() <= 8);
+    assert!(pool.pooled() >= 1);
+    let held: Vec<_> = (0..pool.pooled()).map(|_| pool.get()).collect();
+    let distinct: HashSet<_> = held.iter().map(|buf| buf.as_ptr()).collect();
+    assert_eq!(distinct.len(), held.len(), "a buffer was handed out twice");
+}
+
+#[test]
+fn each_thread_has_its_own_local_pool() {
+    drop(BufferPool::local().get().into_inner());
+    let mut buf = BufferPool::local().get();
+    buf.push(1);
+    drop(buf);
+    assert_eq!(BufferPool::local().pooled(), 1);
+    let other = thread::spawn(|| BufferPool::local().pooled()).join().unwrap();
+    assert_eq!(other, 0);
+}
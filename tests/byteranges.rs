@@ -0,0 +1,184 @@
+// Several ranges of a static file come back as one `multipart/byteranges`
+// body; each part is parsed back out here and checked against the file.
+use std::fs;
+use std::path::PathBuf;
+
+use server_app::http::{MultiRangeResponse, RangePart, Request, Response};
+use server_app::static_files::StaticFileServer;
+
+const LEN: usize = 10_000;
+
+// Bytes that don't repeat every 256, so a part taken from the wrong
+// offset doesn't match by chance.
+fn contents() -> Vec<u8> {
+    (0..LEN).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+fn root(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("byteranges-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("data.bin"), contents()).unwrap();
+    fs::write(dir.join("page.txt"), "0123456789abcdefghij").unwrap();
+    dir
+}
+
+fn fetch(dir: &PathBuf, path: &str, range: &str) -> Response {
+    let mut request = Request::new("GET", path);
+    request.headers.set("Range", range);
+    StaticFileServer::new(dir).handle(&request)
+}
+
+struct Part {
+    content_type: String,
+    content_range: String,
+    data: Vec<u8>,
+}
+
+fn boundary_of(response: &Response) -> String {
+    let content_type = response.header("Content-Type").unwrap();
+    content_type.strip_prefix("multipart/byteranges; boundary=").unwrap_or_else(|| panic!("{}", content_type)).to_string()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// Split a `multipart/byteranges` body into its parts, holding it to the
+// layout RFC 2046 gives: each part opens with `--boundary` and a CRLF,
+// has headers, a blank line and data, and the last boundary has `--`.
+fn parse(response: &Response) -> Vec<Part> {
+    let delimiter = format!("--{}", boundary_of(response));
+    let mut rest = response.body.as_slice();
+    let mut parts = Vec::new();
+    loop {
+        rest = rest.strip_prefix(delimiter.as_bytes()).expect("a boundary");
+        if rest == b"--\r\n" {
+            return parts;
+        }
+        rest = rest.strip_prefix(b"\r\n").expect("CRLF after the boundary");
+        let end = find(rest, b"\r\n\r\n").expect("the end of the part headers");
+        let headers = std::str::from_utf8(&rest[..end]).unwrap();
+        let mut content_type = None;
+        let mut content_range = None;
+        for line in headers.split("\r\n") {
+            let (name, value) = line.split_once(": ").expect("a header");
+            match name {
+                "Content-Type" => content_type = Some(value.to_string()),
+                "Content-Range" => content_range = Some(value.to_string()),
+                other => panic!("unexpected part header {}", other),
+            }
+        }
+        rest = &rest[end + 4..];
+        let next = find(rest, format!("\r\n{}", delimiter).as_bytes()).expect("the next boundary");
+        parts.push(Part {
+            content_type: content_type.expect("a Content-Type"),
+            content_range: content_range.expect("a Content-Range"),
+            data: rest[..next].to_vec(),
+        });
+        rest = &rest[next + 2..];
+    }
+}
+
+// Check each part holds exactly the bytes its `Content-Range` names.
+fn assert_parts_match(parts: &[Part], source: &[u8], expected: &[(usize, usize)]) {
+    assert_eq!(parts.len(), expected.len());
+    for (part, &(first, last)) in parts.iter().zip(expected) {
+        assert_eq!(part.content_range, format!("bytes {}-{}/{}", first, last, source.len()));
+        assert_eq!(part.data, &source[first..=last], "bytes {}-{}", first, last);
+    }
+}
+
+#[test]
+fn each_part_holds_the_bytes_it_says() {
+    let dir = root("parts");
+    let response = fetch(&dir, "/data.bin", "bytes=0-499, 1000-1499, 9000-");
+    assert_eq!(response.status, 206);
+    assert_eq!(response.header("Content-Range"), None, "that's for each part");
+    assert_eq!(response.header("Accept-Ranges"), Some("bytes"));
+    let parts = parse(&response);
+    assert_parts_match(&parts, &contents(), &[(0, 499), (1000, 1499), (9000, LEN - 1)]);
+    assert!(parts.iter().all(|part| part.content_type == "application/octet-stream"));
+
+    let text = fetch(&dir, "/page.txt", "bytes=0-1, 10-11");
+    let parts = parse(&text);
+    assert_parts_match(&parts, b"0123456789abcdefghij", &[(0, 1), (10, 11)]);
+    assert!(parts[0].content_type.starts_with("text/plain"), "{}", parts[0].content_type);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn the_boundary_is_fresh_hex_and_closes_the_body() {
+    let dir = root("boundary");
+    let first = fetch(&dir, "/data.bin", "bytes=0-9, 20-29");
+    let second = fetch(&dir, "/data.bin", "bytes=0-9, 20-29");
+    let boundary = boundary_of(&first);
+    assert_eq!(boundary.len(), 32);
+    assert!(boundary.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()), "{}", boundary);
+    assert_ne!(boundary, boundary_of(&second), "each response gets its own");
+    assert!(first.body.starts_with(format!("--{}\r\n", boundary).as_bytes()));
+    assert!(first.body.ends_with(format!("\r\n--{}--\r\n", boundary).as_bytes()));
+    assert_eq!(String::from_utf8_lossy(&first.body).matches(boundary.as_str()).count(), 3);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn ranges_are_sorted_and_overlaps_merged() {
+    let dir = root("merge");
+    let source = contents();
+    let unsorted = fetch(&dir, "/data.bin", "bytes=5000-5099, 100-199, 2000-2009");
+    assert_parts_match(&parse(&unsorted), &source, &[(100, 199), (2000, 2009), (5000, 5099)]);
+
+    let overlapping = fetch(&dir, "/data.bin", "bytes=0-100, 50-200, 201-300, 1000-1010, 1005-1020");
+    assert_parts_match(&parse(&overlapping), &source, &[(0, 300), (1000, 1020)]);
+
+    // Ranges that merge into one are sent as a plain `206`.
+    let single = fetch(&dir, "/data.bin", "bytes=10-19, 15-29, 20-24");
+    assert_eq!(single.status, 206);
+    assert_eq!(single.header("Content-Range"), Some("bytes 10-29/10000"));
+    assert_eq!(single.body, &source[10..=29]);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn a_suffix_range_takes_the_end() {
+    let dir = root("suffix");
+    let source = contents();
+    let response = fetch(&dir, "/data.bin", "bytes=-500, 0-99");
+    assert_parts_match(&parse(&response), &source, &[(0, 99), (LEN - 500, LEN - 1)]);
+
+    // Longer than the file, it's the whole file and swallows the rest.
+    let whole = fetch(&dir, "/data.bin", "bytes=-20000, 0-99");
+    assert_eq!(whole.header("Content-Range"), Some("bytes 0-9999/10000"));
+    assert_eq!(whole.body, source);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn unsatisfiable_ranges_get_416_with_the_length() {
+    let dir = root("unsatisfiable");
+    for range in ["bytes=10000-", "bytes=20000-30000, 10000-10001", "bytes=-0"] {
+        let response = fetch(&dir, "/data.bin", range);
+        assert_eq!(response.status, 416, "{}", range);
+        assert_eq!(response.header("Content-Range"), Some("bytes */10000"), "{}", range);
+    }
+    // Only the ranges past the end are dropped.
+    let partly = fetch(&dir, "/data.bin", "bytes=0-9, 20000-");
+    assert_eq!((partly.status, partly.header("Content-Range")), (206, Some("bytes 0-9/10000")));
+    // Nonsense is ignored, and the whole file sent.
+    let invalid = fetch(&dir, "/data.bin", "bytes=nine-ten");
+    assert_eq!((invalid.status, invalid.body.len()), (200, LEN));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn the_boundary_never_appears_in_the_data() {
+    let source = contents();
+    let parts = vec![RangePart::slice(&source, &(0..=99)), RangePart::slice(&source, &(200..=299))];
+    let multi = MultiRangeResponse::new("application/octet-stream", parts);
+    let boundary = multi.boundary().to_string();
+    let response = multi.into_response();
+    assert_eq!(boundary_of(&response), boundary);
+    assert_parts_match(&parse(&response), &source, &[(0, 99), (200, 299)]);
+    assert!(find(&source, boundary.as_bytes()).is_none());
+}
@@ -0,0 +1,87 @@
+// `CircularBuffer` keeps the latest items in a fixed amount of memory,
+// for the SSE replay buffer and the access log's recent lines.
+use std::{env, fs, process};
+
+use server_app::access_log::{AccessLog, AccessLogConfig};
+use server_app::collections::CircularBuffer;
+use server_app::sse::{Event, ReplayBuffer};
+
+#[test]
+fn pushing_past_capacity_overwrites_the_oldest() {
+    let mut buffer = CircularBuffer::new(3);
+    assert!(buffer.is_empty());
+    for n in 1..=3 {
+        assert_eq!(buffer.push(n), None);
+    }
+    assert!(buffer.is_full());
+    assert_eq!(buffer.push(4), Some(1));
+    assert_eq!(buffer.push(5), Some(2));
+    assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), [3, 4, 5]);
+    assert_eq!((buffer.get(0), buffer.get(2), buffer.get(3)), (Some(&3), Some(&5), None));
+    assert_eq!(buffer.newest(), Some(&5));
+
+    // Wherever the ring starts, the same items in the same order are equal.
+    let mut other = CircularBuffer::new(3);
+    [3, 4, 5].into_iter().for_each(|n| assert_eq!(other.push(n), None));
+    assert_eq!(buffer, other);
+    assert_eq!(format!("{:?}", buffer), "[3, 4, 5]");
+
+    buffer.clear();
+    assert_eq!((buffer.len(), buffer.newest()), (0, None));
+    assert_eq!(buffer.push(6), None);
+
+    // With no room at all, nothing is kept.
+    let mut none = CircularBuffer::new(0);
+    assert_eq!(none.push("dropped"), Some("dropped"));
+    assert!(none.is_empty());
+}
+
+#[test]
+fn iteration_is_oldest_first_and_length_never_exceeds_capacity() {
+    for capacity in 1..8 {
+        let mut buffer = CircularBuffer::new(capacity);
+        for n in 0..30i32 {
+            buffer.push(n);
+            assert!(buffer.len() <= capacity);
+            let expected: Vec<i32> = ((n + 1 - capacity as i32).max(0)..=n).collect();
+            assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), expected, "capacity {}", capacity);
+            assert_eq!(buffer.iter().len(), expected.len());
+            assert_eq!(buffer.iter().next_back(), expected.last());
+            assert_eq!((&buffer).into_iter().next(), expected.first());
+        }
+        assert_eq!(buffer.capacity(), capacity);
+    }
+}
+
+#[test]
+fn the_sse_replay_buffer_stays_within_its_depth() {
+    let replay = ReplayBuffer::new(4);
+    for n in 0..1000 {
+        replay.record(&Event::new(n.to_string()));
+    }
+    assert_eq!(replay.len(), 4);
+    let data: Vec<String> = replay.since("none").into_iter().map(|event| event.data).collect();
+    assert_eq!(data, ["996", "997", "998", "999"]);
+}
+
+#[test]
+fn the_access_log_keeps_its_latest_lines() {
+    let dir = env::temp_dir().join(format!("circular-buffer-test-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let config = AccessLogConfig { recent_lines: 3, ..AccessLogConfig::new(dir.join("access.log")) };
+    let log = AccessLog::open(config).unwrap();
+    for n in 1..=5 {
+        log.log(format!("line {}", n));
+    }
+    assert_eq!(log.recent(), ["line 3", "line 4", "line 5"]);
+    log.shutdown();
+    // The file still has all of them.
+    assert_eq!(fs::read_to_string(dir.join("access.log")).unwrap().lines().count(), 5);
+
+    let config = AccessLogConfig { recent_lines: 0, ..AccessLogConfig::new(dir.join("none.log")) };
+    let log = AccessLog::open(config).unwrap();
+    log.log("line".to_string());
+    assert!(log.recent().is_empty());
+    log.shutdown();
+    let _ = fs::remove_dir_all(&dir);
+}
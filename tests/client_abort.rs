@@ -0,0 +1,131 @@
+// A client hanging up mid-response is told apart from other write
+// errors, stops streamed bodies and still lets its connection be counted
+// as finished.
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, UNIX_EPOCH};
+
+use server_app::access_log;
+use server_app::http::{Request, Response};
+use server_app::json::{self, Value};
+use server_app::server::{self, Connection, Delivery, Served, Server, ServerConfig};
+use server_app::testing::MockStream;
+
+fn big_response() -> Response {
+    Response::new(200, "OK").with_body(vec![b'x'; 64 * 1024])
+}
+
+#[test]
+fn a_hang_up_is_a_client_abort_with_the_bytes_sent() {
+    let mut connection = Connection::new(MockStream::new(Vec::<Vec<u8>>::new()).fail_after_bytes(1000));
+    match connection.deliver(&big_response()) {
+        Delivery::ClientAborted(bytes) => assert_eq!(bytes, 1000),
+        other => panic!("expected a client abort, got {:?}", other),
+    }
+    assert_eq!(connection.get_ref().written().len(), 1000);
+
+    let mut connection = Connection::new(MockStream::new(Vec::<Vec<u8>>::new()));
+    match connection.deliver(&Response::new(200, "OK").with_body("hi")) {
+        Delivery::Complete(bytes) => assert_eq!(bytes as usize, connection.get_ref().written().len()),
+        other => panic!("expected the response sent, got {:?}", other),
+    }
+}
+
+// A stream that fails every write for some reason other than the client.
+struct FullDisk;
+
+impl Read for FullDisk {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl Write for FullDisk {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("no space left"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn other_write_errors_are_failures() {
+    assert!(server::is_client_abort(&io::Error::from(io::ErrorKind::ConnectionReset)));
+    assert!(!server::is_client_abort(&io::Error::from(io::ErrorKind::TimedOut)));
+    match Connection::new(FullDisk).deliver(&big_response()) {
+        Delivery::Failed(e) => assert_eq!(e.to_string(), "no space left"),
+        other => panic!("expected a failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_hang_up_stops_a_streamed_body() {
+    let pulled = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&pulled);
+    let rows = (0..100_000).map(move |n| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        format!("row {:06} {}\n", n, "-".repeat(100)).into_bytes()
+    });
+    let response = Response::from_iter(200, "text/plain", rows).finalize(&Request::new("GET", "/export"));
+
+    let mut connection = Connection::new(MockStream::new(Vec::<Vec<u8>>::new()).fail_after_bytes(4096));
+    assert!(matches!(connection.deliver(&response), Delivery::ClientAborted(4096)));
+    assert!(pulled.load(Ordering::SeqCst) < 1000, "pulled {} rows", pulled.load(Ordering::SeqCst));
+}
+
+#[test]
+fn the_access_log_records_the_abort() {
+    let request = Request::new("GET", "/export.csv");
+    let response = big_response();
+    let served = Served {
+        id: 7,
+        method: &request.method,
+        path: &request.path,
+        duration: Duration::from_millis(3),
+        response_bytes: response.body.len(),
+        worker: None,
+        client_abort: Some(1000),
+    };
+    let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+    let line = access_log::common_log_line(None, &request, &response, &served, time);
+    assert!(line.ends_with("\"GET /export.csv HTTP/1.1\" 200 1000 client-abort"), "{}", line);
+
+    let value = json::parse(&access_log::json_log_line(None, &request, &response, &served, time)).unwrap();
+    assert_eq!(value.get("level").and_then(Value::as_str), Some("debug"));
+    assert_eq!(value.get("bytes").and_then(Value::as_f64), Some(1000.0));
+    assert_eq!(value.get("client_abort").and_then(Value::as_bool), Some(true));
+
+    let served = Served { client_abort: None, ..served };
+    let line = access_log::common_log_line(None, &request, &response, &served, time);
+    assert!(line.ends_with(" 200 65536"), "{}", line);
+}
+
+#[test]
+fn an_aborted_connection_is_still_counted_as_finished() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, deliveries) = mpsc::channel();
+    let config = ServerConfig { workers: 1, ..ServerConfig::default() };
+    let server = Server::new(config, move |stream, initial, _| {
+        let mut connection = Connection::with_initial(stream, &initial);
+        let response = Response::new(200, "OK").with_body(vec![b'x'; 32 * 1024 * 1024]);
+        let _ = sender.send(connection.deliver(&response));
+    });
+
+    let client = TcpStream::connect(addr).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    drop(client);
+    server.serve(stream);
+
+    match deliveries.recv_timeout(Duration::from_secs(10)).unwrap() {
+        Delivery::ClientAborted(bytes) => assert!(bytes < 32 * 1024 * 1024),
+        other => panic!("expected a client abort, got {:?}", other),
+    }
+    assert!(server.wait_idle(Duration::from_secs(5)));
+    assert_eq!(server.in_flight(), 0);
+}
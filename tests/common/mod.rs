@@ -0,0 +1,110 @@
+// Runs the binary for end-to-end tests: each copy gets its own port, a
+// temp directory holding its config file, and is killed when dropped.
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Running {
+    pub child: Child,
+    pub port: u16,
+    pub dir: PathBuf,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Start the binary with `extra` appended to its `[server]` section, in
+/// the crate directory so it finds `index.html` and the static files.
+pub fn start(name: &str, extra: &str) -> Running {
+    start_with(name, extra, &[])
+}
+
+pub fn start_with(name: &str, extra: &str, args: &[&str]) -> Running {
+    spawn(name, extra, args, Stdio::null(), Path::new(env!("CARGO_MANIFEST_DIR")))
+}
+
+/// `start`, in `cwd` instead of the crate directory, for a server with
+/// none of its files around.
+pub fn start_in(name: &str, extra: &str, cwd: &Path) -> Running {
+    spawn(name, extra, &[], Stdio::null(), cwd)
+}
+
+/// `start`, with each line the server prints sent to the receiver.
+pub fn start_logging(name: &str, extra: &str) -> (Running, mpsc::Receiver<String>) {
+    let mut running = spawn(name, extra, &[], Stdio::piped(), Path::new(env!("CARGO_MANIFEST_DIR")));
+    let stdout = running.child.stdout.take().unwrap();
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    (running, lines)
+}
+
+fn spawn(name: &str, extra: &str, args: &[&str], stdout: Stdio, cwd: &Path) -> Running {
+    // A port nothing else is using, as far as the kernel knows.
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let dir = std::env::temp_dir().join(format!("e2e-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("server.toml");
+    fs::write(&config, format!("[server]\naddr = \"127.0.0.1:{}\"\n{}", port, extra)).unwrap();
+
+    let child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(args)
+        .arg(&config)
+        .current_dir(cwd)
+        .stdout(stdout)
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let running = Running { child, port, dir };
+    let started = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(started.elapsed() < Duration::from_secs(10), "{} server did not start", name);
+        thread::sleep(Duration::from_millis(20));
+    }
+    running
+}
+
+/// Send `request` as it is and read until the server closes.
+pub fn raw(port: u16, request: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(15))).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+/// `GET path` with `headers` (each ending in CRLF), closing after.
+pub fn get(port: u16, path: &str, headers: &str) -> String {
+    raw(port, &format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", path, headers))
+}
+
+/// The value of header `name` in the head of `response`.
+pub fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    let head = response.split("\r\n\r\n").next()?;
+    head.lines().skip(1).find_map(|line| {
+        let (field, value) = line.split_once(':')?;
+        field.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+pub fn body(response: &str) -> &str {
+    response.split_once("\r\n\r\n").map_or("", |(_, body)| body)
+}
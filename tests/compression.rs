@@ -0,0 +1,426 @@
+// `CompressionMiddleware` picks brotli, gzip or deflate from
+// `Accept-Encoding`, and what it sends decodes back to the body,
+// checksums and all.
+#![cfg(feature = "compression")]
+
+use server_app::compression::{self, negotiate_encoding, negotiate_encodings, CompressionMiddleware, Encoding};
+use server_app::hash;
+use server_app::http::{Request, Response};
+use server_app::router::Router;
+
+// A DEFLATE decoder for the stored and fixed-Huffman blocks, which is
+// all the encoder writes.
+struct Bits<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, len: u8) -> u32 {
+        let mut value = 0;
+        for i in 0..len {
+            let byte = self.data[self.bit / 8];
+            value |= u32::from((byte >> (self.bit % 8)) & 1) << i;
+            self.bit += 1;
+        }
+        value
+    }
+
+    // Huffman codes come most significant bit first.
+    fn code(&mut self, len: u8) -> u32 {
+        (0..len).fold(0, |code, _| (code << 1) | self.read(1))
+    }
+
+    fn literal(&mut self) -> u16 {
+        let code = self.code(7);
+        if code <= 0b0010111 {
+            return 256 + code as u16;
+        }
+        let code = (code << 1) | self.read(1);
+        match code {
+            0x30..=0xbf => (code - 0x30) as u16,
+            0xc0..=0xc7 => (280 + code - 0xc0) as u16,
+            _ => (144 + ((code << 1) | self.read(1)) - 0x190) as u16,
+        }
+    }
+}
+
+const LENGTH_BASE: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const DIST_BASE: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+
+fn length_extra(index: usize) -> u8 {
+    if index < 8 || index == 28 { 0 } else { (index / 4 - 1) as u8 }
+}
+
+fn dist_extra(index: usize) -> u8 {
+    if index < 4 { 0 } else { (index / 2 - 1) as u8 }
+}
+
+// Returns the data and how many bytes of `data` the stream took.
+fn inflate(data: &[u8]) -> (Vec<u8>, usize) {
+    let mut bits = Bits { data, bit: 0 };
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let last = bits.read(1) == 1;
+        match bits.read(2) {
+            0 => {
+                bits.bit = bits.bit.div_ceil(8) * 8;
+                let len = bits.read(16) as usize;
+                assert_eq!(bits.read(16) as usize, !len & 0xffff);
+                let start = bits.bit / 8;
+                out.extend_from_slice(&data[start..start + len]);
+                bits.bit += len * 8;
+            }
+            1 => loop {
+                let symbol = usize::from(bits.literal());
+                match symbol {
+                    0..=255 => out.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let index = symbol - 257;
+                        let len = LENGTH_BASE[index] + bits.read(length_extra(index)) as usize;
+                        let index = bits.code(5) as usize;
+                        let dist = DIST_BASE[index] + bits.read(dist_extra(index)) as usize;
+                        assert!(dist <= out.len(), "distance {} past the start", dist);
+                        for _ in 0..len {
+                            out.push(out[out.len() - dist]);
+                        }
+                    }
+                }
+            },
+            kind => panic!("block type {}", kind),
+        }
+        if last {
+            return (out, bits.bit.div_ceil(8));
+        }
+    }
+}
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    assert_eq!(&data[..4], [0x1f, 0x8b, 8, 0], "magic, deflate, no flags");
+    let (out, used) = inflate(&data[10..]);
+    let trailer = &data[10 + used..];
+    assert_eq!(trailer.len(), 8);
+    assert_eq!(u32::from_le_bytes(trailer[..4].try_into().unwrap()), hash::crc32(&out));
+    assert_eq!(u32::from_le_bytes(trailer[4..].try_into().unwrap()), out.len() as u32);
+    out
+}
+
+fn unzlib(data: &[u8]) -> Vec<u8> {
+    assert_eq!(data[0] & 0x0f, 8, "deflate");
+    assert_eq!(u16::from_be_bytes([data[0], data[1]]) % 31, 0);
+    let (out, used) = inflate(&data[2..]);
+    let trailer = &data[2 + used..];
+    assert_eq!(trailer, hash::adler32(&out).to_be_bytes());
+    out
+}
+
+const INSERT_BASE: [usize; 24] = [
+    0, 1, 2, 3, 4, 5, 6, 8, 10, 14, 18, 26, 34, 50, 66, 98, 130, 194, 322, 578, 1090, 2114, 6210, 22594,
+];
+const INSERT_EXTRA: [u8; 24] = [0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 12, 14, 24];
+const COPY_BASE: [usize; 24] = [
+    2, 3, 4, 5, 6, 7, 8, 9, 10, 12, 14, 18, 22, 30, 38, 54, 70, 102, 134, 198, 326, 582, 1094, 2118,
+];
+const COPY_EXTRA: [u8; 24] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 24];
+
+// A canonical prefix code as (length, code, symbol), or a single symbol
+// that takes no bits.
+enum Prefix {
+    Single(usize),
+    Codes(Vec<(u8, u32, usize)>),
+}
+
+impl Prefix {
+    fn from_lengths(lengths: &[u8]) -> Prefix {
+        let used: Vec<usize> = (0..lengths.len()).filter(|&symbol| lengths[symbol] > 0).collect();
+        if used.len() == 1 {
+            return Prefix::Single(used[0]);
+        }
+        let mut codes = Vec::new();
+        let mut code = 0;
+        for len in 1..=15 {
+            for symbol in used.iter().copied().filter(|&symbol| lengths[symbol] == len) {
+                codes.push((len, code, symbol));
+                code += 1;
+            }
+            code <<= 1;
+        }
+        Prefix::Codes(codes)
+    }
+
+    fn decode(&self, bits: &mut Bits) -> usize {
+        let codes = match self {
+            Prefix::Single(symbol) => return *symbol,
+            Prefix::Codes(codes) => codes,
+        };
+        let mut code = 0;
+        for len in 1..=15 {
+            code = (code << 1) | bits.read(1);
+            if let Some((_, _, symbol)) = codes.iter().find(|(l, c, _)| *l == len && *c == code) {
+                return *symbol;
+            }
+        }
+        panic!("no symbol for code {:b}", code);
+    }
+}
+
+// A prefix code as the encoder writes it: simple with one symbol, or
+// complex with no skipped code lengths and no repeat codes.
+fn read_prefix(bits: &mut Bits, alphabet_size: usize, alphabet_bits: u8) -> Prefix {
+    match bits.read(2) {
+        1 => {
+            assert_eq!(bits.read(2), 0, "one symbol");
+            let symbol = bits.read(alphabet_bits) as usize;
+            assert!(symbol < alphabet_size);
+            return Prefix::Single(symbol);
+        }
+        hskip => assert_eq!(hskip, 0, "HSKIP"),
+    }
+    let mut length_lengths = [0u8; 18];
+    let mut space = 32;
+    for symbol in [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15] {
+        let len = match bits.read(2) {
+            0 => 0,
+            1 => 4,
+            2 => 3,
+            _ if bits.read(1) == 0 => 2,
+            _ if bits.read(1) == 0 => 1,
+            _ => 5,
+        };
+        length_lengths[symbol] = len;
+        if len > 0 {
+            space -= 32 >> len;
+            if space <= 0 {
+                break;
+            }
+        }
+    }
+    let length_code = Prefix::from_lengths(&length_lengths);
+
+    let mut lengths = vec![0u8; alphabet_size];
+    let mut space = 32768;
+    for len in lengths.iter_mut() {
+        let symbol = length_code.decode(bits);
+        assert!(symbol <= 15, "repeat code {}", symbol);
+        *len = symbol as u8;
+        if symbol > 0 {
+            space -= 32768 >> symbol;
+            if space == 0 {
+                break;
+            }
+        }
+    }
+    assert_eq!(space, 0, "incomplete code");
+    Prefix::from_lengths(&lengths)
+}
+
+// A brotli decoder for what the encoder writes: compressed meta-blocks
+// with one block type each, one literal and one distance tree, and
+// explicit distances.
+fn unbrotli(data: &[u8]) -> Vec<u8> {
+    let mut bits = Bits { data, bit: 0 };
+    assert_eq!(bits.read(1), 0, "WBITS 16");
+    let mut out = Vec::new();
+    loop {
+        let last = bits.read(1) == 1;
+        if last && bits.read(1) == 1 {
+            assert!(out.is_empty(), "ISLASTEMPTY only for the empty stream");
+        } else {
+            let nibbles = match bits.read(2) {
+                3 => panic!("metadata block"),
+                n => 4 + n as u8,
+            };
+            let mut remaining = bits.read(nibbles * 4) as usize + 1;
+            if !last {
+                assert_eq!(bits.read(1), 0, "ISUNCOMPRESSED");
+            }
+            assert_eq!(bits.read(3), 0, "one block type of each kind");
+            assert_eq!(bits.read(6), 0, "NPOSTFIX and NDIRECT");
+            bits.read(2);
+            assert_eq!(bits.read(2), 0, "one literal and one distance tree");
+            let literals = read_prefix(&mut bits, 256, 8);
+            let commands = read_prefix(&mut bits, 704, 10);
+            let distances = read_prefix(&mut bits, 64, 6);
+            while remaining > 0 {
+                let command = commands.decode(&mut bits);
+                let (insert_group, copy_group) = match command >> 6 {
+                    2 => (0, 0),
+                    3 => (0, 1),
+                    4 => (1, 0),
+                    5 => (1, 1),
+                    6 => (0, 2),
+                    7 => (2, 0),
+                    8 => (1, 2),
+                    9 => (2, 1),
+                    10 => (2, 2),
+                    _ => panic!("implicit distance in command {}", command),
+                };
+                let insert_code = insert_group * 8 + ((command >> 3) & 7);
+                let copy_code = copy_group * 8 + (command & 7);
+                let insert = INSERT_BASE[insert_code] + bits.read(INSERT_EXTRA[insert_code]) as usize;
+                let copy = COPY_BASE[copy_code] + bits.read(COPY_EXTRA[copy_code]) as usize;
+                for _ in 0..insert {
+                    out.push(literals.decode(&mut bits) as u8);
+                }
+                remaining -= insert;
+                if remaining == 0 {
+                    break;
+                }
+                let symbol = distances.decode(&mut bits);
+                assert!(symbol >= 16, "last-distance code {}", symbol);
+                let extra = 1 + ((symbol - 16) >> 1) as u8;
+                let offset = ((2 + ((symbol - 16) & 1)) << extra) - 4;
+                let dist = offset + bits.read(extra) as usize + 1;
+                assert!(dist <= out.len(), "distance {} past the start", dist);
+                for _ in 0..copy {
+                    out.push(out[out.len() - dist]);
+                }
+                remaining -= copy;
+            }
+        }
+        if last {
+            assert_eq!(bits.read(((8 - bits.bit % 8) % 8) as u8), 0, "zero padding");
+            assert_eq!(bits.bit / 8, data.len(), "trailing bytes");
+            return out;
+        }
+    }
+}
+
+fn samples() -> Vec<Vec<u8>> {
+    let mut binary = Vec::new();
+    let mut x: u32 = 12345;
+    for _ in 0..70_000 {
+        x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        binary.push((x >> 16) as u8);
+    }
+    vec![
+        Vec::new(),
+        b"a".to_vec(),
+        b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_vec(),
+        "<li>item</li>\n".repeat(5_000).into_bytes(),
+        (0..=255u8).cycle().take(1_000).collect(),
+        // Every literal with the same code length.
+        (0..=127u8).collect(),
+        // Past the 32K window, with matches reaching all the way back.
+        binary.iter().chain(binary.iter().take(40_000)).copied().collect(),
+        binary,
+    ]
+}
+
+#[test]
+fn every_encoding_round_trips() {
+    for sample in samples() {
+        assert_eq!(gunzip(&compression::gzip(&sample)), sample, "gzip of {} bytes", sample.len());
+        assert_eq!(unzlib(&compression::zlib(&sample)), sample, "zlib of {} bytes", sample.len());
+        assert_eq!(inflate(&compression::deflate(&sample)).0, sample);
+        assert_eq!(gunzip(&Encoding::Gzip.encode(&sample)), sample);
+        assert_eq!(unzlib(&Encoding::Deflate.encode(&sample)), sample);
+        assert_eq!(unbrotli(&compression::brotli(&sample)), sample, "brotli of {} bytes", sample.len());
+        assert_eq!(unbrotli(&Encoding::Brotli.encode(&sample)), sample);
+    }
+    let text = "<li>item</li>\n".repeat(5_000).into_bytes();
+    assert!(compression::gzip(&text).len() < text.len() / 20);
+    assert!(compression::brotli(&text).len() < text.len() / 20);
+}
+
+#[test]
+fn brotli_writes_compressed_meta_blocks() {
+    // The empty stream is the well-known single byte.
+    assert_eq!(compression::brotli(b""), [0x06]);
+    // WBITS, ISLAST, ISLASTEMPTY, MNIBBLES, then MLEN - 1 = 1 over the
+    // next 16 bits.
+    let encoded = compression::brotli(b"hi");
+    assert_eq!(encoded[..2], [0x22, 0x00]);
+    assert_eq!(unbrotli(&encoded), b"hi");
+    // Past 64K, into a five-nibble length.
+    let big = vec![b'x'; (1 << 16) + 1];
+    let encoded = compression::brotli(&big);
+    assert!(encoded.len() < 1_000);
+    assert_eq!(unbrotli(&encoded), big);
+}
+
+#[test]
+fn the_client_preference_picks_the_encoding() {
+    assert_eq!(negotiate_encoding("gzip"), Some(Encoding::Gzip));
+    assert_eq!(negotiate_encoding("x-gzip"), Some(Encoding::Gzip));
+    assert_eq!(negotiate_encoding("deflate"), Some(Encoding::Deflate));
+    assert_eq!(negotiate_encoding("gzip, deflate"), Some(Encoding::Gzip));
+    assert_eq!(negotiate_encoding("deflate, gzip"), Some(Encoding::Gzip), "a tie goes to gzip over deflate");
+    assert_eq!(negotiate_encoding("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+    assert_eq!(negotiate_encoding("GZIP;Q=0.2, Deflate;q=0.1"), Some(Encoding::Gzip));
+    assert_eq!(negotiate_encoding("*"), Some(Encoding::Brotli));
+    assert_eq!(negotiate_encoding("*;q=0.5, br;q=0, gzip;q=0"), Some(Encoding::Deflate));
+    assert_eq!(negotiate_encoding("gzip;q=0, deflate;q=0"), None);
+    assert_eq!(negotiate_encoding("identity"), None);
+    assert_eq!(negotiate_encoding(""), None);
+    assert_eq!(negotiate_encoding("br"), Some(Encoding::Brotli));
+    assert_eq!(negotiate_encoding("BR;q=0.4"), Some(Encoding::Brotli));
+    // A tie goes to brotli, but not a lower quality value.
+    assert_eq!(negotiate_encoding("gzip, deflate, br"), Some(Encoding::Brotli));
+    assert_eq!(negotiate_encoding("gzip;q=0.8, br;q=0.8"), Some(Encoding::Brotli));
+    assert_eq!(negotiate_encoding("gzip, br;q=0.9"), Some(Encoding::Gzip));
+
+    assert_eq!(negotiate_encodings("gzip, deflate, br"), [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate]);
+    assert_eq!(negotiate_encodings("deflate;q=0.9, br;q=0.5, gzip;q=0.9"), [Encoding::Gzip, Encoding::Deflate, Encoding::Brotli]);
+    assert_eq!(negotiate_encodings("br;q=0, identity"), []);
+}
+
+fn serve(content_type: &'static str, body: Vec<u8>, accept_encoding: &str) -> Response {
+    let mut router = Router::new();
+    router
+        .middleware(CompressionMiddleware::new())
+        .get("/", move |_: &Request| Response::new(200, "OK").with_header("Content-Type", content_type).with_body(body.clone()));
+    let mut request = Request::new("GET", "/");
+    request.headers.set("Accept-Encoding", accept_encoding);
+    router.dispatch(&request)
+}
+
+#[test]
+fn compressed_responses_decode_to_the_original() {
+    let body = "{\"items\": [1, 2, 3]}\n".repeat(100).into_bytes();
+    let response = serve("application/json", body.clone(), "gzip");
+    assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(gunzip(&response.body), body);
+
+    let response = serve("text/html; charset=utf-8", body.clone(), "deflate");
+    assert_eq!(response.header("Content-Encoding"), Some("deflate"));
+    assert_eq!(unzlib(&response.body), body);
+
+    // Left alone: other types, small bodies, and clients that don't ask.
+    let response = serve("image/png", body.clone(), "gzip");
+    assert_eq!((response.header("Content-Encoding"), response.header("Vary")), (None, None));
+    let response = serve("text/plain", b"short".to_vec(), "gzip");
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+}
+
+#[test]
+fn brotli_is_sent_when_preferred() {
+    let body = "{\"items\": [1, 2, 3]}\n".repeat(100).into_bytes();
+    let response = serve("application/json", body.clone(), "gzip, deflate, br");
+    assert_eq!(response.header("Content-Encoding"), Some("br"));
+    assert_eq!(unbrotli(&response.body), body);
+    let response = serve("application/json", body.clone(), "br;q=0.5, deflate");
+    assert_eq!(response.header("Content-Encoding"), Some("deflate"));
+}
+
+#[test]
+fn a_body_no_encoding_shrinks_goes_as_it_is() {
+    let mut body = Vec::new();
+    let mut x: u32 = 12345;
+    for _ in 0..2_000 {
+        x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        body.push((x >> 16) as u8);
+    }
+    let response = serve("text/plain", body.clone(), "br, gzip, deflate");
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(response.body, body);
+}
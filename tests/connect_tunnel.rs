@@ -0,0 +1,100 @@
+// `CONNECT` through `ConnectHandler` opens a tunnel that carries
+// whatever the client sends, here a plain HTTP exchange.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use server_app::http::Request;
+use server_app::proxy::ConnectHandler;
+use server_app::router::Router;
+use server_app::server::{Connection, Incoming, ServerConfig};
+
+// An upstream that answers one request and closes.
+fn upstream() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut chunk = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let n = stream.read(&mut chunk).unwrap();
+            request.extend_from_slice(&chunk[..n]);
+        }
+        assert!(request.starts_with(b"GET /through HTTP/1.1\r\n"));
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\ntunneled").unwrap();
+    });
+    port
+}
+
+// A proxy that serves one connection with `handler` in front of an empty router.
+fn proxy(handler: ConnectHandler) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let mut router = Router::new();
+    router.middleware(handler);
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let config = ServerConfig { allowed_hosts: Vec::new(), connect_tunnel: true, ..ServerConfig::default() };
+        let mut connection = Connection::new(stream);
+        let request = match connection.read_request(&config, |_| None) {
+            Incoming::Request(request) => request,
+            _ => panic!("expected a request"),
+        };
+        let response = router.dispatch(&request).finalize(&request);
+        connection.send(&response).unwrap();
+        if let Some(upgrade) = response.upgrade {
+            upgrade.run(connection.into_inner());
+        }
+    });
+    addr
+}
+
+fn connect(proxy: &str, target: &str) -> (TcpStream, String) {
+    let mut client = TcpStream::connect(proxy).unwrap();
+    write!(client, "CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, target).unwrap();
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") && client.read(&mut byte).unwrap() == 1 {
+        head.push(byte[0]);
+    }
+    (client, String::from_utf8(head).unwrap())
+}
+
+#[test]
+fn tunnels_a_request_to_the_target() {
+    let port = upstream();
+    let proxy = proxy(ConnectHandler::new().with_allowed_ports(vec![port]));
+    let (mut client, head) = connect(&proxy, &format!("127.0.0.1:{}", port));
+    assert_eq!(head, "HTTP/1.1 200 Connection Established\r\n\r\n");
+
+    client.write_all(b"GET /through HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n").unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\ntunneled"));
+}
+
+#[test]
+fn refuses_ports_not_allowed() {
+    let proxy = proxy(ConnectHandler::new());
+    let (_, head) = connect(&proxy, "127.0.0.1:25");
+    assert!(head.starts_with("HTTP/1.1 403 "), "{}", head);
+}
+
+#[test]
+fn unreachable_targets_are_a_bad_gateway() {
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let proxy = proxy(ConnectHandler::new().with_allowed_ports(Vec::new()));
+    let (_, head) = connect(&proxy, &format!("127.0.0.1:{}", closed));
+    assert!(head.starts_with("HTTP/1.1 502 "), "{}", head);
+}
+
+#[test]
+fn other_methods_pass_through() {
+    let mut router = Router::new();
+    router.middleware(ConnectHandler::new());
+    router.get("/", |_: &Request| server_app::http::Response::new(200, "OK"));
+    assert_eq!(router.dispatch(&Request::new("GET", "/")).status, 200);
+    assert_eq!(router.dispatch(&Request::new("CONNECT", "/")).status, 400);
+}
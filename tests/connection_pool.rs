@@ -0,0 +1,90 @@
+// `ConnectionPool` on its own: checkouts dial or reuse, only connections
+// marked reusable come back, and the pool keeps no more than its
+// capacity and nothing dead or expired.
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use server_app::pool::{ConnectionPool, PoolStats};
+
+// A listener that accepts connections and holds them open, or closes
+// each straight away.
+fn listener(keep_open: bool) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut open = Vec::new();
+        for stream in listener.incoming() {
+            if keep_open {
+                open.push(stream.unwrap());
+            }
+        }
+    });
+    addr
+}
+
+#[test]
+fn an_empty_pool_dials() {
+    let pool = Arc::new(ConnectionPool::new(listener(true), 2));
+    let conn = pool.acquire().unwrap();
+    assert!(!conn.reused());
+    assert_eq!(conn.addr(), pool.addr());
+    assert_eq!(pool.stats(), PoolStats { hits: 0, misses: 1, discards: 0 });
+}
+
+#[test]
+fn only_connections_marked_reusable_are_released() {
+    let pool = Arc::new(ConnectionPool::new(listener(true), 2));
+    drop(pool.acquire().unwrap());
+    assert_eq!(pool.idle_count(), 0, "dropped part-way, so closed");
+
+    let mut conn = pool.acquire().unwrap();
+    conn.mark_reusable();
+    let local = conn.local_addr().unwrap();
+    drop(conn);
+    assert_eq!(pool.idle_count(), 1);
+
+    let conn = pool.acquire().unwrap();
+    assert!(conn.reused());
+    assert_eq!(conn.local_addr().unwrap(), local);
+    assert_eq!(pool.idle_count(), 0);
+    assert_eq!(pool.stats(), PoolStats { hits: 1, misses: 2, discards: 0 });
+}
+
+#[test]
+fn connections_past_the_capacity_are_closed() {
+    let pool = Arc::new(ConnectionPool::new(listener(true), 1));
+    let mut first = pool.acquire().unwrap();
+    let mut second = pool.acquire().unwrap();
+    first.mark_reusable();
+    second.mark_reusable();
+    drop(first);
+    drop(second);
+    assert_eq!(pool.idle_count(), 1);
+    assert_eq!(pool.stats().discards, 1);
+}
+
+#[test]
+fn expired_connections_are_evicted() {
+    let pool = Arc::new(ConnectionPool::new(listener(true), 2).with_idle_timeout(Duration::from_millis(100)));
+    let mut conn = pool.acquire().unwrap();
+    conn.mark_reusable();
+    drop(conn);
+
+    thread::sleep(Duration::from_millis(150));
+    assert!(!pool.acquire().unwrap().reused(), "the idle one had expired");
+    assert_eq!(pool.stats(), PoolStats { hits: 0, misses: 2, discards: 1 });
+}
+
+#[test]
+fn connections_the_peer_closed_are_evicted() {
+    let pool = Arc::new(ConnectionPool::new(listener(false), 2));
+    let mut conn = pool.acquire().unwrap();
+    conn.mark_reusable();
+    drop(conn);
+    thread::sleep(Duration::from_millis(50)); // Let the close arrive.
+
+    assert!(!pool.acquire().unwrap().reused());
+    assert_eq!(pool.stats(), PoolStats { hits: 0, misses: 2, discards: 1 });
+}
@@ -0,0 +1,48 @@
+// `Response::as_attachment`, and the static file server applying it by
+// extension.
+use std::{env, fs, process};
+
+use server_app::encoding;
+use server_app::http::{Request, Response};
+use server_app::static_files::StaticFileServer;
+
+fn disposition(filename: &str) -> String {
+    let response = Response::new(200, "OK").as_attachment(filename);
+    response.headers.get("Content-Disposition").unwrap().to_string()
+}
+
+#[test]
+fn ascii_names_are_quoted() {
+    assert_eq!(disposition("report.pdf"), "attachment; filename=\"report.pdf\"");
+    assert_eq!(disposition("say \"hi\".txt"), "attachment; filename=\"say \\\"hi\\\".txt\"");
+}
+
+#[test]
+fn spaces_stay_in_the_quoted_name() {
+    assert_eq!(disposition("annual report 2024.pdf"), "attachment; filename=\"annual report 2024.pdf\"");
+}
+
+#[test]
+fn non_ascii_names_get_an_encoded_filename_star() {
+    assert_eq!(
+        disposition("naïve résumé.pdf"),
+        "attachment; filename=\"na_ve r_sum_.pdf\"; filename*=UTF-8''na%C3%AFve%20r%C3%A9sum%C3%A9.pdf",
+    );
+    assert_eq!(disposition("данные.csv"), "attachment; filename=\"______.csv\"; filename*=UTF-8''%D0%B4%D0%B0%D0%BD%D0%BD%D1%8B%D0%B5.csv");
+    assert_eq!(encoding::rfc5987_encode("a b'c"), "UTF-8''a%20b%27c");
+}
+
+#[test]
+fn download_extensions_make_attachments() {
+    let root = env::temp_dir().join(format!("disposition-test-{}", process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("Manual.PDF"), b"%PDF").unwrap();
+    fs::write(root.join("page.html"), b"<p>hi</p>").unwrap();
+    let server = StaticFileServer::new(&root).with_download_extensions(vec![".pdf".to_string(), "zip".to_string()]);
+
+    let pdf = server.handle(&Request::new("GET", "/Manual.PDF"));
+    assert_eq!(pdf.headers.get("Content-Disposition"), Some("attachment; filename=\"Manual.PDF\""));
+    let page = server.handle(&Request::new("GET", "/page.html"));
+    assert_eq!(page.headers.get("Content-Disposition"), None);
+    fs::remove_dir_all(root).unwrap();
+}
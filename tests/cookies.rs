@@ -0,0 +1,86 @@
+// `Set-Cookie` values carry their attributes, and names with a
+// `__Host-` or `__Secure-` prefix must have the attributes it promises.
+use std::time::{Duration, UNIX_EPOCH};
+
+use server_app::http::{self, CookieError, CookieOptions, Response, SameSite};
+
+fn host_options() -> CookieOptions {
+    CookieOptions { path: Some("/".to_string()), secure: true, ..CookieOptions::default() }
+}
+
+#[test]
+fn cookies_are_written_with_their_attributes() {
+    let mut response = Response::new(200, "OK");
+    response.set_cookie("theme", "dark", &CookieOptions::default()).unwrap();
+    let session = CookieOptions {
+        http_only: true,
+        same_site: Some(SameSite::Strict),
+        max_age: Some(3600),
+        ..host_options()
+    };
+    response.set_cookie("__Host-session", "abc123", &session).unwrap();
+    let tracking = CookieOptions {
+        domain: Some("example.com".to_string()),
+        secure: true,
+        same_site: Some(SameSite::None),
+        ..CookieOptions::default()
+    };
+    response.set_cookie("__Secure-id", "\"quoted\"", &tracking).unwrap();
+
+    let cookies: Vec<&str> = response.headers.get_all("Set-Cookie").collect();
+    assert_eq!(
+        cookies,
+        [
+            "theme=dark",
+            "__Host-session=abc123; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Strict",
+            "__Secure-id=\"quoted\"; Domain=example.com; Secure; SameSite=None",
+        ]
+    );
+    assert_eq!(
+        http::set_cookie_value("lang", "en", &CookieOptions { same_site: Some(SameSite::Lax), ..CookieOptions::default() }),
+        Ok("lang=en; SameSite=Lax".to_string())
+    );
+    let expiring = CookieOptions {
+        max_age: Some(0),
+        expires: Some(UNIX_EPOCH + Duration::from_secs(784_111_777)),
+        ..CookieOptions::default()
+    };
+    assert_eq!(
+        http::set_cookie_value("old", "", &expiring),
+        Ok("old=; Max-Age=0; Expires=Sun, 06 Nov 1994 08:49:37 GMT".to_string())
+    );
+}
+
+#[test]
+fn prefixes_are_enforced() {
+    let violations = [
+        ("__Host-a", CookieOptions { secure: false, ..host_options() }),
+        ("__Host-a", CookieOptions { path: None, ..host_options() }),
+        ("__Host-a", CookieOptions { path: Some("/app".to_string()), ..host_options() }),
+        ("__Host-a", CookieOptions { domain: Some("example.com".to_string()), ..host_options() }),
+        ("__Secure-a", CookieOptions::default()),
+        ("__Secure-a", CookieOptions { path: Some("/".to_string()), ..CookieOptions::default() }),
+    ];
+    for (name, options) in violations {
+        let mut response = Response::new(200, "OK");
+        assert_eq!(response.set_cookie(name, "1", &options), Err(CookieError::InvalidPrefix), "{} {:?}", name, options);
+        assert_eq!(response.headers.get("Set-Cookie"), None);
+    }
+    // Only the exact, case-sensitive prefixes count.
+    assert!(http::set_cookie_value("__host-a", "1", &CookieOptions::default()).is_ok());
+    assert!(http::set_cookie_value("Host-a", "1", &CookieOptions::default()).is_ok());
+}
+
+#[test]
+fn bad_names_values_and_attributes_are_refused() {
+    let none = CookieOptions::default();
+    assert_eq!(http::set_cookie_value("", "1", &none), Err(CookieError::InvalidName));
+    assert_eq!(http::set_cookie_value("a b", "1", &none), Err(CookieError::InvalidName));
+    assert_eq!(http::set_cookie_value("a=b", "1", &none), Err(CookieError::InvalidName));
+    for value in ["a b", "a;b", "a,b", "a\\b", "a\"b", "a\r\nSet-Cookie: x=1"] {
+        assert_eq!(http::set_cookie_value("a", value, &none), Err(CookieError::InvalidValue), "{:?}", value);
+    }
+    let injected = CookieOptions { path: Some("/; Domain=evil.example".to_string()), ..CookieOptions::default() };
+    assert_eq!(http::set_cookie_value("a", "1", &injected), Err(CookieError::InvalidAttribute));
+    assert_eq!(http::set_cookie_value("a", "", &none), Ok("a=".to_string()));
+}
@@ -0,0 +1,188 @@
+// Digest authentication: a fresh nonce with the right credentials gets
+// through, and counting up `nc` keeps it going; a replayed request or an
+// expired nonce gets a `stale=true` challenge, and bad credentials a
+// plain one.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use server_app::clock::MockClock;
+use server_app::hash;
+use server_app::http::{Request, Response};
+use server_app::middleware::{DigestAuthMiddleware, DEFAULT_NONCE_TTL};
+use server_app::router::Router;
+
+const REALM: &str = "api@example.com";
+
+fn router(middleware: DigestAuthMiddleware) -> Router {
+    let mut router = Router::new();
+    router.middleware(middleware).get("/private", |_: &Request| Response::new(200, "OK").with_body("secret"));
+    router
+}
+
+fn middleware(clock: &Arc<MockClock>) -> DigestAuthMiddleware {
+    DigestAuthMiddleware::new(REALM).with_user("Mufasa", "Circle of Life").with_clock(Arc::clone(clock) as _)
+}
+
+fn sha256_hex(text: &str) -> String {
+    hash::to_hex(&hash::sha256(text.as_bytes()))
+}
+
+// The `WWW-Authenticate` parameters of a 401, without the quotes.
+fn challenge(response: &Response) -> HashMap<String, String> {
+    assert_eq!(response.status, 401);
+    let value = response.header("WWW-Authenticate").expect("a challenge");
+    let params = value.strip_prefix("Digest ").expect("a Digest challenge");
+    params
+        .split(", ")
+        .map(|param| {
+            let (name, value) = param.split_once('=').unwrap();
+            (name.to_string(), value.trim_matches('"').to_string())
+        })
+        .collect()
+}
+
+// What a client with `password` sends for `GET uri` with `nonce`, its
+// `nc`th time.
+fn authorization(nonce: &str, nc: u32, uri: &str, password: &str) -> String {
+    let cnonce = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+    let ha1 = sha256_hex(&format!("Mufasa:{}:{}", REALM, password));
+    let ha2 = sha256_hex(&format!("GET:{}", uri));
+    let response = sha256_hex(&format!("{}:{}:{:08x}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2));
+    format!(
+        "Digest username=\"Mufasa\", realm=\"{}\", uri=\"{}\", algorithm=SHA-256, nonce=\"{}\", nc={:08x}, \
+         cnonce=\"{}\", qop=auth, response=\"{}\"",
+        REALM, uri, nonce, nc, cnonce, response
+    )
+}
+
+fn get(router: &Router, authorization: Option<&str>) -> Response {
+    let mut request = Request::new("GET", "/private");
+    if let Some(authorization) = authorization {
+        request.headers.set("Authorization", authorization);
+    }
+    router.dispatch(&request)
+}
+
+fn fresh_nonce(router: &Router) -> String {
+    challenge(&get(router, None))["nonce"].clone()
+}
+
+#[test]
+fn a_request_without_credentials_is_challenged() {
+    let router = router(middleware(&Arc::new(MockClock::new())));
+    let response = get(&router, None);
+    let params = challenge(&response);
+    assert_eq!(params["realm"], REALM);
+    assert_eq!(params["qop"], "auth");
+    assert_eq!(params["algorithm"], "SHA-256");
+    assert!(!params.contains_key("stale"));
+    assert_eq!(response.body, b"Unauthorized");
+    // Every challenge has a nonce of its own.
+    assert_ne!(params["nonce"], fresh_nonce(&router));
+}
+
+#[test]
+fn a_fresh_nonce_gets_through_and_nc_counts_up() {
+    let router = router(middleware(&Arc::new(MockClock::new())));
+    let nonce = fresh_nonce(&router);
+    for nc in 1..=3 {
+        let response = get(&router, Some(&authorization(&nonce, nc, "/private", "Circle of Life")));
+        assert_eq!((response.status, response.body.as_slice()), (200, &b"secret"[..]), "nc {}", nc);
+    }
+    // Counts may be skipped, as when requests go out in parallel.
+    assert_eq!(get(&router, Some(&authorization(&nonce, 9, "/private", "Circle of Life"))).status, 200);
+}
+
+#[test]
+fn a_replayed_request_is_stale() {
+    let router = router(middleware(&Arc::new(MockClock::new())));
+    let nonce = fresh_nonce(&router);
+    let sent = authorization(&nonce, 1, "/private", "Circle of Life");
+    assert_eq!(get(&router, Some(&sent)).status, 200);
+
+    let replayed = get(&router, Some(&sent));
+    let params = challenge(&replayed);
+    assert_eq!(params["stale"], "true");
+    assert_eq!(replayed.body, b"Stale nonce");
+    assert_ne!(params["nonce"], nonce);
+
+    // The client retries with the new nonce, without asking the user again.
+    let retried = get(&router, Some(&authorization(&params["nonce"], 1, "/private", "Circle of Life")));
+    assert_eq!(retried.status, 200);
+}
+
+#[test]
+fn an_expired_nonce_is_stale() {
+    let clock = Arc::new(MockClock::new());
+    let router = router(middleware(&clock).with_nonce_ttl(Duration::from_secs(60)));
+    let nonce = fresh_nonce(&router);
+    assert_eq!(get(&router, Some(&authorization(&nonce, 1, "/private", "Circle of Life"))).status, 200);
+
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(get(&router, Some(&authorization(&nonce, 2, "/private", "Circle of Life"))).status, 200, "good to the end");
+    clock.advance(Duration::from_secs(1));
+    let response = get(&router, Some(&authorization(&nonce, 3, "/private", "Circle of Life")));
+    assert_eq!(challenge(&response)["stale"], "true");
+
+    // Only right credentials learn that the nonce is the trouble.
+    let wrong = get(&router, Some(&authorization(&nonce, 4, "/private", "Hakuna Matata")));
+    assert!(!challenge(&wrong).contains_key("stale"));
+}
+
+#[test]
+fn replays_are_remembered_for_as_long_as_the_nonce_lasts() {
+    let clock = Arc::new(MockClock::new());
+    let router = router(middleware(&clock));
+    // Issued halfway to the filter's first rotation, so the nonce outlives it.
+    clock.advance(DEFAULT_NONCE_TTL / 2);
+    let nonce = fresh_nonce(&router);
+    let sent = authorization(&nonce, 1, "/private", "Circle of Life");
+    assert_eq!(get(&router, Some(&sent)).status, 200);
+
+    clock.advance(DEFAULT_NONCE_TTL / 2 + Duration::from_secs(1));
+    assert_eq!(challenge(&get(&router, Some(&sent)))["stale"], "true");
+    // The nonce itself is still good; it was the replay that was caught.
+    assert_eq!(get(&router, Some(&authorization(&nonce, 2, "/private", "Circle of Life"))).status, 200);
+}
+
+#[test]
+fn bad_credentials_and_forged_nonces_get_a_plain_challenge() {
+    let router = router(middleware(&Arc::new(MockClock::new())));
+    let nonce = fresh_nonce(&router);
+
+    let wrong = get(&router, Some(&authorization(&nonce, 1, "/private", "Hakuna Matata")));
+    assert!(!challenge(&wrong).contains_key("stale"));
+    assert_eq!(wrong.body, b"Unauthorized");
+    // And a failed attempt doesn't use up its `nc`.
+    assert_eq!(get(&router, Some(&authorization(&nonce, 1, "/private", "Circle of Life"))).status, 200);
+
+    // A nonce from another middleware, or tampered with, wasn't issued here.
+    let other = DigestAuthMiddleware::new(REALM).nonce();
+    let response = get(&router, Some(&authorization(&other, 1, "/private", "Circle of Life")));
+    assert!(!challenge(&response).contains_key("stale"));
+    let (signed, mac) = nonce.rsplit_once('.').unwrap();
+    let tampered = format!("{}.{}{}", signed, if mac.starts_with('0') { '1' } else { '0' }, &mac[1..]);
+    let response = get(&router, Some(&authorization(&tampered, 2, "/private", "Circle of Life")));
+    assert!(!challenge(&response).contains_key("stale"));
+
+    for authorization in ["Basic TXVmYXNhOkNpcmNsZSBvZiBMaWZl", "Digest", "Digest username=\"Mufasa\""] {
+        assert!(!challenge(&get(&router, Some(authorization))).contains_key("stale"), "{}", authorization);
+    }
+}
+
+#[test]
+fn a_uri_for_another_target_is_a_400() {
+    let router = router(middleware(&Arc::new(MockClock::new())));
+    let nonce = fresh_nonce(&router);
+    let response = get(&router, Some(&authorization(&nonce, 1, "/other", "Circle of Life")));
+    assert_eq!(response.status, 400);
+    assert_eq!(response.body, b"Digest uri does not match the request target");
+}
+
+#[test]
+fn the_debug_output_leaves_out_passwords() {
+    let debug = format!("{:?}", middleware(&Arc::new(MockClock::new())));
+    assert!(debug.contains("Mufasa"), "{}", debug);
+    assert!(!debug.contains("Circle of Life"), "{}", debug);
+}
@@ -0,0 +1,103 @@
+// `DirectoryIndex` keeps file metadata in memory, and its watcher picks
+// up changes on disk within a polling cycle or two.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use server_app::http::Request;
+use server_app::static_files::{DirectoryIndex, StaticFileServer};
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("directory-index-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("css")).unwrap();
+        fs::write(dir.join("a.txt"), "first").unwrap();
+        fs::write(dir.join("css/site.css"), "body {}").unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Wait up to `limit` for `done`.
+fn within(limit: Duration, done: impl Fn() -> bool) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < limit {
+        if done() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    done()
+}
+
+#[test]
+fn every_file_is_indexed_once() {
+    let dir = TempDir::new("scan");
+    let index = DirectoryIndex::new(&dir.0).unwrap();
+    assert_eq!(index.len(), 2);
+    let entry = index.get(Path::new("css/site.css")).unwrap();
+    assert_eq!(entry.len, 7);
+    assert!(entry.etag.starts_with("\"7-"));
+    assert_eq!(index.get(Path::new("css")), None);
+    assert_eq!(index.refresh(), 0, "nothing changed");
+}
+
+#[test]
+fn refresh_sees_changed_added_and_removed_files() {
+    let dir = TempDir::new("refresh");
+    let index = DirectoryIndex::new(&dir.0).unwrap();
+    let before = index.get(Path::new("a.txt")).unwrap();
+
+    fs::write(dir.0.join("a.txt"), "second version").unwrap();
+    fs::write(dir.0.join("css/print.css"), "").unwrap();
+    fs::remove_file(dir.0.join("css/site.css")).unwrap();
+    assert_eq!(index.refresh(), 3);
+
+    let after = index.get(Path::new("a.txt")).unwrap();
+    assert_eq!(after.len, 14);
+    assert_ne!(after.etag, before.etag);
+    assert!(index.get(Path::new("css/print.css")).is_some());
+    assert!(index.get(Path::new("css/site.css")).is_none());
+}
+
+#[test]
+fn the_watcher_updates_within_two_polling_cycles() {
+    let interval = Duration::from_millis(100);
+    let dir = TempDir::new("watch");
+    let index = Arc::new(DirectoryIndex::new(&dir.0).unwrap());
+    let server = StaticFileServer::with_index(Arc::clone(&index));
+    let _watcher = index.watch(interval);
+
+    fs::write(dir.0.join("a.txt"), "changed!").unwrap();
+    fs::write(dir.0.join("new.txt"), "new").unwrap();
+    let changed = within(interval * 2, || {
+        index.get(Path::new("a.txt")).is_some_and(|entry| entry.len == 8) && index.get(Path::new("new.txt")).is_some()
+    });
+    assert!(changed, "the index still has the old metadata");
+
+    let response = server.handle(&Request::new("GET", "/a.txt"));
+    assert_eq!(response.body, b"changed!");
+    assert_eq!(response.header("ETag"), Some(index.get(Path::new("a.txt")).unwrap().etag.as_str()));
+    assert_eq!(server.handle(&Request::new("GET", "/new.txt")).status, 200);
+}
+
+#[test]
+fn files_the_index_does_not_know_yet_are_missing() {
+    let dir = TempDir::new("unknown");
+    let index = Arc::new(DirectoryIndex::new(&dir.0).unwrap());
+    let server = StaticFileServer::with_index(Arc::clone(&index));
+    fs::write(dir.0.join("late.txt"), "late").unwrap();
+    assert_eq!(server.handle(&Request::new("GET", "/late.txt")).status, 404);
+    index.refresh();
+    assert_eq!(server.handle(&Request::new("GET", "/late.txt")).status, 200);
+}
@@ -0,0 +1,101 @@
+// SIGTERM drains the running binary: it stops accepting, `/readyz` turns
+// to 503 for connections still open, the request in hand is finished,
+// and whatever is left when `drain_timeout_secs` runs out is cut off.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A 100,000-row CSV, about 2.6MB, sent chunked.
+const EXPORT: &str = "GET /export.csv HTTP/1.1\r\nHost: localhost\r\n\r\n";
+const END: &str = "\r\n0\r\n\r\n";
+
+fn terminate(server: &common::Running) {
+    let status = Command::new("kill").args(["-TERM", &server.child.id().to_string()]).status().unwrap();
+    assert!(status.success());
+}
+
+// Wait for the process to exit, returning when it did.
+fn exited(server: &mut common::Running, within: Duration) -> Instant {
+    let deadline = Instant::now() + within;
+    loop {
+        if server.child.try_wait().unwrap().is_some() {
+            return Instant::now();
+        }
+        assert!(Instant::now() < deadline, "the server did not exit");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+// A connection with an export in flight: two are asked for, and with
+// nobody reading, the first fills the server's send buffer (4MB at most)
+// and the second is held up partway.
+fn export_in_flight(port: u16) -> TcpStream {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(15))).unwrap();
+    stream.write_all(format!("{}{}", EXPORT, EXPORT).as_bytes()).unwrap();
+    let mut start = [0; 64];
+    stream.read_exact(&mut start).unwrap();
+    assert!(start.starts_with(b"HTTP/1.1 200 "));
+    thread::sleep(Duration::from_millis(300));
+    stream
+}
+
+#[test]
+fn in_flight_requests_finish_and_readyz_turns_503() {
+    let mut server = common::start("drain-finish", "drain_timeout_secs = 10\n");
+    let ready = common::get(server.port, "/readyz", "");
+    assert!(ready.starts_with("HTTP/1.1 200 "), "{}", ready);
+    assert_eq!(common::body(&ready), "ready");
+
+    let mut exporting = export_in_flight(server.port);
+    let terminated = Instant::now();
+    terminate(&server);
+    thread::sleep(Duration::from_millis(300));
+
+    // No new connections once draining has begun.
+    assert!(TcpStream::connect(("127.0.0.1", server.port)).is_err());
+
+    // The export in hand runs to its end, then a `/readyz` sent on the same
+    // connection says we're going away, and the connection closes.
+    exporting.write_all(b"GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut rest = Vec::new();
+    exporting.read_to_end(&mut rest).unwrap();
+    let rest = String::from_utf8(rest).unwrap();
+    let responses: Vec<&str> = rest.split(END).collect();
+    assert_eq!(responses.len(), 3, "both exports finish");
+    assert!(responses[..2].iter().all(|export| export.ends_with("\n100000,item-100000,0\n")));
+    let readyz = responses[2];
+    assert!(readyz.starts_with("HTTP/1.1 503 "), "{}", readyz);
+    assert_eq!(common::header(readyz, "Connection"), Some("close"));
+    assert_eq!(common::body(readyz), "draining");
+
+    // With nothing left in flight, the server exits well before the deadline.
+    let exit = exited(&mut server, Duration::from_secs(10));
+    assert!(exit - terminated < Duration::from_secs(5));
+}
+
+#[test]
+fn connections_still_open_at_the_deadline_are_cut_off() {
+    let mut server = common::start("drain-deadline", "drain_timeout_secs = 1\n");
+    let mut stalled = export_in_flight(server.port);
+    let terminated = Instant::now();
+    terminate(&server);
+
+    let exit = exited(&mut server, Duration::from_secs(10));
+    let waited = exit - terminated;
+    assert!(waited >= Duration::from_millis(900), "exited after {:?}, before the deadline", waited);
+    assert!(waited < Duration::from_secs(5), "exited after {:?}", waited);
+
+    // What was sent arrives, but never the end of the second export.
+    let mut rest = Vec::new();
+    let _ = stalled.read_to_end(&mut rest);
+    let rest = String::from_utf8_lossy(&rest);
+    assert_eq!(rest.matches(END).count(), 1, "only the first export finished");
+    assert!(!rest.ends_with(END));
+}
@@ -0,0 +1,98 @@
+// The build script embeds `tests/fixtures/embedded` unless told
+// otherwise; these tests run against that set.
+use std::{env, fs, path::PathBuf, process};
+
+use server_app::config::Config;
+use server_app::embedded::{self, EMBEDDED_FILES};
+use server_app::http::Request;
+use server_app::server::ServerConfig;
+use server_app::static_files::{StaticFileServer, StaticSource};
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/embedded")
+}
+
+// A document root that shares `app.css` with the fixtures, with other contents.
+fn disk_root(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("embedded-test-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("app.css"), b"/* on disk */").unwrap();
+    fs::write(dir.join("disk-only.txt"), b"only on disk").unwrap();
+    dir
+}
+
+fn get(server: &StaticFileServer, path: &str) -> (u16, Vec<u8>) {
+    let response = server.handle(&Request::new("GET", path));
+    (response.status, response.body)
+}
+
+#[test]
+fn embeds_the_fixtures() {
+    let paths: Vec<&str> = EMBEDDED_FILES.iter().map(|file| file.path).collect();
+    assert_eq!(paths, ["app.css", "app.js", "data.json", "docs/index.html"]);
+    for file in EMBEDDED_FILES {
+        assert_eq!(file.bytes, &fs::read(fixtures().join(file.path)).unwrap()[..], "{}", file.path);
+    }
+    assert_eq!(embedded::get("app.css").unwrap().content_type, "text/css; charset=utf-8");
+    assert_eq!(embedded::get("app.js").unwrap().content_type, "text/javascript; charset=utf-8");
+    assert_eq!(embedded::get("data.json").unwrap().content_type, "application/json");
+    assert_eq!(embedded::get("docs/index.html").unwrap().content_type, "text/html; charset=utf-8");
+    assert!(embedded::get("missing.txt").is_none());
+}
+
+#[test]
+fn serves_embedded_files_with_build_time_etags() {
+    let root = disk_root("etags");
+    let server = StaticFileServer::new(&root).with_source(StaticSource::Embedded);
+    let file = embedded::get("app.css").unwrap();
+
+    let response = server.handle(&Request::new("GET", "/app.css"));
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, file.bytes);
+    assert_eq!(response.headers.get("ETag"), Some(file.etag));
+    assert_eq!(response.headers.get("Last-Modified"), None);
+
+    let mut revalidate = Request::new("GET", "/app.css");
+    revalidate.headers.set("If-None-Match", file.etag);
+    assert_eq!(server.handle(&revalidate).status, 304);
+
+    let mut range = Request::new("GET", "/app.css");
+    range.headers.set("Range", "bytes=0-3");
+    let partial = server.handle(&range);
+    assert_eq!(partial.status, 206);
+    assert_eq!(partial.body, &file.bytes[..4]);
+
+    assert_eq!(get(&server, "/docs/").1, embedded::get("docs/index.html").unwrap().bytes);
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn sources_decide_the_lookup_order() {
+    let root = disk_root("order");
+    let embedded_css = embedded::get("app.css").unwrap().bytes.to_vec();
+
+    let disk = StaticFileServer::new(&root);
+    assert_eq!(get(&disk, "/app.css"), (200, b"/* on disk */".to_vec()));
+    assert_eq!(get(&disk, "/app.js").0, 404);
+
+    let only_embedded = StaticFileServer::new(&root).with_source(StaticSource::Embedded);
+    assert_eq!(get(&only_embedded, "/app.css"), (200, embedded_css.clone()));
+    assert_eq!(get(&only_embedded, "/disk-only.txt").0, 404);
+
+    let fallback = StaticFileServer::new(&root).with_source(StaticSource::EmbeddedFallback);
+    assert_eq!(get(&fallback, "/app.css"), (200, embedded_css));
+    assert_eq!(get(&fallback, "/disk-only.txt"), (200, b"only on disk".to_vec()));
+    assert_eq!(get(&fallback, "/missing.txt").0, 404);
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn static_source_is_configurable() {
+    let config = Config::parse("[server]\nstatic_source = \"embedded_fallback\"\n").unwrap();
+    assert_eq!(ServerConfig::from_config(&config).unwrap().static_source, StaticSource::EmbeddedFallback);
+    assert_eq!(ServerConfig::default().static_source, StaticSource::Disk);
+
+    let config = Config::parse("[server]\nstatic_source = \"cdn\"\n").unwrap();
+    assert!(ServerConfig::from_config(&config).is_err());
+}
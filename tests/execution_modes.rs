@@ -0,0 +1,132 @@
+// A `Server` runs the connections it's given on its pools, inline, or on
+// a thread each with an optional cap, as its `ExecutionMode` says.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+use server_app::server::{ExecutionMode, Server, ServerConfig};
+
+// A connected pair on an ephemeral port: what the server accepted, and
+// the client's end.
+fn connection(listener: &TcpListener) -> (TcpStream, TcpStream) {
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (accepted, _) = listener.accept().unwrap();
+    (accepted, client)
+}
+
+// Answer one request with "ok", after `delay`.
+fn answer(mut stream: TcpStream, delay: Duration) {
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request).unwrap();
+    thread::sleep(delay);
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok").unwrap();
+}
+
+fn request(mut client: TcpStream) -> String {
+    client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    response
+}
+
+// A server in `mode` that records the thread each connection ran on and
+// how many ran at once.
+struct Recording {
+    server: Server,
+    threads: Arc<Mutex<Vec<ThreadId>>>,
+    most_at_once: Arc<AtomicUsize>,
+}
+
+fn recording(mode: ExecutionMode, delay: Duration) -> Recording {
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    let (running, most_at_once) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let (seen, most) = (Arc::clone(&threads), Arc::clone(&most_at_once));
+    let config = ServerConfig { workers: 2, ..ServerConfig::default() };
+    let server = Server::new(config, move |stream, _, _| {
+        seen.lock().unwrap().push(thread::current().id());
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        most.fetch_max(now, Ordering::SeqCst);
+        answer(stream, delay);
+        running.fetch_sub(1, Ordering::SeqCst);
+    })
+    .with_mode(mode);
+    Recording { server, threads, most_at_once }
+}
+
+#[test]
+fn modes_are_named() {
+    assert_eq!("pool".parse(), Ok(ExecutionMode::Pool));
+    assert_eq!("single".parse(), Ok(ExecutionMode::Single));
+    assert_eq!("thread-per-conn".parse(), Ok(ExecutionMode::ThreadPerConnection { max_threads: None }));
+    assert!("threads".parse::<ExecutionMode>().is_err());
+    assert_eq!(ExecutionMode::default(), ExecutionMode::Pool);
+    assert_eq!(ExecutionMode::ThreadPerConnection { max_threads: Some(8) }.to_string(), "thread-per-conn (at most 8)");
+}
+
+#[test]
+fn pool_mode_serves_on_the_workers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let recording = recording(ExecutionMode::Pool, Duration::ZERO);
+    for _ in 0..2 {
+        let (accepted, client) = connection(&listener);
+        recording.server.serve(accepted);
+        assert!(request(client).ends_with("ok"));
+    }
+    assert!(recording.server.wait_idle(Duration::from_secs(5)));
+    assert!(!recording.threads.lock().unwrap().contains(&thread::current().id()));
+}
+
+#[test]
+fn single_mode_serves_inline() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let recording = recording(ExecutionMode::Single, Duration::ZERO);
+    for _ in 0..2 {
+        let (accepted, client) = connection(&listener);
+        // The client can write before being served, and read after.
+        let client = thread::spawn(move || request(client));
+        recording.server.serve(accepted);
+        assert_eq!(recording.server.in_flight(), 0);
+        assert!(client.join().unwrap().ends_with("ok"));
+    }
+    assert_eq!(*recording.threads.lock().unwrap(), [thread::current().id(); 2]);
+}
+
+#[test]
+fn thread_per_connection_mode_respects_its_cap() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mode = ExecutionMode::ThreadPerConnection { max_threads: Some(2) };
+    let recording = recording(mode, Duration::from_millis(100));
+    let clients: Vec<_> = (0..5)
+        .map(|_| {
+            let (accepted, client) = connection(&listener);
+            let client = thread::spawn(move || request(client));
+            recording.server.serve(accepted);
+            client
+        })
+        .collect();
+    for client in clients {
+        assert!(client.join().unwrap().ends_with("ok"));
+    }
+    assert!(recording.server.wait_idle(Duration::from_secs(5)));
+    assert_eq!(recording.most_at_once.load(Ordering::SeqCst), 2);
+    let mut threads = recording.threads.lock().unwrap().clone();
+    threads.dedup();
+    assert_eq!(threads.len(), 5, "a thread each");
+}
+
+#[test]
+fn worker_counts_come_from_config() {
+    use server_app::config::Config;
+    let config = Config::parse("[server]\nworkers = 3\njob_workers = 2\n").unwrap();
+    let config = ServerConfig::from_config(&config).unwrap();
+    assert_eq!((config.workers, config.job_workers), (3, 2));
+    // One job worker per CPU until told otherwise.
+    let cpus = thread::available_parallelism().map_or(4, |n| n.get());
+    assert_eq!(ServerConfig::default().job_workers, cpus);
+    assert!(ServerConfig::from_config(&Config::parse("[server]\njob_workers = 0\n").unwrap()).is_err());
+}
@@ -0,0 +1,122 @@
+// `Expect: 100-continue`: the go-ahead is sent only once the head has
+// passed every check, and never to a request that will be refused.
+use std::io::{self, Read, Write};
+
+use server_app::http::{Limits, Request, Response};
+use server_app::server::{read_request, Incoming, ServerConfig};
+
+const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
+// Replays `chunks` and notes each read and write in order, so a test can
+// tell whether `100 Continue` went out before the body was read.
+struct Client {
+    chunks: Vec<Vec<u8>>,
+    written: Vec<u8>,
+    events: Vec<&'static str>,
+}
+
+impl Client {
+    fn new(chunks: &[&[u8]]) -> Client {
+        Client { chunks: chunks.iter().rev().map(|c| c.to_vec()).collect(), written: Vec::new(), events: Vec::new() }
+    }
+
+    fn reads(&self) -> usize {
+        self.events.iter().filter(|e| **e == "read").count()
+    }
+}
+
+impl Read for Client {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(mut chunk) = self.chunks.pop() else { return Ok(0) };
+        self.events.push("read");
+        let n = chunk.len().min(buf.len());
+        buf[..n].copy_from_slice(&chunk[..n]);
+        if n < chunk.len() {
+            self.chunks.push(chunk.split_off(n));
+        }
+        Ok(n)
+    }
+}
+
+impl Write for Client {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.events.push("write");
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn config() -> ServerConfig {
+    let mut config = ServerConfig {
+        limits: Limits { max_body_bytes: 16, ..Limits::default() },
+        ..ServerConfig::default()
+    };
+    config.allowed_hosts.clear();
+    config
+}
+
+fn read<F>(client: &mut Client, precheck: F) -> Incoming
+where
+    F: Fn(&Request) -> Option<Response>,
+{
+    read_request(client, &mut Vec::new(), &config(), precheck)
+}
+
+#[test]
+fn continue_goes_out_after_the_head_and_before_the_body_is_read() {
+    let mut client = Client::new(&[b"PUT /f HTTP/1.1\r\nHost: a\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n", b"hello"]);
+    match read(&mut client, |_| None) {
+        Incoming::Request(request) => assert_eq!(request.body, b"hello"),
+        _ => panic!("not a request"),
+    }
+    assert_eq!(client.written, CONTINUE);
+    assert_eq!(client.events, ["read", "write", "read"]);
+}
+
+#[test]
+fn a_refused_request_gets_no_continue() {
+    let mut client = Client::new(&[b"PUT /f HTTP/1.1\r\nHost: a\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n", b"hello"]);
+    let refuse = |_: &_| Some(Response::new(401, "Unauthorized"));
+    assert!(matches!(read(&mut client, refuse), Incoming::Reject(response) if response.status == 401));
+    assert!(client.written.is_empty());
+    assert_eq!(client.reads(), 1, "the body was never read");
+}
+
+#[test]
+fn an_oversized_content_length_gets_413_and_no_continue() {
+    let mut client = Client::new(&[b"PUT /f HTTP/1.1\r\nHost: a\r\nExpect: 100-continue\r\nContent-Length: 17\r\n\r\n", b"0123456789abcdefg"]);
+    match read(&mut client, |_| None) {
+        Incoming::Reject(response) => {
+            assert_eq!(response.status, 413);
+            assert_eq!(response.header("Connection"), Some("close"));
+        }
+        _ => panic!("not refused"),
+    }
+    assert!(client.written.is_empty());
+    assert_eq!(client.reads(), 1, "the body was never read");
+}
+
+#[test]
+fn an_unknown_expectation_is_417() {
+    let mut client = Client::new(&[b"PUT /f HTTP/1.1\r\nHost: a\r\nExpect: something-else\r\nContent-Length: 5\r\n\r\n", b"hello"]);
+    assert!(matches!(read(&mut client, |_| None), Incoming::Reject(response) if response.status == 417));
+    assert!(client.written.is_empty());
+}
+
+#[test]
+fn http_1_0_clients_get_no_continue() {
+    let mut client = Client::new(&[b"PUT /f HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n", b"hello"]);
+    assert!(matches!(read(&mut client, |_| None), Incoming::Request(request) if request.body == b"hello"));
+    assert!(client.written.is_empty());
+}
+
+#[test]
+fn a_client_already_sending_the_body_gets_no_continue() {
+    let mut client = Client::new(&[b"PUT /f HTTP/1.1\r\nHost: a\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\nhe", b"llo"]);
+    assert!(matches!(read(&mut client, |_| None), Incoming::Request(request) if request.body == b"hello"));
+    assert!(client.written.is_empty());
+}
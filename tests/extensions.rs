@@ -0,0 +1,110 @@
+// Middleware and handlers pass typed values to each other in a request's
+// extensions, and the server leaves a few there itself.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use server_app::http::{Extensions, Request, Response};
+use server_app::router::{Next, RouteParams, Router};
+use server_app::server::{RequestContext, RequestId};
+
+// Neither is `Clone`.
+#[derive(Debug, PartialEq)]
+struct User {
+    name: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct Quota(u32);
+
+// What the handler did, for the middleware to see on the way out.
+struct Charged(u32);
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router
+        .middleware(|request: &Request, next: Next<'_>| {
+            if let Some(name) = request.header("X-User") {
+                request.extensions().insert(User { name: name.to_string() });
+            }
+            next.run(request)
+        })
+        .middleware(|request: &Request, next: Next<'_>| {
+            request.extensions().insert(Quota(10));
+            let response = next.run(request);
+            let charged = request.extensions().get::<Charged>().map_or(0, |charged| charged.0);
+            let left = request.extensions().get::<Quota>().unwrap().0 - charged;
+            response.with_header("X-RateLimit-Remaining", &left.to_string())
+        })
+        .get("/users/:id", |request: &Request| {
+            let user = request.extensions().get::<User>();
+            let route = request.extensions().get::<RouteParams>().unwrap();
+            request.extensions().insert(Charged(3));
+            let name = user.as_ref().map_or("nobody", |user| user.name.as_str());
+            Response::new(200, "OK").with_body(format!("{} via {} for {}", name, route.pattern, route.params["id"]))
+        });
+    router
+}
+
+#[test]
+fn values_flow_from_middleware_to_the_handler_and_back() {
+    let router = router();
+    let mut request = Request::new("GET", "/users/42");
+    request.headers.set("X-User", "ada");
+    let response = router.dispatch(&request);
+    assert_eq!(response.body, b"ada via /users/:id for 42");
+    assert_eq!(response.header("X-RateLimit-Remaining"), Some("7"));
+
+    // The handler's copy of the request shared them with this one.
+    assert_eq!(request.extensions().get::<User>().unwrap().name, "ada");
+    let route = request.extensions().get::<RouteParams>().unwrap();
+    assert_eq!(route.params, HashMap::from([("id".to_string(), "42".to_string())]));
+
+    // Each request has its own.
+    let response = router.dispatch(&Request::new("GET", "/users/7"));
+    assert_eq!(response.body, b"nobody via /users/:id for 7");
+    assert!(Request::new("GET", "/").extensions().is_empty());
+}
+
+#[test]
+fn values_come_back_only_as_their_own_type() {
+    struct Other(u32);
+
+    let extensions = Extensions::new();
+    assert_eq!(extensions.insert(Quota(5)), None);
+    assert_eq!(extensions.insert(5u32), None);
+    assert_eq!(extensions.insert("text"), None);
+    assert_eq!(extensions.get::<Other>().map(|other| other.0), None, "a u32 by another name");
+    assert!(extensions.get::<u64>().is_none());
+    assert!(extensions.get::<String>().is_none(), "a &str is not a String");
+    assert_eq!(*extensions.get::<u32>().unwrap(), 5);
+    assert_eq!(*extensions.get::<&str>().unwrap(), "text");
+    assert_eq!(extensions.len(), 3);
+
+    // Inserting again replaces, and hands back what was there.
+    let previous = extensions.insert(Quota(6)).unwrap();
+    assert_eq!(*previous, Quota(5));
+    assert_eq!(*extensions.get::<Quota>().unwrap(), Quota(6));
+    assert_eq!(extensions.remove::<Quota>().as_deref(), Some(&Quota(6)));
+    assert!(!extensions.contains::<Quota>());
+    assert_eq!(extensions.len(), 2);
+
+    // A value taken out stays good after the request is gone.
+    let request = Request::new("GET", "/");
+    request.extensions().insert(User { name: "grace".to_string() });
+    let user: Arc<User> = request.extensions().get().unwrap();
+    drop(request);
+    assert_eq!(user.name, "grace");
+}
+
+#[test]
+fn the_context_shares_the_request_id_and_extensions() {
+    fn shareable<T: Send + Sync>() {}
+    shareable::<Extensions>();
+    shareable::<Request>();
+
+    let request = Request::new("GET", "/");
+    let context = RequestContext::for_request(9, &request);
+    assert_eq!(request.extensions().get::<RequestId>().as_deref(), Some(&RequestId(9)));
+    context.extensions.insert(Quota(1));
+    assert!(request.extensions().contains::<Quota>());
+}
@@ -0,0 +1,90 @@
+// `/favicon.ico` comes from the document root when it has one and is
+// built in otherwise; the access log can leave it out.
+use std::{env, fs, path::PathBuf, process, time::Duration};
+
+use server_app::access_log::{AccessLog, AccessLogConfig};
+use server_app::embedded_assets;
+use server_app::favicon::{self, Fallback};
+use server_app::http::{Request, Response};
+use server_app::router::Router;
+use server_app::server::Served;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("favicon-test-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn get_favicon(router: &Router) -> Response {
+    router.dispatch(&Request::new("GET", "/favicon.ico"))
+}
+
+#[test]
+fn document_root_icon_wins() {
+    let root = scratch_dir("root");
+    fs::write(root.join("favicon.ico"), b"on disk").unwrap();
+    let mut router = Router::new();
+    favicon::register(&mut router, Some(&root), Fallback::Embedded);
+
+    let response = get_favicon(&router);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"on disk");
+    assert_eq!(response.headers.get("Content-Type"), Some("image/x-icon"));
+    assert_eq!(response.headers.get("Cache-Control"), Some(favicon::CACHE_CONTROL));
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn fallbacks_without_a_file() {
+    let root = scratch_dir("empty");
+    let mut embedded = Router::new();
+    favicon::register(&mut embedded, Some(&root), Fallback::Embedded);
+    let response = get_favicon(&embedded);
+    assert_eq!(response.status, 200);
+    assert!(response.body.starts_with(&[0, 0, 1, 0]), "an ICO header");
+    assert_eq!(response.body, embedded_assets::get("favicon.ico").unwrap().bytes);
+    assert_eq!(response.headers.get("Cache-Control"), Some(favicon::CACHE_CONTROL));
+
+    let mut no_content = Router::new();
+    favicon::register(&mut no_content, None, Fallback::NoContent);
+    let response = get_favicon(&no_content);
+    assert_eq!(response.status, 204);
+    assert!(response.body.is_empty());
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn error_pages_are_embedded() {
+    let page = embedded_assets::get("404.html").unwrap();
+    assert!(page.content_type.starts_with("text/html"));
+    assert_eq!(page.response(404).status, 404);
+    assert!(embedded_assets::get("missing.html").is_none());
+}
+
+#[test]
+fn access_log_can_skip_favicons() {
+    let dir = scratch_dir("log");
+    for (log_favicon, expected_lines) in [(true, 2), (false, 1)] {
+        let path = dir.join(format!("access-{}.log", log_favicon));
+        let config = AccessLogConfig { log_favicon, ..AccessLogConfig::new(&path) };
+        let log = AccessLog::open(config).unwrap();
+        for target in ["/favicon.ico", "/"] {
+            let request = Request::new("GET", target);
+            let served = Served {
+                id: 1,
+                method: &request.method,
+                path: &request.path,
+                duration: Duration::ZERO,
+                response_bytes: 0,
+                worker: None,
+                client_abort: None,
+            };
+            log.log_request(None, &request, &Response::new(200, "OK"), &served);
+        }
+        log.shutdown();
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(written.lines().count(), expected_lines, "{}", written);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
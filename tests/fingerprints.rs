@@ -0,0 +1,104 @@
+// Far-future caching under the immutable prefixes, `no-cache` for HTML,
+// and content hashes that stay put for the same bytes.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use server_app::http::Request;
+use server_app::static_files::{fingerprint, AssetFingerprints, StaticFileServer, IMMUTABLE};
+
+fn root(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fingerprints-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("assets/img")).unwrap();
+    fs::write(dir.join("assets/app.css"), "body { color: red }").unwrap();
+    fs::write(dir.join("assets/img/logo.svg"), "<svg/>").unwrap();
+    fs::write(dir.join("assets/page.html"), "<p>asset page</p>").unwrap();
+    fs::write(dir.join("index.html"), "<p>home</p>").unwrap();
+    fs::write(dir.join("site.css"), "p {}").unwrap();
+    dir
+}
+
+fn server(root: &Path) -> StaticFileServer {
+    StaticFileServer::new(root).with_immutable_prefixes(vec!["/assets/".to_string(), "/fonts/".to_string()])
+}
+
+fn cache_control(server: &StaticFileServer, target: &str) -> Option<String> {
+    let response = server.handle(&Request::new("GET", target));
+    assert_eq!(response.status, 200, "{}", target);
+    response.header("Cache-Control").map(str::to_string)
+}
+
+#[test]
+fn the_header_is_picked_by_prefix_then_type() {
+    let server = StaticFileServer::new("unused").with_immutable_prefixes(vec!["/assets/".to_string()]);
+    assert_eq!(server.cache_control_for("/assets/app.css", "text/css"), Some(IMMUTABLE));
+    assert_eq!(server.cache_control_for("/assets/page.html", "text/html; charset=utf-8"), Some(IMMUTABLE));
+    assert_eq!(server.cache_control_for("/index.html", "text/html; charset=utf-8"), Some("no-cache"));
+    assert_eq!(server.cache_control_for("/site.css", "text/css"), None);
+    // A prefix is matched as given: `/assets` without its slash isn't under `/assets/`.
+    assert_eq!(server.cache_control_for("/assets", "text/css"), None);
+    assert_eq!(server.cache_control_for("/assetsbackup/a.css", "text/css"), None);
+
+    let none = StaticFileServer::new("unused");
+    assert_eq!(none.cache_control_for("/assets/app.css", "text/css"), None);
+}
+
+#[test]
+fn served_files_carry_the_header_for_their_path() {
+    let dir = root("served");
+    let server = server(&dir);
+    assert_eq!(cache_control(&server, "/assets/app.css").as_deref(), Some(IMMUTABLE));
+    assert_eq!(cache_control(&server, "/assets/img/logo.svg").as_deref(), Some(IMMUTABLE));
+    assert_eq!(cache_control(&server, "/assets/page.html").as_deref(), Some(IMMUTABLE));
+    assert_eq!(cache_control(&server, "/index.html").as_deref(), Some("no-cache"));
+    assert_eq!(cache_control(&server, "/").as_deref(), Some("no-cache"));
+    assert_eq!(cache_control(&server, "/site.css"), None);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn the_version_query_is_ignored_when_serving() {
+    let dir = root("query");
+    let server = server(&dir);
+    let plain = server.handle(&Request::new("GET", "/assets/app.css"));
+    let versioned = server.handle(&Request::new("GET", "/assets/app.css?v=abc123"));
+    assert_eq!(versioned.status, 200);
+    assert_eq!(versioned.body, plain.body);
+    assert_eq!(versioned.header("Cache-Control"), Some(IMMUTABLE));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn a_hash_depends_only_on_the_bytes() {
+    // The first 8 hex digits of FNV-1a (64-bit).
+    assert_eq!(fingerprint(b""), "cbf29ce4");
+    assert_eq!(fingerprint(b"a"), "af63dc4c");
+    assert_eq!(fingerprint(b"foobar"), "85944171");
+    assert_eq!(fingerprint(b"body { color: red }"), fingerprint(b"body { color: red }"));
+    assert_ne!(fingerprint(b"body { color: red }"), fingerprint(b"body { color: blue }"));
+}
+
+#[test]
+fn scanned_urls_change_exactly_when_the_content_does() {
+    let dir = root("scan");
+    let assets = dir.join("assets");
+    let first = AssetFingerprints::scan(&assets, "/assets/").unwrap();
+    assert_eq!(first.len(), 3);
+    let css = fingerprint(b"body { color: red }");
+    assert_eq!(first.get("app.css"), Some(css.as_str()));
+    assert_eq!(first.get("img/logo.svg"), Some(fingerprint(b"<svg/>").as_str()));
+    assert_eq!(first.url_for("/assets/app.css"), format!("/assets/app.css?v={}", css));
+    assert_eq!(first.url_for("/assets/missing.css"), "/assets/missing.css");
+    assert_eq!(first.url_for("/site.css"), "/site.css");
+
+    // Scanning again, with the file touched but no different, changes nothing.
+    fs::write(assets.join("app.css"), "body { color: red }").unwrap();
+    let again = AssetFingerprints::scan(&assets, "/assets/").unwrap();
+    assert_eq!(again.url_for("/assets/app.css"), first.url_for("/assets/app.css"));
+
+    fs::write(assets.join("app.css"), "body { color: blue }").unwrap();
+    let changed = AssetFingerprints::scan(&assets, "/assets/").unwrap();
+    assert_ne!(changed.url_for("/assets/app.css"), first.url_for("/assets/app.css"));
+    assert_eq!(changed.get("img/logo.svg"), first.get("img/logo.svg"));
+    let _ = fs::remove_dir_all(&dir);
+}
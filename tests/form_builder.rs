@@ -0,0 +1,77 @@
+// `FormBuilder` encodes form fields as a browser would, and
+// `Request::form` gets them back.
+use server_app::encoding::{form_urldecode, form_urlencode, parse_form};
+use server_app::http::Request;
+use server_app::testing::FormBuilder;
+
+const FIELDS: &[(&str, &str)] = &[
+    ("name", "Alice"),
+    ("age", "30"),
+    ("greeting", "hello world & goodbye"),
+    ("math", "1+1=2; 50% off"),
+    ("path", "/a/b?c=d#e"),
+    ("unicode", "naïve café ☕"),
+    ("empty", ""),
+    ("tags", "a"),
+    ("tags", "b"),
+    ("line breaks", "one\r\ntwo"),
+];
+
+fn builder() -> FormBuilder {
+    FIELDS.iter().fold(FormBuilder::new(), |form, (name, value)| form.field(name, value))
+}
+
+fn owned(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+    fields.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+}
+
+#[test]
+fn the_body_is_urlencoded_and_joined_with_ampersands() {
+    let body = FormBuilder::new().field("name", "Alice").field("age", "30").build();
+    assert_eq!(body, b"name=Alice&age=30");
+    let body = FormBuilder::new().field("a b", "x&y=z").field("c", "100%").build();
+    assert_eq!(body, b"a+b=x%26y%3Dz&c=100%25");
+    assert!(FormBuilder::new().build().is_empty());
+}
+
+#[test]
+fn every_field_survives_the_round_trip() {
+    let request = builder().into_request("/signup");
+    assert_eq!(request.method.as_str(), "POST");
+    assert_eq!(request.path, "/signup");
+    assert_eq!(request.header("Content-Type"), Some("application/x-www-form-urlencoded"));
+    assert_eq!(request.header("Content-Length"), Some(request.body.len().to_string().as_str()));
+    assert!(request.body.is_ascii());
+    assert_eq!(request.form().unwrap(), owned(FIELDS));
+}
+
+#[test]
+fn the_request_parses_off_the_wire_with_its_fields() {
+    let request = builder().into_request("/signup");
+    let mut wire = format!(
+        "POST /signup HTTP/1.1\r\nHost: a\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        request.header("Content-Type").unwrap(),
+        request.header("Content-Length").unwrap()
+    )
+    .into_bytes();
+    wire.extend_from_slice(&request.body);
+    let parsed = Request::parse(&wire).unwrap();
+    assert_eq!(parsed.form().unwrap(), owned(FIELDS));
+}
+
+#[test]
+fn form_decoding_is_strict_about_what_it_accepts() {
+    assert_eq!(form_urlencode("a b*-._~"), "a+b*-._%7E");
+    assert_eq!(form_urldecode("a+b%2B%7e").as_deref(), Some("a b+~"));
+    assert_eq!(form_urldecode("100%"), None);
+    assert_eq!(form_urldecode("%zz"), None);
+    assert_eq!(form_urldecode("%FF"), None, "not UTF-8");
+    assert_eq!(parse_form("a=1&&b&c=").unwrap(), owned(&[("a", "1"), ("b", ""), ("c", "")]));
+
+    // Only a form body is a form.
+    let mut request = builder().into_request("/");
+    request.headers.set("Content-Type", "application/json");
+    assert_eq!(request.form(), None);
+    request.headers.set("Content-Type", "Application/X-WWW-Form-Urlencoded; charset=utf-8");
+    assert!(request.form().is_some());
+}
@@ -0,0 +1,84 @@
+// Routes declared with `register_route!` from separate modules all land
+// in the one global registry, however many threads register them.
+use std::sync::Mutex;
+use std::thread;
+
+use server_app::http::Request;
+use server_app::register_route;
+use server_app::router::{self, Router};
+
+// The registry is shared by every test in this file.
+static REGISTRY: Mutex<()> = Mutex::new(());
+
+mod users {
+    use server_app::http::Request;
+    use server_app::register_route;
+
+    pub fn register() {
+        register_route!(GET "/api/users", |_: &Request| "all users");
+        register_route!(GET "/api/users/:id", |request: &Request| format!("user {}", request.param("id").unwrap()));
+    }
+}
+
+mod orders {
+    use server_app::http::Request;
+    use server_app::register_route;
+
+    pub fn register() {
+        register_route!(POST "/api/orders", |_: &Request| "order placed");
+        register_route!(GET "/api/orders", |_: &Request| "all orders");
+    }
+}
+
+fn status_and_body(router: &Router, method: &str, target: &str) -> (u16, String) {
+    let response = router.dispatch(&Request::new(method, target));
+    (response.status, String::from_utf8(response.body.clone()).unwrap())
+}
+
+#[test]
+fn routes_from_both_modules_are_served() {
+    let _registry = REGISTRY.lock().unwrap();
+    users::register();
+    orders::register();
+
+    let router = router::take_global();
+    assert_eq!(status_and_body(&router, "GET", "/api/users"), (200, "all users".to_string()));
+    assert_eq!(status_and_body(&router, "GET", "/api/users/7"), (200, "user 7".to_string()));
+    assert_eq!(status_and_body(&router, "POST", "/api/orders"), (200, "order placed".to_string()));
+    assert_eq!(status_and_body(&router, "GET", "/api/orders"), (200, "all orders".to_string()));
+    assert_eq!(router.dispatch(&Request::new("DELETE", "/api/orders")).status, 405);
+
+    // Taking them empties the registry.
+    assert_eq!(router::take_global().dispatch(&Request::new("GET", "/api/users")).status, 404);
+}
+
+#[test]
+fn mounting_the_registry_adds_to_the_routes_main_declared() {
+    let _registry = REGISTRY.lock().unwrap();
+    orders::register();
+
+    let mut router = Router::new();
+    router.get("/", |_: &Request| "home");
+    router.mount("", router::take_global());
+    assert_eq!(status_and_body(&router, "GET", "/"), (200, "home".to_string()));
+    assert_eq!(status_and_body(&router, "GET", "/api/orders"), (200, "all orders".to_string()));
+}
+
+#[test]
+fn threads_can_register_at_the_same_time() {
+    let _registry = REGISTRY.lock().unwrap();
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            thread::spawn(move || {
+                let path = format!("/thread/{}", i);
+                register_route!(GET path.as_str(), move |_: &Request| format!("thread {}", i));
+            })
+        })
+        .collect();
+    threads.into_iter().for_each(|thread| thread.join().unwrap());
+
+    let router = router::take_global();
+    for i in 0..8 {
+        assert_eq!(status_and_body(&router, "GET", &format!("/thread/{}", i)), (200, format!("thread {}", i)));
+    }
+}
@@ -0,0 +1,103 @@
+// `graceful_restart` swaps in new workers: connections already on the old
+// ones finish there, new ones land on the new workers with the new
+// settings, and a handler panicking on an old worker doesn't stop it.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use server_app::server::{Server, ServerConfig};
+
+fn connection(listener: &TcpListener) -> (TcpStream, TcpStream) {
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (accepted, _) = listener.accept().unwrap();
+    (accepted, client)
+}
+
+// Answers with the number of workers in the settings it was served with;
+// `/slow` takes a while first, and `/panic` panics.
+fn server(workers: usize) -> Arc<Server> {
+    let config = ServerConfig { workers, ..ServerConfig::default() };
+    Arc::new(Server::new(config, |mut stream, _, config| {
+        let mut request = [0; 1024];
+        let n = stream.read(&mut request).unwrap();
+        let request = String::from_utf8_lossy(&request[..n]).into_owned();
+        if request.starts_with("GET /panic ") {
+            panic!("handler failed");
+        }
+        if request.starts_with("GET /slow ") {
+            thread::sleep(Duration::from_millis(500));
+        }
+        let body = config.workers.to_string();
+        let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+        stream.write_all(response.as_bytes()).unwrap();
+    }))
+}
+
+fn send(client: &mut TcpStream, path: &str) {
+    client.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).unwrap();
+}
+
+fn answer(mut client: TcpStream) -> String {
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    response.split("\r\n\r\n").nth(1).unwrap_or("").to_string()
+}
+
+// Restart onto `workers` workers on a thread of its own, returning once
+// the new ones are taking connections.
+fn restart(server: &Arc<Server>, workers: usize) -> thread::JoinHandle<()> {
+    let restarting = Arc::clone(server);
+    let handle = thread::spawn(move || restarting.graceful_restart(ServerConfig { workers, ..ServerConfig::default() }));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.config().workers != workers {
+        assert!(Instant::now() < deadline, "the new workers never took over");
+        thread::sleep(Duration::from_millis(5));
+    }
+    handle
+}
+
+#[test]
+fn old_connections_finish_and_new_ones_land_on_the_new_workers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = server(2);
+    let (accepted, mut slow) = connection(&listener);
+    send(&mut slow, "/slow");
+    server.serve(accepted);
+
+    let restarting = restart(&server, 3);
+    // The old workers are still draining the slow request.
+    assert!(!restarting.is_finished());
+    let (accepted, mut fresh) = connection(&listener);
+    send(&mut fresh, "/");
+    server.serve(accepted);
+    let started = Instant::now();
+    assert_eq!(answer(fresh), "3");
+    assert!(started.elapsed() < Duration::from_millis(400), "the new connection waited for the old workers");
+
+    assert_eq!(answer(slow), "2");
+    restarting.join().unwrap();
+    assert!(server.wait_idle(Duration::from_secs(5)));
+}
+
+#[test]
+fn a_panic_on_the_old_workers_does_not_stop_the_restart() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = server(1);
+    let (accepted, mut panicking) = connection(&listener);
+    send(&mut panicking, "/panic");
+    server.serve(accepted);
+    let (accepted, mut queued) = connection(&listener);
+    send(&mut queued, "/slow");
+    server.serve(accepted);
+
+    restart(&server, 2).join().unwrap();
+    assert_eq!(answer(panicking), "", "the connection is closed without an answer");
+    assert_eq!(answer(queued), "1", "the old worker carried on with the queue");
+
+    let (accepted, mut after) = connection(&listener);
+    send(&mut after, "/");
+    server.serve(accepted);
+    assert_eq!(answer(after), "2");
+}
@@ -0,0 +1,88 @@
+// A `HEAD` request gets the head its `GET` would have had, framing
+// headers included, and no body.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use server_app::http::{Request, Response, StreamBody};
+use server_app::router::Router;
+
+fn wire(router: &Router, method: &str, path: &str) -> String {
+    let request = Request::new(method, path);
+    let mut out = Vec::new();
+    router.dispatch(&request).finalize(&request).write_to(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn split(wire: &str) -> (&str, &str) {
+    let end = wire.find("\r\n\r\n").unwrap() + 4;
+    wire.split_at(end)
+}
+
+#[test]
+fn head_has_the_get_head_and_no_body() {
+    let mut router = Router::new();
+    router.get("/hello", |_: &Request| {
+        Response::new(200, "OK").with_header("Content-Type", "text/plain").with_body("Hello, world!")
+    });
+
+    let get = wire(&router, "GET", "/hello");
+    let head = wire(&router, "HEAD", "/hello");
+    let (get_head, get_body) = split(&get);
+    assert_eq!(get_body, "Hello, world!");
+    assert!(get_head.contains("Content-Length: 13\r\n"));
+    assert_eq!(head, get_head);
+}
+
+#[test]
+fn head_of_a_missing_page_has_no_body() {
+    let router = Router::new();
+    let get = wire(&router, "GET", "/missing");
+    let head = wire(&router, "HEAD", "/missing");
+    let (get_head, get_body) = split(&get);
+    assert!(head.starts_with("HTTP/1.1 404 "));
+    assert!(!get_body.is_empty());
+    assert_eq!(head, get_head);
+}
+
+#[test]
+fn an_iterator_body_is_announced_but_never_pulled() {
+    let pulled = Arc::new(AtomicUsize::new(0));
+    let mut router = Router::new();
+    let counter = Arc::clone(&pulled);
+    router.get("/export.csv", move |_: &Request| {
+        let counter = Arc::clone(&counter);
+        let rows = (0..3).map(move |i| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            format!("{}\n", i).into_bytes()
+        });
+        Response::from_iter(200, "text/csv", rows)
+    });
+
+    let head = wire(&router, "HEAD", "/export.csv");
+    assert_eq!(head, "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nTransfer-Encoding: chunked\r\n\r\n");
+    assert_eq!(pulled.load(Ordering::SeqCst), 0);
+
+    let get = wire(&router, "GET", "/export.csv");
+    assert!(get.starts_with(&head));
+    assert_eq!(pulled.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn a_streamed_body_is_never_run() {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut router = Router::new();
+    let counter = Arc::clone(&runs);
+    router.get("/stream", move |_: &Request| {
+        let counter = Arc::clone(&counter);
+        Response::new(200, "OK").with_stream(StreamBody::new(move |w| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            w.write_all(b"streamed")
+        }))
+    });
+
+    let head = wire(&router, "HEAD", "/stream");
+    assert!(head.ends_with("Connection: close\r\n\r\n"));
+    assert_eq!(runs.load(Ordering::SeqCst), 0);
+    assert!(wire(&router, "GET", "/stream").ends_with("\r\n\r\nstreamed"));
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,119 @@
+// Requests signed with the shared secret reach the handler; unsigned,
+// forged and tampered ones get a 401.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use server_app::hash::{self, HmacSha256};
+use server_app::http::{Request, Response};
+use server_app::middleware::{HmacAlgorithm, HmacSignatureMiddleware};
+use server_app::router::Router;
+
+const SECRET: &[u8] = b"It's a Secret to Everybody";
+const HEADER: &str = "X-Hub-Signature-256";
+
+fn middleware() -> HmacSignatureMiddleware {
+    HmacSignatureMiddleware::new(SECRET, HEADER, HmacAlgorithm::Sha256)
+}
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.middleware(middleware()).post("/webhook", |request: &Request| {
+        Response::new(200, "OK").with_body(format!("got {} bytes", request.body.len()))
+    });
+    router
+}
+
+fn post(body: &[u8], signature: Option<&str>) -> Request {
+    let mut request = Request::new("POST", "/webhook");
+    request.body = body.to_vec();
+    if let Some(signature) = signature {
+        request.headers.set(HEADER, signature);
+    }
+    request
+}
+
+#[test]
+fn hmac_sha256_matches_the_published_vectors() {
+    // RFC 4231, test cases 1, 2 and 6 (a key longer than a block).
+    let cases: [(&[u8], &[u8], &str); 3] = [
+        (&[0x0b; 20], b"Hi There", "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"),
+        (b"Jefe", b"what do ya want for nothing?", "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"),
+        (
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+        ),
+    ];
+    for (key, data, mac) in cases {
+        assert_eq!(hash::to_hex(&hash::hmac_sha256(key, data)), mac);
+        let mut pieces = HmacSha256::new(key);
+        data.chunks(5).for_each(|piece| pieces.update(piece));
+        assert_eq!(hash::to_hex(&pieces.finish()), mac);
+    }
+    // GitHub's documented example for webhook signatures.
+    assert_eq!(
+        middleware().sign(b"Hello, World!"),
+        "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+    );
+}
+
+#[test]
+fn only_correctly_signed_bodies_get_through() {
+    let router = router();
+    let body = br#"{"action": "opened"}"#;
+    let signature = middleware().sign(body);
+    let response = router.dispatch(&post(body, Some(&signature)));
+    assert_eq!((response.status, response.body.as_slice()), (200, b"got 20 bytes".as_slice()));
+
+    // Without the prefix, and in capitals, it's the same signature.
+    let bare = signature.trim_start_matches("sha256=");
+    assert_eq!(router.dispatch(&post(body, Some(bare))).status, 200);
+    assert_eq!(router.dispatch(&post(body, Some(&signature.to_uppercase()))).status, 200);
+
+    // A body changed on the way no longer matches.
+    let response = router.dispatch(&post(br#"{"action": "closed"}"#, Some(&signature)));
+    assert_eq!((response.status, response.body.as_slice()), (401, b"Invalid signature".as_slice()));
+
+    let response = router.dispatch(&post(body, None));
+    assert_eq!(response.status, 401);
+    assert_eq!(response.body, b"Missing X-Hub-Signature-256 header");
+
+    // Signed with another secret, truncated, or not hex at all.
+    let other = HmacSignatureMiddleware::new(b"guess", HEADER, HmacAlgorithm::Sha256).sign(body);
+    for forged in [other.as_str(), &signature[..signature.len() - 2], "sha256=", "sha256=+f", "sha256=zz", "é"] {
+        assert_eq!(router.dispatch(&post(body, Some(forged))).status, 401, "{:?}", forged);
+    }
+    assert!(!format!("{:?}", middleware()).contains("Secret"), "the secret isn't printed");
+}
+
+// Whichever byte a guess gets wrong, comparing it takes about as long: a
+// comparison that stopped at the first difference would be hundreds of
+// times quicker for the first byte than the last.
+#[test]
+fn comparison_takes_as_long_wherever_the_difference_is() {
+    let size = 1 << 20;
+    let secret = vec![0x5a_u8; size];
+    let mut first = secret.clone();
+    first[0] ^= 1;
+    let mut last = secret.clone();
+    last[size - 1] ^= 1;
+
+    assert!(hash::constant_time_eq(&secret, &secret.clone()));
+    assert!(!hash::constant_time_eq(&secret, &first));
+    assert!(!hash::constant_time_eq(&secret, &last));
+    assert!(!hash::constant_time_eq(&secret, &secret[1..]));
+    assert!(hash::constant_time_eq(b"", b""));
+
+    let fastest = |guess: &[u8]| -> Duration {
+        (0..15)
+            .map(|_| {
+                let started = Instant::now();
+                black_box(hash::constant_time_eq(black_box(&secret), black_box(guess)));
+                started.elapsed()
+            })
+            .min()
+            .unwrap()
+    };
+    let (early, late) = (fastest(&first), fastest(&last));
+    assert!(early * 8 > late, "first byte wrong took {:?}, last byte wrong {:?}", early, late);
+}
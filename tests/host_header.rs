@@ -0,0 +1,88 @@
+// `Host` is required in HTTP/1.1, must appear once, and must be a plain
+// `host[:port]`; anything else is a `400` that says what was wrong.
+use server_app::http::{Limits, ParseError, Request, Response};
+use server_app::server::{read_request, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+fn head(version: &str, hosts: &[&str]) -> Vec<u8> {
+    let mut head = format!("GET / {}\r\n", version);
+    for host in hosts {
+        head.push_str(&format!("Host:{}\r\n", host));
+    }
+    head.push_str("\r\n");
+    head.into_bytes()
+}
+
+fn parse(version: &str, hosts: &[&str]) -> Result<Request, ParseError> {
+    Request::parse_head(&head(version, hosts), &Limits::default()).map(|(request, _)| request)
+}
+
+fn rejection(version: &str, hosts: &[&str]) -> Response {
+    let mut config = ServerConfig::default();
+    config.allowed_hosts.clear();
+    match read_request(&mut MockStream::new([head(version, hosts)]), &mut Vec::new(), &config, |_| None) {
+        Incoming::Reject(response) => response,
+        Incoming::Request(_) => panic!("{:?} accepted", hosts),
+        Incoming::Closed => panic!("closed"),
+    }
+}
+
+#[test]
+fn a_missing_host_is_a_400_with_a_plain_text_reason() {
+    assert_eq!(parse("HTTP/1.1", &[]).unwrap_err(), ParseError::MissingHost);
+    let response = rejection("HTTP/1.1", &[]);
+    let mut wire = Vec::new();
+    response.write_to(&mut wire).unwrap();
+    assert_eq!(
+        String::from_utf8(wire).unwrap(),
+        "HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain; charset=utf-8\r\nConnection: close\r\n\
+         Content-Length: 19\r\n\r\nMissing Host header"
+    );
+}
+
+#[test]
+fn a_second_host_is_refused_even_if_it_agrees() {
+    assert_eq!(parse("HTTP/1.1", &["a", "b"]).unwrap_err(), ParseError::InvalidHost);
+    assert_eq!(parse("HTTP/1.1", &["a", "a"]).unwrap_err(), ParseError::InvalidHost);
+    let response = rejection("HTTP/1.1", &["a", "a"]);
+    assert_eq!(response.status, 400);
+    assert_eq!(response.header("Content-Type"), Some("text/plain; charset=utf-8"));
+    assert_eq!(response.body, b"Invalid Host header");
+}
+
+#[test]
+fn malformed_hosts_are_refused() {
+    for host in [
+        "a:http",         // A port that isn't a number,
+        "a:65536",        // or is too big,
+        "a:-1",
+        "a:1:2",
+        "user@a",         // userinfo,
+        "user:pass@a:80",
+        "a b",            // whitespace inside,
+        "a\tb",
+        "a/b",            // a path,
+        "[zz::1]",        // a bracketed literal that isn't IPv6,
+        "[::1",
+        "[::1]x",
+    ] {
+        assert_eq!(parse("HTTP/1.1", &[host]).unwrap_err(), ParseError::InvalidHost, "{:?}", host);
+        assert_eq!(rejection("HTTP/1.1", &[host]).status, 400, "{:?}", host);
+    }
+}
+
+#[test]
+fn well_formed_hosts_are_accepted() {
+    for host in ["a", "example.com", "example.com:8080", "  padded.example  ", "127.0.0.1:1", "[::1]", "[::1]:443", "a:"] {
+        let request = parse("HTTP/1.1", &[host]).unwrap_or_else(|e| panic!("{:?}: {}", host, e));
+        assert_eq!(request.header("Host"), Some(host.trim()));
+    }
+}
+
+#[test]
+fn http_1_0_may_leave_host_out_but_not_send_a_bad_one() {
+    let request = parse("HTTP/1.0", &[]).unwrap();
+    assert_eq!(request.header("Host"), None);
+    assert_eq!(parse("HTTP/1.0", &["user@a"]).unwrap_err(), ParseError::InvalidHost);
+    assert_eq!(parse("HTTP/1.0", &["a", "b"]).unwrap_err(), ParseError::InvalidHost);
+}
@@ -0,0 +1,66 @@
+// `SecurityHeadersMiddleware` sends `Strict-Transport-Security` only on
+// responses to requests that came in over TLS.
+use std::time::Duration;
+
+use server_app::config::Config;
+use server_app::http::{Request, Response};
+use server_app::router::Router;
+use server_app::security::{HstsConfig, SecurityHeadersMiddleware};
+
+fn router(middleware: SecurityHeadersMiddleware) -> Router {
+    let mut router = Router::new();
+    router
+        .middleware(middleware)
+        .get("/", |_: &Request| Response::new(200, "OK"))
+        .get("/own", |_: &Request| Response::new(200, "OK").with_header("Strict-Transport-Security", "max-age=60"));
+    router
+}
+
+fn get(router: &Router, path: &str, secure: bool) -> Response {
+    let mut request = Request::new("GET", path);
+    request.secure = secure;
+    router.dispatch(&request)
+}
+
+#[test]
+fn tls_responses_carry_hsts_and_plain_ones_do_not() {
+    let router = router(SecurityHeadersMiddleware::new().with_hsts(HstsConfig::default()));
+    let tls = get(&router, "/", true);
+    assert_eq!(tls.header("Strict-Transport-Security"), Some("max-age=31536000; includeSubDomains"));
+    assert_eq!(tls.header("X-Content-Type-Options"), Some("nosniff"));
+
+    let plain = get(&router, "/", false);
+    assert_eq!(plain.header("Strict-Transport-Security"), None);
+    assert_eq!(plain.header("X-Content-Type-Options"), Some("nosniff"));
+
+    // A handler's own value stands, and a 404 gets the header too.
+    assert_eq!(get(&router, "/own", true).header("Strict-Transport-Security"), Some("max-age=60"));
+    assert!(get(&router, "/missing", true).header("Strict-Transport-Security").is_some());
+}
+
+#[test]
+fn the_tls_feature_turns_hsts_on_by_default() {
+    let middleware = SecurityHeadersMiddleware::new();
+    assert_eq!(middleware.hsts().is_some(), cfg!(feature = "tls"));
+    let default = router(middleware);
+    let tls = get(&default, "/", true);
+    assert_eq!(tls.header("Strict-Transport-Security").is_some(), cfg!(feature = "tls"));
+    assert_eq!(get(&default, "/", false).header("Strict-Transport-Security"), None);
+
+    let without = router(SecurityHeadersMiddleware::new().without_hsts());
+    assert_eq!(get(&without, "/", true).header("Strict-Transport-Security"), None);
+}
+
+#[test]
+fn the_header_follows_the_config() {
+    let config = Config::parse("[hsts]\nmax_age_secs = 600\ninclude_subdomains = true\npreload = true\n").unwrap();
+    let hsts = HstsConfig::from_config(&config, "hsts").unwrap();
+    assert_eq!(hsts.max_age, Duration::from_secs(600));
+    assert_eq!(hsts.header_value(), "max-age=600; includeSubDomains; preload");
+
+    let hsts = HstsConfig { include_subdomains: false, ..HstsConfig::default() };
+    assert_eq!(hsts.header_value(), "max-age=31536000");
+
+    let config = Config::parse("[hsts]\ninclude_subdomains = false\npreload = true\n").unwrap();
+    assert!(HstsConfig::from_config(&config, "hsts").is_err(), "preload needs includeSubDomains");
+}
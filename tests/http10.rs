@@ -0,0 +1,106 @@
+// HTTP/1.0 clients: no Host needed, `Connection: close` unless they ask
+// for keep-alive, and never a chunked body.
+mod common;
+
+use server_app::http::{HttpVersion, Request, Response};
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+fn config() -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.allowed_hosts.clear();
+    config
+}
+
+fn read(connection: &mut Connection<MockStream>) -> Request {
+    match connection.read_request(&config(), |_| None) {
+        Incoming::Request(request) => request,
+        Incoming::Reject(response) => panic!("rejected with {}", response.status),
+        Incoming::Closed => panic!("closed"),
+    }
+}
+
+fn wire(response: Response, request: &Request) -> String {
+    let mut out = Vec::new();
+    response.finalize(request).write_to(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn a_get_without_host_is_a_request() {
+    let mut connection = Connection::new(MockStream::new([b"GET /status HTTP/1.0\r\n\r\n".to_vec()]));
+    let request = read(&mut connection);
+    assert_eq!(request.version, HttpVersion::Http10);
+    assert_eq!(request.path, "/status");
+    assert_eq!(request.header("Host"), None);
+    assert!(!request.wants_keep_alive());
+
+    // The same without Host in 1.1 is refused.
+    let mut connection = Connection::new(MockStream::new([b"GET /status HTTP/1.1\r\n\r\n".to_vec()]));
+    assert!(matches!(connection.read_request(&config(), |_| None), Incoming::Reject(response) if response.status == 400));
+}
+
+#[test]
+fn a_plain_request_gets_connection_close_and_a_content_length() {
+    let mut connection = Connection::new(MockStream::new([b"GET / HTTP/1.0\r\n\r\n".to_vec()]));
+    let request = read(&mut connection);
+    let response = Response::new(200, "OK").with_body("hello").finalize(&request);
+    assert!(!response.keeps_alive());
+    assert_eq!(
+        wire(response, &request),
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 5\r\n\r\nhello"
+    );
+}
+
+#[test]
+fn keep_alive_is_spelled_out_and_the_connection_serves_another_request() {
+    let bytes = b"GET /a HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /b HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n";
+    let mut connection = Connection::new(MockStream::new([bytes.to_vec()]));
+    for path in ["/a", "/b"] {
+        let request = read(&mut connection);
+        assert_eq!(request.path, path);
+        assert!(request.wants_keep_alive());
+        let response = Response::new(200, "OK").with_body("hi").finalize(&request);
+        assert!(response.keeps_alive());
+        assert_eq!(response.header("Connection"), Some("keep-alive"));
+        connection.send(&response).unwrap();
+    }
+    assert!(matches!(connection.read_request(&config(), |_| None), Incoming::Closed));
+    let sent = String::from_utf8(connection.get_ref().written().to_vec()).unwrap();
+    assert_eq!(sent.matches("Connection: keep-alive\r\nContent-Length: 2\r\n\r\nhi").count(), 2);
+}
+
+#[test]
+fn a_streamed_body_falls_back_to_close_delimited() {
+    let mut request = Request::new("GET", "/export");
+    request.version = HttpVersion::Http10;
+    request.headers.set("Connection", "keep-alive");
+    let response = || Response::from_iter(200, "text/plain", vec![b"one ".to_vec(), b"two".to_vec()]);
+    // Its end can only be marked by closing, keep-alive or not.
+    assert!(!response().finalize(&request).keeps_alive());
+    let out = wire(response(), &request);
+    assert_eq!(out, "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\none two");
+    assert!(!out.contains("Transfer-Encoding"));
+}
+
+#[test]
+fn a_buffered_body_keeps_its_content_length() {
+    let mut request = Request::new("GET", "/");
+    request.version = HttpVersion::Http10;
+    request.headers.set("Connection", "keep-alive");
+    let out = wire(Response::new(200, "OK").with_body("known length"), &request);
+    assert!(out.contains("Content-Length: 12\r\n"));
+    assert!(out.contains("Connection: keep-alive\r\n"));
+    assert!(!out.contains("Transfer-Encoding"));
+}
+
+#[test]
+fn the_binary_answers_a_hostless_1_0_request_and_closes() {
+    let server = common::start("http10", "");
+    let response = common::raw(server.port, "GET /hello HTTP/1.0\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 "), "{}", response);
+    assert_eq!(common::header(&response, "Connection"), Some("close"));
+    assert_eq!(common::header(&response, "Content-Length"), Some("15"));
+    assert_eq!(common::header(&response, "Transfer-Encoding"), None);
+    assert_eq!(common::body(&response), "<h1>Hello!</h1>");
+}
@@ -0,0 +1,73 @@
+// A retried request with the same Idempotency-Key is answered from the
+// first response, without running the handler again.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use server_app::cache::LruCache;
+use server_app::clock::MockClock;
+use server_app::http::{Request, Response};
+use server_app::idempotency::IdempotencyMiddleware;
+use server_app::router::Router;
+
+fn router(middleware: IdempotencyMiddleware, calls: &Arc<AtomicUsize>) -> Router {
+    let calls = Arc::clone(calls);
+    let mut router = Router::new();
+    router.middleware(middleware).post("/orders", move |_: &Request| {
+        let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+        Response::new(201, "Created").with_body(format!("order {}", n))
+    });
+    router
+}
+
+fn post(key: Option<&str>) -> Request {
+    let mut request = Request::new("POST", "/orders");
+    if let Some(key) = key {
+        request.headers.set("Idempotency-Key", key);
+    }
+    request
+}
+
+#[test]
+fn same_key_runs_the_handler_once() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let router = router(IdempotencyMiddleware::new(100, Duration::from_secs(60)), &calls);
+
+    let first = router.dispatch(&post(Some("abc")));
+    let second = router.dispatch(&post(Some("abc")));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!((first.status, second.status), (201, 201));
+    assert_eq!(first.body, b"order 1");
+    assert_eq!(second.body, first.body);
+    assert_eq!(first.headers.get("Idempotent-Replayed"), None);
+    assert_eq!(second.headers.get("Idempotent-Replayed"), Some("true"));
+
+    // Another key, or none at all, is a new request.
+    assert_eq!(router.dispatch(&post(Some("def"))).body, b"order 2");
+    assert_eq!(router.dispatch(&post(None)).body, b"order 3");
+    assert_eq!(router.dispatch(&post(None)).body, b"order 4");
+}
+
+#[test]
+fn keys_expire_after_the_ttl() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let clock = Arc::new(MockClock::new());
+    let cache = LruCache::with_clock(100, Duration::from_secs(60), clock.clone());
+    let router = router(IdempotencyMiddleware::with_cache(Arc::new(Mutex::new(cache))), &calls);
+
+    router.dispatch(&post(Some("abc")));
+    clock.advance(Duration::from_secs(59));
+    assert_eq!(router.dispatch(&post(Some("abc"))).body, b"order 1");
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(router.dispatch(&post(Some("abc"))).body, b"order 2");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn an_oversized_key_is_rejected() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let router = router(IdempotencyMiddleware::new(100, Duration::from_secs(60)), &calls);
+
+    assert_eq!(router.dispatch(&post(Some(&"k".repeat(256)))).status, 400);
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,153 @@
+// Idle keep-alive clients mustn't keep a new one from being served:
+// they're parked off the workers, or evicted when every worker is busy.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use server_app::http::Response;
+use server_app::server::{
+    Connection, ConnectionRegistry, ConnectionState, IdleConnection, IdleWatcher, Incoming, Server, ServerConfig,
+    TrackedConnection,
+};
+
+const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+// Answer requests on `connection` until it closes, parking it between
+// requests when there's a watcher and waiting on it otherwise.
+fn serve(
+    mut connection: Connection<TcpStream>,
+    tracked: Option<TrackedConnection>,
+    mut served: usize,
+    config: &ServerConfig,
+    idle: Option<&IdleWatcher>,
+) {
+    loop {
+        let incoming = connection.read_request(config, |_| None);
+        if let Some(tracked) = &tracked {
+            tracked.set_state(ConnectionState::Handling);
+        }
+        match incoming {
+            Incoming::Request(_) => served += 1,
+            _ => return,
+        }
+        if connection.send(&Response::new(200, "OK").with_body("served")).is_err() {
+            return;
+        }
+        match idle {
+            Some(idle) => {
+                let parked = IdleConnection { stream: connection.into_inner(), tracked, served };
+                idle.park(parked, config.keep_alive_timeout);
+                return;
+            }
+            None => {
+                if let Some(tracked) = &tracked {
+                    tracked.set_state(ConnectionState::Idle);
+                }
+            }
+        }
+    }
+}
+
+fn start(config: ServerConfig, watch: bool) -> (String, ConnectionRegistry) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let registry = ConnectionRegistry::new();
+    let idle = if watch { Some(IdleWatcher::start().unwrap()) } else { None };
+
+    let handler_registry = registry.clone();
+    let handler_idle = idle.clone();
+    let server = Arc::new(
+        Server::new(config, move |stream, buffer, config| {
+            let tracked = handler_registry.track(&stream).ok();
+            serve(Connection::with_initial(stream, &buffer), tracked, 0, config, handler_idle.as_ref());
+        })
+        .with_connections(registry.clone()),
+    );
+    if let Some(idle) = &idle {
+        let server = Arc::downgrade(&server);
+        idle.on_ready(move |parked, idle| {
+            if let Some(server) = server.upgrade() {
+                server.resume(move |config| {
+                    serve(Connection::new(parked.stream), parked.tracked, parked.served, config, Some(&idle));
+                });
+            }
+        });
+    }
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            server.serve(stream.unwrap());
+        }
+    });
+    (addr, registry)
+}
+
+fn config(evict: bool) -> ServerConfig {
+    ServerConfig {
+        workers: 2,
+        keep_alive_timeout: Duration::from_secs(30),
+        evict_idle_when_busy: evict,
+        ..ServerConfig::default()
+    }
+}
+
+// Send one request and read its response, or whatever arrives before the timeout.
+fn get(stream: &mut TcpStream) -> String {
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    stream.write_all(REQUEST).unwrap();
+    let mut response = Vec::new();
+    let mut chunk = [0; 1024];
+    while !response.ends_with(b"served") {
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => response.extend_from_slice(&chunk[..n]),
+        }
+    }
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+fn wait_for(registry: &ConnectionRegistry, state: ConnectionState, count: usize) {
+    for _ in 0..200 {
+        if registry.count(state) == count {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("expected {} connections {:?}, found {}", count, state, registry.count(state));
+}
+
+#[test]
+fn parked_clients_leave_workers_free() {
+    let (addr, registry) = start(config(false), true);
+    let mut idle_clients: Vec<TcpStream> = (0..2).map(|_| TcpStream::connect(&addr).unwrap()).collect();
+    for client in &mut idle_clients {
+        assert!(get(client).ends_with("served"));
+    }
+    wait_for(&registry, ConnectionState::Idle, 2);
+
+    let mut newcomer = TcpStream::connect(&addr).unwrap();
+    assert!(get(&mut newcomer).ends_with("served"));
+    // The parked ones are still usable.
+    assert!(get(&mut idle_clients[0]).ends_with("served"));
+}
+
+#[test]
+fn busy_workers_evict_the_longest_idle_client() {
+    let (addr, registry) = start(config(true), false);
+    let mut first = TcpStream::connect(&addr).unwrap();
+    assert!(get(&mut first).ends_with("served"));
+    wait_for(&registry, ConnectionState::Idle, 1);
+    let mut second = TcpStream::connect(&addr).unwrap();
+    assert!(get(&mut second).ends_with("served"));
+    wait_for(&registry, ConnectionState::Idle, 2);
+
+    // Both workers are waiting on idle clients; the newcomer gets one.
+    let mut newcomer = TcpStream::connect(&addr).unwrap();
+    assert!(get(&mut newcomer).ends_with("served"));
+
+    // The first client, idle longest, was the one closed.
+    let mut byte = [0; 1];
+    assert_eq!(first.read(&mut byte).unwrap(), 0);
+    assert!(get(&mut second).ends_with("served"));
+}
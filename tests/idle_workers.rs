@@ -0,0 +1,46 @@
+// Idle workers park until a job wakes one of them, and a worker that
+// takes a job while more are queued wakes the next, so queued jobs
+// spread over the idle workers and none waits behind a busy one.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use server_app::{current_worker_id, ThreadPool};
+
+const WAIT: Duration = Duration::from_secs(5);
+
+#[test]
+fn jobs_queued_together_run_on_every_idle_worker() {
+    let pool = ThreadPool::new(4);
+    let arrived = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..4 {
+        let (arrived, tx) = (Arc::clone(&arrived), tx.clone());
+        // Each job holds its worker until all four are running at once.
+        pool.execute(move || {
+            arrived.fetch_add(1, Ordering::SeqCst);
+            let deadline = Instant::now() + WAIT;
+            while arrived.load(Ordering::SeqCst) < 4 && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            tx.send((arrived.load(Ordering::SeqCst), current_worker_id())).unwrap();
+        });
+    }
+    let mut workers: Vec<_> = (0..4).map(|_| rx.recv_timeout(WAIT).unwrap()).collect();
+    assert!(workers.iter().all(|(arrived, _)| *arrived == 4), "{:?}", workers);
+    workers.sort();
+    workers.dedup();
+    assert_eq!(workers.len(), 4);
+}
+
+#[test]
+fn a_job_sent_to_an_idle_pool_is_never_missed() {
+    let pool = ThreadPool::new(3);
+    let (tx, rx) = mpsc::channel();
+    for round in 0..2_000 {
+        let tx = tx.clone();
+        pool.execute(move || tx.send(round).unwrap());
+        assert_eq!(rx.recv_timeout(WAIT), Ok(round));
+    }
+}
@@ -0,0 +1,111 @@
+// Handlers can return strings, bytes, tuples, statuses, options and
+// results, which the router turns into responses.
+use std::collections::HashMap;
+
+use server_app::http::{IntoResponse, Request, Response, ServerError};
+use server_app::json;
+use server_app::router::Router;
+
+#[test]
+fn each_conversion_sets_status_and_content_type() {
+    let response = "<p>hi</p>".to_string().into_response();
+    assert_eq!((response.status, response.reason.as_str()), (200, "OK"));
+    assert_eq!(response.header("Content-Type"), Some("text/html; charset=utf-8"));
+    assert_eq!(response.body, b"<p>hi</p>");
+
+    let response = (404, "<p>gone</p>".to_string()).into_response();
+    assert_eq!(response.status, 404);
+    assert_eq!(response.header("Content-Type"), Some("text/html; charset=utf-8"));
+
+    let response = vec![0u8, 1, 2].into_response();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/octet-stream"));
+    assert_eq!(response.body, [0, 1, 2]);
+
+    let headers = HashMap::from([("Content-Type".to_string(), "text/csv".to_string()), ("X-Rows".to_string(), "1".to_string())]);
+    let response = (201, headers, b"a,b\n".to_vec()).into_response();
+    assert_eq!((response.status, response.reason.as_str()), (201, "Created"));
+    assert_eq!(response.header("Content-Type"), Some("text/csv"));
+    assert_eq!(response.header("X-Rows"), Some("1"));
+    assert_eq!(response.body, b"a,b\n");
+
+    let response = (204, HashMap::new(), Vec::new()).into_response();
+    assert_eq!(response.header("Content-Type"), Some("application/octet-stream"));
+}
+
+#[test]
+fn the_router_converts_what_handlers_return() {
+    let mut router = Router::new();
+    router
+        .get("/text", |_: &Request| "hello".to_string())
+        .get("/status", |_: &Request| (418, "short and stout".to_string()))
+        .get("/bytes", |_: &Request| vec![0xff_u8])
+        .fallback(|_: &Request| (404, "nothing here".to_string()));
+
+    let response = router.dispatch(&Request::new("GET", "/text"));
+    assert_eq!((response.status, response.body.as_slice()), (200, b"hello".as_slice()));
+    assert_eq!(router.dispatch(&Request::new("GET", "/status")).status, 418);
+    let response = router.dispatch(&Request::new("GET", "/bytes"));
+    assert_eq!(response.header("Content-Type"), Some("application/octet-stream"));
+    assert_eq!(router.dispatch(&Request::new("GET", "/missing")).body, b"nothing here");
+}
+
+// A handler that uses `?`.
+fn parse_count(request: &Request) -> Result<String, ServerError> {
+    let text = std::str::from_utf8(&request.body).map_err(|_| ServerError::bad_request("not UTF-8"))?;
+    let value = json::parse(text)?;
+    let count = value.get("count").and_then(|count| count.as_f64()).ok_or_else(|| ServerError::bad_request("no count"))?;
+    Ok(format!("count is {}", count))
+}
+
+#[test]
+fn statuses_options_and_results_convert_too() {
+    let response = 204.into_response();
+    assert_eq!((response.status, response.reason.as_str()), (204, "No Content"));
+    assert!(response.body.is_empty());
+
+    assert_eq!(Some("here").into_response().body, b"here");
+    let response = None::<String>.into_response();
+    assert_eq!(response.status, 404);
+    assert_eq!(response.header("Content-Type"), Some("text/plain; charset=utf-8"));
+
+    let ok: Result<Vec<u8>, ServerError> = Ok(vec![7]);
+    assert_eq!(ok.into_response().header("Content-Type"), Some("application/octet-stream"));
+    let response = Err::<String, _>(ServerError::new(409, "already taken")).into_response();
+    assert_eq!((response.status, response.body.as_slice()), (409, b"already taken".as_slice()));
+
+    // A server error's message stays in the log.
+    let failed: Result<String, ServerError> = Err(std::io::Error::other("disk on fire").into());
+    let response = failed.into_response();
+    assert_eq!((response.status, response.body.as_slice()), (500, b"Internal Server Error".as_slice()));
+    assert_eq!(ServerError::not_found("no such user").to_string(), "404 NOT FOUND: no such user");
+}
+
+#[test]
+fn the_router_takes_every_kind_of_handler() {
+    let mut router = Router::new();
+    router
+        .get("/response", |_: &Request| Response::new(202, "Accepted"))
+        .get("/str", |_: &Request| "<b>static</b>")
+        .get("/status", |_: &Request| 204)
+        .get("/users/:id", |request: &Request| (request.params["id"] == "1").then_some("ada".to_string()))
+        .post("/count", parse_count);
+
+    let get = |path: &str| router.dispatch(&Request::new("GET", path));
+    assert_eq!(get("/response").status, 202);
+    assert_eq!(get("/str").header("Content-Type"), Some("text/html; charset=utf-8"));
+    assert_eq!((get("/status").status, get("/status").body.len()), (204, 0));
+    assert_eq!(get("/users/1").body, b"ada");
+    assert_eq!(get("/users/2").status, 404);
+
+    let post = |body: &str| {
+        let mut request = Request::new("POST", "/count");
+        request.body = body.as_bytes().to_vec();
+        router.dispatch(&request)
+    };
+    assert_eq!(post(r#"{"count": 3}"#).body, b"count is 3");
+    let response = post("{");
+    assert_eq!(response.status, 400);
+    assert!(String::from_utf8(response.body).unwrap().starts_with("invalid JSON at byte"));
+    assert_eq!(post("{}").body, b"no count");
+}
@@ -0,0 +1,96 @@
+// Bodies pulled from an iterator go out chunked (close-delimited to 1.0
+// clients), and a failed write stops the iterator early.
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use server_app::http::{HttpVersion, Request, Response};
+
+#[test]
+fn chunks_are_framed_with_chunked_encoding() {
+    let chunks = vec![b"id,name\n".to_vec(), b"1,a\n".to_vec(), Vec::new(), b"2,bb\n".to_vec()];
+    let response = Response::from_iter(200, "text/csv", chunks).finalize(&Request::new("GET", "/export.csv"));
+    assert!(response.keeps_alive());
+
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nTransfer-Encoding: chunked\r\n\r\n\
+         8\r\nid,name\n\r\n4\r\n1,a\n\r\n5\r\n2,bb\n\r\n0\r\n\r\n"
+    );
+}
+
+#[test]
+fn http_1_0_clients_get_a_close_delimited_body() {
+    let mut request = Request::new("GET", "/export.csv");
+    request.version = HttpVersion::Http10;
+    let chunks = vec![b"a\n".to_vec(), b"b\n".to_vec(), b"c\n".to_vec()];
+    let response = Response::from_iter(200, "text/csv", chunks).finalize(&request);
+    assert!(!response.keeps_alive());
+
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nConnection: close\r\n\r\na\nb\nc\n"
+    );
+}
+
+// Takes `limit` bytes, then fails as if the client had hung up.
+struct HangsUp {
+    written: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for HangsUp {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written.len() + buf.len() > self.limit {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "client hung up"));
+        }
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Counts the chunks pulled from it, and notes when it's dropped.
+struct Rows {
+    pulled: Arc<AtomicUsize>,
+    dropped: Arc<AtomicBool>,
+}
+
+impl Iterator for Rows {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let n = self.pulled.fetch_add(1, Ordering::SeqCst);
+        (n < 100).then(|| vec![b'x'; 64 * 1024])
+    }
+}
+
+impl Drop for Rows {
+    fn drop(&mut self) {
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn a_failed_write_drops_the_iterator_early() {
+    let (pulled, dropped) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicBool::new(false)));
+    let rows = Rows { pulled: Arc::clone(&pulled), dropped: Arc::clone(&dropped) };
+    let response = Response::from_iter(200, "text/csv", rows);
+
+    // Room for the head and the first chunk, with its framing, only.
+    let mut client = HangsUp { written: Vec::new(), limit: 200 + 64 * 1024 };
+    let err = response.write_to(&mut client).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    assert!(dropped.load(Ordering::SeqCst));
+    assert!(pulled.load(Ordering::SeqCst) <= 2);
+
+    let body_start = client.written.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert!(client.written[body_start..].starts_with(b"10000\r\nxxxx"));
+}
@@ -0,0 +1,87 @@
+// JSON log lines parse back with the crate's own JSON parser, fields and
+// escaping intact.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use server_app::access_log;
+use server_app::http::{Request, Response};
+use server_app::json::{self, Value};
+use server_app::log::{self, Level, LogFormat};
+use server_app::server::{self, RequestContext, Served};
+use server_app::timeutil;
+
+#[test]
+fn access_line_has_every_field() {
+    let path = "/say/\"hi\"/\u{e9}t\u{e9}/\u{1f600}\\";
+    let mut request = Request::new("GET", "/");
+    request.path = path.to_string();
+    request.headers.set("User-Agent", "curl/8 \"quoted\"\tagent");
+    let response = Response::new(404, "Not Found").with_body("nope");
+    let served = Served {
+        id: 42,
+        method: &request.method,
+        path: &request.path,
+        duration: Duration::from_micros(12_500),
+        response_bytes: 4,
+        worker: Some(1),
+        client_abort: None,
+    };
+    let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+    let peer = "192.0.2.7:5000".parse().ok();
+
+    let line = access_log::json_log_line(peer, &request, &response, &served, time);
+    assert!(!line.contains('\n'));
+    let value = json::parse(&line).unwrap();
+    assert_eq!(value.get("ts").and_then(Value::as_str), Some("2023-11-14T22:13:20.123Z"));
+    assert_eq!(value.get("level").and_then(Value::as_str), Some("info"));
+    assert_eq!(value.get("request_id").and_then(Value::as_f64), Some(42.0));
+    assert_eq!(value.get("method").and_then(Value::as_str), Some("GET"));
+    assert_eq!(value.get("path").and_then(Value::as_str), Some(path));
+    assert_eq!(value.get("status").and_then(Value::as_f64), Some(404.0));
+    assert_eq!(value.get("duration_ms").and_then(Value::as_f64), Some(12.5));
+    assert_eq!(value.get("bytes").and_then(Value::as_f64), Some(4.0));
+    assert_eq!(value.get("peer").and_then(Value::as_str), Some("192.0.2.7"));
+    assert_eq!(value.get("user_agent").and_then(Value::as_str), Some("curl/8 \"quoted\"\tagent"));
+
+    let anonymous = access_log::json_log_line(None, &Request::new("GET", "/"), &response, &served, time);
+    let value = json::parse(&anonymous).unwrap();
+    assert!(value.get("peer").is_some_and(Value::is_null));
+    assert!(value.get("user_agent").is_some_and(Value::is_null));
+}
+
+#[test]
+fn message_lines_follow_the_format() {
+    let time = SystemTime::now();
+    let message = "Slow request: GET /\"x\" took 5ms";
+    assert_eq!(log::line(LogFormat::Text, Level::Warn, message, time), message);
+
+    let line = log::line(LogFormat::Json, Level::Warn, message, time);
+    let value = json::parse(&line).unwrap();
+    assert_eq!(value.get("level").and_then(Value::as_str), Some("warn"));
+    assert_eq!(value.get("message").and_then(Value::as_str), Some(message));
+    assert_eq!(value.get("ts").and_then(Value::as_str), Some(timeutil::format_rfc3339(time).as_str()));
+    assert!(value.get("request_id").is_none());
+
+    let context = RequestContext::for_request(9, &Request::new("GET", "/"));
+    let line = server::with_request_context(context, || log::line(LogFormat::Json, Level::Error, "boom", time));
+    assert_eq!(json::parse(&line).unwrap().get("request_id").and_then(Value::as_f64), Some(9.0));
+}
+
+#[test]
+fn rfc3339_timestamps() {
+    assert_eq!(timeutil::format_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    assert_eq!(timeutil::format_rfc3339(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29T00:00:00.000Z");
+    assert_eq!(timeutil::format_rfc3339(UNIX_EPOCH + Duration::from_secs(2_147_483_648)), "2038-01-19T03:14:08.000Z");
+}
+
+#[test]
+fn parser_round_trips_and_rejects() {
+    let document = r#" {"a": [1, -2.5e3, true, null], "b": {"c": "\u00e9\ud83d\ude00\n"}} "#;
+    let value = json::parse(document).unwrap();
+    assert_eq!(value.get("b").and_then(|b| b.get("c")).and_then(Value::as_str), Some("\u{e9}\u{1f600}\n"));
+    assert_eq!(json::parse(&value.to_string()).unwrap(), value);
+
+    for bad in ["", "{", "[1,]", "{\"a\" 1}", "01x", "\"\\ud800\"", "\"a\nb\"", "1 2", "\"\\u+123\""] {
+        assert!(json::parse(bad).is_err(), "{:?}", bad);
+    }
+    assert!(json::parse(&"[".repeat(10_000)).is_err());
+}
@@ -0,0 +1,92 @@
+// `server.keep_alive_max_requests` caps the requests after the first on
+// one connection: with 2, the third response says `Connection: close`
+// and the socket closes after it.
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use server_app::config::Config;
+use server_app::server::ServerConfig;
+
+const READYZ: &str = "GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+fn connect(port: u16) -> TcpStream {
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(15))).unwrap();
+    stream
+}
+
+// Read one response, going by its `Content-Length`.
+fn response(stream: &mut TcpStream) -> String {
+    let mut wire = Vec::new();
+    let mut byte = [0];
+    while !wire.ends_with(b"\r\n\r\n") {
+        assert_eq!(stream.read(&mut byte).unwrap(), 1, "closed mid-head: {:?}", String::from_utf8_lossy(&wire));
+        wire.push(byte[0]);
+    }
+    let head = String::from_utf8(wire).unwrap();
+    let length: usize = common::header(&head, "Content-Length").unwrap().parse().unwrap();
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).unwrap();
+    head + &String::from_utf8(body).unwrap()
+}
+
+fn closed(stream: &mut TcpStream) -> bool {
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    rest.is_empty()
+}
+
+#[test]
+fn the_limit_is_read_from_the_config() {
+    let config = ServerConfig::from_config(&Config::parse("[server]\nkeep_alive_max_requests = 2\n").unwrap()).unwrap();
+    assert_eq!(config.keep_alive_max_requests, Some(2));
+    assert!(!config.keep_alive_exhausted(1));
+    assert!(!config.keep_alive_exhausted(2));
+    assert!(config.keep_alive_exhausted(3));
+    assert_eq!(ServerConfig::default().keep_alive_max_requests, None);
+    assert!(!ServerConfig::default().keep_alive_exhausted(usize::MAX));
+}
+
+#[test]
+fn the_third_response_closes_the_connection() {
+    let server = common::start("keep-alive-max", "keep_alive_max_requests = 2\n");
+    let mut stream = connect(server.port);
+    for i in 1..=2 {
+        stream.write_all(READYZ.as_bytes()).unwrap();
+        let response = response(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 200 "), "{}", response);
+        assert_ne!(common::header(&response, "Connection"), Some("close"), "response {} closes", i);
+    }
+
+    stream.write_all(READYZ.as_bytes()).unwrap();
+    let third = response(&mut stream);
+    assert!(third.starts_with("HTTP/1.1 200 "), "{}", third);
+    assert_eq!(common::header(&third, "Connection"), Some("close"));
+    assert_eq!(common::body(&third), "ready");
+    assert!(closed(&mut stream), "more came after the closing response");
+}
+
+#[test]
+fn pipelined_requests_past_the_limit_go_unanswered() {
+    let server = common::start("keep-alive-max-pipelined", "keep_alive_max_requests = 2\n");
+    let mut stream = connect(server.port);
+    stream.write_all(READYZ.repeat(5).as_bytes()).unwrap();
+    let answered: Vec<String> = (0..3).map(|_| response(&mut stream)).collect();
+    assert_eq!(common::header(&answered[1], "Connection"), None);
+    assert_eq!(common::header(&answered[2], "Connection"), Some("close"));
+    assert!(closed(&mut stream));
+}
+
+#[test]
+fn without_a_limit_the_connection_stays_open() {
+    let server = common::start("keep-alive-unlimited", "");
+    let mut stream = connect(server.port);
+    for _ in 0..5 {
+        stream.write_all(READYZ.as_bytes()).unwrap();
+        let response = response(&mut stream);
+        assert_eq!(common::header(&response, "Connection"), None, "{}", response);
+    }
+}
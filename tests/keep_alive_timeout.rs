@@ -0,0 +1,73 @@
+// The keep-alive read timeout is set for every request, so it runs from
+// the last response rather than from when the connection opened.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use server_app::http::Response;
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+fn config(timeout: Duration) -> ServerConfig {
+    ServerConfig { keep_alive_timeout: timeout, ..ServerConfig::default() }
+}
+
+#[test]
+fn the_timeout_is_set_before_every_request() {
+    let pipelined = "GET /a HTTP/1.1\r\nHost: localhost\r\n\r\nGET /b HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let mut connection = Connection::new(MockStream::new([pipelined]));
+    let (first, second) = (Duration::from_secs(5), Duration::from_secs(2));
+
+    assert!(matches!(connection.read_next_request(&config(first), |_| None), Incoming::Request(_)));
+    assert_eq!(connection.get_ref().read_timeouts(), [Some(first)]);
+    // Settings replaced mid-session, as by a restart, apply to the next request.
+    assert!(matches!(connection.read_next_request(&config(second), |_| None), Incoming::Request(_)));
+    assert!(matches!(connection.read_next_request(&config(second), |_| None), Incoming::Closed));
+    assert_eq!(connection.get_ref().read_timeouts(), [Some(first), Some(second), Some(second)]);
+}
+
+// Serve `stream` until the client closes or goes quiet; returns the
+// number of requests served.
+fn serve(stream: TcpStream, timeout: Duration) -> usize {
+    let mut connection = Connection::new(stream);
+    let mut served = 0;
+    while let Incoming::Request(_) = connection.read_next_request(&config(timeout), |_| None) {
+        served += 1;
+        connection.send(&Response::new(200, "OK").with_body("ok")).unwrap();
+    }
+    served
+}
+
+fn read_response(client: &mut TcpStream) {
+    let mut response = Vec::new();
+    let mut chunk = [0; 256];
+    while !response.ends_with(b"ok") {
+        let n = client.read(&mut chunk).unwrap();
+        assert!(n > 0, "connection closed early");
+        response.extend_from_slice(&chunk[..n]);
+    }
+}
+
+#[test]
+fn a_busy_client_outlasts_the_timeout_and_a_stalled_one_does_not() {
+    let timeout = Duration::from_millis(300);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    let server = thread::spawn(move || serve(stream, timeout));
+
+    // Four requests 200ms apart keep the connection open for well over
+    // one timeout.
+    for _ in 0..4 {
+        thread::sleep(Duration::from_millis(200));
+        client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        read_response(&mut client);
+    }
+
+    // Then the client goes quiet and is let go about one timeout later.
+    let stalled = Instant::now();
+    assert_eq!(server.join().unwrap(), 4);
+    let waited = stalled.elapsed();
+    assert!(waited >= Duration::from_millis(250) && waited < Duration::from_secs(2), "{:?}", waited);
+}
@@ -0,0 +1,105 @@
+// Request heads and bodies over the configured limits are refused with
+// the status for the limit they broke, right at the limit they're fine,
+// and handler-supplied header text can't inject lines of its own.
+use server_app::http::{Limits, Response};
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+fn config() -> ServerConfig {
+    let mut config = ServerConfig {
+        limits: Limits {
+            max_request_line: 32,
+            max_header_line: 24,
+            max_headers: 3,
+            max_header_bytes: 64,
+            max_body_bytes: 16,
+            ..Limits::default()
+        },
+        ..ServerConfig::default()
+    };
+    config.allowed_hosts.clear();
+    config
+}
+
+// The status `wire` is refused with, or 200 if it's read as a request.
+fn status(wire: impl Into<Vec<u8>>) -> u16 {
+    let mut connection = Connection::new(MockStream::new([wire.into()]));
+    match connection.read_request(&config(), |_| None) {
+        Incoming::Request(_) => 200,
+        Incoming::Reject(response) => response.status,
+        Incoming::Closed => panic!("the connection closed without an answer"),
+    }
+}
+
+// A request line exactly `len` bytes long.
+fn request_line(len: usize) -> String {
+    format!("GET /{} HTTP/1.1", "a".repeat(len - "GET / HTTP/1.1".len()))
+}
+
+#[test]
+fn a_long_request_line_is_414() {
+    assert_eq!(request_line(32).len(), 32);
+    assert_eq!(status(format!("{}\r\nHost: a\r\n\r\n", request_line(32))), 200);
+    assert_eq!(status(format!("{}\r\nHost: a\r\n\r\n", request_line(33))), 414);
+    // Refused before its end arrives.
+    assert_eq!(status(request_line(33)), 414);
+}
+
+#[test]
+fn a_long_header_line_is_431() {
+    let line = |len: usize| format!("X-Long: {}", "v".repeat(len - "X-Long: ".len()));
+    assert_eq!(status(format!("GET / HTTP/1.1\r\nHost: a\r\n{}\r\n\r\n", line(24))), 200);
+    assert_eq!(status(format!("GET / HTTP/1.1\r\nHost: a\r\n{}\r\n\r\n", line(25))), 431);
+}
+
+#[test]
+fn too_many_headers_is_431() {
+    assert_eq!(status("GET / HTTP/1.1\r\nHost: a\r\nX-1: 1\r\nX-2: 2\r\n\r\n"), 200);
+    assert_eq!(status("GET / HTTP/1.1\r\nHost: a\r\nX-1: 1\r\nX-2: 2\r\nX-3: 3\r\n\r\n"), 431);
+}
+
+#[test]
+fn too_big_a_head_is_431() {
+    // Every line is within its limit, and there aren't too many of them,
+    // but together they come to `len` bytes, not counting the blank line.
+    let head = |len: usize| {
+        let head = format!("GET / HTTP/1.1\r\nHost: a\r\nX-Pad: {}\r\nX-Pad: ", "p".repeat(17));
+        let pad = len - head.len();
+        format!("{}{}\r\n\r\n", head, "p".repeat(pad))
+    };
+    assert_eq!(head(64).len(), 64 + 4);
+    assert_eq!(status(head(64)), 200);
+    assert_eq!(status(head(65)), 431);
+}
+
+#[test]
+fn too_long_a_body_is_413() {
+    let post = |len: usize| format!("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\n{}", len, "b".repeat(len));
+    assert_eq!(status(post(16)), 200);
+    assert_eq!(status(post(17)), 413);
+}
+
+#[test]
+fn limits_come_from_config() {
+    use server_app::config::Config;
+    let config = Config::parse("[server]\nmax_request_line = 100\nmax_headers = 5\nmax_body_bytes = 1024\n").unwrap();
+    let limits = ServerConfig::from_config(&config).unwrap().limits;
+    assert_eq!((limits.max_request_line, limits.max_headers, limits.max_body_bytes), (100, 5, 1024));
+    assert_eq!(limits.max_header_line, Limits::default().max_header_line);
+}
+
+#[test]
+fn header_injection_is_stripped() {
+    let response = Response::new(200, "OK\r\nX-Reason: injected")
+        .with_header("X-Value", "safe\r\nSet-Cookie: session=stolen")
+        .with_header("X-Bad\r\nName", "dropped")
+        .with_header("Bad Name", "dropped")
+        .with_body("body");
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    let wire = String::from_utf8(out).unwrap();
+    assert_eq!(
+        wire,
+        "HTTP/1.1 200 OKX-Reason: injected\r\nX-Value: safeSet-Cookie: session=stolen\r\nContent-Length: 4\r\n\r\nbody"
+    );
+}
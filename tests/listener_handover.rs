@@ -0,0 +1,128 @@
+// Handing the listener from one `Server` to the next, as a re-exec
+// restart does between processes: the new one accepts on the old one's
+// descriptor before the old one stops, so no connection is refused.
+#![cfg(unix)]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::IntoRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use server_app::http::Response;
+use server_app::net;
+use server_app::server::{inherited_fd_arg, read_request, AcceptLoop, Incoming, Server, ServerConfig};
+
+fn config() -> ServerConfig {
+    let mut config = ServerConfig { workers: 2, ..ServerConfig::default() };
+    config.allowed_hosts.clear();
+    config
+}
+
+// A server that answers every request with `name`.
+fn named_server(name: &'static str) -> Server {
+    Server::new(config(), move |mut stream, mut buffer, config| {
+        if let Incoming::Request(_) = read_request(&mut stream, &mut buffer, config, |_| None) {
+            let response = Response::new(200, "OK").with_header("Connection", "close").with_body(name);
+            let _ = response.write_to(&mut stream);
+        }
+    })
+}
+
+// Accept on `listener` for `server` until the returned flag is set.
+fn start(listener: TcpListener, server: Server) -> (Arc<AtomicBool>, thread::JoinHandle<()>) {
+    let mut accept = AcceptLoop::new(listener).unwrap().with_poll_interval(Duration::from_millis(10));
+    let stop = accept.shutdown_flag();
+    let running = thread::spawn(move || accept.run(|stream| server.serve(stream)).unwrap());
+    (stop, running)
+}
+
+// Requests one after another until told to stop: which server answered
+// each, and whatever went wrong.
+fn keep_asking(address: SocketAddr, stop: Arc<AtomicBool>) -> thread::JoinHandle<(Vec<String>, Vec<String>)> {
+    thread::spawn(move || {
+        let (mut answers, mut failures) = (Vec::new(), Vec::new());
+        while !stop.load(Ordering::SeqCst) {
+            let result = TcpStream::connect(address).and_then(|mut stream| {
+                stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+                stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+                let mut response = String::new();
+                stream.read_to_string(&mut response)?;
+                Ok(response)
+            });
+            match result {
+                Ok(response) if response.starts_with("HTTP/1.1 200 ") => {
+                    answers.push(response.rsplit("\r\n\r\n").next().unwrap().to_string());
+                }
+                Ok(response) => failures.push(format!("unexpected response {:?}", response)),
+                Err(e) => failures.push(e.to_string()),
+            }
+        }
+        (answers, failures)
+    })
+}
+
+#[test]
+fn no_connection_is_refused_across_the_handover() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    // What the old process passes on: its own descriptor, kept open.
+    let handed_over = listener.try_clone().unwrap().into_raw_fd();
+    let (stop_old, old) = start(listener, named_server("old"));
+
+    let done = Arc::new(AtomicBool::new(false));
+    let clients: Vec<_> = (0..4).map(|_| keep_asking(address, Arc::clone(&done))).collect();
+    thread::sleep(Duration::from_millis(150));
+
+    // The new server takes over the same socket by its number, and only
+    // once it is accepting does the old one stop and close its copy.
+    let inherited = net::inherited_fd(handed_over).unwrap();
+    assert_eq!(inherited.local_addr().unwrap(), address);
+    let (stop_new, new) = start(inherited, named_server("new"));
+    thread::sleep(Duration::from_millis(150));
+    stop_old.store(true, Ordering::SeqCst);
+    old.join().unwrap();
+    thread::sleep(Duration::from_millis(150));
+
+    done.store(true, Ordering::SeqCst);
+    let mut answers = Vec::new();
+    for client in clients {
+        let (answered, failures) = client.join().unwrap();
+        assert!(failures.is_empty(), "{:?}", failures);
+        answers.extend(answered);
+    }
+    assert!(answers.iter().any(|answer| answer == "old"));
+    assert!(answers.iter().any(|answer| answer == "new"));
+
+    // With the old server gone, the new one has the port to itself.
+    for _ in 0..20 {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("\r\n\r\nnew"), "{}", response);
+    }
+    stop_new.store(true, Ordering::SeqCst);
+    new.join().unwrap();
+}
+
+#[test]
+fn a_descriptor_that_is_not_a_listener_is_refused() {
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    let connected = TcpStream::connect(peer.local_addr().unwrap()).unwrap().into_raw_fd();
+    let error = net::inherited_fd(connected).unwrap_err();
+    assert!(error.to_string().contains("is not listening"), "{}", error);
+}
+
+#[test]
+fn the_flag_names_the_descriptor() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    assert_eq!(inherited_fd_arg(args(&["server.toml"])), Ok(None));
+    assert_eq!(inherited_fd_arg(args(&["--inherited-fd", "5", "server.toml"])), Ok(Some(5)));
+    assert_eq!(inherited_fd_arg(args(&["server.toml", "--inherited-fd", "12"])), Ok(Some(12)));
+    assert!(inherited_fd_arg(args(&["--inherited-fd"])).is_err());
+    assert!(inherited_fd_arg(args(&["--inherited-fd", "-1"])).is_err());
+    assert!(inherited_fd_arg(args(&["--inherited-fd", "three"])).is_err());
+}
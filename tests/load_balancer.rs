@@ -0,0 +1,152 @@
+// `LoadBalancer` spreads proxied requests over its upstreams, takes
+// refusing ones out for a cooldown and lets them back in afterwards.
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use server_app::clock::MockClock;
+use server_app::config::Config;
+use server_app::http::Request;
+use server_app::proxy::{LoadBalancer, ProxyError, ReverseProxy, Strategy};
+
+// An upstream answering every request with `name`.
+fn serve(listener: TcpListener, name: &'static str) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            thread::spawn(move || answer(stream, name));
+        }
+    });
+}
+
+fn answer(stream: TcpStream, name: &str) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        if line == "\r\n" {
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", name.len(), name);
+            writer.write_all(response.as_bytes()).unwrap();
+        }
+    }
+}
+
+fn upstream(name: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    serve(listener, name);
+    addr
+}
+
+// An address with nothing listening on it.
+fn stopped() -> String {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string()
+}
+
+fn proxy(balancer: LoadBalancer) -> ReverseProxy {
+    ReverseProxy::balanced(balancer)
+}
+
+fn get(proxy: &ReverseProxy) -> String {
+    let response = proxy.forward(&Request::new("GET", "/")).unwrap();
+    String::from_utf8(response.body).unwrap()
+}
+
+#[test]
+fn round_robin_alternates() {
+    let balancer = LoadBalancer::new(vec![upstream("a"), upstream("b")], Strategy::RoundRobin);
+    let proxy = proxy(balancer);
+    let answers: Vec<String> = (0..4).map(|_| get(&proxy)).collect();
+    assert_eq!(answers, ["a", "b", "a", "b"]);
+}
+
+#[test]
+fn a_stopped_upstream_fails_over_and_comes_back_after_the_cooldown() {
+    let down = stopped();
+    let clock = Arc::new(MockClock::new());
+    let balancer = LoadBalancer::new(vec![upstream("a"), down.clone()], Strategy::RoundRobin)
+        .with_ejection(2, Duration::from_secs(10))
+        .with_clock(clock.clone());
+    let proxy = proxy(balancer);
+
+    // Every request still gets an answer; two refusals eject the upstream.
+    for _ in 0..6 {
+        assert_eq!(get(&proxy), "a");
+    }
+    assert!(proxy.balancer().is_ejected(&down));
+
+    serve(TcpListener::bind(&down).unwrap(), "b");
+    clock.advance(Duration::from_secs(9));
+    assert!((0..4).all(|_| get(&proxy) == "a"), "still cooling down");
+
+    clock.advance(Duration::from_secs(1));
+    assert!(!proxy.balancer().is_ejected(&down));
+    let answers: Vec<String> = (0..4).map(|_| get(&proxy)).collect();
+    assert_eq!(answers.iter().filter(|answer| *answer == "b").count(), 2);
+}
+
+#[test]
+fn one_failure_after_the_cooldown_ejects_again() {
+    let clock = Arc::new(MockClock::new());
+    let balancer = LoadBalancer::new(vec![stopped()], Strategy::RoundRobin)
+        .with_ejection(3, Duration::from_secs(10))
+        .with_clock(clock.clone());
+    for _ in 0..3 {
+        balancer.pick(&[]).unwrap().failed();
+    }
+    assert!(balancer.pick(&[]).is_none());
+
+    clock.advance(Duration::from_secs(10));
+    balancer.pick(&[]).unwrap().failed();
+    assert!(balancer.pick(&[]).is_none());
+}
+
+#[test]
+fn with_every_upstream_ejected_the_answer_is_503() {
+    let balancer = LoadBalancer::new(vec![stopped(), stopped()], Strategy::RoundRobin)
+        .with_ejection(1, Duration::from_secs(60));
+    let proxy = proxy(balancer);
+    assert!(matches!(proxy.forward(&Request::new("GET", "/")), Err(ProxyError::Upstream(_))));
+    match proxy.forward(&Request::new("GET", "/")) {
+        Err(e @ ProxyError::NoHealthyUpstream) => assert_eq!(e.to_response().status, 503),
+        other => panic!("expected no healthy upstream, got {:?}", other.map(|r| r.status)),
+    }
+}
+
+#[test]
+fn least_connections_avoids_the_busy_upstream() {
+    let balancer = LoadBalancer::new(vec!["a:1".to_string(), "b:1".to_string()], Strategy::LeastConnections);
+    let busy = balancer.pick(&[]).unwrap();
+    for _ in 0..3 {
+        assert_ne!(balancer.pick(&[]).unwrap().index(), busy.index());
+    }
+}
+
+#[test]
+fn upstreams_and_strategy_come_from_config() {
+    let config = Config::parse(
+        "[api]\nupstreams = [\"127.0.0.1:9000\", \"127.0.0.1:9001\"]\nstrategy = \"least_connections\"\n",
+    )
+    .unwrap();
+    let balancer = LoadBalancer::from_config(&config, "api").unwrap();
+    assert_eq!(balancer.strategy(), Strategy::LeastConnections);
+    assert_eq!(balancer.upstreams().collect::<Vec<_>>(), ["127.0.0.1:9000", "127.0.0.1:9001"]);
+
+    let config = Config::parse("[api]\nupstreams = [\"a:1\"]\nstrategy = \"fastest\"\n").unwrap();
+    assert!(LoadBalancer::from_config(&config, "api").is_err());
+}
+
+#[test]
+fn max_failures_from_config_must_be_positive() {
+    let config = Config::parse("[api]\nupstreams = [\"a:1\"]\nmax_failures = 0\n").unwrap();
+    let error = LoadBalancer::from_config(&config, "api").err().expect("0 is refused");
+    assert!(error.to_string().contains("api.max_failures"), "{}", error);
+
+    let config = Config::parse("[api]\nupstreams = [\"a:1\"]\nmax_failures = 1\n").unwrap();
+    assert!(LoadBalancer::from_config(&config, "api").is_ok());
+}
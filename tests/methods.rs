@@ -0,0 +1,80 @@
+// Methods parse into `Method`, extension methods reach their routes, and
+// CONNECT and TRACE are refused unless turned on.
+use server_app::http::{Method, ParseError, Request, Response};
+use server_app::router::Router;
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+#[test]
+fn parses_every_standard_method() {
+    let standard = [
+        ("GET", Method::Get),
+        ("HEAD", Method::Head),
+        ("POST", Method::Post),
+        ("PUT", Method::Put),
+        ("DELETE", Method::Delete),
+        ("OPTIONS", Method::Options),
+        ("PATCH", Method::Patch),
+        ("TRACE", Method::Trace),
+        ("CONNECT", Method::Connect),
+    ];
+    for (name, method) in standard {
+        assert_eq!(name.parse::<Method>(), Ok(method.clone()));
+        assert_eq!(method.as_str(), name);
+    }
+    assert_eq!("PURGE".parse::<Method>(), Ok(Method::Extension("PURGE".to_string())));
+    assert_eq!("get".parse::<Method>(), Ok(Method::Extension("get".to_string())));
+    for invalid in ["", "GE T", "GET/", "(GET)", "G\u{e9}T"] {
+        assert_eq!(invalid.parse::<Method>(), Err(ParseError::InvalidMethod), "{:?}", invalid);
+    }
+}
+
+#[test]
+fn an_extension_method_reaches_its_handler() {
+    let mut router = Router::new();
+    router.route("purge", "/cache/*path", |request: &Request| {
+        Response::new(200, "OK").with_body(format!("purged {}", request.param("path").unwrap_or("")))
+    });
+    router.get("/cache/*path", |_: &Request| Response::new(200, "OK"));
+
+    let request = Request::parse(b"PURGE /cache/a/b HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    assert_eq!(request.method, Method::Extension("PURGE".to_string()));
+    let response = router.dispatch(&request);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"purged a/b");
+
+    let response = router.dispatch(&Request::new("BREW", "/cache/a"));
+    assert_eq!(response.status, 405);
+    assert_eq!(response.header("Allow"), Some("GET, PURGE"));
+}
+
+fn read(raw: &str, config: &ServerConfig) -> Incoming {
+    let mut connection = Connection::new(MockStream::new([raw.as_bytes().to_vec()]));
+    connection.read_request(config, |_| None)
+}
+
+#[test]
+fn a_method_that_is_not_a_token_is_a_bad_request() {
+    match read("GE(T / HTTP/1.1\r\nHost: localhost\r\n\r\n", &ServerConfig::default()) {
+        Incoming::Reject(response) => assert_eq!(response.status, 400),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+}
+
+#[test]
+fn connect_and_trace_are_refused_by_default() {
+    let config = ServerConfig { allowed_hosts: Vec::new(), ..ServerConfig::default() };
+    match read("CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n", &config) {
+        Incoming::Reject(response) => assert_eq!(response.status, 405),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+    match read("TRACE / HTTP/1.1\r\nHost: localhost\r\n\r\n", &config) {
+        Incoming::Reject(response) => assert_eq!(response.status, 501),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+
+    let enabled = ServerConfig { connect_tunnel: true, enable_trace: true, ..config };
+    let raw = "CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n";
+    assert!(matches!(read(raw, &enabled), Incoming::Request(request) if request.method == Method::Connect));
+    assert!(matches!(read("TRACE / HTTP/1.1\r\nHost: localhost\r\n\r\n", &enabled), Incoming::Request(_)));
+}
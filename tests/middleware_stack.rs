@@ -0,0 +1,64 @@
+// A `MiddlewareStack` stops at the first middleware that halts.
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use server_app::http::{Request, Response};
+use server_app::router::{MiddlewareResult, MiddlewareStack, Router};
+
+fn counting(calls: &Arc<AtomicUsize>) -> impl Fn(Request) -> MiddlewareResult + Send + Sync + 'static {
+    let calls = Arc::clone(calls);
+    move |request| {
+        calls.fetch_add(1, Ordering::SeqCst);
+        MiddlewareResult::Continue(request)
+    }
+}
+
+fn router(stack: MiddlewareStack, handled: &Arc<AtomicUsize>) -> Router {
+    let handled = Arc::clone(handled);
+    let mut router = Router::new();
+    router.middleware(stack).get("/", move |_: &Request| {
+        handled.fetch_add(1, Ordering::SeqCst);
+        Response::new(200, "OK")
+    });
+    router
+}
+
+#[test]
+fn halt_skips_the_rest_of_the_chain() {
+    let (first, third, handled) = (Arc::default(), Arc::default(), Arc::default());
+    let stack = MiddlewareStack::new()
+        .with(counting(&first))
+        .with(|request: Request| match request.header("Authorization") {
+            Some(_) => MiddlewareResult::Continue(request),
+            None => MiddlewareResult::Halt(Response::new(401, "Unauthorized")),
+        })
+        .with(counting(&third));
+    let router = router(stack, &handled);
+
+    let response = router.dispatch(&Request::new("GET", "/"));
+    assert_eq!(response.status, 401);
+    assert_eq!(first.load(Ordering::SeqCst), 1);
+    assert_eq!(third.load(Ordering::SeqCst), 0);
+    assert_eq!(handled.load(Ordering::SeqCst), 0);
+
+    let mut request = Request::new("GET", "/");
+    request.headers.set("Authorization", "Bearer t");
+    assert_eq!(router.dispatch(&request).status, 200);
+    assert_eq!(third.load(Ordering::SeqCst), 1);
+    assert_eq!(handled.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn continue_passes_the_rewritten_request_on() {
+    let stack = MiddlewareStack::new().with(|mut request: Request| {
+        request.headers.set("X-User", "alice");
+        MiddlewareResult::Continue(request)
+    });
+    let mut router = Router::new();
+    router.middleware(stack).get("/", |request: &Request| {
+        Response::new(200, "OK").with_body(request.header("X-User").unwrap_or(""))
+    });
+    assert_eq!(router.dispatch(&Request::new("GET", "/")).body, b"alice");
+}
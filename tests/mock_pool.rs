@@ -0,0 +1,58 @@
+// `MockThreadPool` queues jobs without running them until
+// `drain_pending`, so code written against `PoolLike` can be tested
+// without threads; the same code runs unchanged on a `ThreadPool`.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use server_app::testing::MockThreadPool;
+use server_app::{PoolLike, ThreadPool};
+
+// Code under test: one job per item, each adding its square to `total`.
+fn submit_squares<P: PoolLike>(pool: &P, items: &[usize], total: &Arc<AtomicUsize>) {
+    for &item in items {
+        let total = Arc::clone(total);
+        pool.execute(move || {
+            total.fetch_add(item * item, Ordering::SeqCst);
+        });
+    }
+}
+
+#[test]
+fn jobs_wait_until_drained() {
+    let pool = MockThreadPool::new();
+    let total = Arc::new(AtomicUsize::new(0));
+    submit_squares(&pool, &[1, 2, 3], &total);
+    assert_eq!(pool.pending(), 3);
+    assert_eq!(total.load(Ordering::SeqCst), 0, "nothing runs on submission");
+
+    assert_eq!(pool.drain_pending(), 3);
+    assert_eq!(total.load(Ordering::SeqCst), 14);
+    assert_eq!((pool.pending(), pool.executed()), (0, 3));
+    assert_eq!(pool.drain_pending(), 0);
+}
+
+#[test]
+fn jobs_run_in_submission_order_with_the_ones_they_submit() {
+    let pool = Arc::new(MockThreadPool::new());
+    let order = Arc::new(Mutex::new(Vec::new()));
+    for name in ["a", "b"] {
+        let (order, inner_pool) = (Arc::clone(&order), Arc::clone(&pool));
+        pool.execute(move || {
+            order.lock().unwrap().push(name.to_string());
+            let order = Arc::clone(&order);
+            inner_pool.execute(move || order.lock().unwrap().push(format!("{} again", name)));
+        });
+    }
+    assert_eq!(pool.drain_pending(), 4);
+    assert_eq!(*order.lock().unwrap(), ["a", "b", "a again", "b again"]);
+    assert_eq!(pool.executed(), 4);
+}
+
+#[test]
+fn the_same_code_runs_on_a_real_pool() {
+    let pool = ThreadPool::new(2);
+    let total = Arc::new(AtomicUsize::new(0));
+    submit_squares(&pool, &[1, 2, 3], &total);
+    drop(pool); // Runs what's queued, then waits for the workers.
+    assert_eq!(total.load(Ordering::SeqCst), 14);
+}
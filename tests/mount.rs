@@ -0,0 +1,75 @@
+// `Router::mount` nests a router's routes under a prefix: handlers see
+// the full path, and the sub-router's middleware only runs beneath it.
+use std::sync::{Arc, Mutex};
+
+use server_app::http::Request;
+use server_app::router::{Next, Router};
+
+type Log = Arc<Mutex<Vec<String>>>;
+
+fn logging(log: &Log, name: &'static str) -> impl Fn(&Request, Next<'_>) -> server_app::http::Response + Send + Sync {
+    let log = Arc::clone(log);
+    move |request, next| {
+        log.lock().unwrap().push(format!("{} {}", name, request.path));
+        next.run(request)
+    }
+}
+
+fn router(log: &Log) -> Router {
+    let mut users = Router::new();
+    users
+        .middleware(logging(log, "api"))
+        .get("/users", |request: &Request| request.path.clone())
+        .get("/users/:id", |request: &Request| format!("user {}", request.param("id").unwrap()));
+
+    let mut router = Router::new();
+    router
+        .middleware(logging(log, "root"))
+        .get("/health", |_: &Request| "ok")
+        .mount("/api/v1", users);
+    router
+}
+
+fn body(router: &Router, path: &str) -> (u16, String) {
+    let response = router.dispatch(&Request::new("GET", path));
+    (response.status, String::from_utf8(response.body).unwrap())
+}
+
+#[test]
+fn routes_answer_under_the_prefix_with_the_full_path() {
+    let log = Log::default();
+    let router = router(&log);
+    assert_eq!(body(&router, "/api/v1/users"), (200, "/api/v1/users".to_string()));
+    assert_eq!(body(&router, "/api/v1/users/7"), (200, "user 7".to_string()));
+    assert_eq!(body(&router, "/users").0, 404);
+    assert_eq!(body(&router, "/health"), (200, "ok".to_string()));
+    assert!(router.routes().contains(&("GET", "/api/v1/users/:id")));
+}
+
+#[test]
+fn slashes_around_the_prefix_do_not_matter() {
+    let log = Log::default();
+    let router = router(&log);
+    assert_eq!(body(&router, "/api/v1/users/").0, 200);
+    assert_eq!(body(&router, "/api/v1/users/7/").0, 200);
+
+    let mut inner = Router::new();
+    inner.get("/b/", |_: &Request| "b");
+    let mut outer = Router::new();
+    outer.mount("/a/", inner);
+    assert_eq!(body(&outer, "/a/b").0, 200);
+    assert_eq!(body(&outer, "/a//b").0, 200);
+}
+
+#[test]
+fn sub_router_middleware_only_runs_beneath_the_prefix() {
+    let log = Log::default();
+    let router = router(&log);
+    body(&router, "/health");
+    body(&router, "/api/v1/users");
+    body(&router, "/missing");
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["root /health", "root /api/v1/users", "api /api/v1/users", "root /missing"]
+    );
+}
@@ -0,0 +1,92 @@
+// `NdjsonWriter` streams JSON records one per line in a chunked body.
+use std::io::{self, Read};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use server_app::http::{ndjson_line, read_chunked_body, Limits, NdjsonWriter, Response};
+use server_app::json::{self, ToJson, Value};
+
+struct Row {
+    id: u64,
+    name: &'static str,
+}
+
+impl ToJson for Row {
+    fn to_json(&self) -> Value {
+        Value::object().with("id", self.id).with("name", self.name)
+    }
+}
+
+const ROWS: [Row; 5] = [
+    Row { id: 1, name: "one" },
+    Row { id: 2, name: "two\nlines" },
+    Row { id: 3, name: "three" },
+    Row { id: 4, name: "\"four\"" },
+    Row { id: 5, name: "five" },
+];
+
+// The head, and the chunked body decoded.
+fn split(wire: Vec<u8>) -> (String, Vec<u8>) {
+    let end = wire.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8(wire[..end].to_vec()).unwrap();
+    let mut rest = wire[end..].to_vec();
+    let (body, _) = read_chunked_body(&mut io::empty(), &mut rest, &Limits::default()).unwrap();
+    assert!(rest.is_empty());
+    (head, body)
+}
+
+fn records(body: &[u8]) -> Vec<Value> {
+    let text = std::str::from_utf8(body).unwrap();
+    assert!(text.ends_with('\n'));
+    text.lines().map(|line| json::parse(line).unwrap()).collect()
+}
+
+#[test]
+fn five_records_are_five_lines_of_json() {
+    let mut writer = NdjsonWriter::new(Vec::new()).unwrap();
+    for row in &ROWS {
+        writer.write_record(row).unwrap();
+    }
+    assert_eq!(writer.records(), 5);
+    let (head, body) = split(writer.finish().unwrap());
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains("Content-Type: application/x-ndjson\r\n"));
+    assert!(head.contains("Transfer-Encoding: chunked\r\n"));
+
+    let records = records(&body);
+    assert_eq!(records.len(), 5);
+    for (record, row) in records.iter().zip(&ROWS) {
+        assert_eq!(record.get("id").and_then(Value::as_f64), Some(row.id as f64));
+        assert_eq!(record.get("name").and_then(Value::as_str), Some(row.name));
+    }
+}
+
+#[test]
+fn records_go_out_as_they_are_written_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let response = Response::new(200, "OK").with_header("X-Export", "rows");
+        let mut writer = NdjsonWriter::with_response(&mut stream, response).unwrap();
+        for row in &ROWS {
+            writer.write_record(row).unwrap();
+        }
+        writer.finish().unwrap();
+    });
+    let mut client = TcpStream::connect(address).unwrap();
+    let mut wire = Vec::new();
+    client.read_to_end(&mut wire).unwrap();
+    server.join().unwrap();
+
+    let (head, body) = split(wire);
+    assert!(head.contains("X-Export: rows\r\n"));
+    assert_eq!(records(&body).len(), 5);
+}
+
+#[test]
+fn a_line_is_compact_and_escapes_its_newlines() {
+    assert_eq!(ndjson_line(&ROWS[1]), b"{\"id\":2,\"name\":\"two\\nlines\"}\n");
+    let value = Value::object().with("ok", true);
+    assert_eq!(ndjson_line(&value), b"{\"ok\":true}\n");
+}
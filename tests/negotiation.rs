@@ -0,0 +1,117 @@
+// `negotiate_content_type` picks the offered type the client's `Accept`
+// header prefers, and `/hello` and the built-in pages answer in HTML or
+// JSON by it.
+mod common;
+
+use server_app::http::{negotiate_content_type, Request};
+use server_app::json;
+use server_app::negotiation::{negotiate, parse_accept, status_page, MediaRange};
+
+const OFFERED: [&str; 2] = ["text/html", "application/json"];
+
+#[test]
+fn anything_goes_takes_the_first_offered() {
+    assert_eq!(negotiate_content_type("*/*", &OFFERED), Some("text/html"));
+    assert_eq!(negotiate_content_type("", &OFFERED), Some("text/html"));
+    assert_eq!(negotiate_content_type("*/*", &["application/json", "text/html"]), Some("application/json"));
+}
+
+#[test]
+fn the_higher_quality_wins() {
+    assert_eq!(negotiate_content_type("application/json;q=0.9,text/html", &OFFERED), Some("text/html"));
+    assert_eq!(negotiate_content_type("application/json,text/html;q=0.9", &OFFERED), Some("application/json"));
+    assert_eq!(negotiate_content_type("text/html;q=0.5, application/json ; q=0.8", &OFFERED), Some("application/json"));
+}
+
+#[test]
+fn nothing_acceptable_is_none() {
+    assert_eq!(negotiate_content_type("image/png", &OFFERED), None);
+    assert_eq!(negotiate_content_type("text/html;q=0, application/json;q=0", &OFFERED), None);
+    assert_eq!(negotiate_content_type("*/*", &[]), None);
+}
+
+#[test]
+fn hello_answers_in_the_preferred_type() {
+    let server = common::start("negotiation", "");
+    let json = common::get(server.port, "/hello", "Accept: application/json;q=0.9, text/plain\r\n");
+    assert_eq!(common::header(&json, "Content-Type"), Some("application/json"));
+    assert_eq!(common::body(&json), r#"{"message":"Hello!"}"#);
+
+    let html = common::get(server.port, "/hello", "Accept: application/json;q=0.9,text/html\r\n");
+    assert_eq!(common::header(&html, "Content-Type"), Some("text/html"));
+    assert_eq!(common::body(&html), "<h1>Hello!</h1>");
+
+    let refused = common::get(server.port, "/hello", "Accept: image/png\r\n");
+    assert!(refused.starts_with("HTTP/1.1 406 "), "{}", refused);
+}
+
+#[test]
+fn media_ranges_are_parsed_with_their_qualities() {
+    let ranges = parse_accept("text/html, application/*;q=0.5 , */*;q=0.1;level=1, bogus, image/png;q=2");
+    let parsed: Vec<(&str, &str, f32)> = ranges.iter().map(|r| (r.kind.as_str(), r.subtype.as_str(), r.q)).collect();
+    assert_eq!(
+        parsed,
+        [("text", "html", 1.0), ("application", "*", 0.5), ("*", "*", 0.1), ("image", "png", 1.0)]
+    );
+    assert!(parse_accept("*/html, /, text/").is_empty());
+    assert_eq!(parse_accept("text/plain;q=abc")[0].q, 1.0, "an unreadable q counts as 1");
+}
+
+#[test]
+fn qualities_order_the_choice_and_ties_keep_the_offer_order() {
+    let offered = ["text/html", "application/json", "text/plain"];
+    assert_eq!(negotiate("text/plain;q=0.9, application/json;q=0.8, */*;q=0.1", &offered), Some("text/plain"));
+    assert_eq!(negotiate("application/json, text/plain", &offered), Some("application/json"));
+    assert_eq!(negotiate("text/plain, application/json", &offered), Some("application/json"));
+    assert_eq!(negotiate("*/*;q=0.5, text/plain;q=0.6", &offered), Some("text/plain"));
+}
+
+#[test]
+fn the_most_specific_range_sets_the_quality() {
+    let range = |kind: &str, subtype: &str| MediaRange { kind: kind.to_string(), subtype: subtype.to_string(), q: 1.0 };
+    assert_eq!(range("text", "html").specificity("text/html"), Some(2));
+    assert_eq!(range("TEXT", "*").specificity("text/html"), Some(1));
+    assert_eq!(range("*", "*").specificity("text/html"), Some(0));
+    assert_eq!(range("image", "*").specificity("text/html"), None);
+
+    // `text/html` is refused outright though `text/*` would take it.
+    let offered = ["text/html", "text/plain"];
+    assert_eq!(negotiate("text/*, text/html;q=0", &offered), Some("text/plain"));
+    assert_eq!(negotiate("*/*;q=0.1, text/html", &offered), Some("text/html"));
+    assert_eq!(negotiate("*/*, text/html;q=0.2", &offered), Some("text/plain"));
+}
+
+#[test]
+fn malformed_headers_fall_back_to_the_default() {
+    assert_eq!(negotiate("garbage", &OFFERED), Some("text/html"));
+    assert_eq!(negotiate(";;,,", &OFFERED), Some("text/html"));
+    let mut request = Request::new("GET", "/");
+    request.headers.set("Accept", "not a media type");
+    assert_eq!(status_page(&request, 404, "Not Found").header("Content-Type"), Some("text/html; charset=utf-8"));
+}
+
+#[test]
+fn status_pages_come_as_json_when_preferred() {
+    let mut request = Request::new("GET", "/");
+    request.headers.set("Accept", "text/html;q=0.5, application/json");
+    let page = status_page(&request, 405, "Method \"X\" Not Allowed");
+    assert_eq!(page.header("Content-Type"), Some("application/json"));
+    let value = json::parse(std::str::from_utf8(&page.body).unwrap()).unwrap();
+    assert_eq!(value.get("status").and_then(|s| s.as_f64()), Some(405.0));
+    assert_eq!(value.get("error").and_then(|e| e.as_str()), Some("Method \"X\" Not Allowed"));
+}
+
+#[test]
+fn the_server_answers_a_json_404() {
+    let server = common::start("negotiation-404", "");
+    let response = common::get(server.port, "/no/such/page", "Accept: application/json\r\n");
+    assert!(response.starts_with("HTTP/1.1 404 "), "{}", response);
+    assert_eq!(common::header(&response, "Content-Type"), Some("application/json"));
+    // The binary's compression, when built in, varies it by encoding too.
+    let vary = if cfg!(feature = "compression") { "Accept, Accept-Encoding" } else { "Accept" };
+    assert_eq!(common::header(&response, "Vary"), Some(vary));
+    assert_eq!(common::body(&response), r#"{"status":404,"error":"Not Found"}"#);
+
+    let html = common::get(server.port, "/no/such/page", "");
+    assert_eq!(common::header(&html, "Content-Type"), Some("text/html"));
+}
@@ -0,0 +1,198 @@
+// Throws malformed, truncated and oversized input at the request parser
+// and at the connection loop, checking that every input ends in either a
+// request or a typed error, never a panic.
+//
+// Random inputs come from a fixed-seed PRNG, so a failure reproduces.
+use std::time::Duration;
+
+use server_app::http::{ParseError, Request};
+use server_app::server::{self, Connection, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+const VALID: &[u8] = b"POST /submit?x=1&y=2 HTTP/1.1\r\n\
+Host: 127.0.0.1:7878\r\n\
+User-Agent: robustness\r\n\
+Accept: text/html, application/json;q=0.9\r\n\
+Content-Type: application/x-www-form-urlencoded\r\n\
+Content-Length: 11\r\n\r\n\
+name=value!";
+
+// xorshift64*, good enough to scatter mutations.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn config() -> ServerConfig {
+    ServerConfig {
+        keep_alive_timeout: Duration::from_millis(10),
+        ..ServerConfig::default()
+    }
+}
+
+// Parse `input` every way a request can be read. Returns the parse result;
+// the connection loop only has to finish.
+fn exercise(input: &[u8]) -> Result<Request, ParseError> {
+    let parsed = Request::parse(input);
+    if let Err(e) = &parsed {
+        assert!((400..=505).contains(&e.status()), "{:?} has status {}", e, e.status());
+    }
+
+    // Delivered whole and a few bytes at a time, through the request loop
+    // a connection runs. Each request read uses up input, so it ends.
+    // Dribbling megabytes in would only be slow.
+    let config = config();
+    let chunk_lens = if input.len() < 4096 { vec![input.len().max(1), 7] } else { vec![input.len()] };
+    for chunk_len in chunk_lens {
+        let chunks: Vec<Vec<u8>> = input.chunks(chunk_len).map(<[u8]>::to_vec).collect();
+        let mut connection = Connection::new(MockStream::new(chunks));
+        loop {
+            match connection.read_request(&config, |_| None) {
+                Incoming::Request(_) => continue,
+                Incoming::Reject(response) => assert!(response.status >= 400, "rejected with {}", response.status),
+                Incoming::Closed => {}
+            }
+            break;
+        }
+    }
+
+    let mut buffer = Vec::new();
+    let _ = server::read_request_line(&mut MockStream::new([input.to_vec()]), &mut buffer, 8 * 1024);
+    parsed
+}
+
+#[test]
+fn valid_request_parses() {
+    let request = exercise(VALID).unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/submit");
+    assert_eq!(request.body, b"name=value!");
+}
+
+#[test]
+fn every_truncation_is_incomplete() {
+    for len in 0..VALID.len() {
+        assert_eq!(exercise(&VALID[..len]).unwrap_err(), ParseError::Incomplete, "truncated at {}", len);
+    }
+}
+
+#[test]
+fn random_mutations() {
+    let mut rng = Rng(0x5eed_1234_abcd_0042);
+    let mut parsed = 0;
+    for _ in 0..5_000 {
+        let mut input = VALID.to_vec();
+        for _ in 0..1 + rng.below(8) {
+            let at = rng.below(input.len());
+            match rng.below(4) {
+                0 => input[at] = rng.next() as u8,
+                1 => input[at] ^= 1 << rng.below(8),
+                2 => input.insert(at, b"\r\n:? \0%\xff"[rng.below(8)]),
+                _ => {
+                    input.remove(at);
+                }
+            }
+            if input.is_empty() {
+                break;
+            }
+        }
+        if exercise(&input).is_ok() {
+            parsed += 1;
+        }
+    }
+    // Some mutations (in the body, say) leave a valid request.
+    assert!(parsed > 0);
+}
+
+#[test]
+fn random_bytes() {
+    let mut rng = Rng(0x0dd_ba11);
+    for _ in 0..2_000 {
+        let len = rng.below(512);
+        let input: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+        let _ = exercise(&input);
+    }
+}
+
+#[test]
+fn repeated_headers() {
+    for count in [101, 10_000, 100_000] {
+        let mut input = b"GET / HTTP/1.1\r\nHost: 127.0.0.1:7878\r\n".to_vec();
+        for _ in 0..count {
+            input.extend_from_slice(b"X-Repeat: 1\r\n");
+        }
+        input.extend_from_slice(b"\r\n");
+        let error = exercise(&input).unwrap_err();
+        assert!(matches!(error, ParseError::TooManyHeaders | ParseError::HeadersTooLarge), "{:?}", error);
+    }
+
+    let hosts = b"GET / HTTP/1.1\r\nHost: a\r\nHost: a\r\n\r\n";
+    assert_eq!(exercise(hosts).unwrap_err(), ParseError::InvalidHost);
+    let lengths = b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab";
+    let _ = exercise(lengths);
+}
+
+#[test]
+fn nul_bytes() {
+    for at in 0..VALID.len() {
+        let mut input = VALID.to_vec();
+        input.insert(at, 0);
+        let _ = exercise(&input);
+    }
+    let _ = exercise(b"GET /a\0b HTTP/1.1\r\nHost: a\r\n\r\n");
+    let _ = exercise(b"GET / HTTP/1.1\r\nHost: a\r\nX-Nul: a\0b\r\n\r\n");
+}
+
+#[test]
+fn bare_line_endings() {
+    let lf = b"GET / HTTP/1.1\nHost: a\n\n";
+    let cr = b"GET / HTTP/1.1\rHost: a\r\r";
+    let mixed = b"GET / HTTP/1.1\r\nHost: a\nX: b\r\n\r\n";
+    let cr_in_value = b"GET / HTTP/1.1\r\nHost: a\r\nX: b\rc\r\n\r\n";
+    for input in [&lf[..], cr, mixed, cr_in_value] {
+        let _ = exercise(input);
+    }
+}
+
+#[test]
+fn megabyte_tokens() {
+    let huge = "a".repeat(1024 * 1024);
+    let inputs = [
+        format!("{} / HTTP/1.1\r\nHost: a\r\n\r\n", huge),
+        format!("GET /{} HTTP/1.1\r\nHost: a\r\n\r\n", huge),
+        format!("GET / HTTP/{}\r\nHost: a\r\n\r\n", huge),
+        format!("GET / HTTP/1.1\r\n{}: 1\r\nHost: a\r\n\r\n", huge),
+        format!("GET / HTTP/1.1\r\nHost: a\r\nX: {}\r\n\r\n", huge),
+        format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", huge),
+        format!("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1{}\r\n\r\n", "0".repeat(1024 * 1024)),
+        format!("GET / HTTP/1.1\r\nHost: a\r\n{}\r\n\r\n", "\r".repeat(1024 * 1024)),
+    ];
+    for input in &inputs {
+        assert!(exercise(input.as_bytes()).is_err());
+    }
+}
+
+#[test]
+fn odd_numbers() {
+    let inputs: [&[u8]; 6] = [
+        b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: -1\r\n\r\n",
+        b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 18446744073709551616\r\n\r\n",
+        b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: +5\r\n\r\nhello",
+        b"GET / HTTP/1.1\r\nHost: a:99999\r\n\r\n",
+        b"GET / HTTP/1.1\r\nHost: [::1\r\n\r\n",
+        b"GET / HTTP/9999999999999999999.1\r\nHost: a\r\n\r\n",
+    ];
+    for input in inputs {
+        let _ = exercise(input);
+    }
+}
@@ -0,0 +1,68 @@
+// `execute_in_worker` runs a job on the worker with that id, after what
+// is already pinned to it; an id past the last worker is refused.
+use std::sync::mpsc;
+use std::time::Duration;
+
+use server_app::{current_worker_id, PoolError, ThreadPool};
+
+const WAIT: Duration = Duration::from_secs(5);
+
+#[test]
+fn a_pinned_job_runs_on_the_worker_it_names() {
+    let pool = ThreadPool::new(4);
+    let (tx, rx) = mpsc::channel();
+    for round in 0..5 {
+        for id in 0..4 {
+            let tx = tx.clone();
+            pool.execute_in_worker(id, move || tx.send((round, id, current_worker_id())).unwrap()).unwrap();
+        }
+    }
+    for _ in 0..20 {
+        let (round, asked, ran_on) = rx.recv_timeout(WAIT).unwrap();
+        assert_eq!(ran_on, Some(asked), "round {}", round);
+    }
+    assert_eq!(current_worker_id(), None, "the test thread isn't a worker");
+}
+
+#[test]
+fn jobs_pinned_to_one_worker_run_in_order() {
+    let pool = ThreadPool::new(3);
+    let (tx, rx) = mpsc::channel();
+    for i in 0..50 {
+        let tx = tx.clone();
+        pool.execute_in_worker(2, move || tx.send(i).unwrap()).unwrap();
+    }
+    let order: Vec<i32> = (0..50).map(|_| rx.recv_timeout(WAIT).unwrap()).collect();
+    assert_eq!(order, (0..50).collect::<Vec<_>>());
+}
+
+#[test]
+fn a_pinned_job_waits_for_its_worker_even_when_others_are_free() {
+    let pool = ThreadPool::new(2);
+    let (started_tx, started) = mpsc::channel();
+    let (release, release_rx) = mpsc::channel::<()>();
+    pool.execute_in_worker(0, move || {
+        started_tx.send(()).unwrap();
+        release_rx.recv().unwrap();
+    })
+    .unwrap();
+    started.recv_timeout(WAIT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    pool.execute_in_worker(0, move || tx.send(current_worker_id()).unwrap()).unwrap();
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err(), "ran while worker 0 was busy");
+    release.send(()).unwrap();
+    assert_eq!(rx.recv_timeout(WAIT).unwrap(), Some(0));
+}
+
+#[test]
+fn an_id_past_the_last_worker_is_refused() {
+    let pool = ThreadPool::new(2);
+    let (tx, rx) = mpsc::channel::<()>();
+    let result = pool.execute_in_worker(2, move || tx.send(()).unwrap());
+    assert_eq!(result, Err(PoolError::InvalidWorkerId));
+    assert_eq!(pool.execute_in_worker(usize::MAX, || {}), Err(PoolError::InvalidWorkerId));
+    assert_eq!(PoolError::InvalidWorkerId.to_string(), "no worker has that id");
+    // The job was dropped rather than run somewhere else.
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+}
@@ -0,0 +1,115 @@
+// Requests sent back to back in one write are each read and answered in
+// order, and a request that arrives over several reads is put together
+// whole, through `Connection` and through the running binary.
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use server_app::http::Request;
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+fn config() -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.allowed_hosts.clear();
+    config
+}
+
+fn next(connection: &mut Connection<MockStream>) -> Request {
+    match connection.read_request(&config(), |_| None) {
+        Incoming::Request(request) => request,
+        Incoming::Reject(response) => panic!("rejected with {}", response.status),
+        Incoming::Closed => panic!("closed"),
+    }
+}
+
+#[test]
+fn two_requests_in_one_read_are_read_in_turn() {
+    let both = "POST /first HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhelloGET /second?x=1 HTTP/1.1\r\nHost: a\r\n\r\n";
+    let mut connection = Connection::new(MockStream::new([both]));
+
+    let first = next(&mut connection);
+    assert_eq!((first.method.as_str(), first.path.as_str()), ("POST", "/first"));
+    assert_eq!(first.body, b"hello");
+    assert!(connection.buffered().starts_with(b"GET /second"), "the second request is kept");
+
+    let second = next(&mut connection);
+    assert_eq!((second.method.as_str(), second.path.as_str()), ("GET", "/second"));
+    assert!(second.body.is_empty());
+    assert!(connection.buffered().is_empty());
+    assert!(matches!(connection.read_request(&config(), |_| None), Incoming::Closed));
+}
+
+#[test]
+fn a_request_split_across_reads_is_read_whole() {
+    let request = b"POST /upload HTTP/1.1\r\nHost: a\r\nContent-Length: 11\r\n\r\nhello world";
+    // A byte a read, and split right at the blank line and inside the body.
+    let one_byte: Vec<Vec<u8>> = request.iter().map(|&b| vec![b]).collect();
+    let at_the_seams = [&request[..20], &request[20..52], &request[52..56], &request[56..]].map(<[u8]>::to_vec);
+    for chunks in [one_byte, at_the_seams.to_vec()] {
+        let reads = chunks.len();
+        let mut connection = Connection::new(MockStream::new(chunks));
+        let request = next(&mut connection);
+        assert_eq!(request.path, "/upload", "over {} reads", reads);
+        assert_eq!(request.body, b"hello world", "over {} reads", reads);
+        assert!(matches!(connection.read_request(&config(), |_| None), Incoming::Closed));
+    }
+}
+
+#[test]
+fn a_second_request_split_across_reads_follows_the_first() {
+    let chunks = ["GET /a HTTP/1.1\r\nHost: a\r\n\r\nGET /b HT", "TP/1.1\r\nHo", "st: a\r\n", "\r\n"];
+    let mut connection = Connection::new(MockStream::new(chunks));
+    assert_eq!(next(&mut connection).path, "/a");
+    assert_eq!(next(&mut connection).path, "/b");
+}
+
+// Split `wire` into responses by their `Content-Length`.
+fn responses(wire: &str) -> Vec<&str> {
+    let mut rest = wire;
+    let mut responses = Vec::new();
+    while !rest.is_empty() {
+        let head_end = rest.find("\r\n\r\n").expect("a response head") + 4;
+        let length: usize = common::header(rest, "Content-Length").expect("a Content-Length").parse().unwrap();
+        responses.push(&rest[..head_end + length]);
+        rest = &rest[head_end + length..];
+    }
+    responses
+}
+
+#[test]
+fn the_server_answers_pipelined_requests_in_order() {
+    let server = common::start("pipelining-order", "");
+    let wire = common::raw(
+        server.port,
+        "GET /hello HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\n\r\n\
+         GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n\
+         GET /hello HTTP/1.1\r\nHost: localhost\r\nAccept: text/html\r\nConnection: close\r\n\r\n",
+    );
+    let responses = responses(&wire);
+    assert_eq!(responses.len(), 3, "{}", wire);
+    assert!(responses.iter().all(|response| response.starts_with("HTTP/1.1 200 ")));
+    assert_eq!(common::body(responses[0]), r#"{"message":"Hello!"}"#);
+    assert_eq!(common::body(responses[1]), "ready");
+    assert_eq!(common::body(responses[2]), "<h1>Hello!</h1>");
+    assert_eq!(common::header(responses[2], "Connection"), Some("close"));
+}
+
+#[test]
+fn the_server_waits_for_the_rest_of_a_split_request() {
+    let server = common::start("pipelining-split", "");
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(15))).unwrap();
+    stream.set_nodelay(true).unwrap();
+    for piece in ["GET /rea", "dyz HTTP/1.1\r\nHost: loc", "alhost\r\nConnection: close\r", "\n\r\n"] {
+        stream.write_all(piece.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(50));
+    }
+    let mut wire = String::new();
+    stream.read_to_string(&mut wire).unwrap();
+    assert!(wire.starts_with("HTTP/1.1 200 "), "{}", wire);
+    assert_eq!(common::body(&wire), "ready");
+}
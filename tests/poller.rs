@@ -0,0 +1,74 @@
+// Poller reports which registered sockets have something to read, gives
+// up at its timeout, and forgets deregistered sockets.
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use server_app::poller::Poller;
+
+// A connected localhost pair: (client end, server end).
+fn pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (client, server)
+}
+
+#[test]
+fn reports_the_socket_that_was_written_to() {
+    let (mut first_client, first_server) = pair();
+    let (_second_client, second_server) = pair();
+    let mut poller = Poller::new();
+    poller.register(&first_server, 1);
+    poller.register(&second_server, 2);
+    assert_eq!(poller.len(), 2);
+
+    first_client.write_all(b"ping").unwrap();
+    assert_eq!(poller.wait(Duration::from_secs(5)).unwrap(), vec![1]);
+}
+
+#[test]
+fn reports_a_hang_up_as_readable() {
+    let (client, server) = pair();
+    let mut poller = Poller::new();
+    poller.register(&server, 7);
+    drop(client);
+    assert_eq!(poller.wait(Duration::from_secs(5)).unwrap(), vec![7]);
+}
+
+#[test]
+fn times_out_with_nothing_ready() {
+    let (_client, server) = pair();
+    let mut poller = Poller::new();
+    poller.register(&server, 1);
+
+    let started = Instant::now();
+    assert!(poller.wait(Duration::from_millis(50)).unwrap().is_empty());
+    assert!(started.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn deregistered_sockets_are_not_reported() {
+    let (mut client, server) = pair();
+    let mut poller = Poller::new();
+    poller.register(&server, 1);
+    assert!(poller.deregister(1));
+    assert!(!poller.deregister(1));
+    assert!(poller.is_empty());
+
+    client.write_all(b"ping").unwrap();
+    assert!(poller.wait(Duration::from_millis(50)).unwrap().is_empty());
+}
+
+#[test]
+fn registering_a_token_again_replaces_its_socket() {
+    let (_quiet_client, quiet_server) = pair();
+    let (mut client, server) = pair();
+    let mut poller = Poller::new();
+    poller.register(&server, 1);
+    poller.register(&quiet_server, 1);
+    assert_eq!(poller.len(), 1);
+
+    client.write_all(b"ping").unwrap();
+    assert!(poller.wait(Duration::from_millis(50)).unwrap().is_empty());
+}
@@ -0,0 +1,63 @@
+// `preload_cache` stores every file under the size limit, in nested
+// directories too, and leaves bigger ones and HTML to be read on request.
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use server_app::cache::{CacheConfig, ResponseCache};
+use server_app::static_files::StaticFileServer;
+
+fn root(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("preload-cache-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("css/deep")).unwrap();
+    fs::write(dir.join("small.txt"), "hello").unwrap();
+    fs::write(dir.join("css/site.css"), vec![b'a'; 1023]).unwrap();
+    fs::write(dir.join("css/deep/tiny.js"), "1").unwrap();
+    fs::write(dir.join("limit.txt"), vec![b'b'; 1024]).unwrap();
+    fs::write(dir.join("big.bin"), vec![b'c'; 5000]).unwrap();
+    fs::write(dir.join("page.html"), "<p>page</p>").unwrap();
+    dir
+}
+
+#[test]
+fn files_under_the_limit_are_cached_and_larger_ones_are_not() {
+    let dir = root("limit");
+    let cache = Arc::new(ResponseCache::new(CacheConfig::default()));
+    let server = StaticFileServer::new(&dir).with_cache(Arc::clone(&cache), "/static/");
+    assert_eq!(server.preload_cache(1024).unwrap(), 3);
+    assert_eq!(cache.len(), 3);
+
+    for path in ["/static/small.txt", "/static/css/site.css", "/static/css/deep/tiny.js"] {
+        assert!(cache.contains(&format!("GET {}", path)), "{} was not preloaded", path);
+    }
+    let small = cache.get("GET /static/small.txt").unwrap();
+    assert_eq!(small.status, 200);
+    assert_eq!(small.body, b"hello");
+    assert_eq!(cache.get("GET /static/css/site.css").unwrap().body.len(), 1023);
+
+    // 1024 bytes is not under 1024; HTML is never stored.
+    for path in ["/static/limit.txt", "/static/big.bin", "/static/page.html"] {
+        assert!(!cache.contains(&format!("GET {}", path)), "{} was preloaded", path);
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn a_bigger_limit_takes_the_bigger_files_too() {
+    let dir = root("bigger");
+    let cache = Arc::new(ResponseCache::new(CacheConfig::default()));
+    let server = StaticFileServer::new(&dir).with_cache(Arc::clone(&cache), "/static");
+    assert_eq!(server.preload_cache(1 << 20).unwrap(), 5);
+    assert!(cache.contains("GET /static/limit.txt"));
+    assert!(cache.contains("GET /static/big.bin"));
+    assert!(!cache.contains("GET /static/page.html"));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn without_a_cache_nothing_is_preloaded() {
+    let dir = root("none");
+    assert_eq!(StaticFileServer::new(&dir).preload_cache(1024).unwrap(), 0);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,50 @@
+// Range headers parse into byte ranges, which are checked against the
+// representation's size as RFC 7233 says.
+use server_app::http::{parse_ranges, ByteRange, RangeError, RangeSpec, MAX_RANGES};
+
+#[test]
+fn parses_each_kind_of_range() {
+    assert_eq!(
+        RangeSpec::parse("bytes=0-499, 9500-, -500"),
+        Ok(vec![ByteRange::FromTo(0, 499), ByteRange::From(9500), ByteRange::Suffix(500)])
+    );
+    assert_eq!(RangeSpec::parse(" Bytes = 5-5 "), Ok(vec![ByteRange::FromTo(5, 5)]));
+}
+
+#[test]
+fn malformed_headers_are_invalid() {
+    for header in ["", "bytes=", "bytes=-", "bytes=5", "bytes=9-5", "bytes=a-b", "bytes=+1-2", "items=0-5", "0-5"] {
+        assert_eq!(RangeSpec::parse(header), Err(RangeError::Invalid), "{:?}", header);
+    }
+    let too_many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+    assert_eq!(RangeSpec::parse(&too_many), Err(RangeError::Invalid));
+}
+
+#[test]
+fn suffix_ranges_take_the_end() {
+    assert_eq!(ByteRange::Suffix(500).validate(10_000), Ok((9500, 9999)));
+    // Longer than the file: the whole file.
+    assert_eq!(ByteRange::Suffix(500).validate(100), Ok((0, 99)));
+    assert_eq!(ByteRange::Suffix(0).validate(100), Err(RangeError::Unsatisfiable));
+    assert_eq!(ByteRange::Suffix(5).validate(0), Err(RangeError::Unsatisfiable));
+}
+
+#[test]
+fn ends_beyond_eof_are_clipped_and_starts_beyond_it_unsatisfiable() {
+    assert_eq!(ByteRange::FromTo(90, 500).validate(100), Ok((90, 99)));
+    assert_eq!(ByteRange::From(50).validate(100), Ok((50, 99)));
+    assert_eq!(ByteRange::FromTo(100, 200).validate(100), Err(RangeError::Unsatisfiable));
+    assert_eq!(ByteRange::From(100).validate(100), Err(RangeError::Unsatisfiable));
+}
+
+#[test]
+fn multiple_ranges_are_sorted_and_overlaps_merged() {
+    assert_eq!(parse_ranges("bytes=50-59, 0-9, 20-29", 100), Ok(vec![0..=9, 20..=29, 50..=59]));
+    // Overlapping and adjacent ranges become one.
+    assert_eq!(parse_ranges("bytes=0-20, 10-30, 31-40, -5", 100), Ok(vec![0..=40, 95..=99]));
+    // Ranges past the end are dropped while others remain...
+    assert_eq!(parse_ranges("bytes=0-9, 200-300", 100), Ok(vec![0..=9]));
+    // ...but if none remain, the request is unsatisfiable.
+    assert_eq!(parse_ranges("bytes=200-300, 500-", 100), Err(RangeError::Unsatisfiable));
+    assert_eq!(RangeSpec::unsatisfied_content_range(100), "bytes */100");
+}
@@ -0,0 +1,109 @@
+// Requests are logged as parsed fields, not raw bytes, and credentials in
+// headers and query strings come out as `<redacted>` in every format.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use server_app::access_log;
+use server_app::config::Config;
+use server_app::http::{Request, Response};
+use server_app::json::{self, Value};
+use server_app::log::{self, LogFormat};
+use server_app::redact::Redactor;
+use server_app::router::{Router, TraceMiddleware};
+use server_app::server::{Served, ServerConfig};
+
+fn request() -> Request {
+    let mut request = Request::new("GET", "/account");
+    request.query = Some("token=s3cret&page=2&Token=again&flag".to_string());
+    request.headers.set("Host", "localhost");
+    request.headers.set("Authorization", "Bearer s3cret");
+    request.headers.append("Cookie", "session=s3cret");
+    request.headers.append("Cookie", "theme=dark");
+    request.headers.set("X-Api-Key", "s3cret");
+    request.headers.set("User-Agent", "curl/8");
+    request
+}
+
+#[test]
+fn text_lines_redact_the_values_named() {
+    let redactor = Redactor::default().with_header("X-Api-Key").with_query_param("token");
+    let line = log::request_line(LogFormat::Text, 7, &request(), &redactor, true, SystemTime::now());
+    assert_eq!(
+        line,
+        "Request #7: GET /account?token=<redacted>&page=2&Token=<redacted>&flag HTTP/1.1; Host: localhost; \
+         Authorization: <redacted>; Cookie: <redacted>; Cookie: <redacted>; X-Api-Key: <redacted>; User-Agent: curl/8"
+    );
+    assert!(!line.contains("s3cret"));
+
+    // Without the headers, it's the request line alone.
+    let line = log::request_line(LogFormat::Text, 7, &request(), &redactor, false, SystemTime::now());
+    assert_eq!(line, "Request #7: GET /account?token=<redacted>&page=2&Token=<redacted>&flag HTTP/1.1");
+
+    // Only what's named is redacted.
+    let line = log::request_line(LogFormat::Text, 7, &request(), &Redactor::none(), true, SystemTime::now());
+    assert!(line.contains("Authorization: Bearer s3cret") && line.contains("token=s3cret"));
+}
+
+#[test]
+fn json_lines_redact_the_values_named() {
+    let redactor = Redactor::default().with_query_param("TOKEN");
+    let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let line = log::request_line(LogFormat::Json, 7, &request(), &redactor, true, time);
+    assert!(!line.contains("s3cret=") && !line.contains("Bearer"));
+
+    let value = json::parse(&line).unwrap();
+    assert_eq!(value.get("level").and_then(Value::as_str), Some("info"));
+    assert_eq!(value.get("request_id").and_then(Value::as_f64), Some(7.0));
+    let fields = value.get("request").unwrap();
+    assert_eq!(fields.get("method").and_then(Value::as_str), Some("GET"));
+    assert_eq!(fields.get("target").and_then(Value::as_str), Some("/account?token=<redacted>&page=2&Token=<redacted>&flag"));
+    assert_eq!(fields.get("version").and_then(Value::as_str), Some("HTTP/1.1"));
+    let headers = fields.get("headers").unwrap();
+    let header = |name| headers.get(name).and_then(Value::as_str);
+    assert_eq!(header("Authorization"), Some("<redacted>"));
+    assert_eq!(header("Cookie"), Some("<redacted>, <redacted>"));
+    // Headers not on the list pass through.
+    assert_eq!(header("Host"), Some("localhost"));
+    assert_eq!(header("X-Api-Key"), Some("s3cret"));
+    assert_eq!(header("User-Agent"), Some("curl/8"));
+
+    let line = log::request_line(LogFormat::Json, 7, &request(), &redactor, false, time);
+    assert!(json::parse(&line).unwrap().get("request").unwrap().get("headers").is_none());
+}
+
+#[test]
+fn the_access_log_and_trace_use_the_process_redactor() {
+    log::set_redactor(Redactor::default().with_header("X-Api-Key").with_query_param("token"));
+    let request = request();
+    let served = Served {
+        id: 1,
+        method: &request.method,
+        path: &request.path,
+        duration: Duration::ZERO,
+        response_bytes: 0,
+        worker: None,
+        client_abort: None,
+    };
+    let line = access_log::common_log_line(None, &request, &Response::new(200, "OK"), &served, SystemTime::now());
+    assert!(line.contains("\"GET /account?token=<redacted>&page=2&Token=<redacted>&flag HTTP/1.1\""), "{}", line);
+
+    let mut router = Router::new();
+    router.middleware(TraceMiddleware::new());
+    let mut trace = request.clone();
+    trace.method = "TRACE".parse().unwrap();
+    let echo = String::from_utf8(router.dispatch(&trace).body).unwrap();
+    assert!(echo.starts_with("TRACE /account?token=<redacted>&page=2&Token=<redacted>&flag HTTP/1.1\r\n"));
+    assert!(echo.contains("User-Agent: curl/8\r\n"));
+    assert!(!echo.contains("s3cret"), "{}", echo);
+}
+
+#[test]
+fn the_lists_come_from_the_config() {
+    let server = ServerConfig::from_config(&Config::parse("[server]\n").unwrap()).unwrap();
+    assert_eq!(server.redact, Redactor::default());
+
+    let config = Config::parse("[server]\nredact_headers = [\"X-Api-Key\"]\nredact_query_params = [\"token\", \"sig\"]\n");
+    let server = ServerConfig::from_config(&config.unwrap()).unwrap();
+    assert!(server.redact.redacts_header("x-api-key"));
+    assert!(!server.redact.redacts_header("Authorization"), "the list replaces the defaults");
+    assert_eq!(server.redact.query("sig=abc&a=b"), "sig=<redacted>&a=b");
+}
@@ -0,0 +1,97 @@
+// Every request body is read to its end, chunked ones and their trailers
+// included, so the next request on the connection parses from the right
+// place; one that can't be read closes the connection instead.
+use server_app::http::{Limits, Request};
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+fn connect<I, C>(chunks: I) -> Connection<MockStream>
+where
+    I: IntoIterator<Item = C>,
+    C: Into<Vec<u8>>,
+{
+    Connection::new(MockStream::new(chunks))
+}
+
+fn next(connection: &mut Connection<MockStream>, config: &ServerConfig) -> Request {
+    match connection.read_request(config, |_| None) {
+        Incoming::Request(request) => request,
+        other => panic!("expected a request, got {:?}", other),
+    }
+}
+
+const FOLLOWING: &str = "GET /next HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+#[test]
+fn an_ignored_body_does_not_become_the_next_request() {
+    let config = ServerConfig::default();
+    let body = "GET /smuggled HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let post = format!("POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+    let mut all_at_once = connect([post.as_str(), FOLLOWING]);
+    // The handler never looks at the body; the next request is still `/next`.
+    assert_eq!(next(&mut all_at_once, &config).path, "/upload");
+    assert_eq!(next(&mut all_at_once, &config).path, "/next");
+
+    let mut trickled = connect(["POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\n01234", "56789GET /next", " HTTP/1.1\r\nHost: localhost\r\n\r\n"]);
+    assert_eq!(next(&mut trickled, &config).body, b"0123456789");
+    assert_eq!(next(&mut trickled, &config).path, "/next");
+}
+
+const CHUNKED: &str = "POST /upload HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+    5\r\nhello\r\n7;name=value\r\n, world\r\n0\r\nChecksum: abc123\r\nExpires: never\r\n\r\n";
+
+#[test]
+fn chunked_bodies_are_decoded_with_their_trailers() {
+    let config = ServerConfig::default();
+    let mut connection = connect([CHUNKED, FOLLOWING]);
+    let request = next(&mut connection, &config);
+    assert_eq!(request.body, b"hello, world");
+    assert_eq!(request.trailers().get("Checksum"), Some("abc123"));
+    assert_eq!(request.trailers().get("Expires"), Some("never"));
+    assert_eq!(request.header("Checksum"), None);
+    assert_eq!(next(&mut connection, &config).path, "/next");
+
+    // A byte at a time makes no difference.
+    let mut connection = connect(format!("{}{}", CHUNKED, FOLLOWING).bytes().map(|b| vec![b]));
+    assert_eq!(next(&mut connection, &config).body, b"hello, world");
+    assert_eq!(next(&mut connection, &config).path, "/next");
+
+    let parsed = Request::parse(CHUNKED.as_bytes()).unwrap();
+    assert_eq!(parsed.body, b"hello, world");
+    assert_eq!(parsed.trailers().get("Checksum"), Some("abc123"));
+    assert!(Request::new("GET", "/").trailers().is_empty());
+}
+
+fn rejected(status: u16, chunks: &[&str], config: &ServerConfig) {
+    let mut connection = connect(chunks.iter().copied());
+    match connection.read_request(config, |_| None) {
+        Incoming::Reject(response) => {
+            assert_eq!(response.status, status, "{:?}", chunks);
+            assert_eq!(response.header("Connection"), Some("close"));
+        }
+        other => panic!("expected a {} for {:?}, got {:?}", status, chunks, other),
+    }
+}
+
+#[test]
+fn a_body_that_is_not_read_closes_the_connection() {
+    let limits = Limits { max_body_bytes: 8, ..Limits::default() };
+    let config = ServerConfig { limits, ..ServerConfig::default() };
+    rejected(413, &["POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 9\r\n\r\n012345678", FOLLOWING], &config);
+    rejected(413, &["POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n01234\r\n4\r\n5678\r\n0\r\n\r\n"], &config);
+    // Right at the limit is fine.
+    let mut connection = connect(["POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n01234\r\n3\r\n567\r\n0\r\n\r\n", FOLLOWING]);
+    assert_eq!(next(&mut connection, &config).body, b"01234567");
+    assert_eq!(next(&mut connection, &config).path, "/next");
+}
+
+#[test]
+fn malformed_framing_is_refused() {
+    let config = ServerConfig::default();
+    let head = "POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n";
+    for body in ["z\r\nhello\r\n0\r\n\r\n", "5\r\nhelloXX0\r\n\r\n", "5\nhello\r\n0\r\n\r\n", "0\r\nno colon\r\n\r\n", "5\r\nhel"] {
+        rejected(400, &[&format!("{}{}", head, body)], &config);
+    }
+    rejected(501, &["POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n"], &config);
+    rejected(400, &["POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked, gzip\r\n\r\n0\r\n\r\n"], &config);
+}
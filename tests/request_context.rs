@@ -0,0 +1,33 @@
+// The request context is there while a request is served and gone
+// between pool jobs.
+use std::sync::mpsc;
+
+use server_app::http::Request;
+use server_app::server::{self, RequestContext};
+use server_app::ThreadPool;
+
+#[test]
+fn context_is_set_inside_a_job_and_cleared_after() {
+    let pool = ThreadPool::new(1);
+    let (sender, receiver) = mpsc::channel();
+
+    let inside = sender.clone();
+    pool.execute(move || {
+        let mut request = Request::new("GET", "/");
+        request.headers.set("traceparent", "00-abc-def-01");
+        let context = RequestContext::for_request(7, &request);
+        server::with_request_context(context, || {
+            inside.send(server::current_request_context()).unwrap();
+        });
+        inside.send(server::current_request_context()).unwrap();
+    });
+    let context = receiver.recv().unwrap().expect("a context inside the job");
+    assert_eq!(context.request_id, 7);
+    assert_eq!(context.worker_id, Some(0));
+    assert_eq!(context.trace_header("Traceparent"), Some("00-abc-def-01"));
+    assert_eq!(receiver.recv().unwrap(), None, "restored once the request is done");
+
+    pool.execute(move || sender.send(server::current_request_context()).unwrap());
+    assert_eq!(receiver.recv().unwrap(), None);
+    assert_eq!(server::current_request_context(), None);
+}
@@ -0,0 +1,240 @@
+// How the parser treats stray line breaks and ambiguous body framing.
+// Each fixture is the raw bytes a client might send with what becomes of
+// them, with strict line endings (the default) and with
+// `lenient_line_endings`. Ambiguous framing is refused either way, since
+// behind a proxy that reads it differently it smuggles a second request
+// past it.
+use std::time::Duration;
+
+use server_app::config::Config;
+use server_app::http::{Limits, ParseError, Request};
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Outcome {
+    Accepted(&'static str), // With this body.
+    Rejected(ParseError),
+}
+
+use Outcome::{Accepted, Rejected};
+
+struct Fixture {
+    name: &'static str,
+    bytes: &'static [u8],
+    strict: Outcome,
+    lenient: Outcome,
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "plain CRLF",
+        bytes: b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 2\r\n\r\nhi",
+        strict: Accepted("hi"),
+        lenient: Accepted("hi"),
+    },
+    Fixture {
+        name: "one leading CRLF is skipped",
+        bytes: b"\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n",
+        strict: Accepted(""),
+        lenient: Accepted(""),
+    },
+    Fixture {
+        name: "a second leading CRLF is an empty request line",
+        bytes: b"\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n",
+        strict: Rejected(ParseError::InvalidRequestLine),
+        lenient: Rejected(ParseError::InvalidRequestLine),
+    },
+    Fixture {
+        name: "a leading bare LF",
+        bytes: b"\nGET / HTTP/1.1\r\nHost: a\r\n\r\n",
+        strict: Rejected(ParseError::InvalidRequestLine),
+        lenient: Accepted(""),
+    },
+    Fixture {
+        name: "bare LF line endings throughout",
+        bytes: b"POST / HTTP/1.1\nHost: a\nContent-Length: 2\n\nhi",
+        strict: Rejected(ParseError::InvalidRequestLine),
+        lenient: Accepted("hi"),
+    },
+    Fixture {
+        name: "a bare LF after a header",
+        bytes: b"GET / HTTP/1.1\r\nHost: a\nX-Other: b\r\n\r\n",
+        strict: Rejected(ParseError::InvalidHeader),
+        lenient: Accepted(""),
+    },
+    Fixture {
+        name: "CRLF lines ended by a bare LF",
+        bytes: b"GET / HTTP/1.1\r\nHost: a\r\n\n",
+        strict: Rejected(ParseError::InvalidHeader),
+        lenient: Accepted(""),
+    },
+    Fixture {
+        name: "a bare CR inside a header value",
+        bytes: b"GET / HTTP/1.1\r\nHost: a\r\nX-Split: a\rb\r\n\r\n",
+        strict: Rejected(ParseError::InvalidHeader),
+        lenient: Rejected(ParseError::InvalidHeader),
+    },
+    Fixture {
+        name: "a bare CR ending the request line",
+        bytes: b"GET / HTTP/1.1\rHost: a\r\n\r\n",
+        strict: Rejected(ParseError::InvalidRequestLine),
+        lenient: Rejected(ParseError::InvalidRequestLine),
+    },
+    Fixture {
+        name: "a NUL in a header value",
+        bytes: b"GET / HTTP/1.1\r\nHost: a\r\nX-Nul: a\0b\r\n\r\n",
+        strict: Rejected(ParseError::InvalidHeader),
+        lenient: Rejected(ParseError::InvalidHeader),
+    },
+    Fixture {
+        name: "a vertical tab ending a header name",
+        bytes: b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length\x0b: 2\r\n\r\nhi",
+        strict: Rejected(ParseError::InvalidHeader),
+        lenient: Rejected(ParseError::InvalidHeader),
+    },
+    Fixture {
+        name: "a separator in a header name",
+        bytes: b"GET / HTTP/1.1\r\nHost: a\r\nX(Other): b\r\n\r\n",
+        strict: Rejected(ParseError::InvalidHeader),
+        lenient: Rejected(ParseError::InvalidHeader),
+    },
+    Fixture {
+        name: "padded header values are trimmed",
+        bytes: b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: \t 2  \r\n\r\nhi",
+        strict: Accepted("hi"),
+        lenient: Accepted("hi"),
+    },
+    Fixture {
+        name: "a folded header line",
+        bytes: b"GET / HTTP/1.1\r\nHost: a\r\nX-Long: a\r\n b\r\n\r\n",
+        strict: Rejected(ParseError::InvalidHeader),
+        lenient: Rejected(ParseError::InvalidHeader),
+    },
+    Fixture {
+        name: "Transfer-Encoding and Content-Length",
+        bytes: b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nContent-Length: 4\r\n\r\n0\r\n\r\n",
+        strict: Rejected(ParseError::ConflictingLength),
+        lenient: Rejected(ParseError::ConflictingLength),
+    },
+    Fixture {
+        name: "Content-Length and then Transfer-Encoding",
+        bytes: b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 0\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        strict: Rejected(ParseError::ConflictingLength),
+        lenient: Rejected(ParseError::ConflictingLength),
+    },
+    Fixture {
+        name: "two Content-Lengths that differ",
+        bytes: b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 2\r\nContent-Length: 5\r\n\r\nhello",
+        strict: Rejected(ParseError::ConflictingLength),
+        lenient: Rejected(ParseError::ConflictingLength),
+    },
+    Fixture {
+        name: "a Content-Length list that differs",
+        bytes: b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 2, 5\r\n\r\nhello",
+        strict: Rejected(ParseError::ConflictingLength),
+        lenient: Rejected(ParseError::ConflictingLength),
+    },
+    Fixture {
+        name: "two Content-Lengths that agree",
+        bytes: b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 2\r\nContent-Length: 2, 2\r\n\r\nhi",
+        strict: Accepted("hi"),
+        lenient: Accepted("hi"),
+    },
+    Fixture {
+        name: "a signed Content-Length",
+        bytes: b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: +2\r\n\r\nhi",
+        strict: Rejected(ParseError::InvalidHeader),
+        lenient: Rejected(ParseError::InvalidHeader),
+    },
+];
+
+// The body parsed, or why not, next to what the fixture expects.
+fn outcome(bytes: &[u8], limits: &Limits, expected: &Outcome) -> (Result<Vec<u8>, ParseError>, Result<Vec<u8>, ParseError>) {
+    let expected = match expected {
+        Accepted(body) => Ok(body.as_bytes().to_vec()),
+        Rejected(e) => Err(e.clone()),
+    };
+    (Request::parse_with_limits(bytes, limits).map(|request| request.body), expected)
+}
+
+fn lenient() -> Limits {
+    Limits { lenient_line_endings: true, ..Limits::default() }
+}
+
+#[test]
+fn every_fixture_has_its_outcome() {
+    for fixture in FIXTURES {
+        let (parsed, expected) = outcome(fixture.bytes, &Limits::default(), &fixture.strict);
+        assert_eq!(parsed, expected, "strict: {}", fixture.name);
+        let (parsed, expected) = outcome(fixture.bytes, &lenient(), &fixture.lenient);
+        assert_eq!(parsed, expected, "lenient: {}", fixture.name);
+    }
+}
+
+#[test]
+fn a_strict_parser_refuses_bare_lf_before_the_head_is_complete() {
+    // Otherwise it would wait for a CRLF CRLF that isn't coming.
+    let partial = b"GET / HTTP/1.1\nHost: a\n";
+    assert_eq!(Request::parse_head(partial, &Limits::default()).unwrap_err(), ParseError::InvalidRequestLine);
+    assert_eq!(Request::parse_head(partial, &lenient()).unwrap_err(), ParseError::Incomplete);
+    assert_eq!(Request::parse_head(b"\r", &Limits::default()).unwrap_err(), ParseError::Incomplete);
+    assert_eq!(Request::parse_head(b"\r\n", &Limits::default()).unwrap_err(), ParseError::Incomplete);
+}
+
+fn config(lenient_line_endings: bool) -> ServerConfig {
+    let mut config = ServerConfig { keep_alive_timeout: Duration::from_millis(10), ..ServerConfig::default() };
+    config.limits.lenient_line_endings = lenient_line_endings;
+    config.allowed_hosts.clear();
+    config
+}
+
+#[test]
+fn rejected_framing_gets_400_and_closes_the_connection() {
+    for fixture in FIXTURES {
+        let Rejected(error) = &fixture.strict else { continue };
+        let mut connection = Connection::new(MockStream::new([fixture.bytes.to_vec()]));
+        match connection.read_request(&config(false), |_| None) {
+            Incoming::Reject(response) => {
+                assert_eq!(response.status, error.status(), "{}", fixture.name);
+                assert_eq!(response.header("Connection"), Some("close"), "{}", fixture.name);
+            }
+            _ => panic!("{} was not rejected", fixture.name),
+        }
+    }
+    assert_eq!(ParseError::ConflictingLength.status(), 400);
+}
+
+#[test]
+fn a_crlf_after_a_body_is_skipped_between_pipelined_requests() {
+    let bytes = b"POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: 2\r\n\r\nhi\r\nGET /b HTTP/1.1\r\nHost: a\r\n\r\n\r\n";
+    let mut connection = Connection::new(MockStream::new([bytes.to_vec()]));
+    let config = config(false);
+    let paths: Vec<String> = std::iter::from_fn(|| match connection.read_request(&config, |_| None) {
+        Incoming::Request(request) => Some(request.path),
+        _ => None,
+    })
+    .collect();
+    assert_eq!(paths, ["/a", "/b"]);
+
+    // The CRLF left over at the end isn't a request cut short.
+    let mut connection = Connection::new(MockStream::new([b"\r\n".to_vec()]));
+    assert!(matches!(connection.read_request(&config, |_| None), Incoming::Closed));
+}
+
+#[test]
+fn bare_lf_is_accepted_on_a_connection_only_when_configured() {
+    let bytes = b"GET /lf HTTP/1.1\nHost: a\n\n";
+    let mut connection = Connection::new(MockStream::new([bytes.to_vec()]));
+    assert!(matches!(connection.read_request(&config(false), |_| None), Incoming::Reject(response) if response.status == 400));
+
+    let mut connection = Connection::new(MockStream::new([bytes.to_vec()]));
+    match connection.read_request(&config(true), |_| None) {
+        Incoming::Request(request) => assert_eq!(request.path, "/lf"),
+        _ => panic!("not accepted"),
+    }
+
+    let server = ServerConfig::from_config(&Config::parse("[server]\nlenient_line_endings = true\n").unwrap()).unwrap();
+    assert!(server.limits.lenient_line_endings);
+    assert!(!ServerConfig::default().limits.lenient_line_endings);
+}
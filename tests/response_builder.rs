@@ -0,0 +1,67 @@
+// `Response::builder` output checked byte for byte against the HTTP/1.1
+// wire format: status line, headers in order, framing, then the body.
+use server_app::http::{Response, ResponseBuilder};
+
+fn wire(response: &Response) -> String {
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn a_built_response_is_a_status_line_headers_length_and_body() {
+    let response = Response::builder(200, "OK").header("Content-Type", "text/plain").body("hello").build();
+    assert_eq!(wire(&response), "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhello");
+}
+
+#[test]
+fn headers_keep_their_order_and_repeats() {
+    let response = Response::builder(302, "Found")
+        .header("Location", "/login")
+        .header("Set-Cookie", "a=1")
+        .header("Set-Cookie", "b=2")
+        .build();
+    assert_eq!(
+        wire(&response),
+        "HTTP/1.1 302 Found\r\nLocation: /login\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nContent-Length: 0\r\n\r\n"
+    );
+    assert_eq!(ResponseBuilder::new(302, "Found").header("Location", "/login").build().status, 302);
+}
+
+#[test]
+fn framing_comes_from_the_body_not_the_headers() {
+    let response = Response::builder(200, "OK")
+        .header("Content-Length", "999")
+        .header("Transfer-Encoding", "chunked")
+        .body(vec![0u8, 1, 2])
+        .build();
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    assert_eq!(out, b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n\x00\x01\x02");
+}
+
+#[test]
+fn bodiless_statuses_get_no_length() {
+    for (status, reason) in [(204, "No Content"), (304, "Not Modified")] {
+        let response = Response::builder(status, reason).header("ETag", "\"v1\"").body("ignored").build();
+        assert_eq!(wire(&response), format!("HTTP/1.1 {} {}\r\nETag: \"v1\"\r\n\r\n", status, reason));
+    }
+}
+
+#[test]
+fn line_breaks_cannot_split_the_head() {
+    let response = Response::builder(200, "OK\r\nX-Evil: 1")
+        .header("X-Note", "a\r\nX-Injected: yes")
+        .header("Bad Name", "dropped")
+        .build();
+    assert_eq!(
+        wire(&response),
+        "HTTP/1.1 200 OKX-Evil: 1\r\nX-Note: aX-Injected: yes\r\nContent-Length: 0\r\n\r\n"
+    );
+}
+
+#[test]
+fn vary_fields_merge_into_one_header() {
+    let response = Response::builder(200, "OK").vary("Accept").vary("accept").vary("Accept-Encoding").build();
+    assert_eq!(wire(&response), "HTTP/1.1 200 OK\r\nVary: Accept, Accept-Encoding\r\nContent-Length: 0\r\n\r\n");
+}
@@ -0,0 +1,150 @@
+// `CacheMiddleware` answers repeated GETs from a `ResponseCache` keyed by
+// method, path, query and the configured Vary headers, within the TTL.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use server_app::cache::{CacheConfig, CacheMiddleware, ResponseCache};
+use server_app::clock::MockClock;
+use server_app::http::{Request, Response};
+use server_app::router::Router;
+
+struct Setup {
+    router: Router,
+    calls: Arc<AtomicUsize>,
+    clock: Arc<MockClock>,
+}
+
+fn setup(config: CacheConfig) -> Setup {
+    let clock = Arc::new(MockClock::new());
+    let cache = Arc::new(ResponseCache::with_clock(config, clock.clone()));
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&calls);
+    let mut router = Router::new();
+    router.middleware(CacheMiddleware::new(cache)).any("/*path", move |request: &Request| {
+        let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut response = Response::new(200, "OK").with_body(format!("response {}", n));
+        for (path, cache_control) in [("/no-store", "no-store"), ("/private", "private"), ("/short", "max-age=5")] {
+            if request.path == path {
+                response = response.with_header("Cache-Control", cache_control);
+            }
+        }
+        if request.path == "/negotiated" {
+            response = response.with_header("Vary", "Accept-Language");
+        }
+        if request.path == "/missing" {
+            response.status = 404;
+        }
+        response
+    });
+    Setup { router, calls, clock }
+}
+
+fn get(setup: &Setup, target: &str) -> Response {
+    setup.router.dispatch(&Request::new("GET", target))
+}
+
+fn body(response: &Response) -> &str {
+    std::str::from_utf8(&response.body).unwrap()
+}
+
+#[test]
+fn a_second_request_is_served_without_the_handler() {
+    let setup = setup(CacheConfig::default());
+    let first = get(&setup, "/page");
+    assert_eq!(first.header("X-Cache"), Some("MISS"));
+    assert_eq!(first.header("Age"), None);
+
+    setup.clock.advance(Duration::from_secs(3));
+    let second = get(&setup, "/page");
+    assert_eq!(second.header("X-Cache"), Some("HIT"));
+    assert_eq!(second.header("Age"), Some("3"));
+    assert_eq!(body(&second), "response 1");
+    assert_eq!(setup.calls.load(Ordering::SeqCst), 1);
+
+    // HEAD shares GET's entry; 404s are stored too.
+    assert_eq!(setup.router.dispatch(&Request::new("HEAD", "/page")).header("X-Cache"), Some("HIT"));
+    get(&setup, "/missing");
+    assert_eq!(get(&setup, "/missing").header("X-Cache"), Some("HIT"));
+    assert_eq!(setup.calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn the_key_is_method_path_and_query() {
+    let setup = setup(CacheConfig::default());
+    assert_eq!(body(&get(&setup, "/page?a=1")), "response 1");
+    assert_eq!(body(&get(&setup, "/page?a=2")), "response 2");
+    assert_eq!(body(&get(&setup, "/other?a=1")), "response 3");
+    assert_eq!(body(&get(&setup, "/page?a=1")), "response 1");
+
+    // Other methods are passed through and never stored.
+    let post = setup.router.dispatch(&Request::new("POST", "/page?a=1"));
+    assert_eq!(body(&post), "response 4");
+    assert_eq!(post.header("X-Cache"), None);
+}
+
+#[test]
+fn entries_expire_after_their_ttl() {
+    let setup = setup(CacheConfig { default_ttl: Duration::from_secs(60), ..CacheConfig::default() });
+    get(&setup, "/short");
+    get(&setup, "/page");
+    setup.clock.advance(Duration::from_secs(4));
+    assert_eq!(get(&setup, "/short").header("X-Cache"), Some("HIT"));
+
+    setup.clock.advance(Duration::from_secs(1));
+    assert_eq!(get(&setup, "/short").header("X-Cache"), Some("MISS"), "max-age=5 has run out");
+    assert_eq!(get(&setup, "/page").header("X-Cache"), Some("HIT"));
+
+    setup.clock.advance(Duration::from_secs(55));
+    assert_eq!(get(&setup, "/page").header("X-Cache"), Some("MISS"), "so has the default of 60 s");
+    assert_eq!(setup.calls.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn no_store_and_private_responses_are_not_stored() {
+    let setup = setup(CacheConfig::default());
+    for path in ["/no-store", "/private"] {
+        get(&setup, path);
+        assert_eq!(get(&setup, path).header("X-Cache"), Some("MISS"));
+    }
+    assert_eq!(setup.calls.load(Ordering::SeqCst), 4);
+
+    // Nor does a request asking for no-store use the cache.
+    get(&setup, "/page");
+    let mut request = Request::new("GET", "/page");
+    request.headers.set("Cache-Control", "no-store");
+    assert_eq!(setup.router.dispatch(&request).header("X-Cache"), None);
+    assert_eq!(setup.calls.load(Ordering::SeqCst), 6);
+}
+
+#[test]
+fn vary_headers_are_part_of_the_key_only_when_configured() {
+    let with_language = |language: &str| {
+        let mut request = Request::new("GET", "/negotiated");
+        request.headers.set("Accept-Language", language);
+        request
+    };
+
+    // Varying on a header the key leaves out can't be stored safely.
+    let unkeyed = setup(CacheConfig::default());
+    unkeyed.router.dispatch(&with_language("en"));
+    assert_eq!(unkeyed.router.dispatch(&with_language("en")).header("X-Cache"), Some("MISS"));
+
+    let keyed = setup(CacheConfig { vary_headers: vec!["Accept-Language".to_string()], ..CacheConfig::default() });
+    assert_eq!(body(&keyed.router.dispatch(&with_language("en"))), "response 1");
+    assert_eq!(body(&keyed.router.dispatch(&with_language("fr"))), "response 2");
+    let english = keyed.router.dispatch(&with_language("en"));
+    assert_eq!((english.header("X-Cache"), body(&english)), (Some("HIT"), "response 1"));
+}
+
+#[test]
+fn least_recently_used_entries_make_room() {
+    // Room for two entries of 16 bytes, `GET /a` and `response 1`.
+    let setup = setup(CacheConfig { max_bytes: 40, ..CacheConfig::default() });
+    get(&setup, "/a");
+    get(&setup, "/b");
+    get(&setup, "/a"); // `/b` is now the least recently used.
+    get(&setup, "/c");
+    assert_eq!(get(&setup, "/a").header("X-Cache"), Some("HIT"));
+    assert_eq!(get(&setup, "/b").header("X-Cache"), Some("MISS"));
+}
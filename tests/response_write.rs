@@ -0,0 +1,77 @@
+// `write!` and `writeln!` straight into a `Response`: the bytes go on the
+// end of the body, and `Content-Length` is worked out when it's sent.
+use std::io::Write;
+
+use server_app::http::{Request, Response};
+use server_app::router::Router;
+
+fn wire(response: &Response) -> String {
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn writes_accumulate_in_order() {
+    let mut response = Response::new(200, "OK");
+    let title = "Title";
+    write!(response, "<h1>{}</h1>", title).unwrap();
+    write!(response, "<p>{} + {} = {}</p>", 1, 2, 1 + 2).unwrap();
+    response.write_all(b"<hr>").unwrap();
+    assert_eq!(response.body, b"<h1>Title</h1><p>1 + 2 = 3</p><hr>");
+}
+
+#[test]
+fn writeln_appends_the_newline() {
+    let mut response = Response::new(200, "OK");
+    writeln!(response, "first").unwrap();
+    writeln!(response).unwrap();
+    writeln!(response, "{}", 3).unwrap();
+    assert_eq!(response.body, b"first\n\n3\n");
+}
+
+#[test]
+fn writing_adds_to_a_body_set_earlier() {
+    let mut response = Response::new(200, "OK").with_body("<ul>");
+    for item in ["a", "b"] {
+        write!(response, "<li>{}</li>", item).unwrap();
+    }
+    write!(response, "</ul>").unwrap();
+    response.flush().unwrap();
+    assert_eq!(response.body, b"<ul><li>a</li><li>b</li></ul>");
+}
+
+#[test]
+fn the_content_length_is_counted_when_sending() {
+    let mut response = Response::new(200, "OK");
+    write!(response, "12345").unwrap();
+    assert!(wire(&response).ends_with("Content-Length: 5\r\n\r\n12345"), "{}", wire(&response));
+
+    // Written after a first serialisation, the new bytes are counted too.
+    writeln!(response, "6789").unwrap();
+    let sent = wire(&response);
+    assert!(sent.ends_with("Content-Length: 10\r\n\r\n123456789\n"), "{}", sent);
+    assert_eq!(sent.matches("Content-Length").count(), 1);
+
+    // Unicode counts in bytes, not characters.
+    let mut response = Response::new(200, "OK");
+    let word = "héllo";
+    write!(response, "{}", word).unwrap();
+    assert!(wire(&response).contains("Content-Length: 6\r\n"));
+}
+
+#[test]
+fn handlers_can_build_their_page_with_write() {
+    let mut router = Router::new();
+    router.get("/items/:count", |request: &Request| {
+        let count: usize = request.param("count").unwrap().parse().unwrap();
+        let mut response = Response::new(200, "OK").with_header("Content-Type", "text/plain");
+        for i in 1..=count {
+            writeln!(response, "item {}", i).unwrap();
+        }
+        response
+    });
+    let response = router.dispatch(&Request::new("GET", "/items/3"));
+    assert_eq!(response.body, b"item 1\nitem 2\nitem 3\n");
+    assert!(wire(&response).contains("Content-Length: 21\r\n"));
+}
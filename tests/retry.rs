@@ -0,0 +1,99 @@
+// `RetryMiddleware` retries failed forwards of idempotent requests with
+// growing delays, and gives up with a 502 after `max_attempts`.
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use server_app::http::{Request, Response};
+use server_app::proxy::{ExponentialBackoff, ReverseProxy, RetryMiddleware};
+use server_app::router::Router;
+
+// A route whose upstream refuses the first `failures` connections and
+// then answers; `calls` counts the attempts.
+fn router(retry: RetryMiddleware, failures: usize, calls: &Arc<AtomicUsize>) -> Router {
+    let refusing = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let down = Arc::new(ReverseProxy::new(&refusing));
+    let calls = Arc::clone(calls);
+    let handler = move |request: &Request| {
+        if calls.fetch_add(1, Ordering::SeqCst) < failures {
+            down.handle(request)
+        } else {
+            Response::new(200, "OK").with_body("upstream")
+        }
+    };
+    let mut router = Router::new();
+    router.middleware(retry).get("/", handler.clone()).post("/", handler.clone()).put("/", handler);
+    router
+}
+
+fn quick(max_attempts: u32) -> RetryMiddleware {
+    let mut retry = RetryMiddleware::new(max_attempts);
+    retry.backoff = ExponentialBackoff { initial: Duration::from_millis(1), factor: 2, max: Duration::from_millis(4) };
+    retry
+}
+
+#[test]
+fn two_failures_then_success_gives_200() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let router = router(RetryMiddleware::new(3), 2, &calls);
+    let started = Instant::now();
+    let response = router.dispatch(&Request::new("GET", "/"));
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"upstream");
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert!(started.elapsed() >= Duration::from_millis(150), "slept 50 ms, then 100 ms");
+}
+
+#[test]
+fn handlers_with_a_timeout_are_retried_too() {
+    // The handler runs on the router's timeout pool, not the thread the
+    // middleware runs on.
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut router = router(quick(3), 2, &calls);
+    router.default_timeout(Some(Duration::from_secs(5)));
+    let response = router.dispatch(&Request::new("GET", "/"));
+    assert_eq!(response.status, 200);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn after_max_attempts_the_answer_is_502() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let router = router(quick(3), usize::MAX, &calls);
+    assert_eq!(router.dispatch(&Request::new("GET", "/")).status, 502);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn non_idempotent_methods_are_not_retried() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let router = router(quick(3), 1, &calls);
+    assert_eq!(router.dispatch(&Request::new("POST", "/")).status, 502);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // `PUT` can be repeated safely.
+    assert_eq!(router.dispatch(&Request::new("PUT", "/")).status, 200);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn errors_the_upstream_sent_are_not_retried() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut router = Router::new();
+    let counter = Arc::clone(&calls);
+    router.middleware(quick(3)).get("/", move |_: &Request| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Response::new(502, "Bad Gateway")
+    });
+    assert_eq!(router.dispatch(&Request::new("GET", "/")).status, 502);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn the_delays_double_up_to_the_cap() {
+    let backoff = ExponentialBackoff::default();
+    let delays: Vec<u64> = (0..8).map(|retry| backoff.delay(retry).as_millis() as u64).collect();
+    assert_eq!(delays, [50, 100, 200, 400, 800, 1600, 2000, 2000]);
+    assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(2));
+}
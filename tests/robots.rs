@@ -0,0 +1,112 @@
+// `RobotsTxt` writes standard robots.txt groups, `robots::register`
+// serves them, and a robots.txt in the static directory wins.
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use server_app::config::Config;
+use server_app::http::{Request, Response};
+use server_app::robots::{self, RobotsTxt};
+use server_app::router::Router;
+use server_app::server::ServerConfig;
+
+fn root(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("robots-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn fetch(router: &Router) -> Response {
+    router.dispatch(&Request::new("GET", "/robots.txt"))
+}
+
+#[test]
+fn the_shortcuts_keep_everyone_out_or_let_everyone_in() {
+    assert_eq!(RobotsTxt::builder().disallow_all().build().to_string(), "User-agent: *\nDisallow: /\n");
+    assert_eq!(RobotsTxt::builder().allow_all().build().to_string(), "User-agent: *\nDisallow:\n");
+    assert_eq!(RobotsTxt::builder().build().to_string(), "");
+}
+
+#[test]
+fn rules_are_written_in_order_one_group_per_agent() {
+    let policy = RobotsTxt::builder()
+        .add_rule("Googlebot", &["/public/", "/docs/"], &["/private/"])
+        .add_rule("BadBot", &[], &["/"])
+        .add_rule("*", &["/"], &[])
+        .build();
+    assert_eq!(
+        policy.to_string(),
+        "User-agent: Googlebot\nAllow: /public/\nAllow: /docs/\nDisallow: /private/\n\
+         \n\
+         User-agent: BadBot\nDisallow: /\n\
+         \n\
+         User-agent: *\nAllow: /\n"
+    );
+    assert_eq!(policy.rules().len(), 3);
+    assert_eq!(policy.rules()[0].user_agent, "Googlebot");
+    assert_eq!(policy.rules()[0].disallow, ["/private/"]);
+}
+
+#[test]
+fn the_policy_is_served_as_plain_text() {
+    let mut router = Router::new();
+    robots::register(&mut router, RobotsTxt::builder().add_rule("*", &[], &["/admin/"]).build(), None);
+    let response = fetch(&router);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("text/plain; charset=utf-8"));
+    assert_eq!(response.body, b"User-agent: *\nDisallow: /admin/\n");
+}
+
+#[test]
+fn a_file_in_the_static_directory_takes_precedence() {
+    let dir = root("override");
+    let mut router = Router::new();
+    robots::register(&mut router, RobotsTxt::builder().disallow_all().build(), Some(&dir));
+    assert_eq!(fetch(&router).body, b"User-agent: *\nDisallow: /\n", "generated while there's no file");
+
+    fs::write(dir.join("robots.txt"), "User-agent: *\nAllow: /\nSitemap: https://example.com/sitemap.xml\n").unwrap();
+    let response = fetch(&router);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"User-agent: *\nAllow: /\nSitemap: https://example.com/sitemap.xml\n");
+    assert!(!String::from_utf8_lossy(&response.body).contains("Disallow"), "nothing generated alongside it");
+
+    // Taking the file away brings the generated one back.
+    fs::remove_file(dir.join("robots.txt")).unwrap();
+    assert_eq!(fetch(&router).body, b"User-agent: *\nDisallow: /\n");
+
+    // A directory of that name isn't a file to serve.
+    fs::create_dir(dir.join("robots.txt")).unwrap();
+    assert_eq!(fetch(&router).body, b"User-agent: *\nDisallow: /\n");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn the_config_picks_a_policy_by_name() {
+    let load = |value: &str| ServerConfig::from_config(&Config::parse(&format!("[server]\nrobots = \"{}\"\n", value)).unwrap());
+    assert_eq!(load("allow_all").unwrap().robots_policy, Some(RobotsTxt::builder().allow_all().build()));
+    assert_eq!(load("disallow_all").unwrap().robots_policy, Some(RobotsTxt::builder().disallow_all().build()));
+    assert!(load("sometimes").is_err());
+    assert_eq!(ServerConfig::default().robots_policy, None);
+}
+
+#[test]
+fn the_binary_serves_the_configured_policy_unless_there_is_a_file() {
+    let cwd = root("e2e");
+    let server = common::start_in("robots", "robots = \"disallow_all\"\n", &cwd);
+    let response = common::get(server.port, "/robots.txt", "");
+    assert!(response.starts_with("HTTP/1.1 200 "), "{}", response);
+    assert_eq!(common::body(&response), "User-agent: *\nDisallow: /\n");
+
+    fs::write(cwd.join("robots.txt"), "User-agent: *\nAllow: /\n").unwrap();
+    let response = common::get(server.port, "/robots.txt", "");
+    assert_eq!(common::body(&response), "User-agent: *\nAllow: /\n");
+    drop(server);
+
+    // With no policy configured there's no route; the file isn't served either.
+    let server = common::start_in("no-robots", "", &cwd);
+    assert!(common::get(server.port, "/robots.txt", "").starts_with("HTTP/1.1 404 "));
+    drop(server);
+    let _ = fs::remove_dir_all(&cwd);
+}
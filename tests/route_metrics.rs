@@ -0,0 +1,139 @@
+// `RouteMetrics` counts and times responses per route pattern, and
+// `metrics::register` serves them at `/metrics` and `/admin/routes`.
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use server_app::http::{Request, Response};
+use server_app::json;
+use server_app::metrics::{self, RouteMetrics, LATENCY_BUCKETS, UNMATCHED};
+use server_app::router::Router;
+
+fn router(metrics: &RouteMetrics) -> Router {
+    let mut router = Router::new();
+    router
+        .middleware(metrics.clone())
+        .get("/users/:id", |request: &Request| match request.param("id") {
+            Some("0") => Response::new(500, "Internal Server Error"),
+            _ => Response::new(200, "OK"),
+        })
+        .get("/old", |_: &Request| Response::new(301, "Moved Permanently").with_header("Location", "/users/1"))
+        .post("/form", |_: &Request| Response::new(200, "OK"));
+    metrics::register(&mut router, metrics.clone());
+    router
+}
+
+fn get(router: &Router, path: &str) -> Response {
+    router.dispatch(&Request::new("GET", path))
+}
+
+#[test]
+fn requests_count_under_their_pattern_by_status_class() {
+    let route_metrics = RouteMetrics::new();
+    let router = router(&route_metrics);
+    for id in ["1", "2", "3", "0"] {
+        get(&router, &format!("/users/{}", id));
+    }
+    get(&router, "/old");
+    get(&router, "/old");
+    get(&router, "/missing");
+    get(&router, "/also/missing");
+    get(&router, "/form"); // 405: the path only takes POST.
+
+    let users = route_metrics.route("/users/:id").unwrap();
+    assert_eq!(users.requests, 4);
+    assert_eq!((users.class(2), users.class(5), users.class(4)), (3, 1, 0));
+    assert_eq!(users.buckets.iter().sum::<u64>(), 4);
+
+    let old = route_metrics.route("/old").unwrap();
+    assert_eq!((old.requests, old.class(3)), (2, 2));
+
+    let unmatched = route_metrics.route(UNMATCHED).unwrap();
+    assert_eq!((unmatched.requests, unmatched.class(4)), (3, 3));
+    assert!(route_metrics.route("/missing").is_none());
+    assert!(route_metrics.route("/form").is_none());
+
+    // The busiest first.
+    let order: Vec<String> = route_metrics.snapshot().into_iter().map(|stats| stats.route).collect();
+    assert_eq!(order, ["/users/:id", UNMATCHED, "/old"]);
+}
+
+#[test]
+fn latencies_fall_in_histogram_buckets() {
+    let route_metrics = RouteMetrics::new();
+    route_metrics.record("/a", 200, Duration::from_micros(500));
+    route_metrics.record("/a", 200, Duration::from_millis(30));
+    route_metrics.record("/a", 200, Duration::from_secs(10));
+    let stats = route_metrics.route("/a").unwrap();
+    let bucket = |secs: f64| LATENCY_BUCKETS.iter().position(|bound| *bound == secs).unwrap();
+    assert_eq!(stats.buckets[bucket(0.001)], 1);
+    assert_eq!(stats.buckets[bucket(0.05)], 1);
+    assert_eq!(stats.buckets[LATENCY_BUCKETS.len()], 1);
+    assert_eq!(stats.latency, Duration::from_micros(10_030_500));
+    assert_eq!(stats.mean_latency(), Duration::from_micros(3_343_500));
+}
+
+#[test]
+fn metrics_are_served_with_a_route_label() {
+    let route_metrics = RouteMetrics::new();
+    let router = router(&route_metrics);
+    get(&router, "/users/7");
+    get(&router, "/users/8");
+    get(&router, "/nowhere");
+
+    let response = get(&router, "/metrics");
+    assert!(response.header("Content-Type").unwrap().starts_with("text/plain; version=0.0.4"));
+    let text = String::from_utf8(response.body).unwrap();
+    assert!(text.contains("# TYPE http_route_requests_total counter\n"));
+    assert!(text.contains("http_route_requests_total{route=\"/users/:id\",status=\"2xx\"} 2\n"));
+    assert!(text.contains("http_route_requests_total{route=\"<unmatched>\",status=\"4xx\"} 1\n"));
+    assert!(text.contains("http_route_request_duration_seconds_bucket{route=\"/users/:id\",le=\"+Inf\"} 2\n"));
+    assert!(text.contains("http_route_request_duration_seconds_count{route=\"/users/:id\"} 2\n"));
+    assert!(!text.contains("/users/7"));
+
+    // `/metrics` itself has been counted by now.
+    let response = get(&router, "/admin/routes");
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    let routes = json::parse(std::str::from_utf8(&response.body).unwrap()).unwrap();
+    let routes = routes.as_array().unwrap();
+    let names: Vec<&str> = routes.iter().map(|route| route.get("route").and_then(|r| r.as_str()).unwrap()).collect();
+    assert_eq!(names, ["/users/:id", "/metrics", UNMATCHED]);
+    let users = &routes[0];
+    assert_eq!(users.get("requests").and_then(|n| n.as_f64()), Some(2.0));
+    assert_eq!(users.get("status").and_then(|s| s.get("2xx")).and_then(|n| n.as_f64()), Some(2.0));
+    assert_eq!(users.get("latency_seconds").and_then(|l| l.get("buckets")).and_then(|b| b.as_array()).map(|b| b.len()), Some(LATENCY_BUCKETS.len() + 1));
+
+    assert_eq!(router.sitemap_paths(), ["/old"]);
+}
+
+#[test]
+fn concurrent_requests_are_all_counted() {
+    let route_metrics = RouteMetrics::new();
+    let router = Arc::new(router(&route_metrics));
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let router = Arc::clone(&router);
+            thread::spawn(move || {
+                for i in 0..250 {
+                    let path = if i % 2 == 0 { format!("/users/{}", t + 1) } else { "/old".to_string() };
+                    get(&router, &path);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(route_metrics.route("/users/:id").unwrap().requests, 1000);
+    assert_eq!(route_metrics.route("/old").unwrap().requests, 1000);
+}
+
+#[test]
+fn route_metrics_can_be_turned_off() {
+    use server_app::config::Config;
+    use server_app::server::ServerConfig;
+
+    assert!(ServerConfig::default().route_metrics);
+    let server = ServerConfig::from_config(&Config::parse("[server]\nroute_metrics = false\n").unwrap()).unwrap();
+    assert!(!server.route_metrics);
+}
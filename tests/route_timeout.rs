@@ -0,0 +1,198 @@
+// A route with a timeout answers `503` with `Retry-After` once its
+// handler overruns; the handler finishes in the background and what it
+// returns goes nowhere.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use server_app::config::Config;
+use server_app::http::Request;
+use server_app::router::{Router, DEFAULT_TIMEOUT_POOL_SIZE};
+use server_app::server::{self, RequestContext, ServerConfig};
+use server_app::ThreadPool;
+
+// A router with `/slow`, which takes a second against a 200ms timeout,
+// and says on `finished` when it is done.
+fn slow_router(finished: mpsc::Sender<()>) -> Router {
+    let finished = Mutex::new(finished);
+    let mut router = Router::new();
+    router
+        .timeout_pool(Arc::new(ThreadPool::new(2)))
+        .get_with_timeout(
+            "/slow",
+            move |_: &Request| {
+                thread::sleep(Duration::from_secs(1));
+                finished.lock().unwrap().send(()).unwrap();
+                "finally"
+            },
+            Duration::from_millis(200),
+        )
+        .get_with_timeout("/quick", |_: &Request| "quick", Duration::from_millis(200));
+    router
+}
+
+#[test]
+fn an_overrunning_handler_gets_a_prompt_503() {
+    let (finished_tx, finished) = mpsc::channel();
+    let router = slow_router(finished_tx);
+
+    let started = Instant::now();
+    let response = router.dispatch(&Request::new("GET", "/slow"));
+    let took = started.elapsed();
+    assert_eq!(response.status, 503);
+    assert_eq!(response.header("Retry-After"), Some("1"));
+    assert!(took >= Duration::from_millis(200), "answered after {:?}", took);
+    assert!(took < Duration::from_millis(500), "answered after {:?}", took);
+    assert!(!String::from_utf8_lossy(&response.body).contains("finally"));
+
+    // The handler runs on to its end without upsetting anything.
+    finished.recv_timeout(Duration::from_secs(3)).expect("the handler finished");
+    thread::sleep(Duration::from_millis(50));
+    let response = router.dispatch(&Request::new("GET", "/quick"));
+    assert_eq!((response.status, response.body.as_slice()), (200, &b"quick"[..]));
+}
+
+#[test]
+fn the_late_response_is_never_written_to_the_connection() {
+    let (finished_tx, finished) = mpsc::channel();
+    let router = Arc::new(slow_router(finished_tx));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    // The connection stays with the side that waited: it writes the 503
+    // and holds the socket open past the handler's finish.
+    let serving = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let response = router.dispatch(&Request::new("GET", "/slow"));
+        response.write_to(&mut stream).unwrap();
+        stream.flush().unwrap();
+        finished.recv_timeout(Duration::from_secs(3)).unwrap();
+        thread::sleep(Duration::from_millis(200));
+    });
+
+    let mut client = TcpStream::connect(address).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    serving.join().unwrap();
+    let received = String::from_utf8(received).unwrap();
+    assert!(received.starts_with("HTTP/1.1 503 "), "{}", received);
+    assert_eq!(received.matches("HTTP/1.1").count(), 1, "{}", received);
+    assert!(!received.contains("finally"));
+}
+
+#[test]
+fn handlers_within_their_time_answer_as_usual() {
+    let mut router = Router::new();
+    router.get_with_timeout("/", |_: &Request| "in time", Duration::from_secs(1));
+    let response = router.dispatch(&Request::new("GET", "/"));
+    assert_eq!((response.status, response.body.as_slice()), (200, &b"in time"[..]));
+    assert_eq!(response.header("Retry-After"), None);
+}
+
+#[test]
+fn the_default_applies_to_routes_without_their_own() {
+    let mut router = Router::new();
+    router
+        .default_timeout(Some(Duration::from_millis(100)))
+        .get("/default", |_: &Request| {
+            thread::sleep(Duration::from_millis(400));
+            "late"
+        })
+        .get_with_timeout(
+            "/own",
+            |_: &Request| {
+                thread::sleep(Duration::from_millis(400));
+                "on time"
+            },
+            Duration::from_secs(2),
+        );
+    assert_eq!(router.dispatch(&Request::new("GET", "/default")).status, 503);
+    assert_eq!(router.dispatch(&Request::new("GET", "/own")).status, 200, "its own timeout wins");
+
+    let mut unlimited = Router::new();
+    unlimited.get("/", |_: &Request| {
+        thread::sleep(Duration::from_millis(300));
+        "whenever"
+    });
+    assert_eq!(unlimited.dispatch(&Request::new("GET", "/")).status, 200);
+}
+
+#[test]
+fn a_mounted_router_keeps_its_default() {
+    let late = |_: &Request| {
+        thread::sleep(Duration::from_millis(400));
+        "late"
+    };
+    let mut api = Router::new();
+    api.default_timeout(Some(Duration::from_millis(100)))
+        .get("/slow", late)
+        .get_with_timeout("/own", late, Duration::from_secs(2));
+    let mut router = Router::new();
+    router.default_timeout(Some(Duration::from_secs(2))).mount("/api", api).get("/slow", late);
+
+    assert_eq!(router.dispatch(&Request::new("GET", "/api/slow")).status, 503);
+    assert_eq!(router.dispatch(&Request::new("GET", "/api/own")).status, 200, "its own timeout wins");
+    assert_eq!(router.dispatch(&Request::new("GET", "/slow")).status, 200, "the parent's routes keep the parent's");
+}
+
+#[test]
+fn a_handler_that_panics_is_a_500() {
+    let mut router = Router::new();
+    router.get_with_timeout("/", |_: &Request| -> &str { panic!("handler failed") }, Duration::from_secs(1));
+    assert_eq!(router.dispatch(&Request::new("GET", "/")).status, 500);
+}
+
+#[test]
+fn the_handler_runs_in_the_request_context() {
+    let mut router = Router::new();
+    router.get_with_timeout(
+        "/",
+        |_: &Request| match server::current_request_context() {
+            Some(context) => format!("request {}", context.request_id),
+            None => "no context".to_string(),
+        },
+        Duration::from_secs(1),
+    );
+    let request = Request::new("GET", "/");
+    let response = server::with_request_context(RequestContext::for_request(42, &request), || router.dispatch(&request));
+    assert_eq!(response.body, b"request 42");
+    // And outside a request, there's none to carry over.
+    assert_eq!(router.dispatch(&request).body, b"no context");
+}
+
+#[test]
+fn the_default_pool_runs_four_overrunning_handlers_at_once() {
+    assert_eq!(DEFAULT_TIMEOUT_POOL_SIZE, 4);
+    let (finished_tx, finished) = mpsc::channel();
+    let finished_tx = Mutex::new(finished_tx);
+    let mut router = Router::new();
+    router.get_with_timeout(
+        "/slow",
+        move |_: &Request| {
+            thread::sleep(Duration::from_millis(300));
+            finished_tx.lock().unwrap().send(Instant::now()).unwrap();
+            "late"
+        },
+        Duration::from_millis(50),
+    );
+    for _ in 0..DEFAULT_TIMEOUT_POOL_SIZE + 1 {
+        assert_eq!(router.dispatch(&Request::new("GET", "/slow")).status, 503);
+    }
+    let mut done: Vec<Instant> = (0..=DEFAULT_TIMEOUT_POOL_SIZE).map(|_| finished.recv().unwrap()).collect();
+    done.sort();
+    // The first four share the pool; the fifth waits for one of them.
+    let first = done[0];
+    assert!(done[DEFAULT_TIMEOUT_POOL_SIZE - 1] - first < Duration::from_millis(300), "{:?}", done);
+    assert!(done[DEFAULT_TIMEOUT_POOL_SIZE] - first >= Duration::from_millis(300), "{:?}", done);
+}
+
+#[test]
+fn the_config_sets_the_default() {
+    let load = |text: &str| ServerConfig::from_config(&Config::parse(text).unwrap()).unwrap().route_timeout;
+    assert_eq!(load("[server]\nroute_timeout_ms = 2500\n"), Some(Duration::from_millis(2500)));
+    assert_eq!(load("[server]\nroute_timeout_ms = 0\n"), None);
+    assert_eq!(load("[server]\n"), None);
+}
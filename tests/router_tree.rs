@@ -0,0 +1,110 @@
+// The router finds routes through a tree of their segments: the most
+// specific pattern wins wherever patterns overlap, whatever order they
+// were registered in, and overlapping captures must share names.
+use server_app::http::{Request, Response};
+use server_app::router::Router;
+
+fn named(name: &'static str) -> impl Fn(&Request) -> Response + Send + Sync + 'static {
+    move |request: &Request| {
+        let mut params: Vec<String> = request.params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        params.sort();
+        Response::new(200, "OK").with_body(format!("{} {}", name, params.join(" ")).trim_end().to_string())
+    }
+}
+
+fn body(router: &Router, method: &str, path: &str) -> String {
+    let response = router.dispatch(&Request::new(method, path));
+    format!("{} {}", response.status, String::from_utf8(response.body).unwrap())
+}
+
+#[test]
+fn static_segments_beat_params_and_params_beat_wildcards() {
+    let mut router = Router::new();
+    router
+        .get("/files/*path", named("files"))
+        .get("/users/:id", named("user"))
+        .get("/users/new", named("new user"))
+        .get("/users/:id/edit", named("edit"))
+        .get("/*rest", named("catch-all"));
+
+    assert_eq!(body(&router, "GET", "/users/new"), "200 new user");
+    assert_eq!(body(&router, "GET", "/users/42"), "200 user id=42");
+    // `new` has no `edit` under it, so the search backs out to `:id`.
+    assert_eq!(body(&router, "GET", "/users/new/edit"), "200 edit id=new");
+    assert_eq!(body(&router, "GET", "/users/42/delete"), "200 catch-all rest=users/42/delete");
+    assert_eq!(body(&router, "GET", "/files/a/b.txt"), "200 files path=a/b.txt");
+
+    let matched = router.matched("GET", "/users/new").unwrap();
+    assert_eq!(matched.pattern, "/users/new");
+    assert!(matched.params.is_empty());
+}
+
+#[test]
+fn a_wildcard_can_match_nothing() {
+    let mut router = Router::new();
+    router.get("/assets/*path", named("assets")).get("/*", named("anything"));
+    assert_eq!(body(&router, "GET", "/assets"), "200 assets path=");
+    assert_eq!(body(&router, "GET", "/assets/"), "200 assets path=");
+    // An unnamed wildcard captures nothing.
+    assert_eq!(body(&router, "GET", "/"), "200 anything");
+    assert_eq!(body(&router, "GET", "/x/y"), "200 anything");
+}
+
+#[test]
+fn empty_segments_and_trailing_slashes_are_ignored() {
+    let mut router = Router::new();
+    router.get("/a/:b/", named("ab")).get("//c//d", named("cd"));
+    assert_eq!(body(&router, "GET", "/a/1"), "200 ab b=1");
+    assert_eq!(body(&router, "GET", "/a//1//"), "200 ab b=1");
+    assert_eq!(body(&router, "GET", "/c/d/"), "200 cd");
+    assert_eq!(router.dispatch(&Request::new("GET", "/a")).status, 404);
+    assert!(router.matched("GET", "/c").is_none());
+}
+
+#[test]
+fn a_more_specific_route_for_another_method_falls_through() {
+    let mut router = Router::new();
+    router
+        .get("/items/:id", named("get item"))
+        .post("/items/special", named("post special"))
+        .post("/items/:id", named("post item"));
+
+    assert_eq!(body(&router, "GET", "/items/special"), "200 get item id=special");
+    assert_eq!(body(&router, "POST", "/items/special"), "200 post special");
+    assert_eq!(body(&router, "POST", "/items/7"), "200 post item id=7");
+
+    // Every route the path matches is named in `Allow`.
+    let response = router.dispatch(&Request::new("DELETE", "/items/special"));
+    assert_eq!(response.status, 405);
+    assert_eq!(response.header("Allow"), Some("GET, POST"));
+}
+
+#[test]
+fn routes_with_the_same_pattern_keep_registration_order() {
+    let mut router = Router::new();
+    router.any("/same/:x", named("first")).get("/same/:x", named("second"));
+    assert_eq!(body(&router, "GET", "/same/1"), "200 first x=1");
+}
+
+#[test]
+#[should_panic(expected = "overlapping routes must use the same names")]
+fn params_at_the_same_position_must_share_a_name() {
+    let mut router = Router::new();
+    router.get("/users/:id", named("user")).get("/users/:name/posts", named("posts"));
+}
+
+#[test]
+#[should_panic(expected = "\"/static/*file\" captures \"file\" where \"/static/*path\" captures \"path\"")]
+fn wildcards_at_the_same_position_must_share_a_name() {
+    let mut router = Router::new();
+    router.get("/static/*path", named("a")).post("/static/*file", named("b"));
+}
+
+#[test]
+#[should_panic(expected = "overlapping routes must use the same names")]
+fn mounted_routes_are_checked_too() {
+    let mut api = Router::new();
+    api.get("/:version/status", named("status"));
+    let mut router = Router::new();
+    router.get("/api/:v", named("version")).mount("/api", api);
+}
@@ -0,0 +1,137 @@
+// The binary answers the same way whichever execution mode serves its
+// connections: the worker pools, one at a time on the accepting thread,
+// or a thread each.
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A running copy of the binary, stopped when dropped.
+struct Running {
+    child: Child,
+    port: u16,
+    dir: PathBuf,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn start(mode: &str, args: &[&str]) -> Running {
+    // A port nothing else is using, as far as the kernel knows.
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let dir = std::env::temp_dir().join(format!("run-modes-{}-{}", mode, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("server.toml");
+    fs::write(&config, format!("[server]\naddr = \"127.0.0.1:{}\"\n", port)).unwrap();
+
+    let child = Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(args)
+        .arg(&config)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let running = Running { child, port, dir };
+    let started = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(started.elapsed() < Duration::from_secs(10), "{} server did not start", mode);
+        thread::sleep(Duration::from_millis(20));
+    }
+    running
+}
+
+// Send `requests` on one connection, the last asking to close it, and
+// return everything that came back.
+fn exchange(port: u16, requests: &[&str]) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(15))).unwrap();
+    for (i, request) in requests.iter().enumerate() {
+        let connection = if i + 1 == requests.len() { "close" } else { "keep-alive" };
+        write!(stream, "{}Host: localhost\r\nConnection: {}\r\n\r\n", request, connection).unwrap();
+    }
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn get(port: u16, path: &str, headers: &str) -> String {
+    exchange(port, &[&format!("GET {} HTTP/1.1\r\n{}", path, headers)])
+}
+
+// The parts of a response that don't change from run to run.
+fn summary(response: &str) -> (String, Vec<String>, String) {
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let mut lines = head.lines();
+    let status = lines.next().unwrap().to_string();
+    let mut headers: Vec<String> = lines.filter(|line| !line.starts_with("Date:")).map(str::to_string).collect();
+    headers.sort();
+    (status, headers, body.to_string())
+}
+
+const MODES: [(&str, &[&str]); 3] = [
+    ("pool", &["--mode", "pool"]),
+    ("single", &["--mode", "single"]),
+    ("thread-per-conn", &["--mode", "thread-per-conn", "--max-threads", "4"]),
+];
+
+#[test]
+fn every_mode_answers_alike() {
+    let servers: Vec<Running> = MODES.iter().map(|(mode, args)| start(mode, args)).collect();
+    let (pool, others) = servers.split_first().unwrap();
+
+    let requests = [
+        ("/hello", "Accept: application/json\r\n"),
+        ("/hello", "Accept: text/html\r\n"),
+        ("/greeting", "Accept-Language: fr;q=0.9, de;q=0.5\r\n"),
+        ("/healthz", ""),
+        ("/readyz", ""),
+        ("/missing", "Accept: application/json\r\n"),
+    ];
+    for (path, headers) in requests {
+        let answer = summary(&get(pool.port, path, headers));
+        for (server, (mode, _)) in others.iter().zip(&MODES[1..]) {
+            assert_eq!(answer, summary(&get(server.port, path, headers)), "{} {} {:?}", mode, path, headers);
+        }
+    }
+    assert!(get(pool.port, "/greeting", "Accept-Language: fr\r\n").ends_with("Bonjour"));
+    assert!(get(others[0].port, "/missing", "").starts_with("HTTP/1.1 404"));
+
+    // The home page counts visits, separately in each process.
+    for server in &servers {
+        assert!(get(server.port, "/", "").contains("Visit number 1,"));
+        assert!(get(server.port, "/", "").contains("Visit number 2,"));
+    }
+
+    // Keep-alive carries several requests on one connection.
+    let requests = ["GET /healthz HTTP/1.1\r\n", "GET /hello HTTP/1.1\r\n", "GET /readyz HTTP/1.1\r\n"];
+    for server in &servers {
+        let answers = exchange(server.port, &requests);
+        assert_eq!(answers.matches("HTTP/1.1 200 OK").count(), 3, "{}", answers);
+        assert!(answers.ends_with("ready"));
+    }
+}
+
+#[test]
+fn sleep_is_served_in_every_mode() {
+    // `--single-thread` is another way of asking for `--mode single`.
+    let mut servers: Vec<Running> = MODES.iter().map(|(mode, args)| start(&format!("sleep-{}", mode), args)).collect();
+    servers.push(start("sleep-single-thread", &["--single-thread"]));
+    let started = Instant::now();
+    let answers: Vec<String> = thread::scope(|scope| {
+        let sleeping: Vec<_> = servers.iter().map(|server| scope.spawn(|| get(server.port, "/sleep", ""))).collect();
+        sleeping.into_iter().map(|sleeping| sleeping.join().unwrap()).collect()
+    });
+    assert!(started.elapsed() >= Duration::from_secs(5));
+    for answer in &answers {
+        assert!(answer.starts_with("HTTP/1.1 200 OK"), "{}", answer);
+        assert!(answer.contains("Visit number 1,"), "{}", answer);
+    }
+}
@@ -0,0 +1,58 @@
+// A handler sleeping on the shutdown token wakes as soon as the server
+// starts draining, instead of holding the drain up.
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use server_app::http::{self, Request, Response};
+use server_app::router::Router;
+use server_app::server::{self, ConnectionRegistry, RequestContext, ShutdownToken};
+
+// Like the binary's `/sleep`: five seconds, or less if shutdown begins.
+fn sleep_route() -> Router {
+    let mut router = Router::new();
+    router.get("/sleep", |_: &Request| {
+        let shutdown = server::current_request_context().map(|context| context.shutdown).unwrap_or_default();
+        if shutdown.sleep(Duration::from_secs(5)) {
+            Response::new(200, "OK").with_body("slept")
+        } else {
+            Response::new(503, http::reason_phrase(503)).with_body("woke early")
+        }
+    });
+    router
+}
+
+#[test]
+fn draining_wakes_a_sleeping_handler() {
+    let connections = ConnectionRegistry::new();
+    let shutdown = connections.shutdown_token();
+    let (sender, receiver) = mpsc::channel();
+    let started = Instant::now();
+    thread::spawn(move || {
+        let request = Request::new("GET", "/sleep");
+        let context = RequestContext::for_request(1, &request).with_shutdown(shutdown);
+        let response = server::with_request_context(context, || sleep_route().dispatch(&request));
+        sender.send(response).unwrap();
+    });
+
+    thread::sleep(Duration::from_millis(50));
+    connections.start_draining();
+    let response = receiver.recv_timeout(Duration::from_secs(2)).expect("the handler to wake up");
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(response.status, 503);
+    assert_eq!(response.body, b"woke early");
+    assert!(connections.is_draining());
+}
+
+#[test]
+fn sleep_runs_its_course_without_shutdown() {
+    let token = ShutdownToken::new();
+    let started = Instant::now();
+    assert!(token.sleep(Duration::from_millis(30)));
+    assert!(started.elapsed() >= Duration::from_millis(30));
+    assert!(!token.is_shutting_down());
+
+    token.clone().shutdown();
+    assert!(token.is_shutting_down());
+    assert!(!token.sleep(Duration::from_secs(5)), "already shutting down");
+}
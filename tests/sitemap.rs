@@ -0,0 +1,152 @@
+// `SitemapGenerator` lists the static `GET` routes, and only those, in
+// a well-formed sitemap, served at `/sitemap.xml`.
+mod common;
+
+use server_app::http::Request;
+use server_app::router::Router;
+use server_app::sitemap::SitemapGenerator;
+
+// Check `xml` is well-formed as far as a sitemap goes: one prolog, tags
+// that nest and close, attribute values in quotes, and nothing but the
+// predefined entities and character references.
+fn assert_well_formed(xml: &str) {
+    let body = xml
+        .strip_prefix("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")
+        .unwrap_or_else(|| panic!("no prolog: {}", xml));
+    let mut open: Vec<&str> = Vec::new();
+    let mut rest = body;
+    let mut roots = 0;
+    while let Some(start) = rest.find(['<', '&']) {
+        check_text(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with('&') {
+            let end = rest.find(';').expect("an unterminated entity");
+            let entity = &rest[1..end];
+            let known = ["amp", "lt", "gt", "quot", "apos"].contains(&entity)
+                || entity.strip_prefix('#').is_some_and(|n| n.parse::<u32>().is_ok());
+            assert!(known, "unknown entity &{};", entity);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let end = rest.find('>').expect("an unterminated tag");
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            assert_eq!(open.pop(), Some(name), "mismatched </{}>", name);
+        } else {
+            let name = tag.split(' ').next().unwrap();
+            assert!(!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric()), "tag {:?}", tag);
+            for attribute in tag.split(' ').skip(1) {
+                let (key, value) = attribute.split_once('=').expect("an attribute without a value");
+                assert!(!key.is_empty());
+                assert!(value.len() >= 2 && value.starts_with('"') && value.ends_with('"'), "{}", attribute);
+            }
+            if open.is_empty() {
+                roots += 1;
+            }
+            open.push(name);
+        }
+    }
+    check_text(rest);
+    assert!(open.is_empty(), "unclosed {:?}", open);
+    assert_eq!(roots, 1, "one root element");
+}
+
+fn check_text(text: &str) {
+    assert!(!text.contains(['<', '>', '&']), "{:?}", text);
+}
+
+// The `<loc>` values, unescaped.
+fn locations(xml: &str) -> Vec<String> {
+    xml.split("<loc>")
+        .skip(1)
+        .map(|rest| rest.split("</loc>").next().unwrap())
+        .map(|loc| loc.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&#39;", "'").replace("&amp;", "&"))
+        .collect()
+}
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router
+        .get("/", |_: &Request| "home")
+        .get("/about", |_: &Request| "about")
+        .get("/users/:id", |_: &Request| "a user")
+        .get("/files/*path", |_: &Request| "a file")
+        .post("/contact", |_: &Request| "sent")
+        .put("/about", |_: &Request| "updated")
+        .get("/healthz", |_: &Request| "ok");
+    router.no_sitemap();
+    router.get("/docs/intro", |_: &Request| "intro");
+    router
+}
+
+#[test]
+fn only_static_get_routes_are_listed() {
+    let xml = SitemapGenerator::from_router(&router(), "https://example.com");
+    assert_well_formed(&xml);
+    assert_eq!(locations(&xml), ["https://example.com/", "https://example.com/about", "https://example.com/docs/intro"]);
+    assert!(!xml.contains(":id") && !xml.contains("*path") && !xml.contains("contact") && !xml.contains("healthz"));
+}
+
+#[test]
+fn the_output_is_exactly_a_sitemap() {
+    let mut router = Router::new();
+    router.get("/", |_: &Request| "home");
+    assert_eq!(
+        SitemapGenerator::from_router(&router, "https://example.com/"),
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n  \
+         <url><loc>https://example.com/</loc></url>\n\
+         </urlset>\n"
+    );
+    let empty = SitemapGenerator::from_router(&Router::new(), "https://example.com");
+    assert_well_formed(&empty);
+    assert!(locations(&empty).is_empty());
+}
+
+#[test]
+fn paths_are_escaped_and_listed_once() {
+    let mut router = Router::new();
+    router
+        .get("/a&b", |_: &Request| "and")
+        .get("/<tag>", |_: &Request| "tag")
+        .get("/it's", |_: &Request| "quote")
+        .get("/twice", |_: &Request| "first")
+        .get("/between", |_: &Request| "between")
+        .get("/twice", |_: &Request| "second");
+    let xml = SitemapGenerator::from_router(&router, "https://example.com");
+    assert_well_formed(&xml);
+    assert!(xml.contains("<loc>https://example.com/a&amp;b</loc>"));
+    assert_eq!(
+        locations(&xml),
+        ["https://example.com/a&b", "https://example.com/<tag>", "https://example.com/it's", "https://example.com/twice", "https://example.com/between"]
+    );
+}
+
+#[test]
+fn registering_serves_the_routes_so_far_but_not_itself() {
+    let mut router = router();
+    SitemapGenerator::register(&mut router, "https://example.com");
+    router.get("/later", |_: &Request| "too late");
+    let response = router.dispatch(&Request::new("GET", "/sitemap.xml"));
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/xml; charset=utf-8"));
+    let xml = String::from_utf8(response.body).unwrap();
+    assert_well_formed(&xml);
+    assert_eq!(locations(&xml).len(), 3);
+    assert!(!xml.contains("sitemap.xml") && !xml.contains("/later"));
+}
+
+#[test]
+fn the_binary_lists_its_pages() {
+    let server = common::start("sitemap", "sitemap_base_url = \"https://example.com\"\n");
+    let response = common::get(server.port, "/sitemap.xml", "");
+    assert!(response.starts_with("HTTP/1.1 200 "), "{}", response);
+    let xml = common::body(&response);
+    assert_well_formed(xml);
+    let locations = locations(xml);
+    assert!(locations.contains(&"https://example.com/".to_string()), "{:?}", locations);
+    for path in ["/sleep", "/readyz", "/export.csv", "/sitemap.xml", "/static"] {
+        assert!(!locations.iter().any(|loc| loc.contains(path)), "{} in {:?}", path, locations);
+    }
+}
@@ -0,0 +1,96 @@
+// `threshold_warning` picks out slow requests and large responses, and
+// the server logs the warning for a handler that runs past the limit.
+mod common;
+
+use std::time::{Duration, Instant};
+
+use server_app::config::Config;
+use server_app::http::Method;
+use server_app::server::{threshold_warning, Served, ServerConfig};
+
+fn thresholds(slow_ms: u64, large_bytes: usize) -> ServerConfig {
+    ServerConfig {
+        slow_request_warn: Some(Duration::from_millis(slow_ms)),
+        large_response_warn: Some(large_bytes),
+        ..ServerConfig::default()
+    }
+}
+
+fn served(method: &Method, duration_ms: u64, response_bytes: usize, worker: Option<usize>) -> Served<'_> {
+    Served {
+        id: 7,
+        method,
+        path: "/sleep",
+        duration: Duration::from_millis(duration_ms),
+        response_bytes,
+        worker,
+        client_abort: None,
+    }
+}
+
+#[test]
+fn only_what_goes_past_a_threshold_is_reported() {
+    let config = thresholds(100, 1024);
+    let get = Method::Get;
+    assert_eq!(threshold_warning(&config, &served(&get, 99, 1023, Some(1))), None);
+    // At the limit is still fine; past it isn't.
+    assert_eq!(threshold_warning(&config, &served(&get, 100, 1024, Some(1))), None);
+    assert!(threshold_warning(&config, &served(&get, 101, 0, Some(1))).is_some());
+    assert!(threshold_warning(&config, &served(&get, 0, 1025, Some(1))).is_some());
+}
+
+#[test]
+fn the_warning_names_what_was_exceeded() {
+    let config = thresholds(100, 1024);
+    let get = Method::Get;
+    assert_eq!(
+        threshold_warning(&config, &served(&get, 5003, 10, Some(2))).unwrap(),
+        "Slow request #7: GET /sleep took 5003ms, 10 bytes, worker 2"
+    );
+    assert_eq!(
+        threshold_warning(&config, &served(&get, 3, 4096, None)).unwrap(),
+        "Large response #7: GET /sleep took 3ms, 4096 bytes, no worker"
+    );
+    let post = Method::Post;
+    assert_eq!(
+        threshold_warning(&config, &served(&post, 250, 2048, Some(0))).unwrap(),
+        "Slow request, large response #7: POST /sleep took 250ms, 2048 bytes, worker 0"
+    );
+}
+
+#[test]
+fn thresholds_can_be_turned_off() {
+    let off = ServerConfig { slow_request_warn: None, large_response_warn: None, ..ServerConfig::default() };
+    assert_eq!(threshold_warning(&off, &served(&Method::Get, 60_000, usize::MAX, None)), None);
+
+    let config = Config::parse("[server]\nslow_request_warn_ms = 0\nlarge_response_warn_bytes = 0\n").unwrap();
+    let config = ServerConfig::from_config(&config).unwrap();
+    assert_eq!((config.slow_request_warn, config.large_response_warn), (None, None));
+    let config = Config::parse("[server]\nslow_request_warn_ms = 250\nlarge_response_warn_bytes = 64\n").unwrap();
+    let config = ServerConfig::from_config(&config).unwrap();
+    assert_eq!(config.slow_request_warn, Some(Duration::from_millis(250)));
+    assert_eq!(config.large_response_warn, Some(64));
+}
+
+#[test]
+fn the_server_warns_about_a_handler_that_sleeps_past_the_threshold() {
+    let (server, lines) = common::start_logging("slow-requests", "slow_request_warn_ms = 200\n");
+    let response = common::get(server.port, "/sleep", "");
+    assert!(response.starts_with("HTTP/1.1 200 "), "{}", response);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let warning = loop {
+        let line = lines.recv_timeout(deadline.saturating_duration_since(Instant::now())).expect("no warning logged");
+        if line.contains("Slow request #") {
+            break line;
+        }
+    };
+    assert!(warning.contains(": GET /sleep took "), "{}", warning);
+    assert!(warning.contains(" bytes, worker "), "{}", warning);
+
+    // A quick request isn't reported.
+    common::get(server.port, "/hello", "");
+    while let Ok(line) = lines.recv_timeout(Duration::from_millis(300)) {
+        assert!(!line.contains("/hello took"), "{}", line);
+    }
+}
@@ -0,0 +1,171 @@
+// Socket activation: with `LISTEN_PID` and `LISTEN_FDS` set for this
+// process, `net::listen` serves on descriptor 3 instead of binding. The
+// service manager is simulated by `dup2`-ing a listener we made onto it.
+#![cfg(any(target_os = "linux", target_os = "macos"))]
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+use server_app::http::Response;
+use server_app::net::{self, SocketOptions};
+use server_app::server::{read_request, Incoming, ServerConfig};
+
+extern "C" {
+    fn dup(fd: i32) -> i32;
+    fn dup2(fd: i32, to: i32) -> i32;
+    fn close(fd: i32) -> i32;
+}
+
+// The variables and descriptor 3 belong to the whole process.
+static ACTIVATION: Mutex<()> = Mutex::new(());
+
+const ACTIVATED: RawFd = 3;
+
+// Take the lock, and make sure descriptor 3 is in use, so that sockets
+// the test opens land elsewhere.
+fn exclusive() -> (MutexGuard<'static, ()>, Option<File>) {
+    let guard = ACTIVATION.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let placeholder = File::open("/dev/null").unwrap();
+    let placeholder = (placeholder.as_raw_fd() == ACTIVATED).then_some(placeholder);
+    (guard, placeholder)
+}
+
+// Put a copy of `fd` at descriptor 3 and announce it, as systemd would.
+// Whatever had descriptor 3 is kept aside and put back on drop.
+struct Activation {
+    saved: Option<RawFd>,
+}
+
+impl Activation {
+    fn pass(fd: RawFd, pid: u32, count: &str) -> Activation {
+        let saved = match unsafe { dup(ACTIVATED) } {
+            -1 => None,
+            saved => Some(saved),
+        };
+        assert_ne!(fd, ACTIVATED);
+        assert_eq!(unsafe { dup2(fd, ACTIVATED) }, ACTIVATED);
+        std::env::set_var("LISTEN_PID", pid.to_string());
+        std::env::set_var("LISTEN_FDS", count);
+        std::env::set_var("LISTEN_FDNAMES", "http");
+        Activation { saved }
+    }
+}
+
+impl Drop for Activation {
+    fn drop(&mut self) {
+        match self.saved {
+            Some(saved) => unsafe {
+                dup2(saved, ACTIVATED);
+                close(saved);
+            },
+            None => unsafe {
+                close(ACTIVATED);
+            },
+        }
+    }
+}
+
+fn activation_options() -> SocketOptions {
+    SocketOptions { socket_activation: true, ..SocketOptions::default() }
+}
+
+fn variables_cleared() -> bool {
+    ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"].iter().all(|name| std::env::var(name).is_err())
+}
+
+// Answer one request on `listener`, from a thread.
+fn serve_one(listener: TcpListener) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut config = ServerConfig::default();
+        config.allowed_hosts.clear();
+        match read_request(&mut stream, &mut Vec::new(), &config, |_| None) {
+            Incoming::Request(request) => {
+                let response = Response::new(200, "OK").with_body(format!("activated {}", request.path));
+                response.write_to(&mut stream).unwrap();
+            }
+            _ => panic!("no request"),
+        }
+    })
+}
+
+fn get(port: u16, path: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn the_passed_listener_is_served_instead_of_binding() {
+    let _exclusive = exclusive();
+    let passed = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = passed.local_addr().unwrap().port();
+    let _passed = Activation::pass(passed.as_raw_fd(), std::process::id(), "1");
+    // Only the copy at descriptor 3 is left to accept on.
+    drop(passed);
+
+    let listener = net::listen("127.0.0.1:0", &activation_options()).unwrap();
+    assert_eq!(listener.as_raw_fd(), ACTIVATED);
+    assert_eq!(listener.local_addr().unwrap().port(), port, "not a freshly bound port");
+    assert!(variables_cleared());
+
+    let served = serve_one(listener);
+    let response = get(port, "/socket");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nactivated /socket"), "{}", response);
+    served.join().unwrap();
+}
+
+#[test]
+fn without_the_flag_the_variables_are_ignored() {
+    let _exclusive = exclusive();
+    let passed = TcpListener::bind("127.0.0.1:0").unwrap();
+    let _passed = Activation::pass(passed.as_raw_fd(), std::process::id(), "1");
+
+    let listener = net::listen("127.0.0.1:0", &SocketOptions::default()).unwrap();
+    assert_ne!(listener.local_addr().unwrap(), passed.local_addr().unwrap());
+    assert!(!variables_cleared(), "left for whoever does want them");
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+}
+
+#[test]
+fn variables_meant_for_another_process_are_cleared_and_ignored() {
+    let _exclusive = exclusive();
+    let passed = TcpListener::bind("127.0.0.1:0").unwrap();
+    for (pid, count) in [(std::process::id() + 1, "1"), (std::process::id(), "0"), (std::process::id(), "many")] {
+        let _passed = Activation::pass(passed.as_raw_fd(), pid, count);
+        assert!(net::inherited_listener().unwrap().is_none(), "{} {}", pid, count);
+        assert!(variables_cleared());
+
+        let listener = net::listen("127.0.0.1:0", &activation_options()).unwrap();
+        assert_ne!(listener.local_addr().unwrap(), passed.local_addr().unwrap(), "bound afresh");
+    }
+}
+
+#[test]
+fn a_descriptor_that_is_not_a_listening_socket_is_refused() {
+    let _exclusive = exclusive();
+    let file = File::open(env!("CARGO_MANIFEST_DIR").to_string() + "/Cargo.toml").unwrap();
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    let connected = TcpStream::connect(peer.local_addr().unwrap()).unwrap();
+
+    for (fd, complaint) in [(file.as_raw_fd(), "is not a socket"), (connected.as_raw_fd(), "is not listening")] {
+        let _passed = Activation::pass(fd, std::process::id(), "1");
+        let error = net::inherited_listener().unwrap_err();
+        assert!(error.to_string().contains(complaint), "{}", error);
+        assert!(variables_cleared());
+    }
+
+    // `listen` reports it and binds instead.
+    let _passed = Activation::pass(file.as_raw_fd(), std::process::id(), "1");
+    let listener = net::listen("127.0.0.1:0", &activation_options()).unwrap();
+    assert_ne!(listener.as_raw_fd(), ACTIVATED);
+}
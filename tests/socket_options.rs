@@ -0,0 +1,190 @@
+// The configured socket options reach every accepted connection, the
+// listener queues as many connections as its backlog says, processes
+// can share a port with `reuse_port`, and a restarted server can bind
+// its port again straight away.
+use std::io::Read;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use server_app::config::Config;
+use server_app::net::{self, SocketOptions};
+use server_app::server::{ExecutionMode, Server, ServerConfig};
+
+// An accepted connection, as the server would have it, and its client.
+fn accepted(options: &SocketOptions) -> (TcpStream, TcpStream) {
+    let listener = net::bind("127.0.0.1:0", options).unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    (stream, client)
+}
+
+#[test]
+fn nodelay_is_on_by_default_and_can_be_turned_off() {
+    let options = SocketOptions::default();
+    assert!(options.nodelay);
+    let (stream, _client) = accepted(&options);
+    net::configure_stream(&stream, &options).unwrap();
+    assert!(stream.nodelay().unwrap());
+    assert_eq!(net::keepalive(&stream).unwrap(), None);
+
+    let options = SocketOptions { nodelay: false, ..SocketOptions::default() };
+    let (stream, _client) = accepted(&options);
+    stream.set_nodelay(true).unwrap();
+    net::configure_stream(&stream, &options).unwrap();
+    assert!(!stream.nodelay().unwrap());
+}
+
+#[test]
+fn keepalive_probes_are_configured() {
+    let options = SocketOptions {
+        keepalive: Some(Duration::from_secs(45)),
+        keepalive_interval: Some(Duration::from_secs(5)),
+        ..SocketOptions::default()
+    };
+    let (stream, _client) = accepted(&options);
+    net::configure_stream(&stream, &options).unwrap();
+    assert!(stream.nodelay().unwrap());
+    assert_eq!(net::keepalive(&stream).unwrap(), Some(Duration::from_secs(45)));
+
+    // Less than a second rounds up to the kernel's smallest, one.
+    let (stream, _client) = accepted(&options);
+    net::set_keepalive(&stream, Duration::from_millis(10), None).unwrap();
+    assert_eq!(net::keepalive(&stream).unwrap(), Some(Duration::from_secs(1)));
+}
+
+#[test]
+fn options_come_from_the_config_file() {
+    let config = Config::parse("[server]\nnodelay = false\ntcp_keepalive_secs = 60\ntcp_keepalive_interval_secs = 10\n").unwrap();
+    let socket = ServerConfig::from_config(&config).unwrap().socket;
+    assert!(!socket.nodelay);
+    assert_eq!(socket.keepalive, Some(Duration::from_secs(60)));
+    assert_eq!(socket.keepalive_interval, Some(Duration::from_secs(10)));
+
+    let defaults = ServerConfig::from_config(&Config::parse("").unwrap()).unwrap().socket;
+    assert_eq!(defaults, SocketOptions::default());
+    assert!(ServerConfig::from_config(&Config::parse("[server]\ntcp_keepalive_secs = -1\n").unwrap()).is_err());
+}
+
+// Whether `Server::serve` handed its handler a connection with nodelay on.
+fn served_with_nodelay(options: SocketOptions) -> bool {
+    let (sender, received) = mpsc::channel();
+    let config = ServerConfig { socket: options, ..ServerConfig::default() };
+    let server = Server::new(config, move |stream, _, _| sender.send(stream.nodelay().unwrap()).unwrap())
+        .with_mode(ExecutionMode::Single);
+    let (stream, _client) = accepted(&options);
+    stream.set_nodelay(!options.nodelay).unwrap();
+    server.serve(stream);
+    received.recv_timeout(Duration::from_secs(5)).unwrap()
+}
+
+#[test]
+fn the_server_sets_nodelay_on_every_connection_it_serves() {
+    assert!(served_with_nodelay(SocketOptions::default()));
+    assert!(!served_with_nodelay(SocketOptions { nodelay: false, ..SocketOptions::default() }));
+}
+
+// A port with a connection in TIME_WAIT on it, as a server that hung up
+// on its clients and exited leaves behind.
+fn port_in_time_wait() -> SocketAddr {
+    let listener = net::bind("127.0.0.1:0", &SocketOptions::default()).unwrap();
+    let address = listener.local_addr().unwrap();
+    let mut client = TcpStream::connect(address).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    drop(stream); // The server closes first, so the wait is on its side.
+    let _ = client.read_to_end(&mut Vec::new());
+    address
+}
+
+#[test]
+fn a_restart_can_bind_its_port_again_at_once() {
+    let address = port_in_time_wait();
+    let listener = net::bind(address, &SocketOptions::default()).unwrap();
+    assert_eq!(listener.local_addr().unwrap(), address);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn without_reuse_address_the_port_stays_taken() {
+    let address = port_in_time_wait();
+    let options = SocketOptions { reuse_address: false, ..SocketOptions::default() };
+    let error = net::bind(address, &options).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+}
+
+// Connect without accepting until the queue is full: how many got in.
+#[cfg(target_os = "linux")]
+fn pending_connections(listener: &std::net::TcpListener, held: &mut Vec<TcpStream>) -> usize {
+    let address = listener.local_addr().unwrap();
+    let before = held.len();
+    while let Ok(stream) = TcpStream::connect_timeout(&address, Duration::from_millis(300)) {
+        held.push(stream);
+        assert!(held.len() < 10_000, "the queue never filled");
+    }
+    held.len() - before
+}
+
+// Linux queues one more connection than the backlog it was given.
+#[cfg(target_os = "linux")]
+#[test]
+fn the_listener_queues_as_many_connections_as_its_backlog() {
+    for backlog in [1, 4, 16] {
+        let listener = net::bind("127.0.0.1:0", &SocketOptions { backlog, ..SocketOptions::default() }).unwrap();
+        let mut held = Vec::new();
+        assert_eq!(pending_connections(&listener, &mut held), backlog as usize + 1, "backlog {}", backlog);
+
+        // Taking one off the queue makes room for exactly one more.
+        let _ = listener.accept().unwrap();
+        assert_eq!(pending_connections(&listener, &mut held), 1, "backlog {}", backlog);
+    }
+
+    let listener = net::bind("127.0.0.1:0", &SocketOptions::default()).unwrap();
+    assert_eq!(pending_connections(&listener, &mut Vec::new()), 129, "the default is 128");
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[test]
+fn with_reuse_port_two_listeners_share_a_port() {
+    let options = SocketOptions { reuse_port: true, ..SocketOptions::default() };
+    let first = net::bind("127.0.0.1:0", &options).unwrap();
+    let address = first.local_addr().unwrap();
+    let second = net::bind(address, &options).unwrap();
+    assert_eq!(second.local_addr().unwrap(), address);
+
+    // Both take connections: each is queued on one or the other.
+    let clients: Vec<_> = (0..64).map(|_| TcpStream::connect(address).unwrap()).collect();
+    let mut taken = [0, 0];
+    for (i, listener) in [&first, &second].into_iter().enumerate() {
+        listener.set_nonblocking(true).unwrap();
+        while listener.accept().is_ok() {
+            taken[i] += 1;
+        }
+    }
+    assert_eq!(taken[0] + taken[1], clients.len());
+    if cfg!(target_os = "linux") {
+        // Linux spreads connections across the group.
+        assert!(taken[0] > 0 && taken[1] > 0, "{:?}", taken);
+    }
+}
+
+#[test]
+fn without_reuse_port_a_second_listener_is_refused() {
+    let first = net::bind("127.0.0.1:0", &SocketOptions::default()).unwrap();
+    let address = first.local_addr().unwrap();
+    let error = net::bind(address, &SocketOptions::default()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+    // Both sides have to ask for it.
+    let error = net::bind(address, &SocketOptions { reuse_port: true, ..SocketOptions::default() }).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+}
+
+#[test]
+fn backlog_and_reuse_port_come_from_the_config_file() {
+    let load = |text: &str| ServerConfig::from_config(&Config::parse(text).unwrap()).map(|config| config.socket);
+    let socket = load("[server]\nbacklog = 1024\nreuse_port = true\n").unwrap();
+    assert_eq!((socket.backlog, socket.reuse_port), (1024, true));
+    assert_eq!(load("").unwrap().backlog, 128);
+    assert!(!load("").unwrap().reuse_port);
+    assert!(load("[server]\nbacklog = 0\n").is_err());
+    assert!(load("[server]\nbacklog = -5\n").is_err());
+}
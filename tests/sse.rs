@@ -0,0 +1,93 @@
+// Server-sent events go out in the `text/event-stream` wire format, and
+// a client that disconnects stops the loop producing them.
+use std::io::ErrorKind;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use server_app::http::Request;
+use server_app::sse::{self, comment_to_wire, Event, SseWriter};
+use server_app::testing::MockStream;
+
+#[test]
+fn events_are_framed_field_by_field() {
+    assert_eq!(Event::new("hello").to_wire(), "data: hello\n\n");
+    let event = Event::new("line one\nline two\r\nline three")
+        .with_name("log")
+        .with_id("42")
+        .with_retry(Duration::from_secs(3));
+    assert_eq!(
+        event.to_wire(),
+        "id: 42\nevent: log\nretry: 3000\ndata: line one\ndata: line two\ndata: line three\n\n"
+    );
+    assert_eq!(Event::new("").to_wire(), "data: \n\n");
+
+    // A line break can't smuggle in a field of its own.
+    assert_eq!(Event::new("x").with_name("a\ndata: forged").to_wire(), "event: adata: forged\ndata: x\n\n");
+    assert_eq!(comment_to_wire("keep-alive\nping"), ": keep-alive\n: ping\n");
+}
+
+#[test]
+fn the_writer_flushes_each_frame_whole() {
+    let mut writer = SseWriter::new(MockStream::new(Vec::<Vec<u8>>::new()));
+    writer.send_event("tick", "1").unwrap();
+    writer.send_comment("still here").unwrap();
+    let written = writer.into_inner();
+    assert_eq!(written.written(), b"event: tick\ndata: 1\n\n: still here\n");
+}
+
+#[test]
+fn a_write_error_is_returned_to_the_producer() {
+    let mut writer = SseWriter::new(MockStream::new(Vec::<Vec<u8>>::new()).fail_writes_after(2));
+    writer.send_event("tick", "1").unwrap();
+    writer.send_event("tick", "2").unwrap();
+    assert_eq!(writer.send_event("tick", "3").unwrap_err().kind(), ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn the_response_streams_without_a_length_and_closes() {
+    let request = Request::new("GET", "/events");
+    let response = sse::response(|events| {
+        events.send_event("tick", "1").unwrap();
+    })
+    .finalize(&request);
+    assert!(!response.keeps_alive());
+
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    let (head, body) = text.split_once("\r\n\r\n").unwrap();
+    assert!(head.contains("Content-Type: text/event-stream\r\n"));
+    assert!(head.contains("Cache-Control: no-cache\r\n"));
+    assert!(head.ends_with("Connection: close"));
+    assert!(!head.contains("Content-Length"));
+    assert_eq!(body, "event: tick\ndata: 1\n\n");
+}
+
+#[test]
+fn a_disconnected_client_stops_the_producer() {
+    let (done, finished) = mpsc::channel();
+    let response = sse::response(move |events| {
+        let done = done.clone();
+        // Produce from another thread, the way a scheduled job would.
+        thread::spawn(move || {
+            let mut sent = 0;
+            while events.send_event("tick", &sent.to_string()).is_ok() {
+                sent += 1;
+                thread::sleep(Duration::from_millis(1));
+            }
+            assert!(events.is_closed());
+            done.send(sent).unwrap();
+        });
+    });
+
+    // The head, then three events, then the client is gone.
+    let mut stream = MockStream::new(Vec::<Vec<u8>>::new()).fail_writes_after(4);
+    let error = response.write_to(&mut stream).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+    let sent = finished.recv_timeout(Duration::from_secs(5)).expect("the producer kept going");
+    assert!(sent >= 4, "{} events were sent before the disconnect was seen", sent);
+
+    let body = String::from_utf8_lossy(stream.written()).into_owned();
+    assert_eq!(body.matches("event: tick\n").count(), 3);
+}
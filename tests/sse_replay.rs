@@ -0,0 +1,67 @@
+// A client that reconnects with `Last-Event-ID` is sent what it missed.
+use std::sync::Arc;
+
+use server_app::http::Request;
+use server_app::sse::{self, Event, ReplayBuffer};
+
+// Connect, let `start` send, and return the event-stream body.
+fn connect(replay: &Arc<ReplayBuffer>, last_event_id: Option<&str>, send: &'static [&'static str]) -> String {
+    let mut request = Request::new("GET", "/events");
+    if let Some(id) = last_event_id {
+        request.headers.set("Last-Event-ID", id);
+    }
+    let response = sse::replayed(&request, Arc::clone(replay), move |events| {
+        for data in send {
+            events.send(&Event::new(*data)).unwrap();
+        }
+    });
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    text[text.find("\r\n\r\n").unwrap() + 4..].to_string()
+}
+
+fn data_lines(body: &str) -> Vec<&str> {
+    body.lines().filter_map(|line| line.strip_prefix("data: ")).collect()
+}
+
+#[test]
+fn reconnecting_client_gets_missed_events() {
+    let replay = Arc::new(ReplayBuffer::new(10));
+
+    // First connection: sees events 1 and 2, then drops.
+    let first = connect(&replay, None, &["a", "b"]);
+    assert_eq!(data_lines(&first), ["a", "b"]);
+    assert!(first.contains("id: 2\n"));
+
+    // Meanwhile another client's stream carries on.
+    connect(&replay, None, &["c", "d"]);
+
+    // The first client comes back having seen id 2.
+    let back = connect(&replay, Some("2"), &["e"]);
+    assert_eq!(data_lines(&back), ["c", "d", "e"]);
+
+    // Caught up: nothing to replay.
+    assert_eq!(data_lines(&connect(&replay, Some("5"), &[])), Vec::<&str>::new());
+}
+
+#[test]
+fn depth_bounds_the_buffer() {
+    let replay = Arc::new(ReplayBuffer::new(3));
+    connect(&replay, None, &["1", "2", "3", "4", "5"]);
+    assert_eq!(replay.len(), 3);
+
+    // Id 1 has been pruned, so everything still buffered is replayed.
+    assert_eq!(data_lines(&connect(&replay, Some("1"), &[])), ["3", "4", "5"]);
+    assert_eq!(data_lines(&connect(&replay, Some("4"), &[])), ["5"]);
+}
+
+#[test]
+fn broadcast_events_are_kept_once() {
+    let replay = ReplayBuffer::new(10);
+    let event = Event::new("news").with_id("n1");
+    replay.record(&event);
+    replay.record(&event);
+    assert_eq!(replay.len(), 1);
+    assert_eq!(replay.since("missing"), [event]);
+}
@@ -0,0 +1,117 @@
+// Static files over `STREAM_THRESHOLD` are sent a chunk at a time from
+// the open file, Range requests included; smaller ones are read whole.
+use std::fs;
+use std::path::PathBuf;
+
+use server_app::http::{Body, Request, Response};
+use server_app::static_files::{StaticFileServer, STREAM_THRESHOLD};
+
+const LEN: usize = STREAM_THRESHOLD as usize * 3 + 123;
+
+fn contents(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+fn root(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("static-streaming-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("big.bin"), contents(LEN)).unwrap();
+    fs::write(dir.join("edge.bin"), contents(STREAM_THRESHOLD as usize)).unwrap();
+    dir
+}
+
+fn fetch(dir: &PathBuf, path: &str, range: Option<&str>) -> Response {
+    let mut request = Request::new("GET", path);
+    if let Some(range) = range {
+        request.headers.set("Range", range);
+    }
+    StaticFileServer::new(dir).handle(&request)
+}
+
+// Run a streamed body and undo its chunked encoding, checking that no
+// chunk is bigger than the file is read in.
+fn streamed(response: &Response) -> Vec<u8> {
+    let Some(Body::Iter(body)) = &response.stream else { panic!("not streamed: {:?}", response.stream) };
+    assert!(response.body.is_empty());
+    let mut wire = Vec::new();
+    body.run(&mut wire).unwrap();
+
+    let mut rest = wire.as_slice();
+    let mut data = Vec::new();
+    loop {
+        let line = rest.iter().position(|&b| b == b'\n').unwrap();
+        let size = usize::from_str_radix(std::str::from_utf8(&rest[..line - 1]).unwrap(), 16).unwrap();
+        rest = &rest[line + 1..];
+        if size == 0 {
+            assert_eq!(rest, b"\r\n");
+            return data;
+        }
+        assert!(size <= 64 * 1024, "a {} byte chunk", size);
+        data.extend_from_slice(&rest[..size]);
+        rest = rest[size..].strip_prefix(b"\r\n").unwrap();
+    }
+}
+
+#[test]
+fn a_large_file_is_streamed_whole() {
+    let dir = root("whole");
+    let response = fetch(&dir, "/big.bin", None);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/octet-stream"));
+    assert!(response.header("ETag").is_some());
+    assert_eq!(streamed(&response), contents(LEN));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn a_file_at_the_threshold_is_still_read_whole() {
+    let dir = root("edge");
+    let response = fetch(&dir, "/edge.bin", None);
+    assert_eq!(response.status, 200);
+    assert!(response.stream.is_none());
+    assert_eq!(response.body, contents(STREAM_THRESHOLD as usize));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn one_range_of_a_large_file_is_streamed_from_its_offset() {
+    let dir = root("range");
+    let first = STREAM_THRESHOLD as usize - 10;
+    let last = first + 200_000;
+    let response = fetch(&dir, "/big.bin", Some(&format!("bytes={}-{}", first, last)));
+    assert_eq!(response.status, 206);
+    assert_eq!(response.header("Content-Range"), Some(format!("bytes {}-{}/{}", first, last, LEN).as_str()));
+    assert_eq!(response.header("Content-Type"), Some("application/octet-stream"));
+    assert!(response.header("ETag").is_some());
+    assert_eq!(streamed(&response), &contents(LEN)[first..=last]);
+
+    let suffix = fetch(&dir, "/big.bin", Some("bytes=-5"));
+    assert_eq!(streamed(&suffix), &contents(LEN)[LEN - 5..]);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn several_ranges_of_a_large_file_read_only_those_parts() {
+    let dir = root("multi");
+    let response = fetch(&dir, "/big.bin", Some("bytes=0-9,500000-500009"));
+    assert_eq!(response.status, 206);
+    assert!(response.stream.is_none());
+    let content_type = response.header("Content-Type").unwrap();
+    assert!(content_type.starts_with("multipart/byteranges; boundary="), "{}", content_type);
+    let body = String::from_utf8_lossy(&response.body);
+    assert!(body.contains(&format!("Content-Range: bytes 0-9/{}", LEN)));
+    assert!(body.contains(&format!("Content-Range: bytes 500000-500009/{}", LEN)));
+    assert!(response.body.windows(10).any(|w| w == &contents(LEN)[500_000..500_010]));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn an_unsatisfiable_range_of_a_large_file_is_a_416() {
+    let dir = root("unsatisfiable");
+    let response = fetch(&dir, "/big.bin", Some(&format!("bytes={}-", LEN)));
+    assert_eq!(response.status, 416);
+    assert_eq!(response.header("Content-Range"), Some(format!("bytes */{}", LEN).as_str()));
+    assert!(response.stream.is_none());
+    let _ = fs::remove_dir_all(&dir);
+}
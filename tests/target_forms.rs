@@ -0,0 +1,147 @@
+// The four request-target forms: which methods may use which, what the
+// parser makes of each, `OPTIONS *` answered for the whole server, and an
+// absolute-form `GET` routed by its path.
+mod common;
+
+use server_app::http::{Limits, ParseError, Request, Response, TargetForm};
+use server_app::router::Router;
+use server_app::server::{read_request, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+fn parse(head: &str) -> Result<Request, ParseError> {
+    Request::parse_head(head.as_bytes(), &Limits::default()).map(|(request, _)| request)
+}
+
+fn read(head: &str) -> Result<Request, Response> {
+    let mut config = ServerConfig::default();
+    config.allowed_hosts.clear();
+    match read_request(&mut MockStream::new([head.as_bytes().to_vec()]), &mut Vec::new(), &config, |_| None) {
+        Incoming::Request(request) => Ok(request),
+        Incoming::Reject(response) => Err(response),
+        Incoming::Closed => panic!("closed"),
+    }
+}
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.get("/items", |request: &Request| {
+        Response::new(200, "OK").with_body(format!("items {}", request.query.as_deref().unwrap_or("")))
+    });
+    router.post("/items", |_: &Request| Response::new(201, "Created"));
+    router.put("/items/:id", |_: &Request| Response::new(204, "No Content"));
+    router
+}
+
+#[test]
+fn each_form_is_told_apart_by_method_and_target() {
+    for (method, target, form) in [
+        ("GET", "/", Some(TargetForm::Origin)),
+        ("GET", "/a/b?c=d", Some(TargetForm::Origin)),
+        ("GET", "http://example.com/a", Some(TargetForm::Absolute)),
+        ("GET", "HTTPS://example.com:8443", Some(TargetForm::Absolute)),
+        ("OPTIONS", "*", Some(TargetForm::Asterisk)),
+        ("OPTIONS", "/", Some(TargetForm::Origin)),
+        ("CONNECT", "example.com:443", Some(TargetForm::Authority)),
+        ("CONNECT", "[::1]:8080", Some(TargetForm::Authority)),
+        // `*` is for `OPTIONS` alone, and authority-form for `CONNECT`.
+        ("GET", "*", None),
+        ("HEAD", "*", None),
+        ("GET", "example.com:443", None),
+        ("CONNECT", "example.com", None),
+        ("CONNECT", "/", None),
+        ("CONNECT", ":443", None),
+        // Nor is anything else a target.
+        ("GET", "", None),
+        ("GET", "items", None),
+        ("GET", "ftp://example.com/", None),
+        ("GET", "http:///path", None),
+        ("GET", "http://user@example.com/", None),
+    ] {
+        assert_eq!(TargetForm::of(method, target), form, "{} {:?}", method, target);
+    }
+}
+
+#[test]
+fn the_parser_records_the_form_and_splits_the_target() {
+    let origin = parse("GET /a?b=c HTTP/1.1\r\nHost: h\r\n\r\n").unwrap();
+    assert_eq!(origin.target_form, TargetForm::Origin);
+    assert_eq!((origin.path.as_str(), origin.query.as_deref()), ("/a", Some("b=c")));
+    assert_eq!((origin.scheme.as_deref(), origin.authority.as_deref()), (None, None));
+
+    let absolute = parse("GET Http://Example.com:8080/a/b?q=1 HTTP/1.1\r\nHost: h\r\n\r\n").unwrap();
+    assert_eq!(absolute.target_form, TargetForm::Absolute);
+    assert_eq!((absolute.path.as_str(), absolute.query.as_deref()), ("/a/b", Some("q=1")));
+    assert_eq!(absolute.scheme.as_deref(), Some("http"));
+    assert_eq!(absolute.authority.as_deref(), Some("Example.com:8080"));
+
+    // With nothing after the authority, the path is `/`.
+    for target in ["http://example.com", "http://example.com?q"] {
+        let bare = parse(&format!("GET {} HTTP/1.1\r\nHost: h\r\n\r\n", target)).unwrap();
+        assert_eq!(bare.path, "/", "{}", target);
+    }
+
+    let asterisk = parse("OPTIONS * HTTP/1.1\r\nHost: h\r\n\r\n").unwrap();
+    assert_eq!(asterisk.target_form, TargetForm::Asterisk);
+    assert_eq!((asterisk.path.as_str(), asterisk.query.as_deref()), ("*", None));
+
+    let authority = parse("CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n").unwrap();
+    assert_eq!(authority.target_form, TargetForm::Authority);
+    assert_eq!(authority.authority.as_deref(), Some("example.com:443"));
+}
+
+#[test]
+fn a_target_in_no_form_is_a_400() {
+    for head in [
+        "GET * HTTP/1.1\r\nHost: h\r\n\r\n",
+        "POST * HTTP/1.1\r\nHost: h\r\nContent-Length: 0\r\n\r\n",
+        "GET items HTTP/1.1\r\nHost: h\r\n\r\n",
+        "CONNECT /tunnel HTTP/1.1\r\nHost: h\r\n\r\n",
+    ] {
+        assert_eq!(parse(head).unwrap_err(), ParseError::InvalidTarget, "{:?}", head);
+        let response = read(head).unwrap_err();
+        assert_eq!(response.status, 400, "{:?}", head);
+        assert_eq!(response.body, b"invalid request target");
+        assert_eq!(response.header("Connection"), Some("close"));
+    }
+}
+
+#[test]
+fn options_asterisk_is_a_204_listing_every_method() {
+    let request = read("OPTIONS * HTTP/1.1\r\nHost: h\r\n\r\n").unwrap();
+    let response = router().dispatch(&request);
+    assert_eq!(response.status, 204);
+    assert_eq!(response.header("Allow"), Some("GET, HEAD, OPTIONS, POST, PUT"));
+    assert!(response.body.is_empty());
+
+    // Even a router with no routes answers for itself.
+    assert_eq!(Router::new().dispatch(&request).header("Allow"), Some("OPTIONS"));
+}
+
+#[test]
+fn an_absolute_form_get_is_routed_by_its_path() {
+    let request = read("GET http://example.com/items?page=2 HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+    let response = router().dispatch(&request);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"items page=2");
+
+    let missing = read("GET http://example.com/nothing HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+    assert_eq!(router().dispatch(&missing).status, 404);
+}
+
+#[test]
+fn the_server_answers_each_form() {
+    let server = common::start("target-forms", "");
+    let options = common::raw(server.port, "OPTIONS * HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    assert!(options.starts_with("HTTP/1.1 204 "), "{}", options);
+    let allow = common::header(&options, "Allow").unwrap();
+    assert!(allow.split(", ").any(|method| method == "GET"), "{}", allow);
+    assert!(allow.split(", ").any(|method| method == "OPTIONS"), "{}", allow);
+
+    let star = common::raw(server.port, "GET * HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert!(star.starts_with("HTTP/1.1 400 "), "{}", star);
+    assert_eq!(common::body(&star), "invalid request target");
+
+    let absolute = common::raw(server.port, "GET http://localhost/readyz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    assert!(absolute.starts_with("HTTP/1.1 200 "), "{}", absolute);
+    assert_eq!(common::body(&absolute), "ready");
+}
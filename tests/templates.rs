@@ -0,0 +1,97 @@
+// Template rendering: escaping, raw tags, missing variables and stray
+// braces; the file cache; and the home page when `index.html` is gone.
+mod common;
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use server_app::http::Response;
+use server_app::templates::{self, escape_html, render};
+
+fn vars(pairs: &[(&'static str, &str)]) -> HashMap<&'static str, String> {
+    pairs.iter().map(|(name, value)| (*name, value.to_string())).collect()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("templates-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn values_are_html_escaped() {
+    let vars = vars(&[("name", "<b>Tom & \"Jerry\"</b> isn't")]);
+    assert_eq!(
+        render("Hi {{name}}!", &vars),
+        "Hi &lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt; isn&#39;t!"
+    );
+    assert_eq!(render("{{ name }}", &vars), render("{{name}}", &vars));
+    assert_eq!(escape_html("plain text"), "plain text");
+}
+
+#[test]
+fn triple_braces_insert_the_value_as_it_is() {
+    let vars = vars(&[("html", "<em>hi</em>")]);
+    assert_eq!(render("<p>{{{html}}}</p>", &vars), "<p><em>hi</em></p>");
+    assert_eq!(render("<p>{{{ html }}}</p>", &vars), "<p><em>hi</em></p>");
+}
+
+#[test]
+fn a_missing_variable_renders_as_nothing() {
+    let vars = vars(&[("a", "1")]);
+    assert_eq!(render("[{{a}}][{{b}}][{{{c}}}]", &vars), "[1][][]");
+}
+
+#[test]
+fn braces_that_are_not_tags_are_left_alone() {
+    let vars = vars(&[("a", "1")]);
+    assert_eq!(render(r"\{{a}} is {{a}}", &vars), "{{a}} is 1");
+    assert_eq!(render("function f() { return {x: 1}; }", &vars), "function f() { return {x: 1}; }");
+    assert_eq!(render("{{ unclosed", &vars), "{{ unclosed");
+    assert_eq!(render("{{a{{a}}}}", &vars), "{{a1}}");
+    assert_eq!(render("{{{a}} and {{a}}", &vars), "{{{a}} and 1");
+    assert_eq!(render("{{}}", &vars), "");
+}
+
+#[test]
+fn a_template_is_cached_until_its_file_changes() {
+    let dir = temp_dir("cache");
+    let path = dir.join("page.html");
+    let old = SystemTime::now() - Duration::from_secs(60);
+    fs::write(&path, "first {{x}}").unwrap();
+    File::options().write(true).open(&path).unwrap().set_modified(old).unwrap();
+    assert_eq!(&*templates::load(&path).unwrap(), "first {{x}}");
+
+    // The same modification time: still the cached copy.
+    fs::write(&path, "other {{x}}").unwrap();
+    File::options().write(true).open(&path).unwrap().set_modified(old).unwrap();
+    assert_eq!(&*templates::load(&path).unwrap(), "first {{x}}");
+
+    // A new one: read again.
+    File::options().write(true).open(&path).unwrap().set_modified(old + Duration::from_secs(1)).unwrap();
+    assert_eq!(&*templates::load(&path).unwrap(), "other {{x}}");
+    let page = Response::html_template(&path, &vars(&[("x", "<1>")])).unwrap();
+    assert_eq!(page.header("Content-Type"), Some("text/html; charset=utf-8"));
+    assert_eq!(page.body, b"other &lt;1&gt;");
+
+    fs::remove_file(&path).unwrap();
+    assert!(templates::load(&path).is_err());
+    assert!(Response::html_template(&path, &HashMap::new()).is_err());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn the_home_page_is_served_without_index_html_on_disk() {
+    let cwd = temp_dir("no-index");
+    let server = common::start_in("no-index", "", &cwd);
+    let response = common::get(server.port, "/", "");
+    assert!(response.starts_with("HTTP/1.1 200 "), "{}", response);
+    assert_eq!(common::header(&response, "Content-Type"), Some("text/html; charset=utf-8"));
+    assert!(common::body(&response).contains("Visit number 1, at "));
+    assert!(!common::body(&response).contains("{{"));
+    drop(server);
+    let _ = fs::remove_dir_all(&cwd);
+}
@@ -0,0 +1,112 @@
+// Calendar dates for HTTP headers, cookies and logs, worked out without a
+// date crate: known moments either side of the edge cases, and every
+// parse undoing its format.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use server_app::timeutil::{self, CivilTime};
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[test]
+fn known_moments_are_formatted() {
+    let cases = [
+        (0, "Thu, 01 Jan 1970 00:00:00 GMT", "1970-01-01T00:00:00.000Z"),
+        (784_111_777, "Sun, 06 Nov 1994 08:49:37 GMT", "1994-11-06T08:49:37.000Z"),
+        (946_684_800, "Sat, 01 Jan 2000 00:00:00 GMT", "2000-01-01T00:00:00.000Z"),
+        (951_782_400, "Tue, 29 Feb 2000 00:00:00 GMT", "2000-02-29T00:00:00.000Z"),
+        (951_868_800, "Wed, 01 Mar 2000 00:00:00 GMT", "2000-03-01T00:00:00.000Z"),
+        (1_709_164_800, "Thu, 29 Feb 2024 00:00:00 GMT", "2024-02-29T00:00:00.000Z"),
+        (1_735_689_599, "Tue, 31 Dec 2024 23:59:59 GMT", "2024-12-31T23:59:59.000Z"),
+        (2_147_483_647, "Tue, 19 Jan 2038 03:14:07 GMT", "2038-01-19T03:14:07.000Z"),
+        (2_147_483_648, "Tue, 19 Jan 2038 03:14:08 GMT", "2038-01-19T03:14:08.000Z"),
+        (4_107_542_400, "Mon, 01 Mar 2100 00:00:00 GMT", "2100-03-01T00:00:00.000Z"),
+        (253_402_300_799, "Fri, 31 Dec 9999 23:59:59 GMT", "9999-12-31T23:59:59.000Z"),
+    ];
+    for (secs, http_date, rfc3339) in cases {
+        assert_eq!(timeutil::format_http_date(at(secs)), http_date, "{}", secs);
+        assert_eq!(timeutil::format_cookie_expires(at(secs)), http_date, "{}", secs);
+        assert_eq!(timeutil::format_rfc3339(at(secs)), rfc3339, "{}", secs);
+    }
+    assert_eq!(timeutil::format_rfc3339(at(0) + Duration::from_nanos(123_999_999)), "1970-01-01T00:00:00.123Z");
+    assert_eq!(timeutil::format_clf_date(at(971_186_136)), "10/Oct/2000:13:55:36 +0000");
+    assert_eq!(timeutil::format_http_date(UNIX_EPOCH - Duration::from_secs(1)), "Wed, 31 Dec 1969 23:59:59 GMT");
+}
+
+#[test]
+fn all_three_http_date_forms_are_parsed() {
+    let expected = Some(at(784_111_777));
+    assert_eq!(timeutil::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), expected);
+    assert_eq!(timeutil::parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), expected);
+    assert_eq!(timeutil::parse_http_date("Sun Nov  6 08:49:37 1994"), expected);
+    assert_eq!(timeutil::parse_http_date("  Sun, 06 Nov 1994 08:49:37 GMT "), expected);
+    assert_eq!(timeutil::parse_http_date("Sat Jan 01 00:00:00 2000"), Some(at(946_684_800)));
+    // A leap second is read as the next one.
+    assert_eq!(timeutil::parse_http_date("Sat, 31 Dec 2016 23:59:60 GMT"), Some(at(1_483_228_800)));
+}
+
+#[test]
+fn malformed_dates_are_refused() {
+    let invalid = [
+        "",
+        "yesterday",
+        "Sun, 30 Feb 2000 00:00:00 GMT",
+        "Thu, 29 Feb 1900 00:00:00 GMT",
+        "Fri, 29 Feb 2023 00:00:00 GMT",
+        "Sun, 31 Apr 2000 00:00:00 GMT",
+        "Sun, 00 Jan 2000 00:00:00 GMT",
+        "Sun, 06 Foo 1994 08:49:37 GMT",
+        "Sun, 06 nov 1994 08:49:37 GMT",
+        "Sun, 06 Nov 1994 24:00:00 GMT",
+        "Sun, 06 Nov 1994 08:60:00 GMT",
+        "Sun, 06 Nov 1994 08:49:61 GMT",
+        "Sun, 06 Nov 1994 8:49:37 GMT",
+        "Sun, 06 Nov 1994 08:49:37 UTC",
+        "Sun, 6 Nov 1994 08:49:37 GMT",
+        "Sun, 06 Nov 94 08:49:37 GMT",
+        "Funday, 06 Nov 1994 08:49:37 GMT",
+        "Sun, 06-Nov-94 08:49:37 GMT",
+        "Sunday, 06-Nov-1994 08:49:37 GMT",
+        "Sun Nov 6 08:49:37 1994",
+        "Sun Nov  6 08:49:37 94",
+        "Sun, +6 Nov 1994 08:49:37 GMT",
+    ];
+    for text in invalid {
+        assert_eq!(timeutil::parse_http_date(text), None, "{:?}", text);
+    }
+}
+
+#[test]
+fn formatting_and_parsing_round_trip() {
+    // Every 1,000,003 seconds (about 11.6 days, so each weekday, hour and
+    // leap year comes up) from the epoch to past 2100.
+    for secs in (0..4_200_000_000u64).step_by(1_000_003) {
+        let time = at(secs) + Duration::from_millis(secs % 1000);
+        let text = timeutil::format_http_date(time);
+        assert_eq!(timeutil::parse_http_date(&text), Some(at(secs)), "{}", text);
+        let civil = CivilTime::from_system_time(time);
+        assert_eq!(civil.to_system_time(), Some(time), "{:?}", civil);
+    }
+}
+
+#[test]
+fn civil_time_fields() {
+    let sunday = CivilTime::from_system_time(at(784_111_777));
+    let expected = CivilTime { year: 1994, month: 11, day: 6, hour: 8, minute: 49, second: 37, nanos: 0, weekday: 0 };
+    assert_eq!(sunday, expected);
+    assert_eq!(CivilTime::from_system_time(at(946_684_800)).weekday, 6);
+
+    let before = UNIX_EPOCH - Duration::from_millis(1500);
+    let civil = CivilTime::from_system_time(before);
+    let expected = CivilTime { year: 1969, month: 12, day: 31, hour: 23, minute: 59, second: 58, nanos: 500_000_000, weekday: 3 };
+    assert_eq!(civil, expected);
+    assert_eq!(civil.to_system_time(), Some(before));
+    let long_ago = UNIX_EPOCH - Duration::from_secs(2_208_988_800);
+    assert_eq!(CivilTime::from_system_time(long_ago).year, 1900);
+    assert_eq!(CivilTime::from_system_time(long_ago).to_system_time(), Some(long_ago));
+
+    assert_eq!(CivilTime { month: 13, ..expected }.to_system_time(), None);
+    assert_eq!(CivilTime { day: 32, ..expected }.to_system_time(), None);
+    assert_eq!(CivilTime { nanos: 1_000_000_000, ..expected }.to_system_time(), None);
+}
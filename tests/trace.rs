@@ -0,0 +1,76 @@
+// Trace spans nest, render as `Server-Timing`, and cost nothing when off.
+use std::time::{Duration, Instant};
+
+use server_app::http::Request;
+use server_app::trace::{self, Trace};
+
+#[test]
+fn spans_nest() {
+    let trace = Trace::new();
+    trace.span("handler", || {
+        trace.span("fs_read", || std::thread::sleep(Duration::from_millis(2)));
+        let template = trace.enter("template");
+        trace.span("escape", || {});
+        trace.exit(template);
+    });
+    trace.span("write", || {});
+
+    let spans = trace.spans();
+    let shape: Vec<(&str, usize)> = spans.iter().map(|span| (span.name, span.depth)).collect();
+    assert_eq!(shape, [("handler", 0), ("fs_read", 1), ("template", 1), ("escape", 2), ("write", 0)]);
+    assert!(spans[1].duration >= Duration::from_millis(2));
+    assert!(spans[0].duration >= spans[1].duration + spans[2].duration);
+    assert!(spans[2].start >= spans[1].start + spans[1].duration);
+    assert!(trace.tree()[1].starts_with("    fs_read "));
+}
+
+#[test]
+fn server_timing_lists_top_level_spans() {
+    let trace = Trace::new();
+    let started = Instant::now();
+    trace.record("parse", started, Duration::from_micros(1_500));
+    trace.record("route", started, Duration::from_micros(250));
+    trace.span("handler", || trace.span("fs_read", || {}));
+
+    let timing = trace.server_timing().unwrap();
+    let entries: Vec<&str> = timing.split(", ").collect();
+    assert_eq!(&entries[..2], ["parse;dur=1.500", "route;dur=0.250"]);
+    assert!(entries[2].starts_with("handler;dur="), "{}", timing);
+    assert_eq!(entries.len(), 3, "{}", timing);
+
+    assert_eq!(Trace::new().server_timing(), None);
+}
+
+#[test]
+fn disabled_records_nothing() {
+    let trace = Trace::disabled();
+    let id = trace.enter("handler");
+    assert_eq!(trace.span("fs_read", || 7), 7);
+    trace.record("parse", Instant::now(), Duration::from_millis(1));
+    trace.exit(id);
+    assert!(trace.spans().is_empty());
+    assert_eq!(trace.server_timing(), None);
+}
+
+// The only test that touches the process-wide switch, so none race on it.
+#[test]
+fn current_trace_collects_free_spans() {
+    assert!(trace::begin().is_none(), "off until enabled");
+    assert_eq!(trace::span("fs_read", || 1), 1);
+    assert!(trace::current().is_none());
+
+    trace::set_enabled(true);
+    let trace = trace::begin().unwrap();
+    trace::span("handler", || trace::record("fs_read", Instant::now(), Duration::ZERO));
+    trace::end();
+    trace::span("after", || {});
+    trace::set_enabled(false);
+
+    let names: Vec<&str> = trace.spans().iter().map(|span| span.name).collect();
+    assert_eq!(names, ["handler", "fs_read"]);
+
+    let mut request = Request::new("GET", "/?a=b&trace=1");
+    assert!(trace::requested(&request));
+    request.query = Some("trace=10".to_string());
+    assert!(!trace::requested(&request));
+}
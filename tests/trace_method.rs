@@ -0,0 +1,39 @@
+// `TRACE` echoes the request's headers back as `message/http`.
+use server_app::http::{Request, Response};
+use server_app::router::{Router, TraceMiddleware};
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.middleware(TraceMiddleware::new()).get("/", |_: &Request| Response::new(200, "OK"));
+    router
+}
+
+#[test]
+fn trace_reflects_headers() {
+    let mut request = Request::new("TRACE", "/some/path?q=1");
+    request.headers.set("Host", "example.com");
+    request.headers.set("X-Forwarded-For", "192.0.2.1");
+    request.headers.set("Cookie", "session=secret");
+    request.headers.set("Authorization", "Basic c2VjcmV0");
+
+    let response = router().dispatch(&request);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.headers.get("Content-Type"), Some("message/http"));
+    let body = String::from_utf8(response.body).unwrap();
+    assert!(body.starts_with("TRACE /some/path?q=1 HTTP/1.1\r\n"), "{}", body);
+    assert!(body.contains("Host: example.com\r\n"));
+    assert!(body.contains("X-Forwarded-For: 192.0.2.1\r\n"));
+    assert!(body.ends_with("\r\n\r\n"));
+    assert!(!body.contains("secret") && !body.contains("c2VjcmV0"), "credentials are left out");
+}
+
+#[test]
+fn other_methods_pass_through() {
+    let response = router().dispatch(&Request::new("GET", "/"));
+    assert_eq!((response.status, response.headers.get("Content-Type")), (200, None));
+
+    // Without the middleware TRACE is just another unrouted method.
+    let mut plain = Router::new();
+    plain.get("/", |_: &Request| Response::new(200, "OK"));
+    assert_eq!(plain.dispatch(&Request::new("TRACE", "/")).status, 405);
+}
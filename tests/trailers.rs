@@ -0,0 +1,78 @@
+// Trailers come after the zero-length chunk that ends a chunked body:
+// `ChunkedResponseWriter` sends them there, announced up front, and the
+// request reader picks them up from there.
+use std::io::Write;
+
+use server_app::http::{ChunkedResponseWriter, Response};
+use server_app::server::{read_request, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+
+fn response() -> Response {
+    Response::new(200, "OK").with_header("Content-Type", "text/plain")
+}
+
+#[test]
+fn trailers_follow_the_last_chunk() {
+    let mut writer = ChunkedResponseWriter::new(Vec::new(), &response(), &["Checksum", "Server-Timing"]).unwrap();
+    writer.write_all(b"hello ").unwrap();
+    writer.write_all(b"world").unwrap();
+    writer.add_trailer("Checksum", "abc123");
+    writer.add_trailer("Server-Timing", "db;dur=12");
+    let wire = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert_eq!(
+        wire,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTrailer: Checksum, Server-Timing\r\n\
+         Transfer-Encoding: chunked\r\n\r\n\
+         6\r\nhello \r\n5\r\nworld\r\n0\r\nChecksum: abc123\r\nServer-Timing: db;dur=12\r\n\r\n"
+    );
+}
+
+#[test]
+fn without_trailers_the_body_just_ends() {
+    let mut writer = ChunkedResponseWriter::new(Vec::new(), &response(), &[]).unwrap();
+    writer.write_all(b"").unwrap(); // Would end the body early if sent.
+    writer.write_all(b"x").unwrap();
+    let wire = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert!(!wire.contains("Trailer:"), "{}", wire);
+    assert!(wire.ends_with("\r\n\r\n1\r\nx\r\n0\r\n\r\n"), "{:?}", wire);
+}
+
+#[test]
+fn forbidden_and_malformed_trailers_are_left_out() {
+    let names = ["Content-Length", "host", "Set-Cookie", "Bad Name", "Checksum"];
+    let mut writer = ChunkedResponseWriter::new(Vec::new(), &response(), &names).unwrap();
+    writer.write_all(b"body").unwrap();
+    writer.add_trailer("Content-Length", "4");
+    writer.add_trailer("Authorization", "secret");
+    writer.add_trailer("Bad Name", "x");
+    writer.add_trailer("Checksum", "ok\r\nInjected: yes");
+    let wire = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert!(wire.contains("\r\nTrailer: Checksum\r\n"), "{}", wire);
+    let (_, tail) = wire.split_once("\r\n0\r\n").unwrap();
+    assert_eq!(tail, "Checksum: okInjected: yes\r\n\r\n");
+}
+
+fn read(chunks: &[&str]) -> Incoming {
+    let mut config = ServerConfig::default();
+    config.allowed_hosts.clear();
+    let mut stream = MockStream::new(chunks.iter().map(|chunk| chunk.as_bytes().to_vec()));
+    read_request(&mut stream, &mut Vec::new(), &config, |_| None)
+}
+
+#[test]
+fn trailers_on_a_request_are_read_after_the_last_chunk() {
+    let head = "POST /upload HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nTrailer: Checksum\r\n\r\n";
+    let body = "4\r\nabcd\r\n0\r\nChecksum: 1234\r\nExpires: never\r\n\r\n";
+    // In one read, and with the trailers arriving on their own.
+    for chunks in [vec![head, body], vec![head, "4\r\nabcd\r\n0\r\n", "Checksum: 1234\r\n", "Expires: never\r\n", "\r\n"]] {
+        let Incoming::Request(request) = read(&chunks) else { panic!("not read: {:?}", chunks) };
+        assert_eq!(request.body, b"abcd");
+        assert_eq!(request.trailers().get("Checksum"), Some("1234"));
+        assert_eq!(request.trailers().get("Expires"), Some("never"));
+        assert_eq!(request.header("Checksum"), None, "trailers aren't headers");
+    }
+
+    let Incoming::Request(plain) = read(&[head, "0\r\n\r\n"]) else { panic!("not read") };
+    assert!(plain.body.is_empty());
+    assert!(plain.trailers().is_empty());
+}
@@ -0,0 +1,128 @@
+// Connections asking for a protocol `UpgradeRegistry` knows are switched
+// over and handed to its handler raw; ones asking for anything else are
+// told what is supported.
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+use server_app::http::{Request, Response};
+use server_app::router::Router;
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::upgrade::UpgradeRegistry;
+
+// A dummy protocol: the handler says which request and peer it was given,
+// then echoes each line back in capitals until the client hangs up.
+fn registry() -> UpgradeRegistry {
+    let mut registry = UpgradeRegistry::new();
+    registry
+        .register("shout/1", |stream: TcpStream, request: Request| {
+            let mut writer = stream.try_clone()?;
+            writeln!(writer, "{} from {}", request.path, stream.peer_addr()?)?;
+            for line in BufReader::new(stream).lines() {
+                writeln!(writer, "{}", line?.to_uppercase())?;
+            }
+            Ok(())
+        })
+        .register("h2c", |_, _| Ok(()));
+    registry
+}
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.middleware(registry()).get("/chat", |_: &Request| Response::new(200, "OK").with_body("plain"));
+    router
+}
+
+// A server that serves one connection with `router`.
+fn serve(router: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let config = ServerConfig { allowed_hosts: Vec::new(), ..ServerConfig::default() };
+        let mut connection = Connection::new(stream);
+        let request = match connection.read_request(&config, |_| None) {
+            Incoming::Request(request) => request,
+            _ => panic!("expected a request"),
+        };
+        let response = router.dispatch(&request).finalize(&request);
+        connection.send(&response).unwrap();
+        if let Some(upgrade) = response.upgrade {
+            upgrade.run(connection.into_inner());
+        }
+    });
+    addr
+}
+
+fn upgrade_request(path: &str, upgrade: &str) -> Request {
+    let mut request = Request::new("GET", path);
+    request.headers.set("Connection", "Upgrade");
+    request.headers.set("Upgrade", upgrade);
+    request
+}
+
+#[test]
+fn the_raw_stream_is_handed_to_the_handler() {
+    let addr = serve(router());
+    let client = TcpStream::connect(addr).unwrap();
+    let mut writer = client.try_clone().unwrap();
+    write!(writer, "GET /chat HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: shout/1\r\n\r\n").unwrap();
+
+    // The switch, byte for byte, then the protocol's own lines.
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        assert!(reader.read_line(&mut head).unwrap() > 0, "{}", head);
+    }
+    assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"), "{}", head);
+    assert!(head.contains("\r\nUpgrade: shout/1\r\n"), "{}", head);
+    assert!(head.contains("\r\nConnection: Upgrade\r\n"), "{}", head);
+
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, format!("/chat from {}\n", client.local_addr().unwrap()));
+    writer.write_all(b"hello\nno http here\n").unwrap();
+    writer.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "HELLO\nNO HTTP HERE\n");
+}
+
+#[test]
+fn unsupported_protocols_are_told_what_is_supported() {
+    let router = router();
+    let response = router.dispatch(&upgrade_request("/chat", "h3, spdy/3"));
+    assert_eq!(response.status, 426);
+    assert_eq!(response.header("Upgrade"), Some("shout/1, h2c"));
+    assert!(response.upgrade.is_none());
+
+    // The first offer that is registered wins, whatever its case.
+    let response = router.dispatch(&upgrade_request("/chat", "h3, H2C, shout/1"));
+    assert_eq!((response.status, response.header("Upgrade")), (101, Some("h2c")));
+    assert!(response.upgrade.is_some());
+
+    // `Upgrade` without `Connection: Upgrade` isn't a request to switch.
+    let mut request = upgrade_request("/chat", "shout/1");
+    request.headers.set("Connection", "keep-alive");
+    assert_eq!(router.dispatch(&request).body, b"plain");
+    assert_eq!(router.dispatch(&Request::new("GET", "/chat")).body, b"plain");
+    // An error from the route stands.
+    assert_eq!(router.dispatch(&upgrade_request("/missing", "h3")).status, 404);
+}
+
+#[test]
+fn websocket_routes_still_switch() {
+    let mut router = router();
+    router.websocket("/ws", |_| {});
+    let mut request = upgrade_request("/ws", "websocket");
+    request.headers.set("Sec-WebSocket-Version", "13");
+    request.headers.set("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==");
+    let response = router.dispatch(&request);
+    assert_eq!((response.status, response.header("Upgrade")), (101, Some("websocket")));
+    assert!(response.upgrade.is_some());
+
+    // The handshake's own refusal is kept.
+    request.headers.set("Sec-WebSocket-Version", "8");
+    let response = router.dispatch(&request);
+    assert_eq!((response.status, response.header("Sec-WebSocket-Version")), (426, Some("13")));
+}
@@ -0,0 +1,99 @@
+// An upstream can't make the proxy allocate whatever it claims: bodies
+// over the limit, by `Content-Length`, chunk sizes or close-delimited,
+// and over-long or endless header lines are refused with a 502.
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use server_app::config::Config;
+use server_app::http::Request;
+use server_app::proxy::{ProxyError, ReverseProxy, DEFAULT_MAX_BODY_BYTES};
+
+// An upstream that reads one request head per connection and answers
+// with `response` as it is, then closes.
+fn upstream(response: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+                line.clear();
+            }
+            let _ = stream.write_all(&response);
+        }
+    });
+    addr
+}
+
+fn proxy(response: &str, max_body: usize) -> ReverseProxy {
+    let addr = upstream(response.as_bytes().to_vec());
+    ReverseProxy::new(&addr).with_max_body_bytes(max_body)
+}
+
+fn refused(proxy: &ReverseProxy) {
+    let result = proxy.forward(&Request::new("GET", "/"));
+    assert!(matches!(result, Err(ProxyError::ResponseTooLarge)), "{:?}", result.map(|r| r.status));
+    let response = proxy.handle(&Request::new("GET", "/"));
+    assert_eq!(response.status, 502);
+    assert_eq!(response.body, b"Bad Gateway");
+}
+
+#[test]
+fn a_content_length_of_u64_max_is_a_502_not_an_allocation() {
+    let proxy = ReverseProxy::new(&upstream(b"HTTP/1.1 200 OK\r\nContent-Length: 18446744073709551615\r\n\r\nhi".to_vec()));
+    refused(&proxy);
+    assert_eq!(ProxyError::ResponseTooLarge.to_string(), "upstream response too large");
+
+    // Past what even a u64 holds, it isn't a length at all.
+    let proxy = ReverseProxy::new(&upstream(b"HTTP/1.1 200 OK\r\nContent-Length: 99999999999999999999\r\n\r\n".to_vec()));
+    assert!(matches!(proxy.forward(&Request::new("GET", "/")), Err(ProxyError::InvalidResponse(_))));
+}
+
+#[test]
+fn bodies_up_to_the_limit_come_through() {
+    let proxy = proxy("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n0123456789", 10);
+    assert_eq!(proxy.forward(&Request::new("GET", "/")).unwrap().body, b"0123456789");
+    refused(&self::proxy("HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\n0123456789A", 10));
+}
+
+#[test]
+fn chunk_sizes_cannot_overflow_or_add_up_past_the_limit() {
+    refused(&proxy("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\n", 1024));
+    // Each chunk fits, but together they don't.
+    refused(&proxy("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nabcdef\r\n6\r\nghijkl\r\n0\r\n\r\n", 10));
+    let fits = proxy("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nabcde\r\n5\r\nfghij\r\n0\r\n\r\n", 10);
+    assert_eq!(fits.forward(&Request::new("GET", "/")).unwrap().body, b"abcdefghij");
+}
+
+#[test]
+fn a_close_delimited_body_stops_at_the_limit() {
+    refused(&proxy(&format!("HTTP/1.1 200 OK\r\n\r\n{}", "x".repeat(2000)), 1000));
+    let fits = proxy(&format!("HTTP/1.1 200 OK\r\n\r\n{}", "x".repeat(1000)), 1000);
+    assert_eq!(fits.forward(&Request::new("GET", "/")).unwrap().body.len(), 1000);
+}
+
+#[test]
+fn header_lines_and_counts_are_capped() {
+    let long = format!("HTTP/1.1 200 OK\r\nX-Long: {}\r\nContent-Length: 0\r\n\r\n", "a".repeat(20_000));
+    refused(&proxy(&long, DEFAULT_MAX_BODY_BYTES));
+    let endless_status = format!("HTTP/1.1 200 {}", "a".repeat(100_000));
+    refused(&proxy(&endless_status, DEFAULT_MAX_BODY_BYTES));
+    let many: String = (0..500).map(|i| format!("X-{}: y\r\n", i)).collect();
+    refused(&proxy(&format!("HTTP/1.1 200 OK\r\n{}Content-Length: 0\r\n\r\n", many), DEFAULT_MAX_BODY_BYTES));
+    let trailers: String = (0..500).map(|i| format!("X-{}: y\r\n", i)).collect();
+    let chunked = format!("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n{}\r\n", trailers);
+    refused(&proxy(&chunked, DEFAULT_MAX_BODY_BYTES));
+}
+
+#[test]
+fn the_limit_is_read_from_the_config() {
+    let addr = upstream(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_vec());
+    let config = Config::parse(&format!("[api]\nupstreams = [\"{}\"]\nmax_body_bytes = 4\n", addr)).unwrap();
+    refused(&ReverseProxy::from_config(&config, "api").unwrap());
+
+    let negative = Config::parse(&format!("[api]\nupstreams = [\"{}\"]\nmax_body_bytes = -1\n", addr)).unwrap();
+    assert!(ReverseProxy::from_config(&negative, "api").is_err());
+}
@@ -0,0 +1,161 @@
+// Proxied requests reuse idle upstream connections, and connections the
+// upstream closed, or said it would, are discarded instead.
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use server_app::http::Request;
+use server_app::proxy::{ConnectionPool, PoolStats, ReverseProxy};
+
+// An upstream that answers each request on a connection with the
+// connection's number, closing after `per_connection` responses.
+// `close_header` says whether the last one announces it.
+struct Upstream {
+    addr: String,
+    accepted: Arc<AtomicUsize>,
+}
+
+fn upstream(per_connection: usize, close_header: bool) -> Upstream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&accepted);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                for answered in 1..=per_connection {
+                    // Read one request head; the tests only send GETs.
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    let body = format!("connection {}", n);
+                    let close = if answered == per_connection && close_header { "Connection: close\r\n" } else { "" };
+                    let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n{}\r\n{}", body.len(), close, body);
+                    writer.write_all(response.as_bytes()).unwrap();
+                }
+            });
+        }
+    });
+    Upstream { addr, accepted }
+}
+
+fn pool(proxy: &ReverseProxy, addr: &str) -> Arc<ConnectionPool> {
+    proxy.pool(addr).expect("a request went there")
+}
+
+fn get(proxy: &ReverseProxy) -> String {
+    let response = proxy.forward(&Request::new("GET", "/")).unwrap();
+    assert_eq!(response.status, 200);
+    String::from_utf8(response.body).unwrap()
+}
+
+#[test]
+fn a_second_request_reuses_the_idle_connection() {
+    let upstream = upstream(usize::MAX, false);
+    let proxy = ReverseProxy::new(&upstream.addr);
+
+    assert_eq!(get(&proxy), "connection 1");
+    assert_eq!(pool(&proxy, &upstream.addr).idle_count(), 1);
+    assert_eq!(get(&proxy), "connection 1");
+    assert_eq!(upstream.accepted.load(Ordering::SeqCst), 1);
+    assert_eq!(pool(&proxy, &upstream.addr).stats(), PoolStats { hits: 1, misses: 1, discards: 0 });
+}
+
+#[test]
+fn a_connection_the_upstream_closed_is_discarded() {
+    let upstream = upstream(1, false);
+    let proxy = ReverseProxy::new(&upstream.addr);
+
+    assert_eq!(get(&proxy), "connection 1");
+    assert_eq!(pool(&proxy, &upstream.addr).idle_count(), 1);
+    thread::sleep(Duration::from_millis(50)); // Let the close arrive.
+
+    assert_eq!(get(&proxy), "connection 2");
+    assert_eq!(upstream.accepted.load(Ordering::SeqCst), 2);
+    assert_eq!(pool(&proxy, &upstream.addr).stats(), PoolStats { hits: 0, misses: 2, discards: 1 });
+}
+
+#[test]
+fn connection_close_keeps_it_out_of_the_pool() {
+    let upstream = upstream(1, true);
+    let proxy = ReverseProxy::new(&upstream.addr);
+
+    assert_eq!(get(&proxy), "connection 1");
+    assert_eq!(pool(&proxy, &upstream.addr).idle_count(), 0);
+    assert_eq!(get(&proxy), "connection 2");
+    assert_eq!(pool(&proxy, &upstream.addr).stats(), PoolStats { hits: 0, misses: 2, discards: 0 });
+}
+
+#[test]
+fn a_shared_pool_serves_every_proxy_using_it() {
+    let upstream = upstream(usize::MAX, false);
+    let pool = Arc::new(ConnectionPool::new(upstream.addr.parse().unwrap(), 8));
+    let (first, second) = (ReverseProxy::with_pool(Arc::clone(&pool)), ReverseProxy::with_pool(Arc::clone(&pool)));
+
+    assert_eq!(get(&first), "connection 1");
+    assert_eq!(get(&second), "connection 1");
+    assert_eq!(pool.stats(), PoolStats { hits: 1, misses: 1, discards: 0 });
+}
+
+// An upstream that answers the first request on each connection and
+// reads the second, counting it, but closes without answering.
+fn answers_once() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let received = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&received);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let counter = Arc::clone(&counter);
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                for answered in 0..2 {
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    if answered == 0 {
+                        writer.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok").unwrap();
+                    }
+                }
+            });
+        }
+    });
+    (addr, received)
+}
+
+#[test]
+fn only_idempotent_requests_are_resent_after_a_reused_connection_fails() {
+    let (addr, received) = answers_once();
+    let proxy = ReverseProxy::new(&addr);
+    assert_eq!(get(&proxy), "ok");
+    // The upstream got the GET before closing, and gets it again on a
+    // fresh connection.
+    assert_eq!(get(&proxy), "ok");
+    assert_eq!(received.load(Ordering::SeqCst), 3);
+
+    // The connection is pooled again; a POST on it isn't resent.
+    assert!(proxy.forward(&Request::new("POST", "/")).is_err());
+    assert_eq!(received.load(Ordering::SeqCst), 4);
+}
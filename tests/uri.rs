@@ -0,0 +1,136 @@
+// Request paths are validated and normalized before routing, and static
+// files are looked up by the normalized form.
+use std::{env, fs, process};
+
+use server_app::http::Request;
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::static_files::StaticFileServer;
+use server_app::testing::MockStream;
+use server_app::uri::{self, UriError};
+
+#[test]
+fn normalizes_paths() {
+    let cases: &[(&str, Result<&str, UriError>)] = &[
+        ("/", Ok("/")),
+        ("/index.html", Ok("/index.html")),
+        ("/a/b/c", Ok("/a/b/c")),
+        ("/a/b/", Ok("/a/b/")),
+        ("//", Ok("/")),
+        ("//etc/passwd", Ok("/etc/passwd")),
+        ("/a//b///c", Ok("/a/b/c")),
+        ("/a/./b", Ok("/a/b")),
+        ("/a/.", Ok("/a/")),
+        ("/./", Ok("/")),
+        ("/a/b/../c", Ok("/a/c")),
+        ("/a/b/..", Ok("/a/")),
+        ("/a/../../b", Err(UriError::AboveRoot)),
+        ("/..", Err(UriError::AboveRoot)),
+        ("/../etc/passwd", Err(UriError::AboveRoot)),
+        ("//../etc", Err(UriError::AboveRoot)),
+        ("/a/%2e%2e/b", Ok("/b")),
+        ("/a/%2E/b", Ok("/a/b")),
+        ("/.%2e/secret", Err(UriError::AboveRoot)),
+        ("/%2e%2e/%2e%2e/etc/passwd", Err(UriError::AboveRoot)),
+        ("/...", Ok("/...")),
+        ("/a..b/.c", Ok("/a..b/.c")),
+        ("/%7euser/%41", Ok("/~user/A")),
+        ("/a%2fb", Ok("/a%2Fb")),
+        ("/a%2f..%2fb", Ok("/a%2F..%2Fb")),
+        ("/caf%c3%a9", Ok("/caf%C3%A9")),
+        ("/a\\b", Err(UriError::InvalidCharacter)),
+        ("/..\\..\\windows", Err(UriError::InvalidCharacter)),
+        ("/a%5cb", Err(UriError::InvalidEncoding)),
+        ("/a%00.html", Err(UriError::EncodedControl)),
+        ("/a%0d%0aSet-Cookie:x", Err(UriError::EncodedControl)),
+        ("/a%1F", Err(UriError::EncodedControl)),
+        ("/a%7f", Err(UriError::EncodedControl)),
+        ("/a%20b", Ok("/a%20b")),
+        ("/a%zz", Err(UriError::InvalidEncoding)),
+        ("/a%2", Err(UriError::InvalidEncoding)),
+        ("/a b", Err(UriError::InvalidCharacter)),
+        ("/a\tb", Err(UriError::InvalidCharacter)),
+        ("/a\x7f", Err(UriError::InvalidCharacter)),
+        ("a/b", Err(UriError::NotAbsolute)),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(uri::normalize_path(input, false).as_deref(), expected.as_deref(), "{:?}", input);
+    }
+}
+
+#[test]
+fn lowercases_paths_but_not_escapes() {
+    assert_eq!(uri::normalize_path("/Docs/%7EReadMe%2f%c3%a9", true).as_deref(), Ok("/docs/~readme%2F%C3%A9"));
+    assert_eq!(uri::normalize_path("/Docs/%41", false).as_deref(), Ok("/Docs/A"));
+}
+
+fn read(raw: &str, config: &ServerConfig) -> Incoming {
+    let mut connection = Connection::new(MockStream::new([raw.as_bytes().to_vec()]));
+    connection.read_request(config, |_| None)
+}
+
+#[test]
+fn requests_are_routed_on_the_normalized_path() {
+    match read("GET //static/./css/../app.js?v=1 HTTP/1.1\r\nHost: localhost\r\n\r\n", &ServerConfig::default()) {
+        Incoming::Request(request) => {
+            assert_eq!(request.path, "/static/app.js");
+            assert_eq!(request.original_path.as_deref(), Some("//static/./css/../app.js"));
+            assert_eq!(request.target(), "/static/app.js?v=1");
+            assert_eq!(request.original_target(), "//static/./css/../app.js?v=1");
+        }
+        other => panic!("expected a request, got {:?}", other),
+    }
+
+    let config = ServerConfig { lowercase_paths: true, ..ServerConfig::default() };
+    match read("GET /About HTTP/1.1\r\nHost: localhost\r\n\r\n", &config) {
+        Incoming::Request(request) => assert_eq!(request.path, "/about"),
+        other => panic!("expected a request, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_path_without_a_normal_form_is_a_bad_request() {
+    for target in ["/../etc/passwd", "/%2e%2e/x", "/a%5cb", "/a\x01b"] {
+        match read(&format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target), &ServerConfig::default()) {
+            Incoming::Reject(response) => assert_eq!(response.status, 400, "{:?}", target),
+            other => panic!("expected a rejection for {:?}, got {:?}", target, other),
+        }
+    }
+}
+
+#[test]
+fn an_encoded_control_character_has_its_own_400() {
+    let reject = |target: &str, accept: &str| {
+        let raw = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nAccept: {}\r\n\r\n", target, accept);
+        match read(&raw, &ServerConfig::default()) {
+            Incoming::Reject(response) => response,
+            other => panic!("expected a rejection for {:?}, got {:?}", target, other),
+        }
+    };
+    assert_eq!(UriError::EncodedControl.to_string(), "encoded control character in request path");
+
+    let json = reject("/a%00.html", "application/json");
+    assert_eq!(json.status, 400);
+    assert_eq!(json.body, br#"{"status":400,"error":"encoded control character in request path"}"#);
+    let html = reject("/a%0A", "text/html");
+    assert_eq!(html.status, 400);
+    assert!(String::from_utf8(html.body).unwrap().contains("<h1>400 encoded control character in request path</h1>"));
+
+    // Which is not what a malformed escape gets.
+    let malformed = reject("/a%zz", "application/json");
+    assert_eq!(malformed.body, br#"{"status":400,"error":"invalid percent-encoding in request path"}"#);
+}
+
+#[test]
+fn static_files_refuse_traversal_in_any_spelling() {
+    let root = env::temp_dir().join(format!("uri-test-{}", process::id()));
+    fs::create_dir_all(root.join("css")).unwrap();
+    fs::write(root.join("app.js"), b"app").unwrap();
+    let server = StaticFileServer::new(root.join("css"));
+
+    for path in ["/../app.js", "/%2e%2e/app.js", "/.%2E/app.js", "/..\\app.js", "/x/../../app.js"] {
+        assert_eq!(server.handle(&Request::new("GET", path)).status, 404, "{:?}", path);
+    }
+    let server = StaticFileServer::new(&root);
+    assert_eq!(server.handle(&Request::new("GET", "//css/../app.js")).body, b"app");
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,95 @@
+// Layers that each add to `Vary` build one header between them instead
+// of overwriting each other, and negotiating on `Accept` adds `Accept`.
+use server_app::http::{Request, Response};
+use server_app::negotiation::ContentNegotiationMiddleware;
+use server_app::router::Router;
+
+fn negotiating_router() -> Router {
+    let mut router = Router::new();
+    router
+        .middleware(ContentNegotiationMiddleware::new())
+        .get("/hello", |request: &Request| {
+            let content_type = request.negotiate_content_type(&["text/html", "application/json"]).unwrap_or("text/html");
+            Response::new(200, "OK").with_header("Content-Type", content_type)
+        })
+        .get("/plain", |_: &Request| Response::new(200, "OK").with_header("Content-Type", "text/plain"))
+        .get("/encoded", |request: &Request| {
+            request.set_used_accept_negotiation();
+            Response::new(200, "OK").with_header("Vary", "Accept-Encoding")
+        })
+        .get("/anything", |request: &Request| {
+            request.set_used_accept_negotiation();
+            Response::new(200, "OK").with_header("Vary", "*")
+        });
+    router
+}
+
+fn get(router: &Router, path: &str, accept: &str) -> Response {
+    let mut request = Request::new("GET", path);
+    request.headers.set("Accept", accept);
+    router.dispatch(&request)
+}
+
+#[test]
+fn fields_are_merged_once_each_in_order() {
+    let mut response = Response::new(200, "OK").with_vary("Accept-Encoding");
+    response.vary("Accept");
+    response.vary("accept-encoding");
+    response.vary("Accept-Language");
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding, Accept, Accept-Language"));
+    assert_eq!(response.headers.get_all("Vary").count(), 1);
+
+    // A hand-set value is kept, and `*` already covers every field.
+    let response = Response::new(200, "OK").with_header("Vary", "Cookie").with_vary("Accept");
+    assert_eq!(response.header("Vary"), Some("Cookie, Accept"));
+    let response = Response::new(200, "OK").with_header("Vary", "*").with_vary("Accept");
+    assert_eq!(response.header("Vary"), Some("*"));
+
+    let built = Response::builder(200, "OK").vary("Accept").vary("Origin").vary("Accept").build();
+    assert_eq!(built.header("Vary"), Some("Accept, Origin"));
+}
+
+#[test]
+fn negotiated_representations_vary_on_accept() {
+    let router = negotiating_router();
+    for accept in ["text/html", "application/json", "*/*"] {
+        assert_eq!(get(&router, "/hello", accept).header("Vary"), Some("Accept"), "{}", accept);
+    }
+    assert_eq!(get(&router, "/hello", "application/json").header("Content-Type"), Some("application/json"));
+}
+
+#[test]
+fn without_negotiation_there_is_no_vary() {
+    let router = negotiating_router();
+    assert_eq!(get(&router, "/plain", "application/json").header("Vary"), None);
+    // The built-in 404 page comes as HTML or JSON, so it does vary.
+    assert_eq!(get(&router, "/missing", "text/plain").header("Vary"), Some("Accept"));
+}
+
+#[test]
+fn an_existing_vary_is_extended_unless_it_is_a_star() {
+    let router = negotiating_router();
+    assert_eq!(get(&router, "/encoded", "*/*").header("Vary"), Some("Accept-Encoding, Accept"));
+    assert_eq!(get(&router, "/anything", "*/*").header("Vary"), Some("*"));
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compression_and_negotiation_both_contribute() {
+    use server_app::compression::CompressionMiddleware;
+
+    let mut router = Router::new();
+    router
+        .middleware(ContentNegotiationMiddleware::new())
+        .middleware(CompressionMiddleware::new())
+        .get("/hello", |request: &Request| {
+            let content_type = request.negotiate_content_type(&["text/html", "application/json"]).unwrap_or("text/html");
+            Response::new(200, "OK").with_header("Content-Type", content_type).with_body("hello")
+        });
+    let mut request = Request::new("GET", "/hello");
+    request.headers.set("Accept", "application/json");
+    request.headers.set("Accept-Encoding", "gzip");
+    let response = router.dispatch(&request);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding, Accept"));
+}
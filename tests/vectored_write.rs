@@ -0,0 +1,141 @@
+// `write_all_vectored` and `Response::write_to` put out exactly the same
+// bytes through a writer that takes only 7 at a time, whichever slices
+// they straddle, as they do into a `Vec`.
+use std::io::{self, IoSlice, Write};
+
+use server_app::http::{write_all_vectored, Body, IterBody, Response};
+
+// Takes at most 7 bytes a call. With `vectored`, those can span slices;
+// without, it keeps `Write`'s default and takes only from the first
+// non-empty one. Every third call is `Interrupted` first.
+struct SevenAtATime {
+    out: Vec<u8>,
+    vectored: bool,
+    calls: usize,
+}
+
+impl SevenAtATime {
+    fn new(vectored: bool) -> SevenAtATime {
+        SevenAtATime { out: Vec::new(), vectored, calls: 0 }
+    }
+
+    fn interrupt(&mut self) -> bool {
+        self.calls += 1;
+        self.calls.is_multiple_of(3)
+    }
+}
+
+impl Write for SevenAtATime {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.interrupt() {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        let n = buf.len().min(7);
+        self.out.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        if !self.vectored {
+            let first = bufs.iter().find(|buf| !buf.is_empty()).map_or(&[][..], |buf| &**buf);
+            return self.write(first);
+        }
+        if self.interrupt() {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        let mut left = 7;
+        for buf in bufs {
+            let n = buf.len().min(left);
+            self.out.extend_from_slice(&buf[..n]);
+            left -= n;
+            if left == 0 {
+                break;
+            }
+        }
+        Ok(7 - left)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Bytes that don't repeat for a while, so a slip of a few is visible.
+fn body(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 + i / 97) as u8).collect()
+}
+
+fn expected(response: &Response) -> Vec<u8> {
+    let mut out = Vec::new();
+    response.write_to(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn slices_are_written_whole_and_in_order() {
+    let parts: [&[u8]; 6] = [b"", b"ab", b"", b"cdefghijklmnop", b"q", b"rstuvwxyz0123456789"];
+    let whole: Vec<u8> = parts.concat();
+    for vectored in [true, false] {
+        let mut writer = SevenAtATime::new(vectored);
+        let mut slices: Vec<IoSlice> = parts.iter().map(|part| IoSlice::new(part)).collect();
+        write_all_vectored(&mut writer, &mut slices).unwrap();
+        assert_eq!(writer.out, whole, "vectored: {}", vectored);
+    }
+}
+
+#[test]
+fn nothing_but_empty_slices_writes_nothing() {
+    let mut writer = SevenAtATime::new(true);
+    write_all_vectored(&mut writer, &mut [IoSlice::new(b""), IoSlice::new(b"")]).unwrap();
+    assert!(writer.out.is_empty());
+    assert_eq!(writer.calls, 0);
+}
+
+#[test]
+fn a_writer_that_takes_nothing_is_an_error() {
+    struct Full;
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let err = write_all_vectored(&mut Full, &mut [IoSlice::new(b"x")]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+}
+
+#[test]
+fn a_response_is_byte_exact_seven_bytes_at_a_time() {
+    for len in [0, 1, 6, 7, 8, 13, 14, 1000, 4099] {
+        let response = Response::new(200, "OK")
+            .with_header("Content-Type", "application/octet-stream")
+            .with_header("X-Trace", "abc")
+            .with_body(body(len));
+        let expected = expected(&response);
+        assert!(expected.ends_with(&body(len)));
+        for vectored in [true, false] {
+            let mut writer = SevenAtATime::new(vectored);
+            response.write_to(&mut writer).unwrap();
+            assert_eq!(writer.out, expected, "{} bytes, vectored: {}", len, vectored);
+        }
+    }
+}
+
+#[test]
+fn a_streamed_response_is_byte_exact_seven_bytes_at_a_time() {
+    let chunks = || vec![body(5), Vec::new(), body(100), body(7)];
+    let streamed = |chunks: Vec<Vec<u8>>| {
+        let mut response = Response::new(200, "OK").with_header("Content-Type", "text/plain");
+        response.stream = Some(Body::Iter(IterBody::new(chunks)));
+        response
+    };
+    let expected = expected(&streamed(chunks()));
+    assert!(expected.ends_with(b"\r\n0\r\n\r\n"));
+    for vectored in [true, false] {
+        let mut writer = SevenAtATime::new(vectored);
+        streamed(chunks()).write_to(&mut writer).unwrap();
+        assert_eq!(writer.out, expected, "vectored: {}", vectored);
+    }
+}
@@ -0,0 +1,74 @@
+// `GET /version` reports the build and the process's uptime, which only
+// ever counts up.
+mod common;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use server_app::http::Request;
+use server_app::info::{self, BuildInfo};
+use server_app::json::{self, Value};
+use server_app::router::Router;
+
+fn fields(text: &str) -> Value {
+    let value = json::parse(text).unwrap();
+    for field in ["version", "git_hash", "started_at", "uptime_secs", "workers"] {
+        assert!(value.get(field).is_some(), "no {} in {}", field, text);
+    }
+    value
+}
+
+#[test]
+fn every_field_is_there() {
+    let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let info = BuildInfo::new(4);
+    let value = fields(&info.to_json());
+    assert_eq!(value.get("version").and_then(Value::as_str), Some(env!("CARGO_PKG_VERSION")));
+    assert!(!value.get("git_hash").and_then(Value::as_str).unwrap().is_empty());
+    assert_eq!(value.get("workers").and_then(Value::as_f64), Some(4.0));
+    assert_eq!(value.get("uptime_secs").and_then(Value::as_f64), Some(0.0));
+    let started = value.get("started_at").and_then(Value::as_f64).unwrap() as u64;
+    let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    assert!((before..=after).contains(&started));
+
+    // The members alone, for the health check to embed.
+    assert_eq!(format!("{{{}}}", info.json_fields()), info.to_json());
+}
+
+#[test]
+fn uptime_counts_up_and_never_back() {
+    let info = BuildInfo::new(1);
+    let mut last = info.uptime_secs();
+    for _ in 0..12 {
+        thread::sleep(Duration::from_millis(100));
+        let uptime = info.uptime_secs();
+        assert!(uptime >= last);
+        last = uptime;
+    }
+    assert!(last >= 1, "a second and more has passed");
+}
+
+#[test]
+fn the_route_serves_the_info_uncached() {
+    let mut router = Router::new();
+    info::register(&mut router, Arc::new(BuildInfo::new(2)));
+    let response = router.dispatch(&Request::new("GET", "/version"));
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    assert_eq!(response.header("Cache-Control"), Some("no-store"));
+    let value = fields(std::str::from_utf8(&response.body).unwrap());
+    assert_eq!(value.get("workers").and_then(Value::as_f64), Some(2.0));
+}
+
+#[test]
+fn the_binary_serves_version_unless_turned_off() {
+    let server = common::start("version", "");
+    let response = common::get(server.port, "/version", "");
+    assert!(response.starts_with("HTTP/1.1 200 "), "{}", response);
+    fields(common::body(&response));
+
+    let server = common::start("version-off", "version_endpoint = false\n");
+    let response = common::get(server.port, "/version", "");
+    assert!(response.starts_with("HTTP/1.1 404 "), "{}", response);
+}
@@ -0,0 +1,115 @@
+// `VirtualHosts` serves the same path with a different router depending
+// on the `Host` header, with wildcards and a default for the rest.
+use std::fs;
+
+use server_app::config::Config;
+use server_app::http::{HttpVersion, Request, Response};
+use server_app::router::Router;
+use server_app::vhost::{normalize_host, VirtualHosts};
+
+fn site(name: &'static str) -> Router {
+    let mut router = Router::new();
+    router.get("/", move |_: &Request| Response::new(200, "OK").with_body(name));
+    router
+}
+
+fn hosts() -> VirtualHosts {
+    let mut hosts = VirtualHosts::new(site("default"));
+    hosts
+        .add("docs.example.test", site("docs"))
+        .add("App.Example.Test", site("app"))
+        .add("*.example.test", site("any subdomain"))
+        .add("*.eu.example.test", site("eu"));
+    hosts
+}
+
+fn body_for(hosts: &VirtualHosts, host: Option<&str>) -> String {
+    let mut request = Request::new("GET", "/");
+    if let Some(host) = host {
+        request.headers.set("Host", host);
+    }
+    let response = hosts.dispatch(&request);
+    assert_eq!(response.status, 200);
+    String::from_utf8(response.body).unwrap()
+}
+
+#[test]
+fn the_same_path_serves_each_host_its_own_content() {
+    let hosts = hosts();
+    assert_eq!(body_for(&hosts, Some("docs.example.test")), "docs");
+    assert_eq!(body_for(&hosts, Some("app.example.test")), "app");
+    assert_eq!(body_for(&hosts, Some("DOCS.example.test:8080")), "docs");
+    assert_eq!(body_for(&hosts, Some("docs.example.test.")), "docs");
+}
+
+#[test]
+fn wildcards_take_subdomains_longest_suffix_first() {
+    let hosts = hosts();
+    assert_eq!(body_for(&hosts, Some("blog.example.test")), "any subdomain");
+    assert_eq!(body_for(&hosts, Some("a.b.example.test")), "any subdomain");
+    assert_eq!(body_for(&hosts, Some("paris.eu.example.test")), "eu");
+    assert_eq!(body_for(&hosts, Some("example.test")), "default", "the bare domain isn't a subdomain");
+}
+
+#[test]
+fn unknown_hosts_and_hostless_http_1_0_get_the_default() {
+    let hosts = hosts();
+    assert_eq!(body_for(&hosts, Some("other.test")), "default");
+    assert_eq!(body_for(&hosts, Some("127.0.0.1:8080")), "default");
+
+    let mut request = Request::new("GET", "/");
+    request.version = HttpVersion::Http10;
+    assert_eq!(hosts.dispatch(&request).body, b"default");
+}
+
+#[test]
+fn http_1_1_needs_exactly_one_host() {
+    let hosts = hosts();
+    assert_eq!(hosts.dispatch(&Request::new("GET", "/")).status, 400);
+
+    let mut request = Request::new("GET", "/");
+    request.headers.append("Host", "docs.example.test");
+    request.headers.append("Host", "app.example.test");
+    assert_eq!(hosts.dispatch(&request).status, 400);
+}
+
+#[test]
+fn an_absolute_target_picks_the_host_by_its_authority() {
+    let hosts = hosts();
+    let mut request = Request::parse(b"GET http://app.example.test/ HTTP/1.1\r\nHost: docs.example.test\r\n\r\n").unwrap();
+    request.headers.remove("Host");
+    assert_eq!(hosts.dispatch(&request).body, b"app");
+}
+
+#[test]
+fn sites_come_from_config_with_their_own_roots() {
+    let dir = std::env::temp_dir().join(format!("vhosts-{}", std::process::id()));
+    for site in ["docs", "app"] {
+        fs::create_dir_all(dir.join(site)).unwrap();
+        fs::write(dir.join(site).join("index.html"), format!("<h1>{}</h1>", site)).unwrap();
+    }
+    let config = Config::parse(&format!(
+        "[vhosts]\nsites = [\"docs\", \"app\"]\n\
+         [docs]\nhosts = [\"docs.example.test\"]\nroot = \"{0}/docs\"\n\
+         [app]\nhosts = [\"app.example.test\", \"*.app.example.test\"]\nroot = \"{0}/app\"\n",
+        dir.display()
+    ))
+    .unwrap();
+    let hosts = VirtualHosts::from_config(&config, site("default")).unwrap();
+    assert_eq!(body_for(&hosts, Some("docs.example.test")), "<h1>docs</h1>");
+    assert_eq!(body_for(&hosts, Some("app.example.test")), "<h1>app</h1>");
+    assert_eq!(body_for(&hosts, Some("eu.app.example.test")), "<h1>app</h1>");
+    assert_eq!(body_for(&hosts, Some("elsewhere.test")), "default");
+
+    let mut missing = Request::new("GET", "/nope.txt");
+    missing.headers.set("Host", "docs.example.test");
+    assert_eq!(hosts.dispatch(&missing).status, 404);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn hosts_are_normalized() {
+    assert_eq!(normalize_host("Docs.Example.Test.:8080"), "docs.example.test");
+    assert_eq!(normalize_host("[::1]:8080"), "[::1]");
+    assert_eq!(normalize_host(" localhost "), "localhost");
+}
@@ -0,0 +1,203 @@
+// The WebSocket handshake and framing follow RFC 6455: keys, masking,
+// fragments, control frames, the close handshake and the size limit.
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use server_app::http::Request;
+use server_app::router::Router;
+use server_app::server::{Connection, Incoming, ServerConfig};
+use server_app::testing::MockStream;
+use server_app::websocket::{self, Frame, Message, Opcode, Role, WebSocket, WsError};
+
+const SAMPLE_KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+
+// A frame as a client sends it, masked.
+fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [0x37, 0xfa, 0x21, 0x3d];
+    let mut out = vec![if fin { 0x80 } else { 0 } | opcode];
+    match payload.len() {
+        n if n < 126 => out.push(0x80 | n as u8),
+        n if n <= u16::MAX as usize => {
+            out.push(0x80 | 126);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            out.push(0x80 | 127);
+            out.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(&mask);
+    out.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+    out
+}
+
+fn server(frames: Vec<Vec<u8>>) -> WebSocket<MockStream> {
+    WebSocket::new(MockStream::new(frames))
+}
+
+// The frames the server wrote, read back as the client would.
+fn sent(ws: &WebSocket<MockStream>) -> Vec<Frame> {
+    let mut client = WebSocket::with_role(MockStream::new([ws.get_ref().written().to_vec()]), Role::Client);
+    let mut frames = Vec::new();
+    while let Ok(frame) = client.read_frame() {
+        frames.push(frame);
+    }
+    frames
+}
+
+fn handshake_request(key: &str) -> Request {
+    let mut request = Request::new("GET", "/ws");
+    request.headers.set("Upgrade", "websocket");
+    request.headers.set("Connection", "keep-alive, Upgrade");
+    request.headers.set("Sec-WebSocket-Version", "13");
+    request.headers.set("Sec-WebSocket-Key", key);
+    request
+}
+
+#[test]
+fn the_accept_key_matches_the_rfc_sample() {
+    assert_eq!(websocket::accept_key(SAMPLE_KEY), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+    let response = websocket::handshake(&handshake_request(SAMPLE_KEY)).unwrap();
+    assert_eq!(response.status, 101);
+    assert_eq!(response.header("Sec-WebSocket-Accept"), Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+    assert_eq!(response.header("Upgrade"), Some("websocket"));
+}
+
+#[test]
+fn bad_handshakes_are_refused() {
+    assert_eq!(websocket::handshake(&handshake_request("c2hvcnQ=")).unwrap_err().status, 400);
+    let mut request = handshake_request(SAMPLE_KEY);
+    request.headers.set("Sec-WebSocket-Version", "8");
+    let refused = websocket::handshake(&request).unwrap_err();
+    assert_eq!((refused.status, refused.header("Sec-WebSocket-Version")), (426, Some("13")));
+    let mut request = handshake_request(SAMPLE_KEY);
+    request.headers.remove("Upgrade");
+    assert_eq!(websocket::handshake(&request).unwrap_err().status, 400);
+}
+
+#[test]
+fn unmasked_client_frames_are_a_protocol_error() {
+    let mut ws = server(vec![vec![0x81, 0x02, b'h', b'i']]);
+    assert!(matches!(ws.read_frame(), Err(WsError::Protocol(_))));
+    let close = &sent(&ws)[0];
+    assert_eq!((close.opcode, &close.payload[..2]), (Opcode::Close, &1002u16.to_be_bytes()[..]));
+}
+
+#[test]
+fn fragments_are_joined_with_control_frames_between_them() {
+    let mut ws = server(vec![
+        client_frame(false, 0x1, b"Hel"),
+        client_frame(true, 0x9, b"are you there"),
+        client_frame(false, 0x0, b"lo, "),
+        client_frame(true, 0x0, b"world"),
+    ]);
+    assert_eq!(ws.read_message().unwrap(), Message::Text("Hello, world".to_string()));
+    let pong = &sent(&ws)[0];
+    assert_eq!((pong.opcode, pong.payload.as_slice()), (Opcode::Pong, &b"are you there"[..]));
+
+    // A continuation with nothing to continue is an error.
+    let mut ws = server(vec![client_frame(true, 0x0, b"orphan")]);
+    assert!(matches!(ws.read_message(), Err(WsError::Protocol(_))));
+}
+
+#[test]
+fn control_frames_over_125_bytes_are_refused() {
+    let mut ws = server(vec![client_frame(true, 0x9, &[0; 126])]);
+    assert!(matches!(ws.read_frame(), Err(WsError::Protocol(_))));
+    let mut ws = server(vec![client_frame(true, 0x9, &[0; 125])]);
+    assert_eq!(ws.read_frame().unwrap().payload.len(), 125);
+    let mut ws = server(vec![client_frame(false, 0x9, b"split")]);
+    assert!(matches!(ws.read_frame(), Err(WsError::Protocol(_))));
+}
+
+#[test]
+fn a_close_is_echoed_with_its_code() {
+    let mut payload = 1001u16.to_be_bytes().to_vec();
+    payload.extend_from_slice(b"going away");
+    let mut ws = server(vec![client_frame(true, 0x8, &payload)]);
+    assert_eq!(ws.read_message().unwrap(), Message::Close(Some((1001, "going away".to_string()))));
+    let echo = sent(&ws);
+    assert_eq!(echo.len(), 1);
+    assert_eq!((echo[0].opcode, echo[0].payload.as_slice()), (Opcode::Close, &1001u16.to_be_bytes()[..]));
+
+    // Once we've closed, the peer's answer isn't echoed again.
+    let mut ws = server(vec![client_frame(true, 0x8, &1000u16.to_be_bytes())]);
+    ws.close(1000, "bye").unwrap();
+    assert_eq!(ws.read_message().unwrap(), Message::Close(Some((1000, String::new()))));
+    assert_eq!(sent(&ws).len(), 1);
+}
+
+#[test]
+fn messages_over_the_limit_are_refused() {
+    let mut ws = server(vec![client_frame(true, 0x2, &[7; 16])]).with_max_message_size(16);
+    assert_eq!(ws.read_message().unwrap(), Message::Binary(vec![7; 16]));
+
+    let mut ws = server(vec![client_frame(true, 0x2, &[7; 17])]).with_max_message_size(16);
+    assert!(matches!(ws.read_message(), Err(WsError::MessageTooLarge)));
+    assert_eq!(&sent(&ws)[0].payload[..2], &1009u16.to_be_bytes());
+
+    // Fragments count together.
+    let mut ws = server(vec![client_frame(false, 0x2, &[7; 10]), client_frame(true, 0x0, &[7; 10])])
+        .with_max_message_size(16);
+    assert!(matches!(ws.read_message(), Err(WsError::MessageTooLarge)));
+
+    let mut ws = server(vec![client_frame(true, 0x1, &[0xff, 0xfe])]);
+    assert!(matches!(ws.read_message(), Err(WsError::InvalidUtf8)));
+}
+
+#[test]
+fn a_scripted_client_talks_to_an_echo_route() {
+    let mut router = Router::new();
+    router.websocket("/ws", |ws| {
+        while let Ok(Message::Text(text)) = ws.read_message() {
+            if ws.send_text(&text).is_err() {
+                return;
+            }
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let config = ServerConfig { allowed_hosts: Vec::new(), ..ServerConfig::default() };
+        let mut connection = Connection::new(stream);
+        let Incoming::Request(request) = connection.read_request(&config, |_| None) else { panic!("no request") };
+        let response = router.dispatch(&request).finalize(&request);
+        connection.send(&response).unwrap();
+        response.upgrade.unwrap().run(connection.into_inner());
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "GET /ws HTTP/1.1\r\nHost: a\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: {}\r\n\r\n",
+        SAMPLE_KEY
+    )
+    .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        reader.read_line(&mut head).unwrap();
+    }
+    assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"), "{}", head);
+    assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    assert!(reader.buffer().is_empty());
+
+    let mut client = WebSocket::with_role(stream, Role::Client);
+    client.send_text("echo me").unwrap();
+    assert_eq!(client.read_message().unwrap(), Message::Text("echo me".to_string()));
+    client.ping(b"p").unwrap();
+    let pong = client.read_frame().unwrap();
+    assert_eq!((pong.opcode, pong.payload), (Opcode::Pong, b"p".to_vec()));
+    client.close(1000, "done").unwrap();
+    assert_eq!(client.read_message().unwrap(), Message::Close(Some((1000, String::new()))));
+    server.join().unwrap();
+
+    let mut rest = Vec::new();
+    let _ = client.get_ref().read_to_end(&mut rest);
+    assert!(rest.is_empty());
+}
@@ -0,0 +1,43 @@
+// A job that panics is caught on its worker: the worker stays up for the
+// jobs after it, pinned ones included, and the pool still shuts down.
+use std::sync::mpsc;
+use std::time::Duration;
+
+use server_app::{current_worker_id, ThreadPool};
+
+const WAIT: Duration = Duration::from_secs(5);
+
+#[test]
+fn the_worker_carries_on_after_a_panicking_job() {
+    let pool = ThreadPool::new(1);
+    let (tx, rx) = mpsc::channel();
+    for i in 0..3 {
+        pool.execute(|| panic!("job failed"));
+        let tx = tx.clone();
+        pool.execute(move || tx.send((i, current_worker_id())).unwrap());
+    }
+    for i in 0..3 {
+        assert_eq!(rx.recv_timeout(WAIT).unwrap(), (i, Some(0)));
+    }
+    // Dropping the pool joins the worker, which is still there to join.
+    drop(pool);
+}
+
+#[test]
+fn a_pinned_job_after_a_panic_still_runs() {
+    let pool = ThreadPool::new(2);
+    let (tx, rx) = mpsc::channel();
+    pool.execute_in_worker(1, || panic!("pinned job failed")).unwrap();
+    pool.execute_in_worker(1, move || tx.send(current_worker_id()).unwrap()).unwrap();
+    assert_eq!(rx.recv_timeout(WAIT).unwrap(), Some(1));
+}
+
+#[test]
+fn a_panic_with_a_formatted_message_is_caught_too() {
+    let pool = ThreadPool::new(1);
+    let (tx, rx) = mpsc::channel();
+    let code = 7;
+    pool.execute(move || panic!("job failed with {}", code));
+    pool.execute(move || tx.send(()).unwrap());
+    rx.recv_timeout(WAIT).unwrap();
+}
@@ -0,0 +1,141 @@
+// Routes tagged with `.pool("api")` are served on the server's "api"
+// pool, picked from the request line alone, so slow API calls can fill
+// that pool without holding up anything else.
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use server_app::http::Request;
+use server_app::router::Router;
+use server_app::server::{AcceptLoop, Connection, Incoming, Server, ServerConfig};
+
+const API_POOL: usize = 2;
+const SLOW: Duration = Duration::from_millis(800);
+
+struct Running {
+    address: SocketAddr,
+    in_api: Arc<AtomicUsize>,
+}
+
+fn start() -> Running {
+    let in_api = Arc::new(AtomicUsize::new(0));
+    let mut router = Router::new();
+    let busy = Arc::clone(&in_api);
+    router
+        .get("/api/*rest", move |_: &Request| {
+            busy.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(SLOW);
+            busy.fetch_sub(1, Ordering::SeqCst);
+            "api"
+        })
+        .pool("api")
+        .post("/api/echo", |request: &Request| request.body.clone())
+        .pool("api")
+        .get("/static/app.css", |_: &Request| "body {}");
+    let router = Arc::new(router);
+
+    let mut config = ServerConfig { workers: 2, ..ServerConfig::default() };
+    config.allowed_hosts.clear();
+    let handler_router = Arc::clone(&router);
+    let server = Server::new(config, move |stream, initial, config| {
+        // The bytes the pool was picked from come first.
+        let mut connection = Connection::with_initial(stream, &initial);
+        if let Incoming::Request(request) = connection.read_request(config, |_| None) {
+            let _ = connection.send(&handler_router.dispatch(&request).with_header("Connection", "close"));
+        }
+    })
+    .with_pool("api", API_POOL)
+    .with_pool_selector(move |method, path| router.pool_for(method, path).map(str::to_string));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let mut accept = AcceptLoop::new(listener).unwrap().with_poll_interval(Duration::from_millis(10));
+    thread::spawn(move || accept.run(|stream| server.serve(stream)).unwrap());
+    Running { address, in_api }
+}
+
+fn request(address: SocketAddr, raw: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    stream.write_all(raw.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn get(address: SocketAddr, path: &str) -> JoinHandle<String> {
+    let raw = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    thread::spawn(move || request(address, &raw))
+}
+
+fn wait_for(what: &str, done: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn a_saturated_api_pool_leaves_static_requests_quick() {
+    let server = start();
+    // Twice what the pool can take at once, so some are queued behind.
+    let api: Vec<_> = (0..API_POOL * 2).map(|i| get(server.address, &format!("/api/{}", i))).collect();
+    wait_for("the api pool to fill", || server.in_api.load(Ordering::SeqCst) == API_POOL);
+
+    for _ in 0..5 {
+        let started = Instant::now();
+        let response = get(server.address, "/static/app.css").join().unwrap();
+        let took = started.elapsed();
+        assert!(response.ends_with("\r\n\r\nbody {}"), "{}", response);
+        assert!(took < Duration::from_millis(200), "a static request took {:?}", took);
+    }
+    assert_eq!(server.in_api.load(Ordering::SeqCst), API_POOL, "and the api pool is still full");
+
+    // The pool never ran more than its size at once.
+    let started = Instant::now();
+    for handle in api {
+        assert!(handle.join().unwrap().ends_with("\r\n\r\napi"));
+    }
+    assert!(started.elapsed() >= SLOW, "the queued calls waited their turn");
+}
+
+#[test]
+fn bytes_read_to_pick_the_pool_reach_the_handler() {
+    let server = start();
+    let body = "x".repeat(5000);
+    let raw = format!("POST /api/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+    let response = request(server.address, &raw);
+    assert!(response.starts_with("HTTP/1.1 200 "), "{}", response);
+    assert!(response.ends_with(&format!("\r\n\r\n{}", body)));
+
+    // A request line split across writes works too.
+    let mut stream = TcpStream::connect(server.address).unwrap();
+    stream.write_all(b"GET /stat").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(b"ic/app.css HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("\r\n\r\nbody {}"), "{}", response);
+}
+
+#[test]
+fn routes_pick_their_pool_by_method_and_path() {
+    let mut router = Router::new();
+    router
+        .get("/api/*rest", |_: &Request| "api")
+        .pool("api")
+        .get("/reports", |_: &Request| "slow")
+        .pool("reports")
+        .post("/reports", |_: &Request| "quick")
+        .get("/", |_: &Request| "home");
+    assert_eq!(router.pool_for("GET", "/api/users/1"), Some("api"));
+    assert_eq!(router.pool_for("GET", "/reports"), Some("reports"));
+    assert_eq!(router.pool_for("HEAD", "/reports"), Some("reports"));
+    assert_eq!(router.pool_for("POST", "/reports"), None);
+    assert_eq!(router.pool_for("GET", "/"), None);
+    assert_eq!(router.pool_for("GET", "/missing"), None);
+}